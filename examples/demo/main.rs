@@ -0,0 +1,40 @@
+//! Evaluate the demo scenario through the library alone: pair its suppliers, optimize a plan
+//! for each pair, simulate the plans and print them from most to least profitable
+//!
+//! ```bash
+//! cargo run --release --example demo
+//! ```
+
+mod scenario;
+
+use supply_chain_sim::prelude::*;
+
+/// Monte Carlo runs per supplier pair
+const NUM_SIMULATIONS: usize = 500;
+
+fn main() -> Result<(), SimulationError> {
+    let params = scenario::params();
+    let suppliers = scenario::suppliers();
+
+    let mut results = generate_intelligent_pairs(&suppliers, false)
+        .iter()
+        .map(|pair| {
+            let quantities = find_optimal_production_quantities(&params, pair)?;
+            let order = split_order_quantities(&quantities, pair, &params);
+            let stats = run_monte_carlo_simulation(&params, pair, &order, &MonteCarloConfig::new(NUM_SIMULATIONS))?;
+            Ok((pair.name(), quantities, stats))
+        })
+        .collect::<Result<Vec<_>, SimulationError>>()?;
+    results.sort_by(|a, b| b.2.mean_profit.total_cmp(&a.2.mean_profit));
+
+    for (name, quantities, stats) in &results {
+        let units: Vec<String> = quantities.iter()
+            .map(|(id, qty)| {
+                let product = params.get_product(*id).map_or_else(|| format!("Product {}", id), |p| p.name.clone());
+                format!("{} {}", qty, product)
+            })
+            .collect();
+        println!("{:<32} mean profit {:>14.0} ± {:>10.0}  ({})", name, stats.mean_profit, 1.96 * stats.mean_std_error, units.join(", "));
+    }
+    Ok(())
+}
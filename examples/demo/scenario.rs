@@ -0,0 +1,159 @@
+//! The demo scenario: two products sold over a May to December season, from four suppliers
+//! with lead times of zero to four months
+//!
+//! The `supply-chain-sim` binary runs its pipeline on this scenario, and the `demo` example
+//! evaluates it through the library's public API.
+
+use std::collections::HashMap;
+use supply_chain_sim::prelude::*;
+
+/// The two products and their demand, with two order changes per season at escalating fees
+pub fn params() -> SimulationParams {
+    let products = vec![
+        Product {
+            id: ProductId(0),
+            name: "Model A".to_string(),
+            selling_price: 230.0,
+            liquidation_price: 144.0,
+            monthly_holding_cost: 4.60,
+            goodwill_penalty: None,
+        },
+        Product {
+            id: ProductId(1),
+            name: "Model B".to_string(),
+            selling_price: 280.0,
+            liquidation_price: 175.0,
+            monthly_holding_cost: 5.60,
+            goodwill_penalty: None,
+        },
+    ];
+
+    // Initialize demand parameters per product
+    // Expected demand is what the model uses for decisions
+    // Actual demand is what happens during the season (may differ)
+    let demand_params = vec![
+        ProductDemandParams {
+            product_id: ProductId(0),
+            mean_demand: 35_000.0,
+            std_dev_demand: 7_000.0,
+            actual_mean_demand: 32_000.0,
+            actual_std_dev_demand: 7_000.0,
+        },
+        ProductDemandParams {
+            product_id: ProductId(1),
+            mean_demand: 25_000.0,
+            std_dev_demand: 8_000.0,
+            actual_mean_demand: 28_000.0,
+            actual_std_dev_demand: 8_000.0,
+        },
+    ];
+
+    SimulationParams {
+        products,
+        demand_params,
+        // Contract allows two order changes per season at escalating fees
+        order_change_fees: vec![2_000_000.0, 3_500_000.0],
+        order_change_fee_per_product: 0.0,
+        cancellation_fee_per_unit: 15.0,
+        calibrate_to_observed_demand: true,
+        season_months: 8,  // May through December
+        regional_disruptions: Vec::new(),
+        capacity_policy: CapacityPolicy::Clamp,
+        expansion_offers: Vec::new(),
+        opening_inventory: Vec::new(),
+        onboarded_suppliers: Vec::new(),
+        carry_over_stock: false,
+        time_step: TimeStep::Monthly,
+        cost_of_capital_annual: 0.0,
+    }
+}
+
+/// Two overseas suppliers with long lead times and two nearshore suppliers that deliver within
+/// the month and let each month's delivery flex by 10%
+pub fn suppliers() -> Vec<Supplier> {
+    // Initialize suppliers with unit costs per product
+    // Product 0 = Model A, Product 1 = Model B
+    vec![
+        Supplier {
+            id: SupplierId(0),
+            name: "FarFarAway".to_string(),
+            fixed_capacity: 60_000,
+            product_capacity: None,
+            capacity_groups: Vec::new(),
+            soft_capacity_tiers: Vec::new(),
+            lead_time_months: 4,
+            ramp_up_months: Vec::new(),
+            unit_costs: HashMap::from([
+                (ProductId(0), 160.0),  // Model A
+                (ProductId(1), 170.0),  // Model B (more complex to produce)
+            ]),
+            setup_cost: 1_000_000.0,
+            reservation_fee: None,
+            monthly_flex: None,
+            disrupted_months: Vec::new(),
+            region: Some("Overseas".to_string()),
+            risk_class: None,
+        },
+        Supplier {
+            id: SupplierId(1),
+            name: "FarAway".to_string(),
+            fixed_capacity: 60_000,
+            product_capacity: None,
+            capacity_groups: Vec::new(),
+            soft_capacity_tiers: Vec::new(),
+            lead_time_months: 3,
+            ramp_up_months: Vec::new(),
+            unit_costs: HashMap::from([
+                (ProductId(0), 160.0),  // Model A
+                (ProductId(1), 170.0),  // Model B
+            ]),
+            setup_cost: 2_000_000.0,
+            reservation_fee: None,
+            monthly_flex: None,
+            disrupted_months: Vec::new(),
+            region: Some("Overseas".to_string()),
+            risk_class: None,
+        },
+        Supplier {
+            id: SupplierId(2),
+            name: "PrettyClose".to_string(),
+            fixed_capacity: 35_000,
+            product_capacity: None,
+            capacity_groups: Vec::new(),
+            soft_capacity_tiers: Vec::new(),
+            lead_time_months: 0,
+            ramp_up_months: Vec::new(),
+            unit_costs: HashMap::from([
+                (ProductId(0), 170.0),  // Model A (premium for shorter lead time)
+                (ProductId(1), 180.0),  // Model B
+            ]),
+            setup_cost: 1_000_000.0,
+            reservation_fee: None,
+            // Nearby suppliers let us flex each month's delivery by ±10%
+            monthly_flex: Some(Flex { max_fraction: 0.10, fee_per_unit: 2.0 }),
+            disrupted_months: Vec::new(),
+            region: Some("Nearshore".to_string()),
+            risk_class: None,
+        },
+        Supplier {
+            id: SupplierId(3),
+            name: "VeryClose".to_string(),
+            fixed_capacity: 40_000,
+            product_capacity: None,
+            capacity_groups: Vec::new(),
+            soft_capacity_tiers: Vec::new(),
+            lead_time_months: 0,
+            ramp_up_months: Vec::new(),
+            unit_costs: HashMap::from([
+                (ProductId(0), 170.0),  // Model A
+                (ProductId(1), 180.0),  // Model B
+            ]),
+            setup_cost: 2_000_000.0,
+            reservation_fee: None,
+            monthly_flex: Some(Flex { max_fraction: 0.10, fee_per_unit: 2.0 }),
+            disrupted_months: Vec::new(),
+            region: Some("Nearshore".to_string()),
+            risk_class: None,
+        },
+    ]
+}
@@ -0,0 +1,20 @@
+//! A run's settings from arbitrary bytes are refused or read, but never panic: the budget the
+//! server and the C interface take as JSON, and the option values the binary parses from its
+//! command line with the library's parsers
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use supply_chain_sim::models::{CapacityPolicy, ExpansionOffer, RankingMetric, RegionalDisruption};
+use supply_chain_sim::scenario::Budget;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    let _ = serde_json::from_str::<Budget>(text);
+    let _ = text.parse::<RegionalDisruption>();
+    let _ = text.parse::<ExpansionOffer>();
+    let _ = text.parse::<CapacityPolicy>();
+    let _ = text.parse::<RankingMetric>();
+});
@@ -0,0 +1,19 @@
+//! A scenario from arbitrary bytes, as the server and the C interface take them, is refused or
+//! evaluated on a token budget, but never panics
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use supply_chain_sim::progress::NoProgress;
+use supply_chain_sim::scenario::{Budget, Scenario};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(json) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(scenario) = Scenario::from_json(json) else {
+        return;
+    };
+    let budget = Budget { simulations: 2, seed: 0, max_pairs: Some(2), parallel: false };
+    let _ = scenario.evaluate(&budget, &NoProgress);
+});
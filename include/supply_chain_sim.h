@@ -0,0 +1,92 @@
+#ifndef SUPPLY_CHAIN_SIM_H
+#define SUPPLY_CHAIN_SIM_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Outcome of the calling thread's last call into the library
+ */
+typedef enum ScsStatus {
+  SCS_STATUS_OK = 0,
+  /**
+   * A pointer that may not be null was
+   */
+  SCS_STATUS_NULL_ARGUMENT = 1,
+  /**
+   * A string passed in was not UTF-8
+   */
+  SCS_STATUS_INVALID_UTF8 = 2,
+  /**
+   * A scenario or budget could not be read from its JSON
+   */
+  SCS_STATUS_INVALID_JSON = 3,
+  /**
+   * The scenario could not be evaluated
+   */
+  SCS_STATUS_SIMULATION_FAILED = 4,
+  /**
+   * The library panicked; the call did nothing the caller can use
+   */
+  SCS_STATUS_PANICKED = 5,
+} ScsStatus;
+
+/**
+ * The season and the suppliers to pair up for it
+ */
+typedef struct Scenario Scenario;
+
+/**
+ * Read a scenario, `params` and `suppliers`, from JSON; null when it cannot be read
+ * The scenario is the caller's to free with `scs_scenario_free`
+ *
+ * # Safety
+ * `json` is null or points to a NUL-terminated string
+ */
+Scenario *scs_scenario_from_json(const char *json);
+
+/**
+ * Free a scenario from `scs_scenario_from_json`; null does nothing
+ *
+ * # Safety
+ * `scenario` is null or came from `scs_scenario_from_json` and has not been freed
+ */
+void scs_scenario_free(Scenario *scenario);
+
+/**
+ * Evaluate `scenario` on the budget in `config_json` (`simulations`, `seed`, and optionally
+ * `max_pairs` and `parallel`) and return the run as JSON, as `--format json` prints it; null
+ * when it cannot
+ * The string is the caller's to free with `scs_string_free`
+ *
+ * # Safety
+ * `scenario` is null or a live scenario from `scs_scenario_from_json`, and `config_json` is
+ * null or points to a NUL-terminated string
+ */
+char *scs_evaluate(const Scenario *scenario, const char *config_json);
+
+/**
+ * Free a string from `scs_evaluate`; null does nothing
+ *
+ * # Safety
+ * `string` is null or came from `scs_evaluate` and has not been freed
+ */
+void scs_string_free(char *string);
+
+/**
+ * Outcome of the calling thread's last call, `Ok` when it succeeded
+ */
+ScsStatus scs_last_error_code(void);
+
+/**
+ * Why the calling thread's last call failed, or null when it succeeded
+ * The message belongs to the library and lasts until the thread next calls a function other
+ * than this one and `scs_last_error_code`
+ */
+const char *scs_last_error_message(void);
+
+#endif  /* SUPPLY_CHAIN_SIM_H */
@@ -0,0 +1,776 @@
+//! Analyses across runs of the simulation
+//! Sensitivity analysis nudges each model input down and up while a chosen plan stays fixed,
+//! re-simulating it on the same demand paths to see how far its mean profit moves; the
+//! scenario matrix compares supplier pairs across several scenarios by their regret; the value
+//! of information and of the order-change options compares a plan with what foresight or
+//! rigidity would have made of the same demand paths, the dual-source value a pair with its
+//! base supplier alone, and a plan comparison plans of one's own with the optimizer's
+
+use std::collections::HashMap;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::capacity::create_order;
+use crate::demand::with_demand_stream;
+use crate::error::{ScenarioError, SimulationError};
+use crate::evaluation::optimizer_seed;
+use crate::models::{CapacityPolicy, MonteCarloStats, MonthlyOrder, PairedComparison, ProductId, RunOutput, SimulationParams, Supplier, SupplierId, SupplierPair};
+use crate::monte_carlo::{paired_comparison, paired_replications, run_monte_carlo_simulation, MonteCarloConfig, SampleRetention};
+use crate::optimizer::find_optimal_plan;
+use crate::progress::NoProgress;
+use crate::simulation::{check_capacity, run_monthly_simulation_internal, split_base_first, split_order_quantities, DemandSource};
+use crate::streaming::interpolated_quantile;
+
+/// A model input that sensitivity analysis varies
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parameter {
+    SellingPrice { product_id: ProductId },
+    HoldingCost { product_id: ProductId },
+    LiquidationPrice { product_id: ProductId },
+    /// Mean of the demand that actually materializes; the plan was made on the forecast
+    DemandMean { product_id: ProductId },
+    /// Every unit cost of a supplier
+    UnitCost { supplier_id: SupplierId },
+}
+
+impl Parameter {
+    /// Scale this input by `factor` in the scenario and the pair
+    /// Inputs of products or suppliers that are not part of them are left alone
+    pub fn apply(&self, params: &mut SimulationParams, pair: &mut SupplierPair, factor: f64) {
+        match *self {
+            Parameter::SellingPrice { product_id } => {
+                if let Some(product) = params.products.iter_mut().find(|p| p.id == product_id) {
+                    product.selling_price *= factor;
+                }
+            }
+            Parameter::HoldingCost { product_id } => {
+                if let Some(product) = params.products.iter_mut().find(|p| p.id == product_id) {
+                    product.monthly_holding_cost *= factor;
+                }
+            }
+            Parameter::LiquidationPrice { product_id } => {
+                if let Some(product) = params.products.iter_mut().find(|p| p.id == product_id) {
+                    product.liquidation_price *= factor;
+                }
+            }
+            Parameter::DemandMean { product_id } => {
+                if let Some(dp) = params.demand_params.iter_mut().find(|dp| dp.product_id == product_id) {
+                    dp.actual_mean_demand *= factor;
+                }
+            }
+            Parameter::UnitCost { supplier_id } => {
+                for supplier in [&mut pair.base_supplier, &mut pair.surge_supplier] {
+                    if supplier.id == supplier_id {
+                        supplier.unit_costs.values_mut().for_each(|cost| *cost *= factor);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Readable name of this input, e.g. "Model A selling price" or "FarAway unit cost"
+    pub fn label(&self, params: &SimulationParams, suppliers: &[Supplier]) -> String {
+        let product = |product_id: ProductId| {
+            params.get_product(product_id)
+                .map(|product| product.name.clone())
+                .unwrap_or_else(|| format!("Product {}", product_id))
+        };
+        match *self {
+            Parameter::SellingPrice { product_id } => format!("{} selling price", product(product_id)),
+            Parameter::HoldingCost { product_id } => format!("{} holding cost", product(product_id)),
+            Parameter::LiquidationPrice { product_id } => format!("{} liquidation price", product(product_id)),
+            Parameter::DemandMean { product_id } => format!("{} demand", product(product_id)),
+            Parameter::UnitCost { supplier_id } => {
+                let name = suppliers.iter()
+                    .find(|supplier| supplier.id == supplier_id)
+                    .map(|supplier| supplier.name.clone())
+                    .unwrap_or_else(|| format!("Supplier {}", supplier_id));
+                format!("{} unit cost", name)
+            }
+        }
+    }
+}
+
+/// An input varied down and up by the same fraction, e.g. 0.1 for ±10%
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Perturbation {
+    pub parameter: Parameter,
+    pub fraction: f64,
+}
+
+/// How a plan's mean profit responds to one perturbation
+#[derive(Clone, Debug, PartialEq)]
+pub struct SensitivityResult {
+    pub label: String,
+    pub fraction: f64,
+    /// Mean profit of the plan as it stands
+    pub base_mean_profit: f64,
+    /// Change in mean profit with the input lowered by `fraction`
+    pub low_delta: f64,
+    /// Change in mean profit with the input raised by `fraction`
+    pub high_delta: f64,
+}
+
+impl SensitivityResult {
+    /// Largest change in mean profit either way, by which a tornado chart is sorted
+    pub fn impact(&self) -> f64 {
+        self.low_delta.abs().max(self.high_delta.abs())
+    }
+}
+
+/// The perturbations the `--sensitivity` report runs: each product's price ±10%, holding cost
+/// ±20%, liquidation price ±10% and demand ±15%, and the unit costs of both suppliers ±5%
+pub fn default_perturbations(params: &SimulationParams, pair: &SupplierPair) -> Vec<Perturbation> {
+    let mut perturbations = Vec::new();
+    for product in &params.products {
+        let product_id = product.id;
+        perturbations.push(Perturbation { parameter: Parameter::SellingPrice { product_id }, fraction: 0.10 });
+        perturbations.push(Perturbation { parameter: Parameter::HoldingCost { product_id }, fraction: 0.20 });
+        perturbations.push(Perturbation { parameter: Parameter::LiquidationPrice { product_id }, fraction: 0.10 });
+        perturbations.push(Perturbation { parameter: Parameter::DemandMean { product_id }, fraction: 0.15 });
+    }
+    for supplier in [&pair.base_supplier, &pair.surge_supplier] {
+        perturbations.push(Perturbation { parameter: Parameter::UnitCost { supplier_id: supplier.id }, fraction: 0.05 });
+    }
+    perturbations
+}
+
+/// Re-evaluate a fixed plan under each perturbation, lowered and raised
+/// Every run uses `config`'s master seed, so all of them see the same demand paths and the
+/// deltas reflect the inputs rather than sampling noise
+/// Fails when the plan cannot be simulated as given or under any perturbation
+pub fn sensitivity(
+    params: &SimulationParams,
+    suppliers: &[Supplier],
+    pair: &SupplierPair,
+    monthly_order: &MonthlyOrder,
+    perturbations: &[Perturbation],
+    config: &MonteCarloConfig,
+) -> Result<Vec<SensitivityResult>, SimulationError> {
+    let base_mean_profit = run_monte_carlo_simulation(params, pair, monthly_order, config)?.mean_profit;
+    let mean_profit_with = |parameter: Parameter, factor: f64| {
+        let mut params = params.clone();
+        let mut pair = pair.clone();
+        parameter.apply(&mut params, &mut pair, factor);
+        Ok::<f64, SimulationError>(run_monte_carlo_simulation(&params, &pair, monthly_order, config)?.mean_profit)
+    };
+
+    perturbations.iter()
+        .map(|perturbation| Ok(SensitivityResult {
+            label: perturbation.parameter.label(params, suppliers),
+            fraction: perturbation.fraction,
+            base_mean_profit,
+            low_delta: mean_profit_with(perturbation.parameter, 1.0 - perturbation.fraction)? - base_mean_profit,
+            high_delta: mean_profit_with(perturbation.parameter, 1.0 + perturbation.fraction)? - base_mean_profit,
+        }))
+        .collect()
+}
+
+/// Rounds of the product-by-product search for the best quantities on a known demand path
+const FORESIGHT_ROUNDS: usize = 3;
+
+/// Expected value of perfect information: how much more a planner who knew each season's demand
+/// before ordering would make than the stochastic-optimal plan does
+/// The pair's plan is optimized on the forecast, on the demand stream of `master_seed`, and run
+/// on `num_simulations` demand paths of it, order changes and all. Each path is then replayed to
+/// a planner who knows it: the constant monthly quantities doing best on it with no order
+/// changes, keeping the plan's reservations, or the plan itself where none do better. The first
+/// arm is the foresight profit and the second the plan's, so the mean difference is the EVPI,
+/// never negative path by path
+pub fn evpi(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    num_simulations: usize,
+    master_seed: u64,
+) -> Result<PairedComparison, SimulationError> {
+    let (quantities, monthly_order) = optimized_order(params, pair, master_seed)?;
+    let profits = paired_replications(num_simulations, master_seed, |_, seed| {
+        let (monthly_results, profit) = with_demand_stream(seed, || {
+            run_monthly_simulation_internal(params, pair, &monthly_order, true, true, DemandSource::Stochastic(None))
+        })?;
+        let path: Vec<HashMap<ProductId, u32>> = monthly_results.iter()
+            .map(|month| month.product_results.iter().map(|result| (result.product_id, result.demand)).collect())
+            .collect();
+        let foresight = foresight_profit(params, pair, &monthly_order, &quantities, &path, seed)?;
+        Ok((foresight.max(profit), profit))
+    })?;
+    Ok(paired_comparison("perfect information".to_string(), pair.name(), &profits))
+}
+
+/// The plan the optimizer makes for `pair` on the demand stream of `seed`: its quantity of each
+/// product and their split between the pair's suppliers, with the capacity it reserves
+fn optimized_order(params: &SimulationParams, pair: &SupplierPair, seed: u64) -> Result<(Vec<(ProductId, u32)>, MonthlyOrder), SimulationError> {
+    let (quantities, reservations, _) = with_demand_stream(seed, || find_optimal_plan(params, pair, &NoProgress))?;
+    let mut monthly_order = split_order_quantities(&quantities, pair, params);
+    monthly_order.reservations = reservations;
+    Ok((quantities, monthly_order))
+}
+
+/// Profit of the best constant monthly quantities on the known demand `path`, with no order
+/// changes and `monthly_order`'s reservations: each product's quantity in turn is searched from
+/// none to the most the path's busiest period asks for a month, starting from `quantities`,
+/// until a round over the products improves nothing. The season's other random events draw on
+/// `seed`
+fn foresight_profit(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    monthly_order: &MonthlyOrder,
+    quantities: &[(ProductId, u32)],
+    path: &[HashMap<ProductId, u32>],
+    seed: u64,
+) -> Result<f64, SimulationError> {
+    let profit_of = |quantities: &[(ProductId, u32)]| {
+        let mut order = split_order_quantities(quantities, pair, params);
+        order.reservations = monthly_order.reservations.clone();
+        with_demand_stream(seed, || run_monthly_simulation_internal(params, pair, &order, false, true, DemandSource::Scripted(path)))
+            .map(|(_, profit)| profit)
+    };
+    let per_month = params.time_step.periods_per_month(params.season_months);
+    let mut quantities = quantities.to_vec();
+    let mut best = profit_of(&quantities)?;
+    for _ in 0..FORESIGHT_ROUNDS {
+        let before = best;
+        for index in 0..quantities.len() {
+            let product_id = quantities[index].0;
+            let peak = path.iter().filter_map(|period| period.get(&product_id)).max().copied().unwrap_or(0);
+            let (mut low, mut high) = (0, ((peak as f64 * per_month).ceil() as u32).max(quantities[index].1));
+            let mut profit_with = |quantity: u32| {
+                let mut candidate = quantities.clone();
+                candidate[index].1 = quantity;
+                let profit = profit_of(&candidate)?;
+                if profit > best {
+                    best = profit;
+                    quantities = candidate;
+                }
+                Ok::<f64, SimulationError>(profit)
+            };
+            // Ternary search of a profit that rises with the quantity until stock outlasts demand
+            while high - low > 2 {
+                let third = (high - low) / 3;
+                if profit_with(low + third)? < profit_with(high - third)? {
+                    low += third + 1;
+                } else {
+                    high -= third + 1;
+                }
+            }
+            for quantity in low..=high {
+                profit_with(quantity)?;
+            }
+        }
+        if best <= before {
+            break;
+        }
+    }
+    Ok(best)
+}
+
+/// What the order-change options earned a plan: its profit with changes exercised as the
+/// season unfolds against its profit as ordered, on the same `num_simulations` demand paths of
+/// `master_seed`; the first arm is with the options and the second without
+pub fn option_value_realized(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    monthly_order: &MonthlyOrder,
+    num_simulations: usize,
+    master_seed: u64,
+) -> Result<PairedComparison, SimulationError> {
+    let profits = paired_replications(num_simulations, master_seed, |_, seed| {
+        let profit = |enable_options: bool| {
+            with_demand_stream(seed, || run_monthly_simulation_internal(params, pair, monthly_order, enable_options, true, DemandSource::Stochastic(None)))
+                .map(|(_, profit)| profit)
+        };
+        Ok((profit(true)?, profit(false)?))
+    })?;
+    Ok(paired_comparison("with order changes".to_string(), "as ordered".to_string(), &profits))
+}
+
+/// What a pair's surge supplier adds to its base supplier alone, path by path
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DualSourceValue {
+    /// Profit of the pair less its base supplier's alone on each demand path, in replication order
+    pub differences: Vec<f64>,
+    /// The pair as first arm and its base supplier alone as second
+    pub comparison: PairedComparison,
+}
+
+impl DualSourceValue {
+    /// Expected profit the surge supplier adds
+    pub fn mean(&self) -> f64 {
+        self.comparison.mean_difference
+    }
+
+    /// Half-width of the confidence interval on the expected profit added
+    pub fn ci_half_width(&self) -> f64 {
+        (self.comparison.difference_ci_high - self.comparison.difference_ci_low) / 2.0
+    }
+
+    /// Profit added on the path at `level` (in percent) of the differences, interpolated
+    pub fn percentile(&self, level: f64) -> f64 {
+        let mut sorted = self.differences.clone();
+        sorted.sort_by(f64::total_cmp);
+        interpolated_quantile(&sorted, level / 100.0)
+    }
+}
+
+/// Marginal value of the pair's surge supplier: the pair's profit less that of its base
+/// supplier alone, each with the plan the optimizer makes for it on the seed a pair evaluation
+/// would use, on `config.num_simulations` plain replications of `config`'s master seed
+/// The base supplier alone keeps any backup and pooled suppliers and drops only the surge
+/// supplier, whose setup cost it then never pays. The pair places its plan as the optimizer
+/// splits it or filling the base supplier first, whichever does better on the same number of
+/// replications of the optimizer's seed, and does without the surge supplier if neither beats
+/// the base supplier alone there, when the surge supplier adds nothing
+pub fn dual_source_value(params: &SimulationParams, pair: &SupplierPair, config: &MonteCarloConfig) -> Result<DualSourceValue, SimulationError> {
+    let base_only = SupplierPair {
+        surge_supplier: Supplier { fixed_capacity: 0, ..pair.base_supplier.clone() },
+        ..pair.clone()
+    };
+    let pair_seed = optimizer_seed(config.master_seed, pair);
+    let (pair_quantities, pair_order) = optimized_order(params, pair, pair_seed)?;
+    let (_, base_order) = optimized_order(params, &base_only, optimizer_seed(config.master_seed, &base_only))?;
+    let mut base_first_order = split_base_first(&pair_quantities, pair, params);
+    base_first_order.reservations = pair_order.reservations.clone();
+
+    let profit = |pair: &SupplierPair, order: &MonthlyOrder, seed: u64| {
+        with_demand_stream(seed, || run_monthly_simulation_internal(params, pair, order, true, true, DemandSource::Stochastic(None)))
+            .map(|(_, profit)| profit)
+    };
+    // The optimizer's split hands the surge supplier a share of every product whatever its units
+    // cost, which can leave the pair behind its base supplier alone; the choice is made apart from
+    // the paths the value is measured on
+    let mut chosen = (&base_only, &base_order, 0.0);
+    for order in [&pair_order, &base_first_order] {
+        let profits = paired_replications(config.num_simulations, pair_seed, |_, seed| {
+            Ok((profit(pair, order, seed)?, profit(&base_only, &base_order, seed)?))
+        })?;
+        let gain = profits.iter().map(|(with_surge, alone)| with_surge - alone).sum::<f64>() / profits.len() as f64;
+        if gain > chosen.2 {
+            chosen = (pair, order, gain);
+        }
+    }
+    let (chosen_pair, chosen_order, _) = chosen;
+
+    let profits = paired_replications(config.num_simulations, config.master_seed, |_, seed| {
+        Ok((profit(chosen_pair, chosen_order, seed)?, profit(&base_only, &base_order, seed)?))
+    })?;
+    Ok(DualSourceValue {
+        differences: profits.iter().map(|(with_surge, alone)| with_surge - alone).collect(),
+        comparison: paired_comparison(pair.name(), base_only.name(), &profits),
+    })
+}
+
+/// Name the optimizer's plan goes by in a plan comparison
+pub const OPTIMIZER_PLAN: &str = "Optimizer";
+
+/// A plan of one's own for a supplier pair, such as last year's or a consultant's: units a
+/// month of each product, by product name, from the base and from the surge supplier
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct NamedPlan {
+    pub name: String,
+    /// Products left out order none from the base supplier
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub base: HashMap<String, u32>,
+    /// Products left out order none from the surge supplier
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub surge: HashMap<String, u32>,
+}
+
+impl NamedPlan {
+    /// The monthly order the plan places for the scenario's products
+    /// The error names the plan and the field at fault, such as a product the scenario does not sell
+    pub fn monthly_order(&self, params: &SimulationParams) -> Result<MonthlyOrder, ScenarioError> {
+        if self.name.trim().is_empty() {
+            return Err(self.invalid("name", "must not be empty"));
+        }
+        let quantities = |field: &str, units: &HashMap<String, u32>| {
+            let mut names: Vec<&String> = units.keys().collect();
+            names.sort();
+            if let Some(unknown) = names.into_iter().find(|name| !params.products.iter().any(|product| product.name == **name)) {
+                return Err(self.invalid(&format!("{}.{}", field, unknown), "is not one of the scenario's products"));
+            }
+            Ok(params.products.iter()
+                .map(|product| (product.id, units.get(&product.name).copied().unwrap_or(0)))
+                .collect())
+        };
+        Ok(create_order(quantities("base", &self.base)?, quantities("surge", &self.surge)?))
+    }
+
+    fn invalid(&self, field: &str, reason: impl Into<String>) -> ScenarioError {
+        ScenarioError::InvalidPlan { plan: self.name.clone(), field: field.to_string(), reason: reason.into() }
+    }
+}
+
+/// How one plan of a comparison fared on the demand paths every plan shared
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PlanResult {
+    pub name: String,
+    /// Units a month the plan orders from the base and from the surge supplier, over all products
+    pub base_units: u32,
+    pub surge_units: u32,
+    pub mean_profit: f64,
+    /// Mean profit over the worst 5% of the paths
+    pub cvar_5: f64,
+    /// Mean share of a season's demand met
+    pub fill_rate: f64,
+    /// The plan as first arm and the optimizer's as second; None for the optimizer's own plan
+    pub versus_optimizer: Option<PairedComparison>,
+}
+
+/// Plans for one supplier pair side by side, the optimizer's first and the others in the
+/// order given
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PlanComparison {
+    pub pair: String,
+    pub num_simulations: usize,
+    pub plans: Vec<PlanResult>,
+}
+
+/// Simulate `plans` and the plan the optimizer makes for the pair on the seed a pair evaluation
+/// would use, all on `config`'s demand paths, and test each plan's profit against the
+/// optimizer's path by path
+/// A plan is refused, by name, when another has its name (the optimizer's plan goes by
+/// `OPTIMIZER_PLAN`), when it orders a product the scenario does not sell, or when it does not
+/// fit its suppliers under the strict capacity policy
+pub fn compare_plans(params: &SimulationParams, pair: &SupplierPair, plans: Vec<NamedPlan>, config: &MonteCarloConfig) -> Result<PlanComparison, SimulationError> {
+    // Every plan is checked before the optimizer spends any time on its own
+    let mut orders: Vec<(String, MonthlyOrder)> = Vec::with_capacity(plans.len() + 1);
+    for plan in plans {
+        let order = plan.monthly_order(params)?;
+        if plan.name == OPTIMIZER_PLAN || orders.iter().any(|(name, _)| *name == plan.name) {
+            return Err(plan.invalid("name", "is already the name of another plan").into());
+        }
+        if params.capacity_policy == CapacityPolicy::Strict {
+            check_capacity(params, pair, &order).map_err(|err| plan.invalid("base and surge", format!("do not fit: {}", err)))?;
+        }
+        orders.push((plan.name, order));
+    }
+    let (_, optimized) = optimized_order(params, pair, optimizer_seed(config.master_seed, pair))?;
+    orders.insert(0, (OPTIMIZER_PLAN.to_string(), optimized));
+
+    // Every replication's profit is kept to pair it with the optimizer's on the same path
+    let config = MonteCarloConfig { sample_retention: SampleRetention::All, ..config.clone() };
+    let runs = orders.into_iter()
+        .map(|(name, order)| Ok((name, run_monte_carlo_simulation(params, pair, &order, &config)?, order)))
+        .collect::<Result<Vec<_>, SimulationError>>()?;
+    let optimizer = &runs[0].1;
+    let plans = runs.iter().enumerate()
+        .map(|(index, (name, stats, order))| {
+            let profits: Vec<(f64, f64)> = stats.samples.iter().zip(&optimizer.samples)
+                .map(|(sample, optimizer)| (sample.profit, optimizer.profit))
+                .collect();
+            PlanResult {
+                name: name.clone(),
+                base_units: order.total_base_quantity(),
+                surge_units: order.total_surge_quantity(),
+                mean_profit: stats.mean_profit,
+                cvar_5: stats.cvar_5,
+                fill_rate: stats.samples.iter().map(|sample| sample.fill_rate).sum::<f64>() / stats.samples.len() as f64,
+                versus_optimizer: (index > 0).then(|| paired_comparison(name.clone(), OPTIMIZER_PLAN.to_string(), &profits)),
+            }
+        })
+        .collect();
+    Ok(PlanComparison { pair: pair.name(), num_simulations: config.num_simulations, plans })
+}
+
+/// Mean profit of supplier pairs across several scenarios, with each scenario's winner and
+/// each pair's regret: how much less it makes than the scenario's best pair
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ScenarioResults {
+    /// Scenario names, in run order
+    pub scenarios: Vec<String>,
+    /// Pairs as "base + surge" supplier names, in the order they were first run
+    pub pairs: Vec<String>,
+    /// Mean profit by scenario, then pair; None where screening dropped the pair from a scenario
+    pub mean_profits: Vec<Vec<Option<f64>>>,
+    /// Index into `pairs` of each scenario's most profitable pair
+    pub winners: Vec<Option<usize>>,
+    /// Regret by scenario, then pair, None where the pair was not run
+    pub regrets: Vec<Vec<Option<f64>>>,
+    /// Each pair's largest regret over the scenarios it ran in
+    pub max_regrets: Vec<Option<f64>>,
+    /// Index into `pairs` of the pair whose largest regret is smallest, among the pairs run in
+    /// every scenario
+    pub minimax_regret_pair: Option<usize>,
+}
+
+impl ScenarioResults {
+    /// Tabulate the mean profit of every pair of each named run
+    pub fn from_runs(runs: &[(String, RunOutput)]) -> Self {
+        let pair_name = MonteCarloStats::pair_name;
+        let mut pairs: Vec<String> = Vec::new();
+        for (_, run) in runs {
+            for pair in &run.pairs {
+                let name = pair_name(&pair.stats);
+                if !pairs.contains(&name) {
+                    pairs.push(name);
+                }
+            }
+        }
+        let mean_profits = runs.iter()
+            .map(|(_, run)| {
+                pairs.iter()
+                    .map(|name| {
+                        run.pairs.iter()
+                            .find(|pair| pair_name(&pair.stats) == *name)
+                            .map(|pair| pair.stats.mean_profit)
+                    })
+                    .collect()
+            })
+            .collect();
+        ScenarioResults::new(runs.iter().map(|(name, _)| name.clone()).collect(), pairs, mean_profits)
+    }
+
+    /// Work out winners and regrets from a matrix of mean profits by scenario, then pair
+    pub fn new(scenarios: Vec<String>, pairs: Vec<String>, mean_profits: Vec<Vec<Option<f64>>>) -> Self {
+        let winners: Vec<Option<usize>> = mean_profits.iter()
+            .map(|row| {
+                (0..row.len())
+                    .filter(|&pair| row[pair].is_some())
+                    .max_by(|&a, &b| row[a].unwrap_or(f64::NEG_INFINITY).total_cmp(&row[b].unwrap_or(f64::NEG_INFINITY)))
+            })
+            .collect();
+        let regrets: Vec<Vec<Option<f64>>> = mean_profits.iter().zip(&winners)
+            .map(|(row, winner)| {
+                let best = winner.and_then(|winner| row[winner]);
+                row.iter().map(|profit| Some(best? - (*profit)?)).collect()
+            })
+            .collect();
+        let max_regrets: Vec<Option<f64>> = (0..pairs.len())
+            .map(|pair| regrets.iter().filter_map(|row| row[pair]).reduce(f64::max))
+            .collect();
+        let minimax_regret_pair = (0..pairs.len())
+            .filter(|&pair| regrets.iter().all(|row| row[pair].is_some()))
+            .min_by(|&a, &b| max_regrets[a].unwrap_or(0.0).total_cmp(&max_regrets[b].unwrap_or(0.0)));
+        ScenarioResults { scenarios, pairs, mean_profits, winners, regrets, max_regrets, minimax_regret_pair }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monte_carlo::tests::two_product_scenario;
+    use crate::simulation::split_order_quantities;
+
+    #[test]
+    fn test_scenario_regret_and_winners() {
+        // The cautious pair never wins but never trails by much; the third was screened out of
+        // the downturn
+        let results = ScenarioResults::new(
+            vec!["Boom".to_string(), "Downturn".to_string()],
+            vec!["Aggressive".to_string(), "Cautious".to_string(), "Nearshore".to_string()],
+            vec![
+                vec![Some(1_000.0), Some(900.0), Some(950.0)],
+                vec![Some(200.0), Some(500.0), None],
+            ],
+        );
+        assert_eq!(results.winners, vec![Some(0), Some(1)]);
+        assert_eq!(results.regrets[0], vec![Some(0.0), Some(100.0), Some(50.0)]);
+        assert_eq!(results.regrets[1], vec![Some(300.0), Some(0.0), None]);
+        assert_eq!(results.max_regrets, vec![Some(300.0), Some(100.0), Some(50.0)]);
+        // The nearshore pair's small regret covers only one scenario, so it cannot be chosen
+        assert_eq!(results.minimax_regret_pair, Some(1));
+
+        let empty = ScenarioResults::new(vec!["Empty".to_string()], vec!["Only".to_string()], vec![vec![None]]);
+        assert_eq!((empty.winners[0], empty.max_regrets[0], empty.minimax_regret_pair), (None, None, None));
+    }
+
+    #[test]
+    fn test_perturbations_scale_only_their_input() {
+        let (params, pair) = two_product_scenario();
+        let scaled = |parameter: Parameter| {
+            let (mut params, mut pair) = (params.clone(), pair.clone());
+            parameter.apply(&mut params, &mut pair, 1.2);
+            (params, pair)
+        };
+
+        let (changed, _) = scaled(Parameter::HoldingCost { product_id: ProductId(1) });
+        assert!((changed.products[1].monthly_holding_cost - params.products[1].monthly_holding_cost * 1.2).abs() < 1e-9);
+        assert_eq!(changed.products[0], params.products[0]);
+
+        let (changed, _) = scaled(Parameter::DemandMean { product_id: ProductId(0) });
+        assert!((changed.demand_params[0].actual_mean_demand - params.demand_params[0].actual_mean_demand * 1.2).abs() < 1e-9);
+        // The forecast the plan was made on stays put
+        assert_eq!(changed.demand_params[0].mean_demand, params.demand_params[0].mean_demand);
+
+        let surge_id = pair.surge_supplier.id;
+        let (_, changed) = scaled(Parameter::UnitCost { supplier_id: surge_id });
+        for (product_id, cost) in &changed.surge_supplier.unit_costs {
+            assert!((cost - pair.surge_supplier.unit_costs[product_id] * 1.2).abs() < 1e-9);
+        }
+        assert_eq!(changed.base_supplier, pair.base_supplier);
+
+        // Unknown products and suppliers change nothing
+        let (changed_params, changed_pair) = scaled(Parameter::SellingPrice { product_id: ProductId(99) });
+        assert_eq!((changed_params.products, changed_pair.base_supplier), (params.products.clone(), pair.base_supplier.clone()));
+        assert_eq!(Parameter::UnitCost { supplier_id: SupplierId(99) }.label(&params, &[]), "Supplier 99 unit cost");
+    }
+
+    #[test]
+    fn test_zero_perturbation_leaves_profit_unchanged() {
+        let (params, pair) = two_product_scenario();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let config = MonteCarloConfig { master_seed: 12, ..MonteCarloConfig::new(20) };
+        let suppliers = [pair.base_supplier.clone(), pair.surge_supplier.clone()];
+        let perturbations: Vec<Perturbation> = default_perturbations(&params, &pair).into_iter()
+            .map(|perturbation| Perturbation { fraction: 0.0, ..perturbation })
+            .collect();
+
+        // Matched seeds make the unperturbed runs identical
+        for result in sensitivity(&params, &suppliers, &pair, &monthly_order, &perturbations, &config).unwrap() {
+            assert_eq!((result.low_delta, result.high_delta), (0.0, 0.0), "{}", result.label);
+        }
+
+        // A higher selling price can only help a fixed plan
+        let price = [Perturbation { parameter: Parameter::SellingPrice { product_id: ProductId(0) }, fraction: 0.1 }];
+        let result = &sensitivity(&params, &suppliers, &pair, &monthly_order, &price, &config).unwrap()[0];
+        assert!(result.low_delta < 0.0 && result.high_delta > 0.0, "{:?}", result);
+        assert_eq!(result.label, "Widget selling price");
+    }
+
+    /// The fixture's Widget alone, its demand spread by `spread` of its mean and running 20%
+    /// above the forecast when it is spread at all
+    fn one_product(spread: f64) -> (SimulationParams, SupplierPair) {
+        let (mut params, pair) = two_product_scenario();
+        params.products.truncate(1);
+        params.demand_params.truncate(1);
+        let demand = &mut params.demand_params[0];
+        demand.std_dev_demand = spread * demand.mean_demand;
+        demand.actual_std_dev_demand = demand.std_dev_demand;
+        if spread > 0.0 {
+            demand.actual_mean_demand *= 1.2;
+        }
+        (params, pair)
+    }
+
+    /// Whether a mean difference is at least zero up to three standard errors
+    fn non_negative(comparison: &PairedComparison) -> bool {
+        comparison.mean_difference >= -3.0 * comparison.std_dev_difference / (comparison.num_simulations as f64).sqrt()
+    }
+
+    #[test]
+    fn test_foresight_and_order_changes_are_worth_something() {
+        let (params, pair) = one_product(0.3);
+        let value = evpi(&params, &pair, 20, 3).unwrap();
+        assert!(value.mean_difference > 0.0 && non_negative(&value), "{:?}", value);
+        assert!((value.mean_difference - (value.mean_profit_first - value.mean_profit_second)).abs() < 1e-6 * value.mean_profit_first);
+
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000)], &pair, &params);
+        let options = option_value_realized(&params, &pair, &monthly_order, 20, 3).unwrap();
+        assert!(non_negative(&options), "{:?}", options);
+        // Run on the same paths, the plan with its options makes what a Monte Carlo run of it does
+        let config = MonteCarloConfig { master_seed: 3, ..MonteCarloConfig::new(20) };
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+        assert!((options.mean_profit_first - stats.mean_profit).abs() < 1e-6 * stats.mean_profit.abs());
+    }
+
+    #[test]
+    fn test_certain_demand_leaves_nothing_to_learn_or_change() {
+        let (params, pair) = one_product(0.0);
+        // Only the optimizer's grid, its points 4.5% of the forecast apart, keeps its plan from
+        // ordering exactly the demand foresight orders
+        let value = evpi(&params, &pair, 5, 3).unwrap();
+        assert!(value.mean_difference >= 0.0 && value.mean_difference < 0.03 * value.mean_profit_second, "{:?}", value);
+        assert_eq!(value.std_dev_difference, 0.0);
+
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000)], &pair, &params);
+        let options = option_value_realized(&params, &pair, &monthly_order, 5, 3).unwrap();
+        assert_eq!(options.mean_difference, 0.0);
+    }
+
+    #[test]
+    fn test_surge_supplier_pays_off_when_demand_outruns_the_base_supplier() {
+        let config = MonteCarloConfig { master_seed: 3, ..MonteCarloConfig::new(20) };
+        // The fixture asks 9,000 units a month of a base supplier making 7,000, leaving the
+        // surge supplier to cover the rest
+        let (params, pair) = two_product_scenario();
+        let value = dual_source_value(&params, &pair, &config).unwrap();
+        assert_eq!(value.differences.len(), 20);
+        assert!((value.mean() - value.differences.iter().sum::<f64>() / 20.0).abs() < 1e-6 * value.mean().abs().max(1.0));
+        assert!(value.comparison.difference_ci_low > 0.0, "{:?}", value.comparison);
+        assert!(value.percentile(10.0) <= value.percentile(90.0));
+
+        // Widget alone fits the base supplier, and at a price of 150 the margin on units beyond
+        // it is worth a change fee: with demand known the surge supplier goes unused, its setup
+        // cost unpaid, and adds nothing
+        let surge_value = |spread: f64| {
+            let (mut params, pair) = one_product(spread);
+            params.products[0].selling_price = 150.0;
+            dual_source_value(&params, &pair, &config).unwrap()
+        };
+        assert_eq!(surge_value(0.0).mean(), 0.0);
+
+        // Demand running above the forecast is met through the surge supplier's short lead time,
+        // worth more the further demand can run
+        let (moderate, high) = (surge_value(0.5), surge_value(1.0));
+        assert!(high.comparison.difference_ci_low > 0.0, "{:?}", high.comparison);
+        assert!(0.0 < moderate.mean() && moderate.mean() < high.mean(), "{} then {}", moderate.mean(), high.mean());
+    }
+
+    fn plan(name: &str, base: u32, surge: u32) -> NamedPlan {
+        NamedPlan {
+            name: name.to_string(),
+            base: HashMap::from([("Widget".to_string(), base)]),
+            surge: HashMap::from([("Widget".to_string(), surge)]),
+        }
+    }
+
+    #[test]
+    fn test_plans_are_compared_with_the_optimizer_on_the_same_paths() {
+        let (params, pair) = one_product(0.3);
+        let config = MonteCarloConfig { master_seed: 5, ..MonteCarloConfig::new(20) };
+        let (_, optimized) = optimized_order(&params, &pair, optimizer_seed(5, &pair)).unwrap();
+        let plans = vec![
+            plan("Same as optimizer", optimized.total_base_quantity(), optimized.total_surge_quantity()),
+            plan("Last year", 5_000, 1_000),
+            plan("Nothing", 0, 0),
+        ];
+        let comparison = compare_plans(&params, &pair, plans, &config).unwrap();
+        let names: Vec<&str> = comparison.plans.iter().map(|plan| plan.name.as_str()).collect();
+        assert_eq!(names, [OPTIMIZER_PLAN, "Same as optimizer", "Last year", "Nothing"]);
+        let optimizer = &comparison.plans[0];
+        assert!(optimizer.versus_optimizer.is_none());
+
+        for plan in &comparison.plans[1..] {
+            let versus = plan.versus_optimizer.as_ref().unwrap();
+            assert_eq!(versus.num_simulations, 20);
+            assert!((versus.mean_profit_first - plan.mean_profit).abs() < 1e-6 * plan.mean_profit.abs().max(1.0));
+            assert!((versus.mean_difference - (plan.mean_profit - optimizer.mean_profit)).abs() < 1e-6 * optimizer.mean_profit);
+            assert!(plan.cvar_5 <= plan.mean_profit && (0.0..=1.0).contains(&plan.fill_rate));
+        }
+        // The optimizer's own quantities make its profit on every path
+        let same = comparison.plans[1].versus_optimizer.as_ref().unwrap();
+        assert_eq!((same.mean_difference, same.std_dev_difference), (0.0, 0.0));
+        assert!(!same.is_significant());
+        // Ordering nothing up front leaves the season to order changes, far short of the plan
+        let nothing = &comparison.plans[3];
+        assert!(nothing.fill_rate < optimizer.fill_rate);
+        assert!(nothing.versus_optimizer.as_ref().unwrap().difference_ci_high < 0.0);
+        assert!(nothing.versus_optimizer.as_ref().unwrap().is_significant());
+    }
+
+    #[test]
+    fn test_invalid_plans_are_refused_by_name_and_field() {
+        let (mut params, pair) = one_product(0.3);
+        let config = MonteCarloConfig::new(5);
+        let refusal = |params: &SimulationParams, plans: Vec<NamedPlan>| match compare_plans(params, &pair, plans, &config) {
+            Err(SimulationError::Scenario(ScenarioError::InvalidPlan { plan, field, .. })) => (plan, field),
+            other => panic!("expected an invalid plan, got {:?}", other.map(|comparison| comparison.plans.len())),
+        };
+
+        let mut unknown = plan("Typo", 5_000, 0);
+        unknown.surge.insert("Gizmo".to_string(), 100);
+        assert_eq!(refusal(&params, vec![unknown]), ("Typo".to_string(), "surge.Gizmo".to_string()));
+        assert_eq!(refusal(&params, vec![plan(OPTIMIZER_PLAN, 5_000, 0)]).1, "name");
+        assert_eq!(refusal(&params, vec![plan("Twice", 5_000, 0), plan("Twice", 6_000, 0)]).1, "name");
+        assert_eq!(refusal(&params, vec![plan(" ", 5_000, 0)]).1, "name");
+
+        params.capacity_policy = CapacityPolicy::Strict;
+        let (plan_name, field) = refusal(&params, vec![plan("Too much", 50_000, 0)]);
+        assert_eq!((plan_name.as_str(), field.as_str()), ("Too much", "base and surge"));
+    }
+}
@@ -1,7 +1,14 @@
-/// Capacity allocation module
-/// Handles shared capacity allocation between products for suppliers
+//! Supplier capacity: checking a plan against it, sharing it among products and capacity groups,
+//! and the ledger of what each supplier delivered against what it was asked for
 
-use crate::models::{MonthlyOrder, ProductOrder, SupplierPair};
+use std::fmt;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::models::{CapacityGroup, MonthlyOrder, ProductId, ProductOrder, Reservation, SimulationParams, Supplier, SupplierId, SupplierPair};
+use crate::simulation::month_name;
+
+/// Utilization above which a supplier has too little headroom to absorb a disruption
+pub const FRAGILE_UTILIZATION: f64 = 0.95;
 
 /// Error type for capacity constraint violations
 #[allow(dead_code)]
@@ -11,9 +18,24 @@ pub struct CapacityError {
     pub supplier_name: String,
     pub capacity: u32,
     pub requested: u32,
+    /// Month of the season the violation falls in, when it is one month's
+    pub month: Option<usize>,
 }
 
-/// Validates that total product orders don't exceed supplier capacity
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} asked for {} units", self.message, self.supplier_name, self.requested)?;
+        if let Some(month) = self.month {
+            write!(f, " in {}", month_name(month))?;
+        }
+        write!(f, " with {} available", self.capacity)
+    }
+}
+
+impl std::error::Error for CapacityError {}
+
+/// Validates that total product orders don't exceed supplier capacity, nor any product's orders
+/// what the suppliers can make of it, overtime up to each supplier's hard ceiling included
 #[allow(dead_code)]
 pub fn validate_capacity_constraint(
     order: &MonthlyOrder,
@@ -22,56 +44,744 @@ pub fn validate_capacity_constraint(
     let total_base = order.total_base_quantity();
     let total_surge = order.total_surge_quantity();
     
-    if total_base > pair.base_supplier.fixed_capacity {
+    let base_capacity: u32 = pair.committed_tier().map(Supplier::max_capacity).sum();
+    if total_base > base_capacity {
         return Err(CapacityError {
             message: "Base supplier capacity exceeded".to_string(),
             supplier_name: pair.base_supplier.name.clone(),
-            capacity: pair.base_supplier.fixed_capacity,
+            capacity: base_capacity,
             requested: total_base,
+            month: None,
         });
     }
     
-    if total_surge > pair.surge_supplier.fixed_capacity {
+    if total_surge > pair.surge_supplier.max_capacity() {
         return Err(CapacityError {
             message: "Surge supplier capacity exceeded".to_string(),
             supplier_name: pair.surge_supplier.name.clone(),
-            capacity: pair.surge_supplier.fixed_capacity,
+            capacity: pair.surge_supplier.max_capacity(),
             requested: total_surge,
+            month: None,
         });
     }
+
+    // A supplier splitting its capacity has to fit both tranches into it
+    if pair.shares_capacity() && total_base + total_surge > pair.base_supplier.max_capacity() {
+        return Err(CapacityError {
+            message: "Shared supplier capacity exceeded".to_string(),
+            supplier_name: pair.base_supplier.name.clone(),
+            capacity: pair.base_supplier.max_capacity(),
+            requested: total_base + total_surge,
+            month: None,
+        });
+    }
+    
+    // Each product within what the suppliers can make of it
+    for base_order in order.base_orders() {
+        let capacity: u32 = pair.committed_tier().map(|supplier| supplier.max_capacity_for(base_order.product_id)).sum();
+        if base_order.quantity > capacity {
+            return Err(CapacityError {
+                message: format!("Base supplier capacity for product {} exceeded", base_order.product_id),
+                supplier_name: pair.base_supplier.name.clone(),
+                capacity,
+                requested: base_order.quantity,
+                month: None,
+            });
+        }
+    }
+    for surge_order in order.surge_orders() {
+        let capacity = pair.surge_supplier.max_capacity_for(surge_order.product_id);
+        if surge_order.quantity > capacity {
+            return Err(CapacityError {
+                message: format!("Surge supplier capacity for product {} exceeded", surge_order.product_id),
+                supplier_name: pair.surge_supplier.name.clone(),
+                capacity,
+                requested: surge_order.quantity,
+                month: None,
+            });
+        }
+    }
+    if pair.shares_capacity() {
+        for base_order in order.base_orders() {
+            let capacity = pair.base_supplier.max_capacity_for(base_order.product_id);
+            let requested = base_order.quantity + order.surge_quantity_for(base_order.product_id);
+            if requested > capacity {
+                return Err(CapacityError {
+                    message: format!("Shared supplier capacity for product {} exceeded", base_order.product_id),
+                    supplier_name: pair.base_supplier.name.clone(),
+                    capacity,
+                    requested,
+                    month: None,
+                });
+            }
+        }
+    }
     
+    // Each capacity group within its limit, beside what the other suppliers in the role can make
+    // of its products; a supplier splitting its capacity has to fit both tranches into its groups
+    let base_units: Vec<(ProductId, u32)> = order.base_orders().iter()
+        .map(|base_order| {
+            let shared = if pair.shares_capacity() { order.surge_quantity_for(base_order.product_id) } else { 0 };
+            (base_order.product_id, base_order.quantity + shared)
+        })
+        .collect();
+    check_capacity_groups(pair.committed_tier(), &base_units)?;
+    if !pair.is_self_paired() {
+        let surge_units: Vec<(ProductId, u32)> = order.surge_orders().iter().map(|surge_order| (surge_order.product_id, surge_order.quantity)).collect();
+        check_capacity_groups([&pair.surge_supplier].into_iter().chain(pair.backup_supplier.as_ref()), &surge_units)?;
+    }
+
     Ok(())
 }
 
-/// Create an empty order for the given product IDs
+/// Each capacity group of `suppliers`, sharing orders in one role, with the most units of its
+/// products the suppliers can make together: the group's limit and the others' capacity for them
+fn group_capacities<'a>(suppliers: impl Iterator<Item = &'a Supplier> + Clone) -> Vec<(&'a Supplier, &'a CapacityGroup, u32)> {
+    suppliers.clone()
+        .flat_map(|supplier| supplier.capacity_groups.iter().map(move |group| (supplier, group)))
+        .map(|(supplier, group)| {
+            let others: u32 = suppliers.clone()
+                .filter(|other| other.id != supplier.id)
+                .map(|other| other.max_capacity_for_all(&group.product_ids))
+                .sum();
+            (supplier, group, group.limit + others)
+        })
+        .collect()
+}
+
+/// Validates that `units` of each product, as (product_id, units), fit every capacity group of
+/// the suppliers sharing them; the error names the group
+fn check_capacity_groups<'a>(suppliers: impl Iterator<Item = &'a Supplier> + Clone, units: &[(ProductId, u32)]) -> Result<(), CapacityError> {
+    for (supplier, group, capacity) in group_capacities(suppliers) {
+        let requested = group.units_of(units);
+        if requested > capacity {
+            return Err(CapacityError {
+                message: format!("Capacity group {} exceeded", group.name),
+                supplier_name: supplier.name.clone(),
+                capacity,
+                requested,
+                month: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Cut `requests` of each product so that, beside the units already `placed` with the same
+/// suppliers, they fit every capacity group of the suppliers sharing them: products take what
+/// their groups have left by descending priority, as in `allocate_by_priority`, and tied products
+/// short of room in a group share it by largest remainder
+pub fn fit_capacity_groups<'a>(
+    requests: &[(ProductId, u32)],
+    placed: &[(ProductId, u32)],
+    suppliers: impl Iterator<Item = &'a Supplier> + Clone,
+    priorities: &[(ProductId, f64)],
+) -> Vec<(ProductId, u32)> {
+    let groups = group_capacities(suppliers);
+    if groups.is_empty() {
+        return requests.to_vec();
+    }
+    let mut room: Vec<u32> = groups.iter().map(|(_, group, capacity)| capacity.saturating_sub(group.units_of(placed))).collect();
+    let priority = |index: usize| {
+        priorities.iter()
+            .find(|(product_id, _)| *product_id == requests[index].0)
+            .map_or(f64::NEG_INFINITY, |(_, priority)| *priority)
+    };
+    let mut ranked: Vec<usize> = (0..requests.len()).collect();
+    ranked.sort_by(|&a, &b| priority(b).total_cmp(&priority(a)));
+
+    let mut fitted: Vec<(ProductId, u32)> = requests.iter().map(|&(product_id, _)| (product_id, 0)).collect();
+    for tied in ranked.chunk_by(|&a, &b| priority(a) == priority(b)) {
+        let mut wanted: Vec<u32> = tied.iter().map(|&index| requests[index].1).collect();
+        // Cutting for one group never pushes another over, overlapping or not
+        for ((_, group, _), &room) in groups.iter().zip(&room) {
+            let members: Vec<usize> = (0..tied.len()).filter(|&member| group.contains(requests[tied[member]].0)).collect();
+            let shares = largest_remainder(&members.iter().map(|&member| wanted[member]).collect::<Vec<u32>>(), room);
+            for (&member, share) in members.iter().zip(shares) {
+                wanted[member] = share;
+            }
+        }
+        for (&index, units) in tied.iter().zip(wanted) {
+            fitted[index].1 = units;
+            for ((_, group, _), room) in groups.iter().zip(room.iter_mut()) {
+                if group.contains(requests[index].0) {
+                    *room -= units;
+                }
+            }
+        }
+    }
+    fitted
+}
+
+/// Validates that each of an order's capacity reservations is of one of the pair's suppliers,
+/// covers the `months` of the season and reserves no more than the supplier's capacity in a month
 #[allow(dead_code)]
-pub fn create_empty_order(product_ids: &[usize]) -> MonthlyOrder {
-    MonthlyOrder {
-        base_orders: product_ids.iter().map(|&id| ProductOrder { product_id: id, quantity: 0 }).collect(),
-        surge_orders: product_ids.iter().map(|&id| ProductOrder { product_id: id, quantity: 0 }).collect(),
+pub fn validate_reservations(order: &MonthlyOrder, pair: &SupplierPair, months: usize) -> Result<(), CapacityError> {
+    for reservation in &order.reservations {
+        let Some(supplier) = pair.suppliers().find(|supplier| supplier.id == reservation.supplier_id) else {
+            return Err(CapacityError {
+                message: "Reservation of a supplier outside the pair".to_string(),
+                supplier_name: format!("supplier {}", reservation.supplier_id),
+                capacity: 0,
+                requested: reservation.per_month.iter().copied().max().unwrap_or(0),
+                month: None,
+            });
+        };
+        if reservation.per_month.len() != months {
+            return Err(CapacityError {
+                message: format!("Reservation covers {} months of a {}-month season", reservation.per_month.len(), months),
+                supplier_name: supplier.name.clone(),
+                capacity: supplier.fixed_capacity,
+                requested: 0,
+                month: None,
+            });
+        }
+        if let Some((month, &units)) = reservation.per_month.iter().enumerate().find(|(_, &units)| units > supplier.max_capacity()) {
+            return Err(CapacityError {
+                message: "Reservation exceeds capacity".to_string(),
+                supplier_name: supplier.name.clone(),
+                capacity: supplier.max_capacity(),
+                requested: units,
+                month: Some(month),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Units an order asked of a supplier in a month beyond what it had left
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct CapacityShortfall {
+    pub supplier_id: SupplierId,
+    pub month: usize,
+    pub requested: u32,
+    /// Units the supplier took on, all it had left
+    pub reserved: u32,
+}
+
+impl CapacityShortfall {
+    /// The shortfall as an error naming the pair's supplier, for refusing the order behind it
+    pub fn to_error(&self, pair: &SupplierPair) -> CapacityError {
+        let supplier_name = pair.suppliers()
+            .find(|supplier| supplier.id == self.supplier_id)
+            .map_or_else(|| format!("supplier {}", self.supplier_id), |supplier| supplier.name.clone());
+        CapacityError {
+            message: "Capacity exceeded".to_string(),
+            supplier_name,
+            capacity: self.reserved,
+            requested: self.requested,
+            month: Some(self.month),
+        }
     }
 }
 
-/// Create an order with specified quantities for each product
+/// How much of one supplier's capacity a season used
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct CapacityUsage {
+    pub supplier_id: SupplierId,
+    /// Units the supplier delivered over the season
+    pub delivered: u32,
+    /// Its nominal capacity summed over the season, within any reservation and with any expansion
+    pub nominal_capacity: u32,
+    /// Months it delivered all of its nominal capacity, or more in overtime
+    pub months_at_capacity: usize,
+    /// Units orders asked of it beyond what it had left, summed over the season
+    pub shortfall: u32,
+}
+
+/// Each supplier's monthly capacity and how much of it orders have committed, month by month
+/// A supplier in two roles, like one splitting its capacity, has one capacity for both; a
+/// supplier working overtime has all of it, up to its hard ceiling
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CapacityLedger {
+    months: usize,
+    supplier_ids: Vec<SupplierId>,
+    /// Capacity of each supplier, in the order of `supplier_ids`, month after month
+    capacity: Vec<u32>,
+    /// Nominal capacity of each supplier, short of any overtime, laid out as `capacity`
+    nominal: Vec<u32>,
+    /// Units committed of each supplier, laid out as `capacity`
+    committed: Vec<u32>,
+    /// Units each supplier delivered, laid out as `capacity`
+    delivered: Vec<u32>,
+    shortfalls: Vec<CapacityShortfall>,
+    /// Suppliers whose ramp-up has started
+    ramped: Vec<SupplierId>,
+}
+
+impl CapacityLedger {
+    /// A ledger of every supplier in the pair over `months` months, nothing committed yet
+    pub fn for_pair(pair: &SupplierPair, months: usize) -> Self {
+        let mut supplier_ids = Vec::new();
+        let mut capacity = Vec::new();
+        let mut nominal = Vec::new();
+        for supplier in pair.suppliers() {
+            // A single source stands in as its own surge supplier without capacity; its own
+            // capacity is the one that counts
+            if !supplier_ids.contains(&supplier.id) {
+                supplier_ids.push(supplier.id);
+                capacity.extend(std::iter::repeat_n(supplier.max_capacity(), months));
+                nominal.extend(std::iter::repeat_n(supplier.fixed_capacity, months));
+            }
+        }
+        let slots = capacity.len();
+        CapacityLedger { months, supplier_ids, capacity, nominal, committed: vec![0; slots], delivered: vec![0; slots], shortfalls: Vec::new(), ramped: Vec::new() }
+    }
+
+    /// Hold a supplier to what a reservation holds of its capacity each month
+    pub fn limit_to(&mut self, reservation: &Reservation) {
+        for month in 0..self.months {
+            if let Some(slot) = self.slot(reservation.supplier_id, month) {
+                self.capacity[slot] = self.capacity[slot].min(reservation.units_in(month));
+                self.nominal[slot] = self.nominal[slot].min(reservation.units_in(month));
+            }
+        }
+    }
+
+    /// Scale a supplier's capacity in each month from `first_month` on by its ramp-up, `first_month`
+    /// coming `months_ordered` months after its first order; a ramp starts once, later calls
+    /// leaving it as it is
+    pub fn ramp_up(&mut self, supplier: &Supplier, first_month: usize, months_ordered: usize) {
+        if self.ramped.contains(&supplier.id) {
+            return;
+        }
+        self.ramped.push(supplier.id);
+        for month in first_month..self.months {
+            let fraction = supplier.ramp_fraction(months_ordered + month - first_month);
+            if let Some(slot) = self.slot(supplier.id, month) {
+                self.capacity[slot] = (self.capacity[slot] as f64 * fraction).round() as u32;
+                self.nominal[slot] = (self.nominal[slot] as f64 * fraction).round() as u32;
+            }
+        }
+    }
+
+    /// Add `extra` units to a supplier's capacity in every month from `first_month` on
+    pub fn expand(&mut self, supplier_id: SupplierId, first_month: usize, extra: u32) {
+        for month in first_month..self.months {
+            if let Some(slot) = self.slot(supplier_id, month) {
+                self.capacity[slot] += extra;
+                self.nominal[slot] += extra;
+            }
+        }
+    }
+
+    /// Position of a supplier's month in `capacity` and `committed`, if the ledger covers it
+    fn slot(&self, supplier_id: SupplierId, month: usize) -> Option<usize> {
+        let index = self.supplier_ids.iter().position(|id| *id == supplier_id)?;
+        (month < self.months).then_some(index * self.months + month)
+    }
+
+    /// A supplier's capacity in a month, within any reservation it is held to
+    #[allow(dead_code)]
+    pub fn capacity(&self, supplier_id: SupplierId, month: usize) -> u32 {
+        self.slot(supplier_id, month).map_or(0, |slot| self.capacity[slot])
+    }
+
+    /// Units committed of a supplier's capacity in a month
+    #[allow(dead_code)]
+    pub fn committed(&self, supplier_id: SupplierId, month: usize) -> u32 {
+        self.slot(supplier_id, month).map_or(0, |slot| self.committed[slot])
+    }
+
+    /// Units of a supplier's capacity still free in a month; none outside the ledger
+    pub fn available(&self, supplier_id: SupplierId, month: usize) -> u32 {
+        self.slot(supplier_id, month)
+            .map_or(0, |slot| self.capacity[slot].saturating_sub(self.committed[slot]))
+    }
+
+    /// Commit `quantity` units of a supplier's capacity in a month, or nothing if it has fewer left
+    pub fn try_reserve(&mut self, supplier_id: SupplierId, month: usize, quantity: u32) -> Result<(), CapacityError> {
+        let available = self.available(supplier_id, month);
+        if quantity > available {
+            return Err(CapacityError {
+                message: "Capacity exceeded".to_string(),
+                supplier_name: format!("supplier {}", supplier_id),
+                capacity: available,
+                requested: quantity,
+                month: Some(month),
+            });
+        }
+        self.take_available(supplier_id, month, quantity);
+        Ok(())
+    }
+
+    /// Commit as much of `quantity` as the supplier has left in a month and return the units
+    /// committed, leaving the rest for another supplier
+    pub fn take_available(&mut self, supplier_id: SupplierId, month: usize, quantity: u32) -> u32 {
+        let reserved = quantity.min(self.available(supplier_id, month));
+        if let Some(slot) = self.slot(supplier_id, month) {
+            self.committed[slot] += reserved;
+        }
+        reserved
+    }
+
+    /// Commit as much of `quantity` as the supplier has left in a month, recording any shortfall
+    /// in place of one recorded earlier for that supplier and month, and return the units committed
+    pub fn reserve_up_to(&mut self, supplier_id: SupplierId, month: usize, quantity: u32) -> u32 {
+        let reserved = self.take_available(supplier_id, month, quantity);
+        if reserved < quantity {
+            self.shortfalls.retain(|shortfall| (shortfall.supplier_id, shortfall.month) != (supplier_id, month));
+            self.shortfalls.push(CapacityShortfall { supplier_id, month, requested: quantity, reserved });
+        }
+        reserved
+    }
+
+    /// Hand back `quantity` committed units of a supplier's capacity in a month
+    pub fn release(&mut self, supplier_id: SupplierId, month: usize, quantity: u32) {
+        if let Some(slot) = self.slot(supplier_id, month) {
+            self.committed[slot] = self.committed[slot].saturating_sub(quantity);
+        }
+    }
+
+    /// Each supplier and month an order asked more of than it had left, the latest ask for each
+    #[allow(dead_code)]
+    pub fn shortfalls(&self) -> &[CapacityShortfall] {
+        &self.shortfalls
+    }
+
+    /// Drop the shortfalls of every month from `first_month` on, whose commitments are being
+    /// made afresh
+    pub fn forget_shortfalls_from(&mut self, first_month: usize) {
+        self.shortfalls.retain(|shortfall| shortfall.month < first_month);
+    }
+
+    /// Record what each supplier delivered in a month, `delivered_by` its units by supplier ID
+    pub fn record_deliveries(&mut self, month: usize, delivered_by: impl Fn(SupplierId) -> u32) {
+        if month >= self.months {
+            return;
+        }
+        for (index, &supplier_id) in self.supplier_ids.iter().enumerate() {
+            self.delivered[index * self.months + month] = delivered_by(supplier_id);
+        }
+    }
+
+    /// How much of each supplier's capacity the season used, in the pair's order
+    pub fn usage(&self) -> Vec<CapacityUsage> {
+        self.supplier_ids.iter().enumerate()
+            .map(|(index, &supplier_id)| {
+                let slots = index * self.months..(index + 1) * self.months;
+                CapacityUsage {
+                    supplier_id,
+                    delivered: self.delivered[slots.clone()].iter().sum(),
+                    nominal_capacity: self.nominal[slots.clone()].iter().sum(),
+                    months_at_capacity: slots.filter(|&slot| self.nominal[slot] > 0 && self.delivered[slot] >= self.nominal[slot]).count(),
+                    shortfall: self.shortfalls.iter()
+                        .filter(|shortfall| shortfall.supplier_id == supplier_id)
+                        .map(|shortfall| shortfall.requested - shortfall.reserved)
+                        .sum(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Share `capacity` among `requests` in proportion to them, by largest remainder: each takes the
+/// whole units of its share, and the units left over go one apiece to the largest fractions,
+/// earlier requests first on a tie, so the shares add up to exactly the capacity; requests that
+/// all fit are granted in full
+pub fn largest_remainder(requests: &[u32], capacity: u32) -> Vec<u32> {
+    let total: u64 = requests.iter().map(|&units| units as u64).sum();
+    if total <= capacity as u64 {
+        return requests.to_vec();
+    }
+    let scaled = |units: u32| units as u64 * capacity as u64;
+    let mut shares: Vec<u32> = requests.iter().map(|&units| (scaled(units) / total) as u32).collect();
+    let mut by_remainder: Vec<usize> = (0..requests.len()).collect();
+    by_remainder.sort_by_key(|&index| std::cmp::Reverse(scaled(requests[index]) % total));
+    let leftover = capacity - shares.iter().sum::<u32>();
+    for index in by_remainder.into_iter().take(leftover as usize) {
+        shares[index] += 1;
+    }
+    shares
+}
+
+/// Scale orders down to `capacity` in proportion to their quantities, by largest remainder, so
+/// they add up to exactly the capacity; orders that fit are left as they are
+pub fn scale_to_capacity(orders: &[ProductOrder], capacity: u32) -> Vec<ProductOrder> {
+    let quantities: Vec<u32> = orders.iter().map(|order| order.quantity).collect();
+    orders.iter().zip(largest_remainder(&quantities, capacity))
+        .map(|(order, quantity)| ProductOrder { product_id: order.product_id, quantity })
+        .collect()
+}
+
+/// Fill product requests from `capacity` by descending priority, typically unit margin, until it
+/// runs out; requests of equal priority that do not all fit share what is left by largest
+/// remainder, so every unit of capacity goes to a request that wants it. Products without a
+/// priority come last, and the orders come back in the order requested
+pub fn allocate_by_priority(requests: &[(ProductId, u32)], capacity: u32, priorities: &[(ProductId, f64)]) -> Vec<ProductOrder> {
+    let priority = |index: usize| {
+        priorities.iter()
+            .find(|(product_id, _)| *product_id == requests[index].0)
+            .map_or(f64::NEG_INFINITY, |(_, priority)| *priority)
+    };
+    // Stable, so requests of equal priority keep their order
+    let mut ranked: Vec<usize> = (0..requests.len()).collect();
+    ranked.sort_by(|&a, &b| priority(b).total_cmp(&priority(a)));
+
+    let mut granted = vec![0; requests.len()];
+    let mut capacity_left = capacity;
+    for tied in ranked.chunk_by(|&a, &b| priority(a) == priority(b)) {
+        let wanted: Vec<u32> = tied.iter().map(|&index| requests[index].1).collect();
+        for (&index, units) in tied.iter().zip(largest_remainder(&wanted, capacity_left)) {
+            granted[index] = units;
+            capacity_left -= units;
+        }
+    }
+    requests.iter().zip(granted)
+        .map(|(&(product_id, _), quantity)| ProductOrder { product_id, quantity })
+        .collect()
+}
+
+/// Each product's selling price less the supplier's unit cost of it
+pub fn unit_margins(params: &SimulationParams, supplier: &Supplier) -> Vec<(ProductId, f64)> {
+    params.products.iter()
+        .map(|product| (product.id, product.selling_price - supplier.unit_costs.get(&product.id).copied().unwrap_or(0.0)))
+        .collect()
+}
+
+/// Each product's selling price less the supplier's unit cost of it in its first overtime tier
+pub fn overtime_margins(params: &SimulationParams, supplier: &Supplier) -> Vec<(ProductId, f64)> {
+    let multiplier = supplier.soft_capacity_tiers.first().map_or(1.0, |(_, multiplier)| *multiplier);
+    params.products.iter()
+        .map(|product| (product.id, product.selling_price - multiplier * supplier.unit_costs.get(&product.id).copied().unwrap_or(0.0)))
+        .collect()
+}
+
+/// Place what `order` leaves of `product_quantities` in the first overtime tier of the pair's
+/// suppliers: the committed tier's as base orders, then the surge supplier's as surge orders.
+/// Only products that still make a margin at a supplier's premium go to it, the highest margin
+/// first, and each supplier's room is what its orders leave of the tier and of its capacity groups
+pub fn plan_first_tier_overtime(params: &SimulationParams, pair: &SupplierPair, order: &mut MonthlyOrder, product_quantities: &[(ProductId, u32)]) {
+    let overtime = |supplier: &Supplier| supplier.first_tier_capacity() - supplier.fixed_capacity;
+    let overtime_for = |supplier: &Supplier, product_id: ProductId| supplier.first_tier_capacity_for(product_id) - supplier.capacity_for(product_id);
+    let nominal_used_beyond = |units: u32, capacity: u32| units.saturating_sub(capacity);
+
+    // A supplier splitting its capacity works its overtime for both tranches
+    let shared_surge = if pair.shares_capacity() { order.total_surge_quantity() } else { 0 };
+    let base_room = pair.committed_tier().map(overtime).sum::<u32>()
+        .saturating_sub(nominal_used_beyond(order.total_base_quantity() + shared_surge, pair.base_capacity()));
+    let margins = overtime_margins(params, &pair.base_supplier);
+    let requests = overtime_requests(order, product_quantities, &margins, |product_id| pair.committed_tier().map(|supplier| overtime_for(supplier, product_id)).sum());
+    let placed: Vec<(ProductId, u32)> = order.base_orders().iter()
+        .map(|base_order| (base_order.product_id, base_order.quantity + if pair.shares_capacity() { order.surge_quantity_for(base_order.product_id) } else { 0 }))
+        .collect();
+    let requests = fit_capacity_groups(&requests, &placed, pair.committed_tier(), &margins);
+    for placed in allocate_by_priority(&requests, base_room, &margins) {
+        order.set_base_quantity(placed.product_id, order.base_quantity_for(placed.product_id) + placed.quantity);
+    }
+
+    if pair.is_self_paired() {
+        return;
+    }
+    // The backup's nominal capacity comes before the surge supplier's overtime
+    let surge_room = overtime(&pair.surge_supplier)
+        .saturating_sub(nominal_used_beyond(order.total_surge_quantity(), pair.surge_capacity()));
+    let margins = overtime_margins(params, &pair.surge_supplier);
+    let requests = overtime_requests(order, product_quantities, &margins, |product_id| overtime_for(&pair.surge_supplier, product_id));
+    let placed: Vec<(ProductId, u32)> = order.surge_orders().iter().map(|surge_order| (surge_order.product_id, surge_order.quantity)).collect();
+    let requests = fit_capacity_groups(&requests, &placed, pair.surge_tier(), &margins);
+    for placed in allocate_by_priority(&requests, surge_room, &margins) {
+        order.set_surge_quantity(placed.product_id, order.surge_quantity_for(placed.product_id) + placed.quantity);
+    }
+}
+
+/// Units of each product `order` leaves of `product_quantities` that a supplier could make in
+/// overtime, `room_for` of the product at most; none of products without a margin there
+fn overtime_requests(order: &MonthlyOrder, product_quantities: &[(ProductId, u32)], margins: &[(ProductId, f64)], room_for: impl Fn(ProductId) -> u32) -> Vec<(ProductId, u32)> {
+    product_quantities.iter()
+        .map(|&(product_id, quantity)| {
+            let profitable = margins.iter().any(|(id, margin)| *id == product_id && *margin > 0.0);
+            let unplaced = quantity.saturating_sub(order.base_quantity_for(product_id) + order.surge_quantity_for(product_id));
+            (product_id, if profitable { unplaced.min(room_for(product_id)) } else { 0 })
+        })
+        .collect()
+}
+
+/// Create an empty order for the given product IDs, each listed once
+#[allow(dead_code)]
+pub fn create_empty_order(product_ids: &[ProductId]) -> MonthlyOrder {
+    create_order(
+        product_ids.iter().map(|&id| (id, 0)).collect(),
+        product_ids.iter().map(|&id| (id, 0)).collect(),
+    )
+}
+
+/// Create an order with specified quantities for each product, each listed once on each side
 #[allow(dead_code)]
 pub fn create_order(
-    base_quantities: Vec<(usize, u32)>,  // (product_id, quantity)
-    surge_quantities: Vec<(usize, u32)>, // (product_id, quantity)
+    base_quantities: Vec<(ProductId, u32)>,  // (product_id, quantity)
+    surge_quantities: Vec<(ProductId, u32)>, // (product_id, quantity)
 ) -> MonthlyOrder {
-    MonthlyOrder {
-        base_orders: base_quantities.into_iter()
-            .map(|(id, qty)| ProductOrder { product_id: id, quantity: qty })
-            .collect(),
-        surge_orders: surge_quantities.into_iter()
-            .map(|(id, qty)| ProductOrder { product_id: id, quantity: qty })
-            .collect(),
-    }
+    let orders = |quantities: Vec<(ProductId, u32)>| quantities.into_iter()
+        .map(|(id, qty)| ProductOrder { product_id: id, quantity: qty })
+        .collect();
+    MonthlyOrder::new(orders(base_quantities), orders(surge_quantities)).expect("each product listed once on each side")
+}
+
+/// Share of `capacity` taken by `units` a month; a supplier without capacity is unused
+pub fn utilization(units: f64, capacity: u32) -> f64 {
+    if capacity == 0 { 0.0 } else { units / capacity as f64 }
+}
+
+/// Planned monthly utilization of the committed tier and the surge supplier under `order`
+#[allow(dead_code)]
+pub fn planned_utilization(order: &MonthlyOrder, pair: &SupplierPair) -> (f64, f64) {
+    (
+        utilization(order.total_base_quantity() as f64, pair.base_capacity()),
+        utilization(order.total_surge_quantity() as f64, pair.surge_supplier.fixed_capacity),
+    )
+}
+
+/// Whether a supplier run at `utilization` is fragile: any disruption leaves orders unmet
+pub fn is_fragile(utilization: f64) -> bool {
+    utilization > FRAGILE_UTILIZATION
 }
 
 /// Calculate remaining capacity after order allocation
+/// A supplier splitting its capacity has the same capacity left in either role
 #[allow(dead_code)]
 pub fn remaining_capacity(order: &MonthlyOrder, pair: &SupplierPair) -> (u32, u32) {
-    let base_remaining = pair.base_supplier.fixed_capacity.saturating_sub(order.total_base_quantity());
+    if pair.shares_capacity() {
+        let remaining = pair.base_supplier.fixed_capacity.saturating_sub(order.total_base_quantity() + order.total_surge_quantity());
+        return (remaining, remaining);
+    }
+    let base_remaining = pair.base_capacity().saturating_sub(order.total_base_quantity());
     let surge_remaining = pair.surge_supplier.fixed_capacity.saturating_sub(order.total_surge_quantity());
     (base_remaining, surge_remaining)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quantities(orders: &[ProductOrder]) -> Vec<u32> {
+        orders.iter().map(|order| order.quantity).collect()
+    }
+
+    #[test]
+    fn test_highest_margin_fills_first() {
+        let requests = [(ProductId(0), 4_000), (ProductId(1), 3_000), (ProductId(2), 2_000)];
+        let priorities = [(ProductId(0), 10.0), (ProductId(1), 30.0), (ProductId(2), 20.0)];
+        let orders = allocate_by_priority(&requests, 4_000, &priorities);
+        assert_eq!(orders.iter().map(|order| order.product_id).collect::<Vec<_>>(), vec![ProductId(0), ProductId(1), ProductId(2)]);
+        assert_eq!(quantities(&orders), vec![0, 3_000, 1_000]);
+
+        // Requests that fit exactly are granted in full, and a product without a priority is last
+        assert_eq!(quantities(&allocate_by_priority(&requests, 9_000, &priorities)), vec![4_000, 3_000, 2_000]);
+        assert_eq!(quantities(&allocate_by_priority(&requests, 7_000, &priorities[1..])), vec![2_000, 3_000, 2_000]);
+        assert_eq!(quantities(&allocate_by_priority(&requests, 0, &priorities)), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn test_ties_share_in_proportion() {
+        // The two tied products split what the first leaves 2:1, the odd unit to the larger share
+        let requests = [(ProductId(0), 1_000), (ProductId(1), 2_000), (ProductId(2), 1_000)];
+        let priorities = [(ProductId(0), 20.0), (ProductId(1), 20.0), (ProductId(2), 25.0)];
+        assert_eq!(quantities(&allocate_by_priority(&requests, 2_501, &priorities)), vec![500, 1_001, 1_000]);
+        // Equal requests, equal remainders: the earlier request takes the odd unit
+        assert_eq!(largest_remainder(&[151, 151, 151], 100), vec![34, 33, 33]);
+    }
+
+    #[test]
+    fn test_scaling_down_uses_every_unit() {
+        let orders = |quantities: &[u32]| -> Vec<ProductOrder> {
+            quantities.iter().enumerate().map(|(product_id, &quantity)| ProductOrder { product_id: ProductId(product_id as u32), quantity }).collect()
+        };
+        // A third of 100 each is 33.3; truncating would leave a unit unused
+        assert_eq!(quantities(&scale_to_capacity(&orders(&[151, 151, 151]), 100)), vec![34, 33, 33]);
+        assert_eq!(quantities(&scale_to_capacity(&orders(&[2_000, 1_333, 667]), 3_001)), vec![1_501, 1_000, 500]);
+        // Truncating would scale both single units to none with one unit free
+        assert_eq!(quantities(&scale_to_capacity(&orders(&[1, 1]), 1)), vec![1, 0]);
+        assert_eq!(quantities(&scale_to_capacity(&orders(&[700, 300]), 5_000)), vec![700, 300]);
+        for capacity in 0..=453 {
+            let scaled = scale_to_capacity(&orders(&[151, 151, 151]), capacity);
+            assert_eq!(scaled.iter().map(|order| order.quantity).sum::<u32>(), capacity);
+            assert!(scaled.iter().all(|order| order.quantity >= capacity / 3), "capacity {}", capacity);
+        }
+    }
+
+    #[test]
+    fn test_reservations_must_fit_the_pair_and_season() {
+        use crate::models::{Reservation, ReservationProfile};
+
+        let (params, pair) = crate::monte_carlo::tests::two_product_scenario();
+        let months = params.season_months;
+        let mut order = create_order(vec![(ProductId(0), 6_000), (ProductId(1), 1_000)], vec![(ProductId(0), 0), (ProductId(1), 2_000)]);
+        order.reservations = vec![Reservation::with_profile(&pair.surge_supplier, ReservationProfile::FrontLoaded, 0.5, months)];
+        assert!(validate_reservations(&order, &pair, months).is_ok());
+
+        // One month short of the season
+        assert!(validate_reservations(&order, &pair, months + 1).is_err());
+        // More than the surge supplier's 5,000 in a month
+        order.reservations[0].per_month[2] = 5_001;
+        let error = validate_reservations(&order, &pair, months).unwrap_err();
+        assert_eq!((error.capacity, error.requested), (5_000, 5_001));
+        // A supplier outside the pair
+        order.reservations[0] = Reservation { supplier_id: SupplierId(9), per_month: vec![0; months], fee_per_unit: 1.0 };
+        assert!(validate_reservations(&order, &pair, months).is_err());
+    }
+
+    #[test]
+    fn test_overtime_is_costed_tier_by_tier_up_to_the_hard_ceiling() {
+        // 10,000 a month at the unit cost, 1,000 more at +10% and 1,500 beyond those at +25%
+        let (_, mut pair) = crate::monte_carlo::tests::two_product_scenario();
+        pair.base_supplier.fixed_capacity = 10_000;
+        pair.base_supplier.soft_capacity_tiers = vec![(0.10, 1.10), (0.25, 1.25)];
+        let supplier = &pair.base_supplier;
+        assert_eq!((supplier.first_tier_capacity(), supplier.max_capacity()), (11_000, 12_500));
+        let premium = |units: u32| units as f64 * (supplier.cost_multiplier(units) - 1.0);
+        assert_eq!(premium(10_000), 0.0);
+        assert!((premium(10_001) - 0.10).abs() < 1e-6);
+        assert!((premium(11_000) - 100.0).abs() < 1e-6);
+        assert!((premium(11_001) - 100.25).abs() < 1e-6);
+        assert!((premium(12_500) - 475.0).abs() < 1e-6);
+
+        // The last tier's limit is the ceiling plans are held to
+        let within = create_order(vec![(ProductId(0), 8_000), (ProductId(1), 4_500)], vec![(ProductId(0), 0), (ProductId(1), 0)]);
+        assert!(validate_capacity_constraint(&within, &pair).is_ok());
+        let over = create_order(vec![(ProductId(0), 8_000), (ProductId(1), 4_501)], vec![(ProductId(0), 0), (ProductId(1), 0)]);
+        let error = validate_capacity_constraint(&over, &pair).unwrap_err();
+        assert_eq!((error.capacity, error.requested), (12_500, 12_501));
+    }
+
+    #[test]
+    fn test_validation_names_the_capacity_group_exceeded() {
+        use crate::models::CapacityGroup;
+
+        // The base supplier's 7,000 a month go through a cutting line making 4,500 of both models,
+        // and Gadgets through a paint booth making 2,000 of them
+        let (_, mut pair) = crate::monte_carlo::tests::two_product_scenario();
+        pair.base_supplier.capacity_groups = vec![
+            CapacityGroup { name: "Cutting line".to_string(), product_ids: vec![ProductId(0), ProductId(1)], limit: 4_500 },
+            CapacityGroup { name: "Paint booth".to_string(), product_ids: vec![ProductId(1)], limit: 2_000 },
+        ];
+        let within = create_order(vec![(ProductId(0), 2_500), (ProductId(1), 2_000)], vec![(ProductId(0), 3_000), (ProductId(1), 0)]);
+        assert!(validate_capacity_constraint(&within, &pair).is_ok());
+
+        let over_line = create_order(vec![(ProductId(0), 3_000), (ProductId(1), 1_800)], vec![(ProductId(0), 0), (ProductId(1), 0)]);
+        let error = validate_capacity_constraint(&over_line, &pair).unwrap_err();
+        assert_eq!((error.message.as_str(), error.capacity, error.requested), ("Capacity group Cutting line exceeded", 4_500, 4_800));
+        let over_booth = create_order(vec![(ProductId(0), 1_000), (ProductId(1), 2_500)], vec![(ProductId(0), 0), (ProductId(1), 0)]);
+        let error = validate_capacity_constraint(&over_booth, &pair).unwrap_err();
+        assert_eq!((error.message.as_str(), error.capacity, error.requested), ("Capacity group Paint booth exceeded", 2_000, 2_500));
+
+        // Fitting the orders cuts the lower margin first, and cutting for the line leaves the booth
+        // within its limit
+        let fitted = fit_capacity_groups(&[(ProductId(0), 3_000), (ProductId(1), 2_500)], &[], pair.committed_tier(), &[(ProductId(0), 10.0), (ProductId(1), 20.0)]);
+        assert_eq!(fitted, [(ProductId(0), 2_500), (ProductId(1), 2_000)]);
+    }
+
+    #[test]
+    fn test_no_units_are_lost() {
+        let requests: Vec<(ProductId, u32)> = (0..7).map(|id| (ProductId(id), 97 + 13 * id)).collect();
+        let priorities: Vec<(ProductId, f64)> = requests.iter().map(|&(product_id, _)| (product_id, (product_id.0 % 3) as f64)).collect();
+        let wanted: u32 = requests.iter().map(|&(_, units)| units).sum();
+        for capacity in (0..=wanted + 50).step_by(7) {
+            let orders = allocate_by_priority(&requests, capacity, &priorities);
+            assert_eq!(orders.iter().map(|order| order.quantity).sum::<u32>(), capacity.min(wanted), "capacity {}", capacity);
+            assert!(orders.iter().zip(&requests).all(|(order, &(_, units))| order.quantity <= units));
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! SVG charts of Monte Carlo results for the HTML report (`charts` feature)
+//! Each chart is rendered with plotters into a self-contained SVG string
+
+use std::error::Error;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::models::{MonteCarloStats, ReplicationTrace};
+
+/// Size of every chart in pixels
+const CHART_SIZE: (u32, u32) = (800, 400);
+
+type ChartResult<T> = Result<T, Box<dyn Error>>;
+
+/// Render a chart drawn by `draw` on a white canvas and return the SVG
+fn render(draw: impl FnOnce(&DrawingArea<SVGBackend, Shift>) -> ChartResult<()>) -> ChartResult<String> {
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, CHART_SIZE).into_drawing_area();
+        root.fill(&WHITE)?;
+        draw(&root)?;
+        root.present()?;
+    }
+    Ok(svg)
+}
+
+/// Histogram of a pair's replication profits
+pub(crate) fn profit_histogram(stats: &MonteCarloStats) -> ChartResult<String> {
+    render(|root| {
+        let low = stats.histogram.first().map_or(stats.min_profit, |bin| bin.0);
+        let high = stats.histogram.last().map_or(stats.max_profit, |bin| bin.1).max(low + 1.0);
+        let tallest = stats.histogram.iter().map(|bin| bin.2).max().unwrap_or(0);
+
+        let mut chart = ChartBuilder::on(root)
+            .caption(format!("{}: profit distribution", stats.pair_name()), ("sans-serif", 20))
+            .margin(15)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d(low..high, 0..tallest + 1)?;
+        chart.configure_mesh()
+            .x_desc("Season profit")
+            .y_desc("Runs")
+            .x_label_formatter(&|profit| format_millions(*profit))
+            .draw()?;
+        chart.draw_series(stats.histogram.iter().map(|&(bin_low, bin_high, count)| {
+            Rectangle::new([(bin_low, 0), (bin_high, count)], BLUE.mix(0.6).filled())
+        }))?;
+        Ok(())
+    })
+}
+
+/// Box chart comparing pairs: whiskers span the minimum and maximum profit, the box the
+/// quartiles (or the 10th to 90th percentiles when quartiles were not computed), and the bar
+/// across it the median
+pub(crate) fn percentile_boxes(results: &[MonteCarloStats]) -> ChartResult<String> {
+    render(|root| {
+        let low = results.iter().map(|stats| stats.min_profit).fold(f64::INFINITY, f64::min);
+        let high = results.iter().map(|stats| stats.max_profit).fold(f64::NEG_INFINITY, f64::max);
+        let (low, high) = if low < high { (low, high) } else { (low - 1.0, low + 1.0) };
+        let labels: Vec<String> = results.iter()
+            .map(|stats| stats.pair_name())
+            .collect();
+
+        let mut chart = ChartBuilder::on(root)
+            .caption("Profit percentiles by pair", ("sans-serif", 20))
+            .margin(15)
+            .x_label_area_size(40)
+            .y_label_area_size(70)
+            .build_cartesian_2d(-0.5..results.len() as f64 - 0.5, low..high)?;
+        chart.configure_mesh()
+            .disable_x_mesh()
+            .x_labels(results.len())
+            .x_label_formatter(&|x| {
+                let index = x.round();
+                if (x - index).abs() < 1e-6 && index >= 0.0 {
+                    labels.get(index as usize).cloned().unwrap_or_default()
+                } else {
+                    String::new()
+                }
+            })
+            .y_desc("Season profit")
+            .y_label_formatter(&|profit| format_millions(*profit))
+            .draw()?;
+
+        for (index, stats) in results.iter().enumerate() {
+            let x = index as f64;
+            let (box_low, box_high) = match (stats.percentile(25.0), stats.percentile(75.0)) {
+                (Some(q1), Some(q3)) => (q1, q3),
+                _ => (stats.p10().unwrap_or(stats.min_profit), stats.p90().unwrap_or(stats.max_profit)),
+            };
+            let median = stats.p50().unwrap_or(stats.mean_profit);
+            chart.draw_series(std::iter::once(PathElement::new(
+                vec![(x, stats.min_profit), (x, stats.max_profit)],
+                BLACK,
+            )))?;
+            chart.draw_series(std::iter::once(Rectangle::new(
+                [(x - 0.25, box_low), (x + 0.25, box_high)],
+                BLUE.mix(0.4).filled(),
+            )))?;
+            chart.draw_series(std::iter::once(PathElement::new(
+                vec![(x - 0.25, median), (x + 0.25, median)],
+                ShapeStyle::from(&BLACK).stroke_width(2),
+            )))?;
+        }
+        Ok(())
+    })
+}
+
+/// Line chart of a pair's mean profit in each month of the season
+pub(crate) fn monthly_profit(stats: &MonteCarloStats) -> ChartResult<String> {
+    let profits: Vec<f64> = stats.monthly_breakdown.iter().map(|month| month.mean_profit).collect();
+    let months: Vec<String> = stats.monthly_breakdown.iter().map(|month| month.month.clone()).collect();
+    render(|root| {
+        let low = profits.iter().copied().fold(0.0, f64::min);
+        let high = profits.iter().copied().fold(1.0, f64::max);
+        let mut chart = ChartBuilder::on(root)
+            .caption("Mean profit by month", ("sans-serif", 20))
+            .margin(15)
+            .x_label_area_size(40)
+            .y_label_area_size(70)
+            .build_cartesian_2d(0..months.len().saturating_sub(1).max(1), low..high * 1.05)?;
+        chart.configure_mesh()
+            .x_labels(months.len())
+            .x_label_formatter(&|month| months.get(*month).cloned().unwrap_or_default())
+            .y_desc("Profit")
+            .y_label_formatter(&|profit| format_millions(*profit))
+            .draw()?;
+        chart.draw_series(LineSeries::new(profits.iter().copied().enumerate(), BLUE.stroke_width(2)))?;
+        Ok(())
+    })
+}
+
+/// Line chart of the stock left at the end of each month, summed over products, in the
+/// worst and best simulated seasons
+pub(crate) fn season_inventory(worst: &ReplicationTrace, best: &ReplicationTrace) -> ChartResult<String> {
+    let inventory = |trace: &ReplicationTrace| -> Vec<u32> {
+        trace.monthly_results.iter()
+            .map(|month| month.product_results.iter().map(|result| result.inventory_end).sum())
+            .collect()
+    };
+    let (worst_inventory, best_inventory) = (inventory(worst), inventory(best));
+    let months: Vec<String> = worst.monthly_results.iter().map(|month| month.month.clone()).collect();
+    render(|root| {
+        let highest = worst_inventory.iter().chain(&best_inventory).copied().max().unwrap_or(0).max(1);
+        let mut chart = ChartBuilder::on(root)
+            .caption("End-of-month inventory, worst and best season", ("sans-serif", 20))
+            .margin(15)
+            .x_label_area_size(40)
+            .y_label_area_size(70)
+            .build_cartesian_2d(0..months.len().saturating_sub(1).max(1), 0..highest + highest / 20)?;
+        chart.configure_mesh()
+            .x_labels(months.len())
+            .x_label_formatter(&|month| months.get(*month).cloned().unwrap_or_default())
+            .y_desc("Units")
+            .draw()?;
+        chart.draw_series(LineSeries::new(worst_inventory.iter().copied().enumerate(), RED.stroke_width(2)))?
+            .label("Worst season")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+        chart.draw_series(LineSeries::new(best_inventory.iter().copied().enumerate(), GREEN.stroke_width(2)))?
+            .label("Best season")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], GREEN));
+        chart.configure_series_labels().background_style(WHITE.mix(0.8)).border_style(BLACK).draw()?;
+        Ok(())
+    })
+}
+
+/// Axis label for an amount of money, in millions once it reaches a million
+/// e.g. $1.25M, -$36.33M or $850k
+fn format_millions(amount: f64) -> String {
+    let sign = if amount < 0.0 { "-" } else { "" };
+    let magnitude = amount.abs();
+    if magnitude >= 1e6 {
+        format!("{}${:.2}M", sign, magnitude / 1e6)
+    } else if magnitude >= 1e3 {
+        format!("{}${:.0}k", sign, magnitude / 1e3)
+    } else {
+        format!("{}${:.0}", sign, magnitude)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_axis_labels_use_millions() {
+        assert_eq!(format_millions(1_250_000.0), "$1.25M");
+        assert_eq!(format_millions(-36_333_363.0), "-$36.33M");
+        assert_eq!(format_millions(850_000.0), "$850k");
+        assert_eq!(format_millions(0.0), "$0");
+    }
+}
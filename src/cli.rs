@@ -0,0 +1,642 @@
+//! Command-line options for the simulator binary
+
+#[cfg(feature = "server")]
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use anstream::ColorChoice;
+use clap::{Parser, Subcommand};
+use supply_chain_sim::models::{CapacityPolicy, ExpansionOffer, RankingMetric, RegionalDisruption, TimeStep};
+use supply_chain_sim::monte_carlo::SampleRetention;
+use supply_chain_sim::pairing::{PairingRules, ScoringWeights};
+use supply_chain_sim::pairing_utils::ScreeningCriteria;
+use supply_chain_sim::reporting::{Formatter, OutputFormat, Verbosity};
+use supply_chain_sim::selection::SelectionMode;
+
+/// Multi-product supply chain simulation with real options on order changes
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Monte Carlo replications per supplier pair, or "auto" to run each pair until its
+    /// mean profit is known precisely
+    #[arg(long, default_value = "500")]
+    pub simulations: SimulationCount,
+
+    /// Evaluate supplier pairs one at a time instead of in parallel, with progress bars for
+    /// each pair's optimizer and simulation
+    #[arg(long)]
+    pub serial: bool,
+
+    /// How replications are spread over the pairs: fixed (--simulations each) or adaptive (a
+    /// shared budget spent where it best tells the best pair from the rest)
+    #[arg(long, default_value = "fixed")]
+    pub selection: SelectionMode,
+
+    /// Replications across all pairs for --selection adaptive [default: --simulations per pair]
+    #[arg(long, value_name = "N")]
+    pub selection_budget: Option<usize>,
+
+    /// Probability of having found the best pair at which --selection adaptive stops
+    #[arg(long, default_value = "0.95", value_name = "PROBABILITY")]
+    pub selection_confidence: f64,
+
+    /// Statistic to rank supplier pairs by: mean-profit, expected-shortfall, or
+    /// operational-profit (before setup costs and order change fees)
+    #[arg(long, default_value = "mean-profit")]
+    pub rank_by: RankingMetric,
+
+    /// Also evaluate each pair with a third, backup supplier that takes the surge orders the
+    /// surge supplier cannot deliver
+    #[arg(long)]
+    pub backup: bool,
+
+    /// Also evaluate each short-lead supplier as both base and surge supplier, splitting its
+    /// capacity between a committed base tranche and a flexible surge tranche
+    #[arg(long)]
+    pub split_capacity: bool,
+
+    /// Shortest lead time, in months, of a supplier used for base orders
+    #[arg(long, default_value = "1", value_name = "MONTHS")]
+    pub base_min_lead: usize,
+
+    /// Longest lead time, in months, of a supplier used for base orders (default: no limit)
+    #[arg(long, value_name = "MONTHS")]
+    pub base_max_lead: Option<usize>,
+
+    /// Longest lead time, in months, of a supplier used for surge or backup orders
+    #[arg(long, default_value = "1", value_name = "MONTHS")]
+    pub surge_max_lead: usize,
+
+    /// Only pair suppliers whose surge supplier delivers strictly sooner than the base supplier
+    #[arg(long)]
+    pub surge_shorter_than_base: bool,
+
+    /// Only pair suppliers that together make at least this many units a month
+    #[arg(long, value_name = "UNITS")]
+    pub min_combined_capacity: Option<u32>,
+
+    /// Only group suppliers from different regions
+    #[arg(long)]
+    pub distinct_regions: bool,
+
+    /// Most suppliers of a group that may share a risk class (default: no limit)
+    #[arg(long, value_name = "N")]
+    pub max_same_risk_class: Option<usize>,
+
+    /// Most the one-time setup costs of a strategy's suppliers may add up to (default: no cap)
+    #[arg(long, value_name = "AMOUNT")]
+    pub max_setup_cost: Option<f64>,
+
+    /// Also evaluate strategies pooling the committed capacity of several suppliers when no pair
+    /// can make this share of the expected monthly demand
+    #[arg(long, default_value = "0.7", value_name = "FRACTION")]
+    pub pooling_coverage: f64,
+
+    /// Pair only the N best-scored base candidates with the N best-scored surge candidates
+    /// (default: all of them)
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub shortlist: Option<usize>,
+
+    /// Weights of landed cost, capacity, lead time and setup cost in each supplier's score for
+    /// --shortlist, as COST,CAPACITY,LEAD,SETUP
+    #[arg(long, default_value = "0.4,0.3,0.15,0.15", value_name = "WEIGHTS")]
+    pub score_weights: ScoringWeights,
+
+    /// Chance each month that a disruption stops every supplier in a region, as
+    /// REGION=PROBABILITY, e.g. Overseas=0.05; repeat for more regions
+    #[arg(long, value_name = "REGION=PROBABILITY")]
+    pub regional_disruption: Vec<RegionalDisruption>,
+
+    /// What to do with a plan asking a supplier for more than it has in a month: clamp (deliver
+    /// what it has) or strict (refuse the plan)
+    #[arg(long, default_value = "clamp")]
+    pub capacity_policy: CapacityPolicy,
+
+    /// Period the season is simulated in: monthly, or weekly, which reacts to demand and adjusts
+    /// orders week by week; the scenario's figures stay monthly either way
+    #[arg(long, default_value = "monthly")]
+    pub time_step: TimeStep,
+
+    /// Annual cost of capital charged on the stock held at what it cost, e.g. 0.1 for 10%
+    #[arg(long, default_value = "0", value_name = "RATE")]
+    pub cost_of_capital: f64,
+
+    /// A supplier's offer of extra monthly capacity for a fee, taken in season if worth it, as
+    /// SUPPLIER_ID:DECIDE_BY:FROM:UNITS:FEE with months by name, e.g. 1:July:September:10000:500000;
+    /// repeat for more offers
+    #[arg(long, value_name = "OFFER")]
+    pub expansion_offer: Vec<ExpansionOffer>,
+
+    /// Directory to write results.csv (one row per pair), monthly.csv (each pair's mean
+    /// profit by month) and, with --trace-samples, traces.csv into
+    #[arg(long, value_name = "DIR")]
+    pub csv: Option<PathBuf>,
+
+    /// Output for people (text) or a single JSON document on stdout for other programs (json)
+    #[arg(long, default_value = "text")]
+    pub format: OutputFormat,
+
+    /// Re-simulate the best plan with each key input lowered and raised, and chart how far
+    /// its mean profit moves
+    #[arg(long)]
+    pub sensitivity: bool,
+
+    /// Work out what perfect foresight of demand and the order-change options are worth to the
+    /// best pair, on the same demand paths
+    #[arg(long)]
+    pub information_value: bool,
+
+    /// Optimize the best pair's base supplier alone too and report what its surge supplier adds
+    /// to expected profit, on the same demand paths
+    #[arg(long)]
+    pub surge_value: bool,
+
+    /// Print only the final ranked summary
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Also print the optimizer's diagnostics and how long each pair took
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Print without color even on a terminal; setting NO_COLOR does the same
+    #[arg(long)]
+    pub no_color: bool,
+
+    /// Markdown file to write a report of the run to, for sharing
+    #[arg(long, value_name = "PATH")]
+    pub report: Option<PathBuf>,
+
+    /// Jinja template to lay the --report out with instead of the standard Markdown report
+    #[cfg(feature = "templates")]
+    #[arg(long, value_name = "PATH", requires = "report", conflicts_with = "template_builtin")]
+    pub template: Option<PathBuf>,
+
+    /// Built-in template to lay the --report out with: executive (the recommendation on one
+    /// page) or technical (every pair's statistics and breakdowns)
+    #[cfg(feature = "templates")]
+    #[arg(long, value_name = "NAME", requires = "report", value_parser = ["executive", "technical"])]
+    pub template_builtin: Option<String>,
+
+    /// How numbers are written: en (1,234.50) or eu (1 234,50 with the symbol after)
+    #[arg(long, default_value = "en")]
+    pub number_style: NumberStyle,
+
+    /// Currency symbol for all amounts [default: $ for en, € for eu]
+    #[arg(long)]
+    pub currency: Option<String>,
+
+    /// Decimal places on amounts of money
+    #[arg(long, default_value = "2", value_name = "N")]
+    pub decimals: usize,
+
+    /// Abbreviate amounts in the ranking table, e.g. $10.2M
+    #[arg(long)]
+    pub compact: bool,
+
+    /// Full month-by-month traces of this many randomly chosen seasons per pair to write to
+    /// traces.csv in the --csv directory, for debugging the accounting
+    #[arg(long, default_value = "0", value_name = "N")]
+    pub trace_samples: usize,
+
+    /// Season profit to report the best pair's chance of reaching
+    #[arg(long, value_name = "AMOUNT", allow_negative_numbers = true)]
+    pub target_profit: Option<f64>,
+
+    /// HTML file to write a report with charts of the run to
+    #[cfg(feature = "charts")]
+    #[arg(long, value_name = "PATH")]
+    pub html: Option<PathBuf>,
+
+    /// Directory to write pairs.parquet (one row per pair) and replications.parquet (every
+    /// simulated season's seed, profit, exercises and fill rate) into
+    #[cfg(feature = "arrow")]
+    #[arg(long, value_name = "DIR")]
+    pub parquet: Option<PathBuf>,
+
+    /// Excel workbook to write the run to: the ranked pairs, the best plan month by month, each
+    /// pair's risk and the scenario, one sheet each
+    #[cfg(feature = "xlsx")]
+    #[arg(long, value_name = "PATH")]
+    pub xlsx: Option<PathBuf>,
+
+    /// SQLite database to record the run in, for following recommendations over time with the
+    /// history command
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "PATH")]
+    pub history: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// Verbosity chosen by --quiet or --verbose
+    pub fn verbosity(&self) -> Verbosity {
+        if self.quiet {
+            Verbosity::Quiet
+        } else if self.verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        }
+    }
+
+    /// Color on stdout: never with --no-color, otherwise when it is a terminal
+    pub fn color(&self) -> ColorChoice {
+        if self.no_color { ColorChoice::Never } else { ColorChoice::Auto }
+    }
+
+    /// Replication profits each pair's run keeps: all of them for the replication-level
+    /// Parquet table, otherwise none
+    pub fn sample_retention(&self) -> SampleRetention {
+        #[cfg(feature = "arrow")]
+        if self.parquet.is_some() {
+            return SampleRetention::All;
+        }
+        SampleRetention::None
+    }
+
+    /// Which suppliers may be paired, from --base-min-lead, --base-max-lead, --surge-max-lead,
+    /// --surge-shorter-than-base, --min-combined-capacity, --distinct-regions,
+    /// --max-same-risk-class and --max-setup-cost
+    pub fn pairing_rules(&self) -> PairingRules {
+        PairingRules {
+            base_min_lead: self.base_min_lead,
+            base_max_lead: self.base_max_lead,
+            surge_max_lead: self.surge_max_lead,
+            require_surge_shorter_than_base: self.surge_shorter_than_base,
+            min_combined_capacity: self.min_combined_capacity,
+            require_distinct_regions: self.distinct_regions,
+            max_same_risk_class: self.max_same_risk_class.unwrap_or(usize::MAX),
+            max_total_setup_cost: self.max_setup_cost,
+        }
+    }
+
+    /// What a pair must reach in quick screening, with --max-setup-cost as its setup cost cap
+    pub fn screening_criteria(&self) -> ScreeningCriteria {
+        ScreeningCriteria { max_total_setup_cost: self.max_setup_cost, ..ScreeningCriteria::default() }
+    }
+
+    /// Number formatting chosen by --number-style, --currency, --decimals and --compact
+    pub fn formatter(&self) -> Formatter {
+        let style = match self.number_style {
+            NumberStyle::En => Formatter::default(),
+            NumberStyle::Eu => Formatter::european(),
+        };
+        Formatter {
+            currency: self.currency.clone().unwrap_or(style.currency.clone()),
+            decimal_places: self.decimals,
+            compact: self.compact,
+            ..style
+        }
+    }
+}
+
+/// Conventions for writing numbers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumberStyle {
+    /// $1,234.50
+    En,
+    /// 1 234,50 €
+    Eu,
+}
+
+/// Alternatives to evaluating every supplier pair
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Compare two supplier pairs, each with its optimized plan, on identical demand paths
+    ComparePairs {
+        /// First pair as "Base+Surge" supplier names, e.g. FarFarAway+PrettyClose
+        first: PairNames,
+        /// Second pair, likewise
+        second: PairNames,
+    },
+    /// Simulate a plan of your own for a supplier pair, reporting it as the best pair's results
+    Evaluate {
+        /// Pair as "Base+Surge" supplier names, e.g. FarFarAway+PrettyClose
+        pair: PairNames,
+        /// Units a month of each product, in product order, to order from the base supplier
+        #[arg(long, value_delimiter = ',', value_name = "UNITS", required = true)]
+        base: Vec<u32>,
+        /// Units a month of each product, in product order, to order from the surge supplier
+        /// (default: none)
+        #[arg(long, value_delimiter = ',', value_name = "UNITS")]
+        surge: Vec<u32>,
+    },
+    /// Compare plans of your own for a supplier pair with the optimizer's plan for it on the
+    /// same demand paths: each plan's mean profit, CVaR and fill rate, and its difference from
+    /// the optimizer's plan with its significance
+    ComparePlans {
+        /// Pair as "Base+Surge" supplier names, e.g. FarFarAway+PrettyClose
+        pair: PairNames,
+        /// Plan files, TOML when named *.toml and JSON otherwise, each listing plans under
+        /// `plans` with a name and units a month by product name from the base and surge supplier
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Plan several seasons in a row with a supplier pair, each ordering around the stock the
+    /// season before carried over and without paying its suppliers' setup again, and report
+    /// each season and the net present value of them all
+    PlanSeasons {
+        /// Pair as "Base+Surge" supplier names, e.g. FarFarAway+PrettyClose
+        pair: PairNames,
+        /// Seasons to plan
+        #[arg(long, default_value_t = 3, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        seasons: usize,
+        /// Growth of every product's demand from one season to the next, e.g. 0.05 for 5%
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        demand_growth: f64,
+        /// Rate at which each later season's profit is discounted, e.g. 0.08 for 8% a season
+        #[arg(long, default_value_t = 0.08)]
+        discount_rate: f64,
+    },
+    /// Evaluate every supplier pair in each of several scenarios, read as JSON files, on the same
+    /// demand paths, and compare the pairs across them: each scenario's winner and each pair's
+    /// regret, with --report writing the matrix as Markdown
+    Scenarios {
+        /// Scenario files, each named by its file stem, e.g. scenarios/high-demand.json; a run
+        /// archived from --format json joins the matrix with the results it was run with
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// List the runs recorded with --history, or compare two of them
+    #[cfg(feature = "sqlite")]
+    History {
+        /// SQLite database the runs were recorded in
+        db: PathBuf,
+        /// Ids of two runs to compare: each pair's mean profit in both, and the scenario inputs
+        /// that changed between them
+        #[arg(long, num_args = 2, value_names = ["FIRST", "SECOND"])]
+        diff: Option<Vec<i64>>,
+        /// List only the runs of exactly these products, comma-separated, e.g. "Model A,Model B"
+        #[arg(long, value_delimiter = ',', conflicts_with = "diff")]
+        products: Option<Vec<String>>,
+        /// Runs archived from --format json, of any earlier layout, to record in the database
+        #[arg(long, value_name = "FILE", num_args = 1.., conflicts_with_all = ["diff", "products"])]
+        import: Vec<PathBuf>,
+    },
+    /// Serve evaluation over HTTP: POST a scenario to /runs, then poll GET /runs/{id} for its
+    /// status and results, or GET /runs/{id}/results.csv
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+    },
+}
+
+/// Base and surge supplier names identifying a supplier pair
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PairNames {
+    pub base: String,
+    pub surge: String,
+}
+
+impl FromStr for PairNames {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('+') {
+            Some((base, surge)) if !base.trim().is_empty() && !surge.trim().is_empty() => Ok(PairNames {
+                base: base.trim().to_string(),
+                surge: surge.trim().to_string(),
+            }),
+            _ => Err(format!("expected a pair as \"Base+Surge\", got \"{}\"", s)),
+        }
+    }
+}
+
+/// How many replications to run per supplier pair
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimulationCount {
+    Fixed(usize),
+    /// Run until the confidence interval on the mean profit is narrow enough
+    Auto,
+}
+
+impl FromStr for SimulationCount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(SimulationCount::Auto);
+        }
+        match s.parse::<usize>() {
+            Ok(0) => Err("at least one simulation is needed".to_string()),
+            Ok(count) => Ok(SimulationCount::Fixed(count)),
+            Err(_) => Err(format!("expected a number of simulations or \"auto\", got \"{}\"", s)),
+        }
+    }
+}
+
+impl FromStr for NumberStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "en" => Ok(NumberStyle::En),
+            "eu" => Ok(NumberStyle::Eu),
+            _ => Err(format!("expected en or eu, got \"{}\"", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use supply_chain_sim::models::SupplierId;
+
+    #[test]
+    fn test_simulation_count_parsing() {
+        assert_eq!("auto".parse(), Ok(SimulationCount::Auto));
+        assert_eq!("AUTO".parse(), Ok(SimulationCount::Auto));
+        assert_eq!("250".parse(), Ok(SimulationCount::Fixed(250)));
+        assert!("0".parse::<SimulationCount>().is_err());
+        assert!("many".parse::<SimulationCount>().is_err());
+
+        let cli = Cli::parse_from(["supply-chain-sim", "--simulations", "auto"]);
+        assert_eq!(cli.simulations, SimulationCount::Auto);
+        assert_eq!(Cli::parse_from(["supply-chain-sim"]).simulations, SimulationCount::Fixed(500));
+    }
+
+    #[test]
+    fn test_ranking_parsing() {
+        assert_eq!(Cli::parse_from(["supply-chain-sim"]).rank_by, RankingMetric::MeanProfit);
+        let cli = Cli::parse_from(["supply-chain-sim", "--rank-by", "operational-profit"]);
+        assert_eq!(cli.rank_by, RankingMetric::OperationalProfit);
+        assert!("best".parse::<RankingMetric>().is_err());
+    }
+
+    #[test]
+    fn test_selection_options() {
+        let cli = Cli::parse_from(["supply-chain-sim"]);
+        assert_eq!((cli.selection, cli.selection_budget, cli.selection_confidence), (SelectionMode::Fixed, None, 0.95));
+        let cli = Cli::parse_from(["supply-chain-sim", "--selection", "adaptive", "--selection-budget", "2000", "--selection-confidence", "0.99"]);
+        assert_eq!((cli.selection, cli.selection_budget, cli.selection_confidence), (SelectionMode::Adaptive, Some(2_000), 0.99));
+        assert!("best".parse::<SelectionMode>().is_err());
+    }
+
+    #[test]
+    fn test_verbosity_flags() {
+        assert_eq!(Cli::parse_from(["supply-chain-sim"]).verbosity(), Verbosity::Normal);
+        assert_eq!(Cli::parse_from(["supply-chain-sim", "-q"]).verbosity(), Verbosity::Quiet);
+        assert_eq!(Cli::parse_from(["supply-chain-sim", "--verbose"]).verbosity(), Verbosity::Verbose);
+        assert!(Cli::try_parse_from(["supply-chain-sim", "--quiet", "-v"]).is_err());
+    }
+
+    #[test]
+    fn test_number_formatting_options() {
+        assert_eq!(Cli::parse_from(["supply-chain-sim"]).formatter(), Formatter::default());
+        let eu = Cli::parse_from(["supply-chain-sim", "--number-style", "eu", "--decimals", "0"]).formatter();
+        assert_eq!(eu.money(-1_234_567.8), "-1 234 568 €");
+        let francs = Cli::parse_from(["supply-chain-sim", "--number-style", "eu", "--currency", "CHF", "--compact"]).formatter();
+        assert_eq!((francs.currency.as_str(), francs.compact), ("CHF", true));
+        assert!(Cli::try_parse_from(["supply-chain-sim", "--number-style", "fr"]).is_err());
+    }
+
+    #[test]
+    fn test_compare_pairs_parsing() {
+        let cli = Cli::parse_from(["supply-chain-sim", "compare-pairs", "FarFarAway+PrettyClose", "FarAway + PrettyClose"]);
+        let Some(Command::ComparePairs { first, second }) = cli.command else {
+            panic!("expected compare-pairs");
+        };
+        assert_eq!(first, PairNames { base: "FarFarAway".to_string(), surge: "PrettyClose".to_string() });
+        assert_eq!(second.base, "FarAway");
+        assert!("FarAway".parse::<PairNames>().is_err());
+        assert!(Cli::parse_from(["supply-chain-sim"]).command.is_none());
+    }
+
+    #[test]
+    fn test_compare_plans_parsing() {
+        let cli = Cli::parse_from(["supply-chain-sim", "compare-plans", "FarFarAway+PrettyClose", "ours.toml", "consultant.json"]);
+        let Some(Command::ComparePlans { pair, files }) = cli.command else {
+            panic!("expected compare-plans");
+        };
+        assert_eq!(pair.surge, "PrettyClose");
+        assert_eq!(files, vec![PathBuf::from("ours.toml"), PathBuf::from("consultant.json")]);
+        assert!(Cli::try_parse_from(["supply-chain-sim", "compare-plans", "FarFarAway+PrettyClose"]).is_err());
+    }
+
+    #[test]
+    fn test_evaluate_parsing() {
+        let cli = Cli::parse_from(["supply-chain-sim", "--capacity-policy", "strict", "evaluate", "FarFarAway+PrettyClose", "--base", "30000,20000"]);
+        assert_eq!(cli.capacity_policy, CapacityPolicy::Strict);
+        let Some(Command::Evaluate { pair, base, surge }) = cli.command else {
+            panic!("expected evaluate");
+        };
+        assert_eq!((pair.base.as_str(), base, surge), ("FarFarAway", vec![30_000, 20_000], vec![]));
+        assert_eq!(Cli::parse_from(["supply-chain-sim"]).capacity_policy, CapacityPolicy::Clamp);
+        assert!(Cli::try_parse_from(["supply-chain-sim", "evaluate", "FarFarAway+PrettyClose"]).is_err());
+        assert!("lenient".parse::<CapacityPolicy>().is_err());
+        assert_eq!(Cli::parse_from(["supply-chain-sim", "--time-step", "weekly"]).time_step, TimeStep::Weekly);
+        assert_eq!(Cli::parse_from(["supply-chain-sim"]).time_step, TimeStep::Monthly);
+        assert!("daily".parse::<TimeStep>().is_err());
+        assert_eq!(Cli::parse_from(["supply-chain-sim", "--cost-of-capital", "0.1"]).cost_of_capital, 0.1);
+    }
+
+    #[test]
+    fn test_plan_seasons_parsing() {
+        let cli = Cli::parse_from(["supply-chain-sim", "plan-seasons", "FarFarAway+PrettyClose", "--demand-growth", "-0.1"]);
+        let Some(Command::PlanSeasons { pair, seasons, demand_growth, discount_rate }) = cli.command else {
+            panic!("expected plan-seasons");
+        };
+        assert_eq!((pair.surge.as_str(), seasons, demand_growth, discount_rate), ("PrettyClose", 3, -0.1, 0.08));
+        assert!(Cli::try_parse_from(["supply-chain-sim", "plan-seasons", "FarFarAway+PrettyClose", "--seasons", "0"]).is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_history_parsing() {
+        let cli = Cli::parse_from(["supply-chain-sim", "history", "runs.sqlite", "--diff", "3", "5"]);
+        let Some(Command::History { db, diff, products, import }) = cli.command else {
+            panic!("expected history");
+        };
+        assert_eq!((db, diff, products), (PathBuf::from("runs.sqlite"), Some(vec![3, 5]), None));
+        assert!(import.is_empty());
+        let cli = Cli::parse_from(["supply-chain-sim", "history", "runs.sqlite", "--products", "Model A,Model B"]);
+        let Some(Command::History { products, .. }) = cli.command else {
+            panic!("expected history");
+        };
+        assert_eq!(products, Some(vec!["Model A".to_string(), "Model B".to_string()]));
+        assert!(Cli::try_parse_from(["supply-chain-sim", "history", "runs.sqlite", "--diff", "3"]).is_err());
+        let cli = Cli::parse_from(["supply-chain-sim", "history", "runs.sqlite", "--import", "2023.json", "2024.json"]);
+        let Some(Command::History { import, .. }) = cli.command else {
+            panic!("expected history");
+        };
+        assert_eq!(import, vec![PathBuf::from("2023.json"), PathBuf::from("2024.json")]);
+        assert!(Cli::try_parse_from(["supply-chain-sim", "history", "runs.sqlite", "--import", "2023.json", "--diff", "1", "2"]).is_err());
+    }
+
+    #[test]
+    fn test_pairing_rule_options() {
+        assert_eq!(Cli::parse_from(["supply-chain-sim"]).pairing_rules(), PairingRules::default());
+        let rules = Cli::parse_from([
+            "supply-chain-sim", "--base-min-lead", "0", "--surge-max-lead", "2", "--surge-shorter-than-base",
+            "--min-combined-capacity", "80000",
+        ]).pairing_rules();
+        assert_eq!((rules.base_min_lead, rules.base_max_lead, rules.surge_max_lead), (0, None, 2));
+        assert!(rules.require_surge_shorter_than_base);
+        assert_eq!(rules.min_combined_capacity, Some(80_000));
+        assert_eq!((rules.require_distinct_regions, rules.max_same_risk_class), (false, usize::MAX));
+
+        let rules = Cli::parse_from(["supply-chain-sim", "--distinct-regions", "--max-same-risk-class", "1"]).pairing_rules();
+        assert_eq!((rules.require_distinct_regions, rules.max_same_risk_class), (true, 1));
+
+        let cli = Cli::parse_from(["supply-chain-sim", "--max-setup-cost", "2500000"]);
+        assert_eq!(cli.pairing_rules().max_total_setup_cost, Some(2_500_000.0));
+        assert_eq!(cli.screening_criteria().max_total_setup_cost, Some(2_500_000.0));
+    }
+
+    #[test]
+    fn test_shortlist_options() {
+        let cli = Cli::parse_from(["supply-chain-sim"]);
+        assert_eq!((cli.shortlist, cli.score_weights), (None, ScoringWeights::default()));
+        assert_eq!(cli.pooling_coverage, 0.7);
+        let cli = Cli::parse_from(["supply-chain-sim", "--shortlist", "3", "--score-weights", "1, 1, 0, 2"]);
+        assert_eq!(cli.shortlist, Some(3));
+        assert_eq!(cli.score_weights, ScoringWeights { landed_cost: 1.0, capacity: 1.0, lead_time: 0.0, setup_cost: 2.0 });
+        assert!(Cli::try_parse_from(["supply-chain-sim", "--shortlist", "0"]).is_err());
+        assert!("1,1,1".parse::<ScoringWeights>().is_err());
+        assert!("1,-1,1,1".parse::<ScoringWeights>().is_err());
+        assert!("0,0,0,0".parse::<ScoringWeights>().is_err());
+    }
+
+    #[test]
+    fn test_regional_disruption_parsing() {
+        let cli = Cli::parse_from(["supply-chain-sim", "--regional-disruption", "Overseas=0.05", "--regional-disruption", "Nearshore = 0.01"]);
+        assert_eq!(cli.regional_disruption, [
+            RegionalDisruption { region: "Overseas".to_string(), monthly_probability: 0.05 },
+            RegionalDisruption { region: "Nearshore".to_string(), monthly_probability: 0.01 },
+        ]);
+        assert!(Cli::parse_from(["supply-chain-sim"]).regional_disruption.is_empty());
+        assert!("Overseas".parse::<RegionalDisruption>().is_err());
+        assert!("Overseas=1.5".parse::<RegionalDisruption>().is_err());
+        assert!("=0.1".parse::<RegionalDisruption>().is_err());
+    }
+
+    #[test]
+    fn test_expansion_offer_parsing() {
+        let cli = Cli::parse_from(["supply-chain-sim", "--expansion-offer", "1:July:september:10000:500000"]);
+        assert_eq!(cli.expansion_offer, [
+            ExpansionOffer { supplier_id: SupplierId(1), decision_month: 2, start_month: 4, extra_capacity: 10_000, fee: 500_000.0 },
+        ]);
+        assert!(Cli::parse_from(["supply-chain-sim"]).expansion_offer.is_empty());
+        assert!("1:July:10000:500000".parse::<ExpansionOffer>().is_err());
+        assert!("1:Julember:September:10000:500000".parse::<ExpansionOffer>().is_err());
+        assert!("1:September:July:10000:500000".parse::<ExpansionOffer>().is_err());
+        assert!("1:July:September:10000:NaN".parse::<ExpansionOffer>().is_err());
+    }
+
+    #[cfg(feature = "templates")]
+    #[test]
+    fn test_template_options() {
+        let cli = Cli::parse_from(["supply-chain-sim", "--report", "summary.md", "--template-builtin", "executive"]);
+        assert_eq!(cli.template_builtin.as_deref(), Some("executive"));
+        assert!(Cli::try_parse_from(["supply-chain-sim", "--report", "r.md", "--template-builtin", "weekly"]).is_err());
+        assert!(Cli::try_parse_from(["supply-chain-sim", "--template", "layout.j2"]).is_err(), "needs --report");
+        assert!(Cli::try_parse_from([
+            "supply-chain-sim", "--report", "r.md", "--template", "layout.j2", "--template-builtin", "technical",
+        ]).is_err());
+    }
+}
@@ -1,65 +1,232 @@
-/// Demand calculation module
-/// Handles both expected demand (used for planning) and actual demand (realized during simulation)
+//! Demand: the forecast the plan is made on, and the demand realized month by month in the
+//! simulation, drawn at random or replayed from a script
 
-use rand::{thread_rng, Rng};
-use rand_distr::Normal;
-use crate::models::{SimulationParams, ProductDemandParams};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use rand_distr::{Normal, StandardNormal};
+use crate::error::SimulationError;
+use crate::models::{ProductDemandParams, ProductId, SimulationParams};
+use crate::stats::{inverse_standard_normal_cdf, normal_loss};
+
+thread_local! {
+    /// Per-thread demand generator, seeded from entropy unless reseeded explicitly
+    static DEMAND_RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+    /// Whether the current thread mirrors each demand draw about its mean
+    static ANTITHETIC: Cell<bool> = const { Cell::new(false) };
+    /// Standard normal innovations the current thread's next demand draws use instead of fresh ones
+    static SCRIPT: RefCell<VecDeque<f64>> = const { RefCell::new(VecDeque::new()) };
+}
+
+/// Reseed the current thread's demand generator
+/// Runs started after the same seed see identical demand paths, which allows matched comparisons
+pub(crate) fn seed_demand_rng(seed: u64) {
+    DEMAND_RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
+
+/// Mirror (or stop mirroring) the current thread's demand draws about their means
+/// A run reseeded with the same seed and mirrored sees the antithetic demand path: high
+/// months become low ones, which makes the pair's average profit far less noisy
+pub(crate) fn set_antithetic_demand(antithetic: bool) {
+    ANTITHETIC.with(|flag| flag.set(antithetic));
+}
+
+/// Run `f` on a separate, unmirrored demand stream seeded with `seed`, then restore the thread's
+/// own stream, so nested simulations leave the draws of the run around them untouched
+pub(crate) fn with_demand_stream<T>(seed: u64, f: impl FnOnce() -> T) -> T {
+    let saved_rng = DEMAND_RNG.with(|rng| rng.replace(SmallRng::seed_from_u64(seed)));
+    let saved_antithetic = ANTITHETIC.with(|flag| flag.replace(false));
+    let saved_script = SCRIPT.with(|script| script.take());
+    let result = f();
+    DEMAND_RNG.with(|rng| *rng.borrow_mut() = saved_rng);
+    ANTITHETIC.with(|flag| flag.set(saved_antithetic));
+    SCRIPT.with(|script| *script.borrow_mut() = saved_script);
+    result
+}
+
+/// Script the current thread's next demand draws for a stratified replication
+/// `weights` holds the standard deviation of each draw the season will make, in draw order. The
+/// innovations are drawn from the thread's generator conditioned on their weighted sum (the
+/// season's demand surprise) falling in stratum `stratum` of `strata` equal-probability strata
+pub(crate) fn script_stratified_demand(weights: &[f64], stratum: usize, strata: usize) {
+    let variance: f64 = weights.iter().map(|w| w * w).sum();
+    let innovations = DEMAND_RNG.with(|rng| {
+        let mut rng = rng.borrow_mut();
+        // Weighted sum drawn uniformly within its stratum of the normal distribution
+        let u: f64 = rng.gen();
+        let target = variance.sqrt() * inverse_standard_normal_cdf((stratum as f64 + u) / strata as f64);
+        let free: Vec<f64> = weights.iter().map(|_| rng.sample(StandardNormal)).collect();
+        if variance <= 0.0 {
+            return free;
+        }
+        // Given their weighted sum, independent normals are the free draws shifted along the
+        // weights until the sum hits the target
+        let free_sum: f64 = free.iter().zip(weights).map(|(z, w)| z * w).sum();
+        let shift = (target - free_sum) / variance;
+        free.iter().zip(weights).map(|(z, w)| z + w * shift).collect::<Vec<f64>>()
+    });
+    SCRIPT.with(|script| *script.borrow_mut() = innovations.into());
+}
+
+/// Script the current thread's next `draws` demand draws to land on their means, for a
+/// deterministic projection of a plan
+pub(crate) fn script_mean_demand(draws: usize) {
+    SCRIPT.with(|script| *script.borrow_mut() = vec![0.0; draws].into());
+}
+
+/// Drop any scripted draws the current thread has left, returning it to fresh draws
+pub(crate) fn clear_demand_script() {
+    SCRIPT.with(|script| script.borrow_mut().clear());
+}
+
+/// Uniform draw in [0, 1) from the current thread's demand generator, for the season's other
+/// random events, which then follow the same seeding as its demand
+pub(crate) fn event_draw() -> f64 {
+    DEMAND_RNG.with(|rng| rng.borrow_mut().gen())
+}
 
 /// Expected monthly demand for a specific product - used by the model for decision-making
 /// Returns the mean of the expected demand distribution
 #[allow(dead_code)]
-pub fn expected_demand(demand_params: &ProductDemandParams) -> u32 {
+pub(crate) fn expected_demand(demand_params: &ProductDemandParams) -> u32 {
     demand_params.mean_demand as u32
 }
 
 /// Expected monthly demand using SimulationParams - for backwards compatibility
 #[allow(dead_code)]
-pub fn expected_demand_for_product(params: &SimulationParams, product_id: usize) -> u32 {
+pub(crate) fn expected_demand_for_product(params: &SimulationParams, product_id: ProductId) -> u32 {
     params.get_demand_params(product_id)
         .map(|dp| dp.mean_demand as u32)
         .unwrap_or(0)
 }
 
+/// Normal distribution of a product's monthly demand, refusing a mean or standard deviation that
+/// is not finite and a negative standard deviation
+fn demand_distribution(product_id: ProductId, mean: f64, std_dev: f64) -> Result<Normal<f64>, SimulationError> {
+    let invalid = || SimulationError::InvalidDistribution { product_id, mean, std_dev };
+    if !mean.is_finite() || std_dev < 0.0 {
+        return Err(invalid());
+    }
+    Normal::new(mean, std_dev).map_err(|_| invalid())
+}
+
+/// Check that every product's forecast and actual demand can be sampled, so a search that
+/// treats failed simulations as infeasible candidates does not mistake bad inputs for them
+pub(crate) fn validate_demand(params: &SimulationParams) -> Result<(), SimulationError> {
+    for demand_params in &params.demand_params {
+        for use_actual in [false, true] {
+            let (mean, std_dev) = demand_moments(demand_params, use_actual);
+            demand_distribution(demand_params.product_id, mean, std_dev)?;
+        }
+    }
+    Ok(())
+}
+
 /// Simulation monthly demand for a specific product - can be based on forecast (expected) or actuals
+/// Fails on a distribution `demand_distribution` refuses
 #[allow(dead_code)]
-pub fn simulation_demand(demand_params: &ProductDemandParams, use_actual: bool) -> u32 {
-    let mut rng = thread_rng();
-    
-    let (mean, std_dev) = if use_actual {
-        (demand_params.actual_mean_demand, demand_params.actual_std_dev_demand)
-    } else {
-        (demand_params.mean_demand, demand_params.std_dev_demand)
-    };
+pub(crate) fn simulation_demand(demand_params: &ProductDemandParams, use_actual: bool) -> Result<u32, SimulationError> {
+    sample_demand(demand_params, use_actual, None)
+}
 
-    let normal = Normal::new(mean, std_dev)
-        .expect("Invalid normal distribution parameters");
+/// `simulation_demand` drawn on `rng`, or on the thread's demand generator without one
+fn sample_demand(demand_params: &ProductDemandParams, use_actual: bool, rng: Option<&RefCell<SmallRng>>) -> Result<u32, SimulationError> {
+    let (mean, std_dev) = demand_moments(demand_params, use_actual);
 
-    // Sample from the distribution and ensure non-negative
-    let demand = rng.sample(normal);
+    let normal = demand_distribution(demand_params.product_id, mean, std_dev)?;
+
+    // Sample from the distribution, or take the next scripted innovation, and ensure non-negative
+    let demand = match SCRIPT.with(|script| script.borrow_mut().pop_front()) {
+        Some(innovation) => mean + std_dev * innovation,
+        None => match rng {
+            Some(rng) => rng.borrow_mut().sample(normal),
+            None => DEMAND_RNG.with(|rng| rng.borrow_mut().sample(normal)),
+        },
+    };
+    let demand = if ANTITHETIC.with(Cell::get) { 2.0 * mean - demand } else { demand };
     // Cap at 3 standard deviations above mean to prevent extreme outliers
     let max_reasonable_demand = mean + (3.0 * std_dev);
-    (demand.max(0.0) as u32).min(max_reasonable_demand as u32)
+    Ok((demand.max(0.0) as u32).min(max_reasonable_demand as u32))
+}
+
+/// Expected value of `simulation_demand`, allowing for the floor at zero, the cap three standard
+/// deviations above the mean, and the truncation to whole units (half a unit on average)
+pub(crate) fn expected_simulation_demand(demand_params: &ProductDemandParams, use_actual: bool) -> f64 {
+    let (mean, std_dev) = demand_moments(demand_params, use_actual);
+    if std_dev <= 0.0 {
+        return mean.max(0.0).floor();
+    }
+    mean + std_dev * (normal_loss(mean / std_dev) - normal_loss(3.0)) - 0.5
+}
+
+/// Mean and standard deviation of monthly demand under the forecast or the actuals
+fn demand_moments(demand_params: &ProductDemandParams, use_actual: bool) -> (f64, f64) {
+    if use_actual {
+        (demand_params.actual_mean_demand, demand_params.actual_std_dev_demand)
+    } else {
+        (demand_params.mean_demand, demand_params.std_dev_demand)
+    }
 }
 
 /// Simulation monthly demand using SimulationParams
 #[allow(dead_code)]
-pub fn simulation_demand_for_product(params: &SimulationParams, product_id: usize, use_actual: bool) -> u32 {
+pub(crate) fn simulation_demand_for_product(params: &SimulationParams, product_id: ProductId, use_actual: bool) -> Result<u32, SimulationError> {
     params.get_demand_params(product_id)
-        .map(|dp| simulation_demand(dp, use_actual))
-        .unwrap_or(0)
+        .map_or(Ok(0), |dp| simulation_demand(dp, use_actual))
 }
 
 /// Generate demands for all products (independent demands)
 #[allow(dead_code)]
-pub fn simulation_demand_all_products(params: &SimulationParams, use_actual: bool) -> Vec<(usize, u32)> {
+pub(crate) fn simulation_demand_all_products(params: &SimulationParams, use_actual: bool) -> Result<Vec<(ProductId, u32)>, SimulationError> {
     params.demand_params.iter()
-        .map(|dp| (dp.product_id, simulation_demand(dp, use_actual)))
+        .map(|dp| Ok((dp.product_id, simulation_demand(dp, use_actual)?)))
         .collect()
 }
 
+/// Where a season's monthly demand comes from
+#[derive(Clone, Copy, Debug)]
+pub enum DemandSource<'a> {
+    /// Drawn from each product's forecast or actual distribution, as the run asks, on the given
+    /// generator, which also draws the season's other random events; without one, on the
+    /// thread's demand generator
+    Stochastic(Option<&'a RefCell<SmallRng>>),
+    /// Given outright, each month's units by product; a product missing from a month has no
+    /// demand that month, and the run's choice of forecast or actuals does not apply
+    Scripted(&'a [HashMap<ProductId, u32>]),
+}
+
+impl DemandSource<'_> {
+    /// Refuse a script that runs out before a season of `season_months` does
+    pub(crate) fn covers(&self, season_months: usize) -> Result<(), SimulationError> {
+        match self {
+            DemandSource::Scripted(months) if months.len() < season_months => {
+                Err(SimulationError::ShortDemandScript { months: months.len(), season_months })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Demand for `product_id` in `month`, whose distribution is `demand_params`, if it has one
+    pub(crate) fn demand(&self, month: usize, product_id: ProductId, demand_params: Option<&ProductDemandParams>, use_actual: bool) -> Result<u32, SimulationError> {
+        match self {
+            DemandSource::Stochastic(rng) => demand_params.map_or(Ok(0), |dp| sample_demand(dp, use_actual, *rng)),
+            DemandSource::Scripted(months) => Ok(months[month].get(&product_id).copied().unwrap_or(0)),
+        }
+    }
+
+    /// Uniform draw in [0, 1) for the season's other random events, on the source's generator if
+    /// it has one and the thread's demand generator otherwise
+    pub(crate) fn event_draw(&self) -> f64 {
+        match self {
+            DemandSource::Stochastic(Some(rng)) => rng.borrow_mut().gen(),
+            _ => event_draw(),
+        }
+    }
+}
+
 /// Legacy actual demand wrapper for compatibility (uses actuals)
 #[allow(dead_code)]
-pub fn actual_demand(demand_params: &ProductDemandParams) -> u32 {
+pub(crate) fn actual_demand(demand_params: &ProductDemandParams) -> Result<u32, SimulationError> {
     simulation_demand(demand_params, true)
 }
 
@@ -70,7 +237,7 @@ mod tests {
     #[test]
     fn test_expected_demand() {
         let demand_params = ProductDemandParams {
-            product_id: 0,
+            product_id: ProductId(0),
             mean_demand: 100.0,
             std_dev_demand: 20.0,
             actual_mean_demand: 100.0,
@@ -81,9 +248,62 @@ mod tests {
     }
 
     #[test]
+    fn test_simulated_demand_matches_its_expectation_and_mirrors() {
+        let demand_params = ProductDemandParams {
+            product_id: ProductId(0),
+            mean_demand: 1_000.0,
+            std_dev_demand: 400.0,
+            actual_mean_demand: 1_000.0,
+            actual_std_dev_demand: 400.0,
+        };
+
+        seed_demand_rng(21);
+        let draws: Vec<u32> = (0..20_000).map(|_| actual_demand(&demand_params).unwrap()).collect();
+        let mean = draws.iter().map(|&d| d as f64).sum::<f64>() / draws.len() as f64;
+        // The floor at zero and the cap pull in opposite directions; 3 standard errors is about 8.5
+        assert!((mean - expected_simulation_demand(&demand_params, true)).abs() < 8.5, "mean {}", mean);
+
+        // Reseeded and mirrored, each draw lands the same distance on the other side of the mean
+        seed_demand_rng(21);
+        set_antithetic_demand(true);
+        let mirrored: Vec<u32> = (0..100).map(|_| actual_demand(&demand_params).unwrap()).collect();
+        set_antithetic_demand(false);
+        for (&draw, &mirror) in draws.iter().zip(&mirrored).filter(|(&d, _)| (1..=2_000).contains(&d)) {
+            assert!((draw as f64 + mirror as f64 - 2_000.0).abs() <= 1.0, "{} and {}", draw, mirror);
+        }
+    }
+
+    #[test]
+    fn test_stratified_scripts_land_in_their_stratum() {
+        let weights = [1_200.0, 600.0, 1_200.0, 600.0];
+        let total_sd = (2.0f64 * (1_200.0f64.powi(2) + 600.0f64.powi(2))).sqrt();
+        let demand = |std_dev: f64| ProductDemandParams {
+            product_id: ProductId(0),
+            mean_demand: 10_000.0,
+            std_dev_demand: std_dev,
+            actual_mean_demand: 10_000.0,
+            actual_std_dev_demand: std_dev,
+        };
+
+        seed_demand_rng(4);
+        for stratum in 0..4 {
+            script_stratified_demand(&weights, stratum, 4);
+            let surprise: f64 = weights.iter()
+                .map(|&w| actual_demand(&demand(w)).unwrap() as f64 - 10_000.0)
+                .sum();
+            // Quartiles of the surprise sit at ±0.674 standard deviations; allow for whole units
+            let bounds = [f64::NEG_INFINITY, -0.6745 * total_sd, 0.0, 0.6745 * total_sd, f64::INFINITY];
+            assert!(bounds[stratum] - 4.0 <= surprise && surprise <= bounds[stratum + 1] + 4.0, "{}: {}", stratum, surprise);
+        }
+        // A spent script falls back to fresh draws
+        assert!(SCRIPT.with(|script| script.borrow().is_empty()));
+    }
+
+    #[test]
+    #[allow(unused_comparisons, clippy::absurd_extreme_comparisons)]
     fn test_actual_demand_is_non_negative() {
         let demand_params = ProductDemandParams {
-            product_id: 0,
+            product_id: ProductId(0),
             mean_demand: 100.0,
             std_dev_demand: 20.0,
             actual_mean_demand: 100.0,
@@ -91,9 +311,29 @@ mod tests {
         };
 
         for _ in 0..100 {
-            let demand = actual_demand(&demand_params);
+            let demand = actual_demand(&demand_params).unwrap();
             assert!(demand >= 0);
             assert!(demand <= 1000);
         }
     }
+
+    #[test]
+    fn test_invalid_distributions_are_refused_naming_the_product() {
+        let demand = |std_dev: f64| ProductDemandParams {
+            product_id: ProductId(3),
+            mean_demand: 100.0,
+            std_dev_demand: std_dev,
+            actual_mean_demand: 100.0,
+            actual_std_dev_demand: std_dev,
+        };
+
+        for std_dev in [-1.0, f64::NAN, f64::INFINITY] {
+            match actual_demand(&demand(std_dev)) {
+                Err(SimulationError::InvalidDistribution { product_id: ProductId(3), mean, .. }) => assert_eq!(mean, 100.0),
+                other => panic!("{}: {:?}", std_dev, other),
+            }
+        }
+        // No spread is a valid, if certain, demand
+        assert_eq!(actual_demand(&demand(0.0)).unwrap(), 100);
+    }
 }
@@ -0,0 +1,187 @@
+//! Errors a simulation can stop with instead of panicking: bad inputs, a plan its suppliers
+//! cannot make, or results that cannot be summarized
+
+use thiserror::Error;
+use crate::capacity::CapacityError;
+use crate::models::{ProductId, SupplierId};
+
+/// Why a simulation, optimization or evaluation could not produce a result
+#[derive(Debug, Error)]
+pub enum SimulationError {
+    /// A product's demand distribution cannot be sampled
+    #[error("invalid demand distribution for product {product_id}: mean {mean} and standard deviation {std_dev} must be finite, the standard deviation not negative")]
+    InvalidDistribution {
+        /// Product whose demand parameters are invalid
+        product_id: ProductId,
+        /// Mean monthly demand drawn from
+        mean: f64,
+        /// Standard deviation of monthly demand drawn from
+        std_dev: f64,
+    },
+    /// A replication's profit came out as NaN, which would poison every statistic of the run
+    #[error("replication {replication} of {pair} produced a profit that is not a number")]
+    NanProfit {
+        /// Name of the supplier pair simulated
+        pair: String,
+        /// Index of the replication within the run
+        replication: usize,
+    },
+    /// There was nothing to compute statistics over, such as a run of no replications
+    #[error("no samples to summarize: {0}")]
+    EmptySamples(&'static str),
+    /// A scripted demand sequence ends before the season does
+    #[error("scripted demand covers {months} months of a {season_months} month season")]
+    ShortDemandScript {
+        /// Months the script gives demand for
+        months: usize,
+        /// Months in the season
+        season_months: usize,
+    },
+    /// The plan asks more of a supplier than it can make, under the strict capacity policy
+    #[error(transparent)]
+    Capacity(#[from] CapacityError),
+    /// The scenario fails validation, so it is refused before any of it is simulated
+    #[error(transparent)]
+    Scenario(#[from] ScenarioError),
+}
+
+/// Why a scenario from outside, such as a file or a request, cannot be simulated
+#[derive(Debug, Error)]
+pub enum ScenarioError {
+    /// The scenario is not valid JSON, or not the shape of one
+    #[cfg(feature = "serde")]
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// A scenario sells at least one product
+    #[error("the scenario has no products")]
+    NoProducts,
+    /// Two products or two suppliers share an id
+    #[error("{kind} id {id} is used twice")]
+    DuplicateId {
+        /// "product" or "supplier"
+        kind: &'static str,
+        /// The id shared
+        id: u32,
+    },
+    /// Something refers to a product the scenario does not sell
+    #[error("{context} refers to product {product_id}, which is not one of the scenario's products")]
+    UnknownProduct {
+        /// What refers to it
+        context: String,
+        /// Product referred to
+        product_id: ProductId,
+    },
+    /// Something refers to a supplier the scenario does not have
+    #[error("{context} refers to supplier {supplier_id}, which is not one of the scenario's suppliers")]
+    UnknownSupplier {
+        /// What refers to it
+        context: String,
+        /// Supplier referred to
+        supplier_id: SupplierId,
+    },
+    /// A product lacks its demand parameters, or has them twice
+    #[error("product {0} needs exactly one set of demand parameters")]
+    DemandParams(ProductId),
+    /// A supplier quotes no unit cost for a product
+    #[error("supplier {supplier_id} has no unit cost for product {product_id}")]
+    MissingUnitCost {
+        /// Supplier without the cost
+        supplier_id: SupplierId,
+        /// Product it has no cost for
+        product_id: ProductId,
+    },
+    /// A price, cost, fee or demand figure is NaN, infinite or negative
+    #[error("{field} must be a finite amount, not negative, but is {value}")]
+    InvalidAmount {
+        /// Where the amount sits, e.g. "product 0 selling_price"
+        field: String,
+        /// The amount given
+        value: f64,
+    },
+    /// A share, such as a probability or ramp-up fraction, lies outside its range
+    #[error("{field} must be from {min} to {max}, but is {value}")]
+    OutOfRange {
+        /// Where the share sits
+        field: String,
+        /// The share given
+        value: f64,
+        /// Least allowed
+        min: f64,
+        /// Most allowed
+        max: f64,
+    },
+    /// The season is empty, or longer than the simulation handles
+    #[error("the season must run from 1 to {max} months, but runs {months}")]
+    SeasonLength {
+        /// Months in the season given
+        months: usize,
+        /// Longest season allowed
+        max: usize,
+    },
+    /// A number of months, such as a lead time, is beyond the longest season
+    #[error("{field} must be at most {max} months, but is {months}")]
+    TooManyMonths {
+        /// Where the months sit, e.g. "supplier 0 lead_time_months"
+        field: String,
+        /// The months given
+        months: usize,
+        /// Most allowed
+        max: usize,
+    },
+    /// More units a month than the simulation counts in, across products or suppliers
+    #[error("{what} comes to {units:.0} units a month, more than the {max:.0} allowed")]
+    TooManyUnits {
+        /// What adds up to them
+        what: &'static str,
+        /// Units a month they come to
+        units: f64,
+        /// Most units allowed
+        max: f64,
+    },
+    /// An expansion offer must be decided before the capacity it adds starts
+    #[error("expansion offer of supplier {0} must be decided before its capacity starts")]
+    ExpansionOrder(SupplierId),
+    /// A plan given for comparison cannot be simulated as written
+    #[error("plan \"{plan}\": {field} {reason}")]
+    InvalidPlan {
+        /// Name of the plan
+        plan: String,
+        /// Field at fault, e.g. "base.Model A"
+        field: String,
+        /// What is wrong with it
+        reason: String,
+    },
+}
+
+/// Why a set of base and surge orders is not a valid monthly order
+#[derive(Debug, Error, PartialEq)]
+pub enum OrderError {
+    /// A product is ordered twice from the same side, which would deliver it twice
+    #[error("product {product_id} has more than one {side} order")]
+    DuplicateProduct {
+        /// Product ordered more than once
+        product_id: ProductId,
+        /// "base" or "surge"
+        side: &'static str,
+    },
+    /// A product is ordered that the scenario does not sell
+    #[error("product {0} is not one of the scenario's products")]
+    UnknownProduct(ProductId),
+}
+
+/// Why an archived run cannot be read into the current result model
+#[cfg(feature = "serde")]
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    /// The run is not valid JSON, or not the shape of its layout
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// The run was written by a newer simulator, in a layout this one does not know
+    #[error("the run has schema version {found}, newer than the {supported} this simulator reads")]
+    UnsupportedVersion {
+        /// Version the run carries
+        found: u64,
+        /// Newest version this simulator reads
+        supported: u32,
+    },
+}
@@ -0,0 +1,341 @@
+//! Evaluation of supplier pairs: optimize each pair's plan, split it between its suppliers and
+//! simulate it. Pairs share nothing but their inputs, so they can be evaluated in parallel
+
+#[cfg(feature = "parallel")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use tracing::{info, info_span};
+use crate::demand::with_demand_stream;
+use crate::error::SimulationError;
+use crate::models::{MonteCarloStats, MonthlyOrder, PairRun, PairedComparison, ProductDemandParams, ProductId, SimulationParams, SupplierPair};
+use crate::monte_carlo::{compare, run_monte_carlo_simulation_with_progress, run_monte_carlo_until_converged, MonteCarloConfig};
+use crate::optimizer::{find_optimal_plan, OptimizerDiagnostics};
+use crate::pairing_utils::quick_profit_estimate;
+use crate::progress::{NoProgress, ProgressSink};
+use crate::selection::{select_best, Selection, SelectionConfig};
+use crate::simulation::split_order_quantities;
+
+/// How each pair is simulated
+#[derive(Clone, Debug)]
+pub struct EvaluationConfig {
+    /// Every pair's replications run on this config's master seed, so pairs are compared on
+    /// common random numbers, and its optimizer on a seed derived from it and the pair
+    pub monte_carlo: MonteCarloConfig,
+    /// Stop each pair once its mean profit is known this precisely, running at most
+    /// `monte_carlo.num_simulations` replications; None runs them all
+    pub convergence: Option<Convergence>,
+}
+
+/// When a pair has been simulated enough
+#[derive(Clone, Copy, Debug)]
+pub struct Convergence {
+    /// Relative confidence interval half-width at which to stop
+    pub tolerance: f64,
+    /// Fewest replications to run before stopping
+    pub min_simulations: usize,
+}
+
+/// One supplier pair's plan and simulated results
+#[derive(Clone, Debug)]
+pub struct PairEvaluation {
+    /// Rough profit estimate used to screen the pair
+    pub quick_estimate: f64,
+    /// Season quantity per product chosen by the optimizer, as (product_id, units)
+    pub optimal_quantities: Vec<(ProductId, u32)>,
+    pub diagnostics: OptimizerDiagnostics,
+    /// The optimal quantities split between the pair's suppliers
+    pub monthly_order: MonthlyOrder,
+    pub stats: MonteCarloStats,
+    /// Time spent optimizing and then simulating
+    pub optimizing: Duration,
+    pub simulating: Duration,
+}
+
+impl PairEvaluation {
+    /// What the run output keeps of the evaluation
+    pub fn to_run(&self) -> PairRun {
+        PairRun { quick_estimate: self.quick_estimate, optimal_quantities: self.optimal_quantities.clone(), stats: self.stats.clone() }
+    }
+}
+
+/// Seed of a pair's optimizer search, derived from the master seed and the pair's name (FNV-1a),
+/// so each pair searches on its own demand draws wherever and whenever it runs
+pub(crate) fn optimizer_seed(master_seed: u64, pair: &SupplierPair) -> u64 {
+    pair.name().bytes().fold(master_seed ^ 0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// A pair's quick estimate and optimized plan, before it is simulated
+struct PairPlan {
+    quick_estimate: f64,
+    optimal_quantities: Vec<(ProductId, u32)>,
+    diagnostics: OptimizerDiagnostics,
+    monthly_order: MonthlyOrder,
+    optimizing: Duration,
+}
+
+impl PairPlan {
+    fn simulated(self, stats: MonteCarloStats, simulating: Duration) -> PairEvaluation {
+        info!(quantities = ?self.optimal_quantities, mean_profit = stats.mean_profit, "pair evaluated");
+        PairEvaluation {
+            quick_estimate: self.quick_estimate,
+            optimal_quantities: self.optimal_quantities,
+            diagnostics: self.diagnostics,
+            monthly_order: self.monthly_order,
+            stats,
+            optimizing: self.optimizing,
+            simulating,
+        }
+    }
+}
+
+/// Optimize a pair's production quantities and split them between base and surge supplier,
+/// reporting the optimizer to `progress`
+fn plan_pair(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    config: &EvaluationConfig,
+    progress: &dyn ProgressSink,
+) -> Result<PairPlan, SimulationError> {
+    progress.on_pair_start(pair);
+
+    // Quick profit estimate to show potential
+    let quick_estimate = quick_profit_estimate(params, pair);
+
+    // Step 1: Find optimal production quantities with diagnostics, on the pair's own demand
+    // stream rather than whatever the thread drew last
+    let optimizing = Instant::now();
+    let (optimal_quantities, reservations, diagnostics) = with_demand_stream(optimizer_seed(config.monte_carlo.master_seed, pair), || {
+        find_optimal_plan(params, pair, progress)
+    })?;
+    let optimizing = optimizing.elapsed();
+
+    // Step 2: Split order quantity between base and surge, reserving the capacity chosen
+    let mut monthly_order = split_order_quantities(&optimal_quantities, pair, params);
+    monthly_order.reservations = reservations;
+
+    Ok(PairPlan { quick_estimate, optimal_quantities, diagnostics, monthly_order, optimizing })
+}
+
+/// What the pair's forecast cost it: the plan the optimizer would have chosen knowing the
+/// actual demand parameters compared with `monthly_order`, the plan chosen on the forecast
+/// Both plans are run as the season unfolds on the forecast, with its order changes valued and
+/// replanned on it, for `num_simulations` replications on the same demand paths: `monte_carlo`'s
+/// master seed, and the optimizer's seed a pair evaluation derives from it. The paired
+/// comparison's interval tells a real cost from simulation noise. None when the forecast is the
+/// actual demand, which costs nothing
+pub fn cost_of_forecast_error(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    monthly_order: &MonthlyOrder,
+    monte_carlo: &MonteCarloConfig,
+    num_simulations: usize,
+) -> Result<Option<PairedComparison>, SimulationError> {
+    let is_actual = |demand: &ProductDemandParams| {
+        demand.mean_demand == demand.actual_mean_demand && demand.std_dev_demand == demand.actual_std_dev_demand
+    };
+    if params.demand_params.iter().all(is_actual) {
+        return Ok(None);
+    }
+    let hindsight = SimulationParams {
+        demand_params: params.demand_params.iter()
+            .map(|demand| ProductDemandParams {
+                mean_demand: demand.actual_mean_demand,
+                std_dev_demand: demand.actual_std_dev_demand,
+                ..demand.clone()
+            })
+            .collect(),
+        ..params.clone()
+    };
+    let (quantities, reservations, _) = with_demand_stream(optimizer_seed(monte_carlo.master_seed, pair), || {
+        find_optimal_plan(&hindsight, pair, &NoProgress)
+    })?;
+    let mut hindsight_order = split_order_quantities(&quantities, pair, &hindsight);
+    hindsight_order.reservations = reservations;
+
+    let comparison = compare(params, pair, &hindsight_order, pair, monthly_order, num_simulations, monte_carlo.master_seed)?;
+    Ok(Some(comparison))
+}
+
+/// Optimize a pair's production quantities, split them between base and surge supplier and run
+/// the Monte Carlo simulation of the plan, reporting the optimizer and replications to `progress`
+pub fn evaluate_pair(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    config: &EvaluationConfig,
+    progress: &dyn ProgressSink,
+) -> Result<PairEvaluation, SimulationError> {
+    let _span = info_span!("pair", base = %pair.base_supplier.name, surge = %pair.surge_supplier.name).entered();
+    let plan = plan_pair(params, pair, config, progress)?;
+
+    // Step 3: Run Monte Carlo simulation
+    let simulating = Instant::now();
+    let stats = match config.convergence {
+        None => run_monte_carlo_simulation_with_progress(params, pair, &plan.monthly_order, &config.monte_carlo, progress),
+        Some(Convergence { tolerance, min_simulations }) => run_monte_carlo_until_converged(
+            params,
+            pair,
+            &plan.monthly_order,
+            &config.monte_carlo,
+            tolerance,
+            min_simulations,
+            config.monte_carlo.num_simulations,
+        ),
+    }?;
+    let simulating = simulating.elapsed();
+
+    // Step 4: Price the forecast error against the plan made knowing the actual demand
+    let cost_of_forecast_error = cost_of_forecast_error(params, pair, &plan.monthly_order, &config.monte_carlo, stats.num_simulations)?;
+    Ok(plan.simulated(MonteCarloStats { cost_of_forecast_error, ..stats }, simulating))
+}
+
+/// Do `work` on every pair, returned in the order given
+/// In parallel the pairs spread over the rayon thread pool and `progress` hears only as each
+/// pair finishes, since the bars of several pairs at once would interleave; one at a time it
+/// follows each pair's work too
+fn map_pairs<T: Send>(
+    pairs: &[&SupplierPair],
+    parallel: bool,
+    progress: &dyn ProgressSink,
+    work: impl Fn(&SupplierPair, &dyn ProgressSink) -> T + Sync,
+) -> Vec<T> {
+    #[cfg(feature = "parallel")]
+    if parallel {
+        let finished = AtomicUsize::new(0);
+        return pairs.par_iter()
+            .map(|pair| {
+                let done = work(pair, &NoProgress);
+                progress.on_pair_finished(finished.fetch_add(1, Ordering::Relaxed) + 1, pairs.len());
+                done
+            })
+            .collect();
+    }
+    // Without the `parallel` feature there is only one at a time
+    #[cfg(not(feature = "parallel"))]
+    let _ = parallel;
+    pairs.iter().map(|pair| work(pair, progress)).collect()
+}
+
+/// Evaluate every pair, in parallel or one at a time, returned in the order given
+/// Fails with the first pair's error, in the order given, when any pair fails
+pub fn evaluate_pairs(
+    params: &SimulationParams,
+    pairs: &[&SupplierPair],
+    config: &EvaluationConfig,
+    parallel: bool,
+    progress: &dyn ProgressSink,
+) -> Result<Vec<PairEvaluation>, SimulationError> {
+    map_pairs(pairs, parallel, progress, |pair, progress| evaluate_pair(params, pair, config, progress))
+        .into_iter()
+        .collect()
+}
+
+/// Optimize every pair, in parallel or one at a time, then share `selection`'s replication
+/// budget among them by ranking and selection instead of simulating each the same number of
+/// times; the evaluations come back in the order given
+pub fn evaluate_pairs_adaptively(
+    params: &SimulationParams,
+    pairs: &[&SupplierPair],
+    config: &EvaluationConfig,
+    selection: &SelectionConfig,
+    parallel: bool,
+    progress: &dyn ProgressSink,
+) -> Result<(Vec<PairEvaluation>, Selection), SimulationError> {
+    let plans = map_pairs(pairs, parallel, progress, |pair, progress| {
+        let _span = info_span!("pair", base = %pair.base_supplier.name, surge = %pair.surge_supplier.name).entered();
+        plan_pair(params, pair, config, progress)
+    });
+    let plans: Vec<PairPlan> = plans.into_iter().collect::<Result<_, _>>()?;
+    let orders: Vec<(&SupplierPair, &MonthlyOrder)> = pairs.iter().zip(&plans).map(|(pair, plan)| (*pair, &plan.monthly_order)).collect();
+    let (selected, outcome) = select_best(params, &orders, &config.monte_carlo, selection)?;
+    let evaluations = plans.into_iter().zip(selected).zip(pairs)
+        .map(|((plan, run), pair)| {
+            let cost_of_forecast_error = cost_of_forecast_error(params, pair, &plan.monthly_order, &config.monte_carlo, run.stats.num_simulations)?;
+            Ok(plan.simulated(MonteCarloStats { cost_of_forecast_error, ..run.stats }, run.simulating))
+        })
+        .collect::<Result<_, SimulationError>>()?;
+    Ok((evaluations, outcome))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::monte_carlo::tests::two_product_scenario;
+    use crate::progress::tests::RecordingProgress;
+    use crate::progress::NoProgress;
+
+    /// The fixture's pair and five variants with dearer or smaller suppliers
+    fn fixture_pairs() -> (SimulationParams, Vec<SupplierPair>) {
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        let variant = |base: (u32, f64), surge: (u32, f64)| {
+            let mut variant = pair.clone();
+            variant.base_supplier.fixed_capacity = base.0;
+            variant.base_supplier.unit_costs = HashMap::from([(ProductId(0), base.1), (ProductId(1), base.1)]);
+            variant.surge_supplier.fixed_capacity = surge.0;
+            variant.surge_supplier.unit_costs = HashMap::from([(ProductId(0), surge.1), (ProductId(1), surge.1)]);
+            variant
+        };
+        let pairs = vec![
+            variant((7_000, 75.0), (5_000, 80.0)),
+            variant((7_000, 75.0), (5_000, 90.0)),
+            variant((6_000, 72.0), (3_000, 80.0)),
+            variant((7_000, 80.0), (5_000, 80.0)),
+            variant((4_000, 75.0), (6_000, 85.0)),
+            variant((8_000, 78.0), (2_000, 82.0)),
+        ];
+        (params, pairs)
+    }
+
+    fn config(num_simulations: usize) -> EvaluationConfig {
+        EvaluationConfig {
+            monte_carlo: MonteCarloConfig { master_seed: 32, ..MonteCarloConfig::new(num_simulations) },
+            convergence: None,
+        }
+    }
+
+    #[test]
+    fn test_parallel_and_serial_runs_rank_pairs_alike() {
+        let (params, pairs) = fixture_pairs();
+        let pairs: Vec<&SupplierPair> = pairs.iter().collect();
+        let progress = RecordingProgress::default();
+        let serial = evaluate_pairs(&params, &pairs, &config(50), false, &NoProgress).unwrap();
+        let parallel = evaluate_pairs(&params, &pairs, &config(50), true, &progress).unwrap();
+
+        let means = |evaluations: &[PairEvaluation]| evaluations.iter().map(|e| e.stats.mean_profit).collect::<Vec<f64>>();
+        assert_eq!(means(&serial), means(&parallel));
+        let ranking = |evaluations: &[PairEvaluation]| {
+            let mut ranked: Vec<usize> = (0..evaluations.len()).collect();
+            ranked.sort_by(|&a, &b| evaluations[b].stats.mean_profit.total_cmp(&evaluations[a].stats.mean_profit));
+            ranked
+        };
+        assert_eq!(ranking(&serial), ranking(&parallel));
+        assert!(serial.iter().zip(&parallel).all(|(s, p)| s.optimal_quantities == p.optimal_quantities));
+
+        // Only pair completions reach the sink in parallel, one per pair; without the
+        // `parallel` feature the pairs ran one at a time after all
+        if !cfg!(feature = "parallel") {
+            return;
+        }
+        let mut finished = progress.finished.lock().unwrap().clone();
+        finished.sort();
+        assert_eq!(finished, (1..=pairs.len()).map(|done| (done, pairs.len())).collect::<Vec<_>>());
+        assert!(progress.optimizer.lock().unwrap().is_empty() && progress.replications.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_parallel_pairs() {
+        let (params, pairs) = fixture_pairs();
+        let pairs: Vec<&SupplierPair> = pairs.iter().collect();
+        for parallel in [false, true] {
+            let start = Instant::now();
+            evaluate_pairs(&params, &pairs, &config(500), parallel, &NoProgress).unwrap();
+            println!("parallel: {:5} {:>8.1?} for {} pairs", parallel, start.elapsed(), pairs.len());
+        }
+    }
+}
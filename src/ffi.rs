@@ -0,0 +1,182 @@
+//! C interface for embedding the simulator in applications written in other languages, such as
+//! a .NET service through P/Invoke; `include/supply_chain_sim.h` declares it for C
+//!
+//! Ownership: a `Scenario` from `scs_scenario_from_json` belongs to the caller until it hands it
+//! back to `scs_scenario_free`, and a string from `scs_evaluate` until it hands it back to
+//! `scs_string_free`; neither may be freed any other way. Strings passed in stay the caller's
+//! and are only read during the call. The message from `scs_last_error_message` belongs to the
+//! library and lasts until the thread next calls one of the other functions.
+//!
+//! No panic crosses into the caller: each function catches one and reports it like any other
+//! failure, as a null result with the reason kept for the thread to ask about
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use crate::progress::NoProgress;
+use crate::scenario::{Budget, Scenario};
+
+/// Outcome of the calling thread's last call into the library
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScsStatus {
+    Ok = 0,
+    /// A pointer that may not be null was
+    NullArgument = 1,
+    /// A string passed in was not UTF-8
+    InvalidUtf8 = 2,
+    /// A scenario or budget could not be read from its JSON, or the scenario is not valid
+    InvalidJson = 3,
+    /// The scenario could not be evaluated
+    SimulationFailed = 4,
+    /// The library panicked; the call did nothing the caller can use
+    Panicked = 5,
+}
+
+/// Why a call failed
+struct Failure(ScsStatus, String);
+
+thread_local! {
+    static LAST_ERROR: RefCell<(ScsStatus, Option<CString>)> = const { RefCell::new((ScsStatus::Ok, None)) };
+}
+
+fn record(status: ScsStatus, message: Option<String>) {
+    // A message with a NUL inside is cut short there rather than lost
+    let message = message.map(|message| {
+        let end = message.find('\0').unwrap_or(message.len());
+        CString::new(&message[..end]).expect("the message ends at its first NUL")
+    });
+    LAST_ERROR.with(|last| *last.borrow_mut() = (status, message));
+}
+
+/// Run `call` as the body of an exported function: clear the thread's last error, then record
+/// why `call` failed or panicked and return `failed` instead
+fn guard<T>(failed: T, call: impl FnOnce() -> Result<T, Failure>) -> T {
+    record(ScsStatus::Ok, None);
+    match catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(Failure(status, message))) => {
+            record(status, Some(message));
+            failed
+        }
+        Err(payload) => {
+            record(ScsStatus::Panicked, Some(format!("internal error: {}", panic_message(&*payload))));
+            failed
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload.downcast_ref::<&str>().copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("panic")
+}
+
+/// The string `text` points to, or why it cannot be read
+///
+/// # Safety
+/// `text` is null or points to a NUL-terminated string that lives through the call
+unsafe fn read_str<'a>(text: *const c_char, name: &str) -> Result<&'a str, Failure> {
+    if text.is_null() {
+        return Err(Failure(ScsStatus::NullArgument, format!("{} is null", name)));
+    }
+    CStr::from_ptr(text).to_str().map_err(|err| Failure(ScsStatus::InvalidUtf8, format!("{} is not UTF-8: {}", name, err)))
+}
+
+/// Read a scenario, `params` and `suppliers`, from JSON; null when it cannot be read or fails
+/// validation
+/// The scenario is the caller's to free with `scs_scenario_free`
+///
+/// # Safety
+/// `json` is null or points to a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn scs_scenario_from_json(json: *const c_char) -> *mut Scenario {
+    guard(ptr::null_mut(), || {
+        let json = read_str(json, "json")?;
+        let scenario = Scenario::from_json(json).map_err(|err| Failure(ScsStatus::InvalidJson, format!("invalid scenario: {}", err)))?;
+        Ok(Box::into_raw(Box::new(scenario)))
+    })
+}
+
+/// Free a scenario from `scs_scenario_from_json`; null does nothing
+///
+/// # Safety
+/// `scenario` is null or came from `scs_scenario_from_json` and has not been freed
+#[no_mangle]
+pub unsafe extern "C" fn scs_scenario_free(scenario: *mut Scenario) {
+    guard((), || {
+        if !scenario.is_null() {
+            drop(Box::from_raw(scenario));
+        }
+        Ok(())
+    })
+}
+
+/// Evaluate `scenario` on the budget in `config_json` (`simulations`, `seed`, and optionally
+/// `max_pairs` and `parallel`) and return the run as JSON, as `--format json` prints it; null
+/// when it cannot
+/// The string is the caller's to free with `scs_string_free`
+///
+/// # Safety
+/// `scenario` is null or a live scenario from `scs_scenario_from_json`, and `config_json` is
+/// null or points to a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn scs_evaluate(scenario: *const Scenario, config_json: *const c_char) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let scenario = scenario.as_ref().ok_or_else(|| Failure(ScsStatus::NullArgument, "scenario is null".to_string()))?;
+        let config = read_str(config_json, "config_json")?;
+        let budget: Budget = serde_json::from_str(config).map_err(|err| Failure(ScsStatus::InvalidJson, format!("invalid config: {}", err)))?;
+        let run = scenario.evaluate(&budget, &NoProgress).map_err(|err| Failure(ScsStatus::SimulationFailed, err.to_string()))?;
+        let json = serde_json::to_string(&run).expect("a run serializes");
+        Ok(CString::new(json).expect("JSON has no NUL outside strings, and escapes it inside").into_raw())
+    })
+}
+
+/// Free a string from `scs_evaluate`; null does nothing
+///
+/// # Safety
+/// `string` is null or came from `scs_evaluate` and has not been freed
+#[no_mangle]
+pub unsafe extern "C" fn scs_string_free(string: *mut c_char) {
+    guard((), || {
+        if !string.is_null() {
+            drop(CString::from_raw(string));
+        }
+        Ok(())
+    })
+}
+
+/// Outcome of the calling thread's last call, `Ok` when it succeeded
+#[no_mangle]
+pub extern "C" fn scs_last_error_code() -> ScsStatus {
+    LAST_ERROR.with(|last| last.borrow().0)
+}
+
+/// Why the calling thread's last call failed, or null when it succeeded
+/// The message belongs to the library and lasts until the thread next calls a function other
+/// than this one and `scs_last_error_code`
+#[no_mangle]
+pub extern "C" fn scs_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().1.as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panics_become_errors() {
+        let value = guard(-1, || -> Result<i32, Failure> { panic!("the lattice fell over") });
+        assert_eq!(value, -1);
+        assert_eq!(scs_last_error_code(), ScsStatus::Panicked);
+        let message = unsafe { CStr::from_ptr(scs_last_error_message()) };
+        assert_eq!(message.to_str().unwrap(), "internal error: the lattice fell over");
+
+        // The next call starts afresh
+        assert_eq!(guard(-1, || Ok(7)), 7);
+        assert_eq!(scs_last_error_code(), ScsStatus::Ok);
+        assert!(scs_last_error_message().is_null());
+    }
+}
@@ -0,0 +1,517 @@
+//! History of runs in a SQLite database (`sqlite` feature)
+//! Each recorded run keeps its scenario inputs and every pair's results, so a recommendation can
+//! be followed from week to week as the forecasts behind it are updated
+
+use std::error::Error;
+use std::path::Path;
+use rusqlite::{params, Connection};
+use crate::analysis::ScenarioResults;
+use crate::models::{committed_tier_name, group_name, MonteCarloStats, RankingMetric, RunOutput};
+use crate::schema;
+
+type HistoryResult<T> = Result<T, Box<dyn Error>>;
+
+/// Schema version of a current database, kept in its user_version pragma
+pub const SCHEMA_VERSION: i64 = 4;
+
+/// Migrations by the version they bring a database to: the first creates the schema, the
+/// second names each pair's backup supplier, if it had one, the third tells single-source
+/// strategies from suppliers splitting their capacity, the only pairs of a supplier with itself
+/// before it, and the fourth lists each strategy's pooled suppliers as a JSON array
+const MIGRATIONS: [&str; SCHEMA_VERSION as usize] = [
+    "CREATE TABLE runs (
+        id INTEGER PRIMARY KEY,
+        recorded_at TEXT NOT NULL DEFAULT (datetime('now')),
+        ranking TEXT NOT NULL,
+        -- Product names, sorted, as a JSON array
+        products TEXT NOT NULL
+    );
+    CREATE TABLE scenario_parameters (
+        run_id INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+        name TEXT NOT NULL,
+        value REAL NOT NULL,
+        PRIMARY KEY (run_id, name)
+    );
+    CREATE TABLE pairs (
+        id INTEGER PRIMARY KEY,
+        run_id INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+        base_supplier TEXT NOT NULL,
+        surge_supplier TEXT NOT NULL,
+        rank INTEGER NOT NULL,
+        is_best INTEGER NOT NULL
+    );
+    CREATE TABLE pair_results (
+        pair_id INTEGER PRIMARY KEY REFERENCES pairs(id) ON DELETE CASCADE,
+        num_simulations INTEGER NOT NULL,
+        mean_profit REAL NOT NULL,
+        std_dev_profit REAL NOT NULL,
+        mean_ci_low REAL NOT NULL,
+        mean_ci_high REAL NOT NULL,
+        p10 REAL,
+        p90 REAL,
+        var_5 REAL NOT NULL,
+        cvar_5 REAL NOT NULL,
+        prob_loss REAL NOT NULL,
+        exercise_probability REAL NOT NULL,
+        mean_fees_paid REAL NOT NULL
+    );",
+    "ALTER TABLE pairs ADD COLUMN backup_supplier TEXT;",
+    "ALTER TABLE pairs ADD COLUMN single_source INTEGER NOT NULL DEFAULT 0;
+    UPDATE pairs SET single_source = 1 WHERE base_supplier = surge_supplier;",
+    "ALTER TABLE pairs ADD COLUMN pooled_suppliers TEXT NOT NULL DEFAULT '[]';",
+];
+
+/// A recorded run as `list_runs` summarizes it
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunSummary {
+    pub id: i64,
+    /// UTC time of recording, e.g. "2024-05-06 09:30:00"
+    pub recorded_at: String,
+    pub ranking: String,
+    pub pairs: usize,
+    /// The best pair as "base + surge" (" + backup" if it had one) and its mean profit; None if no pair was simulated
+    pub best_pair: Option<String>,
+    pub best_mean_profit: Option<f64>,
+}
+
+/// A recorded run as `load_run` reads it back
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedRun {
+    pub id: i64,
+    pub recorded_at: String,
+    pub ranking: String,
+    pub products: Vec<String>,
+    /// Scenario inputs by name, e.g. ("Model A.mean_demand", 35000.0), sorted by name
+    pub parameters: Vec<(String, f64)>,
+    /// Every simulated pair, best first
+    pub pairs: Vec<RecordedPair>,
+}
+
+/// A pair's results in a recorded run
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedPair {
+    pub base_supplier: String,
+    pub surge_supplier: String,
+    pub backup_supplier: Option<String>,
+    pub pooled_suppliers: Vec<String>,
+    pub single_source: bool,
+    /// 1 for the best pair under the run's ranking
+    pub rank: usize,
+    pub num_simulations: usize,
+    pub mean_profit: f64,
+    pub std_dev_profit: f64,
+    pub mean_ci_low: f64,
+    pub mean_ci_high: f64,
+    pub p10: Option<f64>,
+    pub p90: Option<f64>,
+    pub var_5: f64,
+    pub cvar_5: f64,
+    pub prob_loss: f64,
+    pub exercise_probability: f64,
+    pub mean_fees_paid: f64,
+}
+
+impl RecordedPair {
+    /// The pair as "base + surge", with " + backup" if it had one
+    pub fn name(&self) -> String {
+        group_name(
+            &committed_tier_name(&self.base_supplier, &self.pooled_suppliers),
+            &self.surge_supplier,
+            self.backup_supplier.as_deref(),
+            self.single_source,
+        )
+    }
+}
+
+/// Open the history database at `path`, creating it if need be, with its schema up to date
+pub fn open(path: &Path) -> HistoryResult<Connection> {
+    let conn = Connection::open(path)?;
+    migrate(&conn)?;
+    Ok(conn)
+}
+
+/// Apply the migrations a database has not had yet, by its user_version
+/// A database written by a newer simulator is refused rather than misread
+pub fn migrate(conn: &Connection) -> HistoryResult<()> {
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "the history database has schema version {}, newer than the {} this simulator reads",
+            version, SCHEMA_VERSION
+        ).into());
+    }
+    for (migration, target) in MIGRATIONS.iter().zip(1..).skip(version as usize) {
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", target)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Record a run archived as JSON, in any layout `schema::load_run` reads, and return its id
+pub fn import_run(conn: &Connection, json: &str) -> HistoryResult<i64> {
+    record_run(conn, &schema::load_run(json)?)
+}
+
+/// Record a run and return its id
+pub fn record_run(conn: &Connection, run: &RunOutput) -> HistoryResult<i64> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO runs (ranking, products) VALUES (?1, ?2)",
+        params![ranking_name(run.ranking), product_set(run.params.products.iter().map(|product| product.name.as_str()))],
+    )?;
+    let run_id = tx.last_insert_rowid();
+
+    let mut insert = tx.prepare("INSERT INTO scenario_parameters (run_id, name, value) VALUES (?1, ?2, ?3)")?;
+    for (name, value) in scenario_parameters(run) {
+        insert.execute(params![run_id, name, value])?;
+    }
+
+    let mut ranked: Vec<(usize, &MonteCarloStats)> = run.pairs.iter().map(|pair| &pair.stats).enumerate().collect();
+    ranked.sort_by(|(_, a), (_, b)| run.ranking.score(b).total_cmp(&run.ranking.score(a)));
+    let mut insert_pair = tx.prepare(
+        "INSERT INTO pairs (run_id, base_supplier, surge_supplier, backup_supplier, single_source, pooled_suppliers, rank, is_best)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?;
+    let mut insert_results = tx.prepare(
+        "INSERT INTO pair_results (pair_id, num_simulations, mean_profit, std_dev_profit, mean_ci_low, mean_ci_high,
+            p10, p90, var_5, cvar_5, prob_loss, exercise_probability, mean_fees_paid)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+    )?;
+    for (rank, (index, stats)) in ranked.iter().enumerate() {
+        insert_pair.execute(params![
+            run_id, stats.base_supplier, stats.surge_supplier, stats.backup_supplier, stats.single_source,
+            serde_json::to_string(&stats.pooled_suppliers)?, rank + 1,
+            run.best_pair == Some(*index),
+        ])?;
+        insert_results.execute(params![
+            tx.last_insert_rowid(),
+            stats.num_simulations,
+            stats.mean_profit,
+            stats.std_dev_profit,
+            stats.mean_ci_low,
+            stats.mean_ci_high,
+            stats.p10(),
+            stats.p90(),
+            stats.var_5,
+            stats.cvar_5,
+            stats.prob_loss,
+            stats.exercise_probability,
+            stats.mean_fees_paid,
+        ])?;
+    }
+    drop((insert, insert_pair, insert_results));
+    tx.commit()?;
+    Ok(run_id)
+}
+
+/// Every recorded run, oldest first
+pub fn list_runs(conn: &Connection) -> HistoryResult<Vec<RunSummary>> {
+    query_summaries(conn, "", params![])
+}
+
+/// The runs of exactly the products named, whatever their order, oldest first, to follow how
+/// their best pair changes from run to run
+pub fn best_pair_over_time(conn: &Connection, product_set: &[&str]) -> HistoryResult<Vec<RunSummary>> {
+    query_summaries(conn, "WHERE runs.products = ?1", params![self::product_set(product_set.iter().copied())])
+}
+
+/// Read back a recorded run, or None if there is no run `run_id`
+pub fn load_run(conn: &Connection, run_id: i64) -> HistoryResult<Option<RecordedRun>> {
+    let mut runs = conn.prepare("SELECT recorded_at, ranking, products FROM runs WHERE id = ?1")?;
+    let mut rows = runs.query(params![run_id])?;
+    let Some(row) = rows.next()? else {
+        return Ok(None);
+    };
+    let (recorded_at, ranking, products): (String, String, String) = (row.get(0)?, row.get(1)?, row.get(2)?);
+
+    let parameters = conn.prepare("SELECT name, value FROM scenario_parameters WHERE run_id = ?1 ORDER BY name")?
+        .query_map(params![run_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let pairs = conn.prepare(
+        "SELECT base_supplier, surge_supplier, rank, num_simulations, mean_profit, std_dev_profit, mean_ci_low,
+            mean_ci_high, p10, p90, var_5, cvar_5, prob_loss, exercise_probability, mean_fees_paid, backup_supplier, single_source,
+            pooled_suppliers
+         FROM pairs JOIN pair_results ON pair_results.pair_id = pairs.id
+         WHERE pairs.run_id = ?1 ORDER BY rank",
+    )?
+        .query_map(params![run_id], |row| Ok(RecordedPair {
+            base_supplier: row.get(0)?,
+            surge_supplier: row.get(1)?,
+            backup_supplier: row.get(15)?,
+            pooled_suppliers: json_column(row, 17)?,
+            single_source: row.get(16)?,
+            rank: row.get(2)?,
+            num_simulations: row.get(3)?,
+            mean_profit: row.get(4)?,
+            std_dev_profit: row.get(5)?,
+            mean_ci_low: row.get(6)?,
+            mean_ci_high: row.get(7)?,
+            p10: row.get(8)?,
+            p90: row.get(9)?,
+            var_5: row.get(10)?,
+            cvar_5: row.get(11)?,
+            prob_loss: row.get(12)?,
+            exercise_probability: row.get(13)?,
+            mean_fees_paid: row.get(14)?,
+        }))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(Some(RecordedRun { id: run_id, recorded_at, ranking, products: serde_json::from_str(&products)?, parameters, pairs }))
+}
+
+/// Mean profit of every pair across recorded runs, each run a scenario named by its id and time
+pub fn compare_runs(runs: &[RecordedRun]) -> ScenarioResults {
+    let mut pairs: Vec<String> = Vec::new();
+    for pair in runs.iter().flat_map(|run| &run.pairs) {
+        if !pairs.contains(&pair.name()) {
+            pairs.push(pair.name());
+        }
+    }
+    let mean_profits = runs.iter()
+        .map(|run| {
+            pairs.iter()
+                .map(|name| run.pairs.iter().find(|pair| pair.name() == *name).map(|pair| pair.mean_profit))
+                .collect()
+        })
+        .collect();
+    let scenarios = runs.iter().map(|run| format!("Run {} ({})", run.id, run.recorded_at)).collect();
+    ScenarioResults::new(scenarios, pairs, mean_profits)
+}
+
+/// Scenario inputs that differ between two runs, as (name, first value, second value), with
+/// None where a run lacks the input
+pub fn parameter_changes(first: &RecordedRun, second: &RecordedRun) -> Vec<(String, Option<f64>, Option<f64>)> {
+    let value = |run: &RecordedRun, name: &str| run.parameters.iter().find(|(other, _)| other == name).map(|(_, value)| *value);
+    let mut names: Vec<&String> = first.parameters.iter().chain(&second.parameters).map(|(name, _)| name).collect();
+    names.sort();
+    names.dedup();
+    names.into_iter()
+        .map(|name| (name.clone(), value(first, name), value(second, name)))
+        .filter(|(_, first, second)| first != second)
+        .collect()
+}
+
+/// Summaries of the runs matching `filter`, a WHERE clause over `runs`, oldest first
+fn query_summaries(conn: &Connection, filter: &str, params: impl rusqlite::Params) -> HistoryResult<Vec<RunSummary>> {
+    let sql = format!(
+        "SELECT runs.id, runs.recorded_at, runs.ranking,
+            (SELECT COUNT(*) FROM pairs WHERE pairs.run_id = runs.id),
+            best.base_supplier, best.surge_supplier, best.backup_supplier, best.single_source,
+            best_results.mean_profit, best.pooled_suppliers
+         FROM runs
+         LEFT JOIN pairs AS best ON best.run_id = runs.id AND best.is_best
+         LEFT JOIN pair_results AS best_results ON best_results.pair_id = best.id
+         {} ORDER BY runs.id",
+        filter
+    );
+    let summaries = conn.prepare(&sql)?
+        .query_map(params, |row| {
+            let best: Option<(String, String)> = row.get::<_, Option<String>>(4)?.zip(row.get(5)?);
+            let backup: Option<String> = row.get(6)?;
+            let single_source: Option<bool> = row.get(7)?;
+            let pooled: Vec<String> = if row.get_ref(9)?.as_str_or_null()?.is_some() { json_column(row, 9)? } else { Vec::new() };
+            Ok(RunSummary {
+                id: row.get(0)?,
+                recorded_at: row.get(1)?,
+                ranking: row.get(2)?,
+                pairs: row.get(3)?,
+                best_pair: best.map(|(base, surge)| {
+                    group_name(&committed_tier_name(&base, &pooled), &surge, backup.as_deref(), single_source.unwrap_or(false))
+                }),
+                best_mean_profit: row.get(8)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(summaries)
+}
+
+/// Column `index` of `row`, a JSON array of names
+fn json_column(row: &rusqlite::Row, index: usize) -> rusqlite::Result<Vec<String>> {
+    let text: String = row.get(index)?;
+    serde_json::from_str(&text)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(err)))
+}
+
+/// Every scenario input of a run by name: season settings, then each product's price, costs
+/// and demand, then each supplier's capacity, lead time and costs
+fn scenario_parameters(run: &RunOutput) -> Vec<(String, f64)> {
+    let params = &run.params;
+    let mut parameters = vec![
+        ("season_months".to_string(), params.season_months as f64),
+        ("order_change_fee_per_product".to_string(), params.order_change_fee_per_product),
+        ("cancellation_fee_per_unit".to_string(), params.cancellation_fee_per_unit),
+    ];
+    parameters.extend(params.order_change_fees.iter().enumerate()
+        .map(|(index, fee)| (format!("order_change_fee.{}", index + 1), *fee)));
+    parameters.extend(params.regional_disruptions.iter()
+        .map(|disruption| (format!("regional_disruption.{}", disruption.region), disruption.monthly_probability)));
+    for product in &params.products {
+        let name = |field: &str| format!("{}.{}", product.name, field);
+        parameters.push((name("selling_price"), product.selling_price));
+        parameters.push((name("liquidation_price"), product.liquidation_price));
+        parameters.push((name("monthly_holding_cost"), product.monthly_holding_cost));
+        if let Some(dp) = params.get_demand_params(product.id) {
+            parameters.push((name("mean_demand"), dp.mean_demand));
+            parameters.push((name("std_dev_demand"), dp.std_dev_demand));
+            parameters.push((name("actual_mean_demand"), dp.actual_mean_demand));
+            parameters.push((name("actual_std_dev_demand"), dp.actual_std_dev_demand));
+        }
+    }
+    for supplier in &run.suppliers {
+        let name = |field: &str| format!("{}.{}", supplier.name, field);
+        parameters.push((name("fixed_capacity"), supplier.fixed_capacity as f64));
+        parameters.push((name("lead_time_months"), supplier.lead_time_months as f64));
+        parameters.push((name("setup_cost"), supplier.setup_cost));
+        for product in &params.products {
+            if let Some(cost) = supplier.unit_costs.get(&product.id) {
+                parameters.push((name(&format!("unit_cost.{}", product.name)), *cost));
+            }
+        }
+    }
+    parameters
+}
+
+/// Product names as stored in `runs.products`: sorted, as a JSON array
+fn product_set<'a>(names: impl Iterator<Item = &'a str>) -> String {
+    let mut names: Vec<&str> = names.collect();
+    names.sort_unstable();
+    serde_json::to_string(&names).expect("a list of names serializes")
+}
+
+/// A ranking as --rank-by names it
+fn ranking_name(ranking: RankingMetric) -> &'static str {
+    match ranking {
+        RankingMetric::MeanProfit => "mean-profit",
+        RankingMetric::ExpectedShortfall => "expected-shortfall",
+        RankingMetric::OperationalProfit => "operational-profit",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reporting::tests::fixture_run;
+
+    /// A fresh database file in the temporary directory, removed when the test is done with it
+    struct TempDb(std::path::PathBuf);
+
+    impl TempDb {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("supply-chain-sim-{}-{}.sqlite", name, std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            TempDb(path)
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_record_and_query_runs() {
+        let db = TempDb::new("history");
+        let first = fixture_run();
+        let mut second = fixture_run();
+        second.params.demand_params[0].mean_demand *= 1.1;
+        second.pairs[0].stats.mean_profit += 1_000.0;
+
+        let conn = open(&db.0).unwrap();
+        let first_id = record_run(&conn, &first).unwrap();
+        let second_id = record_run(&conn, &second).unwrap();
+        drop(conn);
+
+        // Reopening finds the schema current and both runs in order
+        let conn = open(&db.0).unwrap();
+        let runs = list_runs(&conn).unwrap();
+        assert_eq!(runs.iter().map(|run| run.id).collect::<Vec<_>>(), vec![first_id, second_id]);
+        let best = &first.pairs[first.best_pair.unwrap()].stats;
+        assert_eq!(runs[0].best_pair, Some(best.pair_name()));
+        assert_eq!(runs[0].best_mean_profit, Some(best.mean_profit));
+        assert_eq!((runs[0].pairs, runs[0].ranking.as_str()), (2, "mean-profit"));
+
+        let loaded = load_run(&conn, second_id).unwrap().unwrap();
+        assert_eq!(loaded.products, vec!["Gadget".to_string(), "Widget".to_string()]);
+        assert_eq!(loaded.pairs.iter().map(|pair| pair.rank).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(loaded.pairs[0].mean_profit >= loaded.pairs[1].mean_profit);
+        assert!(load_run(&conn, 99).unwrap().is_none());
+
+        // Only the forecast that was changed differs
+        let changes = parameter_changes(&load_run(&conn, first_id).unwrap().unwrap(), &loaded);
+        assert_eq!(changes.len(), 1, "{:?}", changes);
+        assert_eq!(changes[0].0, "Widget.mean_demand");
+
+        let diff = compare_runs(&[load_run(&conn, first_id).unwrap().unwrap(), loaded]);
+        assert_eq!(diff.pairs.len(), 2);
+        let changed = &second.pairs[0].stats;
+        let column = diff.pairs.iter().position(|name| *name == changed.pair_name()).unwrap();
+        assert_eq!(diff.mean_profits[1][column].unwrap() - diff.mean_profits[0][column].unwrap(), 1_000.0);
+
+        assert_eq!(best_pair_over_time(&conn, &["Widget", "Gadget"]).unwrap().len(), 2);
+        assert!(best_pair_over_time(&conn, &["Widget"]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_archived_runs_are_imported_in_the_current_layout() {
+        let db = TempDb::new("import");
+        let conn = open(&db.0).unwrap();
+        let run_id = import_run(&conn, include_str!("../tests/fixtures/archived_run_v1.json")).unwrap();
+        let loaded = load_run(&conn, run_id).unwrap().unwrap();
+        assert_eq!(loaded.products, vec!["Model A".to_string()]);
+        assert_eq!(loaded.pairs.iter().map(RecordedPair::name).collect::<Vec<_>>(), vec!["FarFarAway + Nearshore", "FarFarAway (single-source)"]);
+        assert_eq!(loaded.pairs[0].mean_profit, 1_900_000.0);
+
+        let future = fixture_run();
+        let json = serde_json::to_string(&future).unwrap().replacen("\"schema_version\":3", "\"schema_version\":99", 1);
+        assert!(import_run(&conn, &json).is_err());
+        assert_eq!(list_runs(&conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_newer_schema_is_refused() {
+        let db = TempDb::new("schema");
+        let conn = open(&db.0).unwrap();
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+        // Migrating a current database changes nothing
+        migrate(&conn).unwrap();
+
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+        drop(conn);
+        assert!(open(&db.0).is_err());
+    }
+
+    #[test]
+    fn test_older_schema_is_migrated() {
+        let db = TempDb::new("migrate");
+        let conn = Connection::open(&db.0).unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+        conn.execute("INSERT INTO runs (ranking, products) VALUES ('mean-profit', '[]')", []).unwrap();
+        conn.execute("INSERT INTO runs (ranking, products) VALUES ('mean-profit', '[]')", []).unwrap();
+        conn.execute("INSERT INTO pairs (run_id, base_supplier, surge_supplier, rank, is_best) VALUES (1, 'Far', 'Near', 1, 1)", []).unwrap();
+        conn.execute("INSERT INTO pairs (run_id, base_supplier, surge_supplier, rank, is_best) VALUES (2, 'Far', 'Far', 1, 1)", []).unwrap();
+        drop(conn);
+
+        // Pairs recorded before backups existed read back without one, and a supplier paired
+        // with itself then could only be a single source
+        let conn = open(&db.0).unwrap();
+        let mut triple = fixture_run();
+        triple.pairs[0].stats.backup_supplier = Some("Spare".to_string());
+        let triple_id = record_run(&conn, &triple).unwrap();
+        let runs = list_runs(&conn).unwrap();
+        assert_eq!(runs[0].best_pair.as_deref(), Some("Far + Near"));
+        assert_eq!(runs[1].best_pair.as_deref(), Some("Far (single-source)"));
+        let names: Vec<String> = load_run(&conn, triple_id).unwrap().unwrap().pairs.iter().map(RecordedPair::name).collect();
+        let backed_up = triple.pairs[0].stats.pair_name();
+        assert!(backed_up.ends_with(" + Spare") && names.contains(&backed_up), "{:?}", names);
+
+        let mut pooled = fixture_run();
+        pooled.pairs[0].stats.pooled_suppliers = vec!["Extra".to_string(), "Spare".to_string()];
+        let pooled_id = record_run(&conn, &pooled).unwrap();
+        let names: Vec<String> = load_run(&conn, pooled_id).unwrap().unwrap().pairs.iter().map(RecordedPair::name).collect();
+        assert!(names.iter().any(|name| name.contains(" & Extra & Spare + ")), "{:?}", names);
+    }
+}
@@ -0,0 +1,96 @@
+//! Monte Carlo simulation of multi-supplier supply chains under uncertain demand
+//!
+//! A season's plan pairs a long-lead-time base supplier, committed ahead of the season, with a
+//! short-lead-time surge supplier whose orders can still change once demand is seen. The
+//! [`prelude`] holds what it takes to describe a scenario, generate supplier pairs, optimize a
+//! plan for a pair and simulate it, with [`SimulationError`](error::SimulationError) saying why
+//! when one of them cannot:
+//!
+//! ```no_run
+//! use supply_chain_sim::prelude::*;
+//!
+//! fn best_mean_profit(params: &SimulationParams, suppliers: &[Supplier]) -> Result<Option<f64>, SimulationError> {
+//!     let mut best = None;
+//!     for pair in generate_intelligent_pairs(suppliers, false) {
+//!         let quantities = find_optimal_production_quantities(params, &pair)?;
+//!         let order = split_order_quantities(&quantities, &pair, params);
+//!         let mean_profit = run_monte_carlo_simulation(params, &pair, &order, &MonteCarloConfig::new(500))?.mean_profit;
+//!         best = Some(best.map_or(mean_profit, |best: f64| best.max(mean_profit)));
+//!     }
+//!     Ok(best)
+//! }
+//! ```
+//!
+//! The other modules back the `supply-chain-sim` binary and may change between releases.
+
+#![warn(missing_docs)]
+
+pub mod error;
+#[doc(hidden)]
+pub mod models;
+#[doc(hidden)]
+pub mod analysis;
+#[doc(hidden)]
+pub mod options;
+mod demand;
+#[doc(hidden)]
+pub mod simulation;
+#[doc(hidden)]
+pub mod optimizer;
+#[doc(hidden)]
+pub mod monte_carlo;
+#[doc(hidden)]
+pub mod reporting;
+#[doc(hidden)]
+pub mod pairing;
+#[doc(hidden)]
+pub mod pairing_utils;
+#[doc(hidden)]
+pub mod capacity;
+mod stats;
+mod streaming;
+#[doc(hidden)]
+pub mod progress;
+#[doc(hidden)]
+pub mod evaluation;
+#[doc(hidden)]
+pub mod selection;
+#[doc(hidden)]
+pub mod planning;
+#[doc(hidden)]
+pub mod validation;
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub mod scenario;
+#[doc(hidden)]
+pub mod schema;
+#[cfg(feature = "charts")]
+mod charts;
+#[cfg(feature = "templates")]
+mod templates;
+#[cfg(feature = "sqlite")]
+#[doc(hidden)]
+pub mod history;
+#[cfg(feature = "money")]
+#[doc(hidden)]
+pub mod money;
+#[cfg(feature = "wasm")]
+#[doc(hidden)]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+#[doc(hidden)]
+pub mod ffi;
+#[cfg(feature = "server")]
+#[doc(hidden)]
+pub mod server;
+
+/// The stable surface of the simulator: scenario inputs, supplier pairing, plan optimization
+/// and Monte Carlo evaluation
+pub mod prelude {
+    pub use crate::error::SimulationError;
+    pub use crate::models::{CapacityPolicy, Flex, GoodwillModel, MonteCarloStats, MonthlyOrder, Product, ProductDemandParams, ProductId, ProductOrder, SimulationParams, Supplier, SupplierId, SupplierPair, TimeStep};
+    pub use crate::monte_carlo::{run_monte_carlo_simulation, MonteCarloConfig};
+    pub use crate::optimizer::find_optimal_production_quantities;
+    pub use crate::pairing::generate_intelligent_pairs;
+    pub use crate::simulation::split_order_quantities;
+}
@@ -1,169 +1,241 @@
-mod models;
-mod options;
-mod demand;
-mod simulation;
-mod optimizer;
-mod monte_carlo;
-mod reporting;
-mod pairing;
-mod pairing_utils;
-mod capacity;
-
-use std::collections::HashMap;
-use std::io;
-use models::{Product, ProductDemandParams, Supplier, SimulationParams};
-use optimizer::find_optimal_production_quantities_with_diagnostics;
-use simulation::split_order_quantities;
-use monte_carlo::run_monte_carlo_simulation;
-use reporting::{display_optimization_start, display_finding_optimal, display_found_quantities,
-                display_combination_results, display_all_results, display_best_result};
-use pairing::generate_intelligent_pairs;
-use pairing_utils::{quick_profit_estimate, is_pair_promising};
+mod cli;
+#[path = "../examples/demo/scenario.rs"]
+mod scenario;
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process;
+use clap::Parser;
+use tracing_subscriber::EnvFilter;
+use serde::Deserialize;
+use supply_chain_sim::analysis::{compare_plans, default_perturbations, dual_source_value, evpi, option_value_realized, sensitivity, NamedPlan, ScenarioResults};
+use cli::{Cli, Command, PairNames, SimulationCount};
+use supply_chain_sim::error::SimulationError;
+use supply_chain_sim::models::{CapacityPolicy, MonteCarloStats, ProductId, RejectedPair, RejectionReason, RunOutput, SupplierPair, SimulationParams};
+use supply_chain_sim::optimizer::find_optimal_plan;
+use supply_chain_sim::planning::{multi_season, MultiSeasonConfig};
+use supply_chain_sim::options::exercise_boundary;
+use supply_chain_sim::capacity::create_order;
+use supply_chain_sim::simulation::{check_capacity, project_mean_demand, split_order_quantities};
+use supply_chain_sim::evaluation::{evaluate_pairs, evaluate_pairs_adaptively, Convergence, EvaluationConfig};
+use supply_chain_sim::selection::{SelectionConfig, SelectionMode};
+use supply_chain_sim::monte_carlo::{compare, run_monte_carlo_simulation, MonteCarloConfig};
+use supply_chain_sim::progress::{NoProgress, ProgressSink, TerminalProgress};
+use supply_chain_sim::reporting::{export_results_csv, export_monthly_csv, export_traces_csv, write_markdown_report, write_scenario_matrix, Reporter, Verbosity};
+use supply_chain_sim::scenario::{Budget, Scenario};
+use supply_chain_sim::schema::{self, SCHEMA_VERSION};
+use supply_chain_sim::pairing::{generate_pairs_among, generate_pooled_strategies, generate_single_supplier_strategies, generate_split_capacity_pairs, prune_dominated, rejected_for_diversity, score_suppliers, shortlist, with_backups};
+use supply_chain_sim::pairing_utils::{promising_by_estimate, screen_pair, ScreeningReport};
+#[cfg(feature = "sqlite")]
+use supply_chain_sim::history;
+
+/// Relative confidence interval half-width at which `--simulations auto` stops a pair
+const AUTO_TOLERANCE: f64 = 0.005;
+/// Fewest and most replications `--simulations auto` runs per pair
+const AUTO_MIN_SIMULATIONS: usize = 200;
+const AUTO_MAX_SIMULATIONS: usize = 10_000;
 
 fn main() {
-    // Initialize products
-    let products = vec![
-        Product {
-            id: 0,
-            name: "Model A".to_string(),
-            selling_price: 230.0,
-            liquidation_price: 144.0,
-            monthly_holding_cost: 4.60,
-        },
-        Product {
-            id: 1,
-            name: "Model B".to_string(),
-            selling_price: 280.0,
-            liquidation_price: 175.0,
-            monthly_holding_cost: 5.60,
-        },
-    ];
-
-    // Initialize demand parameters per product
-    // Expected demand is what the model uses for decisions
-    // Actual demand is what happens during the season (may differ)
-    let demand_params = vec![
-        ProductDemandParams {
-            product_id: 0,
-            mean_demand: 35_000.0,
-            std_dev_demand: 7_000.0,
-            actual_mean_demand: 32_000.0,
-            actual_std_dev_demand: 7_000.0,
-        },
-        ProductDemandParams {
-            product_id: 1,
-            mean_demand: 25_000.0,
-            std_dev_demand: 8_000.0,
-            actual_mean_demand: 28_000.0,
-            actual_std_dev_demand: 8_000.0,
-        },
-    ];
+    let cli = Cli::parse();
+
+    // Diagnostics go to stderr, and only as RUST_LOG asks, e.g. RUST_LOG=supply_chain_sim=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
 
+    // The demo scenario: two products from four suppliers over a May to December season
     let params = SimulationParams {
-        products,
-        demand_params,
-        order_change_fee: 2_000_000.0,
+        // Set with --regional-disruption
+        regional_disruptions: cli.regional_disruption.clone(),
+        // Set with --capacity-policy
+        capacity_policy: cli.capacity_policy,
+        // Set with --time-step
+        time_step: cli.time_step,
+        // Set with --cost-of-capital
+        cost_of_capital_annual: cli.cost_of_capital,
+        // Set with --expansion-offer
+        expansion_offers: cli.expansion_offer.clone(),
+        ..scenario::params()
     };
+    let suppliers = scenario::suppliers();
 
-    // Initialize suppliers with unit costs per product
-    // Product 0 = Model A, Product 1 = Model B
-    let suppliers = vec![
-        Supplier {
-            id: 0,
-            name: "FarFarAway".to_string(),
-            fixed_capacity: 60_000,
-            lead_time_months: 4,
-            unit_costs: HashMap::from([
-                (0, 160.0),  // Model A
-                (1, 170.0),  // Model B (more complex to produce)
-            ]),
-            setup_cost: 1_000_000.0,
-        },
-        Supplier {
-            id: 1,
-            name: "FarAway".to_string(),
-            fixed_capacity: 60_000,
-            lead_time_months: 3,
-            unit_costs: HashMap::from([
-                (0, 160.0),  // Model A
-                (1, 170.0),  // Model B
-            ]),
-            setup_cost: 2_000_000.0,
-        },
-        Supplier {
-            id: 2,
-            name: "PrettyClose".to_string(),
-            fixed_capacity: 35_000,
-            lead_time_months: 0,
-            unit_costs: HashMap::from([
-                (0, 170.0),  // Model A (premium for shorter lead time)
-                (1, 180.0),  // Model B
-            ]),
-            setup_cost: 1_000_000.0,
-        },
-        Supplier {
-            id: 3,
-            name: "VeryClose".to_string(),
-            fixed_capacity: 40_000,
-            lead_time_months: 0,
-            unit_costs: HashMap::from([
-                (0, 170.0),  // Model A
-                (1, 180.0),  // Model B
-            ]),
-            setup_cost: 2_000_000.0,
-        },
-    ];
+    // Everything printed goes through the reporter, which stays quiet under --format json
+    let reporter = Reporter::new(cli.format, cli.verbosity(), cli.formatter(), cli.color());
+    #[cfg(feature = "sqlite")]
+    if let Some(Command::History { db, diff, products, import }) = &cli.command {
+        if let Err(err) = show_history(&reporter, db, diff.as_deref(), products.as_deref(), import) {
+            eprintln!("Could not read the history in {}: {}", db.display(), err);
+            process::exit(1);
+        }
+        return;
+    }
+    #[cfg(feature = "server")]
+    if let Some(Command::Serve { addr }) = &cli.command {
+        eprintln!("Serving runs on http://{}", addr);
+        if let Err(err) = supply_chain_sim::server::serve(*addr) {
+            eprintln!("Could not serve on {}: {}", addr, err);
+            process::exit(1);
+        }
+        return;
+    }
+    if let Some(Command::Scenarios { files }) = &cli.command {
+        let SimulationCount::Fixed(num_simulations) = cli.simulations else {
+            eprintln!("scenarios needs a fixed number of --simulations");
+            process::exit(2);
+        };
+        compare_scenarios(&reporter, &cli, files, num_simulations);
+        return;
+    }
+    reporter.display_scenario(&params);
 
-    println!("╔══════════════════════════════════════════════════════════════════════════════╗");
-    println!("║               MULTI-PRODUCT SUPPLY CHAIN SIMULATION                         ║");
-    println!("╚══════════════════════════════════════════════════════════════════════════════╝\n");
+    // Shortlist the best-scored base and surge candidates, all of them unless --shortlist says
+    let rules = cli.pairing_rules();
+    let scores = score_suppliers(&params, &suppliers, &cli.score_weights);
+    let shortlist = shortlist(&suppliers, &scores, &rules, cli.shortlist.unwrap_or(usize::MAX));
 
-    println!("Products:");
-    for product in &params.products {
-        let dp = params.get_demand_params(product.id).unwrap();
-        println!("  {}: Price=${:.0}, Holding=${:.2}/mo, Liquidation=${:.0}",
-                 product.name, product.selling_price, product.monthly_holding_cost, product.liquidation_price);
-        println!("      Expected Demand: {:.0} ± {:.0}, Actual: {:.0} ± {:.0}",
-                 dp.mean_demand, dp.std_dev_demand, dp.actual_mean_demand, dp.actual_std_dev_demand);
+    // Generate supplier pairs under the pairing rules (long lead time base + short lead time
+    // surge by default) and single-supplier strategies, to show what a second supplier is worth
+    let mut pairs = generate_pairs_among(&shortlist.base, &shortlist.surge, &rules);
+    if cli.backup {
+        pairs = with_backups(pairs, &shortlist.surge, &rules);
+    }
+    // Pool the committed capacity of several suppliers when no pair could meet demand
+    let shortlisted = shortlist.suppliers(&suppliers);
+    pairs.extend(generate_pooled_strategies(&params, &shortlisted, &rules, cli.pooling_coverage));
+    pairs.extend(generate_single_supplier_strategies(&shortlisted, &rules));
+    if cli.split_capacity {
+        pairs.extend(generate_split_capacity_pairs(&shortlist.surge, &rules));
     }
-    println!();
 
-    // Generate intelligent supplier pairs (long lead time + short lead time)
-    let pairs = generate_intelligent_pairs(&suppliers);
+    if let Some(Command::ComparePairs { first, second }) = &cli.command {
+        let SimulationCount::Fixed(num_simulations) = cli.simulations else {
+            eprintln!("compare-pairs needs a fixed number of --simulations");
+            process::exit(2);
+        };
+        compare_pairs(&reporter, &params, &pairs, first, second, num_simulations);
+        return;
+    }
 
-    println!("\n{} supplier pairs generated. Filtering for promising combinations...", pairs.len());
+    if let Some(Command::PlanSeasons { pair, seasons, demand_growth, discount_rate }) = &cli.command {
+        let SimulationCount::Fixed(num_simulations) = cli.simulations else {
+            eprintln!("plan-seasons needs a fixed number of --simulations");
+            process::exit(2);
+        };
+        plan_seasons(&reporter, &params, &pairs, pair, *seasons, *demand_growth, *discount_rate, num_simulations);
+        return;
+    }
+
+    if let Some(Command::ComparePlans { pair, files }) = &cli.command {
+        let SimulationCount::Fixed(num_simulations) = cli.simulations else {
+            eprintln!("compare-plans needs a fixed number of --simulations");
+            process::exit(2);
+        };
+        compare_plan_files(&reporter, &params, &pairs, pair, files, num_simulations);
+        return;
+    }
+
+    if let Some(Command::Evaluate { pair, base, surge }) = &cli.command {
+        let SimulationCount::Fixed(num_simulations) = cli.simulations else {
+            eprintln!("evaluate needs a fixed number of --simulations");
+            process::exit(2);
+        };
+        evaluate_plan(&reporter, &params, &pairs, pair, base, surge, num_simulations, cli.target_profit);
+        return;
+    }
+
+    // Drop pairs another pair sharing one supplier beats on every count
+    let generated = pairs.len();
+    let (pairs, dominated) = prune_dominated(pairs, &params);
 
     // Quick profitability filter to skip obviously poor pairs
-    let min_profit_threshold = 0.0; // Set to 0 to keep all pairs, or higher to filter aggressively
-    let promising_pairs: Vec<_> = pairs.iter()
-        .filter(|pair| is_pair_promising(&params, pair, min_profit_threshold))
+    // Raise min_profit to filter aggressively, or set max_cost_to_price to skip pairs whose
+    // units cost too much of their selling price
+    let criteria = cli.screening_criteria();
+    let screened: Vec<(&SupplierPair, ScreeningReport)> = pairs.iter()
+        .map(|pair| (pair, screen_pair(&params, pair, &criteria)))
         .collect();
+    // Most promising first, so a time budget is spent on the likeliest winners
+    let promising_pairs = promising_by_estimate(&screened);
+    // Pairs the diversity rules kept out of generation and dominated pairs are reported
+    // alongside those screened out
+    let mut rejected_pairs = rejected_for_diversity(&shortlist.base, &shortlist.surge, &rules, cli.backup);
+    rejected_pairs.extend(dominated);
+    rejected_pairs.extend(screened.iter()
+        .filter_map(|(pair, report)| report.failures.first()
+            .map(|failure| RejectedPair::new(pair, RejectionReason::Screening(failure.clone())))));
 
-    println!("{} pairs passed initial profitability screening.\n", promising_pairs.len());
+    reporter.display_shortlist(&shortlist.excluded);
+    reporter.display_pair_screening(generated, promising_pairs.len());
+    reporter.display_screening_summary(&rejected_pairs);
 
     // Number of Monte Carlo simulations per supplier combination
-    // 250 provides good statistical confidence; 500+ for publication-quality
-    let num_simulations = 500;
+    // The precision this buys is reported per pair as the confidence interval half-width;
+    // with --simulations auto each pair instead runs until that half-width is small enough
+    let num_simulations = match cli.simulations {
+        SimulationCount::Fixed(count) => count,
+        SimulationCount::Auto => AUTO_MAX_SIMULATIONS,
+    };
+    let evaluation_config = EvaluationConfig {
+        monte_carlo: MonteCarloConfig {
+            sample_retention: cli.sample_retention(),
+            trace_samples: cli.trace_samples,
+            ..MonteCarloConfig::new(num_simulations)
+        },
+        convergence: match cli.simulations {
+            SimulationCount::Fixed(_) => None,
+            SimulationCount::Auto => Some(Convergence { tolerance: AUTO_TOLERANCE, min_simulations: AUTO_MIN_SIMULATIONS }),
+        },
+    };
+
+    // Rank pairs by expected profit unless --rank-by asks for the safest downside or for
+    // operational profit, which leaves out setup costs amortized over several seasons
+    let ranking = cli.rank_by;
+
+    // Progress bars on stderr, only when someone is watching it and wants more than the summary
+    let progress: Box<dyn ProgressSink> = if io::stderr().is_terminal() && reporter.shows(Verbosity::Normal) {
+        Box::new(TerminalProgress::new())
+    } else {
+        Box::new(NoProgress)
+    };
+
+    // Evaluate the promising pairs, in parallel unless --serial, and report each in turn once
+    // all are done so their output never interleaves; --selection adaptive shares the
+    // replications among them instead of running --simulations each
+    let evaluations = match cli.selection {
+        SelectionMode::Fixed => or_exit(evaluate_pairs(&params, &promising_pairs, &evaluation_config, !cli.serial, progress.as_ref())),
+        SelectionMode::Adaptive => {
+            let budget = cli.selection_budget.unwrap_or(num_simulations * promising_pairs.len());
+            let selection_config = SelectionConfig { confidence: cli.selection_confidence, ..SelectionConfig::new(budget) };
+            let (evaluations, selection) = or_exit(evaluate_pairs_adaptively(
+                &params,
+                &promising_pairs,
+                &evaluation_config,
+                &selection_config,
+                !cli.serial,
+                progress.as_ref(),
+            ));
+            reporter.display_selection(&selection);
+            evaluations
+        }
+    };
 
-    let mut all_results = Vec::new();
-    let mut best_mean_profit = f64::NEG_INFINITY;
+    let mut pair_runs = Vec::new();
+    let mut best_score = f64::NEG_INFINITY;
+    let mut best_plan = None;
+    let mut best_pair = None;
 
-    // Iterate over promising supplier pairs
-    for pair in &promising_pairs {
-        // Quick profit estimate to show potential
-        let quick_estimate = quick_profit_estimate(&params, pair);
-        
+    for (pair, evaluation) in promising_pairs.iter().zip(&evaluations) {
         // Display evaluation progress
-        display_optimization_start(&pair.base_supplier.name, &pair.surge_supplier.name);
-        println!("  Quick estimate: ${:.2}", quick_estimate);
-
-        // Step 1: Find optimal production quantities with diagnostics
-        display_finding_optimal();
-        let _ = io::Write::flush(&mut io::stdout());
-        let optimal_quantities = find_optimal_production_quantities_with_diagnostics(&params, &pair);
-        
+        reporter.display_optimization_start(pair);
+        reporter.display_quick_estimate(evaluation.quick_estimate);
+
         // Build display quantities with names
-        let display_quantities: Vec<(usize, String, u32)> = optimal_quantities.iter()
+        reporter.display_finding_optimal();
+        let display_quantities: Vec<(ProductId, String, u32)> = evaluation.optimal_quantities.iter()
             .map(|(id, qty)| {
                 let name = params.get_product(*id)
                     .map(|p| p.name.clone())
@@ -171,45 +243,398 @@ fn main() {
                 (*id, name, *qty)
             })
             .collect();
-        display_found_quantities(&display_quantities);
-
-        // Step 2: Split order quantity between base and surge
-        let monthly_order = split_order_quantities(
-            &optimal_quantities,
-            &pair,
-            &params,
-        );
+        reporter.display_found_quantities(&display_quantities);
+        reporter.display_optimizer_diagnostics(&evaluation.diagnostics);
 
-        // Step 3: Run Monte Carlo simulation
-        let stats = run_monte_carlo_simulation(
-            &params,
-            &pair,
-            &monthly_order,
-            num_simulations,
-        );
+        reporter.display_combination_results(&evaluation.stats);
+        reporter.display_pair_timing(evaluation.optimizing, evaluation.simulating);
 
-        display_combination_results(
-            stats.mean_profit,
-            stats.std_dev_profit,
-            stats.min_profit,
-            stats.max_profit,
-        );
-
-        if stats.mean_profit > best_mean_profit {
-            best_mean_profit = stats.mean_profit;
+        if ranking.score(&evaluation.stats) > best_score {
+            best_score = ranking.score(&evaluation.stats);
+            best_plan = Some((*pair, evaluation.monthly_order.clone()));
+            best_pair = Some(pair_runs.len());
         }
 
-        all_results.push(stats);
+        pair_runs.push(evaluation.to_run());
     }
 
     // Present Monte Carlo results for all combinations
-    let mut sorted_results = all_results.clone();
-    sorted_results.sort_by(|a, b| b.mean_profit.partial_cmp(&a.mean_profit).unwrap());
-    display_all_results(sorted_results.clone());
+    let mut sorted_results: Vec<MonteCarloStats> = pair_runs.iter().map(|run| run.stats.clone()).collect();
+    sorted_results.sort_by(|a, b| ranking.score(b).total_cmp(&ranking.score(a)));
+    reporter.display_all_results(sorted_results.clone(), ranking);
+
+    if let Some(dir) = &cli.csv {
+        if let Err(err) = write_csv_files(&reporter, dir, &sorted_results) {
+            eprintln!("Could not write CSV files to {}: {}", dir.display(), err);
+        }
+    }
 
     // Present best combination results
     if !sorted_results.is_empty() {
         let best_result = &sorted_results[0];
-        display_best_result(best_result);
+        // What the surge supplier adds, on as many paths as the pair's Monte Carlo run took and
+        // from its master seed, so the pair's plan is the one the evaluation made
+        let surge_value = match &best_plan {
+            Some((pair, _)) if cli.surge_value && !best_result.single_source => Some(or_exit(dual_source_value(
+                &params,
+                pair,
+                &MonteCarloConfig { num_simulations: best_result.num_simulations, ..evaluation_config.monte_carlo.clone() },
+            ))),
+            _ => None,
+        };
+        reporter.display_best_result(best_result, cli.target_profit, surge_value.as_ref());
+        if let Some((pair, monthly_order)) = &best_plan {
+            // The best plan has just been simulated, so it fits any strict capacity policy
+            if let Ok(projection) = project_mean_demand(&params, pair, monthly_order) {
+                reporter.display_monthly_plan(&params, pair, monthly_order, &projection);
+            }
+        }
+        reporter.display_product_pnl(&best_result.product_pnl);
+        if let Some((pair, _)) = &best_plan {
+            reporter.display_utilization(pair, best_result);
+        }
+        reporter.display_histogram(best_result);
+        if let Some(trace) = &best_result.worst_case_trace {
+            reporter.display_trace("Worst Simulated Season", trace);
+        }
+    }
+
+    // Show when in-season demand would justify a surge order for the best plan
+    if let Some((pair, monthly_order)) = best_plan {
+        let boundaries: Vec<Option<u32>> = (0..params.season_months)
+            .map(|month_idx| exercise_boundary(&params, pair, &monthly_order, month_idx))
+            .collect();
+        reporter.display_exercise_boundaries(&boundaries);
+
+        // Rerun the plan as many times as the pair's own Monte Carlo run did, on one set of seeds
+        if cli.sensitivity {
+            let num_simulations = sorted_results.first().map_or(num_simulations, |stats| stats.num_simulations);
+            let results = or_exit(sensitivity(
+                &params,
+                &suppliers,
+                pair,
+                &monthly_order,
+                &default_perturbations(&params, pair),
+                &MonteCarloConfig::new(num_simulations),
+            ));
+            reporter.display_tornado(&results);
+        }
+
+        // Foresight and flexibility priced on as many paths as the pair's Monte Carlo run took
+        if cli.information_value {
+            let num_simulations = sorted_results.first().map_or(num_simulations, |stats| stats.num_simulations);
+            let seed = rand::random();
+            let foresight = or_exit(evpi(&params, pair, num_simulations, seed));
+            let options = or_exit(option_value_realized(&params, pair, &monthly_order, num_simulations, seed));
+            reporter.display_information_value(&foresight, &options);
+        }
+    }
+
+    let run = RunOutput { schema_version: SCHEMA_VERSION, params, suppliers, ranking, pairs: pair_runs, best_pair, rejected_pairs };
+    if let Some(path) = &cli.report {
+        match write_report(&cli, path, &run) {
+            Ok(()) => reporter.display_files_written(path),
+            Err(err) => eprintln!("Could not write the report to {}: {}", path.display(), err),
+        }
+    }
+    #[cfg(feature = "charts")]
+    if let Some(path) = &cli.html {
+        match supply_chain_sim::reporting::write_html_report(path, &run, &cli.formatter()) {
+            Ok(()) => reporter.display_files_written(path),
+            Err(err) => eprintln!("Could not write the HTML report to {}: {}", path.display(), err),
+        }
+    }
+    #[cfg(feature = "arrow")]
+    if let Some(dir) = &cli.parquet {
+        match supply_chain_sim::reporting::export_parquet(dir, &run) {
+            Ok(()) => reporter.display_files_written(dir),
+            Err(err) => eprintln!("Could not write Parquet files to {}: {}", dir.display(), err),
+        }
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &cli.history {
+        match history::open(path).and_then(|conn| history::record_run(&conn, &run)) {
+            Ok(run_id) => reporter.display_run_recorded(path, run_id),
+            Err(err) => eprintln!("Could not record the run in {}: {}", path.display(), err),
+        }
+    }
+    #[cfg(feature = "xlsx")]
+    if let Some(path) = &cli.xlsx {
+        match supply_chain_sim::reporting::export_xlsx(path, &run, &cli.formatter()) {
+            Ok(()) => reporter.display_files_written(path),
+            Err(err) => eprintln!("Could not write the workbook to {}: {}", path.display(), err),
+        }
+    }
+    if let Err(err) = reporter.emit_json(&run) {
+        eprintln!("Could not write the JSON output: {}", err);
+        process::exit(1);
+    }
+}
+
+/// Write the --report: the run laid out by --template or --template-builtin if one is given,
+/// otherwise the standard Markdown report
+fn write_report(cli: &Cli, path: &Path, run: &RunOutput) -> Result<(), Box<dyn Error>> {
+    let formatter = cli.formatter();
+    #[cfg(feature = "templates")]
+    {
+        let rendered = match (&cli.template, &cli.template_builtin) {
+            (Some(template), _) => Some(supply_chain_sim::reporting::render_template(template, run, &formatter)?),
+            (None, Some(name)) => Some(supply_chain_sim::reporting::render_builtin_template(name, run, &formatter)?),
+            (None, None) => None,
+        };
+        if let Some(rendered) = rendered {
+            fs::write(path, rendered)?;
+            return Ok(());
+        }
+    }
+    write_markdown_report(path, run, &formatter)?;
+    Ok(())
+}
+
+/// Write the ranked results and their monthly breakdown as CSV files into `dir`
+fn write_csv_files(reporter: &Reporter, dir: &Path, results: &[MonteCarloStats]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    export_results_csv(&dir.join("results.csv"), results)?;
+    export_monthly_csv(&dir.join("monthly.csv"), results)?;
+    export_traces_csv(&dir.join("traces.csv"), results)?;
+    reporter.display_files_written(dir);
+    Ok(())
+}
+
+/// List the runs recorded in the history database `db`, only those of exactly `products` if
+/// given, or compare the two runs in `diff`, after recording the archived runs in `import`
+#[cfg(feature = "sqlite")]
+fn show_history(reporter: &Reporter, db: &Path, diff: Option<&[i64]>, products: Option<&[String]>, import: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+    let conn = history::open(db)?;
+    for file in import {
+        let json = fs::read_to_string(file).map_err(|err| format!("{}: {}", file.display(), err))?;
+        history::import_run(&conn, &json).map_err(|err| format!("{}: {}", file.display(), err))?;
+    }
+    if let Some(&[first, second]) = diff {
+        let load = |run_id: i64| -> Result<history::RecordedRun, Box<dyn Error>> {
+            history::load_run(&conn, run_id)?.ok_or_else(|| format!("no run {}", run_id).into())
+        };
+        let runs: [history::RecordedRun; 2] = [load(first)?, load(second)?];
+        reporter.display_run_diff(&history::compare_runs(&runs), &history::parameter_changes(&runs[0], &runs[1]));
+        return Ok(());
+    }
+    let runs = match products {
+        Some(products) => history::best_pair_over_time(&conn, &products.iter().map(|product| product.trim()).collect::<Vec<_>>())?,
+        None => history::list_runs(&conn)?,
+    };
+    reporter.display_run_history(&runs);
+    Ok(())
+}
+
+/// Evaluate the scenarios in `files` on one master seed, so every scenario sees the same demand
+/// paths, and report their scenario matrix: on the console, as JSON, and with --report as Markdown
+/// A file holding a run archived from --format json, of any layout, joins the matrix as it was
+/// run rather than being evaluated again
+fn compare_scenarios(reporter: &Reporter, cli: &Cli, files: &[PathBuf], num_simulations: usize) {
+    let budget = Budget { simulations: num_simulations, seed: rand::random(), max_pairs: None, parallel: !cli.serial };
+    let runs: Vec<(String, RunOutput)> = files.iter()
+        .map(|file| {
+            let name = file.file_stem().map_or_else(|| file.display().to_string(), |stem| stem.to_string_lossy().into_owned());
+            let json = fs::read_to_string(file).unwrap_or_else(|err| {
+                eprintln!("Could not read {}: {}", file.display(), err);
+                process::exit(1);
+            });
+            let run = match serde_json::from_str::<serde_json::Value>(&json) {
+                Ok(value) if value.get("pairs").is_some() => schema::run_from_value(value).unwrap_or_else(|err| {
+                    eprintln!("Could not read the run in {}: {}", file.display(), err);
+                    process::exit(1);
+                }),
+                _ => {
+                    let scenario = Scenario::from_json(&json).unwrap_or_else(|err| {
+                        eprintln!("Could not read the scenario in {}: {}", file.display(), err);
+                        process::exit(1);
+                    });
+                    or_exit(scenario.evaluate(&budget, &NoProgress))
+                }
+            };
+            (name, run)
+        })
+        .collect();
+    let results = ScenarioResults::from_runs(&runs);
+    reporter.display_scenario_matrix(&results);
+    if let Some(path) = &cli.report {
+        match write_scenario_matrix(path, &results, &cli.formatter()) {
+            Ok(()) => reporter.display_files_written(path),
+            Err(err) => eprintln!("Could not write the report to {}: {}", path.display(), err),
+        }
+    }
+    if let Err(err) = reporter.emit_json(&results) {
+        eprintln!("Could not write the JSON output: {}", err);
+        process::exit(1);
+    }
+}
+
+/// What a simulation produced, or exit with why it could not
+fn or_exit<T>(result: Result<T, SimulationError>) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("Simulation failed: {}", err);
+        process::exit(1);
+    })
+}
+
+/// The supplier pair named, or exit listing the pairs there are
+fn find_pair<'a>(pairs: &'a [SupplierPair], names: &PairNames) -> &'a SupplierPair {
+    pairs.iter()
+        .find(|pair| pair.base_supplier.name == names.base && pair.surge_supplier.name == names.surge)
+        .unwrap_or_else(|| {
+            eprintln!("No supplier pair {}+{}; the pairs are:", names.base, names.surge);
+            for pair in pairs {
+                eprintln!("  {}+{}", pair.base_supplier.name, pair.surge_supplier.name);
+            }
+            process::exit(2);
+        })
+}
+
+/// Simulate a plan given on the command line for a named supplier pair; under the strict
+/// capacity policy a plan beyond the pair's capacity is refused with the supplier, month and
+/// units it does not fit
+#[allow(clippy::too_many_arguments)]
+fn evaluate_plan(
+    reporter: &Reporter,
+    params: &SimulationParams,
+    pairs: &[SupplierPair],
+    names: &PairNames,
+    base: &[u32],
+    surge: &[u32],
+    num_simulations: usize,
+    target_profit: Option<f64>,
+) {
+    let pair = find_pair(pairs, names);
+    let products = params.products.len();
+    if base.len() != products || !(surge.is_empty() || surge.len() == products) {
+        eprintln!("--base and --surge take one quantity for each of the {} products, in order", products);
+        process::exit(2);
+    }
+    let quantities = |units: &[u32]| params.products.iter().map(|product| product.id).zip(units.iter().copied().chain(std::iter::repeat(0))).collect();
+    let order = create_order(quantities(base), quantities(surge));
+    if let Err(err) = check_capacity(params, pair, &order) {
+        if params.capacity_policy == CapacityPolicy::Strict {
+            eprintln!("Plan refused: {}", err);
+            process::exit(1);
+        }
+        eprintln!("Plan clamped to capacity: {}", err);
+    }
+
+    reporter.display_optimization_start(pair);
+    let stats = or_exit(run_monte_carlo_simulation(params, pair, &order, &MonteCarloConfig::new(num_simulations)));
+    reporter.display_best_result(&stats, target_profit, None);
+    if let Err(err) = reporter.emit_json(&stats) {
+        eprintln!("Could not write the JSON output: {}", err);
+        process::exit(1);
+    }
+}
+
+/// Plans listed in a plan file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlanFile {
+    plans: Vec<NamedPlan>,
+}
+
+/// Compare the plans of `files` for a named supplier pair with the optimizer's plan for it
+fn compare_plan_files(
+    reporter: &Reporter,
+    params: &SimulationParams,
+    pairs: &[SupplierPair],
+    names: &PairNames,
+    files: &[PathBuf],
+    num_simulations: usize,
+) {
+    let pair = find_pair(pairs, names);
+    let plans: Vec<NamedPlan> = files.iter()
+        .flat_map(|file| {
+            let text = fs::read_to_string(file).unwrap_or_else(|err| {
+                eprintln!("Could not read {}: {}", file.display(), err);
+                process::exit(1);
+            });
+            let parsed = if file.extension().is_some_and(|extension| extension == "toml") {
+                toml::from_str::<PlanFile>(&text).map_err(|err| err.to_string())
+            } else {
+                serde_json::from_str::<PlanFile>(&text).map_err(|err| err.to_string())
+            };
+            parsed.unwrap_or_else(|err| {
+                eprintln!("Could not read the plans in {}: {}", file.display(), err);
+                process::exit(1);
+            }).plans
+        })
+        .collect();
+
+    reporter.display_optimization_start(pair);
+    let comparison = or_exit(compare_plans(params, pair, plans, &MonteCarloConfig::new(num_simulations)));
+    reporter.display_plan_comparison(&comparison);
+    if let Err(err) = reporter.emit_json(&comparison) {
+        eprintln!("Could not write the JSON output: {}", err);
+        process::exit(1);
+    }
+}
+
+/// Plan `seasons` seasons in a row for a named supplier pair, every product's demand growing by
+/// `demand_growth` from each season to the next
+#[allow(clippy::too_many_arguments)]
+fn plan_seasons(
+    reporter: &Reporter,
+    params: &SimulationParams,
+    pairs: &[SupplierPair],
+    names: &PairNames,
+    seasons: usize,
+    demand_growth: f64,
+    discount_rate: f64,
+    num_simulations: usize,
+) {
+    let pair = find_pair(pairs, names);
+    let params_per_season = (0..seasons)
+        .map(|season| {
+            let growth = (1.0 + demand_growth).powi(season as i32);
+            let mut params = params.clone();
+            for dp in &mut params.demand_params {
+                dp.mean_demand *= growth;
+                dp.std_dev_demand *= growth;
+                dp.actual_mean_demand *= growth;
+                dp.actual_std_dev_demand *= growth;
+            }
+            params
+        })
+        .collect();
+    let config = MultiSeasonConfig { monte_carlo: MonteCarloConfig::new(num_simulations), discount_rate };
+
+    reporter.display_optimization_start(pair);
+    let plan = or_exit(multi_season(params_per_season, pair, &config));
+    reporter.display_season_plan(&plan);
+    if let Err(err) = reporter.emit_json(&plan) {
+        eprintln!("Could not write the JSON output: {}", err);
+        process::exit(1);
+    }
+}
+
+/// Optimize a plan for each of two named supplier pairs and compare them on shared demand paths
+fn compare_pairs(
+    reporter: &Reporter,
+    params: &SimulationParams,
+    pairs: &[SupplierPair],
+    first: &PairNames,
+    second: &PairNames,
+    num_simulations: usize,
+) {
+    let plans: Vec<(&SupplierPair, _)> = [first, second].into_iter()
+        .map(|names| {
+            let pair = find_pair(pairs, names);
+            reporter.display_optimization_start(pair);
+            let (optimal_quantities, reservations, _) = or_exit(find_optimal_plan(params, pair, &NoProgress));
+            let mut monthly_order = split_order_quantities(&optimal_quantities, pair, params);
+            monthly_order.reservations = reservations;
+            (pair, monthly_order)
+        })
+        .collect();
+
+    let comparison = or_exit(compare(params, plans[0].0, &plans[0].1, plans[1].0, &plans[1].1, num_simulations, rand::random()));
+    reporter.display_comparison(&comparison);
+    if let Err(err) = reporter.emit_json(&comparison) {
+        eprintln!("Could not write the JSON output: {}", err);
+        process::exit(1);
     }
 }
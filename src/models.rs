@@ -1,22 +1,109 @@
 use std::clone::Clone;
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::error::OrderError;
+use crate::simulation::month_index;
+use crate::streaming::grid_cdf;
+
+/// Identifier of a product, distinct from a supplier's so neither can stand in for the other
+///
+/// ```
+/// use supply_chain_sim::prelude::*;
+///
+/// let product = Product {
+///     id: ProductId(0),
+///     name: "Jacket".to_string(),
+///     selling_price: 200.0,
+///     liquidation_price: 120.0,
+///     monthly_holding_cost: 4.0,
+///     goodwill_penalty: None,
+/// };
+/// let unit_costs = std::collections::HashMap::from([(product.id, 100.0)]);
+/// assert_eq!(unit_costs[&ProductId::from(0)], 100.0);
+/// assert_eq!(SupplierId(3).to_string(), "3");
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct ProductId(pub u32);
+
+impl fmt::Display for ProductId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u32> for ProductId {
+    fn from(id: u32) -> Self {
+        ProductId(id)
+    }
+}
+
+/// Identifier of a supplier
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct SupplierId(pub u32);
+
+impl fmt::Display for SupplierId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u32> for SupplierId {
+    fn from(id: u32) -> Self {
+        SupplierId(id)
+    }
+}
 
 /// Product data structure representing a product in the supply chain
 #[allow(dead_code)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Product {
-    pub id: usize,
+    pub id: ProductId,
     pub name: String,
     pub selling_price: f64,
     pub liquidation_price: f64,
     pub monthly_holding_cost: f64,
+    /// Brand damage charged for each month in a row the product stocks out
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub goodwill_penalty: Option<GoodwillModel>,
+}
+
+/// Escalating penalty for a product stocking out month after month
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GoodwillModel {
+    /// Penalty for the first, second, ... consecutive month of stockouts; the last applies to
+    /// every month of a longer run
+    pub per_consecutive_month: Vec<f64>,
+}
+
+impl GoodwillModel {
+    /// Penalty for the `streak`th consecutive month of stockouts, counting from 1; nothing
+    /// without a stockout or a schedule
+    pub fn penalty(&self, streak: usize) -> f64 {
+        match streak {
+            0 => 0.0,
+            _ => self.per_consecutive_month.get(streak - 1)
+                .or(self.per_consecutive_month.last())
+                .copied()
+                .unwrap_or(0.0),
+        }
+    }
 }
 
 /// Demand parameters for a specific product
 #[allow(dead_code)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ProductDemandParams {
-    pub product_id: usize,
+    pub product_id: ProductId,
     pub mean_demand: f64,
     pub std_dev_demand: f64,
     pub actual_mean_demand: f64,
@@ -25,64 +112,634 @@ pub struct ProductDemandParams {
 
 /// Supplier data structure representing a supplier in the supply chain
 #[allow(dead_code)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Supplier {
-    pub id: usize,
+    pub id: SupplierId,
     pub name: String,
     pub fixed_capacity: u32,
+    /// Most units of a product the supplier can make a month within `fixed_capacity`
+    /// (product_id -> units); products without an entry are limited by the total alone
+    pub product_capacity: Option<HashMap<ProductId, u32>>,
+    /// Limits on what the supplier makes of groups of products together a month, like lines they
+    /// share, beside its total; groups may overlap
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub capacity_groups: Vec<CapacityGroup>,
+    /// Overtime the supplier works beyond `fixed_capacity` at a premium, as (extra fraction of the
+    /// nominal capacity up to which the tier runs, cost multiplier of the tier's units), tiers in
+    /// rising order; the last tier's limit is the hard ceiling on its monthly output
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub soft_capacity_tiers: Vec<(f64, f64)>,
     pub lead_time_months: usize,
+    /// Share of its capacity a newly onboarded supplier reaches in each month from its first
+    /// order on (e.g., [0.4, 0.7, 1.0]); full capacity past the last. A lead time as long as the
+    /// ramp has it at full capacity by its first delivery
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ramp_up_months: Vec<f64>,
     /// Unit cost per product (product_id -> cost)
-    pub unit_costs: HashMap<usize, f64>,
+    pub unit_costs: HashMap<ProductId, f64>,
     pub setup_cost: f64,
+    /// Fee per unit of monthly capacity reserved ahead of the season, if the supplier sells its
+    /// capacity by reservation; such a supplier delivers no more than a plan reserved of it
+    pub reservation_fee: Option<f64>,
+    /// Contractual flexibility to adjust each month's delivery, if any
+    pub monthly_flex: Option<Flex>,
+    /// Months of the season (0 = May) in which the supplier delivers nothing
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub disrupted_months: Vec<usize>,
+    /// Where the supplier makes its goods; one regional disruption stops every supplier there
+    pub region: Option<String>,
+    /// Risk class the supplier falls in (e.g. "single-plant"), for keeping pairs diverse
+    pub risk_class: Option<String>,
+}
+
+impl Supplier {
+    /// Whether the supplier is disrupted in the given month of the season
+    pub fn is_disrupted(&self, month: usize) -> bool {
+        self.disrupted_months.contains(&month)
+    }
+
+    /// Landed cost of a unit of a product: its unit cost, freight and duty included, plus the
+    /// reservation fee on the capacity it takes if the supplier sells its capacity by reservation
+    pub fn landed_unit_cost(&self, product_id: ProductId) -> f64 {
+        self.unit_costs.get(&product_id).copied().unwrap_or(0.0) + self.reservation_fee.unwrap_or(0.0)
+    }
+
+    /// Most units of a product the supplier can make a month
+    pub fn capacity_for(&self, product_id: ProductId) -> u32 {
+        self.limited_for(product_id, self.fixed_capacity)
+    }
+
+    /// Most units of a product the supplier can make a month when its total is `capacity`
+    fn limited_for(&self, product_id: ProductId, capacity: u32) -> u32 {
+        self.product_capacity.as_ref()
+            .and_then(|limits| limits.get(&product_id))
+            .map_or(capacity, |&limit| limit.min(capacity))
+    }
+
+    /// Most units of the given products together the supplier can make a month when its total is
+    /// `capacity`, within each product's limit and its capacity groups
+    fn limited_for_all(&self, product_ids: &[ProductId], capacity: u32) -> u32 {
+        let sum_of = |included: &dyn Fn(ProductId) -> bool| -> u32 {
+            product_ids.iter().filter(|&&product_id| included(product_id)).map(|&product_id| self.limited_for(product_id, capacity)).sum()
+        };
+        self.capacity_groups.iter()
+            .map(|group| group.limit + sum_of(&|product_id| !group.contains(product_id)))
+            .fold(capacity.min(sum_of(&|_| true)), u32::min)
+    }
+
+    /// Monthly output at the top of each soft capacity tier, in order
+    fn tier_ceilings(&self) -> impl Iterator<Item = u32> + '_ {
+        self.soft_capacity_tiers.iter()
+            .map(|(limit, _)| (self.fixed_capacity as f64 * (1.0 + limit)).round() as u32)
+    }
+
+    /// Most the supplier can make in a month, overtime included: the hard ceiling
+    pub fn max_capacity(&self) -> u32 {
+        self.tier_ceilings().fold(self.fixed_capacity, u32::max)
+    }
+
+    /// Most the supplier can make in a month without going past its first overtime tier, the most
+    /// a plan asks of it
+    pub fn first_tier_capacity(&self) -> u32 {
+        self.tier_ceilings().next().map_or(self.fixed_capacity, |ceiling| ceiling.max(self.fixed_capacity))
+    }
+
+    /// Most units of a product the supplier can make a month, overtime included
+    pub fn max_capacity_for(&self, product_id: ProductId) -> u32 {
+        self.limited_for(product_id, self.max_capacity())
+    }
+
+    /// Most units of a product the supplier can make a month within its first overtime tier
+    pub fn first_tier_capacity_for(&self, product_id: ProductId) -> u32 {
+        self.limited_for(product_id, self.first_tier_capacity())
+    }
+
+    /// Most units of the given products together the supplier can make a month, overtime included
+    pub fn max_capacity_for_all(&self, product_ids: &[ProductId]) -> u32 {
+        self.limited_for_all(product_ids, self.max_capacity())
+    }
+
+    /// Most units of the given products together the supplier can make a month within its first
+    /// overtime tier
+    pub fn first_tier_capacity_for_all(&self, product_ids: &[ProductId]) -> u32 {
+        self.limited_for_all(product_ids, self.first_tier_capacity())
+    }
+
+    /// Mean cost multiplier over `units` delivered in a month: units within the nominal capacity
+    /// cost their unit cost, and those in each overtime tier the tier's multiple of it
+    pub fn cost_multiplier(&self, units: u32) -> f64 {
+        if units <= self.fixed_capacity {
+            return 1.0;
+        }
+        let mut cost = self.fixed_capacity as f64;
+        let mut floor = self.fixed_capacity;
+        for (ceiling, (_, multiplier)) in self.tier_ceilings().zip(&self.soft_capacity_tiers) {
+            cost += units.min(ceiling).saturating_sub(floor) as f64 * multiplier;
+            floor = floor.max(ceiling);
+        }
+        // Past the hard ceiling only when a caller asks; the last tier's premium carries on
+        let last_multiplier = self.soft_capacity_tiers.last().map_or(1.0, |(_, multiplier)| *multiplier);
+        cost += units.saturating_sub(floor) as f64 * last_multiplier;
+        cost / units as f64
+    }
+
+    /// Share of its capacity the supplier has `months` after its first order
+    pub fn ramp_fraction(&self, months: usize) -> f64 {
+        self.ramp_up_months.get(months).copied().unwrap_or(1.0)
+    }
+
+    /// The supplier with the capacity it has in the month of its first delivery, its product
+    /// limits and capacity groups scaled alike, for planning within what it can make then
+    pub fn at_first_delivery(&self) -> Supplier {
+        let fraction = self.ramp_fraction(self.lead_time_months);
+        if fraction >= 1.0 {
+            return self.clone();
+        }
+        let scaled = |units: u32| (units as f64 * fraction).round() as u32;
+        Supplier {
+            fixed_capacity: scaled(self.fixed_capacity),
+            product_capacity: self.product_capacity.as_ref()
+                .map(|limits| limits.iter().map(|(&product_id, &limit)| (product_id, scaled(limit))).collect()),
+            capacity_groups: self.capacity_groups.iter()
+                .map(|group| CapacityGroup { limit: scaled(group.limit), ..group.clone() })
+                .collect(),
+            ramp_up_months: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Units of a product the supplier's capacity groups leave room for beside `placed` units of
+    /// each product, as (product_id, units); unlimited outside every group
+    pub fn group_room_for(&self, product_id: ProductId, placed: &[(ProductId, u32)]) -> u32 {
+        self.capacity_groups.iter()
+            .filter(|group| group.contains(product_id))
+            .map(|group| group.limit.saturating_sub(group.units_of(placed)))
+            .min()
+            .unwrap_or(u32::MAX)
+    }
+
+    /// Whether the supplier is in one of `regions`
+    pub fn is_in_any(&self, regions: &[&str]) -> bool {
+        self.region.as_deref().is_some_and(|region| regions.contains(&region))
+    }
+}
+
+/// Most units a supplier makes of a group of products together a month, such as products
+/// sharing a cutting line
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CapacityGroup {
+    pub name: String,
+    pub product_ids: Vec<ProductId>,
+    pub limit: u32,
+}
+
+impl CapacityGroup {
+    pub fn contains(&self, product_id: ProductId) -> bool {
+        self.product_ids.contains(&product_id)
+    }
+
+    /// Units of the group's products among `units`, as (product_id, units)
+    pub fn units_of(&self, units: &[(ProductId, u32)]) -> u32 {
+        units.iter().filter(|(product_id, _)| self.contains(*product_id)).map(|(_, units)| units).sum()
+    }
+}
+
+/// Chance each month of an event that stops every supplier in a region from delivering that month
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RegionalDisruption {
+    pub region: String,
+    pub monthly_probability: f64,
+}
+
+impl FromStr for RegionalDisruption {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected REGION=PROBABILITY with a probability from 0 to 1, got \"{}\"", s);
+        let (region, probability) = s.split_once('=').ok_or_else(invalid)?;
+        let monthly_probability: f64 = probability.trim().parse().map_err(|_| invalid())?;
+        if region.trim().is_empty() || !(0.0..=1.0).contains(&monthly_probability) {
+            return Err(invalid());
+        }
+        Ok(RegionalDisruption { region: region.trim().to_string(), monthly_probability })
+    }
+}
+
+/// A supplier's offer to add capacity for the rest of the season for a one-off fee, if taken
+/// by a deadline: a real option on capacity rather than on orders
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ExpansionOffer {
+    pub supplier_id: SupplierId,
+    /// Month of the season by whose end the offer must be taken, counting from May as 0
+    pub decision_month: usize,
+    /// First month of the season the extra capacity delivers in
+    pub start_month: usize,
+    /// Units added to the supplier's monthly capacity from the start month on
+    pub extra_capacity: u32,
+    pub fee: f64,
+}
+
+impl FromStr for ExpansionOffer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected SUPPLIER_ID:DECIDE_BY:FROM:UNITS:FEE with the decision month before the first, got \"{}\"", s);
+        let fields: Vec<&str> = s.split(':').map(str::trim).collect();
+        let [supplier_id, decision_month, start_month, extra_capacity, fee] = fields[..] else {
+            return Err(invalid());
+        };
+        let offer = ExpansionOffer {
+            supplier_id: SupplierId(supplier_id.parse().map_err(|_| invalid())?),
+            decision_month: month_index(decision_month).ok_or_else(invalid)?,
+            start_month: month_index(start_month).ok_or_else(invalid)?,
+            extra_capacity: extra_capacity.parse().map_err(|_| invalid())?,
+            fee: fee.parse().map_err(|_| invalid())?,
+        };
+        if offer.decision_month >= offer.start_month || !(offer.fee.is_finite() && offer.fee >= 0.0) {
+            return Err(invalid());
+        }
+        Ok(offer)
+    }
+}
+
+/// Bounds on adjusting a supplier's planned monthly delivery (a swing option)
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Flex {
+    /// Largest adjustment up or down as a fraction of the planned quantity
+    pub max_fraction: f64,
+    /// Fee per unit flexed in either direction
+    pub fee_per_unit: f64,
 }
 
-/// Pair of suppliers: one for base orders, one for surge orders
+/// Pair of suppliers: one for base orders, one for surge orders, and optionally a backup
+/// that takes the surge orders the surge supplier cannot deliver
+/// Base orders the base supplier cannot make go to the pooled suppliers, which with it form the
+/// committed tier, in order of priority
 #[allow(dead_code)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SupplierPair {
     pub base_supplier: Supplier,      // Longer lead time, planned orders
     pub surge_supplier: Supplier,     // Shorter lead time, emergency orders
+    pub backup_supplier: Option<Supplier>,  // Short lead time, only used when surge falls short
+    pub pooled_suppliers: Vec<Supplier>,    // Further committed capacity, after the base supplier's
+}
+
+impl SupplierPair {
+    /// "base + surge", with " + backup" when the pair has one and the committed tier as
+    /// "base & pooled"; a supplier paired with itself is "supplier (single-source)" or
+    /// "supplier (split capacity)"
+    pub fn name(&self) -> String {
+        let pooled: Vec<String> = self.pooled_suppliers.iter().map(|s| s.name.clone()).collect();
+        group_name(
+            &committed_tier_name(&self.base_supplier.name, &pooled),
+            &self.surge_supplier.name,
+            self.backup_supplier.as_ref().map(|backup| backup.name.as_str()),
+            self.is_single_source(),
+        )
+    }
+
+    /// The suppliers sharing the base orders: the base supplier, then the pooled suppliers
+    pub fn committed_tier(&self) -> impl Iterator<Item = &Supplier> + Clone {
+        std::iter::once(&self.base_supplier).chain(&self.pooled_suppliers)
+    }
+
+    /// Every supplier of the pair: the committed tier, the surge supplier and the backup, if any
+    pub fn suppliers(&self) -> impl Iterator<Item = &Supplier> {
+        self.committed_tier().chain([&self.surge_supplier]).chain(self.backup_supplier.as_ref())
+    }
+
+    /// The pair with every supplier at the capacity it has in the month of its first delivery,
+    /// part way up its ramp when its lead time is shorter than the ramp; for planning only, as
+    /// the ramp is no longer ahead of it
+    pub fn at_first_delivery(&self) -> SupplierPair {
+        SupplierPair {
+            base_supplier: self.base_supplier.at_first_delivery(),
+            surge_supplier: self.surge_supplier.at_first_delivery(),
+            backup_supplier: self.backup_supplier.as_ref().map(Supplier::at_first_delivery),
+            pooled_suppliers: self.pooled_suppliers.iter().map(Supplier::at_first_delivery).collect(),
+        }
+    }
+
+    /// The suppliers taking surge orders: the surge supplier, then the backup, if any
+    pub fn surge_tier(&self) -> impl Iterator<Item = &Supplier> + Clone {
+        std::iter::once(&self.surge_supplier).chain(self.backup_supplier.as_ref())
+    }
+
+    /// Monthly capacity for base orders across the committed tier
+    pub fn base_capacity(&self) -> u32 {
+        self.committed_tier().map(|supplier| supplier.fixed_capacity).sum()
+    }
+
+    /// Whether one supplier serves as both base and surge supplier
+    pub fn is_self_paired(&self) -> bool {
+        self.base_supplier.id == self.surge_supplier.id
+    }
+
+    /// Whether one supplier takes every order, standing in as its own surge supplier without
+    /// surge capacity
+    pub fn is_single_source(&self) -> bool {
+        self.is_self_paired() && self.surge_supplier.fixed_capacity == 0
+    }
+
+    /// Whether one supplier splits its capacity between a committed base tranche and a flexible
+    /// surge tranche, so base and surge orders together never exceed its capacity
+    pub fn shares_capacity(&self) -> bool {
+        self.is_self_paired() && self.surge_supplier.fixed_capacity > 0
+    }
+
+    /// Monthly capacity for surge orders: the surge supplier's, plus the backup's
+    pub fn surge_capacity(&self) -> u32 {
+        self.surge_supplier.fixed_capacity + self.backup_capacity()
+    }
+
+    /// Monthly capacity left for surge orders beside `base_units` of base orders, which only
+    /// take from it when the capacity is shared
+    #[allow(dead_code)]
+    pub fn surge_capacity_beside(&self, base_units: u32) -> u32 {
+        if self.shares_capacity() {
+            self.surge_supplier.fixed_capacity.saturating_sub(base_units) + self.backup_capacity()
+        } else {
+            self.surge_capacity()
+        }
+    }
+
+    /// Monthly capacity of every supplier in the pair, counting shared capacity once
+    pub fn total_capacity(&self) -> u32 {
+        if self.shares_capacity() {
+            self.base_capacity() + self.backup_capacity()
+        } else {
+            self.base_capacity() + self.surge_capacity()
+        }
+    }
+
+    /// Monthly capacity for base orders of one product across the committed tier
+    pub fn base_capacity_for(&self, product_id: ProductId) -> u32 {
+        self.committed_tier().map(|supplier| supplier.capacity_for(product_id)).sum()
+    }
+
+    /// Monthly capacity for surge orders of one product, the backup's included
+    pub fn surge_capacity_for(&self, product_id: ProductId) -> u32 {
+        self.surge_supplier.capacity_for(product_id)
+            + self.backup_supplier.as_ref().map_or(0, |backup| backup.capacity_for(product_id))
+    }
+
+    /// Monthly capacity of every supplier in the pair for one product, counting shared
+    /// capacity once
+    pub fn total_capacity_for(&self, product_id: ProductId) -> u32 {
+        if self.shares_capacity() {
+            self.base_capacity_for(product_id) + self.backup_supplier.as_ref().map_or(0, |backup| backup.capacity_for(product_id))
+        } else {
+            self.base_capacity_for(product_id) + self.surge_capacity_for(product_id)
+        }
+    }
+
+    /// The suppliers that work overtime for the pair's plans: the committed tier, then the surge
+    /// supplier unless it is the base supplier, whose overtime the base orders already have
+    pub fn overtime_suppliers(&self) -> impl Iterator<Item = &Supplier> {
+        self.committed_tier().chain((!self.is_self_paired()).then_some(&self.surge_supplier))
+    }
+
+    /// Monthly units the pair's suppliers can make in their first overtime tier, beyond their
+    /// nominal capacity; plans may ask for these at the tier's premium
+    pub fn first_tier_overtime(&self) -> u32 {
+        self.overtime_suppliers().map(|supplier| supplier.first_tier_capacity() - supplier.fixed_capacity).sum()
+    }
+
+    /// Monthly units of one product the pair's suppliers can make in their first overtime tier
+    pub fn first_tier_overtime_for(&self, product_id: ProductId) -> u32 {
+        self.overtime_suppliers().map(|supplier| supplier.first_tier_capacity_for(product_id) - supplier.capacity_for(product_id)).sum()
+    }
+
+    /// One-time setup cost of every supplier in the group, each paid once: the committed tier,
+    /// the surge supplier unless it is also the base supplier, and the backup
+    pub fn total_setup_cost(&self) -> f64 {
+        let surge = if self.is_self_paired() { 0.0 } else { self.surge_supplier.setup_cost };
+        self.committed_tier().map(|supplier| supplier.setup_cost).sum::<f64>()
+            + surge
+            + self.backup_supplier.as_ref().map_or(0.0, |backup| backup.setup_cost)
+    }
+
+    fn backup_capacity(&self) -> u32 {
+        self.backup_supplier.as_ref().map_or(0, |backup| backup.fixed_capacity)
+    }
+}
+
+/// The committed tier of a strategy as reports show it, "base & pooled & ..."
+pub fn committed_tier_name(base: &str, pooled: &[String]) -> String {
+    std::iter::once(base).chain(pooled.iter().map(String::as_str)).collect::<Vec<&str>>().join(" & ")
+}
+
+/// Name of a supplier pair as reports show it
+pub fn group_name(base: &str, surge: &str, backup: Option<&str>, single_source: bool) -> String {
+    match backup {
+        Some(backup) => format!("{} + {} + {}", base, surge, backup),
+        None if single_source => format!("{} (single-source)", base),
+        None if base == surge => format!("{} (split capacity)", base),
+        None => format!("{} + {}", base, surge),
+    }
+}
+
+/// What the simulation does with a plan asking a supplier for more than it has in a month
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CapacityPolicy {
+    /// Deliver what the supplier has and record the shortfall
+    #[default]
+    Clamp,
+    /// Refuse the plan, for audits that must not have infeasible plans quietly fixed
+    Strict,
+}
+
+impl FromStr for CapacityPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(CapacityPolicy::Clamp),
+            "strict" => Ok(CapacityPolicy::Strict),
+            _ => Err(format!("expected clamp or strict, got \"{}\"", s)),
+        }
+    }
+}
+
+/// Period the simulation steps through the season by
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TimeStep {
+    /// A month at a time
+    #[default]
+    Monthly,
+    /// A week at a time, so a surge supplier with a short lead time answers demand within weeks
+    /// and order changes are weighed every week
+    Weekly,
+}
+
+impl TimeStep {
+    /// Periods in a season of `months` months, a year having 52 weeks
+    pub fn periods(self, months: usize) -> usize {
+        match self {
+            TimeStep::Monthly => months,
+            TimeStep::Weekly => (months as f64 * 52.0 / 12.0).round() as usize,
+        }
+    }
+
+    /// Periods to a month over a season of `months` months, the same in every month so the
+    /// season's totals come out as the monthly figures have them
+    pub fn periods_per_month(self, months: usize) -> f64 {
+        match months {
+            0 => 1.0,
+            months => self.periods(months) as f64 / months as f64,
+        }
+    }
+}
+
+impl FromStr for TimeStep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "monthly" => Ok(TimeStep::Monthly),
+            "weekly" => Ok(TimeStep::Weekly),
+            _ => Err(format!("expected monthly or weekly, got \"{}\"", s)),
+        }
+    }
 }
 
 /// Simulation parameters for configuring demand, costs, and pricing
 #[allow(dead_code)]
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SimulationParams {
     /// Products in the simulation
     pub products: Vec<Product>,
     /// Demand parameters per product
     pub demand_params: Vec<ProductDemandParams>,
-    /// Order change fee (paid once per exercise, covers all products)
-    pub order_change_fee: f64,
+    /// Order change fee for each exercise allowed in the season, in order (each covers all products)
+    /// No further changes are possible once the schedule is used up
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub order_change_fees: Vec<f64>,
+    /// Additional fee per product whose orders are changed in an exercise
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub order_change_fee_per_product: f64,
+    /// Fee per unit cancelled when the downward option is exercised
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cancellation_fee_per_unit: f64,
+    /// Update the option lattice's demand forecast and volatility with the demand observed so far
+    /// in the season instead of using the prior forecast throughout
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub calibrate_to_observed_demand: bool,
+    /// Months in the selling season, starting in May; leftover stock is liquidated in the last one
+    pub season_months: usize,
+    /// Disruptions that strike whole regions at random, drawn afresh each month of each season
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub regional_disruptions: Vec<RegionalDisruption>,
+    /// Whether a plan beyond a supplier's capacity is clamped or refused
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub capacity_policy: CapacityPolicy,
+    /// Offers of extra capacity the season may take up, each decided once, in its decision month
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub expansion_offers: Vec<ExpansionOffer>,
+    /// Stock on hand at the start of the season, as (product, units), such as an earlier
+    /// season's leftovers
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub opening_inventory: Vec<(ProductId, u32)>,
+    /// Suppliers whose setup cost was paid in an earlier season and is not charged again
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub onboarded_suppliers: Vec<SupplierId>,
+    /// Keep the stock left at the end of the season for the next one instead of liquidating it
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub carry_over_stock: bool,
+    /// Period the season is simulated in; the scenario's figures stay monthly, and are
+    /// converted to weeks for a weekly season, whose results come week by week
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub time_step: TimeStep,
+    /// Annual rate at which the stock held is financed, charged monthly on its book value at
+    /// the weighted average cost it was bought at
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cost_of_capital_annual: f64,
 }
 
 impl SimulationParams {
     /// Get demand params for a specific product
-    pub fn get_demand_params(&self, product_id: usize) -> Option<&ProductDemandParams> {
+    pub fn get_demand_params(&self, product_id: ProductId) -> Option<&ProductDemandParams> {
         self.demand_params.iter().find(|p| p.product_id == product_id)
     }
     
     /// Get product by ID
-    pub fn get_product(&self, product_id: usize) -> Option<&Product> {
+    pub fn get_product(&self, product_id: ProductId) -> Option<&Product> {
         self.products.iter().find(|p| p.id == product_id)
     }
+
+    /// Periods the season is simulated in
+    pub fn periods(&self) -> usize {
+        self.time_step.periods(self.season_months)
+    }
+
+    /// Get the fee for the next order change after `exercises_used` changes, if one is still allowed
+    pub fn next_order_change_fee(&self, exercises_used: usize) -> Option<f64> {
+        self.order_change_fees.get(exercises_used).copied()
+    }
 }
 
 /// Order quantity for a specific product
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ProductOrder {
-    pub product_id: usize,
+    pub product_id: ProductId,
     pub quantity: u32,
 }
 
 /// Monthly order decision between base and surge suppliers for all products
+/// Built by `new` or `for_params` and changed by the setters, so each product is ordered at most
+/// once on each side
 #[derive(Clone, Debug)]
 pub struct MonthlyOrder {
-    pub base_orders: Vec<ProductOrder>,
-    pub surge_orders: Vec<ProductOrder>,
+    base_orders: Vec<ProductOrder>,
+    surge_orders: Vec<ProductOrder>,
+    /// Capacity the plan reserved ahead of the season, at most one reservation per supplier
+    pub reservations: Vec<Reservation>,
 }
 
 impl MonthlyOrder {
+    /// Order of `base_orders` and `surge_orders`, reserving nothing, each product at most once
+    /// on each side
+    pub fn new(base_orders: Vec<ProductOrder>, surge_orders: Vec<ProductOrder>) -> Result<Self, OrderError> {
+        for (orders, side) in [(&base_orders, "base"), (&surge_orders, "surge")] {
+            for (i, order) in orders.iter().enumerate() {
+                if orders[..i].iter().any(|o| o.product_id == order.product_id) {
+                    return Err(OrderError::DuplicateProduct { product_id: order.product_id, side });
+                }
+            }
+        }
+        Ok(MonthlyOrder { base_orders, surge_orders, reservations: Vec::new() })
+    }
+
+    /// As `new`, also refusing products `params` does not sell
+    pub fn for_params(params: &SimulationParams, base_orders: Vec<ProductOrder>, surge_orders: Vec<ProductOrder>) -> Result<Self, OrderError> {
+        let order = MonthlyOrder::new(base_orders, surge_orders)?;
+        match order.base_orders.iter().chain(&order.surge_orders).find(|o| params.get_product(o.product_id).is_none()) {
+            Some(unknown) => Err(OrderError::UnknownProduct(unknown.product_id)),
+            None => Ok(order),
+        }
+    }
+
+    /// The order with `reservations` in place of those it made
+    pub fn with_reservations(self, reservations: Vec<Reservation>) -> Self {
+        MonthlyOrder { reservations, ..self }
+    }
+
+    pub fn base_orders(&self) -> &[ProductOrder] {
+        &self.base_orders
+    }
+
+    pub fn surge_orders(&self) -> &[ProductOrder] {
+        &self.surge_orders
+    }
+
     /// Get total base quantity across all products
     pub fn total_base_quantity(&self) -> u32 {
         self.base_orders.iter().map(|o| o.quantity).sum()
@@ -93,31 +750,134 @@ impl MonthlyOrder {
         self.surge_orders.iter().map(|o| o.quantity).sum()
     }
     
-    /// Get base quantity for a specific product
-    pub fn base_quantity_for(&self, product_id: usize) -> u32 {
-        self.base_orders.iter()
-            .find(|o| o.product_id == product_id)
-            .map(|o| o.quantity)
-            .unwrap_or(0)
+    /// Get base quantity for a specific product, zero when it is not ordered
+    pub fn base_quantity_for(&self, product_id: ProductId) -> u32 {
+        order_quantity(&self.base_orders, product_id)
     }
     
-    /// Get surge quantity for a specific product
-    pub fn surge_quantity_for(&self, product_id: usize) -> u32 {
-        self.surge_orders.iter()
-            .find(|o| o.product_id == product_id)
-            .map(|o| o.quantity)
-            .unwrap_or(0)
+    /// Get surge quantity for a specific product, zero when it is not ordered
+    pub fn surge_quantity_for(&self, product_id: ProductId) -> u32 {
+        order_quantity(&self.surge_orders, product_id)
+    }
+
+    /// Set base quantity for a specific product, adding it if missing
+    pub fn set_base_quantity(&mut self, product_id: ProductId, quantity: u32) {
+        set_order_quantity(&mut self.base_orders, product_id, quantity);
+    }
+
+    /// Set surge quantity for a specific product, adding it if missing
+    pub fn set_surge_quantity(&mut self, product_id: ProductId, quantity: u32) {
+        set_order_quantity(&mut self.surge_orders, product_id, quantity);
+    }
+
+    /// Add `units` to a product's base quantity, adding the product if missing
+    pub fn add_base_quantity(&mut self, product_id: ProductId, units: u32) {
+        self.set_base_quantity(product_id, self.base_quantity_for(product_id) + units);
+    }
+
+    /// Add `units` to a product's surge quantity, adding the product if missing
+    pub fn add_surge_quantity(&mut self, product_id: ProductId, units: u32) {
+        self.set_surge_quantity(product_id, self.surge_quantity_for(product_id) + units);
+    }
+}
+
+/// How a reservation spreads its capacity over the season
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum ReservationProfile {
+    /// The same units every month
+    Flat,
+    /// Most at the start of the season, tapering off linearly
+    FrontLoaded,
+    /// Least at the start of the season, building up linearly
+    BackLoaded,
+}
+
+impl ReservationProfile {
+    pub const ALL: [ReservationProfile; 3] = [ReservationProfile::Flat, ReservationProfile::FrontLoaded, ReservationProfile::BackLoaded];
+
+    /// Weight of a month of a season of `months`; the weights average one
+    fn weight(self, month: usize, months: usize) -> f64 {
+        let ramp = |step: usize| 2.0 * step as f64 / (months + 1) as f64;
+        match self {
+            ReservationProfile::Flat => 1.0,
+            ReservationProfile::FrontLoaded => ramp(months - month),
+            ReservationProfile::BackLoaded => ramp(month + 1),
+        }
+    }
+}
+
+/// Capacity reserved at a supplier ahead of the season, month by month, and paid for up front
+/// whether it is used or not; the supplier delivers no more than it in any month
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Reservation {
+    pub supplier_id: SupplierId,
+    /// Units reserved in each month of the season
+    pub per_month: Vec<u32>,
+    pub fee_per_unit: f64,
+}
+
+impl Reservation {
+    /// Reserve `level` of the supplier's capacity a month on average over a season of `months`,
+    /// spread by `profile` and never more than its capacity in a month, at its reservation fee
+    pub fn with_profile(supplier: &Supplier, profile: ReservationProfile, level: f64, months: usize) -> Self {
+        let average = level * supplier.fixed_capacity as f64;
+        Reservation {
+            supplier_id: supplier.id,
+            per_month: (0..months)
+                .map(|month| ((average * profile.weight(month, months)).round() as u32).min(supplier.fixed_capacity))
+                .collect(),
+            fee_per_unit: supplier.reservation_fee.unwrap_or(0.0),
+        }
+    }
+
+    /// Units reserved in a month; none outside the season
+    pub fn units_in(&self, month: usize) -> u32 {
+        self.per_month.get(month).copied().unwrap_or(0)
+    }
+
+    /// Fee for the whole reservation, paid up front
+    pub fn cost(&self) -> f64 {
+        self.per_month.iter().map(|&units| units as f64).sum::<f64>() * self.fee_per_unit
+    }
+}
+
+fn order_quantity(orders: &[ProductOrder], product_id: ProductId) -> u32 {
+    order_position(orders, product_id).map_or(0, |position| orders[position].quantity)
+}
+
+/// Set a product's quantity on its order, adding one if missing
+fn set_order_quantity(orders: &mut Vec<ProductOrder>, product_id: ProductId, quantity: u32) {
+    match order_position(orders, product_id) {
+        Some(position) => orders[position].quantity = quantity,
+        None => orders.push(ProductOrder { product_id, quantity }),
+    }
+}
+
+/// Where a product's order is; plans list the products in id order from 0, so it is usually at
+/// the product's id and only an order of some other shape is searched
+fn order_position(orders: &[ProductOrder], product_id: ProductId) -> Option<usize> {
+    let at_id = product_id.0 as usize;
+    match orders.get(at_id) {
+        Some(order) if order.product_id == product_id => Some(at_id),
+        _ => orders.iter().position(|o| o.product_id == product_id),
     }
 }
 
 /// Result for a single product in a month
 #[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ProductMonthlyResult {
-    pub product_id: usize,
+    pub product_id: ProductId,
     pub product_name: String,
     pub inventory_start: u32,
     pub incoming: u32,
+    /// Units of `incoming` delivered by the base, surge and backup suppliers
+    pub base_incoming: u32,
+    pub surge_incoming: u32,
+    pub backup_incoming: u32,
     pub demand: u32,
     pub units_sold: u32,
     pub inventory_end: u32,
@@ -125,17 +885,54 @@ pub struct ProductMonthlyResult {
     pub production_cost: f64,
     pub holding_cost: f64,
     pub liquidation_revenue: f64,
+    /// Cost of financing the month's ending stock at its book value
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub financing_cost: f64,
+    /// Goodwill lost to the month's stockout, escalating with each month in a row
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub goodwill_penalty: f64,
+}
+
+/// Direction of an exercised order-change option
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ExerciseDirection {
+    /// Add surge units on top of the current order
+    Increase,
+    /// Cancel surge (then base) units from the current order
+    Decrease,
 }
 
 /// Result of a single month's simulation (aggregated across products)
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MonthlyResult {
     pub month: String,
     pub product_results: Vec<ProductMonthlyResult>,
     pub order_change_cost: f64,
     pub setup_cost: f64,
     pub monthly_profit: f64,
+    /// Products whose orders were changed by an exercise decided this month (product_id, direction)
+    pub exercised_changes: Vec<(ProductId, ExerciseDirection)>,
+    /// Value of the worthwhile changes compared against the fee, if the option was evaluated this month
+    pub option_value: Option<f64>,
+    /// Whether an order change was exercised this month
+    pub option_exercised: bool,
+    /// Units added (positive) or removed (negative) from this month's deliveries by flexing (product_id, units)
+    pub flex_adjustments: Vec<(ProductId, i64)>,
+    /// Fees paid for flexing this month's deliveries
+    pub flex_cost: f64,
+    /// Fees for the plan's capacity reservations, all paid up front in the first month
+    pub reservation_cost: f64,
+    /// Units the plan reserved of its suppliers' capacity this month, and how many of them
+    /// those suppliers delivered
+    pub reserved_units: u32,
+    pub reserved_units_used: u32,
+    /// Indices into the parameters' expansion offers of those taken this month
+    pub expansions_accepted: Vec<usize>,
+    /// Fees for the expansion offers taken this month
+    pub expansion_cost: f64,
 }
 
 /// Complete simulation result for a supplier combination
@@ -151,9 +948,10 @@ pub struct SimulationResult {
 
 /// Allocation of quantity for a specific product
 #[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ProductAllocation {
-    pub product_id: usize,
+    pub product_id: ProductId,
     pub product_name: String,
     pub base_quantity: u32,
     pub surge_quantity: u32,
@@ -161,31 +959,595 @@ pub struct ProductAllocation {
 
 /// Monte Carlo statistics for a supplier combination
 #[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MonteCarloStats {
     pub base_supplier: String,
     pub base_supplier_lead_time: usize,
     pub surge_supplier: String,
     pub surge_supplier_lead_time: usize,
+    /// Whether the base supplier took every order alone
+    pub single_source: bool,
+    /// Backup supplier taking the surge orders the surge supplier could not deliver, if any
+    pub backup_supplier: Option<String>,
+    /// Suppliers sharing the base orders after the base supplier
+    pub pooled_suppliers: Vec<String>,
     pub product_allocations: Vec<ProductAllocation>,
     pub total_capacity_used: u32,
     pub num_simulations: usize,
+    /// Whether an adaptive run met its precision target; fixed-count runs are always converged
+    pub converged: bool,
     pub mean_profit: f64,
     pub std_dev_profit: f64,
     pub min_profit: f64,
     pub max_profit: f64,
-    pub percentile_10: f64,
-    pub percentile_25: f64,
-    pub percentile_50: f64,  // Median
-    pub percentile_75: f64,
-    pub percentile_90: f64,
+    /// Standard error of the mean profit
+    pub mean_std_error: f64,
+    /// Mean profit under the run's variance reduction, the plain mean without one
+    pub adjusted_mean_profit: f64,
+    /// Standard error of the adjusted mean profit
+    pub adjusted_std_error: f64,
+    /// Profit percentiles as (level in percent, profit), interpolated between replications
+    pub percentiles: Vec<(f64, f64)>,
+    /// Value at risk: the 5th percentile of profit
+    pub var_5: f64,
+    /// Expected shortfall: mean profit over the worst 5% of replications
+    pub cvar_5: f64,
+    /// Share of replications that lost money
+    pub prob_loss: f64,
+    /// Downside semi-deviation: root mean square shortfall of profit below the mean; None for
+    /// streaming runs, which keep no profits to measure it from
+    pub downside_deviation: Option<f64>,
+    /// Profit at evenly spaced probabilities from 0 to 1, summarizing the distribution for `prob_below`
+    pub ecdf_summary: Vec<f64>,
+    /// Profit histogram as (bin low, bin high, replications), with bins spanning [min, max]
+    pub histogram: Vec<(f64, f64, usize)>,
+    /// Confidence level of the interval on the mean profit (e.g., 0.95)
+    pub confidence_level: f64,
+    /// Lower and upper bounds of the confidence interval on the mean profit
+    pub mean_ci_low: f64,
+    pub mean_ci_high: f64,
+    /// Option exercises per product across all replications
+    pub product_exercise_counts: Vec<ProductExerciseCount>,
+    /// Number of replications by how many order changes they exercised (index = exercises)
+    pub exercise_counts: Vec<usize>,
+    /// Share of replications that exercised at least one order change
+    pub exercise_probability: f64,
+    /// Mean order changes exercised per replication
+    pub mean_exercises: f64,
+    /// Number of replications by the month of their first order change, as (month, replications)
+    pub first_exercise_counts: Vec<(String, usize)>,
+    /// Mean order change and cancellation fees paid per replication
+    pub mean_fees_paid: f64,
+    /// Share of replications that took each expansion offer, in the parameters' order
+    pub expansion_acceptance: Vec<f64>,
+    /// Mean supplier setup costs paid per replication
+    pub mean_setup_cost: f64,
+    /// Mean profit before setup costs and order change fees, the season's operational performance
+    pub mean_operational_profit: f64,
+    /// Mean revenue per unit sold at full price
+    pub revenue_per_unit: f64,
+    /// Mean landed cost per unit delivered: unit costs, which include freight and duty, plus
+    /// setup costs spread over the mean units delivered in a season
+    pub landed_cost_per_unit: f64,
+    /// Share of the revenue per unit left after its landed cost
+    pub landed_margin: f64,
+    /// Option value and exercise frequency for each month of the season
+    pub monthly_option_summary: Vec<MonthlyOptionSummary>,
+    /// Mean revenue, costs and profit for each month of the season
+    pub monthly_breakdown: Vec<MonthStats>,
+    /// Mean revenue, costs and contribution of each product over the season
+    pub product_breakdown: Vec<ProductStats>,
+    /// Mean season profit and loss of each product, with shared costs allocated
+    pub product_pnl: Vec<ProductPnl>,
+    /// Share of each supplier's monthly capacity delivered, averaged over the season
+    pub mean_base_utilization: f64,
+    pub mean_surge_utilization: f64,
+    /// Zero without a backup supplier
+    pub mean_backup_utilization: f64,
+    /// Season use of each supplier's capacity, base supplier first, and what orders asked of
+    /// them beyond it
+    pub capacity_usage: Vec<CapacityUsageStats>,
+    /// Replication profits kept under the run's sample retention, in replication order
+    pub samples: Vec<ProfitSample>,
+    /// Month-by-month results of the replication with the lowest profit, the first if tied
+    pub worst_case_trace: Option<ReplicationTrace>,
+    /// Month-by-month results of the replication with the highest profit, the first if tied
+    pub best_case_trace: Option<ReplicationTrace>,
+    /// Month-by-month results of a uniform sample of replications, in replication order, as
+    /// many as the run's `trace_samples`
+    pub sampled_traces: Vec<ReplicationTrace>,
+    /// Lowest-profit month of the worst-case replication and what drove it
+    pub worst_month: Option<WorstMonth>,
+    /// How far each product's realized demand strayed from the forecast the plan was made on
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub forecast_accuracy: Vec<ForecastAccuracy>,
+    /// The plan optimized on the actual demand parameters against the chosen plan, first and
+    /// second, on the same demand paths; None when the forecast is the actual demand
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cost_of_forecast_error: Option<PairedComparison>,
+}
+
+impl MonteCarloStats {
+    /// Name of the simulated pair, e.g. "base + surge"
+    pub fn pair_name(&self) -> String {
+        group_name(
+            &committed_tier_name(&self.base_supplier, &self.pooled_suppliers),
+            &self.surge_supplier,
+            self.backup_supplier.as_deref(),
+            self.single_source,
+        )
+    }
+
+    /// Profit at a requested percentile level (in percent), if it was computed
+    pub fn percentile(&self, level: f64) -> Option<f64> {
+        self.percentiles.iter().find(|(l, _)| *l == level).map(|(_, profit)| *profit)
+    }
+
+    pub fn p10(&self) -> Option<f64> {
+        self.percentile(10.0)
+    }
+
+    /// Median profit
+    pub fn p50(&self) -> Option<f64> {
+        self.percentile(50.0)
+    }
+
+    pub fn p90(&self) -> Option<f64> {
+        self.percentile(90.0)
+    }
+
+    /// Half-width of the confidence interval on the mean profit
+    pub fn mean_ci_half_width(&self) -> f64 {
+        (self.mean_ci_high - self.mean_ci_low) / 2.0
+    }
+
+    /// Estimated probability that a season's profit falls below `threshold`,
+    /// interpolated from the ECDF summary (accurate to its grid spacing)
+    pub fn prob_below(&self, threshold: f64) -> f64 {
+        grid_cdf(&self.ecdf_summary, threshold)
+    }
+
+    /// Whether the confidence intervals on the mean profit of two results overlap,
+    /// in which case ranking one above the other is not statistically meaningful
+    pub fn mean_ci_overlaps(&self, other: &MonteCarloStats) -> bool {
+        self.mean_ci_low <= other.mean_ci_high && other.mean_ci_low <= self.mean_ci_high
+    }
+}
+
+/// Everything a run produced: its scenario, every pair's optimized plan and statistics, and
+/// the best pair, for machine-readable output and reports
+/// Archived runs of any earlier layout are read back through `schema::load_run`
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RunOutput {
+    /// Layout the run is written in, `schema::SCHEMA_VERSION` for a run made by this simulator
+    pub schema_version: u32,
+    pub params: SimulationParams,
+    pub suppliers: Vec<Supplier>,
+    pub ranking: RankingMetric,
+    /// Every simulated pair, in evaluation order
+    pub pairs: Vec<PairRun>,
+    /// Index into `pairs` of the best pair under `ranking`
+    pub best_pair: Option<usize>,
+    /// Pairs screening kept from being simulated, and why
+    pub rejected_pairs: Vec<RejectedPair>,
 }
 
-/// Option valuation state for binomial tree
+/// A quick screening check a supplier pair failed
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ScreeningFailure {
+    /// The quick profit estimate fell short of the threshold
+    EstimateBelowThreshold { estimate: f64, threshold: f64 },
+    /// The suppliers together cannot make `min_coverage` of the expected monthly demand
+    InadequateCapacity { capacity: u32, demand: f64, min_coverage: f64 },
+    /// Units cost too large a share of their selling price, weighted by expected demand
+    CostlyForPrice { cost_to_price: f64, max: f64 },
+    /// The suppliers' setup costs together exceed the cap on onboarding spend
+    SetupCostOverCap { setup_cost: f64, max: f64 },
+}
+
+/// Why a supplier pair was kept from being simulated
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RejectionReason {
+    /// Suppliers in the same region, which one disruption could stop together
+    SharedRegion { region: String },
+    /// More suppliers of one risk class than the pairing rules allow
+    ConcentratedRiskClass { risk_class: String, suppliers: usize, max: usize },
+    /// Another pair sharing one supplier is strictly better on every count
+    Dominated { by: String },
+    /// A quick screening check failed
+    #[cfg_attr(feature = "serde", serde(untagged))]
+    Screening(ScreeningFailure),
+}
+
+/// A supplier pair dropped by screening, named for reports
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RejectedPair {
+    pub base_supplier: String,
+    pub surge_supplier: String,
+    pub backup_supplier: Option<String>,
+    pub pooled_suppliers: Vec<String>,
+    pub single_source: bool,
+    pub reason: RejectionReason,
+}
+
+impl RejectedPair {
+    pub fn new(pair: &SupplierPair, reason: RejectionReason) -> Self {
+        RejectedPair {
+            base_supplier: pair.base_supplier.name.clone(),
+            surge_supplier: pair.surge_supplier.name.clone(),
+            backup_supplier: pair.backup_supplier.as_ref().map(|backup| backup.name.clone()),
+            pooled_suppliers: pair.pooled_suppliers.iter().map(|supplier| supplier.name.clone()).collect(),
+            single_source: pair.is_single_source(),
+            reason,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        group_name(
+            &committed_tier_name(&self.base_supplier, &self.pooled_suppliers),
+            &self.surge_supplier,
+            self.backup_supplier.as_deref(),
+            self.single_source,
+        )
+    }
+}
+
+/// The optimizer's plan and the Monte Carlo statistics of one supplier pair
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PairRun {
+    /// Rough profit estimate used to screen the pair
+    pub quick_estimate: f64,
+    /// Season quantity per product chosen by the optimizer, as (product_id, units)
+    pub optimal_quantities: Vec<(ProductId, u32)>,
+    pub stats: MonteCarloStats,
+}
+
+/// Profit difference between two plans simulated on the same demand paths
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PairedComparison {
+    /// The two plans compared, as "base + surge" supplier names
+    pub first: String,
+    pub second: String,
+    pub num_simulations: usize,
+    pub mean_profit_first: f64,
+    pub mean_profit_second: f64,
+    /// Mean of the per-path profit differences, first minus second
+    pub mean_difference: f64,
+    pub std_dev_difference: f64,
+    /// Confidence level of the interval on the mean difference (e.g., 0.95)
+    pub confidence_level: f64,
+    /// Lower and upper bounds of the confidence interval on the mean difference
+    pub difference_ci_low: f64,
+    pub difference_ci_high: f64,
+    /// Two-sided p-value of the paired test that the mean difference is zero
+    pub p_value: f64,
+}
+
+impl PairedComparison {
+    /// Whether the difference is significant at the comparison's confidence level
+    pub fn is_significant(&self) -> bool {
+        self.p_value < 1.0 - self.confidence_level
+    }
+}
+
+/// Statistic by which supplier pairs are ranked, higher being better
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RankingMetric {
+    /// Expected season profit
+    MeanProfit,
+    /// Mean profit over the worst 5% of seasons, favouring pairs with a safer downside
+    ExpectedShortfall,
+    /// Mean profit before setup costs and order change fees, for setups amortized over several seasons
+    OperationalProfit,
+}
+
+impl FromStr for RankingMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mean-profit" => Ok(RankingMetric::MeanProfit),
+            "expected-shortfall" => Ok(RankingMetric::ExpectedShortfall),
+            "operational-profit" => Ok(RankingMetric::OperationalProfit),
+            _ => Err(format!(
+                "expected mean-profit, expected-shortfall or operational-profit, got \"{}\"", s
+            )),
+        }
+    }
+}
+
+impl RankingMetric {
+    /// Score of a result under this metric
+    pub fn score(&self, stats: &MonteCarloStats) -> f64 {
+        match self {
+            RankingMetric::MeanProfit => stats.mean_profit,
+            RankingMetric::ExpectedShortfall => stats.cvar_5,
+            RankingMetric::OperationalProfit => stats.mean_operational_profit,
+        }
+    }
+}
+
+/// Option activity in one month across Monte Carlo replications
 #[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MonthlyOptionSummary {
+    pub month: String,
+    /// Mean option value over the replications that evaluated the option this month
+    pub mean_option_value: f64,
+    /// Share of replications that exercised an order change this month
+    pub exercise_frequency: f64,
+}
+
+/// Season profit of one Monte Carlo replication
+/// Reseeding the demand generator with `seed`, mirrored if `antithetic` and conditioned on
+/// `stratum`, and simulating the same plan reproduces it
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProfitSample {
+    pub replication: usize,
+    pub seed: u64,
+    /// Whether the replication mirrored its demand draws (antithetic runs)
+    pub antithetic: bool,
+    /// Stratum and number of strata the season's demand was drawn from (stratified runs)
+    pub stratum: Option<(usize, usize)>,
+    pub profit: f64,
+    /// Months in which an order change was exercised
+    pub exercises: usize,
+    /// Share of the season's demand that was met
+    pub fill_rate: f64,
+}
+
+/// Complete monthly results of one Monte Carlo replication, with the sample that reproduces it
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReplicationTrace {
+    pub sample: ProfitSample,
+    pub monthly_results: Vec<MonthlyResult>,
+}
+
+/// Cost that weighed most on a month's profit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LossDriver {
+    /// Sales lost to demand the stock could not meet, valued at the selling price
+    Stockout,
+    /// Carrying unsold stock
+    Holding,
+    /// Order change, flex and setup fees
+    Fees,
+}
+
+/// The lowest-profit month of a season and the cost behind it
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WorstMonth {
+    pub month: String,
+    pub profit: f64,
+    pub driver: LossDriver,
+    /// What the driver cost that month
+    pub driver_cost: f64,
+}
+
+/// Mean profit and loss for one month of the season across Monte Carlo replications
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MonthStats {
+    pub month: String,
+    pub mean_revenue: f64,
+    pub mean_production_cost: f64,
+    pub mean_holding_cost: f64,
+    pub mean_liquidation_revenue: f64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mean_financing_cost: f64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mean_goodwill_penalty: f64,
+    /// Order change, setup, flex and reservation fees, which are not attributed to products
+    pub mean_other_costs: f64,
+    pub mean_profit: f64,
+    /// Share of each supplier's monthly capacity delivered, after order changes and flexing
+    pub mean_base_utilization: f64,
+    pub mean_surge_utilization: f64,
+    pub mean_backup_utilization: f64,
+    /// Units the plan reserved of its suppliers' capacity, and the mean delivered of them
+    pub reserved_units: u32,
+    pub mean_reserved_units_used: f64,
+}
+
+/// Mean use of one supplier's capacity over a season across Monte Carlo replications
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CapacityUsageStats {
+    pub supplier_id: SupplierId,
+    pub supplier_name: String,
+    pub mean_delivered: f64,
+    /// Nominal capacity over the season, within any reservation and with any expansion taken
+    pub mean_nominal_capacity: f64,
+    /// Months the supplier delivered all of its nominal capacity
+    pub mean_months_at_capacity: f64,
+    /// Units orders asked of the supplier beyond what it had left, which it never delivered
+    pub mean_shortfall: f64,
+}
+
+impl CapacityUsageStats {
+    /// Share of the season's nominal capacity delivered
+    pub fn utilization(&self) -> f64 {
+        if self.mean_nominal_capacity > 0.0 { self.mean_delivered / self.mean_nominal_capacity } else { 0.0 }
+    }
+}
+
+/// Mean season totals for one product across Monte Carlo replications
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProductStats {
+    pub product_id: ProductId,
+    pub product_name: String,
+    pub mean_demand: f64,
+    pub mean_units_sold: f64,
+    pub mean_revenue: f64,
+    pub mean_production_cost: f64,
+    pub mean_holding_cost: f64,
+    pub mean_liquidation_revenue: f64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mean_financing_cost: f64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mean_goodwill_penalty: f64,
+    /// Revenue plus liquidation less production, holding and financing costs and goodwill
+    /// penalties, before fees and setup
+    pub mean_contribution: f64,
+    /// Stock left at the end of the season, nothing unless it is carried over
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mean_closing_inventory: f64,
+}
+
+/// How well one product's demand forecast matched the demand its replications realized
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ForecastAccuracy {
+    pub product_id: ProductId,
+    pub product_name: String,
+    /// Monthly demand the plan was made on
+    pub forecast_mean_demand: f64,
+    /// Mean monthly demand across replications
+    pub realized_mean_demand: f64,
+    /// Realized less forecast mean monthly demand, positive when the forecast fell short
+    pub bias: f64,
+    /// Mean absolute error of the forecast as a share of each period's realized demand, over
+    /// every period of every replication that saw any
+    pub mape: f64,
+}
+
+/// Mean season profit and loss of one product in whole currency units
+/// Supplier setup costs and fees, which no product incurs alone, are allocated by revenue share;
+/// unit costs are landed costs, so freight and duty are part of the cost of goods
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProductPnl {
+    pub product_id: ProductId,
+    pub product_name: String,
+    pub revenue: f64,
+    pub cost_of_goods: f64,
+    pub holding_cost: f64,
+    pub liquidation_revenue: f64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub financing_cost: f64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub goodwill_penalty: f64,
+    /// Allocated share of supplier setup costs
+    pub setup_cost: f64,
+    /// Allocated share of order change, cancellation and flex fees
+    pub fees: f64,
+    /// Revenue and liquidation less every cost above
+    pub profit: f64,
+}
+
+/// Number of times a product's orders were changed across Monte Carlo replications
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProductExerciseCount {
+    pub product_id: ProductId,
+    pub product_name: String,
+    pub increases: usize,
+    pub decreases: usize,
+}
+
+/// Observable state of the season at an order change decision, after the month's sales
 #[derive(Clone, Debug)]
 pub struct OptionState {
+    /// Month of the decision (0 = May)
     pub month: usize,
-    pub inventory: u32,
-    pub cumulative_uplifts: i32, // Net up/down movements in binomial tree
+    /// Inventory on hand per product (product_id -> units)
+    pub inventories: HashMap<ProductId, u32>,
+    /// Monthly order currently in force
+    pub current_order: MonthlyOrder,
+    /// Realized demand per product so far this season, oldest first
+    pub observed_demand: HashMap<ProductId, Vec<u32>>,
+    /// Order changes already exercised this season
+    pub exercises_used: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monte_carlo::tests::two_product_scenario;
+
+    fn orders(quantities: &[(u32, u32)]) -> Vec<ProductOrder> {
+        quantities.iter().map(|&(id, quantity)| ProductOrder { product_id: ProductId(id), quantity }).collect()
+    }
+
+    #[test]
+    fn test_orders_name_each_product_once() {
+        let order = MonthlyOrder::new(orders(&[(0, 5_000), (1, 2_000)]), orders(&[(0, 1_000)])).unwrap();
+        assert_eq!((order.total_base_quantity(), order.total_surge_quantity()), (7_000, 1_000));
+        assert_eq!(
+            MonthlyOrder::new(orders(&[(0, 5_000), (1, 2_000), (0, 5_000)]), Vec::new()).unwrap_err(),
+            OrderError::DuplicateProduct { product_id: ProductId(0), side: "base" },
+        );
+        assert_eq!(
+            MonthlyOrder::new(Vec::new(), orders(&[(1, 0), (1, 0)])).unwrap_err(),
+            OrderError::DuplicateProduct { product_id: ProductId(1), side: "surge" },
+        );
+
+        let (params, _) = two_product_scenario();
+        assert!(MonthlyOrder::for_params(&params, orders(&[(0, 5_000), (1, 2_000)]), Vec::new()).is_ok());
+        assert_eq!(
+            MonthlyOrder::for_params(&params, orders(&[(0, 5_000)]), orders(&[(7, 100)])).unwrap_err(),
+            OrderError::UnknownProduct(ProductId(7)),
+        );
+    }
+
+    #[test]
+    fn test_accessors_agree_with_the_totals() {
+        let mut order = MonthlyOrder::new(orders(&[(0, 6_000), (1, 2_000)]), orders(&[(1, 300)])).unwrap();
+        let per_product = |order: &MonthlyOrder| [ProductId(0), ProductId(1)].map(|id| (order.base_quantity_for(id), order.surge_quantity_for(id)));
+        assert_eq!(per_product(&order), [(6_000, 0), (2_000, 300)]);
+        assert_eq!(order.total_base_quantity(), 8_000);
+
+        // Setting a quantity leaves the product a single order
+        order.set_base_quantity(ProductId(0), 4_000);
+        assert_eq!(order.base_orders(), orders(&[(0, 4_000), (1, 2_000)]));
+        order.add_surge_quantity(ProductId(1), 200);
+        order.add_surge_quantity(ProductId(0), 50);
+        order.add_base_quantity(ProductId(1), 500);
+        assert_eq!(per_product(&order), [(4_000, 50), (2_500, 500)]);
+        assert_eq!(order.surge_orders(), orders(&[(1, 500), (0, 50)]));
+        assert_eq!((order.total_base_quantity(), order.total_surge_quantity()), (6_500, 550));
+    }
+
+    /// Per-product lookups on an order of 40 products, each ordered once and so found where its
+    /// id points, against summing over every order as when a product could be ordered twice
+    /// Run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_order_accessors() {
+        let quantities: Vec<(u32, u32)> = (0..40).map(|id| (id, 1_000 + id)).collect();
+        let order = MonthlyOrder::new(orders(&quantities), orders(&quantities)).unwrap();
+        let ids: Vec<ProductId> = (0..40).map(ProductId).collect();
+        let summed = |orders: &[ProductOrder], id: ProductId| orders.iter().filter(|o| o.product_id == id).map(|o| o.quantity).sum::<u32>();
+        let lookups: [(&str, &dyn Fn(ProductId) -> u32); 2] = [
+            ("by id", &|id| order.base_quantity_for(id) + order.surge_quantity_for(id)),
+            ("every order", &|id| summed(order.base_orders(), id) + summed(order.surge_orders(), id)),
+        ];
+        for (name, lookup) in lookups {
+            let start = std::time::Instant::now();
+            let mut total = 0u64;
+            for _ in 0..100_000 {
+                total += ids.iter().map(|&id| lookup(std::hint::black_box(id)) as u64).sum::<u64>();
+            }
+            println!("{:>11}: {:>8.1?} for 4M lookups (total {})", name, start.elapsed(), total);
+        }
+    }
 }
@@ -0,0 +1,363 @@
+//! Amounts of money tagged with their currency
+//! Amounts are held in minor units, so adding and subtracting them is exact, and amounts in
+//! different currencies are never added together: the arithmetic errors instead, and
+//! `FxRates` converts between them
+//! The models still hold their costs, revenues and profits as plain `f64`s in the scenario's
+//! currency: `Supplier::unit_costs` and `setup_cost`, `ProductMonthlyResult::revenue` and
+//! `MonteCarloStats::mean_profit` among them have not moved to `Money`. They are read as `Money`
+//! through the `*_in` accessors below, and `Money::as_f64` goes back to the plain numbers the
+//! optimizer and simulation work on
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::models::{MonteCarloStats, MonthlyResult, Product, ProductId, ProductMonthlyResult, Supplier};
+
+/// Currency an amount is in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "UPPERCASE"))]
+pub enum Currency {
+    Usd,
+    Eur,
+    Gbp,
+    Cny,
+    Jpy,
+}
+
+impl Currency {
+    /// ISO 4217 code, e.g. USD
+    pub fn code(self) -> &'static str {
+        match self {
+            Currency::Usd => "USD",
+            Currency::Eur => "EUR",
+            Currency::Gbp => "GBP",
+            Currency::Cny => "CNY",
+            Currency::Jpy => "JPY",
+        }
+    }
+
+    /// Symbol written next to an amount, e.g. $
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Currency::Usd => "$",
+            Currency::Eur => "€",
+            Currency::Gbp => "£",
+            Currency::Cny | Currency::Jpy => "¥",
+        }
+    }
+
+    /// Decimal places of the minor unit: 2 for cents, 0 for the yen
+    pub fn decimal_places(self) -> usize {
+        match self {
+            Currency::Jpy => 0,
+            _ => 2,
+        }
+    }
+
+    /// Minor units in one whole unit
+    fn minor_per_unit(self) -> f64 {
+        10f64.powi(self.decimal_places() as i32)
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Why an amount of money could not be computed
+#[derive(Debug, Error, PartialEq)]
+pub enum MoneyError {
+    /// Amounts in two currencies were combined without converting one of them
+    #[error("cannot combine {left} with {right} without converting one of them")]
+    CurrencyMismatch {
+        /// Currency of the left-hand amount
+        left: Currency,
+        /// Currency of the right-hand amount
+        right: Currency,
+    },
+    /// There is no exchange rate to convert between two currencies
+    #[error("no exchange rate from {from} to {to}")]
+    MissingRate {
+        /// Currency converted from
+        from: Currency,
+        /// Currency converted to
+        to: Currency,
+    },
+    /// The result does not fit in the minor units an amount is held in
+    #[error("amount out of range")]
+    Overflow,
+}
+
+/// Amount of money in a currency, held in the currency's minor units
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Money {
+    minor_units: i64,
+    currency: Currency,
+}
+
+impl Money {
+    /// Amount of `minor_units`, e.g. cents
+    pub fn from_minor(minor_units: i64, currency: Currency) -> Self {
+        Money { minor_units, currency }
+    }
+
+    /// Amount rounded to the nearest minor unit, halves away from zero
+    /// An amount too large for the minor units saturates, and NaN is zero
+    pub fn from_f64(amount: f64, currency: Currency) -> Self {
+        Money { minor_units: (amount * currency.minor_per_unit()).round() as i64, currency }
+    }
+
+    /// Nothing in `currency`
+    pub fn zero(currency: Currency) -> Self {
+        Money::from_minor(0, currency)
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn minor_units(&self) -> i64 {
+        self.minor_units
+    }
+
+    /// Amount in whole units, for the math that works on plain numbers
+    pub fn as_f64(&self) -> f64 {
+        self.minor_units as f64 / self.currency.minor_per_unit()
+    }
+
+    /// Sum of two amounts in the same currency
+    pub fn checked_add(self, other: Money) -> Result<Money, MoneyError> {
+        let currency = self.same_currency(other)?;
+        let minor_units = self.minor_units.checked_add(other.minor_units).ok_or(MoneyError::Overflow)?;
+        Ok(Money { minor_units, currency })
+    }
+
+    /// Difference of two amounts in the same currency
+    pub fn checked_sub(self, other: Money) -> Result<Money, MoneyError> {
+        self.checked_add(-other)
+    }
+
+    fn same_currency(self, other: Money) -> Result<Currency, MoneyError> {
+        if self.currency == other.currency {
+            Ok(self.currency)
+        } else {
+            Err(MoneyError::CurrencyMismatch { left: self.currency, right: other.currency })
+        }
+    }
+}
+
+impl Add for Money {
+    type Output = Result<Money, MoneyError>;
+
+    fn add(self, other: Money) -> Self::Output {
+        self.checked_add(other)
+    }
+}
+
+impl Sub for Money {
+    type Output = Result<Money, MoneyError>;
+
+    fn sub(self, other: Money) -> Self::Output {
+        self.checked_sub(other)
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money { minor_units: self.minor_units.saturating_neg(), currency: self.currency }
+    }
+}
+
+impl Mul<f64> for Money {
+    type Output = Money;
+
+    /// Amount scaled by `factor`, e.g. a unit cost by a quantity, rounded to the minor unit
+    fn mul(self, factor: f64) -> Money {
+        Money { minor_units: (self.minor_units as f64 * factor).round() as i64, currency: self.currency }
+    }
+}
+
+impl fmt::Display for Money {
+    /// Code and amount to the currency's minor unit, e.g. USD -1234.57
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:.*}", self.currency, self.currency.decimal_places(), self.as_f64())
+    }
+}
+
+/// Exchange rates against a base currency
+#[derive(Clone, Debug, PartialEq)]
+pub struct FxRates {
+    base: Currency,
+    // Units of each currency one unit of the base buys
+    rates: HashMap<Currency, f64>,
+}
+
+impl FxRates {
+    /// Rates knowing only the base currency
+    pub fn new(base: Currency) -> Self {
+        FxRates { base, rates: HashMap::from([(base, 1.0)]) }
+    }
+
+    /// The rates with one unit of the base buying `per_base` units of `currency`
+    pub fn with_rate(mut self, currency: Currency, per_base: f64) -> Self {
+        if currency != self.base {
+            self.rates.insert(currency, per_base);
+        }
+        self
+    }
+
+    pub fn base(&self) -> Currency {
+        self.base
+    }
+
+    /// `money` in `to`, through the base currency and rounded to the nearest minor unit of `to`
+    pub fn convert(&self, money: Money, to: Currency) -> Result<Money, MoneyError> {
+        if money.currency == to {
+            return Ok(money);
+        }
+        let rate = |currency: Currency| self.rates.get(&currency).copied()
+            .filter(|rate| rate.is_finite() && *rate > 0.0)
+            .ok_or(MoneyError::MissingRate { from: money.currency, to });
+        let converted = money.as_f64() / rate(money.currency)? * rate(to)?;
+        let minor_units = (converted * to.minor_per_unit()).round();
+        if minor_units.abs() >= i64::MAX as f64 {
+            return Err(MoneyError::Overflow);
+        }
+        Ok(Money { minor_units: minor_units as i64, currency: to })
+    }
+}
+
+// The models hold amounts as plain numbers in the scenario's currency, not yet `Money`; these
+// read them as `Money` in `currency`
+
+impl Product {
+    pub fn selling_price_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.selling_price, currency)
+    }
+
+    pub fn liquidation_price_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.liquidation_price, currency)
+    }
+
+    pub fn monthly_holding_cost_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.monthly_holding_cost, currency)
+    }
+}
+
+impl Supplier {
+    /// Unit cost of `product_id`, when the supplier makes it
+    pub fn unit_cost_in(&self, product_id: ProductId, currency: Currency) -> Option<Money> {
+        self.unit_costs.get(&product_id).map(|&cost| Money::from_f64(cost, currency))
+    }
+
+    pub fn setup_cost_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.setup_cost, currency)
+    }
+}
+
+impl ProductMonthlyResult {
+    pub fn revenue_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.revenue, currency)
+    }
+
+    pub fn production_cost_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.production_cost, currency)
+    }
+
+    pub fn holding_cost_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.holding_cost, currency)
+    }
+
+    pub fn liquidation_revenue_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.liquidation_revenue, currency)
+    }
+
+    pub fn financing_cost_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.financing_cost, currency)
+    }
+
+    pub fn goodwill_penalty_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.goodwill_penalty, currency)
+    }
+}
+
+impl MonthlyResult {
+    pub fn monthly_profit_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.monthly_profit, currency)
+    }
+
+    /// Order change, setup, flex, reservation and expansion costs of the month together
+    pub fn fees_in(&self, currency: Currency) -> Money {
+        let fees = self.order_change_cost + self.setup_cost + self.flex_cost + self.reservation_cost + self.expansion_cost;
+        Money::from_f64(fees, currency)
+    }
+}
+
+impl MonteCarloStats {
+    pub fn mean_profit_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.mean_profit, currency)
+    }
+
+    pub fn min_profit_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.min_profit, currency)
+    }
+
+    pub fn max_profit_in(&self, currency: Currency) -> Money {
+        Money::from_f64(self.max_profit, currency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reporting::Formatter;
+
+    #[test]
+    fn test_amounts_in_different_currencies_do_not_mix() {
+        let dollars = Money::from_f64(10.0, Currency::Usd);
+        let euros = Money::from_f64(4.0, Currency::Eur);
+        assert_eq!(dollars + euros, Err(MoneyError::CurrencyMismatch { left: Currency::Usd, right: Currency::Eur }));
+        assert_eq!(euros - dollars, Err(MoneyError::CurrencyMismatch { left: Currency::Eur, right: Currency::Usd }));
+        assert_eq!(dollars + Money::from_minor(250, Currency::Usd), Ok(Money::from_minor(1_250, Currency::Usd)));
+        assert_eq!(Money::from_minor(i64::MAX, Currency::Usd) + Money::from_minor(1, Currency::Usd), Err(MoneyError::Overflow));
+    }
+
+    #[test]
+    fn test_amounts_round_to_the_minor_unit() {
+        assert_eq!(Money::from_f64(0.125, Currency::Usd).minor_units(), 13);
+        assert_eq!(Money::from_f64(-0.125, Currency::Usd).minor_units(), -13);
+        assert_eq!(Money::from_f64(99.5, Currency::Jpy).minor_units(), 100);
+        assert_eq!(Money::from_f64(160.0, Currency::Usd) * 3.0, Money::from_f64(480.0, Currency::Usd));
+        assert_eq!(Money::from_minor(1, Currency::Usd) * 0.5, Money::from_minor(1, Currency::Usd));
+
+        // 1 USD buys 0.92 EUR and 151.37 JPY
+        let rates = FxRates::new(Currency::Usd).with_rate(Currency::Eur, 0.92).with_rate(Currency::Jpy, 151.37);
+        assert_eq!(rates.convert(Money::from_f64(10.0, Currency::Usd), Currency::Eur), Ok(Money::from_f64(9.2, Currency::Eur)));
+        assert_eq!(rates.convert(Money::from_f64(1.0, Currency::Eur), Currency::Usd), Ok(Money::from_f64(1.09, Currency::Usd)));
+        assert_eq!(rates.convert(Money::from_f64(0.01, Currency::Usd), Currency::Jpy), Ok(Money::from_minor(2, Currency::Jpy)));
+        assert_eq!(
+            rates.convert(Money::from_f64(1.0, Currency::Usd), Currency::Gbp),
+            Err(MoneyError::MissingRate { from: Currency::Usd, to: Currency::Gbp }),
+        );
+    }
+
+    #[test]
+    fn test_money_formatting() {
+        assert_eq!(Money::from_f64(-1_234.567, Currency::Usd).to_string(), "USD -1234.57");
+        assert_eq!(Money::from_f64(1_500.0, Currency::Jpy).to_string(), "JPY 1500");
+
+        let formatter = Formatter::default();
+        assert_eq!(formatter.currency_money(Money::from_f64(-1_234.567, Currency::Usd)), "-$1,234.57");
+        assert_eq!(formatter.currency_money(Money::from_f64(1_234_567.0, Currency::Jpy)), "¥1,234,567");
+        assert_eq!(Formatter::european().currency_money(Money::from_f64(10_234.5, Currency::Gbp)), "10 234,50 £");
+    }
+}
@@ -1,27 +1,601 @@
-/// Monte Carlo simulation and statistical analysis module
-/// Updated for multi-product simulation
+//! Monte Carlo runs of a plan: replications on independent or matched demand paths, summarized
+//! into profit statistics, in memory or streamed
 
-use crate::models::{MonteCarloStats, MonthlyOrder, ProductAllocation, SimulationParams, SupplierPair};
-use crate::simulation::run_monthly_simulation;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use tracing::{debug, debug_span};
+use std::cmp;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::capacity::{utilization, CapacityUsage};
+use crate::demand::{clear_demand_script, expected_simulation_demand, script_stratified_demand, seed_demand_rng, set_antithetic_demand};
+use crate::error::SimulationError;
+use crate::models::{CapacityUsageStats, ExerciseDirection, ForecastAccuracy, LossDriver, MonteCarloStats, MonthStats, MonthlyOptionSummary, MonthlyOrder, MonthlyResult, PairedComparison, ProductAllocation, ProductExerciseCount, ProductPnl, ProductStats, ProfitSample, ReplicationTrace, SimulationParams, SupplierId, SupplierPair, WorstMonth};
+use crate::progress::{NoProgress, ProgressSink};
+use crate::simulation::{period_demand, run_monthly_simulation_with_usage};
+use crate::stats::{inverse_standard_normal_cdf, standard_normal_cdf};
+use crate::streaming::{grid_cdf, interpolated_quantile, Reservoir, StreamingQuantiles, StreamingStats};
+
+/// How many replications to run and how to seed and schedule them
+#[derive(Clone, Debug)]
+pub struct MonteCarloConfig {
+    /// Number of simulated seasons
+    pub num_simulations: usize,
+    /// Seed from which every replication's demand generator is derived
+    pub master_seed: u64,
+    /// Spread replications over the rayon thread pool; turn off when the caller is already parallel
+    /// Without the `parallel` feature replications always run one at a time
+    pub parallel: bool,
+    /// Confidence level of the interval reported on the mean profit
+    pub confidence_level: f64,
+    /// Profit percentiles to report, in percent (0 and 100 give the extremes)
+    pub percentile_levels: Vec<f64>,
+    /// Bins in the profit histogram; None picks a Freedman–Diaconis width
+    pub histogram_bins: Option<usize>,
+    /// Which replication profits to keep in the statistics for export
+    pub sample_retention: SampleRetention,
+    /// Full traces of this many uniformly chosen replications to keep for debugging; 0 keeps none
+    pub trace_samples: usize,
+    /// How the adjusted mean profit is estimated from the replications
+    pub variance_reduction: VarianceReduction,
+}
+
+/// Replication profits kept alongside the summary statistics
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleRetention {
+    /// Keep only the summary
+    None,
+    /// Keep every replication's profit
+    All,
+    /// Keep a uniform random subset of this many replications, bounding memory on long runs
+    Reservoir(usize),
+}
+
+impl MonteCarloConfig {
+    /// Run `num_simulations` replications in parallel from a randomly drawn master seed,
+    /// reporting a 95% confidence interval on the mean profit and the quartiles, deciles and median
+    pub fn new(num_simulations: usize) -> Self {
+        MonteCarloConfig {
+            num_simulations,
+            master_seed: rand::random(),
+            parallel: true,
+            confidence_level: 0.95,
+            percentile_levels: vec![10.0, 25.0, 50.0, 75.0, 90.0],
+            histogram_bins: None,
+            sample_retention: SampleRetention::None,
+            trace_samples: 0,
+            variance_reduction: VarianceReduction::None,
+        }
+    }
+}
+
+/// Variance reduction behind the adjusted mean profit and its standard error
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VarianceReduction {
+    /// Independent replications; the adjusted mean is the plain mean
+    None,
+    /// Replications run in pairs on one seed, the second mirroring the first's demand about the
+    /// mean, and the standard error comes from the pair averages
+    /// Order changes are decided on each season's own demand and do not mirror, so the more
+    /// seasons exercise them the less of the spread the pairs cancel
+    Antithetic,
+    /// The mean is corrected by regressing profit on the season's total demand, whose
+    /// expectation is known, with the coefficient estimated from the replications
+    ControlVariate,
+    /// Replications are spread evenly over equal-probability strata of the season's demand
+    /// surprise, and the mean and its standard error are combined across strata
+    Stratified { strata: usize },
+}
+
+/// Seed for one replication, mixed from the master seed with SplitMix64 so neighbouring
+/// replications draw unrelated demand paths
+pub(crate) fn replication_seed(master_seed: u64, replication: usize) -> u64 {
+    let mut z = master_seed.wrapping_add((replication as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Reproduce one replication of a run from the demand stream recorded in its `ProfitSample`
+#[allow(dead_code)]
+pub fn replay_replication(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    monthly_order: &MonthlyOrder,
+    sample: &ProfitSample,
+) -> Result<(Vec<MonthlyResult>, f64), SimulationError> {
+    let stream = DemandStream { seed: sample.seed, antithetic: sample.antithetic, stratum: sample.stratum };
+    let (monthly_results, total_profit, _) = stream.simulate(params, pair, monthly_order)?;
+    Ok((monthly_results, total_profit))
+}
+
+/// Confidence level of the interval on the difference reported by `compare`
+const COMPARISON_CONFIDENCE_LEVEL: f64 = 0.95;
+
+/// Compare two plans, possibly with different supplier pairs, on identical demand paths
+/// Replication i of both plans draws its demand from the same seed (common random numbers), so
+/// the per-path differences cancel the demand noise the plans share. The interval and p-value
+/// are those of the paired t-test, with the normal approximation used elsewhere for intervals
+pub fn compare(
+    params: &SimulationParams,
+    pair_a: &SupplierPair,
+    order_a: &MonthlyOrder,
+    pair_b: &SupplierPair,
+    order_b: &MonthlyOrder,
+    num_simulations: usize,
+    master_seed: u64,
+) -> Result<PairedComparison, SimulationError> {
+    let profits = paired_replications(num_simulations, master_seed, |replication, seed| {
+        let stream = DemandStream { seed, antithetic: false, stratum: None };
+        let (_, profit_a, _) = stream.simulate(params, pair_a, order_a)?;
+        let (_, profit_b, _) = stream.simulate(params, pair_b, order_b)?;
+        Ok((checked_profit(pair_a, replication, profit_a)?, checked_profit(pair_b, replication, profit_b)?))
+    })?;
+    let label = SupplierPair::name;
+    Ok(paired_comparison(label(pair_a), label(pair_b), &profits))
+}
+
+/// Profits of two arms of `num_simulations` replications, replication i of both on the seed
+/// `replicate` is given for it, in replication order
+pub(crate) fn paired_replications(
+    num_simulations: usize,
+    master_seed: u64,
+    replicate: impl Fn(usize, u64) -> Result<(f64, f64), SimulationError> + Sync + Send,
+) -> Result<Vec<(f64, f64)>, SimulationError> {
+    if num_simulations == 0 {
+        return Err(SimulationError::EmptySamples("a comparison of no replications"));
+    }
+    #[cfg(feature = "parallel")]
+    let replications = (0..num_simulations).into_par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let replications = 0..num_simulations;
+    replications
+        .map(|replication| replicate(replication, replication_seed(master_seed, replication)))
+        .collect()
+}
+
+/// Paired test of the profits of two arms on the same paths, `first` and `second` naming them
+pub(crate) fn paired_comparison(first: String, second: String, profits: &[(f64, f64)]) -> PairedComparison {
+    let num_simulations = profits.len();
+    let mut first_profits = StreamingStats::new();
+    let mut second_profits = StreamingStats::new();
+    let mut differences = StreamingStats::new();
+    for &(profit_a, profit_b) in profits {
+        first_profits.push(profit_a);
+        second_profits.push(profit_b);
+        differences.push(profit_a - profit_b);
+    }
+
+    let mean_difference = differences.mean();
+    let std_error = standard_error(differences.std_dev(), num_simulations);
+    let half_width = ci_half_width(differences.std_dev(), num_simulations, COMPARISON_CONFIDENCE_LEVEL);
+    let p_value = if std_error > 0.0 {
+        2.0 * (1.0 - standard_normal_cdf((mean_difference / std_error).abs()))
+    } else if mean_difference == 0.0 {
+        1.0
+    } else {
+        0.0
+    };
+
+    PairedComparison {
+        first,
+        second,
+        num_simulations,
+        mean_profit_first: first_profits.mean(),
+        mean_profit_second: second_profits.mean(),
+        mean_difference,
+        std_dev_difference: differences.std_dev(),
+        confidence_level: COMPARISON_CONFIDENCE_LEVEL,
+        difference_ci_low: mean_difference - half_width,
+        difference_ci_high: mean_difference + half_width,
+        p_value,
+    }
+}
+
+/// Replications simulated per batch by the streaming runner, bounding the results held at once
+const STREAMING_BATCH: usize = 1_024;
+
+/// Share of worst outcomes covered by the value at risk and expected shortfall
+const TAIL_PROBABILITY: f64 = 0.05;
+
+/// Intervals in the ECDF summary kept for `MonteCarloStats::prob_below` (0.5% resolution)
+const ECDF_STEPS: usize = 200;
+
+/// Most bins the Freedman–Diaconis rule may pick, keeping the chart readable
+const MAX_HISTOGRAM_BINS: usize = 60;
+
+/// Replications added between precision checks of an adaptive run
+const CONVERGENCE_BATCH: usize = 100;
+
+/// Salt separating the reservoir's choices from the demand streams drawn from the same master seed
+const RESERVOIR_SEED_SALT: u64 = 0x5EED_5A3F_1E00_0001;
+
+/// Salt separating the sampled traces' choices from the profit samples'
+const TRACE_SEED_SALT: u64 = 0x5EED_7ACE_1E00_0002;
 
 /// Run Monte Carlo simulation for a supplier combination with multiple products
 /// Executes the simulation many times to gather statistics
+/// Each replication reseeds its thread's demand generator from the master seed, so the
+/// statistics do not depend on how replications are scheduled across threads
+/// Fails on a run of no replications, or at the first replication that cannot be simulated or
+/// whose profit is NaN
+#[allow(dead_code)]
 pub fn run_monte_carlo_simulation(
     params: &SimulationParams,
     pair: &SupplierPair,
     monthly_order: &MonthlyOrder,
-    num_simulations: usize,
-) -> MonteCarloStats {
-    let mut profits = Vec::with_capacity(num_simulations);
+    config: &MonteCarloConfig,
+) -> Result<MonteCarloStats, SimulationError> {
+    run_monte_carlo_simulation_with_progress(params, pair, monthly_order, config, &NoProgress)
+}
 
-    // Run simulation multiple times
-    for _ in 0..num_simulations {
-        let (_, total_profit) = run_monthly_simulation(params, pair, monthly_order);
-        profits.push(total_profit);
+/// Run Monte Carlo simulation, reporting each finished replication to `progress`
+pub fn run_monte_carlo_simulation_with_progress(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    monthly_order: &MonthlyOrder,
+    config: &MonteCarloConfig,
+    progress: &dyn ProgressSink,
+) -> Result<MonteCarloStats, SimulationError> {
+    if config.num_simulations == 0 {
+        return Err(SimulationError::EmptySamples("a Monte Carlo run of no replications"));
     }
+    let replications = run_replications(params, pair, monthly_order, config, 0..config.num_simulations, progress)?;
 
-    // Calculate statistics
-    profits.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    // Aggregate replications in order
+    let mut run = ExactRun::new(params, config);
+    for (replication, (monthly_results, total_profit, usage)) in replications.into_iter().enumerate() {
+        run.record(params, config, replication, &monthly_results, total_profit, &usage);
+    }
+    Ok(run.finish(params, pair, monthly_order, config))
+}
+
+/// Run Monte Carlo simulation until the mean profit is known precisely enough
+/// Replications run in batches; once at least `min_simulations` are done, the run stops as soon
+/// as the confidence interval half-width falls below `tolerance` times the running mean's
+/// magnitude, or at `max_simulations`. The replication count in `config` is ignored, and the
+/// count actually run and whether the tolerance was met are recorded in the statistics
+pub fn run_monte_carlo_until_converged(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    monthly_order: &MonthlyOrder,
+    config: &MonteCarloConfig,
+    tolerance: f64,
+    min_simulations: usize,
+    max_simulations: usize,
+) -> Result<MonteCarloStats, SimulationError> {
+    let mut run = IncrementalRun::new(params, config, max_simulations);
+    let mut converged = false;
+
+    while run.completed() < max_simulations {
+        let batch_end = cmp::max(min_simulations, run.completed() + CONVERGENCE_BATCH);
+        run.extend(params, pair, monthly_order, batch_end - run.completed())?;
+
+        let half_width = ci_half_width(run.moments.std_dev(), run.completed(), config.confidence_level);
+        if run.completed() >= min_simulations && half_width < tolerance * run.mean().abs() {
+            converged = true;
+            break;
+        }
+    }
+
+    Ok(MonteCarloStats { converged, ..run.finish(params, pair, monthly_order)? })
+}
+
+/// A run extended a batch of replications at a time, for callers deciding from the results so
+/// far how many more to run; replication i is the same whichever batch it falls in
+pub struct IncrementalRun {
+    /// Sized for the longest run, so retaining all samples never has to drop one
+    config: MonteCarloConfig,
+    run: ExactRun,
+    moments: StreamingStats,
+    completed: usize,
+}
+
+impl IncrementalRun {
+    /// An empty run of at most `max_simulations` replications; the count in `config` is ignored
+    pub fn new(params: &SimulationParams, config: &MonteCarloConfig, max_simulations: usize) -> Self {
+        let config = MonteCarloConfig { num_simulations: max_simulations, ..config.clone() };
+        IncrementalRun { run: ExactRun::new(params, &config), config, moments: StreamingStats::new(), completed: 0 }
+    }
+
+    /// Run up to `count` more replications, stopping at the run's maximum
+    pub fn extend(&mut self, params: &SimulationParams, pair: &SupplierPair, monthly_order: &MonthlyOrder, count: usize) -> Result<(), SimulationError> {
+        let batch_end = (self.completed + count).min(self.config.num_simulations);
+        let batch = run_replications(params, pair, monthly_order, &self.config, self.completed..batch_end, &NoProgress)?;
+        for (replication, (monthly_results, total_profit, usage)) in (self.completed..).zip(batch) {
+            self.moments.push(total_profit);
+            self.run.record(params, &self.config, replication, &monthly_results, total_profit, &usage);
+        }
+        self.completed = batch_end;
+        Ok(())
+    }
+
+    /// Replications run so far
+    pub fn completed(&self) -> usize {
+        self.completed
+    }
+
+    /// Mean profit of the replications so far
+    pub fn mean(&self) -> f64 {
+        self.moments.mean()
+    }
+
+    /// Standard error of the mean profit so far
+    pub fn std_error(&self) -> f64 {
+        standard_error(self.moments.std_dev(), self.completed)
+    }
+
+    /// Statistics over every replication run, failing when none was
+    pub fn finish(self, params: &SimulationParams, pair: &SupplierPair, monthly_order: &MonthlyOrder) -> Result<MonteCarloStats, SimulationError> {
+        if self.completed == 0 {
+            return Err(SimulationError::EmptySamples("a Monte Carlo run of no replications"));
+        }
+        let config = MonteCarloConfig { num_simulations: self.completed, ..self.config };
+        Ok(self.run.finish(params, pair, monthly_order, &config))
+    }
+}
+
+/// Run Monte Carlo simulation without keeping every replication's profit
+/// Replications run in batches of `STREAMING_BATCH` and are folded into Welford moments and
+/// P² quantile estimates, so memory stays bounded however many replications are requested.
+/// The mean, spread and extremes match `run_monte_carlo_simulation` up to rounding; percentiles
+/// are exact up to `EXACT_QUANTILE_THRESHOLD` replications and estimated beyond
+#[allow(dead_code)]
+pub fn run_monte_carlo_streaming(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    monthly_order: &MonthlyOrder,
+    config: &MonteCarloConfig,
+) -> Result<MonteCarloStats, SimulationError> {
+    if config.num_simulations == 0 {
+        return Err(SimulationError::EmptySamples("a Monte Carlo run of no replications"));
+    }
+    let mut profits = StreamingStats::new();
+    let mut losses = 0;
+    // Requested percentiles followed by the ECDF grid, tracked together
+    let probabilities: Vec<f64> = config.percentile_levels.iter()
+        .map(|level| level / 100.0)
+        .chain(ecdf_probabilities())
+        .collect();
+    let mut quantiles = StreamingQuantiles::new(&probabilities);
+    let mut activity = OptionActivity::new(params);
+    let mut breakdown = ProfitBreakdown::new(params);
+    let mut usage_totals = UsageTotals::default();
+    let mut samples = sample_reservoir(config);
+    let mut estimator = MeanEstimator::new(params, config);
+    let mut traces = KeptTraces::new(config);
+
+    for batch_start in (0..config.num_simulations).step_by(STREAMING_BATCH) {
+        let batch_end = cmp::min(batch_start + STREAMING_BATCH, config.num_simulations);
+        let batch = run_replications(params, pair, monthly_order, config, batch_start..batch_end, &NoProgress)?;
+        for (replication, (monthly_results, total_profit, usage)) in (batch_start..).zip(batch) {
+            profits.push(total_profit);
+            quantiles.push(total_profit);
+            losses += usize::from(total_profit < 0.0);
+            activity.record(params, &monthly_results);
+            breakdown.record(&monthly_results);
+            usage_totals.record(&usage);
+            estimator.record(&monthly_results, total_profit);
+            traces.record(config, replication, &monthly_results, total_profit);
+            if let Some(samples) = &mut samples {
+                samples.push(profit_sample(config, replication, &monthly_results, total_profit));
+            }
+        }
+    }
+
+    let mut estimates = quantiles.estimates();
+    let ecdf_summary = estimates.split_off(config.percentile_levels.len());
+    let profit = ProfitSummary {
+        mean: profits.mean(),
+        std_dev: profits.std_dev(),
+        min: profits.min(),
+        max: profits.max(),
+        percentiles: config.percentile_levels.iter().copied().zip(estimates).collect(),
+        var_5: interpolated_quantile(&ecdf_summary, TAIL_PROBABILITY),
+        cvar_5: tail_mean_from_grid(&ecdf_summary),
+        prob_loss: losses as f64 / config.num_simulations.max(1) as f64,
+        downside_deviation: None,
+        histogram: histogram_from_grid(&ecdf_summary, config),
+        ecdf_summary,
+        samples: retained_samples(samples),
+        adjusted_mean: profits.mean(),
+        adjusted_std_error: standard_error(profits.std_dev(), config.num_simulations),
+        sampled_traces: traces.sampled(),
+        worst_case_trace: traces.worst,
+        best_case_trace: traces.best,
+    };
+    let profit = match estimator.estimate() {
+        Some((adjusted_mean, adjusted_std_error)) => ProfitSummary { adjusted_mean, adjusted_std_error, ..profit },
+        None => profit,
+    };
+    Ok(build_stats(params, pair, monthly_order, config, profit, activity, breakdown, usage_totals))
+}
+
+/// Replications of an exact run, folded in as they complete
+struct ExactRun {
+    profits: Vec<f64>,
+    activity: OptionActivity,
+    breakdown: ProfitBreakdown,
+    usage: UsageTotals,
+    samples: Option<Reservoir<ProfitSample>>,
+    estimator: MeanEstimator,
+    traces: KeptTraces,
+}
+
+impl ExactRun {
+    fn new(params: &SimulationParams, config: &MonteCarloConfig) -> Self {
+        ExactRun {
+            profits: Vec::with_capacity(config.num_simulations),
+            activity: OptionActivity::new(params),
+            breakdown: ProfitBreakdown::new(params),
+            usage: UsageTotals::default(),
+            samples: sample_reservoir(config),
+            estimator: MeanEstimator::new(params, config),
+            traces: KeptTraces::new(config),
+        }
+    }
+
+    /// Add one replication's results
+    fn record(
+        &mut self,
+        params: &SimulationParams,
+        config: &MonteCarloConfig,
+        replication: usize,
+        monthly_results: &[MonthlyResult],
+        total_profit: f64,
+        usage: &[CapacityUsage],
+    ) {
+        self.profits.push(total_profit);
+        self.activity.record(params, monthly_results);
+        self.breakdown.record(monthly_results);
+        self.usage.record(usage);
+        self.estimator.record(monthly_results, total_profit);
+        self.traces.record(config, replication, monthly_results, total_profit);
+        if let Some(samples) = &mut self.samples {
+            samples.push(profit_sample(config, replication, monthly_results, total_profit));
+        }
+    }
+
+    /// Statistics over every replication recorded, `config` giving their number
+    fn finish(
+        self,
+        params: &SimulationParams,
+        pair: &SupplierPair,
+        monthly_order: &MonthlyOrder,
+        config: &MonteCarloConfig,
+    ) -> MonteCarloStats {
+        let mut traces = self.traces;
+        let profit = ProfitSummary {
+            samples: retained_samples(self.samples),
+            sampled_traces: traces.sampled(),
+            worst_case_trace: traces.worst,
+            best_case_trace: traces.best,
+            ..summarize_profits(self.profits, config)
+        };
+        let profit = match self.estimator.estimate() {
+            Some((adjusted_mean, adjusted_std_error)) => ProfitSummary { adjusted_mean, adjusted_std_error, ..profit },
+            None => profit,
+        };
+        build_stats(params, pair, monthly_order, config, profit, self.activity, self.breakdown, self.usage)
+    }
+}
+
+/// One simulated season: its months, total profit and use of each supplier's capacity
+type Replication = (Vec<MonthlyResult>, f64, Vec<CapacityUsage>);
+
+/// Demand stream of one replication: its seed, whether its draws are mirrored, and the
+/// (stratum, strata) its season's demand is conditioned on
+struct DemandStream {
+    seed: u64,
+    antithetic: bool,
+    stratum: Option<(usize, usize)>,
+}
+
+impl DemandStream {
+    /// Stream of a replication of a run
+    /// Antithetic runs put replications 2k and 2k + 1 on one seed and mirror the second;
+    /// stratified runs deal replications to the strata in turn
+    fn of_replication(config: &MonteCarloConfig, replication: usize) -> Self {
+        let seed = |index: usize| replication_seed(config.master_seed, index);
+        match config.variance_reduction {
+            VarianceReduction::None | VarianceReduction::ControlVariate => {
+                DemandStream { seed: seed(replication), antithetic: false, stratum: None }
+            }
+            VarianceReduction::Antithetic => {
+                DemandStream { seed: seed(replication / 2), antithetic: replication % 2 == 1, stratum: None }
+            }
+            VarianceReduction::Stratified { strata } => {
+                let strata = strata.max(1);
+                DemandStream { seed: seed(replication), antithetic: false, stratum: Some((replication % strata, strata)) }
+            }
+        }
+    }
+
+    /// Simulate one season on this stream, leaving the thread's demand generator unmirrored and unscripted
+    /// Fails on a plan the strict capacity policy refuses, which `simulation::check_capacity`
+    /// tells beforehand
+    fn simulate(&self, params: &SimulationParams, pair: &SupplierPair, monthly_order: &MonthlyOrder) -> Result<Replication, SimulationError> {
+        seed_demand_rng(self.seed);
+        set_antithetic_demand(self.antithetic);
+        if let Some((stratum, strata)) = self.stratum {
+            script_stratified_demand(&season_draw_weights(params), stratum, strata);
+        }
+        let result = run_monthly_simulation_with_usage(params, pair, monthly_order);
+        set_antithetic_demand(false);
+        clear_demand_script();
+        result
+    }
+}
+
+/// Standard deviation of each demand draw a season makes, in the order the simulation draws them
+fn season_draw_weights(params: &SimulationParams) -> Vec<f64> {
+    let per_month = params.time_step.periods_per_month(params.season_months);
+    let per_period: Vec<f64> = params.products.iter()
+        .filter_map(|product| params.get_demand_params(product.id))
+        .map(|demand| period_demand(demand, per_month).actual_std_dev_demand)
+        .collect();
+    per_period.repeat(params.periods())
+}
+
+/// A replication's profit, or the error naming the pair and replication when it is NaN
+fn checked_profit(pair: &SupplierPair, replication: usize, profit: f64) -> Result<f64, SimulationError> {
+    if profit.is_nan() {
+        return Err(SimulationError::NanProfit { pair: pair.name(), replication });
+    }
+    Ok(profit)
+}
+
+/// Simulate the given replications of a run, returned in replication order
+/// Stops at the first replication that fails or whose profit is NaN
+fn run_replications(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    monthly_order: &MonthlyOrder,
+    config: &MonteCarloConfig,
+    replications: Range<usize>,
+    progress: &dyn ProgressSink,
+) -> Result<Vec<Replication>, SimulationError> {
+    let _batch = debug_span!("monte_carlo_batch", start = replications.start, end = replications.end).entered();
+    // Replications before this range count as done, so batched runs report cumulative progress
+    let finished = AtomicUsize::new(replications.start);
+    let replicate = |replication: usize| {
+        let (monthly_results, total_profit, usage) = DemandStream::of_replication(config, replication).simulate(params, pair, monthly_order)?;
+        progress.on_replication(finished.fetch_add(1, Ordering::Relaxed) + 1, config.num_simulations);
+        Ok((monthly_results, checked_profit(pair, replication, total_profit)?, usage))
+    };
+    #[cfg(feature = "parallel")]
+    if config.parallel {
+        return replications.into_par_iter().map(replicate).collect();
+    }
+    replications.map(replicate).collect()
+}
+
+/// Summary of the profit distribution across replications
+struct ProfitSummary {
+    mean: f64,
+    std_dev: f64,
+    min: f64,
+    max: f64,
+    percentiles: Vec<(f64, f64)>, // (level in percent, profit)
+    var_5: f64,
+    cvar_5: f64,
+    prob_loss: f64,
+    downside_deviation: Option<f64>,
+    ecdf_summary: Vec<f64>,       // Profit at each of `ecdf_probabilities`
+    histogram: Vec<(f64, f64, usize)>,
+    samples: Vec<ProfitSample>,
+    adjusted_mean: f64,
+    adjusted_std_error: f64,
+    worst_case_trace: Option<ReplicationTrace>,
+    best_case_trace: Option<ReplicationTrace>,
+    sampled_traces: Vec<ReplicationTrace>,
+}
+
+/// Summarize every replication's profit exactly
+fn summarize_profits(mut profits: Vec<f64>, config: &MonteCarloConfig) -> ProfitSummary {
+    profits.sort_by(f64::total_cmp);
 
     let mean_profit = profits.iter().sum::<f64>() / profits.len() as f64;
     let variance = profits
@@ -29,16 +603,702 @@ pub fn run_monte_carlo_simulation(
         .map(|p| (p - mean_profit).powi(2))
         .sum::<f64>()
         / profits.len() as f64;
-    let std_dev_profit = variance.sqrt();
 
-    let min_profit = profits.first().copied().unwrap_or(0.0);
-    let max_profit = profits.last().copied().unwrap_or(0.0);
+    let downside_variance = profits
+        .iter()
+        .map(|p| (p - mean_profit).min(0.0).powi(2))
+        .sum::<f64>()
+        / profits.len() as f64;
+
+    // Expected shortfall averages the worst replications making up the tail
+    let tail_count = ((TAIL_PROBABILITY * profits.len() as f64).ceil() as usize).max(1).min(profits.len());
+    let cvar_5 = if profits.is_empty() {
+        0.0
+    } else {
+        profits[..tail_count].iter().sum::<f64>() / tail_count as f64
+    };
+
+    ProfitSummary {
+        mean: mean_profit,
+        std_dev: variance.sqrt(),
+        min: profits.first().copied().unwrap_or(0.0),
+        max: profits.last().copied().unwrap_or(0.0),
+        percentiles: config.percentile_levels.iter()
+            .map(|&level| (level, interpolated_quantile(&profits, level / 100.0)))
+            .collect(),
+        var_5: interpolated_quantile(&profits, TAIL_PROBABILITY),
+        cvar_5,
+        prob_loss: profits.iter().filter(|&&profit| profit < 0.0).count() as f64 / profits.len().max(1) as f64,
+        downside_deviation: Some(downside_variance.sqrt()),
+        ecdf_summary: ecdf_probabilities().map(|p| interpolated_quantile(&profits, p)).collect(),
+        histogram: exact_histogram(&profits, config),
+        samples: Vec::new(),
+        adjusted_mean: mean_profit,
+        adjusted_std_error: standard_error(variance.sqrt(), profits.len()),
+        worst_case_trace: None,
+        best_case_trace: None,
+        sampled_traces: Vec::new(),
+    }
+}
+
+/// Evenly spaced probabilities at which the profit distribution is summarized
+fn ecdf_probabilities() -> impl Iterator<Item = f64> {
+    (0..=ECDF_STEPS).map(|step| step as f64 / ECDF_STEPS as f64)
+}
+
+/// Mean profit over the worst `TAIL_PROBABILITY` of the distribution, integrating the
+/// summarized quantile function with the trapezoidal rule
+fn tail_mean_from_grid(ecdf_summary: &[f64]) -> f64 {
+    let tail_steps = (TAIL_PROBABILITY * ECDF_STEPS as f64).round() as usize;
+    let tail = &ecdf_summary[..=tail_steps];
+    let area: f64 = tail.windows(2).map(|pair| (pair[0] + pair[1]) / 2.0).sum();
+    area / tail_steps as f64
+}
+
+/// Reservoir for the replications kept under the configured retention, if any are kept
+/// Keeping all of them is a reservoir large enough never to overflow
+fn sample_reservoir(config: &MonteCarloConfig) -> Option<Reservoir<ProfitSample>> {
+    let capacity = match config.sample_retention {
+        SampleRetention::None => return None,
+        SampleRetention::All => config.num_simulations,
+        SampleRetention::Reservoir(capacity) => capacity,
+    };
+    Some(Reservoir::new(capacity, config.master_seed ^ RESERVOIR_SEED_SALT))
+}
+
+/// Profit and activity of one replication with the demand stream that reproduces it
+fn profit_sample(config: &MonteCarloConfig, replication: usize, monthly_results: &[MonthlyResult], profit: f64) -> ProfitSample {
+    let DemandStream { seed, antithetic, stratum } = DemandStream::of_replication(config, replication);
+    let exercises = monthly_results.iter().filter(|month| !month.exercised_changes.is_empty()).count();
+    let (sold, demand) = monthly_results.iter()
+        .flat_map(|month| &month.product_results)
+        .fold((0u64, 0u64), |(sold, demand), result| (sold + result.units_sold as u64, demand + result.demand as u64));
+    let fill_rate = if demand > 0 { sold as f64 / demand as f64 } else { 1.0 };
+    ProfitSample { replication, seed, antithetic, stratum, profit, exercises, fill_rate }
+}
+
+/// Full results of the lowest- and highest-profit replications seen so far, and of a uniform
+/// sample of `trace_samples` replications, so a run keeps a bounded number of traces however
+/// many replications it has
+struct KeptTraces {
+    worst: Option<ReplicationTrace>,
+    best: Option<ReplicationTrace>,
+    sampled: Option<Reservoir<ReplicationTrace>>,
+}
+
+impl KeptTraces {
+    fn new(config: &MonteCarloConfig) -> Self {
+        let sampled = (config.trace_samples > 0)
+            .then(|| Reservoir::new(config.trace_samples, config.master_seed ^ TRACE_SEED_SALT));
+        KeptTraces { worst: None, best: None, sampled }
+    }
+
+    /// Consider one replication, in replication order so ties keep the earliest
+    fn record(&mut self, config: &MonteCarloConfig, replication: usize, monthly_results: &[MonthlyResult], total_profit: f64) {
+        let trace = || ReplicationTrace {
+            sample: profit_sample(config, replication, monthly_results, total_profit),
+            monthly_results: monthly_results.to_vec(),
+        };
+        if self.worst.as_ref().is_none_or(|worst| total_profit < worst.sample.profit) {
+            self.worst = Some(trace());
+        }
+        if self.best.as_ref().is_none_or(|best| total_profit > best.sample.profit) {
+            self.best = Some(trace());
+        }
+        if let Some(sampled) = &mut self.sampled {
+            sampled.push_with(trace);
+        }
+    }
+
+    /// Sampled traces in replication order
+    fn sampled(&mut self) -> Vec<ReplicationTrace> {
+        let mut traces = self.sampled.take().map_or_else(Vec::new, Reservoir::into_items);
+        traces.sort_by_key(|trace| trace.sample.replication);
+        traces
+    }
+}
 
-    // Calculate percentiles
-    let percentile = |p: f64| {
-        let index = ((p / 100.0) * (profits.len() as f64 - 1.0)).round() as usize;
-        profits[index.min(profits.len() - 1)]
+/// The lowest-profit month of a replication, blamed on whichever of lost sales, holding or
+/// fees cost the most that month
+fn worst_month(params: &SimulationParams, trace: &ReplicationTrace) -> Option<WorstMonth> {
+    let month = trace.monthly_results.iter()
+        .min_by(|a, b| a.monthly_profit.total_cmp(&b.monthly_profit))?;
+    let stockout: f64 = month.product_results.iter()
+        .map(|result| {
+            let price = params.get_product(result.product_id).map_or(0.0, |product| product.selling_price);
+            result.demand.saturating_sub(result.units_sold) as f64 * price
+        })
+        .sum();
+    let holding: f64 = month.product_results.iter().map(|result| result.holding_cost).sum();
+    let fees = month.order_change_cost + month.flex_cost + month.setup_cost + month.reservation_cost + month.expansion_cost;
+    let (driver, driver_cost) = [(LossDriver::Stockout, stockout), (LossDriver::Holding, holding), (LossDriver::Fees, fees)]
+        .into_iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    Some(WorstMonth { month: month.month.clone(), profit: month.monthly_profit, driver, driver_cost })
+}
+
+/// Kept samples in replication order
+fn retained_samples(reservoir: Option<Reservoir<ProfitSample>>) -> Vec<ProfitSample> {
+    let mut samples = reservoir.map_or_else(Vec::new, Reservoir::into_items);
+    samples.sort_by_key(|sample| sample.replication);
+    samples
+}
+
+/// Number of histogram bins between `min` and `max`: the configured count, or by default the
+/// Freedman–Diaconis rule with bin width 2·IQR/∛n
+fn histogram_bin_count(config: &MonteCarloConfig, min: f64, max: f64, iqr: f64) -> usize {
+    if let Some(bins) = config.histogram_bins {
+        return bins.max(1);
+    }
+    let width = 2.0 * iqr / (config.num_simulations as f64).cbrt();
+    if max <= min || width <= 0.0 {
+        return 1;
+    }
+    (((max - min) / width).ceil() as usize).clamp(1, MAX_HISTOGRAM_BINS)
+}
+
+/// Edges of `bins` equal-width bins spanning [min, max]
+fn histogram_edges(min: f64, max: f64, bins: usize) -> Vec<f64> {
+    (0..=bins)
+        .map(|i| if i == bins { max } else { min + (max - min) * i as f64 / bins as f64 })
+        .collect()
+}
+
+/// Histogram of ascending profits; each bin counts profits from its low edge up to its
+/// high edge, with the maximum in the last bin
+fn exact_histogram(sorted: &[f64], config: &MonteCarloConfig) -> Vec<(f64, f64, usize)> {
+    let (Some(&min), Some(&max)) = (sorted.first(), sorted.last()) else {
+        return Vec::new();
     };
+    let iqr = interpolated_quantile(sorted, 0.75) - interpolated_quantile(sorted, 0.25);
+    let bins = histogram_bin_count(config, min, max, iqr);
+    let width = (max - min) / bins as f64;
+
+    let mut counts = vec![0; bins];
+    for &profit in sorted {
+        let bin = if width > 0.0 { ((profit - min) / width) as usize } else { 0 };
+        counts[bin.min(bins - 1)] += 1;
+    }
+    histogram_edges(min, max, bins).windows(2).zip(counts).map(|(edges, count)| (edges[0], edges[1], count)).collect()
+}
+
+/// Histogram estimated from the ECDF summary when the profits were not kept
+/// Replications are shared out by the probability mass the summary puts in each bin, rounding
+/// by largest remainder so the counts still sum to the number of replications
+fn histogram_from_grid(ecdf_summary: &[f64], config: &MonteCarloConfig) -> Vec<(f64, f64, usize)> {
+    let (Some(&min), Some(&max)) = (ecdf_summary.first(), ecdf_summary.last()) else {
+        return Vec::new();
+    };
+    if config.num_simulations == 0 {
+        return Vec::new();
+    }
+    let iqr = interpolated_quantile(ecdf_summary, 0.75) - interpolated_quantile(ecdf_summary, 0.25);
+    let bins = histogram_bin_count(config, min, max, iqr);
+    let n = config.num_simulations;
+    if bins == 1 {
+        // Includes runs where every profit was the same and the summary has no spread
+        return vec![(min, max, n)];
+    }
+    let edges = histogram_edges(min, max, bins);
+
+    let shares: Vec<f64> = edges.windows(2)
+        .map(|edges| (grid_cdf(ecdf_summary, edges[1]) - grid_cdf(ecdf_summary, edges[0])) * n as f64)
+        .collect();
+    let mut counts: Vec<usize> = shares.iter().map(|share| share.floor() as usize).collect();
+    let mut by_remainder: Vec<usize> = (0..bins).collect();
+    by_remainder.sort_by(|&a, &b| (shares[b] - shares[b].floor()).total_cmp(&(shares[a] - shares[a].floor())));
+    let shortfall = n.saturating_sub(counts.iter().sum());
+    for &bin in by_remainder.iter().cycle().take(shortfall) {
+        counts[bin] += 1;
+    }
+    edges.windows(2).zip(counts).map(|(edges, count)| (edges[0], edges[1], count)).collect()
+}
+
+/// Order change activity accumulated across replications
+struct OptionActivity {
+    product_exercise_counts: Vec<ProductExerciseCount>,
+    exercise_counts: Vec<usize>,
+    total_exercises: usize,
+    // Per month: replications whose first order change was exercised then
+    first_exercise_counts: Vec<usize>,
+    total_fees_paid: f64,
+    // Per expansion offer: replications that took it
+    expansions_accepted: Vec<usize>,
+    // Per month: (month name, sum of option values, evaluations, exercises)
+    monthly_option_totals: Vec<(String, f64, usize, usize)>,
+}
+
+impl OptionActivity {
+    fn new(params: &SimulationParams) -> Self {
+        OptionActivity {
+            product_exercise_counts: params.products.iter()
+                .map(|product| ProductExerciseCount {
+                    product_id: product.id,
+                    product_name: product.name.clone(),
+                    increases: 0,
+                    decreases: 0,
+                })
+                .collect(),
+            exercise_counts: vec![0; params.order_change_fees.len() + 1],
+            total_exercises: 0,
+            first_exercise_counts: Vec::new(),
+            total_fees_paid: 0.0,
+            expansions_accepted: vec![0; params.expansion_offers.len()],
+            monthly_option_totals: Vec::new(),
+        }
+    }
+
+    /// Add one replication's order changes, fees and option values
+    fn record(&mut self, params: &SimulationParams, monthly_results: &[MonthlyResult]) {
+        // Count order changes and the fees they cost
+        let exercises = monthly_results.iter().filter(|m| !m.exercised_changes.is_empty()).count();
+        self.exercise_counts[exercises.min(params.order_change_fees.len())] += 1;
+        self.total_exercises += exercises;
+        if self.first_exercise_counts.len() < monthly_results.len() {
+            self.first_exercise_counts.resize(monthly_results.len(), 0);
+        }
+        if let Some(first) = monthly_results.iter().position(|m| !m.exercised_changes.is_empty()) {
+            self.first_exercise_counts[first] += 1;
+        }
+        self.total_fees_paid += monthly_results.iter().map(|m| m.order_change_cost).sum::<f64>();
+        for index in monthly_results.iter().flat_map(|m| &m.expansions_accepted) {
+            self.expansions_accepted[*index] += 1;
+        }
+
+        // Accumulate option values and exercises by month
+        for (month_idx, month) in monthly_results.iter().enumerate() {
+            if self.monthly_option_totals.len() <= month_idx {
+                self.monthly_option_totals.push((month.month.clone(), 0.0, 0, 0));
+            }
+            let totals = &mut self.monthly_option_totals[month_idx];
+            if let Some(value) = month.option_value {
+                totals.1 += value;
+                totals.2 += 1;
+            }
+            if month.option_exercised {
+                totals.3 += 1;
+            }
+        }
+
+        // Count option exercises per product
+        for (product_id, direction) in monthly_results.iter().flat_map(|m| &m.exercised_changes) {
+            if let Some(count) = self.product_exercise_counts.iter_mut().find(|c| c.product_id == *product_id) {
+                match direction {
+                    ExerciseDirection::Increase => count.increases += 1,
+                    ExerciseDirection::Decrease => count.decreases += 1,
+                }
+            }
+        }
+    }
+}
+
+/// Capacity usage of each supplier summed across replications, in the ledger's order
+#[derive(Default)]
+struct UsageTotals {
+    // Per supplier: (supplier_id, delivered, nominal capacity, months at capacity, shortfall)
+    suppliers: Vec<(SupplierId, f64, f64, f64, f64)>,
+}
+
+impl UsageTotals {
+    /// Add one replication's usage
+    fn record(&mut self, usage: &[CapacityUsage]) {
+        if self.suppliers.is_empty() {
+            self.suppliers = usage.iter().map(|supplier| (supplier.supplier_id, 0.0, 0.0, 0.0, 0.0)).collect();
+        }
+        for (totals, supplier) in self.suppliers.iter_mut().zip(usage) {
+            totals.1 += supplier.delivered as f64;
+            totals.2 += supplier.nominal_capacity as f64;
+            totals.3 += supplier.months_at_capacity as f64;
+            totals.4 += supplier.shortfall as f64;
+        }
+    }
+
+    /// Mean usage per replication of each of the pair's suppliers
+    fn finish(self, num_simulations: usize, pair: &SupplierPair) -> Vec<CapacityUsageStats> {
+        let n = num_simulations.max(1) as f64;
+        self.suppliers.into_iter()
+            .map(|(supplier_id, delivered, nominal, months_at_capacity, shortfall)| CapacityUsageStats {
+                supplier_id,
+                supplier_name: pair.suppliers().find(|supplier| supplier.id == supplier_id).map_or_else(String::new, |supplier| supplier.name.clone()),
+                mean_delivered: delivered / n,
+                mean_nominal_capacity: nominal / n,
+                mean_months_at_capacity: months_at_capacity / n,
+                mean_shortfall: shortfall / n,
+            })
+            .collect()
+    }
+}
+
+/// Revenue and cost totals by month and by product accumulated across replications,
+/// so the decomposition needs no per-replication storage
+struct ProfitBreakdown {
+    months: Vec<MonthStats>,     // Sums, divided by the replication count when finished
+    products: Vec<ProductStats>, // Likewise
+    deliveries: Vec<(f64, f64, f64)>, // Base, surge and backup units delivered by month, summed likewise
+    setup_cost: f64,             // Summed over replications
+    // Each product's forecast demand a period, if it has one, with its absolute percentage
+    // errors summed over the periods that saw demand, and their number
+    forecast_errors: Vec<(Option<f64>, f64, usize)>,
+}
+
+impl ProfitBreakdown {
+    fn new(params: &SimulationParams) -> Self {
+        ProfitBreakdown {
+            months: Vec::new(),
+            products: params.products.iter()
+                .map(|product| ProductStats {
+                    product_id: product.id,
+                    product_name: product.name.clone(),
+                    mean_demand: 0.0,
+                    mean_units_sold: 0.0,
+                    mean_revenue: 0.0,
+                    mean_production_cost: 0.0,
+                    mean_holding_cost: 0.0,
+                    mean_liquidation_revenue: 0.0,
+                    mean_financing_cost: 0.0,
+                    mean_goodwill_penalty: 0.0,
+                    mean_contribution: 0.0,
+                    mean_closing_inventory: 0.0,
+                })
+                .collect(),
+            deliveries: Vec::new(),
+            setup_cost: 0.0,
+            forecast_errors: params.products.iter()
+                .map(|product| {
+                    let per_month = params.time_step.periods_per_month(params.season_months);
+                    let forecast = params.get_demand_params(product.id).map(|demand| period_demand(demand, per_month).mean_demand);
+                    (forecast, 0.0, 0)
+                })
+                .collect(),
+        }
+    }
+
+    /// Add one replication's monthly and product results
+    fn record(&mut self, monthly_results: &[MonthlyResult]) {
+        for (month_idx, month) in monthly_results.iter().enumerate() {
+            if self.months.len() <= month_idx {
+                self.months.push(MonthStats {
+                    month: month.month.clone(),
+                    mean_revenue: 0.0,
+                    mean_production_cost: 0.0,
+                    mean_holding_cost: 0.0,
+                    mean_liquidation_revenue: 0.0,
+                    mean_financing_cost: 0.0,
+                    mean_goodwill_penalty: 0.0,
+                    mean_other_costs: 0.0,
+                    mean_profit: 0.0,
+                    mean_base_utilization: 0.0,
+                    mean_surge_utilization: 0.0,
+                    mean_backup_utilization: 0.0,
+                    reserved_units: month.reserved_units,
+                    mean_reserved_units_used: 0.0,
+                });
+                self.deliveries.push((0.0, 0.0, 0.0));
+            }
+            let totals = &mut self.months[month_idx];
+            for result in &month.product_results {
+                self.deliveries[month_idx].0 += result.base_incoming as f64;
+                self.deliveries[month_idx].1 += result.surge_incoming as f64;
+                self.deliveries[month_idx].2 += result.backup_incoming as f64;
+                totals.mean_revenue += result.revenue;
+                totals.mean_production_cost += result.production_cost;
+                totals.mean_holding_cost += result.holding_cost;
+                totals.mean_liquidation_revenue += result.liquidation_revenue;
+                totals.mean_financing_cost += result.financing_cost;
+                totals.mean_goodwill_penalty += result.goodwill_penalty;
+
+                if let Some(index) = self.products.iter().position(|p| p.product_id == result.product_id) {
+                    let product = &mut self.products[index];
+                    product.mean_demand += result.demand as f64;
+                    product.mean_units_sold += result.units_sold as f64;
+                    product.mean_revenue += result.revenue;
+                    product.mean_production_cost += result.production_cost;
+                    product.mean_holding_cost += result.holding_cost;
+                    product.mean_liquidation_revenue += result.liquidation_revenue;
+                    product.mean_financing_cost += result.financing_cost;
+                    product.mean_goodwill_penalty += result.goodwill_penalty;
+                    if month_idx + 1 == monthly_results.len() {
+                        product.mean_closing_inventory += result.inventory_end as f64;
+                    }
+                    if let (Some(forecast), errors, periods) = &mut self.forecast_errors[index] {
+                        if result.demand > 0 {
+                            *errors += (*forecast - result.demand as f64).abs() / result.demand as f64;
+                            *periods += 1;
+                        }
+                    }
+                }
+            }
+            totals.mean_other_costs += month.order_change_cost + month.setup_cost + month.flex_cost + month.reservation_cost + month.expansion_cost;
+            totals.mean_reserved_units_used += month.reserved_units_used as f64;
+            totals.mean_profit += month.monthly_profit;
+            self.setup_cost += month.setup_cost;
+        }
+    }
+
+    /// Turn the totals into means over `num_simulations` replications, with deliveries as
+    /// utilization of the pair's capacity, a month of it spread over `per_month` periods, and
+    /// measure each forecast product's demand against its forecast by the month
+    fn finish(mut self, num_simulations: usize, pair: &SupplierPair, per_month: f64) -> (Vec<MonthStats>, Vec<ProductStats>, Vec<ForecastAccuracy>) {
+        let n = num_simulations.max(1) as f64;
+        let backup_capacity = pair.backup_supplier.as_ref().map_or(0, |backup| backup.fixed_capacity);
+        for (month, (base, surge, backup)) in self.months.iter_mut().zip(self.deliveries) {
+            month.mean_base_utilization = utilization(base * per_month / n, pair.base_capacity());
+            month.mean_surge_utilization = utilization(surge * per_month / n, pair.surge_supplier.fixed_capacity);
+            month.mean_backup_utilization = utilization(backup * per_month / n, backup_capacity);
+            month.mean_revenue /= n;
+            month.mean_production_cost /= n;
+            month.mean_holding_cost /= n;
+            month.mean_liquidation_revenue /= n;
+            month.mean_financing_cost /= n;
+            month.mean_goodwill_penalty /= n;
+            month.mean_other_costs /= n;
+            month.mean_profit /= n;
+            month.mean_reserved_units_used /= n;
+        }
+        for product in &mut self.products {
+            product.mean_demand /= n;
+            product.mean_units_sold /= n;
+            product.mean_revenue /= n;
+            product.mean_production_cost /= n;
+            product.mean_holding_cost /= n;
+            product.mean_liquidation_revenue /= n;
+            product.mean_financing_cost /= n;
+            product.mean_goodwill_penalty /= n;
+            product.mean_closing_inventory /= n;
+            product.mean_contribution = product.mean_revenue + product.mean_liquidation_revenue
+                - product.mean_production_cost - product.mean_holding_cost - product.mean_financing_cost
+                - product.mean_goodwill_penalty;
+        }
+        let months = self.months.len().max(1) as f64 / per_month;
+        let forecast_accuracy = self.products.iter().zip(self.forecast_errors)
+            .filter_map(|(product, (forecast, errors, periods))| {
+                let forecast_mean_demand = forecast? * per_month;
+                let realized_mean_demand = product.mean_demand / months;
+                Some(ForecastAccuracy {
+                    product_id: product.product_id,
+                    product_name: product.product_name.clone(),
+                    forecast_mean_demand,
+                    realized_mean_demand,
+                    bias: realized_mean_demand - forecast_mean_demand,
+                    mape: if periods > 0 { errors / periods as f64 } else { 0.0 },
+                })
+            })
+            .collect();
+        (self.months, self.products, forecast_accuracy)
+    }
+}
+
+/// Half-width of the normal confidence interval on a mean of `n` samples whose population
+/// standard deviation is `std_dev`; a single sample gives no spread
+pub(crate) fn ci_half_width(std_dev: f64, n: usize, confidence_level: f64) -> f64 {
+    inverse_standard_normal_cdf((1.0 + confidence_level) / 2.0) * standard_error(std_dev, n)
+}
+
+/// Standard error of a mean of `n` samples whose population standard deviation is `std_dev`,
+/// using the sample standard deviation; a single sample gives no spread
+pub(crate) fn standard_error(std_dev: f64, n: usize) -> f64 {
+    if n <= 1 {
+        return 0.0;
+    }
+    let n = n as f64;
+    std_dev * (n / (n - 1.0)).sqrt() / n.sqrt()
+}
+
+/// Running estimate of the mean profit under the run's variance reduction
+struct MeanEstimator {
+    method: VarianceReduction,
+    profits: StreamingStats,
+    // Antithetic: average profit of each completed pair, and the first half of the pair in progress
+    pair_means: StreamingStats,
+    pending: Option<f64>,
+    // Control variate: season demand, its known expectation, and the running co-moment with profit
+    controls: StreamingStats,
+    expected_control: f64,
+    co_moment: f64,
+    // Stratified: profits of each stratum
+    strata: Vec<StreamingStats>,
+}
+
+impl MeanEstimator {
+    fn new(params: &SimulationParams, config: &MonteCarloConfig) -> Self {
+        let per_month = params.time_step.periods_per_month(params.season_months);
+        let expected_period_demand: f64 = params.demand_params.iter()
+            .map(|demand| expected_simulation_demand(&period_demand(demand, per_month), true))
+            .sum();
+        MeanEstimator {
+            method: config.variance_reduction,
+            profits: StreamingStats::new(),
+            pair_means: StreamingStats::new(),
+            pending: None,
+            controls: StreamingStats::new(),
+            expected_control: expected_period_demand * params.periods() as f64,
+            co_moment: 0.0,
+            strata: match config.variance_reduction {
+                VarianceReduction::Stratified { strata } => vec![StreamingStats::new(); strata.max(1)],
+                _ => Vec::new(),
+            },
+        }
+    }
+
+    /// Add one replication, in replication order so antithetic pairs arrive together and the
+    /// stratum of each follows from how many came before
+    fn record(&mut self, monthly_results: &[MonthlyResult], total_profit: f64) {
+        match self.method {
+            VarianceReduction::None => {}
+            VarianceReduction::Antithetic => match self.pending.take() {
+                Some(first) => self.pair_means.push((first + total_profit) / 2.0),
+                None => self.pending = Some(total_profit),
+            },
+            VarianceReduction::ControlVariate => {
+                let season_demand: f64 = monthly_results.iter()
+                    .flat_map(|month| &month.product_results)
+                    .map(|result| result.demand as f64)
+                    .sum();
+                // Welford's update of the co-moment, from the profit's deviation before and the
+                // demand's deviation after the means move
+                let profit_deviation = total_profit - self.profits.mean();
+                self.controls.push(season_demand);
+                self.co_moment += profit_deviation * (season_demand - self.controls.mean());
+            }
+            VarianceReduction::Stratified { .. } => {
+                let stratum = self.profits.count() % self.strata.len();
+                self.strata[stratum].push(total_profit);
+            }
+        }
+        self.profits.push(total_profit);
+    }
+
+    /// Adjusted mean profit and its standard error, or None without variance reduction
+    fn estimate(&self) -> Option<(f64, f64)> {
+        match self.method {
+            VarianceReduction::None => None,
+            VarianceReduction::Antithetic => {
+                // An unpaired last replication counts towards the mean but not the spread
+                let pairs = self.pair_means.count();
+                Some((self.profits.mean(), standard_error(self.pair_means.std_dev(), pairs)))
+            }
+            VarianceReduction::ControlVariate => {
+                let n = self.profits.count();
+                let control_variance = self.controls.variance();
+                let covariance = if n > 0 { self.co_moment / n as f64 } else { 0.0 };
+                let coefficient = if control_variance > 0.0 { covariance / control_variance } else { 0.0 };
+                let adjusted_mean = self.profits.mean() - coefficient * (self.controls.mean() - self.expected_control);
+                // The spread left after the regression on demand
+                let residual_variance = (self.profits.variance() - coefficient * covariance).max(0.0);
+                Some((adjusted_mean, standard_error(residual_variance.sqrt(), n)))
+            }
+            VarianceReduction::Stratified { .. } => {
+                // Strata are equally likely, so each stratum's mean gets equal weight
+                let sampled: Vec<&StreamingStats> = self.strata.iter().filter(|stratum| stratum.count() > 0).collect();
+                if sampled.is_empty() {
+                    return None;
+                }
+                let weight = 1.0 / sampled.len() as f64;
+                let mean = sampled.iter().map(|stratum| weight * stratum.mean()).sum();
+                let variance: f64 = sampled.iter()
+                    .map(|stratum| (weight * standard_error(stratum.std_dev(), stratum.count())).powi(2))
+                    .sum();
+                Some((mean, variance.sqrt()))
+            }
+        }
+    }
+}
+
+/// Profit and loss of each product in whole currency units, allocating `setup_cost` and
+/// `fees` by revenue share (evenly when nothing sold)
+/// Each column's rounding remainder goes to the product with the most revenue, so the rows add
+/// up exactly to the rounded totals
+fn product_pnl(products: &[ProductStats], setup_cost: f64, fees: f64) -> Vec<ProductPnl> {
+    let total_revenue: f64 = products.iter().map(|product| product.mean_revenue).sum();
+    let share = |product: &ProductStats| {
+        if total_revenue > 0.0 {
+            product.mean_revenue / total_revenue
+        } else {
+            1.0 / products.len() as f64
+        }
+    };
+    let largest = (0..products.len())
+        .max_by(|&a, &b| products[a].mean_revenue.total_cmp(&products[b].mean_revenue))
+        .unwrap_or(0);
+    let column = |value: &dyn Fn(&ProductStats) -> f64| -> Vec<f64> {
+        let mut rounded: Vec<f64> = products.iter().map(|product| value(product).round()).collect();
+        let total = products.iter().map(value).sum::<f64>().round();
+        let remainder = total - rounded.iter().sum::<f64>();
+        if let Some(row) = rounded.get_mut(largest) {
+            *row += remainder;
+        }
+        rounded
+    };
+
+    let revenue = column(&|product| product.mean_revenue);
+    let cost_of_goods = column(&|product| product.mean_production_cost);
+    let holding_cost = column(&|product| product.mean_holding_cost);
+    let liquidation_revenue = column(&|product| product.mean_liquidation_revenue);
+    let financing_cost = column(&|product| product.mean_financing_cost);
+    let goodwill_penalty = column(&|product| product.mean_goodwill_penalty);
+    let setup = column(&|product| setup_cost * share(product));
+    let product_fees = column(&|product| fees * share(product));
+
+    products.iter().enumerate()
+        .map(|(i, product)| ProductPnl {
+            product_id: product.product_id,
+            product_name: product.product_name.clone(),
+            revenue: revenue[i],
+            cost_of_goods: cost_of_goods[i],
+            holding_cost: holding_cost[i],
+            liquidation_revenue: liquidation_revenue[i],
+            financing_cost: financing_cost[i],
+            goodwill_penalty: goodwill_penalty[i],
+            setup_cost: setup[i],
+            fees: product_fees[i],
+            profit: revenue[i] + liquidation_revenue[i] - cost_of_goods[i] - holding_cost[i] - financing_cost[i]
+                - goodwill_penalty[i] - setup[i] - product_fees[i],
+        })
+        .collect()
+}
+
+/// Mean revenue per unit sold, landed cost per unit delivered, and the margin between them
+/// Setup costs are paid once a season whatever its volume, so they are spread over the mean
+/// units delivered rather than each season's own, which would weigh thin seasons heavily. Unit
+/// costs are landed, freight and duty included; flex and order change fees are left out, as
+/// they buy flexibility rather than goods
+fn unit_economics(products: &[ProductStats], mean_units_delivered: f64, mean_setup_cost: f64) -> (f64, f64, f64) {
+    let sum = |value: fn(&ProductStats) -> f64| products.iter().map(value).sum::<f64>();
+    let units_sold = sum(|product| product.mean_units_sold);
+    let revenue_per_unit = if units_sold > 0.0 { sum(|product| product.mean_revenue) / units_sold } else { 0.0 };
+    let landed_cost_per_unit = if mean_units_delivered > 0.0 {
+        (sum(|product| product.mean_production_cost) + mean_setup_cost) / mean_units_delivered
+    } else {
+        0.0
+    };
+    let landed_margin = if revenue_per_unit > 0.0 { 1.0 - landed_cost_per_unit / revenue_per_unit } else { 0.0 };
+    (revenue_per_unit, landed_cost_per_unit, landed_margin)
+}
+
+/// Assemble the statistics for a pair from its profit summary, option activity and capacity usage
+#[allow(clippy::too_many_arguments)]
+fn build_stats(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    monthly_order: &MonthlyOrder,
+    config: &MonteCarloConfig,
+    profit: ProfitSummary,
+    activity: OptionActivity,
+    breakdown: ProfitBreakdown,
+    usage: UsageTotals,
+) -> MonteCarloStats {
+    let num_simulations = config.num_simulations;
+    let mean_setup_cost = breakdown.setup_cost / num_simulations as f64;
+    let mean_units_delivered = breakdown.deliveries.iter().map(|(base, surge, backup)| base + surge + backup).sum::<f64>() / num_simulations as f64;
+    let (monthly_breakdown, product_breakdown, forecast_accuracy) = breakdown.finish(num_simulations, pair, params.time_step.periods_per_month(params.season_months));
+    let (revenue_per_unit, landed_cost_per_unit, landed_margin) = unit_economics(&product_breakdown, mean_units_delivered, mean_setup_cost);
+    let season_mean = |utilization: fn(&MonthStats) -> f64| {
+        monthly_breakdown.iter().map(utilization).sum::<f64>() / monthly_breakdown.len().max(1) as f64
+    };
+    let mean_base_utilization = season_mean(|month| month.mean_base_utilization);
+    let mean_surge_utilization = season_mean(|month| month.mean_surge_utilization);
+    let mean_backup_utilization = season_mean(|month| month.mean_backup_utilization);
+    let capacity_usage = usage.finish(num_simulations, pair);
 
     // Build product allocations
     let product_allocations: Vec<ProductAllocation> = params.products.iter()
@@ -54,24 +1314,915 @@ pub fn run_monte_carlo_simulation(
         })
         .collect();
 
+    let monthly_option_summary: Vec<MonthlyOptionSummary> = activity.monthly_option_totals.into_iter()
+        .map(|(month, value_sum, evaluations, exercises)| MonthlyOptionSummary {
+            month,
+            mean_option_value: if evaluations > 0 { value_sum / evaluations as f64 } else { 0.0 },
+            exercise_frequency: exercises as f64 / num_simulations as f64,
+        })
+        .collect();
+
+    let first_exercise_counts: Vec<(String, usize)> = monthly_option_summary.iter()
+        .map(|summary| summary.month.clone())
+        .zip(activity.first_exercise_counts)
+        .collect();
+    let exercised_runs = num_simulations - activity.exercise_counts[0];
+    let mean_fees_paid = activity.total_fees_paid / num_simulations as f64;
+    let expansion_acceptance = activity.expansions_accepted.iter().map(|&runs| runs as f64 / num_simulations as f64).collect();
+
+    let other_costs: f64 = monthly_breakdown.iter().map(|month| month.mean_other_costs).sum();
+    let product_pnl = product_pnl(&product_breakdown, mean_setup_cost, other_costs - mean_setup_cost);
+
     let total_capacity_used = monthly_order.total_base_quantity() + monthly_order.total_surge_quantity();
 
+    let ci_half_width = ci_half_width(profit.std_dev, num_simulations, config.confidence_level);
+    debug!(
+        base = %pair.base_supplier.name,
+        surge = %pair.surge_supplier.name,
+        num_simulations,
+        mean_profit = profit.mean,
+        std_dev = profit.std_dev,
+        "monte carlo run finished"
+    );
+
     MonteCarloStats {
         base_supplier: pair.base_supplier.name.clone(),
         base_supplier_lead_time: pair.base_supplier.lead_time_months,
         surge_supplier: pair.surge_supplier.name.clone(),
         surge_supplier_lead_time: pair.surge_supplier.lead_time_months,
+        single_source: pair.is_single_source(),
+        backup_supplier: pair.backup_supplier.as_ref().map(|backup| backup.name.clone()),
+        pooled_suppliers: pair.pooled_suppliers.iter().map(|supplier| supplier.name.clone()).collect(),
         product_allocations,
         total_capacity_used,
         num_simulations,
-        mean_profit,
-        std_dev_profit,
-        min_profit,
-        max_profit,
-        percentile_10: percentile(10.0),
-        percentile_25: percentile(25.0),
-        percentile_50: percentile(50.0),
-        percentile_75: percentile(75.0),
-        percentile_90: percentile(90.0),
+        mean_profit: profit.mean,
+        std_dev_profit: profit.std_dev,
+        min_profit: profit.min,
+        max_profit: profit.max,
+        percentiles: profit.percentiles,
+        var_5: profit.var_5,
+        cvar_5: profit.cvar_5,
+        prob_loss: profit.prob_loss,
+        downside_deviation: profit.downside_deviation,
+        ecdf_summary: profit.ecdf_summary,
+        histogram: profit.histogram,
+        confidence_level: config.confidence_level,
+        mean_ci_low: profit.mean - ci_half_width,
+        mean_ci_high: profit.mean + ci_half_width,
+        product_exercise_counts: activity.product_exercise_counts,
+        exercise_counts: activity.exercise_counts,
+        exercise_probability: exercised_runs as f64 / num_simulations as f64,
+        mean_exercises: activity.total_exercises as f64 / num_simulations as f64,
+        first_exercise_counts,
+        mean_fees_paid,
+        expansion_acceptance,
+        mean_setup_cost,
+        mean_operational_profit: profit.mean + mean_setup_cost + mean_fees_paid,
+        revenue_per_unit,
+        landed_cost_per_unit,
+        landed_margin,
+        monthly_option_summary,
+        monthly_breakdown,
+        product_breakdown,
+        product_pnl,
+        mean_base_utilization,
+        mean_surge_utilization,
+        mean_backup_utilization,
+        capacity_usage,
+        samples: profit.samples,
+        worst_month: profit.worst_case_trace.as_ref().and_then(|trace| worst_month(params, trace)),
+        worst_case_trace: profit.worst_case_trace,
+        forecast_accuracy,
+        cost_of_forecast_error: None,
+        best_case_trace: profit.best_case_trace,
+        sampled_traces: profit.sampled_traces,
+        converged: true,
+        mean_std_error: standard_error(profit.std_dev, num_simulations),
+        adjusted_mean_profit: profit.adjusted_mean,
+        adjusted_std_error: profit.adjusted_std_error,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::capacity::{create_order, is_fragile, planned_utilization};
+    use crate::models::{CapacityPolicy, ExpansionOffer, Product, ProductDemandParams, ProductId, ProductOrder, RankingMetric, RegionalDisruption, Supplier, TimeStep};
+    use crate::optimizer::{find_optimal_plan, find_optimal_production_quantities};
+    use crate::pairing::{generate_single_supplier_strategies, PairingRules};
+    use crate::progress::tests::RecordingProgress;
+    use crate::simulation::split_order_quantities;
+    use std::collections::HashMap;
+
+    /// Two products with different forecasts sourced from one base and one surge supplier
+    pub fn two_product_scenario() -> (SimulationParams, SupplierPair) {
+        let product = |id: u32, name: &str| Product {
+            id: ProductId(id),
+            name: name.to_string(),
+            selling_price: 100.0,
+            liquidation_price: 60.0,
+            monthly_holding_cost: 2.0,
+            goodwill_penalty: None,
+        };
+        let demand = |product_id: u32, mean_demand: f64| ProductDemandParams {
+            product_id: ProductId(product_id),
+            mean_demand,
+            std_dev_demand: 0.2 * mean_demand,
+            actual_mean_demand: mean_demand,
+            actual_std_dev_demand: 0.2 * mean_demand,
+        };
+        let params = SimulationParams {
+            products: vec![product(0, "Widget"), product(1, "Gadget")],
+            demand_params: vec![demand(0, 6_000.0), demand(1, 3_000.0)],
+            order_change_fees: vec![50_000.0; 2],
+            order_change_fee_per_product: 0.0,
+            cancellation_fee_per_unit: 5.0,
+            calibrate_to_observed_demand: true,
+            season_months: 8,
+            regional_disruptions: Vec::new(),
+            capacity_policy: CapacityPolicy::Clamp,
+            expansion_offers: Vec::new(),
+            opening_inventory: Vec::new(),
+            onboarded_suppliers: Vec::new(),
+            carry_over_stock: false,
+            time_step: TimeStep::Monthly,
+            cost_of_capital_annual: 0.0,
+        };
+        let supplier = |id: u32, fixed_capacity: u32, lead_time_months: usize, unit_cost: f64| Supplier {
+            id: SupplierId(id),
+            name: format!("Supplier {}", id),
+            fixed_capacity,
+            product_capacity: None,
+            capacity_groups: Vec::new(),
+            soft_capacity_tiers: Vec::new(),
+            lead_time_months,
+            ramp_up_months: Vec::new(),
+            unit_costs: HashMap::from([(ProductId(0), unit_cost), (ProductId(1), unit_cost)]),
+            setup_cost: 10_000.0,
+            reservation_fee: None,
+            monthly_flex: None,
+            disrupted_months: Vec::new(),
+            region: None,
+            risk_class: None,
+        };
+        let pair = SupplierPair {
+            base_supplier: supplier(0, 7_000, 3, 75.0),
+            surge_supplier: supplier(1, 5_000, 0, 80.0),
+            backup_supplier: None,
+            pooled_suppliers: Vec::new(),
+        };
+        (params, pair)
+    }
+
+    #[test]
+    fn test_landed_margin_on_a_deterministic_season() {
+        // Demand without spread lands on its mean every month and no order change is possible, so
+        // every season sells exactly what is delivered
+        let (mut params, pair) = two_product_scenario();
+        for dp in &mut params.demand_params {
+            dp.std_dev_demand = 0.0;
+            dp.actual_std_dev_demand = 0.0;
+        }
+        params.order_change_fees.clear();
+        let order = |widgets: u32, gadgets: u32| vec![
+            ProductOrder { product_id: ProductId(0), quantity: widgets },
+            ProductOrder { product_id: ProductId(1), quantity: gadgets },
+        ];
+        let monthly_order = MonthlyOrder::new(order(5_000, 2_000), order(1_000, 1_000)).unwrap();
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &MonteCarloConfig::new(4)).unwrap();
+
+        // 9,000 units a month for 8 months, all sold at 100
+        let units = 9_000.0 * 8.0;
+        assert!((stats.revenue_per_unit - 100.0).abs() < 1e-9, "{}", stats.revenue_per_unit);
+        // Each month 7,000 base units at 75 and 2,000 surge units at 80, and both setups once
+        let landed = ((7_000.0 * 75.0 + 2_000.0 * 80.0) * 8.0 + 2.0 * 10_000.0) / units;
+        assert!((stats.landed_cost_per_unit - landed).abs() < 1e-9, "{} vs {}", stats.landed_cost_per_unit, landed);
+        assert!((stats.landed_margin - (100.0 - landed) / 100.0).abs() < 1e-12);
+        assert!((stats.landed_margin - 0.236_111).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_failed_runs_return_the_error_that_stopped_them() {
+        let (mut params, pair) = two_product_scenario();
+        let order = |widgets: u32, gadgets: u32| vec![
+            ProductOrder { product_id: ProductId(0), quantity: widgets },
+            ProductOrder { product_id: ProductId(1), quantity: gadgets },
+        ];
+        let monthly_order = MonthlyOrder::new(order(5_000, 2_000), order(1_000, 1_000)).unwrap();
+        let config = MonteCarloConfig { master_seed: 8, ..MonteCarloConfig::new(10) };
+
+        // No replications leave nothing to take percentiles of
+        let none = MonteCarloConfig { num_simulations: 0, ..config.clone() };
+        assert!(matches!(run_monte_carlo_simulation(&params, &pair, &monthly_order, &none), Err(SimulationError::EmptySamples(_))));
+        assert!(matches!(run_monte_carlo_streaming(&params, &pair, &monthly_order, &none), Err(SimulationError::EmptySamples(_))));
+        assert!(matches!(compare(&params, &pair, &monthly_order, &pair, &monthly_order, 0, 8), Err(SimulationError::EmptySamples(_))));
+
+        // A price that is not a number is caught at the first replication, named with its pair
+        let mut priceless = params.clone();
+        priceless.products[1].selling_price = f64::NAN;
+        match run_monte_carlo_simulation(&priceless, &pair, &monthly_order, &config) {
+            Err(SimulationError::NanProfit { pair: name, replication: 0 }) => assert_eq!(name, pair.name()),
+            other => panic!("{:?}", other.map(|stats| stats.mean_profit)),
+        }
+
+        // A negative spread cannot be sampled
+        params.demand_params[0].actual_std_dev_demand = -1.0;
+        let error = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).map(|stats| stats.mean_profit).unwrap_err();
+        assert!(matches!(error, SimulationError::InvalidDistribution { product_id: ProductId(0), .. }), "{:?}", error);
+
+        // Under the strict policy a plan beyond the base supplier's 7,000 units is refused
+        params.demand_params[0].actual_std_dev_demand = 0.0;
+        params.capacity_policy = CapacityPolicy::Strict;
+        let over = MonthlyOrder::new(order(6_000, 2_000), monthly_order.surge_orders().to_vec()).unwrap();
+        let error = run_monte_carlo_simulation(&params, &pair, &over, &config).map(|stats| stats.mean_profit).unwrap_err();
+        assert!(matches!(error, SimulationError::Capacity(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn test_stats_report_the_simulated_allocations() {
+        let (params, pair) = two_product_scenario();
+        seed_demand_rng(3);
+        let optimal_quantities = find_optimal_production_quantities(&params, &pair).unwrap();
+        let monthly_order = split_order_quantities(&optimal_quantities, &pair, &params);
+        let config = MonteCarloConfig { master_seed: 3, ..MonteCarloConfig::new(20) };
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+
+        let allocations: Vec<(ProductId, &str, u32, u32)> = stats.product_allocations.iter()
+            .map(|a| (a.product_id, a.product_name.as_str(), a.base_quantity, a.surge_quantity))
+            .collect();
+        let simulated: Vec<(ProductId, &str, u32, u32)> = params.products.iter()
+            .map(|p| (p.id, p.name.as_str(), monthly_order.base_quantity_for(p.id), monthly_order.surge_quantity_for(p.id)))
+            .collect();
+        assert_eq!(allocations, simulated);
+        assert!(allocations.iter().all(|&(_, _, base, surge)| base + surge > 0));
+        assert_eq!(
+            stats.total_capacity_used,
+            monthly_order.total_base_quantity() + monthly_order.total_surge_quantity()
+        );
+    }
+
+    #[test]
+    fn test_parallel_and_serial_replications_agree() {
+        let (params, pair) = two_product_scenario();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let serial = MonteCarloConfig { master_seed: 11, parallel: false, ..MonteCarloConfig::new(40) };
+        let parallel = MonteCarloConfig { parallel: true, ..serial.clone() };
+
+        let serial_stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &serial).unwrap();
+        let parallel_stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &parallel).unwrap();
+        assert_eq!(serial_stats, parallel_stats);
+
+        // A different master seed draws different seasons
+        let reseeded = MonteCarloConfig { master_seed: 12, ..parallel };
+        let reseeded_stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &reseeded).unwrap();
+        assert_ne!(serial_stats.mean_profit, reseeded_stats.mean_profit);
+    }
+
+    /// Timing comparison of serial and parallel replications
+    /// Run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_parallel_replications() {
+        let (params, pair) = two_product_scenario();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        for parallel in [false, true] {
+            let config = MonteCarloConfig { master_seed: 0, parallel, ..MonteCarloConfig::new(500) };
+            let start = std::time::Instant::now();
+            run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+            println!("parallel: {:5} {:>8.1?} for 500 replications", parallel, start.elapsed());
+        }
+    }
+
+    #[test]
+    fn test_streaming_run_matches_exact_run() {
+        let (params, pair) = two_product_scenario();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let config = MonteCarloConfig { master_seed: 5, ..MonteCarloConfig::new(60) };
+
+        let exact = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+        let streaming = run_monte_carlo_streaming(&params, &pair, &monthly_order, &config).unwrap();
+        assert!((streaming.mean_profit - exact.mean_profit).abs() < 1e-6 * exact.mean_profit.abs());
+        assert!((streaming.std_dev_profit - exact.std_dev_profit).abs() < 1e-6 * exact.std_dev_profit);
+        assert!((streaming.mean_std_error - exact.mean_std_error).abs() < 1e-6 * exact.mean_std_error);
+
+        // The streaming shortfall integrates the ECDF summary rather than averaging the worst runs
+        assert!((streaming.cvar_5 - exact.cvar_5).abs() < 0.15 * exact.std_dev_profit);
+
+        // Short runs keep every profit, so the rest matches exactly
+        let without_moments = |stats: &MonteCarloStats| MonteCarloStats {
+            mean_profit: 0.0,
+            std_dev_profit: 0.0,
+            mean_std_error: 0.0,
+            adjusted_mean_profit: 0.0,
+            adjusted_std_error: 0.0,
+            mean_operational_profit: 0.0,
+            mean_ci_low: 0.0,
+            mean_ci_high: 0.0,
+            cvar_5: 0.0,
+            downside_deviation: None,
+            histogram: Vec::new(),
+            ..stats.clone()
+        };
+        assert_eq!(without_moments(&streaming), without_moments(&exact));
+
+        // The streaming histogram is spread from the ECDF summary but still accounts for every run
+        assert_eq!(streaming.histogram.len(), exact.histogram.len());
+        assert_eq!(streaming.histogram.iter().map(|bin| bin.2).sum::<usize>(), config.num_simulations);
+    }
+
+    #[test]
+    fn test_mean_ci_shrinks_with_the_square_root_of_replications() {
+        // Without change rights every replication is a plain season, which keeps 1,600 cheap
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+
+        let half_widths: Vec<f64> = [100, 400, 1_600]
+            .iter()
+            .map(|&num_simulations| {
+                let config = MonteCarloConfig { master_seed: 8, ..MonteCarloConfig::new(num_simulations) };
+                let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+                assert!(stats.mean_ci_low < stats.mean_profit && stats.mean_profit < stats.mean_ci_high);
+                stats.mean_ci_half_width()
+            })
+            .collect();
+        for ratio in half_widths.windows(2).map(|pair| pair[0] / pair[1]) {
+            assert!((ratio - 2.0).abs() < 0.3, "half-widths {:?}", half_widths);
+        }
+
+        // A 99% interval is wider than the default 95%
+        let config = MonteCarloConfig { master_seed: 8, confidence_level: 0.99, ..MonteCarloConfig::new(100) };
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+        assert!((stats.mean_ci_half_width() / half_widths[0] - 2.5758 / 1.9600).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_ci_overlap_flags_indistinguishable_pairs() {
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        let config = MonteCarloConfig { master_seed: 1, ..MonteCarloConfig::new(200) };
+        let twin_config = MonteCarloConfig { master_seed: 2, ..config.clone() };
+
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+        let twin = run_monte_carlo_simulation(&params, &pair, &monthly_order, &twin_config).unwrap();
+        assert!(stats.mean_ci_overlaps(&twin) && twin.mean_ci_overlaps(&stats));
+
+        // A plan a third of the size earns clearly less
+        let starved_order = split_order_quantities(&[(ProductId(0), 2_000), (ProductId(1), 1_000)], &pair, &params);
+        let starved = run_monte_carlo_simulation(&params, &pair, &starved_order, &config).unwrap();
+        assert!(!stats.mean_ci_overlaps(&starved));
+    }
+
+    #[test]
+    fn test_requested_percentiles_span_the_extremes() {
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let config = MonteCarloConfig {
+            master_seed: 4,
+            percentile_levels: vec![0.0, 1.0, 50.0, 99.0, 100.0],
+            ..MonteCarloConfig::new(200)
+        };
+
+        for stats in [
+            run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap(),
+            run_monte_carlo_streaming(&params, &pair, &monthly_order, &config).unwrap(),
+        ] {
+            let levels: Vec<f64> = stats.percentiles.iter().map(|(level, _)| *level).collect();
+            assert_eq!(levels, config.percentile_levels);
+            assert_eq!(stats.percentile(0.0), Some(stats.min_profit));
+            assert_eq!(stats.percentile(100.0), Some(stats.max_profit));
+            assert!(stats.percentiles.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+            assert_eq!(stats.p10(), None);
+        }
+    }
+
+    #[test]
+    fn test_histogram_covers_every_replication() {
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+
+        for histogram_bins in [None, Some(7)] {
+            let config = MonteCarloConfig { master_seed: 6, histogram_bins, ..MonteCarloConfig::new(300) };
+            for stats in [
+                run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap(),
+                run_monte_carlo_streaming(&params, &pair, &monthly_order, &config).unwrap(),
+            ] {
+                let histogram = &stats.histogram;
+                if let Some(bins) = histogram_bins {
+                    assert_eq!(histogram.len(), bins);
+                }
+                assert_eq!(histogram.first().unwrap().0, stats.min_profit);
+                assert_eq!(histogram.last().unwrap().1, stats.max_profit);
+                assert!(histogram.windows(2).all(|pair| pair[0].1 == pair[1].0));
+                assert_eq!(histogram.iter().map(|bin| bin.2).sum::<usize>(), 300);
+
+                #[cfg(feature = "serde")]
+                {
+                    let json = serde_json::to_value(&stats).unwrap();
+                    assert_eq!(json["histogram"].as_array().unwrap().len(), histogram.len());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_breakdowns_add_up_to_the_mean_profit() {
+        let (params, pair) = two_product_scenario();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let config = MonteCarloConfig { master_seed: 9, ..MonteCarloConfig::new(100) };
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+        let tolerance = 1e-9 * stats.mean_profit.abs();
+
+        assert_eq!(stats.monthly_breakdown.len(), params.season_months);
+        let monthly_profit: f64 = stats.monthly_breakdown.iter().map(|month| month.mean_profit).sum();
+        assert!((monthly_profit - stats.mean_profit).abs() < tolerance);
+
+        // Product contributions less the unattributed fees also recover the mean profit
+        let contribution: f64 = stats.product_breakdown.iter().map(|product| product.mean_contribution).sum();
+        let other_costs: f64 = stats.monthly_breakdown.iter().map(|month| month.mean_other_costs).sum();
+        assert!((contribution - other_costs - stats.mean_profit).abs() < tolerance);
+        assert!(stats.product_breakdown.iter().all(|product| product.mean_units_sold <= product.mean_demand));
+    }
+
+    #[test]
+    fn test_utilization_of_a_hand_built_plan() {
+        let (mut params, pair) = two_product_scenario();
+        // 5,600 of the base supplier's 7,000 and 1,500 of the surge supplier's 5,000 a month
+        let monthly_order = create_order(vec![(ProductId(0), 3_500), (ProductId(1), 2_100)], vec![(ProductId(0), 1_000), (ProductId(1), 500)]);
+        assert_eq!(planned_utilization(&monthly_order, &pair), (0.8, 0.3));
+
+        // Without order changes or flex every month delivers exactly the plan
+        params.order_change_fees.clear();
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &MonteCarloConfig::new(10)).unwrap();
+        for month in &stats.monthly_breakdown {
+            assert!((month.mean_base_utilization - 0.8).abs() < 1e-12, "{:?}", month);
+            assert!((month.mean_surge_utilization - 0.3).abs() < 1e-12, "{:?}", month);
+        }
+        assert!((stats.mean_base_utilization - 0.8).abs() < 1e-12);
+        assert!((stats.mean_surge_utilization - 0.3).abs() < 1e-12);
+
+        assert_eq!(utilization(1_000.0, 0), 0.0);
+        assert!(!is_fragile(0.95) && is_fragile(0.951));
+    }
+
+    #[test]
+    fn test_capacity_usage_of_a_scripted_season() {
+        use crate::capacity::CapacityUsage;
+        use crate::demand::script_mean_demand;
+        use crate::simulation::run_monthly_simulation_with_usage;
+
+        // 8,000 base units a month asked of a base supplier making 7,000, and 2,000 surge units of
+        // a surge supplier making 2,000 but down in June and September
+        let (mut params, mut pair) = two_product_scenario();
+        params.order_change_fees.clear();
+        pair.surge_supplier.fixed_capacity = 2_000;
+        pair.surge_supplier.disrupted_months = vec![1, 4];
+        let monthly_order = create_order(vec![(ProductId(0), 5_000), (ProductId(1), 3_000)], vec![(ProductId(0), 1_000), (ProductId(1), 1_000)]);
+
+        script_mean_demand(params.products.len() * params.season_months);
+        let (_, _, usage) = run_monthly_simulation_with_usage(&params, &pair, &monthly_order).unwrap();
+        clear_demand_script();
+        assert_eq!(usage, [
+            CapacityUsage { supplier_id: SupplierId(0), delivered: 56_000, nominal_capacity: 56_000, months_at_capacity: 8, shortfall: 8_000 },
+            CapacityUsage { supplier_id: SupplierId(1), delivered: 12_000, nominal_capacity: 16_000, months_at_capacity: 6, shortfall: 0 },
+        ]);
+
+        // Every replication uses its capacity alike, so the means are the season's
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &MonteCarloConfig::new(10)).unwrap();
+        let means: Vec<(&str, f64, f64, f64, f64)> = stats.capacity_usage.iter()
+            .map(|usage| (usage.supplier_name.as_str(), usage.mean_delivered, usage.mean_nominal_capacity, usage.mean_months_at_capacity, usage.mean_shortfall))
+            .collect();
+        assert_eq!(means, [("Supplier 0", 56_000.0, 56_000.0, 8.0, 8_000.0), ("Supplier 1", 12_000.0, 16_000.0, 6.0, 0.0)]);
+        assert_eq!(stats.capacity_usage[1].utilization(), 0.75);
+    }
+
+    #[test]
+    fn test_product_pnl_allocates_by_revenue_and_adds_up() {
+        let product = |product_id: u32, revenue: f64| ProductStats {
+            product_id: ProductId(product_id),
+            product_name: format!("Product {}", product_id),
+            mean_demand: 0.0,
+            mean_units_sold: 0.0,
+            mean_revenue: revenue,
+            mean_production_cost: revenue * 0.6,
+            mean_holding_cost: 0.4,
+            mean_liquidation_revenue: 0.0,
+            mean_financing_cost: 0.0,
+            mean_goodwill_penalty: 0.0,
+            mean_contribution: revenue * 0.4 - 0.4,
+            mean_closing_inventory: 0.0,
+        };
+        // The setup cost shares all round up, so the largest product gives the extra dollar back
+        let products = [product(0, 100.0), product(1, 100.0), product(2, 100.6)];
+        let rows = product_pnl(&products, 1_000.0, 300.0);
+        assert_eq!(rows.iter().map(|row| row.setup_cost).collect::<Vec<_>>(), vec![333.0, 333.0, 334.0]);
+        assert_eq!(rows.iter().map(|row| row.fees).collect::<Vec<_>>(), vec![100.0, 100.0, 100.0]);
+        assert_eq!(rows.iter().map(|row| row.holding_cost).sum::<f64>(), 1.0);
+        assert_eq!(rows.iter().map(|row| row.revenue).sum::<f64>(), 301.0);
+
+        // On a simulated season the rows add up exactly to the rounded statement totals
+        let (params, pair) = two_product_scenario();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let config = MonteCarloConfig { master_seed: 9, ..MonteCarloConfig::new(100) };
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+        let total = |value: fn(&ProductPnl) -> f64| stats.product_pnl.iter().map(value).sum::<f64>();
+        let revenue: f64 = stats.product_breakdown.iter().map(|product| product.mean_revenue).sum();
+        assert_eq!(total(|row| row.revenue), revenue.round());
+        assert_eq!(total(|row| row.setup_cost), stats.mean_setup_cost.round());
+        assert!((total(|row| row.profit) - stats.mean_profit).abs() <= 6.0);
+        for (row, product) in stats.product_pnl.iter().zip(&stats.product_breakdown) {
+            let share = product.mean_revenue / revenue;
+            assert!((row.setup_cost - stats.mean_setup_cost * share).abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn test_sampled_traces_replay_and_export() {
+        let (params, pair) = two_product_scenario();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let config = MonteCarloConfig { master_seed: 31, trace_samples: 4, ..MonteCarloConfig::new(40) };
+
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+        assert_eq!(stats.sampled_traces.len(), 4);
+        assert!(stats.sampled_traces.windows(2).all(|pair| pair[0].sample.replication < pair[1].sample.replication));
+        assert_eq!(run_monte_carlo_streaming(&params, &pair, &monthly_order, &config).unwrap().sampled_traces, stats.sampled_traces);
+
+        // Each trace's seed reruns exactly the season it recorded
+        for trace in &stats.sampled_traces {
+            let (monthly_results, profit) = replay_replication(&params, &pair, &monthly_order, &trace.sample).unwrap();
+            assert_eq!(monthly_results, trace.monthly_results);
+            assert_eq!(profit, trace.sample.profit);
+        }
+
+        let path = std::env::temp_dir().join(format!("supply-chain-sim-traces-{}.csv", std::process::id()));
+        assert!(crate::reporting::export_traces_csv(&path, std::slice::from_ref(&stats)).unwrap());
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // A row per replication, month, product and field, under a header
+        let fields = 14;
+        assert_eq!(csv.lines().count(), 1 + 4 * params.season_months * params.products.len() * fields);
+        assert!(csv.lines().nth(1).unwrap().starts_with(&format!("Supplier 0 + Supplier 1,{},", stats.sampled_traces[0].sample.replication)));
+
+        // Without trace sampling nothing is kept or written
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &MonteCarloConfig { trace_samples: 0, ..config }).unwrap();
+        assert!(stats.sampled_traces.is_empty());
+        assert!(!crate::reporting::export_traces_csv(&path, &[stats]).unwrap());
+    }
+
+    #[test]
+    fn test_retained_samples_replay_and_export() {
+        let (params, pair) = two_product_scenario();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let config = MonteCarloConfig {
+            master_seed: 12,
+            sample_retention: SampleRetention::Reservoir(25),
+            ..MonteCarloConfig::new(80)
+        };
+
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+        assert_eq!(stats.samples.len(), 25);
+        assert!(stats.samples.windows(2).all(|pair| pair[0].replication < pair[1].replication));
+        assert_eq!(run_monte_carlo_streaming(&params, &pair, &monthly_order, &config).unwrap().samples, stats.samples);
+
+        // Any kept replication can be rerun on its own from its seed
+        let sample = stats.samples[7];
+        let (_, replayed) = replay_replication(&params, &pair, &monthly_order, &sample).unwrap();
+        assert_eq!(replayed, sample.profit);
+
+        let all = MonteCarloConfig { sample_retention: SampleRetention::All, ..config };
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &all).unwrap();
+        let mean = stats.samples.iter().map(|sample| sample.profit).sum::<f64>() / 80.0;
+        assert!((mean - stats.mean_profit).abs() < 1e-9 * stats.mean_profit.abs());
+
+        let path = std::env::temp_dir().join(format!("supply-chain-sim-samples-{}.csv", std::process::id()));
+        crate::reporting::export_samples(&path, &stats).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("profit"));
+        let profits: Vec<f64> = lines.map(|line| line.parse().unwrap()).collect();
+        assert_eq!(profits, stats.samples.iter().map(|sample| sample.profit).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_adaptive_runs_stop_once_precise_enough() {
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let config = MonteCarloConfig { master_seed: 13, ..MonteCarloConfig::new(0) };
+
+        // Near-certain demand pins the mean down within the first batch
+        let mut steady = params.clone();
+        for demand in &mut steady.demand_params {
+            demand.std_dev_demand = 0.01 * demand.mean_demand;
+            demand.actual_std_dev_demand = 0.01 * demand.mean_demand;
+        }
+        let stats = run_monte_carlo_until_converged(&steady, &pair, &monthly_order, &config, 0.01, 100, 1_000).unwrap();
+        assert!(stats.converged);
+        assert_eq!(stats.num_simulations, 100);
+        assert!(stats.mean_ci_half_width() < 0.01 * stats.mean_profit);
+
+        // A tolerance the spread cannot reach in time runs to the cap and says so
+        let stats = run_monte_carlo_until_converged(&params, &pair, &monthly_order, &config, 1e-4, 100, 300).unwrap();
+        assert!(!stats.converged);
+        assert_eq!(stats.num_simulations, 300);
+        assert_eq!(stats.histogram.iter().map(|bin| bin.2).sum::<usize>(), 300);
+
+        // The replications run are the same ones a fixed-count run would draw
+        let fixed = MonteCarloConfig { num_simulations: 300, ..config };
+        let fixed = run_monte_carlo_simulation(&params, &pair, &monthly_order, &fixed).unwrap();
+        assert_eq!(MonteCarloStats { converged: true, ..stats }, fixed);
+    }
+
+    #[test]
+    fn test_variance_reduction_shrinks_the_standard_error() {
+        // The fixture's order changes do not mirror between a season and its antithetic twin, which
+        // leaves antithetic pairs at 0.83 to 1.04 of the plain error over 200 runs as the seed
+        // varies; tests/variance_reduction.rs checks both methods with order changes on the
+        // standard scenario, where they do
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let plain = MonteCarloConfig { master_seed: 14, ..MonteCarloConfig::new(100) };
+        let baseline = run_monte_carlo_simulation(&params, &pair, &monthly_order, &plain).unwrap();
+        assert_eq!(baseline.adjusted_mean_profit, baseline.mean_profit);
+        assert_eq!(baseline.adjusted_std_error, baseline.mean_std_error);
+
+        for variance_reduction in [VarianceReduction::Antithetic, VarianceReduction::ControlVariate] {
+            let config = MonteCarloConfig { variance_reduction, ..plain.clone() };
+            let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+            assert!(
+                stats.adjusted_std_error <= 0.8 * baseline.mean_std_error,
+                "{:?}: {} against {}", variance_reduction, stats.adjusted_std_error, baseline.mean_std_error
+            );
+            // Both estimate the same mean as the plain run
+            let gap = (stats.adjusted_mean_profit - baseline.mean_profit).abs();
+            assert!(gap < 3.0 * baseline.mean_std_error, "{:?}: off by {}", variance_reduction, gap);
+        }
+    }
+
+    #[test]
+    fn test_stratified_runs_are_unbiased_and_tighter() {
+        // Without fees, lead times or stockouts the season's profit is linear in its demand
+        let (mut params, mut pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        for demand in &mut params.demand_params {
+            demand.mean_demand /= 2.0;
+            demand.std_dev_demand /= 2.0;
+            demand.actual_mean_demand /= 2.0;
+            demand.actual_std_dev_demand /= 2.0;
+        }
+        pair.base_supplier.lead_time_months = 0;
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let stratified = MonteCarloConfig {
+            master_seed: 15,
+            variance_reduction: VarianceReduction::Stratified { strata: 20 },
+            ..MonteCarloConfig::new(200)
+        };
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &stratified).unwrap();
+        let reference = MonteCarloConfig { master_seed: 16, ..MonteCarloConfig::new(10_000) };
+        let reference = run_monte_carlo_simulation(&params, &pair, &monthly_order, &reference).unwrap();
+        let gap = (stats.adjusted_mean_profit - reference.mean_profit).abs();
+        let tolerance = 3.0 * (stats.adjusted_std_error.powi(2) + reference.mean_std_error.powi(2)).sqrt();
+        assert!(gap < tolerance, "off by {} against {}", gap, tolerance);
+        // A near-linear profit is mostly explained by its stratum
+        assert!(stats.adjusted_std_error < 0.3 * stats.mean_std_error, "{} against {}", stats.adjusted_std_error, stats.mean_std_error);
+
+        // On the real scenario the stratified error still undercuts the plain one
+        let (params, pair) = two_product_scenario();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &MonteCarloConfig {
+            variance_reduction: VarianceReduction::Stratified { strata: 10 },
+            ..MonteCarloConfig::new(200)
+        }).unwrap();
+        assert!(stats.adjusted_std_error < stats.mean_std_error, "{} against {}", stats.adjusted_std_error, stats.mean_std_error);
+    }
+
+    #[test]
+    fn test_fixed_costs_are_split_from_operational_profit() {
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        assert!(monthly_order.total_base_quantity() > 0 && monthly_order.total_surge_quantity() > 0);
+        let config = MonteCarloConfig { master_seed: 18, ..MonteCarloConfig::new(30) };
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+
+        // Both suppliers deliver in every run, so each setup fee is paid exactly once
+        let setup_fees = pair.base_supplier.setup_cost + pair.surge_supplier.setup_cost;
+        assert!((stats.mean_setup_cost - setup_fees).abs() < 1e-6);
+        assert_eq!(stats.mean_fees_paid, 0.0);
+        assert!((stats.mean_operational_profit - (stats.mean_profit + setup_fees)).abs() < 1e-6);
+        assert_eq!(RankingMetric::OperationalProfit.score(&stats), stats.mean_operational_profit);
+    }
+
+    #[test]
+    fn test_single_source_matches_a_pair_without_demand_risk() {
+        // Without demand spread every unit goes to the base supplier, so the surge supplier adds
+        // nothing to the pair but its own setup fee, which is never paid
+        let (mut params, mut pair) = two_product_scenario();
+        for dp in &mut params.demand_params {
+            dp.std_dev_demand = 0.0;
+            dp.actual_std_dev_demand = 0.0;
+        }
+        pair.base_supplier.fixed_capacity = 10_000;
+        let single = generate_single_supplier_strategies(std::slice::from_ref(&pair.base_supplier), &PairingRules::default()).remove(0);
+        assert!(single.is_single_source());
+        let config = MonteCarloConfig { master_seed: 23, ..MonteCarloConfig::new(20) };
+        let run = |pair: &SupplierPair| {
+            let order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], pair, &params);
+            assert_eq!(order.total_base_quantity(), 9_000);
+            run_monte_carlo_simulation(&params, pair, &order, &config).unwrap()
+        };
+        let (paired, alone) = (run(&pair), run(&single));
+
+        assert_eq!(alone.pair_name(), "Supplier 0 (single-source)");
+        assert!(alone.mean_profit >= paired.mean_profit, "single {} vs pair {}", alone.mean_profit, paired.mean_profit);
+        // The one supplier's setup is paid once
+        assert!((alone.mean_setup_cost - pair.base_supplier.setup_cost).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_cross_region_pair_outlasts_regional_disruptions() {
+        // A disruption stops the North one month in five, taking out both suppliers of the
+        // same-region pair at once; the otherwise identical pair keeps its surge supplier south
+        let (mut params, mut pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        params.regional_disruptions = vec![RegionalDisruption { region: "North".to_string(), monthly_probability: 0.2 }];
+        pair.base_supplier.region = Some("North".to_string());
+        pair.surge_supplier.region = Some("North".to_string());
+        let cross_region = SupplierPair {
+            surge_supplier: Supplier { region: Some("South".to_string()), ..pair.surge_supplier.clone() },
+            ..pair.clone()
+        };
+        let order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        assert!(order.total_surge_quantity() > 0);
+
+        let comparison = compare(&params, &cross_region, &order, &pair, &order, 100, 41).unwrap();
+        assert!(comparison.difference_ci_low > 0.0, "cross-region ahead by {} ± {}",
+            comparison.mean_difference, comparison.difference_ci_high - comparison.mean_difference);
+
+        // Without the disruptions the regions make no difference
+        params.regional_disruptions.clear();
+        let comparison = compare(&params, &cross_region, &order, &pair, &order, 50, 41).unwrap();
+        assert_eq!(comparison.mean_difference, 0.0);
+    }
+
+    #[test]
+    fn test_exercises_follow_the_demand_surprise() {
+        let (params, pair) = two_product_scenario();
+        seed_demand_rng(17);
+        let optimal_quantities = find_optimal_production_quantities(&params, &pair).unwrap();
+        let monthly_order = split_order_quantities(&optimal_quantities, &pair, &params);
+        let config = MonteCarloConfig { master_seed: 17, ..MonteCarloConfig::new(50) };
+
+        // Demand landing on the forecast leaves nothing worth a fee
+        let mut steady = params.clone();
+        for demand in &mut steady.demand_params {
+            demand.std_dev_demand = 0.01 * demand.mean_demand;
+            demand.actual_std_dev_demand = 0.01 * demand.mean_demand;
+        }
+        let stats = run_monte_carlo_simulation(&steady, &pair, &monthly_order, &config).unwrap();
+        assert!(stats.exercise_probability < 0.1, "exercised in {} of runs", stats.exercise_probability);
+
+        // Demand well above the forecast is met by raising the orders
+        let mut surprised = params.clone();
+        for demand in &mut surprised.demand_params {
+            demand.actual_mean_demand *= 1.5;
+        }
+        let stats = run_monte_carlo_simulation(&surprised, &pair, &monthly_order, &config).unwrap();
+        assert!(stats.exercise_probability > 0.8, "exercised in {} of runs", stats.exercise_probability);
+        assert!(stats.mean_exercises >= stats.exercise_probability);
+        assert!(stats.mean_fees_paid >= stats.exercise_probability * params.order_change_fees[0]);
+        let first_exercises: usize = stats.first_exercise_counts.iter().map(|(_, runs)| runs).sum();
+        assert_eq!(first_exercises, config.num_simulations - stats.exercise_counts[0]);
+    }
+
+    #[test]
+    fn test_expansion_offer_is_taken_when_demand_outruns_the_surge_supplier() {
+        // The plan takes all 12,000 units the pair can make a month, and the surge supplier
+        // offers 5,000 more from September if taken by July
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        for product in &mut params.products {
+            product.selling_price = 150.0;
+        }
+        params.expansion_offers = vec![ExpansionOffer { supplier_id: SupplierId(1), decision_month: 2, start_month: 4, extra_capacity: 5_000, fee: 100_000.0 }];
+        let monthly_order = split_order_quantities(&[(ProductId(0), 8_000), (ProductId(1), 4_000)], &pair, &params);
+        assert_eq!(monthly_order.total_surge_quantity(), 5_000);
+        let config = MonteCarloConfig { master_seed: 43, ..MonteCarloConfig::new(100) };
+
+        // Demand on the forecast leaves the capacity idle already
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+        assert_eq!(stats.expansion_acceptance, [0.0]);
+
+        // Demand twice the forecast outruns it
+        for demand in &mut params.demand_params {
+            demand.actual_mean_demand *= 2.0;
+        }
+        let with = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+        let without = run_monte_carlo_simulation(&SimulationParams { expansion_offers: Vec::new(), ..params.clone() }, &pair, &monthly_order, &config).unwrap();
+        assert!(with.expansion_acceptance[0] > 0.9, "taken in {:?} of runs", with.expansion_acceptance);
+        for level in [75.0, 90.0] {
+            assert!(with.percentile(level) > without.percentile(level), "{:?} against {:?}", with.percentiles, without.percentiles);
+        }
+        // The fee is paid in July, the month the offer is taken
+        assert!(with.monthly_breakdown[2].mean_other_costs >= 100_000.0 * with.expansion_acceptance[0]);
+    }
+
+    #[test]
+    fn test_paired_comparison_separates_plans_from_noise() {
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+
+        // A plan against itself differs on no path
+        let same = compare(&params, &pair, &monthly_order, &pair, &monthly_order, 100, 19).unwrap();
+        assert!(same.difference_ci_low <= 0.0 && 0.0 <= same.difference_ci_high);
+        assert!(!same.is_significant());
+        assert_eq!(same.first, "Supplier 0 + Supplier 1");
+
+        // Ordering far below demand loses on nearly every path
+        let short_order = split_order_quantities(&[(ProductId(0), 4_500), (ProductId(1), 2_250)], &pair, &params);
+        let worse = compare(&params, &pair, &monthly_order, &pair, &short_order, 100, 19).unwrap();
+        assert!(worse.is_significant(), "p = {}", worse.p_value);
+        assert!(worse.difference_ci_low > 0.0);
+        assert!((worse.mean_difference - (worse.mean_profit_first - worse.mean_profit_second)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_extreme_traces_add_up_and_replay() {
+        let (params, pair) = two_product_scenario();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let config = MonteCarloConfig { master_seed: 20, ..MonteCarloConfig::new(30) };
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+
+        let worst = stats.worst_case_trace.as_ref().unwrap();
+        let best = stats.best_case_trace.as_ref().unwrap();
+        for (trace, profit) in [(worst, stats.min_profit), (best, stats.max_profit)] {
+            let summed: f64 = trace.monthly_results.iter().map(|month| month.monthly_profit).sum();
+            assert_eq!(summed, profit);
+            assert_eq!(trace.sample.profit, profit);
+            let (monthly_results, replayed) = replay_replication(&params, &pair, &monthly_order, &trace.sample).unwrap();
+            assert_eq!(replayed, profit);
+            assert_eq!(monthly_results, trace.monthly_results);
+        }
+    }
+
+    #[test]
+    fn test_progress_callbacks_match_the_budgets() {
+        let (params, pair) = two_product_scenario();
+        let progress = RecordingProgress::default();
+
+        let (_, _, diagnostics) = find_optimal_plan(&params, &pair, &progress).unwrap();
+        let optimizer = progress.optimizer.into_inner().unwrap();
+        // A 6×6 coarse grid then a 5×5 fine one
+        let expected: Vec<(usize, usize)> = (1..=61).map(|evaluated| (evaluated, 61)).collect();
+        assert_eq!(optimizer, expected);
+        assert_eq!(diagnostics.candidates_evaluated + diagnostics.candidates_over_capacity, 61);
+        assert!(diagnostics.simulations_run >= 30 * diagnostics.candidates_evaluated);
+        assert!(diagnostics.best_mean_profit.is_some());
+
+        let progress = RecordingProgress::default();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let config = MonteCarloConfig { master_seed: 15, ..MonteCarloConfig::new(40) };
+        run_monte_carlo_simulation_with_progress(&params, &pair, &monthly_order, &config, &progress).unwrap();
+        let mut replications = progress.replications.into_inner().unwrap();
+        replications.sort();
+        let expected: Vec<(usize, usize)> = (1..=40).map(|done| (done, 40)).collect();
+        assert_eq!(replications, expected);
+    }
+
+    #[test]
+    fn test_risk_metrics_of_a_bimodal_season() {
+        // 900 good seasons at 1M, and a bad mode split between 50 deep and 50 mild losses
+        let mut profits = vec![1_000_000.0; 900];
+        profits.extend([-500_000.0; 50]);
+        profits.extend([-100_000.0; 50]);
+        let summary = summarize_profits(profits, &MonteCarloConfig::new(1_000));
+
+        // The worst 5% are exactly the deep losses; the 5th percentile sits at position 49.95,
+        // 95% of the way from the last deep loss to the first mild one
+        assert_eq!(summary.cvar_5, -500_000.0);
+        assert!((summary.var_5 - -120_000.0).abs() < 1e-6);
+        assert_eq!(summary.prob_loss, 0.1);
+
+        let (params, pair) = two_product_scenario();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let config = MonteCarloConfig::new(1_000);
+        let (activity, breakdown) = (OptionActivity::new(&params), ProfitBreakdown::new(&params));
+        let stats = build_stats(&params, &pair, &monthly_order, &config, summary, activity, breakdown, UsageTotals::default());
+        assert_eq!(stats.prob_below(-500_000.0), 0.0);
+        assert!((stats.prob_below(-100_000.0) - 0.05).abs() < 0.005);
+        assert!((stats.prob_below(0.0) - stats.prob_loss).abs() < 0.005);
+        assert_eq!(stats.prob_below(1_000_001.0), 1.0);
+
+        // Ranking by shortfall prefers a safe season to this one despite its higher mean
+        let safe = MonteCarloStats { mean_profit: 500_000.0, cvar_5: 400_000.0, ..stats.clone() };
+        assert!(RankingMetric::MeanProfit.score(&stats) > RankingMetric::MeanProfit.score(&safe));
+        assert!(RankingMetric::ExpectedShortfall.score(&safe) > RankingMetric::ExpectedShortfall.score(&stats));
     }
 }
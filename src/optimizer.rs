@@ -1,60 +1,237 @@
-/// Optimization module for finding optimal production quantities
-/// This module handles the grid search optimization to find the best supply levels for multiple products
+//! Search for a pair's best plan: the monthly quantity of each product to order, within what its
+//! suppliers can make
+
+use tracing::{debug, debug_span};
+use crate::capacity::{fit_capacity_groups, largest_remainder, plan_first_tier_overtime, unit_margins};
+use crate::demand::{validate_demand, with_demand_stream};
+use crate::error::SimulationError;
+use crate::models::{CapacityGroup, MonthlyOrder, ProductId, ProductOrder, Reservation, ReservationProfile, SimulationParams, Supplier, SupplierId, SupplierPair};
+use crate::progress::{NoProgress, ProgressSink};
+use crate::simulation::{run_monthly_simulation_internal, DemandSource};
+
+/// Average shares of the surge supplier's capacity the searched reservations hold
+const RESERVATION_LEVELS: [f64; 3] = [0.25, 0.5, 1.0];
+
+/// Seed of the demand draws a candidate plan's reservation options are compared on, with the
+/// candidate's index mixed in
+const RESERVATION_SEED: u64 = 0x5E4E_0B1E;
+
+/// Season quantity per product as (product_id, units), the reservations to make alongside them,
+/// and what the search did to find them
+pub type OptimalPlan = (Vec<(ProductId, u32)>, Vec<Reservation>, OptimizerDiagnostics);
+
+/// What the optimizer's search did for one supplier pair
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OptimizerDiagnostics {
+    /// Candidate plans simulated
+    pub candidates_evaluated: usize,
+    /// Candidate plans skipped for exceeding the pair's combined capacity, in total or for a
+    /// product, or refused under the strict capacity policy for exceeding a supplier's in a month
+    pub candidates_over_capacity: usize,
+    /// Seasons simulated across all candidates
+    pub simulations_run: usize,
+    /// Mean profit of the chosen plan under forecast demand, None when no candidate was simulated
+    pub best_mean_profit: Option<f64>,
+}
+
+/// Whether the pair can make a candidate's quantities, in total, of each product and of each
+/// capacity group's products, its suppliers' first overtime tier included
+/// Suppliers still ramping up at their first delivery count at the capacity they have then
+fn fits_capacity(pair: &SupplierPair, quantities: &[(ProductId, u32)]) -> bool {
+    let pair = &pair.at_first_delivery();
+    quantities.iter().map(|(_, quantity)| quantity).sum::<u32>() <= pair.total_capacity() + pair.first_tier_overtime()
+        && quantities.iter().all(|&(product_id, quantity)| quantity <= pair.total_capacity_for(product_id) + pair.first_tier_overtime_for(product_id))
+        && pair.suppliers().all(|supplier| supplier.capacity_groups.iter().all(|group| group.units_of(quantities) <= group_capacity(pair, supplier, group)))
+}
+
+/// Most units of a capacity group's products the pair can make a month: the group's limit at its
+/// supplier and what every other supplier can make of them within its first overtime tier
+fn group_capacity(pair: &SupplierPair, supplier: &Supplier, group: &CapacityGroup) -> u32 {
+    let mut others: Vec<&Supplier> = Vec::new();
+    for other in pair.suppliers().filter(|other| other.id != supplier.id) {
+        if others.iter().all(|counted| counted.id != other.id) {
+            others.push(other);
+        }
+    }
+    group.limit + others.iter()
+        .map(|other| other.first_tier_capacity_for_all(&group.product_ids))
+        .sum::<u32>()
+}
+
+/// A candidate plan ordering each product from the committed tier as far as it can make it, in
+/// total, of the product and of its capacity groups, then from the surge supplier within nominal
+/// capacity, then in overtime where the margin bears it, and the rest from the surge supplier
+/// Suppliers still ramping up at their first delivery are planned at the capacity they have then
+fn candidate_order(params: &SimulationParams, pair: &SupplierPair, quantities: &[(ProductId, u32)]) -> MonthlyOrder {
+    let pair = &pair.at_first_delivery();
+    let mut base_left = pair.base_capacity();
+    let base: Vec<(ProductId, u32)> = quantities.iter()
+        .map(|&(product_id, quantity)| {
+            let base = quantity.min(pair.base_capacity_for(product_id)).min(base_left);
+            base_left -= base;
+            (product_id, base)
+        })
+        .collect();
+    let base = fit_capacity_groups(&base, &[], pair.committed_tier(), &unit_margins(params, &pair.base_supplier));
+    let base_placed: u32 = base.iter().map(|(_, units)| units).sum();
+    let mut surge_left = pair.total_capacity() - base_placed;
+    let surge: Vec<(ProductId, u32)> = quantities.iter().zip(&base)
+        .map(|(&(product_id, quantity), &(_, base))| {
+            let surge = (quantity - base).min(pair.total_capacity_for(product_id) - base).min(surge_left);
+            surge_left -= surge;
+            (product_id, surge)
+        })
+        .collect();
+    let shared_base = if pair.shares_capacity() { base.clone() } else { Vec::new() };
+    let surge = fit_capacity_groups(&surge, &shared_base, pair.surge_tier(), &unit_margins(params, &pair.surge_supplier));
+    let mut order = MonthlyOrder::new(
+        base.into_iter().map(|(product_id, quantity)| ProductOrder { product_id, quantity }).collect(),
+        surge.into_iter().map(|(product_id, quantity)| ProductOrder { product_id, quantity }).collect(),
+    ).expect("one order per product planned");
+    plan_first_tier_overtime(params, pair, &mut order, quantities);
+    for &(product_id, quantity) in quantities {
+        let placed = order.base_quantity_for(product_id) + order.surge_quantity_for(product_id);
+        order.add_surge_quantity(product_id, quantity - placed);
+    }
+    order
+}
+
+/// Reservations every candidate plan makes of the suppliers selling their capacity by
+/// reservation, all of it, other than `searched`
+fn reservations_in_full(params: &SimulationParams, pair: &SupplierPair, searched: Option<SupplierId>) -> Vec<Reservation> {
+    let mut reservations: Vec<Reservation> = Vec::new();
+    for supplier in pair.suppliers().filter(|supplier| supplier.reservation_fee.is_some() && Some(supplier.id) != searched) {
+        // A single source stands in as its own surge supplier; its own capacity is reserved
+        if reservations.iter().all(|reservation| reservation.supplier_id != supplier.id) {
+            reservations.push(Reservation::with_profile(supplier, ReservationProfile::Flat, 1.0, params.season_months));
+        }
+    }
+    reservations
+}
+
+/// Sets of reservations a candidate plan is tried with: when the surge supplier sells its
+/// capacity by reservation, each profile at each level of it, otherwise the one set of in-full
+/// reservations, which is empty when no supplier sells by reservation
+fn reservation_options(params: &SimulationParams, pair: &SupplierPair) -> Vec<Vec<Reservation>> {
+    let surge = &pair.surge_supplier;
+    if surge.reservation_fee.is_none() || pair.is_single_source() {
+        return vec![reservations_in_full(params, pair, None)];
+    }
+    let others = reservations_in_full(params, pair, Some(surge.id));
+    ReservationProfile::ALL.iter()
+        .flat_map(|&profile| RESERVATION_LEVELS.iter().map(move |&level| Reservation::with_profile(surge, profile, level, params.season_months)))
+        .map(|reservation| others.iter().cloned().chain([reservation]).collect())
+        .collect()
+}
+
+/// Mean profit over `simulations` seasons of a candidate plan with the best of its reservation
+/// options, and those reservations; with a choice of options, each is simulated on the same
+/// demand draws, seeded by the candidate's index
+/// None when the plan does not fit its suppliers' capacity with any of the options under the
+/// strict capacity policy, which refuses such plans rather than clamping them
+fn evaluate_candidate(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    monthly_order: MonthlyOrder,
+    reservation_options: &[Vec<Reservation>],
+    enable_options: bool,
+    simulations: usize,
+    candidate: usize,
+) -> Option<(f64, Vec<Reservation>)> {
+    let mean_profit = |order: &MonthlyOrder| -> Option<f64> {
+        let mut profits = Vec::new();
+        for _ in 0..simulations {
+            let (_, total_profit) = run_monthly_simulation_internal(
+                params,
+                pair,
+                order,
+                enable_options,
+                false,
+                DemandSource::Stochastic(None),
+            ).ok()?;
+            profits.push(total_profit);
+        }
+        Some(profits.iter().sum::<f64>() / profits.len() as f64)
+    };
+    let with_reservations = |reservations: &Vec<Reservation>| monthly_order.clone().with_reservations(reservations.clone());
+    if let [reservations] = reservation_options {
+        return mean_profit(&with_reservations(reservations)).map(|profit| (profit, reservations.clone()));
+    }
+    reservation_options.iter()
+        .filter_map(|reservations| {
+            let order = with_reservations(reservations);
+            let profit = with_demand_stream(RESERVATION_SEED ^ candidate as u64, || mean_profit(&order))?;
+            Some((profit, reservations.clone()))
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+}
 
-use crate::models::{MonthlyOrder, ProductOrder, SimulationParams, SupplierPair};
-use crate::simulation::run_monthly_simulation_internal;
+/// Monthly demand for a product left to order once its opening stock is spread evenly over
+/// the season, so the search centers on what still needs buying
+fn net_of_opening_stock(params: &SimulationParams, product_id: ProductId, mean_demand: f64) -> f64 {
+    let opening: u32 = params.opening_inventory.iter()
+        .filter(|(id, _)| *id == product_id)
+        .map(|(_, units)| units)
+        .sum();
+    (mean_demand - opening as f64 / params.season_months.max(1) as f64).max(0.0)
+}
 
 /// Find optimal production quantities for all products using grid search
 /// Tests multiple combinations within shared capacity constraints
 /// When called from within options valuation, options_enabled should be false to avoid infinite recursion
+/// Fails when a product's demand distribution cannot be sampled
 pub fn find_optimal_production_quantities(
     params: &SimulationParams,
     pair: &SupplierPair,
-) -> Vec<(usize, u32)> {
-    find_optimal_production_quantities_internal(params, pair, false)
+) -> Result<Vec<(ProductId, u32)>, SimulationError> {
+    Ok(find_optimal_production_quantities_internal(params, pair, false, &NoProgress)?.0)
 }
 
 fn find_optimal_production_quantities_internal(
     params: &SimulationParams,
     pair: &SupplierPair,
     enable_options: bool,
-) -> Vec<(usize, u32)> {
-    let total_capacity = pair.base_supplier.fixed_capacity + pair.surge_supplier.fixed_capacity;
-    
-    // Get product IDs and their expected demands
-    let products: Vec<(usize, f64)> = params.products.iter()
+    progress: &dyn ProgressSink,
+) -> Result<OptimalPlan, SimulationError> {
+    // Candidates whose simulation fails count as over capacity, so bad inputs must fail first
+    validate_demand(params)?;
+
+    // Get product IDs and their expected demands, less what the opening stock covers
+    let products: Vec<(ProductId, f64)> = params.products.iter()
         .map(|p| {
             let mean = params.get_demand_params(p.id)
                 .map(|dp| dp.mean_demand)
                 .unwrap_or(0.0);
-            (p.id, mean)
+            (p.id, net_of_opening_stock(params, p.id, mean))
         })
         .collect();
 
     if products.len() == 1 {
         // Single product: use original approach
-        return find_optimal_single_product(params, pair, enable_options, 15);
+        return Ok(find_optimal_single_product(params, pair, enable_options, 15, progress));
     }
 
     if products.len() != 2 {
-        // For more than 2 products, use proportional allocation as fallback
-        return allocate_proportionally(&products, total_capacity);
+        // For more than 2 products, use proportional allocation as fallback, reserving every
+        // supplier that sells by reservation in full
+        return Ok((allocate_proportionally(&products, pair), reservations_in_full(params, pair, None), OptimizerDiagnostics::default()));
     }
 
     // Two-product coarse-to-fine grid search
-    coarse_to_fine_grid_search(params, pair, &products, enable_options)
+    Ok(coarse_to_fine_grid_search(params, pair, &products, enable_options, progress))
 }
 
 /// Coarse-to-fine grid search for two products
 fn coarse_to_fine_grid_search(
     params: &SimulationParams,
     pair: &SupplierPair,
-    products: &[(usize, f64)],
+    products: &[(ProductId, f64)],
     enable_options: bool,
-) -> Vec<(usize, u32)> {
+    progress: &dyn ProgressSink,
+) -> (Vec<(ProductId, u32)>, Vec<Reservation>, OptimizerDiagnostics) {
     let (product_a_id, demand_a) = products[0];
     let (product_b_id, demand_b) = products[1];
-    let total_capacity = pair.base_supplier.fixed_capacity + pair.surge_supplier.fixed_capacity;
+    let total_capacity = pair.total_capacity();
 
     // Tighter bounds based on newsvendor theory
     let min_factor = 0.7;
@@ -67,6 +244,16 @@ fn coarse_to_fine_grid_search(
     let mut best_a_coarse = demand_a as u32;
     let mut best_b_coarse = demand_b as u32;
 
+    // Every grid point of both passes counts towards progress, including those over capacity
+    let fine_steps = 4; // 5 points (0..=4)
+    let grid_points = (coarse_steps + 1) * (coarse_steps + 1) + (fine_steps + 1) * (fine_steps + 1);
+    let mut evaluated = 0;
+    let mut diagnostics = OptimizerDiagnostics::default();
+    // Each candidate is tried with each set of reservations, and the best kept
+    let reservation_options = reservation_options(params, pair);
+    let mut best_reservations_coarse = reservation_options[0].clone();
+
+    let coarse_pass = debug_span!("coarse_pass", points = (coarse_steps + 1) * (coarse_steps + 1), simulations = coarse_sims).entered();
     for i in 0..=coarse_steps {
         let factor_a = min_factor + (max_factor - min_factor) * (i as f64 / coarse_steps as f64);
         let qty_a = (demand_a * factor_a) as u32;
@@ -75,48 +262,38 @@ fn coarse_to_fine_grid_search(
             let factor_b = min_factor + (max_factor - min_factor) * (j as f64 / coarse_steps as f64);
             let qty_b = (demand_b * factor_b) as u32;
 
-            if qty_a + qty_b > total_capacity {
+            evaluated += 1;
+            progress.on_optimizer_progress(evaluated, grid_points);
+            if !fits_capacity(pair, &[(product_a_id, qty_a), (product_b_id, qty_b)]) {
+                debug!(qty_a, qty_b, total_capacity, "candidate eliminated: over capacity");
+                diagnostics.candidates_over_capacity += 1;
                 continue;
             }
-
-            let monthly_order = MonthlyOrder {
-                base_orders: vec![
-                    ProductOrder { product_id: product_a_id, quantity: qty_a },
-                    ProductOrder { product_id: product_b_id, quantity: qty_b },
-                ],
-                surge_orders: vec![
-                    ProductOrder { product_id: product_a_id, quantity: 0 },
-                    ProductOrder { product_id: product_b_id, quantity: 0 },
-                ],
+            let monthly_order = candidate_order(params, pair, &[(product_a_id, qty_a), (product_b_id, qty_b)]);
+            let Some((mean_profit, reservations)) = evaluate_candidate(params, pair, monthly_order, &reservation_options, enable_options, coarse_sims, evaluated) else {
+                debug!(qty_a, qty_b, "candidate refused: over capacity in a month");
+                diagnostics.candidates_over_capacity += 1;
+                continue;
             };
-
-            let mut profits = Vec::new();
-            for _ in 0..coarse_sims {
-                let (_, total_profit) = run_monthly_simulation_internal(
-                    params,
-                    pair,
-                    &monthly_order,
-                    enable_options,
-                    false,
-                );
-                profits.push(total_profit);
-            }
-
-            let mean_profit = profits.iter().sum::<f64>() / profits.len() as f64;
+            diagnostics.candidates_evaluated += 1;
+            diagnostics.simulations_run += coarse_sims * reservation_options.len();
+            debug!(qty_a, qty_b, mean_profit, "candidate");
 
             if mean_profit > best_profit_coarse {
                 best_profit_coarse = mean_profit;
                 best_a_coarse = qty_a;
                 best_b_coarse = qty_b;
+                best_reservations_coarse = reservations;
             }
         }
     }
+    drop(coarse_pass);
 
     // FINE PASS: 5x5 grid around best coarse point with 50 simulations
-    let fine_steps = 4; // 5 points (0..=4)
     let fine_sims = 50;
     let mut best_profit_fine = best_profit_coarse;
     let mut best_allocation = vec![(product_a_id, best_a_coarse), (product_b_id, best_b_coarse)];
+    let mut best_reservations = best_reservations_coarse;
 
     // Define fine search window (±15% around best coarse point)
     let a_min = (best_a_coarse as f64 * 0.85) as u32;
@@ -124,49 +301,44 @@ fn coarse_to_fine_grid_search(
     let b_min = (best_b_coarse as f64 * 0.85) as u32;
     let b_max = (best_b_coarse as f64 * 1.15) as u32;
 
+    let fine_pass = debug_span!("fine_pass", points = (fine_steps + 1) * (fine_steps + 1), simulations = fine_sims).entered();
     for i in 0..=fine_steps {
         let qty_a = a_min + ((a_max - a_min) as f64 * (i as f64 / fine_steps as f64)) as u32;
 
         for j in 0..=fine_steps {
             let qty_b = b_min + ((b_max - b_min) as f64 * (j as f64 / fine_steps as f64)) as u32;
 
-            if qty_a + qty_b > total_capacity {
+            evaluated += 1;
+            progress.on_optimizer_progress(evaluated, grid_points);
+            if !fits_capacity(pair, &[(product_a_id, qty_a), (product_b_id, qty_b)]) {
+                debug!(qty_a, qty_b, total_capacity, "candidate eliminated: over capacity");
+                diagnostics.candidates_over_capacity += 1;
                 continue;
             }
-
-            let monthly_order = MonthlyOrder {
-                base_orders: vec![
-                    ProductOrder { product_id: product_a_id, quantity: qty_a },
-                    ProductOrder { product_id: product_b_id, quantity: qty_b },
-                ],
-                surge_orders: vec![
-                    ProductOrder { product_id: product_a_id, quantity: 0 },
-                    ProductOrder { product_id: product_b_id, quantity: 0 },
-                ],
+            let monthly_order = candidate_order(params, pair, &[(product_a_id, qty_a), (product_b_id, qty_b)]);
+            let Some((mean_profit, reservations)) = evaluate_candidate(params, pair, monthly_order, &reservation_options, enable_options, fine_sims, evaluated) else {
+                debug!(qty_a, qty_b, "candidate refused: over capacity in a month");
+                diagnostics.candidates_over_capacity += 1;
+                continue;
             };
-
-            let mut profits = Vec::new();
-            for _ in 0..fine_sims {
-                let (_, total_profit) = run_monthly_simulation_internal(
-                    params,
-                    pair,
-                    &monthly_order,
-                    enable_options,
-                    false,
-                );
-                profits.push(total_profit);
-            }
-
-            let mean_profit = profits.iter().sum::<f64>() / profits.len() as f64;
+            diagnostics.candidates_evaluated += 1;
+            diagnostics.simulations_run += fine_sims * reservation_options.len();
+            debug!(qty_a, qty_b, mean_profit, "candidate");
 
             if mean_profit > best_profit_fine {
                 best_profit_fine = mean_profit;
                 best_allocation = vec![(product_a_id, qty_a), (product_b_id, qty_b)];
+                best_reservations = reservations;
             }
         }
     }
+    drop(fine_pass);
+    debug!(allocation = ?best_allocation, mean_profit = best_profit_fine, "optimizer chose");
 
-    best_allocation
+    if best_profit_fine.is_finite() {
+        diagnostics.best_mean_profit = Some(best_profit_fine);
+    }
+    (best_allocation, best_reservations, diagnostics)
 }
 
 /// Single product optimization (backward compatible)
@@ -175,11 +347,13 @@ fn find_optimal_single_product(
     pair: &SupplierPair,
     enable_options: bool,
     simulations_per_candidate: usize,
-) -> Vec<(usize, u32)> {
+    progress: &dyn ProgressSink,
+) -> (Vec<(ProductId, u32)>, Vec<Reservation>, OptimizerDiagnostics) {
     let product = &params.products[0];
     let base_demand = params.get_demand_params(product.id)
         .map(|dp| dp.mean_demand)
         .unwrap_or(60_000.0);
+    let base_demand = net_of_opening_stock(params, product.id, base_demand);
 
     let num_candidates = 12;
     let min_factor = 0.7;
@@ -187,58 +361,278 @@ fn find_optimal_single_product(
 
     let mut best_profit = f64::NEG_INFINITY;
     let mut best_quantity = base_demand as u32;
+    let mut candidates_over_capacity = 0;
+    let reservation_options = reservation_options(params, pair);
+    let mut best_reservations = reservation_options[0].clone();
 
+    let _search = debug_span!("single_product_search", points = num_candidates, simulations = simulations_per_candidate).entered();
     for i in 0..num_candidates {
         let factor = min_factor + (max_factor - min_factor) * (i as f64 / (num_candidates - 1) as f64);
         let candidate_quantity = (base_demand * factor) as u32;
-
-        let monthly_order = MonthlyOrder {
-            base_orders: vec![ProductOrder { product_id: product.id, quantity: candidate_quantity }],
-            surge_orders: vec![ProductOrder { product_id: product.id, quantity: 0 }],
-        };
-
-        let mut profits = Vec::new();
-        for _ in 0..simulations_per_candidate {
-            let (_, total_profit) = run_monthly_simulation_internal(
-                params,
-                pair,
-                &monthly_order,
-                enable_options,
-                false,
-            );
-            profits.push(total_profit);
+        progress.on_optimizer_progress(i + 1, num_candidates);
+        if !fits_capacity(pair, &[(product.id, candidate_quantity)]) {
+            debug!(quantity = candidate_quantity, "candidate eliminated: over capacity");
+            candidates_over_capacity += 1;
+            continue;
         }
 
-        let mean_profit = profits.iter().sum::<f64>() / profits.len() as f64;
+        let monthly_order = candidate_order(params, pair, &[(product.id, candidate_quantity)]);
+        let Some((mean_profit, reservations)) = evaluate_candidate(params, pair, monthly_order, &reservation_options, enable_options, simulations_per_candidate, i) else {
+            debug!(quantity = candidate_quantity, "candidate refused: over capacity in a month");
+            candidates_over_capacity += 1;
+            continue;
+        };
+        debug!(quantity = candidate_quantity, mean_profit, "candidate");
 
         if mean_profit > best_profit {
             best_profit = mean_profit;
             best_quantity = candidate_quantity;
+            best_reservations = reservations;
         }
     }
 
-    vec![(product.id, best_quantity)]
+    let candidates_evaluated = num_candidates - candidates_over_capacity;
+    let diagnostics = OptimizerDiagnostics {
+        candidates_evaluated,
+        candidates_over_capacity,
+        simulations_run: candidates_evaluated * simulations_per_candidate * reservation_options.len(),
+        best_mean_profit: best_profit.is_finite().then_some(best_profit),
+    };
+    (vec![(product.id, best_quantity)], best_reservations, diagnostics)
 }
 
-/// Allocate capacity proportionally to expected demand (fallback for 3+ products)
-fn allocate_proportionally(products: &[(usize, f64)], total_capacity: u32) -> Vec<(usize, u32)> {
-    let total_demand: f64 = products.iter().map(|(_, d)| d).sum();
-    
-    products.iter()
-        .map(|(id, demand)| {
-            let proportion = demand / total_demand;
-            let qty = (total_capacity as f64 * proportion) as u32;
-            (*id, qty)
-        })
+/// Allocate capacity proportionally to expected demand (fallback for 3+ products), by largest
+/// remainder so the shares add up to the whole capacity, no product getting more than the pair
+/// can make of it
+fn allocate_proportionally(products: &[(ProductId, f64)], pair: &SupplierPair) -> Vec<(ProductId, u32)> {
+    let demands: Vec<u32> = products.iter().map(|(_, demand)| demand.round() as u32).collect();
+    let total_demand: u32 = demands.iter().sum();
+    let total_capacity = pair.total_capacity();
+    // Weights scaled to cover the capacity, so largest remainder shares all of it out
+    let weights: Vec<u32> = if total_demand == 0 {
+        vec![total_capacity; demands.len()]
+    } else {
+        let scale = total_capacity.div_ceil(total_demand).max(1);
+        demands.iter().map(|demand| demand * scale).collect()
+    };
+
+    products.iter().zip(largest_remainder(&weights, total_capacity))
+        .map(|((id, _), qty)| (*id, qty.min(pair.total_capacity_for(*id))))
         .collect()
 }
 
-/// Find optimal production quantities with diagnostic output
+/// Find optimal production quantities and, for suppliers that sell their capacity by
+/// reservation, the reservations to make alongside them, reporting each candidate plan
+/// evaluated to `progress`, along with what the search did
 /// Uses coarse-to-fine approach for efficiency
-pub fn find_optimal_production_quantities_with_diagnostics(
+/// Fails as `find_optimal_production_quantities` does
+pub fn find_optimal_plan(
     params: &SimulationParams,
     pair: &SupplierPair,
-) -> Vec<(usize, u32)> {
+    progress: &dyn ProgressSink,
+) -> Result<OptimalPlan, SimulationError> {
     // Use the same coarse-to-fine approach
-    find_optimal_production_quantities_internal(params, pair, false)
+    find_optimal_production_quantities_internal(params, pair, false, progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing::span::{Attributes, Id};
+    use tracing::{Event, Subscriber};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::Layer;
+    use crate::monte_carlo::tests::two_product_scenario;
+
+    /// Layer recording the spans opened and the span each candidate event was emitted in
+    #[derive(Clone, Default)]
+    struct Recorder {
+        spans: Arc<Mutex<Vec<&'static str>>>,
+        candidates: Arc<Mutex<Vec<Option<&'static str>>>>,
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Recorder {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+            self.spans.lock().unwrap().push(attrs.metadata().name());
+        }
+
+        fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+            if event.metadata().fields().field("qty_a").is_some() {
+                self.candidates.lock().unwrap().push(ctx.event_span(event).map(|span| span.name()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_optimizer_traces_every_grid_point() {
+        let (params, pair) = two_product_scenario();
+        let recorder = Recorder::default();
+        let subscriber = tracing_subscriber::registry().with(recorder.clone());
+        let (_, _, diagnostics) = tracing::subscriber::with_default(subscriber, || {
+            find_optimal_plan(&params, &pair, &NoProgress).unwrap()
+        });
+
+        // A 6x6 coarse grid then a 5x5 fine one, whether or not a point fits the capacity
+        let candidates = recorder.candidates.lock().unwrap();
+        assert_eq!(candidates.len(), 36 + 25);
+        assert_eq!(candidates.len(), diagnostics.candidates_evaluated + diagnostics.candidates_over_capacity);
+        assert_eq!(candidates.iter().filter(|span| **span == Some("coarse_pass")).count(), 36);
+        assert_eq!(candidates.iter().filter(|span| **span == Some("fine_pass")).count(), 25);
+        assert_eq!(*recorder.spans.lock().unwrap(), ["coarse_pass", "fine_pass"]);
+    }
+
+    #[test]
+    fn test_a_product_limit_shifts_volume_to_the_surge_supplier() {
+        use std::collections::HashMap;
+        use crate::capacity::{create_order, validate_capacity_constraint};
+        use crate::demand::with_demand_stream;
+        use crate::simulation::split_order_quantities;
+
+        // The base supplier makes Model B at 75 but at most 1,000 a month of it; the surge
+        // supplier charges 80
+        let (mut params, mut pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        pair.base_supplier.product_capacity = Some(HashMap::from([(ProductId(1), 1_000)]));
+        let (quantities, _, diagnostics) = with_demand_stream(35, || {
+            find_optimal_plan(&params, &pair, &NoProgress).unwrap()
+        });
+        assert!(diagnostics.candidates_evaluated > 0);
+        let model_b = quantities.iter().find(|(product_id, _)| *product_id == ProductId(1)).unwrap().1;
+        assert!(model_b > 1_000, "{:?}", quantities);
+
+        let order = split_order_quantities(&quantities, &pair, &params);
+        assert_eq!(order.base_quantity_for(ProductId(1)), 1_000);
+        assert_eq!(order.surge_quantity_for(ProductId(1)), model_b - 1_000);
+        assert!(validate_capacity_constraint(&order, &pair).is_ok());
+
+        // Within the base supplier's total but over its Model B limit
+        let over = create_order(vec![(ProductId(0), 0), (ProductId(1), 1_500)], vec![(ProductId(0), 0), (ProductId(1), 0)]);
+        let error = validate_capacity_constraint(&over, &pair).unwrap_err();
+        assert_eq!((error.capacity, error.requested), (1_000, 1_500));
+    }
+
+    #[test]
+    fn test_a_binding_capacity_group_changes_the_optimum() {
+        use crate::capacity::validate_capacity_constraint;
+        use crate::demand::with_demand_stream;
+        use crate::simulation::split_order_quantities;
+
+        // Both suppliers' cutting lines make 6,500 of the two models a month between them, short
+        // of the 12,000 they can assemble and of the 9,000 a month forecast
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        let line = |limit: u32| CapacityGroup { name: "Cutting line".to_string(), product_ids: vec![ProductId(0), ProductId(1)], limit };
+        let mut grouped = pair.clone();
+        grouped.base_supplier.capacity_groups = vec![line(4_500)];
+        grouped.surge_supplier.capacity_groups = vec![line(2_000)];
+        let plan = |pair: &SupplierPair| with_demand_stream(38, || find_optimal_plan(&params, pair, &NoProgress).unwrap()).0;
+        let total = |quantities: &[(ProductId, u32)]| quantities.iter().map(|(_, quantity)| quantity).sum::<u32>();
+
+        let unconstrained = plan(&pair);
+        let constrained = plan(&grouped);
+        assert!(total(&unconstrained) > 6_500, "{:?}", unconstrained);
+        assert!(total(&constrained) <= 6_500, "{:?}", constrained);
+        let order = split_order_quantities(&constrained, &grouped, &params);
+        assert!(order.total_base_quantity() <= 4_500 && order.total_surge_quantity() <= 2_000, "{:?}", order);
+        assert!(validate_capacity_constraint(&order, &grouped).is_ok());
+    }
+
+    #[test]
+    fn test_three_products_share_the_whole_capacity() {
+        // 12,000 units a month across three equal forecasts, and across forecasts of 1 : 1 : 5
+        let (_, pair) = two_product_scenario();
+        let shares = |products: &[(ProductId, f64)]| -> Vec<u32> {
+            allocate_proportionally(products, &pair).into_iter().map(|(_, quantity)| quantity).collect()
+        };
+        assert_eq!(shares(&[(ProductId(0), 3_000.0), (ProductId(1), 3_000.0), (ProductId(2), 3_000.0)]), vec![4_000, 4_000, 4_000]);
+        let skewed = shares(&[(ProductId(0), 1_000.0), (ProductId(1), 1_000.0), (ProductId(2), 5_000.0)]);
+        assert_eq!(skewed.iter().sum::<u32>(), 12_000);
+        assert_eq!(skewed, vec![1_714, 1_714, 8_572]);
+    }
+
+    #[test]
+    fn test_overtime_is_planned_only_when_its_margin_pays() {
+        use crate::demand::with_demand_stream;
+        use crate::simulation::split_order_quantities;
+
+        // 9,000 units of monthly demand against 8,000 of nominal capacity, and 20% more from
+        // each supplier at a premium
+        let (mut params, mut pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        pair.base_supplier.fixed_capacity = 5_000;
+        pair.surge_supplier.fixed_capacity = 3_000;
+        let plan_with_premium = |multiplier: f64| {
+            let mut pair = pair.clone();
+            pair.base_supplier.soft_capacity_tiers = vec![(0.20, multiplier)];
+            pair.surge_supplier.soft_capacity_tiers = vec![(0.20, multiplier)];
+            let (quantities, _, _) = with_demand_stream(36, || find_optimal_plan(&params, &pair, &NoProgress).unwrap());
+            (quantities.clone(), split_order_quantities(&quantities, &pair, &params))
+        };
+
+        // At +10% both suppliers still make a margin on overtime: 82.5 and 88 against a price of 100
+        let (quantities, order) = plan_with_premium(1.10);
+        assert!(quantities.iter().map(|(_, quantity)| quantity).sum::<u32>() > 8_000, "{:?}", quantities);
+        assert!(order.total_base_quantity() > 5_000 || order.total_surge_quantity() > 3_000, "{:?}", order);
+        assert!(order.total_base_quantity() <= 6_000 && order.total_surge_quantity() <= 3_600);
+
+        // At +35% every overtime unit costs more than it sells for
+        let (_, order) = plan_with_premium(1.35);
+        assert!(order.total_base_quantity() <= 5_000 && order.total_surge_quantity() <= 3_000, "{:?}", order);
+    }
+
+    #[test]
+    fn test_steady_demand_takes_a_leaner_reservation() {
+        use crate::demand::with_demand_stream;
+
+        // Demand averages the base supplier's 7,000 a month at a margin worth stocking up for,
+        // and the surge supplier sells its capacity by reservation at 2 a unit
+        let reserved_with_spread = |spread: f64| {
+            let (mut params, mut pair) = two_product_scenario();
+            for product in &mut params.products {
+                product.selling_price = 150.0;
+            }
+            for dp in &mut params.demand_params {
+                dp.mean_demand -= 1_000.0;
+                dp.actual_mean_demand -= 1_000.0;
+                dp.std_dev_demand = spread * dp.mean_demand;
+                dp.actual_std_dev_demand = spread * dp.actual_mean_demand;
+            }
+            pair.surge_supplier.reservation_fee = Some(2.0);
+            let (_, reservations, diagnostics) = with_demand_stream(36, || find_optimal_plan(&params, &pair, &NoProgress).unwrap());
+            assert_eq!(diagnostics.simulations_run % 9, 0);
+            assert_eq!(reservations.len(), 1);
+            assert_eq!(reservations[0].supplier_id, pair.surge_supplier.id);
+            reservations[0].per_month.iter().sum::<u32>()
+        };
+        let steady = reserved_with_spread(0.0);
+        let volatile = reserved_with_spread(0.6);
+        // Steady demand fits the base supplier, so the least of the surge capacity is reserved
+        assert_eq!(steady, 10_000);
+        assert!(steady < volatile, "{} reserved under steady demand, {} under volatile", steady, volatile);
+    }
+
+    #[test]
+    fn test_strict_policy_keeps_plans_within_their_reservations() {
+        use crate::demand::with_demand_stream;
+        use crate::models::CapacityPolicy;
+        use crate::simulation::check_capacity;
+
+        // Reserving the surge supplier costs enough that clipping surge orders to a lean
+        // reservation pays; the strict policy refuses every plan that does so
+        let (mut params, mut pair) = two_product_scenario();
+        params.capacity_policy = CapacityPolicy::Strict;
+        pair.surge_supplier.reservation_fee = Some(15.0);
+        let (quantities, reservations, diagnostics) = with_demand_stream(37, || find_optimal_plan(&params, &pair, &NoProgress).unwrap());
+        assert!(diagnostics.candidates_evaluated > 0);
+        let plan = candidate_order(&params, &pair, &quantities).with_reservations(reservations);
+        assert!(check_capacity(&params, &pair, &plan).is_ok(), "{:?}", plan);
+
+        // Clamping, the search settles on surge orders its reservation clips
+        params.capacity_policy = CapacityPolicy::Clamp;
+        let (quantities, reservations, _) = with_demand_stream(37, || find_optimal_plan(&params, &pair, &NoProgress).unwrap());
+        let plan = candidate_order(&params, &pair, &quantities).with_reservations(reservations);
+        assert!(check_capacity(&params, &pair, &plan).is_err(), "{:?}", plan);
+    }
 }
@@ -1,187 +1,1168 @@
-use crate::models::{SimulationParams, SupplierPair};
+use crate::models::{ExerciseDirection, MonthlyOrder, OptionState, Product, ProductId, SimulationParams, SupplierPair};
+use crate::stats::{inverse_standard_normal_cdf, normal_loss};
 use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Number of remaining exercise rights modelled jointly in the lattice
+/// Later rights add little value but multiply the size of the tree
+const MAX_RIGHTS_VALUED: usize = 2;
+
+/// Weight of the prior forecast in the calibrated volatility, in months of observed demand
+const PRIOR_WEIGHT_MONTHS: f64 = 2.0;
+
+/// Errors building an option valuation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptionsError {
+    /// The decision month does not fall inside the configured season
+    MonthOutOfSeason { month: usize, season_months: usize },
+}
+
+impl fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionsError::MonthOutOfSeason { month, season_months } => write!(
+                f,
+                "decision month {} is outside the {}-month season",
+                month, season_months
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OptionsError {}
+
+/// How the benefit of changing orders is computed at a lattice node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayoffModel {
+    /// Reduction in expected overage and underage cost over the remaining horizon,
+    /// using the standard normal loss function and critical-fractile targets
+    NormalLoss,
+    /// Deterministic projection of sales, holding and liquidation against expected demand,
+    /// with a fixed 95% service-level target
+    ProjectedSales,
+}
+
+/// Which products a valuation covers and how much of the fee schedule it models
+#[derive(Clone, Debug)]
+pub struct OptionsConfig {
+    /// Products valued jointly, with their demand and orders aggregated
+    pub product_ids: Vec<ProductId>,
+    /// Remaining exercise rights modelled jointly in the lattice
+    pub max_rights_valued: usize,
+    /// Periods ahead the lattice looks for a better time to exercise; a change made at any of
+    /// them is still valued over every delivery it affects to the season's end
+    pub max_periods_valued: usize,
+    /// Benefit computation for exercising a right
+    pub payoff_model: PayoffModel,
+}
+
+impl OptionsConfig {
+    /// Value the given products jointly with the default number of rights and payoff model,
+    /// looking ahead to the season's end
+    pub fn for_products(product_ids: &[ProductId]) -> Self {
+        OptionsConfig {
+            product_ids: product_ids.to_vec(),
+            max_rights_valued: MAX_RIGHTS_VALUED,
+            max_periods_valued: usize::MAX,
+            payoff_model: PayoffModel::NormalLoss,
+        }
+    }
+}
+
+/// Value of the rights to change orders at a decision state
+/// Node payoffs are net of the fees for changing the products in scope on their own,
+/// and exercising a right at a node keeps the value of the rights that remain after it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OptionValue {
+    /// Value of the more valuable right: adding surge units or cancelling units
+    pub value: f64,
+    /// Change to make if the rights are exercised now, or None when no change adds value
+    pub recommended_action: Option<ExerciseDirection>,
+}
 
 /// American option valuation using binomial method
-/// Updated to work with multi-product simulation using aggregate values
-#[allow(dead_code)]
-pub struct OptionValuation {
-    current_order_quantity: u32,  // Total across all products
-    inventory: u32,               // Total across all products
+/// Values the rights for a set of products, aggregating their demand and costs
+pub struct OptionValuation<'a> {
+    product_ids: Vec<ProductId>,      // Products covered by this valuation
+    current_order_quantity: u32,  // Total across products in scope
+    inventory: u32,               // Total across products in scope
     current_month: usize,
     remaining_months: usize,
-    params: SimulationParams,
-    pair: SupplierPair,
+    lead_time_months: usize,      // Lead time of the surge supplier delivering the change
+    max_rights_valued: usize,
+    max_periods_valued: usize,
+    payoff_model: PayoffModel,
+    remaining_fees: &'a [f64],    // Fee for each exercise right still available, in order
+    forecast_mean: f64,           // Monthly demand forecast for the products in scope
+    volatility: f64,              // Coefficient of variation driving the lattice moves
+    state: &'a OptionState,
+    params: &'a SimulationParams,
+    pair: &'a SupplierPair,
 }
 
-impl OptionValuation {
-    /// Create a new option valuation instance
-    pub fn new(
-        current_order_quantity: u32,
-        inventory: u32,
-        current_month: usize,
-        params: SimulationParams,
-        pair: SupplierPair,
-    ) -> Self {
-        let remaining_months = 8 - current_month; // 8 months total (May=0 to December=7)
-        OptionValuation {
+impl<'a> OptionValuation<'a> {
+    /// Value the products in `config` at a decision state
+    /// The horizon runs to the end of the configured season, changes are delivered by the pair's
+    /// surge supplier, and the state's observed demand calibrates the lattice when
+    /// `calibrate_to_observed_demand` is set
+    pub fn from_state(
+        state: &'a OptionState,
+        params: &'a SimulationParams,
+        pair: &'a SupplierPair,
+        config: &OptionsConfig,
+    ) -> Result<Self, OptionsError> {
+        let remaining_months = params.season_months.checked_sub(state.month)
+            .filter(|&months| months > 0)
+            .ok_or(OptionsError::MonthOutOfSeason { month: state.month, season_months: params.season_months })?;
+
+        let in_scope = |product_id: &ProductId| config.product_ids.contains(product_id);
+        let current_order_quantity = config.product_ids.iter()
+            .map(|&id| state.current_order.base_quantity_for(id) + state.current_order.surge_quantity_for(id))
+            .sum();
+        let inventory = state.inventories.iter()
+            .filter(|(product_id, _)| in_scope(product_id))
+            .map(|(_, units)| units)
+            .sum();
+
+        let mut valuation = OptionValuation {
+            product_ids: config.product_ids.clone(),
             current_order_quantity,
             inventory,
-            current_month,
+            current_month: state.month,
             remaining_months,
+            lead_time_months: pair.surge_supplier.lead_time_months,
+            max_rights_valued: config.max_rights_valued,
+            max_periods_valued: config.max_periods_valued,
+            payoff_model: config.payoff_model,
+            remaining_fees: params.order_change_fees.get(state.exercises_used..).unwrap_or_default(),
+            forecast_mean: 0.0,
+            volatility: 0.0,
+            state,
             params,
             pair,
+        };
+        valuation.forecast_mean = config.product_ids.iter().map(|&id| valuation.updated_mean_demand(id)).sum();
+        valuation.volatility = valuation.calibrated_volatility();
+        Ok(valuation)
+    }
+
+    /// Products covered by this valuation
+    fn products_in_scope(&self) -> impl Iterator<Item = &Product> {
+        self.params.products.iter().filter(|p| self.product_ids.contains(&p.id))
+    }
+
+    /// Average a per-product value over the products in scope
+    fn average_over_products(&self, value: impl Fn(&Product) -> f64) -> f64 {
+        let values: Vec<f64> = self.products_in_scope().map(value).collect();
+        if values.is_empty() {
+            return 0.0;
         }
+        values.iter().sum::<f64>() / values.len() as f64
     }
 
-    /// Get aggregate demand parameters (sum across products)
+    /// Get aggregate demand parameters (sum across products in scope)
     fn get_aggregate_demand_params(&self) -> (f64, f64) {
-        let total_mean: f64 = self.params.demand_params.iter()
-            .map(|dp| dp.mean_demand)
-            .sum();
-        let total_std_dev: f64 = self.params.demand_params.iter()
-            .map(|dp| dp.std_dev_demand)
-            .sum();
-        (total_mean, total_std_dev)
+        let in_scope = self.params.demand_params.iter()
+            .filter(|dp| self.product_ids.contains(&dp.product_id));
+        in_scope.fold((0.0, 0.0), |(mean, std_dev), dp| {
+            (mean + dp.mean_demand, std_dev + dp.std_dev_demand)
+        })
     }
 
-    /// Get weighted average selling price
-    fn get_avg_selling_price(&self) -> f64 {
-        if self.params.products.is_empty() {
-            return 0.0;
+    /// Monthly demand forecast for a product, updated with the months observed so far
+    /// The prior forecast counts as `PRIOR_WEIGHT_MONTHS` months of evidence
+    fn updated_mean_demand(&self, product_id: ProductId) -> f64 {
+        let prior_mean = self.params.get_demand_params(product_id).map(|dp| dp.mean_demand).unwrap_or(0.0);
+        if !self.params.calibrate_to_observed_demand {
+            return prior_mean;
         }
-        let total: f64 = self.params.products.iter()
-            .map(|p| p.selling_price)
-            .sum();
-        total / self.params.products.len() as f64
+        let history = self.state.observed_demand.get(&product_id).map(Vec::as_slice).unwrap_or_default();
+        let observed_total: f64 = history.iter().map(|&demand| demand as f64).sum();
+        (PRIOR_WEIGHT_MONTHS * prior_mean + observed_total) / (PRIOR_WEIGHT_MONTHS + history.len() as f64)
     }
 
-    /// Get weighted average holding cost
-    fn get_avg_holding_cost(&self) -> f64 {
-        if self.params.products.is_empty() {
-            return 0.0;
+    /// Coefficient of variation of aggregate demand for the lattice
+    /// The prior CV is pooled with the realized forecast error of the observed months, then
+    /// narrowed by the share of information still to come, so the tree tightens as actuals accrue
+    fn calibrated_volatility(&self) -> f64 {
+        let (mean_demand, std_dev_demand) = self.get_aggregate_demand_params();
+        let prior_cv = std_dev_demand / mean_demand;
+        if !self.params.calibrate_to_observed_demand {
+            return prior_cv;
         }
-        let total: f64 = self.params.products.iter()
-            .map(|p| p.monthly_holding_cost)
-            .sum();
-        total / self.params.products.len() as f64
+
+        // Sum the products in scope month by month
+        let mut aggregate_history: Vec<f64> = Vec::new();
+        for product_id in &self.product_ids {
+            let history = self.state.observed_demand.get(product_id).map(Vec::as_slice).unwrap_or_default();
+            if aggregate_history.len() < history.len() {
+                aggregate_history.resize(history.len(), 0.0);
+            }
+            for (total, demand) in aggregate_history.iter_mut().zip(history) {
+                *total += *demand as f64;
+            }
+        }
+
+        // Errors are measured against the forecast, so a biased forecast reads as extra uncertainty
+        let observed_months = aggregate_history.len() as f64;
+        let squared_errors: f64 = aggregate_history.iter().map(|d| (d - mean_demand).powi(2)).sum();
+        let pooled_variance = (PRIOR_WEIGHT_MONTHS * std_dev_demand.powi(2) + squared_errors)
+            / (PRIOR_WEIGHT_MONTHS + observed_months);
+        let information_left = PRIOR_WEIGHT_MONTHS / (PRIOR_WEIGHT_MONTHS + observed_months);
+
+        pooled_variance.sqrt() / mean_demand * information_left.sqrt()
+    }
+
+    /// Get average selling price
+    fn get_avg_selling_price(&self) -> f64 {
+        self.average_over_products(|p| p.selling_price)
+    }
+
+    /// Get average holding cost
+    fn get_avg_holding_cost(&self) -> f64 {
+        self.average_over_products(|p| p.monthly_holding_cost)
     }
 
-    /// Get average surge unit cost across products
+    /// Get average surge unit cost
     fn get_avg_surge_unit_cost(&self) -> f64 {
-        if self.params.products.is_empty() {
-            return 0.0;
+        self.average_over_products(|p| self.pair.surge_supplier.unit_costs.get(&p.id).copied().unwrap_or(0.0))
+    }
+
+    /// Get average base unit cost
+    fn get_avg_base_unit_cost(&self) -> f64 {
+        self.average_over_products(|p| self.pair.base_supplier.unit_costs.get(&p.id).copied().unwrap_or(0.0))
+    }
+
+    /// Get average liquidation price
+    fn get_avg_liquidation_price(&self) -> f64 {
+        self.average_over_products(|p| p.liquidation_price)
+    }
+
+    /// Cost of the marginal unit a change adds or removes: surge units are bought at the surge
+    /// price, while cancelled base units save their cost less the cancellation fee
+    fn marginal_unit_cost(&self, direction: ExerciseDirection) -> f64 {
+        match direction {
+            ExerciseDirection::Increase => self.get_avg_surge_unit_cost(),
+            ExerciseDirection::Decrease => self.get_avg_base_unit_cost() - self.params.cancellation_fee_per_unit,
         }
-        let total: f64 = self.params.products.iter()
-            .map(|p| self.pair.surge_supplier.unit_costs.get(&p.id).copied().unwrap_or(0.0))
-            .sum();
-        total / self.params.products.len() as f64
     }
 
-    /// Calculate the value of the option to change orders using recursive binomial method
-    pub fn value_option(&self) -> f64 {
-        if self.remaining_months == 0 {
-            return 0.0; // No time value left
+    /// Underage and overage cost per unit over a horizon of `months` deliveries
+    /// A unit short loses its margin; a unit left over is liquidated at a loss after being held
+    /// for half the horizon on average
+    fn underage_overage_costs(&self, direction: ExerciseDirection, months: usize) -> (f64, f64) {
+        let unit_cost = self.marginal_unit_cost(direction);
+        let underage_cost = (self.get_avg_selling_price() - unit_cost).max(0.0);
+        let overage_cost = unit_cost - self.get_avg_liquidation_price()
+            + self.get_avg_holding_cost() * months as f64 / 2.0;
+        (underage_cost, overage_cost.max(0.0))
+    }
+
+    /// Safety factor for the newsvendor target position
+    fn target_z_score(&self, direction: ExerciseDirection, months: usize) -> f64 {
+        match self.payoff_model {
+            PayoffModel::NormalLoss => {
+                let (underage_cost, overage_cost) = self.underage_overage_costs(direction, months);
+                if underage_cost + overage_cost <= 0.0 {
+                    return 0.0;
+                }
+                inverse_standard_normal_cdf(underage_cost / (underage_cost + overage_cost))
+            }
+            PayoffModel::ProjectedSales => {
+                let overage_cost = self.get_avg_holding_cost();
+                let underage_cost = self.get_avg_selling_price() - self.get_avg_surge_unit_cost();
+
+                // Critical fractile (Newsvendor target service level)
+                let critical_fractile = underage_cost / (underage_cost + overage_cost);
+
+                if critical_fractile > 0.5 { 1.645 } else { 0.0 }
+            }
+        }
+    }
+
+    /// Value the rights to change orders using recursive binomial method
+    pub fn value_option(&self) -> OptionValue {
+        if self.remaining_months <= self.lead_time_months {
+            // No time value left: a change would not arrive before the season ends
+            return OptionValue { value: 0.0, recommended_action: None };
+        }
+
+        let value = |direction| {
+            self.binomial_value_recursive(direction, 0, 0, self.inventory, self.current_order_quantity, 0)
+        };
+        // Without surge capacity for the products, such as from a single source, nothing can be added
+        let can_increase = self.product_ids.iter().any(|&product_id| self.pair.surge_capacity_for(product_id) > 0);
+        let increase = if can_increase { value(ExerciseDirection::Increase) } else { 0.0 };
+        let decrease = value(ExerciseDirection::Decrease);
+
+        let (value, direction) = if increase >= decrease {
+            (increase, ExerciseDirection::Increase)
+        } else {
+            (decrease, ExerciseDirection::Decrease)
+        };
+        OptionValue {
+            value,
+            recommended_action: (value > 0.0).then_some(direction),
+        }
+    }
+
+    /// Value of capacity to raise the monthly deliveries of the products in scope from `delivered`
+    /// by up to `headroom` units, every month from `first_month` to the season's end, along with
+    /// the raise worth making
+    /// The raise re-optimizes the delivery towards the newsvendor target at the calibrated
+    /// forecast, stock carried forward at `delivered` a month until then; its value is the fall in
+    /// expected mismatch cost with the extra units bought at the surge price
+    pub fn capacity_raise_value(&self, delivered: u32, headroom: u32, first_month: usize) -> (f64, u32) {
+        let (prior_mean, prior_std_dev) = self.get_aggregate_demand_params();
+        let season_end = self.current_month + self.remaining_months;
+        let months = season_end.saturating_sub(first_month);
+        if months == 0 || headroom == 0 || prior_mean <= 0.0 {
+            return (0.0, 0);
+        }
+        let std_dev = self.forecast_mean * (prior_std_dev / prior_mean);
+
+        // Deliveries before the extra capacity arrives come at the current rate
+        let mut inventory = self.inventory as f64;
+        for _ in self.current_month + 1..first_month {
+            inventory = (inventory + delivered as f64 - self.forecast_mean).max(0.0);
+        }
+
+        let target = self.target_delivery(ExerciseDirection::Increase, self.forecast_mean, std_dev, inventory, months);
+        let raise = (target - delivered as f64).clamp(0.0, headroom as f64).floor();
+        if raise <= 0.0 {
+            return (0.0, 0);
+        }
+        let horizon_mean = self.forecast_mean * months as f64;
+        let horizon_std_dev = std_dev * (months as f64).sqrt();
+        let position = |monthly_delivery: f64| inventory + monthly_delivery * months as f64;
+        let value = self.expected_mismatch_cost(ExerciseDirection::Increase, position(delivered as f64), horizon_mean, horizon_std_dev, months)
+            - self.expected_mismatch_cost(ExerciseDirection::Increase, position(delivered as f64 + raise), horizon_mean, horizon_std_dev, months);
+        (value, raise as u32)
+    }
+
+    /// Apply the downward option to the products in scope of `current_order`
+    /// Each product's delivery is cut to its target over the remaining deliveries net of inventory
+    /// on hand, cancelling surge units before base units
+    pub fn decreased_order(&self, current_order: &MonthlyOrder) -> MonthlyOrder {
+        let months = self.months_affected(0);
+        let mut new_order = current_order.clone();
+
+        for product in self.products_in_scope() {
+            let mean = self.updated_mean_demand(product.id);
+            let std_dev = self.params.get_demand_params(product.id).map(|dp| dp.std_dev_demand).unwrap_or(0.0);
+            let inventory = self.state.inventories.get(&product.id).copied().unwrap_or(0) as f64;
+            let target = self.target_delivery(ExerciseDirection::Decrease, mean, std_dev, inventory, months) as u32;
+
+            let base_qty = current_order.base_quantity_for(product.id);
+            let surge_qty = current_order.surge_quantity_for(product.id);
+            let mut reduction = (base_qty + surge_qty).saturating_sub(target);
+
+            let surge_cut = cmp::min(reduction, surge_qty);
+            reduction -= surge_cut;
+            let base_cut = cmp::min(reduction, base_qty);
+
+            new_order.set_surge_quantity(product.id, surge_qty - surge_cut);
+            new_order.set_base_quantity(product.id, base_qty - base_cut);
+        }
+
+        new_order
+    }
+
+    /// Apply the upward option to the products in scope of `current_order`
+    /// Each product's surge order is raised so its delivery reaches its target over the remaining
+    /// deliveries net of inventory on hand; capacity is left for the caller to check
+    pub fn increased_order(&self, current_order: &MonthlyOrder) -> MonthlyOrder {
+        let months = self.months_affected(0);
+        let mut new_order = current_order.clone();
+
+        for product in self.products_in_scope() {
+            let mean = self.updated_mean_demand(product.id);
+            let std_dev = self.params.get_demand_params(product.id).map(|dp| dp.std_dev_demand).unwrap_or(0.0);
+            let inventory = self.state.inventories.get(&product.id).copied().unwrap_or(0) as f64;
+            let target = self.target_delivery(ExerciseDirection::Increase, mean, std_dev, inventory, months) as u32;
+
+            let base_qty = current_order.base_quantity_for(product.id);
+            let surge_qty = current_order.surge_quantity_for(product.id);
+            new_order.set_surge_quantity(product.id, cmp::max(surge_qty, target.saturating_sub(base_qty)));
         }
 
-        self.binomial_value_recursive(0, 0, self.inventory)
+        new_order
     }
 
-    /// Recursive binomial tree valuation
+    /// Recursive binomial tree valuation for one exercisable right
     /// period: current period (0 = current month)
     /// cumulative_uplifts: net up movements minus down movements
     /// inventory: current inventory state (aggregate across products)
-    fn binomial_value_recursive(&self, period: usize, cumulative_uplifts: i32, inventory: u32) -> f64 {
-        // Base case: reached end of evaluation period
-        if period >= self.remaining_months {
+    /// order_quantity: monthly delivery in force at this node
+    /// right: index of the next right to exercise in the remaining fee schedule
+    fn binomial_value_recursive(
+        &self,
+        direction: ExerciseDirection,
+        period: usize,
+        cumulative_uplifts: i32,
+        inventory: u32,
+        order_quantity: u32,
+        right: usize,
+    ) -> f64 {
+        // Base case: reached end of evaluation period or ran out of rights
+        if period >= self.remaining_months.min(self.max_periods_valued) || right >= self.remaining_fees.len().min(self.max_rights_valued) {
             return 0.0;
         }
 
-        let (mean_demand, std_dev_demand) = self.get_aggregate_demand_params();
+        let mean_demand = self.forecast_mean;
 
         // Calculate up and down demand scenarios using binomial model
-        let cv = std_dev_demand / mean_demand;
-        let u = cv.exp();
+        let u = self.volatility.exp();
         let d = 1.0 / u;
 
-        // Demand at this node in the binomial tree
+        // Demand realized on the move up or down from this node
         let demand_up = (mean_demand * u.powi(cumulative_uplifts + 1)) as u32;
-        let demand_down = (mean_demand * d.powi(-(cumulative_uplifts + 1))) as u32;
+        let demand_down = (mean_demand * u.powi(cumulative_uplifts - 1)) as u32;
 
-        // Calculate payoff if we exercise the option NOW (change orders)
-        let exercise_payoff = self.calculate_exercise_payoff(inventory, cumulative_uplifts, u);
+        // Risk-neutral probability
+        let p = (1.0 - d) / (u - d);
 
-        // Calculate continuation value (don't exercise, keep current order)
-        let continuation_up = self.binomial_value_recursive(
-            period + 1,
-            cumulative_uplifts + 1,
-            self.update_inventory(inventory, demand_up),
-        );
+        // Expected value of the children under a given order and right
+        let expected_children = |order_quantity: u32, right: usize| {
+            let up = self.binomial_value_recursive(
+                direction,
+                period + 1,
+                cumulative_uplifts + 1,
+                self.update_inventory(inventory, order_quantity, demand_up),
+                order_quantity,
+                right,
+            );
+            let down = self.binomial_value_recursive(
+                direction,
+                period + 1,
+                cumulative_uplifts - 1,
+                self.update_inventory(inventory, order_quantity, demand_down),
+                order_quantity,
+                right,
+            );
+            p * up + (1.0 - p) * down
+        };
 
-        let continuation_down = self.binomial_value_recursive(
-            period + 1,
-            cumulative_uplifts - 1,
-            self.update_inventory(inventory, demand_down),
-        );
+        // Calculate payoff if we exercise the option NOW (change orders)
+        let (benefit, new_order_quantity) =
+            self.calculate_exercise_payoff(direction, period, inventory, order_quantity, cumulative_uplifts, u);
+        let fee = self.remaining_fees[right]
+            + self.params.order_change_fee_per_product * self.product_ids.len() as f64;
+        let mut exercise_value = benefit - fee;
+        if benefit > 0.0 {
+            // Later rights remain available against the changed order
+            exercise_value += expected_children(new_order_quantity, right + 1);
+        }
 
-        // Risk-neutral probability
-        let p = (1.0 - d) / (u - d);
-        let continuation_value = p * continuation_up + (1.0 - p) * continuation_down;
+        // Calculate continuation value (don't exercise, keep current order and right)
+        let continuation_value = expected_children(order_quantity, right);
 
         // American option: take maximum of exercising now or waiting
-        exercise_payoff.max(continuation_value)
+        exercise_value.max(continuation_value)
     }
 
-    /// Calculate the payoff from exercising the option (changing the order)
-    fn calculate_exercise_payoff(&self, _current_inventory: u32, cumulative_uplifts: i32, u: f64) -> f64 {
-        let (mean_demand, std_dev_demand) = self.get_aggregate_demand_params();
-        
-        // Forecasted demand at this node
-        let forecast_demand = mean_demand * u.powi(cumulative_uplifts);
-        
-        // Cost parameters (using aggregate values)
-        let overage_cost = self.get_avg_holding_cost();
-        let underage_cost = self.get_avg_selling_price() - self.get_avg_surge_unit_cost();
-        
-        // Critical fractile (Newsvendor target service level)
-        let critical_fractile = underage_cost / (underage_cost + overage_cost);
-        
-        // Calculate optimal Q* for this node's forecast
-        let node_std_dev = forecast_demand * (std_dev_demand / mean_demand);
-        
-        let z_score = if critical_fractile > 0.5 { 1.645 } else { 0.0 };
-        
-        let optimal_q = forecast_demand + z_score * node_std_dev;
-        
-        let new_q = optimal_q;
-        let old_q = self.current_order_quantity as f64;
-        
-        let margin = self.get_avg_selling_price() - self.get_avg_surge_unit_cost();
+    /// Number of monthly deliveries a change decided at the given lattice period would affect
+    /// The simulation applies a change no earlier than the month after the decision,
+    /// and not before the surge lead time has elapsed
+    fn months_affected(&self, period: usize) -> usize {
+        let season_end = self.current_month + self.remaining_months;
+        let decision_month = self.current_month + period;
+        let first_changed_delivery = decision_month + cmp::max(self.lead_time_months, 1);
+        season_end.saturating_sub(first_changed_delivery)
+    }
+
+    /// Monthly delivery covering the remaining horizon's demand at the target service level,
+    /// net of inventory already on hand
+    fn target_delivery(
+        &self,
+        direction: ExerciseDirection,
+        monthly_demand: f64,
+        monthly_std_dev: f64,
+        inventory: f64,
+        months: usize,
+    ) -> f64 {
+        if months == 0 {
+            return 0.0;
+        }
+        // Demand uncertainty pools over the horizon, so safety stock grows with its square root
+        let horizon_target = monthly_demand * months as f64
+            + self.target_z_score(direction, months) * monthly_std_dev * (months as f64).sqrt();
+        ((horizon_target - inventory) / months as f64).max(0.0)
+    }
+
+    /// Expected overage and underage cost of stocking `position` units against horizon demand
+    /// with the given mean and standard deviation: Co·(Q − μ) + (Cu + Co)·σ·L((Q − μ)/σ)
+    fn expected_mismatch_cost(&self, direction: ExerciseDirection, position: f64, mean: f64, std_dev: f64, months: usize) -> f64 {
+        let (underage_cost, overage_cost) = self.underage_overage_costs(direction, months);
+        if std_dev <= 0.0 {
+            return overage_cost * (position - mean).max(0.0) + underage_cost * (mean - position).max(0.0);
+        }
+        let z = (position - mean) / std_dev;
+        overage_cost * (position - mean) + (underage_cost + overage_cost) * std_dev * normal_loss(z)
+    }
+
+    /// Project sales revenue less holding cost, plus end-of-season liquidation, over the affected
+    /// months when every month delivers the same quantity against expected demand
+    fn projected_sales_value(&self, monthly_delivery: f64, starting_inventory: f64, monthly_demand: f64, months: usize) -> f64 {
+        let mut inventory = starting_inventory;
+        let mut value = 0.0;
+        for _ in 0..months {
+            let available = inventory + monthly_delivery;
+            let sold = available.min(monthly_demand);
+            inventory = available - sold;
+            // Holding accrues on every month's ending stock, margin only on units actually sold
+            value += sold * self.get_avg_selling_price() - inventory * self.get_avg_holding_cost();
+        }
+        value + inventory * self.get_avg_liquidation_price()
+    }
+
+    /// Calculate the benefit from exercising one right (changing the order) before fees,
+    /// along with the monthly delivery the change would put in force
+    /// The benefit covers every remaining delivery the change affects, not just the next month
+    fn calculate_exercise_payoff(
+        &self,
+        direction: ExerciseDirection,
+        period: usize,
+        current_inventory: u32,
+        order_quantity: u32,
+        cumulative_uplifts: i32,
+        u: f64,
+    ) -> (f64, u32) {
+        let (prior_mean, prior_std_dev) = self.get_aggregate_demand_params();
         
-        // Improvement in filled demand (underage reduction)
-        let demand_captured_improvement = if new_q > old_q {
-             (new_q - old_q).min(forecast_demand)
-        } else {
-             0.0
+        // Forecasted demand at this node, keeping the prior CV for safety stock
+        let forecast_demand = self.forecast_mean * u.powi(cumulative_uplifts);
+        let node_std_dev = forecast_demand * (prior_std_dev / prior_mean);
+
+        let old_q = order_quantity as f64;
+        let months = self.months_affected(period);
+
+        // Deliveries before the change lands still arrive at the current rate
+        let lead_gap = cmp::max(self.lead_time_months, 1) - 1;
+        let mut inventory = current_inventory as f64;
+        for _ in 0..lead_gap {
+            inventory = (inventory + old_q - forecast_demand).max(0.0);
+        }
+
+        let new_q = self.target_delivery(direction, forecast_demand, node_std_dev, inventory, months);
+        let changes_order = match direction {
+            ExerciseDirection::Increase => new_q > old_q,
+            ExerciseDirection::Decrease => new_q < old_q,
         };
+        if !changes_order {
+            return (0.0, new_q as u32);
+        }
+
+        if self.payoff_model == PayoffModel::NormalLoss {
+            // Stock positions and demand over the horizon the change affects
+            let horizon_mean = forecast_demand * months as f64;
+            let horizon_std_dev = node_std_dev * (months as f64).sqrt();
+            let position = |monthly_delivery: f64| inventory + monthly_delivery * months as f64;
+            let benefit = self.expected_mismatch_cost(direction, position(old_q), horizon_mean, horizon_std_dev, months)
+                - self.expected_mismatch_cost(direction, position(new_q), horizon_mean, horizon_std_dev, months);
+            return (benefit, new_q as u32);
+        }
+
+        let current_value = self.projected_sales_value(old_q, inventory, forecast_demand, months);
+        let changed_value = self.projected_sales_value(new_q, inventory, forecast_demand, months);
         
-        let holding_savings = if new_q < old_q {
-            (old_q - new_q) * self.get_avg_holding_cost()
+        let benefit = match direction {
+            ExerciseDirection::Increase => {
+                // Extra units are bought from the surge supplier for every affected month
+                let extra_units = (new_q - old_q) * months as f64;
+                changed_value - current_value - extra_units * self.get_avg_surge_unit_cost()
+            }
+            ExerciseDirection::Decrease => {
+                // Cancelled units save their purchase cost but incur the cancellation fee
+                let cancelled_units = (old_q - new_q) * months as f64;
+                changed_value - current_value + cancelled_units * self.marginal_unit_cost(direction)
+            }
+        };
+
+        (benefit, new_q as u32)
+    }
+
+    /// Update inventory after demand realization
+    fn update_inventory(&self, inventory: u32, order_quantity: u32, demand: u32) -> u32 {
+        // Nodes far up a volatile lattice order more than a u32 counts
+        inventory.saturating_add(order_quantity).saturating_sub(demand)
+    }
+}
+
+/// Smallest average monthly demand, observed in every month from May through `month_idx`, at which
+/// adding surge units to `plan` is worth the first order change fee, or None when no level triggers
+/// Each product sees the level in proportion to its forecast, and the observations feed the
+/// valuation exactly like in-season actuals. Only levels from the lower of the planned deliveries
+/// and the forecast upwards are searched: below that, stock builds up and a cut is the question.
+pub fn exercise_boundary(params: &SimulationParams, pair: &SupplierPair, plan: &MonthlyOrder, month_idx: usize) -> Option<u32> {
+    if month_idx >= params.season_months {
+        return None;
+    }
+    let fee = params.next_order_change_fee(0)?;
+    let product_ids: Vec<ProductId> = params.products.iter().map(|p| p.id).collect();
+    let config = OptionsConfig::for_products(&product_ids);
+    let applicable_fee = fee + params.order_change_fee_per_product * product_ids.len() as f64;
+
+    let triggers = |level: u32| {
+        let state = hypothetical_state(params, plan, month_idx, level);
+        let option_value = OptionValuation::from_state(&state, params, pair, &config)
+            .expect("boundary month lies within the season")
+            .value_option();
+        option_value.recommended_action == Some(ExerciseDirection::Increase) && option_value.value > applicable_fee
+    };
+
+    // Levels beyond three times the forecast are not a planning scenario
+    let total_forecast: f64 = params.demand_params.iter().map(|dp| dp.mean_demand).sum();
+    let planned_delivery = plan.total_base_quantity() + plan.total_surge_quantity();
+    let mut low = cmp::min(planned_delivery, total_forecast as u32);
+    let mut high = (3.0 * total_forecast) as u32;
+    if !triggers(high) {
+        return None;
+    }
+    if triggers(low) {
+        return Some(low);
+    }
+
+    // Bisect, keeping `low` below the boundary and `high` at or above it
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if triggers(mid) {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+    Some(high)
+}
+
+/// Decision state after `plan` has delivered against `level` units of demand in every month
+/// through `month_idx`, split across products in proportion to their forecasts
+fn hypothetical_state(params: &SimulationParams, plan: &MonthlyOrder, month_idx: usize, level: u32) -> OptionState {
+    let total_forecast: f64 = params.demand_params.iter().map(|dp| dp.mean_demand).sum();
+    let mut inventories = HashMap::new();
+    let mut observed_demand = HashMap::new();
+
+    for dp in &params.demand_params {
+        let demand = if total_forecast > 0.0 {
+            (level as f64 * dp.mean_demand / total_forecast) as u32
         } else {
-            0.0
+            0
         };
+        let delivery = plan.base_quantity_for(dp.product_id) + plan.surge_quantity_for(dp.product_id);
+        let mut inventory: u32 = 0;
+        for _ in 0..=month_idx {
+            inventory = (inventory + delivery).saturating_sub(demand);
+        }
+        inventories.insert(dp.product_id, inventory);
+        observed_demand.insert(dp.product_id, vec![demand; month_idx + 1]);
+    }
+
+    OptionState {
+        month: month_idx,
+        inventories,
+        current_order: plan.clone(),
+        observed_demand,
+        exercises_used: 0,
+    }
+}
 
-        // Benefit = (Extra Margin from more sales) + (Holding Cost Saved)
-        let benefit = (demand_captured_improvement * margin) + holding_savings;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::SupplierId;
+    use crate::demand::seed_demand_rng;
+    use crate::models::{CapacityPolicy, ProductDemandParams, ProductOrder, Supplier, TimeStep};
+    use crate::simulation::{run_monthly_simulation_internal, DemandSource};
 
-        // Net benefit after paying the fixed fee
-        benefit - self.params.order_change_fee
+    /// One product ordered at 8k per month against a 10k forecast that proves accurate
+    fn under_ordered_scenario() -> (SimulationParams, SupplierPair, MonthlyOrder) {
+        let params = SimulationParams {
+            products: vec![Product {
+                id: ProductId(0),
+                name: "Widget".to_string(),
+                selling_price: 100.0,
+                liquidation_price: 60.0,
+                monthly_holding_cost: 2.0,
+                goodwill_penalty: None,
+            }],
+            demand_params: vec![ProductDemandParams {
+                product_id: ProductId(0),
+                mean_demand: 10_000.0,
+                std_dev_demand: 500.0,
+                actual_mean_demand: 10_000.0,
+                actual_std_dev_demand: 500.0,
+            }],
+            order_change_fees: vec![60_000.0; 8],
+            order_change_fee_per_product: 0.0,
+            cancellation_fee_per_unit: 5.0,
+            calibrate_to_observed_demand: true,
+            season_months: 8,
+            regional_disruptions: Vec::new(),
+            capacity_policy: CapacityPolicy::Clamp,
+            expansion_offers: Vec::new(),
+            opening_inventory: Vec::new(),
+            onboarded_suppliers: Vec::new(),
+            carry_over_stock: false,
+            time_step: TimeStep::Monthly,
+            cost_of_capital_annual: 0.0,
+        };
+        let supplier = |id: u32, lead_time_months: usize, unit_cost: f64| Supplier {
+            id: SupplierId(id),
+            name: format!("Supplier {}", id),
+            fixed_capacity: 20_000,
+            product_capacity: None,
+            capacity_groups: Vec::new(),
+            soft_capacity_tiers: Vec::new(),
+            lead_time_months,
+            ramp_up_months: Vec::new(),
+            unit_costs: HashMap::from([(ProductId(0), unit_cost)]),
+            setup_cost: 0.0,
+            reservation_fee: None,
+            monthly_flex: None,
+            disrupted_months: Vec::new(),
+            region: None,
+            risk_class: None,
+        };
+        let pair = SupplierPair {
+            base_supplier: supplier(0, 3, 75.0),
+            surge_supplier: supplier(1, 0, 80.0),
+            backup_supplier: None,
+            pooled_suppliers: Vec::new(),
+        };
+        let order = MonthlyOrder::new(
+            vec![ProductOrder { product_id: ProductId(0), quantity: 8_000 }],
+            vec![ProductOrder { product_id: ProductId(0), quantity: 0 }],
+        ).unwrap();
+        (params, pair, order)
     }
 
-    /// Update inventory after demand realization
-    fn update_inventory(&self, inventory: u32, demand: u32) -> u32 {
-        let incoming = self.current_order_quantity;
-        let new_inventory = inventory + incoming;
-        let sold = cmp::min(new_inventory, demand);
-        (new_inventory - sold).max(0)
+    /// Decision state for the single product 0 with nothing exercised yet
+    fn decision_state(month: usize, inventory: u32, order_quantity: u32, observed_demand: Vec<u32>) -> OptionState {
+        OptionState {
+            month,
+            inventories: HashMap::from([(ProductId(0), inventory)]),
+            current_order: MonthlyOrder::new(
+                vec![ProductOrder { product_id: ProductId(0), quantity: order_quantity }],
+                vec![ProductOrder { product_id: ProductId(0), quantity: 0 }],
+            ).unwrap(),
+            observed_demand: HashMap::from([(ProductId(0), observed_demand)]),
+            exercises_used: 0,
+        }
+    }
+
+    #[test]
+    fn test_golden_values_one_decision_before_season_end() {
+        // In November with an immediate surge supplier a change affects December only, so the
+        // value is the exercise payoff less the fee. The newsvendor target is
+        // 10,000 + 1.645 * 500 = 10,822.5 units net of inventory on hand.
+        let (mut params, pair, _) = under_ordered_scenario();
+        params.order_change_fees = vec![10_000.0];
+        let config = OptionsConfig {
+            payoff_model: PayoffModel::ProjectedSales,
+            ..OptionsConfig::for_products(&[ProductId(0)])
+        };
+
+        let cases = [
+            // Topping 8,000 up to target: margin on 2,000 more sales, 822.5 units held then
+            // liquidated, 2,822.5 surge units at 80
+            (0, 8_000, 11_905.0, Some(ExerciseDirection::Increase)),
+            // Cutting 10,000 to 5,822.5 on 5,000 in stock: 292,425 saved on cancelled units
+            // against 242,295 lost in holding and liquidation value
+            (5_000, 10_000, 40_130.0, Some(ExerciseDirection::Decrease)),
+            // Already on target: the half-unit change never covers the fee
+            (0, 10_823, 0.0, None),
+        ];
+        for (inventory, order_quantity, expected_value, expected_action) in cases {
+            let state = decision_state(6, inventory, order_quantity, vec![10_000; 7]);
+            let option_value = OptionValuation::from_state(&state, &params, &pair, &config).unwrap().value_option();
+            assert!((option_value.value - expected_value).abs() < 1e-6, "{:?} for inventory {}", option_value, inventory);
+            assert_eq!(option_value.recommended_action, expected_action);
+        }
+
+        // A per-product fee comes straight off the payoff
+        params.order_change_fee_per_product = 5_000.0;
+        let state = decision_state(6, 0, 8_000, vec![10_000; 7]);
+        let option_value = OptionValuation::from_state(&state, &params, &pair, &config).unwrap().value_option();
+        assert!((option_value.value - 6_905.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_horizon_payoff_exercises_where_single_month_heuristic_declines() {
+        let (params, pair, order) = under_ordered_scenario();
+        let fee = params.order_change_fees[0];
+
+        // The single-month heuristic only credited the margin on one month's shortfall
+        let critical_q = 10_000.0 + 1.645 * 500.0;
+        let single_month_benefit = (critical_q - 8_000.0) * (100.0 - 80.0);
+        assert!(single_month_benefit < fee);
+
+        let state = decision_state(0, 0, 8_000, Vec::new());
+        let option_value = OptionValuation::from_state(&state, &params, &pair, &OptionsConfig::for_products(&[ProductId(0)])).unwrap().value_option();
+        assert!(option_value.value > fee);
+        assert_eq!(option_value.recommended_action, Some(ExerciseDirection::Increase));
+
+        // Simulate both branches: never change the order versus exercising the option
+        let mean_profit = |enable_options: bool| {
+            (0..30)
+                .map(|seed| {
+                    seed_demand_rng(seed);
+                    run_monthly_simulation_internal(&params, &pair, &order, enable_options, false, DemandSource::Stochastic(None)).unwrap().1
+                })
+                .sum::<f64>() / 30.0
+        };
+        let gain_net_of_fees = mean_profit(true) - mean_profit(false);
+        assert!(gain_net_of_fees > 2.0 * fee, "exercising gained only {:.0}", gain_net_of_fees);
+    }
+
+    #[test]
+    fn test_no_value_or_fees_once_long_lead_change_cannot_land() {
+        let (params, mut pair, order) = under_ordered_scenario();
+        pair.surge_supplier.lead_time_months = 2;
+
+        // November and December changes would arrive after the season
+        for month in [6, 7] {
+            let state = decision_state(month, 0, 8_000, Vec::new());
+            let option_value = OptionValuation::from_state(&state, &params, &pair, &OptionsConfig::for_products(&[ProductId(0)])).unwrap().value_option();
+            assert_eq!(option_value, OptionValue { value: 0.0, recommended_action: None });
+        }
+
+        for seed in 0..1_000 {
+            seed_demand_rng(seed);
+            let (monthly_results, _) = run_monthly_simulation_internal(&params, &pair, &order, true, false, DemandSource::Stochastic(None)).unwrap();
+            for (month_idx, month) in monthly_results.iter().enumerate() {
+                if month_idx >= 6 {
+                    assert!(month.exercised_changes.is_empty());
+                }
+                // Every fee traces back to a decision taken one lead time earlier
+                if month.order_change_cost > 0.0 {
+                    assert!(!monthly_results[month_idx - 2].exercised_changes.is_empty());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_prohibitive_second_fee_delays_first_exercise() {
+        let (mut params, pair, order) = under_ordered_scenario();
+        params.demand_params[0].std_dev_demand = 2_000.0;
+        params.demand_params[0].actual_std_dev_demand = 2_000.0;
+        params.calibrate_to_observed_demand = false;
+        let fee = 135_000.0;
+
+        // Month of the first exercise under a fee schedule, or TOTAL_MONTHS if never
+        let first_exercise_months = |order_change_fees: Vec<f64>| {
+            let mut params = params.clone();
+            params.order_change_fees = order_change_fees;
+            (0..20)
+                .map(|seed| {
+                    seed_demand_rng(seed);
+                    let (monthly_results, _) = run_monthly_simulation_internal(&params, &pair, &order, true, false, DemandSource::Stochastic(None)).unwrap();
+                    monthly_results
+                        .iter()
+                        .position(|month| !month.exercised_changes.is_empty())
+                        .unwrap_or(8)
+                })
+                .collect::<Vec<_>>()
+        };
+        let repeatable = first_exercise_months(vec![fee; 8]);
+        let single_shot = first_exercise_months(vec![fee, 1e12]);
+
+        // Keeping a cheap follow-up change in reserve makes acting early worth the fee
+        assert!(repeatable.iter().zip(&single_shot).all(|(early, late)| early <= late));
+        assert!(repeatable.iter().sum::<usize>() < single_shot.iter().sum::<usize>());
+    }
+
+    /// Order sits at the 10k forecast, whose CV of 0.2 is the only reason to change it
+    fn volatile_on_plan_scenario() -> (SimulationParams, SupplierPair, MonthlyOrder) {
+        let (mut params, pair, mut order) = under_ordered_scenario();
+        params.demand_params[0].std_dev_demand = 2_000.0;
+        params.demand_params[0].actual_std_dev_demand = 2_000.0;
+        order.set_base_quantity(ProductId(0), 10_000);
+        (params, pair, order)
+    }
+
+    /// First exercise month for each seed, or 8 if the option is never exercised
+    fn first_exercise_months(params: &SimulationParams, pair: &SupplierPair, order: &MonthlyOrder, seeds: u64) -> Vec<usize> {
+        (0..seeds)
+            .map(|seed| {
+                seed_demand_rng(seed);
+                let (monthly_results, _) = run_monthly_simulation_internal(params, pair, order, true, true, DemandSource::Stochastic(None)).unwrap();
+                monthly_results
+                    .iter()
+                    .position(|month| !month.exercised_changes.is_empty())
+                    .unwrap_or(8)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_calibrated_volatility_shrinks_when_actuals_match_forecast() {
+        let (mut params, pair, order) = volatile_on_plan_scenario();
+        params.demand_params[0].actual_std_dev_demand = 0.0;
+        params.order_change_fees = vec![10_000.0; 8];
+
+        let option_values = |params: &SimulationParams| {
+            (0..7)
+                .map(|month| {
+                    let state = decision_state(month, 0, 10_000, vec![10_000; month + 1]);
+                    OptionValuation::from_state(&state, params, &pair, &OptionsConfig::for_products(&[ProductId(0)])).unwrap()
+                        .value_option()
+                        .value
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut static_params = params.clone();
+        static_params.calibrate_to_observed_demand = false;
+        let calibrated = option_values(&params);
+        let prior = option_values(&static_params);
+
+        assert!(calibrated[0] > 0.0);
+        assert!(calibrated.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(calibrated.iter().zip(&prior).all(|(calibrated, prior)| calibrated <= prior));
+
+        // Every month repeats the forecast, so once a plan 1,000 a month over it is cut back only
+        // the prior CV keeps later changes worth the fee
+        let mut over_ordered = order.clone();
+        over_ordered.set_base_quantity(ProductId(0), 11_000);
+        let exercise_months = |params: &SimulationParams| {
+            seed_demand_rng(0);
+            let (monthly_results, _) = run_monthly_simulation_internal(params, &pair, &over_ordered, true, true, DemandSource::Stochastic(None)).unwrap();
+            monthly_results
+                .iter()
+                .enumerate()
+                .filter(|(_, month)| !month.exercised_changes.is_empty())
+                .map(|(month_idx, _)| month_idx)
+                .collect::<Vec<_>>()
+        };
+        let calibrated_months = exercise_months(&params);
+        let prior_months = exercise_months(&static_params);
+        assert_eq!(calibrated_months.first(), Some(&0));
+        assert_eq!(prior_months.first(), Some(&0));
+        assert!(calibrated_months.iter().all(|&month_idx| month_idx < 3), "{:?}", calibrated_months);
+        assert!(prior_months.iter().any(|&month_idx| month_idx >= 3), "{:?}", prior_months);
+    }
+
+    #[test]
+    fn test_calibrated_volatility_exercises_earlier_on_surprising_actuals() {
+        let (mut params, pair, order) = volatile_on_plan_scenario();
+        params.demand_params[0].actual_mean_demand = 16_000.0;
+        params.order_change_fees = vec![100_000.0; 8];
+        let mut static_params = params.clone();
+        static_params.calibrate_to_observed_demand = false;
+
+        let calibrated = first_exercise_months(&params, &pair, &order, 40);
+        let prior = first_exercise_months(&static_params, &pair, &order, 40);
+
+        assert!(calibrated.iter().zip(&prior).all(|(calibrated, prior)| calibrated <= prior));
+        assert!(calibrated.iter().sum::<usize>() < prior.iter().sum::<usize>());
+    }
+
+    #[test]
+    fn test_exercise_boundary_rises_with_fewer_deliveries_left() {
+        let (mut params, mut pair, order) = volatile_on_plan_scenario();
+        params.order_change_fees = vec![100_000.0];
+        pair.surge_supplier.lead_time_months = 2;
+
+        let boundaries: Vec<Option<u32>> = (0..8).map(|month| exercise_boundary(&params, &pair, &order, month)).collect();
+
+        // A sustained demand surprise triggers the surge order, but it must be larger the fewer
+        // deliveries remain to recoup the fee
+        let early: Vec<u32> = boundaries[..5].iter().map(|b| b.expect("boundary early in the season")).collect();
+        assert!(early[0] > 10_000);
+        assert!(early.windows(2).all(|pair| pair[0] <= pair[1]), "boundaries {:?}", boundaries);
+
+        // November and December changes could not land before the season ends
+        assert_eq!(&boundaries[6..], &[None, None]);
+    }
+
+    #[test]
+    fn test_normal_loss_payoff_vanishes_at_critical_fractile() {
+        let (params, pair, _) = under_ordered_scenario();
+        let state = decision_state(0, 0, 8_000, Vec::new());
+        let valuer = OptionValuation::from_state(&state, &params, &pair, &OptionsConfig::for_products(&[ProductId(0)])).unwrap();
+
+        // Seven deliveries remain: Cu = 100 - 80, Co = 80 - 60 + 2 * 7 / 2
+        let fractile = 20.0 / (20.0 + 27.0);
+        let horizon_target = 70_000.0 + crate::stats::inverse_standard_normal_cdf(fractile) * 500.0 * 7.0_f64.sqrt();
+        let critical_q = horizon_target / 7.0;
+
+        for order_quantity in [critical_q.floor() as u32, critical_q.ceil() as u32] {
+            for direction in [ExerciseDirection::Increase, ExerciseDirection::Decrease] {
+                let (benefit, _) = valuer.calculate_exercise_payoff(direction, 0, 0, order_quantity, 0, 1.0);
+                assert!(benefit.abs() < 1.0, "{:?} from {} gains {}", direction, order_quantity, benefit);
+            }
+        }
+
+        // Off target, topping up to Q* reduces the expected mismatch cost
+        let (benefit, new_q) = valuer.calculate_exercise_payoff(ExerciseDirection::Increase, 0, 0, 8_000, 0, 1.0);
+        assert_eq!(new_q, critical_q as u32);
+        assert!(benefit > 0.0);
+    }
+
+    #[test]
+    fn test_from_state_rejects_months_outside_the_season() {
+        let (mut params, pair, _) = under_ordered_scenario();
+        let config = OptionsConfig::for_products(&[ProductId(0)]);
+
+        let state = decision_state(8, 0, 8_000, Vec::new());
+        let error = OptionValuation::from_state(&state, &params, &pair, &config).err();
+        assert_eq!(error, Some(OptionsError::MonthOutOfSeason { month: 8, season_months: 8 }));
+
+        // The same month is inside a 12-month season, with three deliveries left to change
+        params.season_months = 12;
+        let option_value = OptionValuation::from_state(&state, &params, &pair, &config).unwrap().value_option();
+        assert_eq!(option_value.recommended_action, Some(ExerciseDirection::Increase));
+        let state = decision_state(12, 0, 8_000, Vec::new());
+        assert!(OptionValuation::from_state(&state, &params, &pair, &config).is_err());
+    }
+
+    /// Round-number fixture for hand-checked values: 10k monthly demand with a CV of 0.2, a price
+    /// of 100 against a surge cost of 80, holding at 2 a month, and a single change right at 50k
+    fn golden_scenario() -> (SimulationParams, SupplierPair, MonthlyOrder) {
+        let (mut params, pair, order) = under_ordered_scenario();
+        params.demand_params[0].std_dev_demand = 2_000.0;
+        params.demand_params[0].actual_std_dev_demand = 2_000.0;
+        params.order_change_fees = vec![50_000.0];
+        params.calibrate_to_observed_demand = false;
+        (params, pair, order)
+    }
+
+    /// Value of the rights with nothing in stock and `order_quantity` delivered each month
+    fn golden_value(params: &SimulationParams, pair: &SupplierPair, month: usize, order_quantity: u32) -> OptionValue {
+        let state = decision_state(month, 0, order_quantity, Vec::new());
+        OptionValuation::from_state(&state, params, pair, &OptionsConfig::for_products(&[ProductId(0)])).unwrap().value_option()
+    }
+
+    #[test]
+    fn test_golden_one_period_values() {
+        // In November only the December delivery can change: Cu = 100 - 80 = 20,
+        // Co = 80 - 60 + 2 * 1 / 2 = 21, so Q* = 10,000 + Φ⁻¹(20 / 41) * 2,000 = 9,938.85.
+        // Expected mismatch cost is Co * (Q - μ) + (Cu + Co) * σ * L(z): 100,164.34 at 5,000
+        // units and 32,697.98 at Q*, a benefit of 67,466.36 against the 50k fee
+        let (params, pair, _) = golden_scenario();
+        let cases = [
+            (5_000, 17_466.36, Some(ExerciseDirection::Increase)),
+            // 46,831.87 at 8,000 units leaves a benefit of 14,133.89, short of the fee
+            (8_000, 0.0, None),
+        ];
+        for (order_quantity, expected_value, expected_action) in cases {
+            let option_value = golden_value(&params, &pair, 6, order_quantity);
+            assert!((option_value.value - expected_value).abs() < 0.5, "{:?} at {}", option_value, order_quantity);
+            assert_eq!(option_value.recommended_action, expected_action);
+        }
+    }
+
+    #[test]
+    fn test_golden_two_period_values() {
+        // In October a change covers two deliveries (Co = 22); waiting a month reveals demand
+        // of 12,214 or 8,187 with risk-neutral probability p = (1 - e^-0.2) / (e^0.2 - e^-0.2)
+        let (params, pair, _) = golden_scenario();
+        let p = (1.0 - (-0.2_f64).exp()) / (0.2_f64.exp() - (-0.2_f64).exp());
+
+        // Far below target, exercising now is worth 152,698.53 - 50,000 and beats waiting
+        let option_value = golden_value(&params, &pair, 5, 5_000);
+        assert!((option_value.value - 102_698.53).abs() < 0.5, "{:?}", option_value);
+
+        // At 7,700 units exercising now loses 2,713.49, but after an up move the December change
+        // clears the fee by 1,609.40 and after a down move it is left unexercised
+        let option_value = golden_value(&params, &pair, 5, 7_700);
+        assert!((option_value.value - p * 1_609.40).abs() < 0.5, "{:?}", option_value);
+        assert!((option_value.value - 724.50).abs() < 0.5);
+        assert_eq!(option_value.recommended_action, Some(ExerciseDirection::Increase));
+    }
+
+    #[test]
+    fn test_option_value_monotone_in_volatility_and_fee() {
+        let (params, pair, _) = golden_scenario();
+
+        // On plan, all of the value comes from uncertainty about demand
+        for month in 0..6 {
+            let values: Vec<f64> = [500.0, 1_000.0, 2_000.0, 3_000.0, 4_000.0]
+                .iter()
+                .map(|&std_dev| {
+                    let mut params = params.clone();
+                    params.demand_params[0].std_dev_demand = std_dev;
+                    golden_value(&params, &pair, month, 10_000).value
+                })
+                .collect();
+            assert!(values.windows(2).all(|pair| pair[0] <= pair[1]), "month {}: {:?}", month, values);
+        }
+
+        for month in 0..8 {
+            for order_quantity in [5_000, 8_000, 10_000, 14_000] {
+                let values: Vec<f64> = [0.0, 10_000.0, 50_000.0, 100_000.0]
+                    .iter()
+                    .map(|&fee| {
+                        let mut params = params.clone();
+                        params.order_change_fees = vec![fee; 2];
+                        golden_value(&params, &pair, month, order_quantity).value
+                    })
+                    .collect();
+                assert!(values.windows(2).all(|pair| pair[0] >= pair[1]), "month {}: {:?}", month, values);
+
+                // December's delivery is the last, so nothing is left to change
+                if month == 7 {
+                    assert!(values.iter().all(|&value| value == 0.0));
+                }
+            }
+        }
+    }
+
+    /// Best expected value of exercising one right over every stopping rule on the lattice,
+    /// enumerating all up and down demand paths through the remaining periods
+    fn brute_force_value(valuer: &OptionValuation, direction: ExerciseDirection) -> f64 {
+        let periods = valuer.remaining_months;
+        let u = valuer.volatility.exp();
+        let p = (1.0 - 1.0 / u) / (u - 1.0 / u);
+        let fee = valuer.remaining_fees[0];
+        let is_up = |path: u32, period: usize| (path >> period) & 1 == 1;
+
+        // A stopping rule is the set of nodes at which the right is exercised, with nodes
+        // numbered level by level and the moves taken so far in the low bits of the path
+        let mut best = f64::NEG_INFINITY;
+        for rule in 0..(1u64 << ((1 << periods) - 1)) {
+            let mut expected = 0.0;
+            for path in 0..(1u32 << periods) {
+                let probability: f64 = (0..periods).map(|period| if is_up(path, period) { p } else { 1.0 - p }).product();
+                let (mut uplifts, mut inventory) = (0, valuer.inventory);
+                for period in 0..periods {
+                    let node = (1 << period) - 1 + (path & ((1 << period) - 1));
+                    if rule & (1 << node) != 0 {
+                        let (benefit, _) = valuer.calculate_exercise_payoff(
+                            direction, period, inventory, valuer.current_order_quantity, uplifts, u);
+                        expected += probability * (benefit - fee);
+                        break;
+                    }
+                    uplifts += if is_up(path, period) { 1 } else { -1 };
+                    let demand = (valuer.forecast_mean * u.powi(uplifts)) as u32;
+                    inventory = (inventory + valuer.current_order_quantity).saturating_sub(demand);
+                }
+            }
+            best = best.max(expected);
+        }
+        best
+    }
+
+    #[test]
+    fn test_lattice_matches_brute_force_over_three_periods() {
+        // From October three decisions remain, the last with no delivery left to change
+        let (params, pair, _) = golden_scenario();
+        for order_quantity in [5_000, 7_700, 9_000, 12_000, 16_000] {
+            for inventory in [0, 4_000] {
+                let state = decision_state(5, inventory, order_quantity, Vec::new());
+                let valuer = OptionValuation::from_state(&state, &params, &pair, &OptionsConfig::for_products(&[ProductId(0)])).unwrap();
+                assert_eq!(valuer.remaining_months, 3);
+                for direction in [ExerciseDirection::Increase, ExerciseDirection::Decrease] {
+                    let lattice = valuer.binomial_value_recursive(direction, 0, 0, inventory, order_quantity, 0);
+                    let brute_force = brute_force_value(&valuer, direction);
+                    assert!(
+                        (lattice - brute_force).abs() < 1e-6,
+                        "{:?} from {} with {} in stock: lattice {} against {}",
+                        direction, order_quantity, inventory, lattice, brute_force
+                    );
+                }
+            }
+        }
     }
 }
@@ -1,32 +1,731 @@
-use crate::models::{Supplier, SupplierPair};
+use std::str::FromStr;
+use crate::models::{RejectedPair, RejectionReason, SimulationParams, Supplier, SupplierId, SupplierPair};
 
-/// Generate intelligent supplier pairs based on lead times.
-/// Pairs longer lead-time suppliers with shorter lead-time suppliers.
-/// Only suppliers with lead_time >= 1 can be used as base suppliers.
-/// Only suppliers with lead_time < 2 can be used as surge suppliers.
-pub fn generate_intelligent_pairs(suppliers: &[Supplier]) -> Vec<SupplierPair> {
+/// Which suppliers may serve as base and surge supplier, and which pairs of them are worth
+/// evaluating
+#[derive(Clone, Debug, PartialEq)]
+pub struct PairingRules {
+    /// Shortest lead time of a base supplier, in months
+    pub base_min_lead: usize,
+    /// Longest lead time of a base supplier, if any
+    pub base_max_lead: Option<usize>,
+    /// Longest lead time of a surge (or backup) supplier
+    pub surge_max_lead: usize,
+    /// Skip pairs whose surge supplier does not deliver strictly sooner than their base supplier
+    pub require_surge_shorter_than_base: bool,
+    /// Skip pairs whose suppliers together make fewer units a month
+    pub min_combined_capacity: Option<u32>,
+    /// Skip groups with two suppliers in the same region, which one disruption could stop together
+    pub require_distinct_regions: bool,
+    /// Most suppliers of a group that may share a risk class (`usize::MAX` for no limit)
+    pub max_same_risk_class: usize,
+    /// Most a strategy's suppliers' setup costs may add up to; pairs and triples over it are
+    /// rejected at screening, the other strategies are not generated
+    pub max_total_setup_cost: Option<f64>,
+}
+
+impl Default for PairingRules {
+    /// Base suppliers lead by at least a month, surge suppliers by at most one
+    fn default() -> Self {
+        PairingRules {
+            base_min_lead: 1,
+            base_max_lead: None,
+            surge_max_lead: 1,
+            require_surge_shorter_than_base: false,
+            min_combined_capacity: None,
+            require_distinct_regions: false,
+            max_same_risk_class: usize::MAX,
+            max_total_setup_cost: None,
+        }
+    }
+}
+
+impl PairingRules {
+    pub fn base_eligible(&self, supplier: &Supplier) -> bool {
+        supplier.lead_time_months >= self.base_min_lead
+            && self.base_max_lead.is_none_or(|max| supplier.lead_time_months <= max)
+    }
+
+    pub fn surge_eligible(&self, supplier: &Supplier) -> bool {
+        supplier.lead_time_months <= self.surge_max_lead
+    }
+
+    /// Whether a strategy's setup costs fit under the cap, if any
+    pub fn within_setup_cap(&self, strategy: &SupplierPair) -> bool {
+        self.max_total_setup_cost.is_none_or(|max| strategy.total_setup_cost() <= max)
+    }
+
+    /// Whether a pair of eligible suppliers passes the pair-level rules
+    fn admits(&self, pair: &SupplierPair) -> bool {
+        let surge_sooner = pair.surge_supplier.lead_time_months < pair.base_supplier.lead_time_months;
+        (!self.require_surge_shorter_than_base || surge_sooner)
+            && self.min_combined_capacity.is_none_or(|min| pair.total_capacity() >= min)
+            && self.diversity_violation(pair).is_none()
+    }
+
+    /// The diversity rule a pair, with its pooled and backup suppliers, breaks; suppliers of unknown region or
+    /// risk class never count against it
+    pub fn diversity_violation(&self, pair: &SupplierPair) -> Option<RejectionReason> {
+        let suppliers: Vec<&Supplier> = pair.committed_tier()
+            .chain([&pair.surge_supplier])
+            .chain(pair.backup_supplier.as_ref())
+            .collect();
+        if self.require_distinct_regions {
+            let regions: Vec<&str> = suppliers.iter().filter_map(|s| s.region.as_deref()).collect();
+            if let Some(index) = (1..regions.len()).find(|&i| regions[..i].contains(&regions[i])) {
+                return Some(RejectionReason::SharedRegion { region: regions[index].to_string() });
+            }
+        }
+        let risk_classes: Vec<&str> = suppliers.iter().filter_map(|s| s.risk_class.as_deref()).collect();
+        risk_classes.iter()
+            .map(|class| (*class, risk_classes.iter().filter(|other| *other == class).count()))
+            .find(|(_, count)| *count > self.max_same_risk_class)
+            .map(|(class, count)| RejectionReason::ConcentratedRiskClass {
+                risk_class: class.to_string(),
+                suppliers: count,
+                max: self.max_same_risk_class,
+            })
+    }
+
+    /// The same rules without the diversity rules
+    fn ignoring_diversity(&self) -> Self {
+        PairingRules { require_distinct_regions: false, max_same_risk_class: usize::MAX, ..self.clone() }
+    }
+}
+
+/// Generate intelligent supplier pairs based on lead times, under the default `PairingRules`:
+/// only suppliers with lead_time >= 1 can be used as base suppliers, and only suppliers with
+/// lead_time < 2 as surge suppliers.
+/// With `with_backup`, each pair is also offered with every other surge-eligible supplier as
+/// its backup, after the pairs without one.
+#[allow(dead_code)]
+pub fn generate_intelligent_pairs(suppliers: &[Supplier], with_backup: bool) -> Vec<SupplierPair> {
+    let rules = PairingRules::default();
+    let pairs = generate_pairs_with_rules(suppliers, &rules);
+    if with_backup { with_backups(pairs, suppliers, &rules) } else { pairs }
+}
+
+/// Pair each base-eligible supplier with each other surge-eligible supplier, keeping the pairs
+/// the rules admit
+pub fn generate_pairs_with_rules(suppliers: &[Supplier], rules: &PairingRules) -> Vec<SupplierPair> {
+    generate_pairs_among(suppliers, suppliers, rules)
+}
+
+/// Pair each base-eligible supplier of `bases` with each other surge-eligible supplier of
+/// `surges`, keeping the pairs the rules admit
+pub fn generate_pairs_among(bases: &[Supplier], surges: &[Supplier], rules: &PairingRules) -> Vec<SupplierPair> {
     let mut pairs = Vec::new();
+    for base in bases.iter().filter(|s| rules.base_eligible(s)) {
+        for surge in surges.iter().filter(|s| rules.surge_eligible(s)) {
+            let pair = SupplierPair {
+                base_supplier: base.clone(),
+                surge_supplier: surge.clone(),
+                backup_supplier: None,
+                pooled_suppliers: Vec::new(),
+            };
+            if base.id != surge.id && rules.admits(&pair) {
+                pairs.push(pair);
+            }
+        }
+    }
+    pairs
+}
 
-    // Identify eligible base suppliers (lead_time >= 1) and surge suppliers (lead_time < 2)
-    let base_eligible: Vec<&Supplier> = suppliers.iter()
-        .filter(|s| s.lead_time_months >= 1)
+/// `pairs` followed by each of them with every other surge-eligible supplier as its backup;
+/// backups stand in for the surge supplier, so they must be surge-eligible too
+pub fn with_backups(mut pairs: Vec<SupplierPair>, suppliers: &[Supplier], rules: &PairingRules) -> Vec<SupplierPair> {
+    let triples: Vec<SupplierPair> = pairs.iter()
+        .flat_map(|pair| {
+            suppliers.iter()
+                .filter(|backup| rules.surge_eligible(backup))
+                .filter(|backup| backup.id != pair.base_supplier.id && backup.id != pair.surge_supplier.id)
+                .map(|backup| SupplierPair { backup_supplier: Some(backup.clone()), ..pair.clone() })
+                .filter(|triple| rules.diversity_violation(triple).is_none())
+        })
         .collect();
+    pairs.extend(triples);
+    pairs
+}
+
+/// The pairs of `bases` and `surges` (and, `with_backup`, triples) the rules would generate but
+/// for their diversity rules, each with the rule it breaks, for the screening summary
+pub fn rejected_for_diversity(bases: &[Supplier], surges: &[Supplier], rules: &PairingRules, with_backup: bool) -> Vec<RejectedPair> {
+    let lenient = rules.ignoring_diversity();
+    let mut pairs = generate_pairs_among(bases, surges, &lenient);
+    if with_backup {
+        pairs = with_backups(pairs, surges, &lenient);
+    }
+    pairs.iter()
+        .filter_map(|pair| rules.diversity_violation(pair).map(|reason| RejectedPair::new(pair, reason)))
+        .collect()
+}
+
+/// How much each criterion counts toward a supplier's score
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScoringWeights {
+    /// Unit cost, weighted by each product's share of expected demand
+    pub landed_cost: f64,
+    /// Share of the expected monthly demand the supplier can make
+    pub capacity: f64,
+    pub lead_time: f64,
+    pub setup_cost: f64,
+}
+
+impl FromStr for ScoringWeights {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected four non-negative weights as COST,CAPACITY,LEAD,SETUP, got \"{}\"", s);
+        let weights: Vec<f64> = s.split(',')
+            .map(|weight| weight.trim().parse::<f64>().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        match weights[..] {
+            [landed_cost, capacity, lead_time, setup_cost] if weights.iter().all(|w| *w >= 0.0) && weights.iter().sum::<f64>() > 0.0 => {
+                Ok(ScoringWeights { landed_cost, capacity, lead_time, setup_cost })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Default for ScoringWeights {
+    /// Cost and capacity first
+    fn default() -> Self {
+        ScoringWeights { landed_cost: 0.4, capacity: 0.3, lead_time: 0.15, setup_cost: 0.15 }
+    }
+}
+
+/// A supplier's composite score and the figures behind it
+#[derive(Clone, Debug, PartialEq)]
+pub struct SupplierScore {
+    pub supplier_id: SupplierId,
+    pub supplier_name: String,
+    /// Unit cost weighted by each product's share of expected demand
+    pub landed_cost: f64,
+    /// Capacity as a share of the expected monthly demand, at most 1
+    pub capacity_coverage: f64,
+    /// Weighted mean of the criteria, each from 0 (worst) to 1 (best)
+    pub score: f64,
+}
+
+/// A supplier's unit cost weighted by each product's share of expected demand
+fn landed_cost(params: &SimulationParams, supplier: &Supplier) -> f64 {
+    let demand: f64 = params.demand_params.iter().map(|dp| dp.mean_demand).sum();
+    let cost: f64 = params.demand_params.iter()
+        .map(|dp| dp.mean_demand * supplier.unit_costs.get(&dp.product_id).copied().unwrap_or(0.0))
+        .sum();
+    if demand > 0.0 { cost / demand } else { 0.0 }
+}
+
+/// Score each supplier, in order, against the others: landed cost and setup cost relative to
+/// the cheapest supplier, capacity against expected demand, and lead time as 1 / (1 + months)
+pub fn score_suppliers(params: &SimulationParams, suppliers: &[Supplier], weights: &ScoringWeights) -> Vec<SupplierScore> {
+    let demand: f64 = params.demand_params.iter().map(|dp| dp.mean_demand).sum();
+    let landed_cost = |supplier: &Supplier| landed_cost(params, supplier);
+    // Cheapest over own, 1 for the cheapest supplier or one that costs nothing
+    let relative = |lowest: f64, own: f64| if own > 0.0 { lowest / own } else { 1.0 };
+    let lowest_landed_cost = suppliers.iter().map(landed_cost).fold(f64::INFINITY, f64::min);
+    let lowest_setup_cost = suppliers.iter().map(|s| s.setup_cost).fold(f64::INFINITY, f64::min);
+    let total_weight = weights.landed_cost + weights.capacity + weights.lead_time + weights.setup_cost;
+
+    suppliers.iter()
+        .map(|supplier| {
+            let landed_cost = landed_cost(supplier);
+            let capacity_coverage = if demand > 0.0 { (supplier.fixed_capacity as f64 / demand).min(1.0) } else { 1.0 };
+            let weighted = weights.landed_cost * relative(lowest_landed_cost, landed_cost)
+                + weights.capacity * capacity_coverage
+                + weights.lead_time / (1.0 + supplier.lead_time_months as f64)
+                + weights.setup_cost * relative(lowest_setup_cost, supplier.setup_cost);
+            SupplierScore {
+                supplier_id: supplier.id,
+                supplier_name: supplier.name.clone(),
+                landed_cost,
+                capacity_coverage,
+                score: if total_weight > 0.0 { weighted / total_weight } else { 0.0 },
+            }
+        })
+        .collect()
+}
+
+/// Suppliers kept as base and as surge candidates, and the scores of those kept in neither role
+#[derive(Clone, Debug)]
+pub struct Shortlist {
+    pub base: Vec<Supplier>,
+    pub surge: Vec<Supplier>,
+    pub excluded: Vec<SupplierScore>,
+}
+
+impl Shortlist {
+    /// Every supplier not excluded, in the original order
+    pub fn suppliers(&self, suppliers: &[Supplier]) -> Vec<Supplier> {
+        suppliers.iter()
+            .filter(|supplier| self.excluded.iter().all(|score| score.supplier_id != supplier.id))
+            .cloned()
+            .collect()
+    }
+}
+
+/// The `top_k` best-scored base-eligible and surge-eligible suppliers, in the original order; a
+/// supplier eligible for a role it was not shortlisted for is excluded unless kept for another
+pub fn shortlist(suppliers: &[Supplier], scores: &[SupplierScore], rules: &PairingRules, top_k: usize) -> Shortlist {
+    let score_of = |supplier: &Supplier| scores.iter()
+        .find(|score| score.supplier_id == supplier.id)
+        .map_or(f64::NEG_INFINITY, |score| score.score);
+    let top = |eligible: &dyn Fn(&Supplier) -> bool| {
+        let mut ranked: Vec<&Supplier> = suppliers.iter().filter(|s| eligible(s)).collect();
+        ranked.sort_by(|a, b| score_of(b).total_cmp(&score_of(a)));
+        let kept: Vec<SupplierId> = ranked.iter().take(top_k).map(|s| s.id).collect();
+        suppliers.iter().filter(|s| kept.contains(&s.id)).cloned().collect::<Vec<Supplier>>()
+    };
+    let base = top(&|s| rules.base_eligible(s));
+    let surge = top(&|s| rules.surge_eligible(s));
+    let excluded = scores.iter()
+        .filter(|score| suppliers.iter().any(|s| {
+            s.id == score.supplier_id && (rules.base_eligible(s) || rules.surge_eligible(s))
+        }))
+        .filter(|score| base.iter().chain(&surge).all(|s| s.id != score.supplier_id))
+        .cloned()
+        .collect();
+    Shortlist { base, surge, excluded }
+}
+
+/// Strategies pooling the committed capacity of several base-eligible suppliers, for when no
+/// pair the rules admit can make `coverage` of the expected monthly demand; none otherwise
+/// Each surge-eligible supplier gets one: the cheapest other base-eligible suppliers by landed
+/// cost, in that order, as its committed tier, added until the strategy covers the demand;
+/// suppliers whose setup would take the strategy over the setup cost cap are passed over
+pub fn generate_pooled_strategies(
+    params: &SimulationParams,
+    suppliers: &[Supplier],
+    rules: &PairingRules,
+    coverage: f64,
+) -> Vec<SupplierPair> {
+    let target = coverage * params.demand_params.iter().map(|dp| dp.mean_demand).sum::<f64>();
+    let covers = |pair: &SupplierPair| pair.total_capacity() as f64 >= target;
+    if generate_pairs_with_rules(suppliers, rules).iter().any(covers) {
+        return Vec::new();
+    }
+
+    let mut by_cost: Vec<&Supplier> = suppliers.iter().filter(|s| rules.base_eligible(s)).collect();
+    by_cost.sort_by(|a, b| landed_cost(params, a).total_cmp(&landed_cost(params, b)));
+    suppliers.iter()
+        .filter(|surge| rules.surge_eligible(surge))
+        .filter_map(|surge| {
+            let mut committed = by_cost.iter().filter(|base| base.id != surge.id);
+            let mut strategy = SupplierPair {
+                base_supplier: (*committed.next()?).clone(),
+                surge_supplier: surge.clone(),
+                backup_supplier: None,
+                pooled_suppliers: Vec::new(),
+            };
+            while !covers(&strategy) {
+                strategy.pooled_suppliers.push((*committed.next()?).clone());
+                if !rules.within_setup_cap(&strategy) {
+                    strategy.pooled_suppliers.pop();
+                }
+            }
+            Some(strategy)
+        })
+        .filter(|strategy| !strategy.pooled_suppliers.is_empty() && rules.admits(strategy) && rules.within_setup_cap(strategy))
+        .collect()
+}
+
+/// One strategy per supplier ordering everything from it alone, as a degenerate pair whose
+/// surge supplier is the same supplier without surge capacity, to weigh against the pairs;
+/// only the rules' setup cost cap applies, any supplier may otherwise go it alone
+pub fn generate_single_supplier_strategies(suppliers: &[Supplier], rules: &PairingRules) -> Vec<SupplierPair> {
+    suppliers.iter()
+        .map(|supplier| SupplierPair {
+            base_supplier: supplier.clone(),
+            surge_supplier: Supplier { fixed_capacity: 0, ..supplier.clone() },
+            backup_supplier: None,
+            pooled_suppliers: Vec::new(),
+        })
+        .filter(|strategy| rules.within_setup_cap(strategy))
+        .collect()
+}
+
+/// One pair per surge-eligible supplier with itself, splitting its capacity between a committed
+/// base tranche and a flexible surge tranche; how much goes to each follows the plan
+pub fn generate_split_capacity_pairs(suppliers: &[Supplier], rules: &PairingRules) -> Vec<SupplierPair> {
+    suppliers.iter()
+        .filter(|s| rules.surge_eligible(s))
+        .map(|supplier| SupplierPair {
+            base_supplier: supplier.clone(),
+            surge_supplier: supplier.clone(),
+            backup_supplier: None,
+            pooled_suppliers: Vec::new(),
+        })
+        .filter(|pair| rules.within_setup_cap(pair))
+        .collect()
+}
 
-    let surge_eligible: Vec<&Supplier> = suppliers.iter()
-        .filter(|s| s.lead_time_months < 2)
+/// Whether `worse` is strictly worse than `better` on every count: landed cost of each product,
+/// capacity, lead time and setup cost
+fn strictly_dominated(params: &SimulationParams, worse: &Supplier, better: &Supplier) -> bool {
+    params.products.iter().all(|product| worse.landed_unit_cost(product.id) > better.landed_unit_cost(product.id))
+        && worse.fixed_capacity < better.fixed_capacity
+        && worse.lead_time_months > better.lead_time_months
+        && worse.setup_cost > better.setup_cost
+}
+
+/// Whether `other` is `pair` with a strictly better base or a strictly better surge supplier
+/// and everything else the same; only plain pairs of two different suppliers are compared
+fn dominates(params: &SimulationParams, other: &SupplierPair, pair: &SupplierPair) -> bool {
+    let plain = |pair: &SupplierPair| pair.pooled_suppliers.is_empty() && pair.base_supplier.id != pair.surge_supplier.id;
+    let backup_id = |pair: &SupplierPair| pair.backup_supplier.as_ref().map(|backup| backup.id);
+    if !plain(pair) || !plain(other) || backup_id(pair) != backup_id(other) {
+        return false;
+    }
+    if pair.surge_supplier.id == other.surge_supplier.id {
+        strictly_dominated(params, &pair.base_supplier, &other.base_supplier)
+    } else if pair.base_supplier.id == other.base_supplier.id {
+        strictly_dominated(params, &pair.surge_supplier, &other.surge_supplier)
+    } else {
+        false
+    }
+}
+
+/// Split `pairs` into those worth simulating and those another pair sharing one member beats
+/// on every count, each named with a kept pair at least as good on every count
+/// A pair beaten only by pairs pruned in turn is named with the kept pair its chain of dominators
+/// ends at; each step improves a member strictly, so the chain ends
+pub fn prune_dominated(pairs: Vec<SupplierPair>, params: &SimulationParams) -> (Vec<SupplierPair>, Vec<RejectedPair>) {
+    let dominated: Vec<bool> = pairs.iter()
+        .map(|pair| pairs.iter().any(|other| dominates(params, other, pair)))
+        .collect();
+    // A kept dominator if there is one, otherwise any
+    let dominator = |pair: &SupplierPair| (0..pairs.len())
+        .filter(|&index| dominates(params, &pairs[index], pair))
+        .min_by_key(|&index| dominated[index]);
+    let pruned = pairs.iter().zip(&dominated)
+        .filter(|(_, dominated)| **dominated)
+        .filter_map(|(pair, _)| {
+            let mut by = dominator(pair)?;
+            while dominated[by] {
+                by = dominator(&pairs[by])?;
+            }
+            Some(RejectedPair::new(pair, RejectionReason::Dominated { by: pairs[by].name() }))
+        })
         .collect();
+    let kept = pairs.into_iter().zip(dominated)
+        .filter(|(_, dominated)| !dominated)
+        .map(|(pair, _)| pair)
+        .collect();
+    (kept, pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::models::ProductId;
+
+    fn supplier(id: usize, lead_time_months: usize) -> Supplier {
+        Supplier {
+            id: SupplierId(id as u32),
+            name: format!("Supplier {}", id),
+            fixed_capacity: 1_000,
+            product_capacity: None,
+            capacity_groups: Vec::new(),
+            soft_capacity_tiers: Vec::new(),
+            lead_time_months,
+            ramp_up_months: Vec::new(),
+            unit_costs: HashMap::new(),
+            setup_cost: 0.0,
+            reservation_fee: None,
+            monthly_flex: None,
+            disrupted_months: Vec::new(),
+            region: None,
+            risk_class: None,
+        }
+    }
+
+    #[test]
+    fn test_backups_are_surge_eligible_and_distinct() {
+        let suppliers = [supplier(0, 3), supplier(1, 1), supplier(2, 0), supplier(3, 2)];
+        assert!(generate_intelligent_pairs(&suppliers, false).iter().all(|pair| pair.backup_supplier.is_none()));
+
+        let pairs = generate_intelligent_pairs(&suppliers, true);
+        let triples: Vec<String> = pairs.iter().filter(|pair| pair.backup_supplier.is_some()).map(SupplierPair::name).collect();
+        // Supplier 1 + Supplier 2 has no backup left: Supplier 0 and Supplier 3 lead too long
+        assert_eq!(triples, [
+            "Supplier 0 + Supplier 1 + Supplier 2",
+            "Supplier 0 + Supplier 2 + Supplier 1",
+            "Supplier 3 + Supplier 1 + Supplier 2",
+            "Supplier 3 + Supplier 2 + Supplier 1",
+        ]);
+        assert_eq!(pairs.len(), 5 + triples.len());
+    }
+
+    fn names(pairs: &[SupplierPair]) -> Vec<String> {
+        pairs.iter().map(SupplierPair::name).collect()
+    }
 
-    // Create pairs: each base-eligible supplier with each surge-eligible supplier
-    for base in &base_eligible {
-        for surge in &surge_eligible {
-            if base.id != surge.id {
-                pairs.push(SupplierPair {
-                    base_supplier: (*base).clone(),
-                    surge_supplier: (*surge).clone(),
-                });
+    #[test]
+    fn test_default_rules_match_the_fixed_thresholds() {
+        let suppliers: Vec<Supplier> = (0..5).map(|lead_time| supplier(lead_time, lead_time)).collect();
+        let mut expected = Vec::new();
+        for base in suppliers.iter().filter(|s| s.lead_time_months >= 1) {
+            for surge in suppliers.iter().filter(|s| s.lead_time_months < 2 && s.id != base.id) {
+                expected.push(format!("{} + {}", base.name, surge.name));
             }
         }
+        assert_eq!(names(&generate_pairs_with_rules(&suppliers, &PairingRules::default())), expected);
+        assert_eq!(names(&generate_intelligent_pairs(&suppliers, false)), expected);
     }
 
-    pairs
+    #[test]
+    fn test_each_pairing_rule() {
+        // Supplier n leads by n months
+        let suppliers: Vec<Supplier> = (0..4).map(|lead_time| supplier(lead_time, lead_time)).collect();
+        let pairs = |rules: PairingRules| names(&generate_pairs_with_rules(&suppliers, &rules));
+
+        // A 0-month base and a 2-month surge supplier once the lead times allow them
+        let relaxed = PairingRules { base_min_lead: 0, surge_max_lead: 2, ..PairingRules::default() };
+        assert!(pairs(relaxed.clone()).contains(&"Supplier 0 + Supplier 2".to_string()));
+        assert!(pairs(relaxed.clone()).contains(&"Supplier 3 + Supplier 2".to_string()));
+
+        let capped = PairingRules { base_max_lead: Some(2), ..relaxed.clone() };
+        assert!(pairs(capped).iter().all(|name| !name.starts_with("Supplier 3")));
+
+        let sooner = PairingRules { require_surge_shorter_than_base: true, ..relaxed.clone() };
+        assert!(!pairs(sooner.clone()).contains(&"Supplier 0 + Supplier 2".to_string()));
+        assert!(!pairs(sooner.clone()).contains(&"Supplier 1 + Supplier 2".to_string()));
+        assert!(pairs(sooner).contains(&"Supplier 3 + Supplier 2".to_string()));
+
+        // Every supplier makes 1,000 units a month
+        assert_eq!(pairs(PairingRules { min_combined_capacity: Some(2_000), ..relaxed.clone() }), pairs(relaxed.clone()));
+        assert!(pairs(PairingRules { min_combined_capacity: Some(2_001), ..relaxed }).is_empty());
+    }
+
+    #[test]
+    fn test_diversity_rules() {
+        let located = |id: usize, lead_time_months: usize, region: &str, risk_class: &str| Supplier {
+            region: Some(region.to_string()),
+            risk_class: Some(risk_class.to_string()),
+            ..supplier(id, lead_time_months)
+        };
+        let suppliers = [
+            located(0, 3, "Asia", "single-plant"),
+            located(1, 0, "Asia", "multi-plant"),
+            located(2, 1, "Europe", "single-plant"),
+            supplier(3, 0),
+        ];
+        let rules = PairingRules::default();
+        let all = names(&generate_pairs_with_rules(&suppliers, &rules));
+
+        let distinct = PairingRules { require_distinct_regions: true, ..rules.clone() };
+        let kept = names(&generate_pairs_with_rules(&suppliers, &distinct));
+        // Supplier 3 has no known region, so it pairs with anyone
+        assert_eq!(kept.len(), all.len() - 1);
+        assert!(!kept.contains(&"Supplier 0 + Supplier 1".to_string()));
+        let rejected = rejected_for_diversity(&suppliers, &suppliers, &distinct, false);
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].name(), "Supplier 0 + Supplier 1");
+        assert_eq!(rejected[0].reason, RejectionReason::SharedRegion { region: "Asia".to_string() });
+
+        let spread = PairingRules { max_same_risk_class: 1, ..rules.clone() };
+        let kept = names(&generate_pairs_with_rules(&suppliers, &spread));
+        assert!(!kept.contains(&"Supplier 0 + Supplier 2".to_string()));
+        assert!(!kept.contains(&"Supplier 2 + Supplier 0".to_string()) && kept.contains(&"Supplier 2 + Supplier 1".to_string()));
+        assert!(rejected_for_diversity(&suppliers, &suppliers, &rules, true).is_empty());
+
+        // Backups count toward the diversity of their group
+        let triples = with_backups(generate_pairs_with_rules(&suppliers, &distinct), &suppliers, &distinct);
+        assert!(!names(&triples).contains(&"Supplier 0 + Supplier 3 + Supplier 1".to_string()));
+        assert!(names(&triples).contains(&"Supplier 2 + Supplier 3 + Supplier 1".to_string()));
+        assert!(rejected_for_diversity(&suppliers, &suppliers, &distinct, true).iter()
+            .any(|pair| pair.name() == "Supplier 0 + Supplier 3 + Supplier 1"));
+    }
+
+    #[test]
+    fn test_shortlisting_keeps_the_best_suppliers_and_the_winner() {
+        use crate::monte_carlo::tests::two_product_scenario;
+        use crate::monte_carlo::{run_monte_carlo_simulation, MonteCarloConfig};
+        use crate::simulation::split_order_quantities;
+
+        // The fixture's pair, then dearer, smaller suppliers with higher setup costs
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        let dominated = |id: u32, lead_time_months: usize, fixed_capacity: u32, unit_cost: f64| Supplier {
+            id: SupplierId(id),
+            name: format!("Supplier {}", id),
+            fixed_capacity,
+            lead_time_months,
+            unit_costs: HashMap::from([(ProductId(0), unit_cost), (ProductId(1), unit_cost)]),
+            setup_cost: 50_000.0,
+            ..pair.base_supplier.clone()
+        };
+        let suppliers = [
+            pair.base_supplier.clone(),
+            pair.surge_supplier.clone(),
+            dominated(2, 2, 2_000, 95.0),
+            dominated(3, 0, 1_500, 98.0),
+            dominated(4, 1, 3_000, 90.0),
+        ];
+        let rules = PairingRules::default();
+        let scores = score_suppliers(&params, &suppliers, &ScoringWeights::default());
+        assert!(scores[..2].iter().all(|best| scores[2..].iter().all(|other| best.score > other.score)));
+        assert_eq!((scores[0].landed_cost, scores[1].capacity_coverage), (75.0, 5_000.0 / 9_000.0));
+
+        // The mean profit of every pair under one plan decides the winner
+        let config = MonteCarloConfig { master_seed: 29, ..MonteCarloConfig::new(20) };
+        let mean_profit = |pair: &SupplierPair| {
+            let order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], pair, &params);
+            run_monte_carlo_simulation(&params, pair, &order, &config).unwrap().mean_profit
+        };
+        let all = generate_pairs_with_rules(&suppliers, &rules);
+        let winner = all.iter().max_by(|a, b| mean_profit(a).total_cmp(&mean_profit(b))).unwrap().name();
+
+        let pair_counts: Vec<usize> = [usize::MAX, 2, 1].into_iter()
+            .map(|top_k| {
+                let shortlist = shortlist(&suppliers, &scores, &rules, top_k);
+                assert!(shortlist.base.contains(&suppliers[0]) && shortlist.surge.contains(&suppliers[1]));
+                let pairs = generate_pairs_among(&shortlist.base, &shortlist.surge, &rules);
+                assert!(names(&pairs).contains(&winner), "{} dropped at k = {}", winner, top_k);
+                pairs.len()
+            })
+            .collect();
+        // 3 x 3 candidates less Supplier 4 with itself, then 2 x 2 less the same, then 1 x 1
+        assert_eq!(pair_counts, [all.len(), 3, 1]);
+        assert_eq!(all.len(), 8);
+
+        let excluded = |top_k| shortlist(&suppliers, &scores, &rules, top_k).excluded.iter().map(|score| score.supplier_id.0).collect::<Vec<u32>>();
+        assert_eq!(excluded(usize::MAX), Vec::<u32>::new());
+        assert_eq!(excluded(2), [2, 3]);
+        assert_eq!(excluded(1), [2, 3, 4]);
+    }
+
+    #[test]
+    fn test_only_a_pooled_strategy_meets_a_large_season() {
+        use crate::monte_carlo::tests::two_product_scenario;
+        use crate::pairing_utils::is_pair_promising;
+        use crate::simulation::{project_mean_demand, split_order_quantities};
+
+        // 90,000 units a month; the largest pair makes 60,000, under the 63,000 needed
+        let (mut params, _) = two_product_scenario();
+        params.demand_params[0].mean_demand = 60_000.0;
+        params.demand_params[1].mean_demand = 30_000.0;
+        let sized = |id: usize, lead_time_months: usize, fixed_capacity: u32, unit_cost: f64| Supplier {
+            fixed_capacity,
+            unit_costs: HashMap::from([(ProductId(0), unit_cost), (ProductId(1), unit_cost)]),
+            setup_cost: 10_000.0,
+            ..supplier(id, lead_time_months)
+        };
+        let suppliers = [sized(0, 2, 35_000, 78.0), sized(1, 3, 40_000, 75.0), sized(2, 0, 20_000, 85.0)];
+        let rules = PairingRules::default();
+        let pairs = generate_pairs_with_rules(&suppliers, &rules);
+        assert!(pairs.iter().all(|pair| !is_pair_promising(&params, pair, 0.0)));
+
+        let strategies = generate_pooled_strategies(&params, &suppliers, &rules, 0.7);
+        assert_eq!(names(&strategies), ["Supplier 1 & Supplier 0 + Supplier 2"]);
+        let strategy = &strategies[0];
+        assert_eq!((strategy.base_capacity(), strategy.total_capacity()), (75_000, 95_000));
+        assert!(is_pair_promising(&params, strategy, 0.0));
+        assert!(generate_pooled_strategies(&params, &suppliers, &rules, 0.6).is_empty(), "a pair covers 60%");
+
+        // The cheaper supplier fills first, the other takes the rest of the base orders, and
+        // each supplier's setup is paid once
+        let order = split_order_quantities(&[(ProductId(0), 60_000), (ProductId(1), 30_000)], strategy, &params);
+        assert_eq!(order.total_base_quantity(), 75_000);
+        let months = project_mean_demand(&params, strategy, &order).unwrap();
+        let delivered: u32 = months[2].product_results.iter().map(|result| result.base_incoming).sum();
+        assert_eq!(delivered, 75_000);
+        let production: f64 = months[2].product_results.iter().map(|result| result.production_cost).sum();
+        let surge = order.total_surge_quantity() as f64;
+        assert!((production - (40_000.0 * 75.0 + 35_000.0 * 78.0 + surge * 85.0)).abs() < 1e-6, "{}", production);
+        let setup: f64 = months.iter().map(|month| month.setup_cost).sum();
+        assert_eq!(setup, 30_000.0);
+
+        // Under a cap on setup costs below the three suppliers' no strategy is pooled
+        let capped = PairingRules { max_total_setup_cost: Some(25_000.0), ..rules };
+        assert!(generate_pooled_strategies(&params, &suppliers, &capped, 0.7).is_empty());
+    }
+
+    #[test]
+    fn test_strategy_generators_respect_the_setup_cost_cap() {
+        let with_setup = |id: usize, setup_cost: f64| Supplier { setup_cost, ..supplier(id, 1) };
+        let suppliers = [with_setup(0, 1_000_000.0), with_setup(1, 2_000_000.0)];
+        let capped = |max: f64| PairingRules { max_total_setup_cost: Some(max), ..PairingRules::default() };
+
+        assert_eq!(generate_single_supplier_strategies(&suppliers, &PairingRules::default()).len(), 2);
+        assert_eq!(names(&generate_single_supplier_strategies(&suppliers, &capped(1_500_000.0))), ["Supplier 0 (single-source)"]);
+        assert_eq!(names(&generate_split_capacity_pairs(&suppliers, &capped(1_500_000.0))), ["Supplier 0 (split capacity)"]);
+
+        // Pairs and triples reach screening, which names the cap they break
+        let pairs = generate_pairs_with_rules(&suppliers, &capped(2_500_000.0));
+        assert!(pairs.iter().all(|pair| pair.total_setup_cost() == 3_000_000.0 && !capped(2_500_000.0).within_setup_cap(pair)));
+        assert!(pairs.iter().all(|pair| capped(3_000_000.0).within_setup_cap(pair)));
+    }
+
+    #[test]
+    fn test_prune_dominated_keeps_trade_offs() {
+        use crate::monte_carlo::tests::two_product_scenario;
+
+        let (params, _) = two_product_scenario();
+        let sized = |id: usize, lead_time_months: usize, fixed_capacity: u32, unit_cost: f64, setup_cost: f64| Supplier {
+            fixed_capacity,
+            unit_costs: HashMap::from([(ProductId(0), unit_cost), (ProductId(1), unit_cost + 5.0)]),
+            setup_cost,
+            ..supplier(id, lead_time_months)
+        };
+        let surge = sized(0, 0, 5_000, 80.0, 10_000.0);
+        let best = sized(1, 1, 8_000, 70.0, 10_000.0);
+        // Costlier, smaller, slower and dearer to set up than Supplier 1
+        let dominated = sized(2, 3, 7_000, 75.0, 20_000.0);
+        // Cheaper than Supplier 1 but smaller, so a trade-off
+        let trade_off = sized(3, 3, 6_000, 65.0, 20_000.0);
+        let pairs = generate_pairs_with_rules(&[surge, best, dominated, trade_off], &PairingRules::default());
+        assert_eq!(pairs.len(), 5);
+
+        let (kept, pruned) = prune_dominated(pairs, &params);
+        assert_eq!(pruned.iter().map(RejectedPair::name).collect::<Vec<String>>(), ["Supplier 2 + Supplier 0"]);
+        assert_eq!(pruned[0].reason, RejectionReason::Dominated { by: "Supplier 1 + Supplier 0".to_string() });
+        assert!(names(&kept).contains(&"Supplier 3 + Supplier 0".to_string()));
+        // Pairs with another surge supplier are not compared against Supplier 1 + Supplier 0
+        assert!(names(&kept).contains(&"Supplier 2 + Supplier 1".to_string()));
+        assert_eq!(kept.len(), 4);
+    }
+
+    #[test]
+    fn test_pairs_beaten_only_by_pruned_pairs_name_a_kept_one() {
+        use crate::monte_carlo::tests::two_product_scenario;
+
+        let (params, _) = two_product_scenario();
+        let sized = |id: usize, lead_time_months: usize, fixed_capacity: u32, unit_cost: f64, setup_cost: f64| Supplier {
+            fixed_capacity,
+            unit_costs: HashMap::from([(ProductId(0), unit_cost), (ProductId(1), unit_cost)]),
+            setup_cost,
+            ..supplier(id, lead_time_months)
+        };
+        // Supplier 1 beats Supplier 0 as a base and Supplier 3 beats Supplier 2 as a surge
+        // supplier, so Supplier 0 + Supplier 2 is beaten only by pairs that are pruned themselves
+        let suppliers = [
+            sized(0, 4, 5_000, 80.0, 20_000.0),
+            sized(1, 3, 6_000, 75.0, 10_000.0),
+            sized(2, 2, 3_000, 85.0, 20_000.0),
+            sized(3, 1, 4_000, 82.0, 10_000.0),
+        ];
+        let pair = |base: usize, surge: usize| SupplierPair {
+            base_supplier: suppliers[base].clone(),
+            surge_supplier: suppliers[surge].clone(),
+            backup_supplier: None,
+            pooled_suppliers: Vec::new(),
+        };
+        let (kept, pruned) = prune_dominated(vec![pair(0, 2), pair(1, 2), pair(0, 3), pair(1, 3)], &params);
+        assert_eq!(names(&kept), ["Supplier 1 + Supplier 3"]);
+        assert_eq!(pruned.len(), 3);
+        for rejected in &pruned {
+            assert_eq!(rejected.reason, RejectionReason::Dominated { by: "Supplier 1 + Supplier 3".to_string() }, "{}", rejected.name());
+        }
+
+        // Cheaper per unit but dearer landed, once its reservation fee is counted, Supplier 1 no
+        // longer beats Supplier 0
+        let mut reserved = suppliers.clone();
+        reserved[1].reservation_fee = Some(10.0);
+        let pair = |base: usize, surge: usize| SupplierPair { base_supplier: reserved[base].clone(), ..pair(base, surge) };
+        let (kept, _) = prune_dominated(vec![pair(0, 3), pair(1, 3)], &params);
+        assert_eq!(kept.len(), 2);
+    }
 }
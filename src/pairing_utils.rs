@@ -1,87 +1,379 @@
-/// Utility functions for supplier pairing and quick profitability checks
+//! Screening of supplier pairs before they are simulated: a quick profit estimate and the
+//! criteria that drop the pairs not worth the replications
 
-use crate::models::{SimulationParams, SupplierPair};
+use tracing::debug;
+use crate::models::{Product, ProductId, ScreeningFailure, SimulationParams, Supplier, SupplierPair};
+use crate::stats::normal_loss;
+
+/// Unit cost of a product with the order split between base and surge suppliers in proportion
+/// to their capacities, and the base portion across the committed tier in proportion to theirs
+fn landed_unit_cost(pair: &SupplierPair, product_id: ProductId) -> f64 {
+    let unit_cost = |supplier: &Supplier| supplier.unit_costs.get(&product_id).copied().unwrap_or(0.0);
+    let capacity = pair.total_capacity() as f64;
+    if capacity <= 0.0 {
+        return unit_cost(&pair.base_supplier);
+    }
+    let base_cost: f64 = pair.committed_tier()
+        .map(|supplier| supplier.fixed_capacity as f64 * unit_cost(supplier))
+        .sum();
+    (base_cost + (capacity - pair.base_capacity() as f64) * unit_cost(&pair.surge_supplier)) / capacity
+}
 
 /// Quick profitability check for a supplier pair
-/// Returns estimated profit potential without full optimization
+/// Returns estimated profit potential over the season without full optimization
 /// Used to filter out obviously poor supplier combinations early
 pub fn quick_profit_estimate(
     params: &SimulationParams,
     pair: &SupplierPair,
 ) -> f64 {
-    // Calculate weighted average unit cost
-    let mut total_weighted_cost = 0.0;
-    let mut total_demand = 0.0;
-    
-    for product in &params.products {
-        let demand_params = params.get_demand_params(product.id);
-        let demand = demand_params.map(|dp| dp.mean_demand).unwrap_or(0.0);
-        
-        // Use base supplier cost as representative
-        let unit_cost = pair.base_supplier.unit_costs.get(&product.id).copied().unwrap_or(0.0);
-        
-        total_weighted_cost += demand * unit_cost;
-        total_demand += demand;
-    }
-    
-    let avg_cost = if total_demand > 0.0 {
-        total_weighted_cost / total_demand
+    // Quick newsvendor-style estimate
+    // Assume we order ~90% of expected demand (conservative), no more than the pair can make
+    let total_demand: f64 = params.demand_params.iter().map(|dp| dp.mean_demand).sum();
+    let capacity = pair.total_capacity() as f64;
+    let order_fraction = if total_demand > 0.0 {
+        (total_demand * 0.9).min(capacity) / total_demand
     } else {
         0.0
     };
-    
-    // Calculate weighted average selling price
-    let mut total_revenue_potential = 0.0;
+
+    let mut monthly_profit = 0.0;
     for product in &params.products {
-        let demand_params = params.get_demand_params(product.id);
-        let demand = demand_params.map(|dp| dp.mean_demand).unwrap_or(0.0);
-        total_revenue_potential += demand * product.selling_price;
+        let Some(demand_params) = params.get_demand_params(product.id) else {
+            continue;
+        };
+        let order_quantity = demand_params.mean_demand * order_fraction;
+
+        // Expected sales E[min(demand, order)]: expected demand less the expected lost sales
+        let lost_sales = if demand_params.std_dev_demand > 0.0 {
+            let z = (order_quantity - demand_params.mean_demand) / demand_params.std_dev_demand;
+            demand_params.std_dev_demand * normal_loss(z)
+        } else {
+            (demand_params.mean_demand - order_quantity).max(0.0)
+        };
+        let revenue = (demand_params.mean_demand - lost_sales) * product.selling_price;
+
+        let production_cost = order_quantity * landed_unit_cost(pair, product.id);
+
+        // Rough holding cost estimate (assume 20% inventory carryover)
+        let holding_cost = order_quantity * 0.2 * product.monthly_holding_cost;
+
+        monthly_profit += revenue - production_cost - holding_cost;
     }
-    
-    let avg_price = if total_demand > 0.0 {
-        total_revenue_potential / total_demand
-    } else {
-        0.0
-    };
-    
-    // Quick newsvendor-style estimate
-    // Assume we order ~90% of expected demand (conservative)
-    let order_quantity = total_demand * 0.9;
-    
-    // Estimated revenue (assuming we can sell what we ordered)
-    let revenue = order_quantity * avg_price;
-    
-    // Estimated costs
-    let production_cost = order_quantity * avg_cost;
-    let setup_cost = pair.base_supplier.setup_cost;
-    
-    // Rough holding cost estimate (assume 20% inventory carryover)
-    let avg_holding = params.products.iter()
-        .map(|p| p.monthly_holding_cost)
-        .sum::<f64>() / params.products.len() as f64;
-    let holding_cost = order_quantity * 0.2 * avg_holding * 8.0; // 8 months
-    
+
+    // Every supplier's setup, the surge supplier's unless it is also the base supplier
+    let mut setup_cost: f64 = pair.committed_tier().map(|supplier| supplier.setup_cost).sum();
+    if !pair.is_self_paired() {
+        setup_cost += pair.surge_supplier.setup_cost;
+    }
+
     // Estimated profit
-    revenue - production_cost - setup_cost - holding_cost
+    monthly_profit * params.season_months as f64 - setup_cost
+}
+
+/// Share of expected demand a pair's combined capacity must cover to be worth evaluating
+pub const MIN_CAPACITY_COVERAGE: f64 = 0.7;
+
+/// What a supplier pair must reach in quick screening to be worth fully evaluating
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScreeningCriteria {
+    /// Lowest quick profit estimate
+    pub min_profit: f64,
+    /// Lowest share of the expected monthly demand the pair's capacity must cover
+    pub min_capacity_coverage: f64,
+    /// Highest landed unit cost as a share of the selling price, weighted by expected demand
+    pub max_cost_to_price: Option<f64>,
+    /// Most the suppliers' setup costs may add up to, backup included
+    pub max_total_setup_cost: Option<f64>,
+}
+
+impl Default for ScreeningCriteria {
+    /// Keep every pair with a positive estimate and enough capacity
+    fn default() -> Self {
+        ScreeningCriteria { min_profit: 0.0, min_capacity_coverage: MIN_CAPACITY_COVERAGE, max_cost_to_price: None, max_total_setup_cost: None }
+    }
+}
+
+/// How a supplier pair fared in quick screening
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScreeningReport {
+    pub estimate: f64,
+    /// Combined capacity over the expected monthly demand
+    pub capacity_ratio: f64,
+    pub passed: bool,
+    /// Every check the pair failed, in the order they are made
+    pub failures: Vec<ScreeningFailure>,
 }
 
 /// Check if a supplier pair is worth fully evaluating
-/// Returns true if the pair passes basic profitability thresholds
-pub fn is_pair_promising(
+/// Makes every check in `criteria`: the quick estimate, the capacity against expected demand,
+/// and, when a maximum is set, the landed cost against the selling price and the total setup
+/// cost against its cap
+pub fn screen_pair(
     params: &SimulationParams,
     pair: &SupplierPair,
-    min_profit_threshold: f64,
-) -> bool {
+    criteria: &ScreeningCriteria,
+) -> ScreeningReport {
     let estimate = quick_profit_estimate(params, pair);
-    
+    let mut failures = Vec::new();
+    if estimate < criteria.min_profit {
+        failures.push(ScreeningFailure::EstimateBelowThreshold { estimate, threshold: criteria.min_profit });
+    }
+
     // Also check if pair has sufficient capacity
-    let total_capacity = pair.base_supplier.fixed_capacity + pair.surge_supplier.fixed_capacity;
-    let total_demand: f64 = params.demand_params.iter()
+    let capacity = pair.total_capacity();
+    let demand: f64 = params.demand_params.iter()
         .map(|dp| dp.mean_demand)
         .sum();
-    
-    // Require capacity to be at least 70% of expected demand
-    let has_adequate_capacity = (total_capacity as f64) >= (total_demand * 0.7);
-    
-    estimate >= min_profit_threshold && has_adequate_capacity
+    let capacity_ratio = if demand > 0.0 { capacity as f64 / demand } else { f64::INFINITY };
+    if capacity_ratio < criteria.min_capacity_coverage {
+        failures.push(ScreeningFailure::InadequateCapacity { capacity, demand, min_coverage: criteria.min_capacity_coverage });
+    }
+
+    if let Some(max) = criteria.max_cost_to_price {
+        let weighted = |value: &dyn Fn(&Product) -> f64| params.products.iter()
+            .map(|product| params.get_demand_params(product.id).map_or(0.0, |dp| dp.mean_demand) * value(product))
+            .sum::<f64>();
+        let revenue = weighted(&|product| product.selling_price);
+        if revenue > 0.0 {
+            let cost_to_price = weighted(&|product| landed_unit_cost(pair, product.id)) / revenue;
+            if cost_to_price > max {
+                failures.push(ScreeningFailure::CostlyForPrice { cost_to_price, max });
+            }
+        }
+    }
+
+    if let Some(max) = criteria.max_total_setup_cost {
+        let setup_cost = pair.total_setup_cost();
+        if setup_cost > max {
+            failures.push(ScreeningFailure::SetupCostOverCap { setup_cost, max });
+        }
+    }
+
+    if !failures.is_empty() {
+        debug!(
+            base = %pair.base_supplier.name,
+            surge = %pair.surge_supplier.name,
+            ?failures,
+            "pair eliminated by screening"
+        );
+    }
+    ScreeningReport { estimate, capacity_ratio, passed: failures.is_empty(), failures }
+}
+
+/// Whether a supplier pair passes screening at a profit threshold and the default capacity check
+#[allow(dead_code)]
+pub fn is_pair_promising(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    min_profit_threshold: f64,
+) -> bool {
+    screen_pair(params, pair, &ScreeningCriteria { min_profit: min_profit_threshold, ..ScreeningCriteria::default() }).passed
+}
+
+/// The pairs that passed screening, most promising estimate first, so the best are evaluated
+/// before any time budget runs out
+pub fn promising_by_estimate<'a>(screened: &[(&'a SupplierPair, ScreeningReport)]) -> Vec<&'a SupplierPair> {
+    let mut passed: Vec<&(&SupplierPair, ScreeningReport)> = screened.iter().filter(|(_, report)| report.passed).collect();
+    passed.sort_by(|(_, a), (_, b)| b.estimate.total_cmp(&a.estimate));
+    passed.into_iter().map(|(pair, _)| *pair).collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monte_carlo::tests::two_product_scenario;
+    use std::collections::HashMap;
+    use crate::models::SupplierId;
+
+    #[test]
+    fn test_promising_pair_passes_screening() {
+        let (params, pair) = two_product_scenario();
+        let report = screen_pair(&params, &pair, &ScreeningCriteria::default());
+        assert!(report.passed && report.failures.is_empty());
+        assert_eq!(report.estimate, quick_profit_estimate(&params, &pair));
+        assert_eq!(report.capacity_ratio, 12_000.0 / 9_000.0);
+        assert!(is_pair_promising(&params, &pair, 0.0));
+        assert!(!is_pair_promising(&params, &pair, report.estimate + 1.0));
+    }
+
+    #[test]
+    fn test_screening_reports_a_low_estimate() {
+        let (params, mut pair) = two_product_scenario();
+        pair.base_supplier.setup_cost = 1e9;
+        let estimate = quick_profit_estimate(&params, &pair);
+        assert!(estimate < 0.0);
+        let report = screen_pair(&params, &pair, &ScreeningCriteria::default());
+        assert!(!report.passed);
+        assert_eq!(report.failures, [ScreeningFailure::EstimateBelowThreshold { estimate, threshold: 0.0 }]);
+    }
+
+    #[test]
+    fn test_screening_reports_inadequate_capacity() {
+        let (params, mut pair) = two_product_scenario();
+        pair.base_supplier.fixed_capacity = 100;
+        pair.surge_supplier.fixed_capacity = 100;
+        pair.base_supplier.setup_cost = 0.0;
+        pair.surge_supplier.setup_cost = 0.0;
+        let demand: f64 = params.demand_params.iter().map(|dp| dp.mean_demand).sum();
+        let report = screen_pair(&params, &pair, &ScreeningCriteria::default());
+        assert_eq!(report.failures, [ScreeningFailure::InadequateCapacity { capacity: 200, demand, min_coverage: 0.7 }]);
+
+        // A lower coverage requirement lets the same pair through
+        let lenient = ScreeningCriteria { min_capacity_coverage: 0.02, ..ScreeningCriteria::default() };
+        assert!(screen_pair(&params, &pair, &lenient).passed);
+    }
+
+    #[test]
+    fn test_screening_reports_a_high_cost_to_price() {
+        // Units cost 7/12 x 75 + 5/12 x 80 = 77.08 against a price of 100
+        let (params, pair) = two_product_scenario();
+        let capped = |max: f64| ScreeningCriteria { max_cost_to_price: Some(max), ..ScreeningCriteria::default() };
+        assert!(screen_pair(&params, &pair, &capped(0.8)).passed);
+        let report = screen_pair(&params, &pair, &capped(0.75));
+        let [ScreeningFailure::CostlyForPrice { cost_to_price, max }] = report.failures[..] else {
+            panic!("{:?}", report.failures);
+        };
+        assert!((cost_to_price - (7.0 * 75.0 + 5.0 * 80.0) / 12.0 / 100.0).abs() < 1e-12);
+        assert_eq!(max, 0.75);
+    }
+
+    #[test]
+    fn test_screening_reports_setup_costs_over_the_cap() {
+        let (params, mut pair) = two_product_scenario();
+        pair.base_supplier.setup_cost = 1_000_000.0;
+        pair.surge_supplier.setup_cost = 2_000_000.0;
+        let capped = |max: f64| ScreeningCriteria { min_profit: f64::NEG_INFINITY, max_total_setup_cost: Some(max), ..ScreeningCriteria::default() };
+        assert!(screen_pair(&params, &pair, &capped(3_000_000.0)).passed);
+        let report = screen_pair(&params, &pair, &capped(2_500_000.0));
+        assert_eq!(report.failures, [ScreeningFailure::SetupCostOverCap { setup_cost: 3_000_000.0, max: 2_500_000.0 }]);
+
+        // A backup's setup counts towards the cap too
+        let mut triple = pair.clone();
+        triple.backup_supplier = Some(Supplier { id: SupplierId(2), setup_cost: 100_000.0, ..pair.surge_supplier.clone() });
+        assert!(!screen_pair(&params, &triple, &capped(3_000_000.0)).passed);
+    }
+
+    #[test]
+    fn test_every_failed_check_is_reported() {
+        let (params, mut pair) = two_product_scenario();
+        pair.base_supplier.fixed_capacity = 100;
+        pair.surge_supplier.fixed_capacity = 100;
+        pair.base_supplier.setup_cost = 1e9;
+        let criteria = ScreeningCriteria { max_cost_to_price: Some(0.5), max_total_setup_cost: Some(1e6), ..ScreeningCriteria::default() };
+        let report = screen_pair(&params, &pair, &criteria);
+        assert!(matches!(report.failures[..], [
+            ScreeningFailure::EstimateBelowThreshold { .. },
+            ScreeningFailure::InadequateCapacity { .. },
+            ScreeningFailure::CostlyForPrice { .. },
+            ScreeningFailure::SetupCostOverCap { .. },
+        ]));
+    }
+
+    #[test]
+    fn test_promising_pairs_come_best_estimate_first() {
+        let (params, pair) = two_product_scenario();
+        let with_surge_cost = |unit_cost: f64| {
+            let mut pair = pair.clone();
+            pair.surge_supplier.unit_costs = HashMap::from([(ProductId(0), unit_cost), (ProductId(1), unit_cost)]);
+            pair
+        };
+        let mut failing = pair.clone();
+        failing.base_supplier.setup_cost = 1e9;
+        let pairs = [with_surge_cost(90.0), failing, with_surge_cost(70.0), with_surge_cost(80.0)];
+        let screened: Vec<(&SupplierPair, ScreeningReport)> = pairs.iter()
+            .map(|pair| (pair, screen_pair(&params, pair, &ScreeningCriteria::default())))
+            .collect();
+        let surge_costs: Vec<f64> = promising_by_estimate(&screened).iter()
+            .map(|pair| pair.surge_supplier.unit_costs[&ProductId(0)])
+            .collect();
+        assert_eq!(surge_costs, [70.0, 80.0, 90.0]);
+    }
+
+    #[test]
+    fn test_estimate_counts_the_surge_suppliers_cost() {
+        let (params, pair) = two_product_scenario();
+        let mut dearer = pair.clone();
+        dearer.surge_supplier.unit_costs = HashMap::from([(ProductId(0), 95.0), (ProductId(1), 95.0)]);
+        // 7 of every 12 units come from the base supplier at 75, the rest from the surge supplier
+        let estimate = quick_profit_estimate(&params, &pair);
+        let surge_units = 0.9 * 9_000.0 * 5.0 / 12.0 * params.season_months as f64;
+        assert!((estimate - quick_profit_estimate(&params, &dearer) - 15.0 * surge_units).abs() < 1e-6);
+    }
+
+    /// The estimate the screening used before it priced the surge supplier and the capacity
+    /// shortfall: the base supplier's cost and setup for one month of 90% of demand
+    fn base_only_estimate(params: &SimulationParams, pair: &SupplierPair) -> f64 {
+        let demand: f64 = params.demand_params.iter().map(|dp| dp.mean_demand).sum();
+        let weighted = |value: &dyn Fn(ProductId) -> f64| params.demand_params.iter()
+            .map(|dp| dp.mean_demand * value(dp.product_id))
+            .sum::<f64>() / demand;
+        let avg_cost = weighted(&|product_id| pair.base_supplier.unit_costs[&product_id]);
+        let avg_price = weighted(&|product_id| params.products[product_id.0 as usize].selling_price);
+        let avg_holding = params.products.iter().map(|p| p.monthly_holding_cost).sum::<f64>() / params.products.len() as f64;
+        let order_quantity = demand * 0.9;
+        order_quantity * (avg_price - avg_cost)
+            - pair.base_supplier.setup_cost
+            - order_quantity * 0.2 * avg_holding * params.season_months as f64
+    }
+
+    /// Ranks of `values`, ties sharing their mean rank
+    fn ranks(values: &[f64]) -> Vec<f64> {
+        values.iter()
+            .map(|value| {
+                let below = values.iter().filter(|other| *other < value).count() as f64;
+                let tied = values.iter().filter(|other| *other == value).count() as f64;
+                below + (tied + 1.0) / 2.0
+            })
+            .collect()
+    }
+
+    /// Spearman's rank correlation: the Pearson correlation of the ranks
+    fn rank_correlation(a: &[f64], b: &[f64]) -> f64 {
+        let (a, b) = (ranks(a), ranks(b));
+        let mean = |ranks: &[f64]| ranks.iter().sum::<f64>() / ranks.len() as f64;
+        let (mean_a, mean_b) = (mean(&a), mean(&b));
+        let covariance: f64 = a.iter().zip(&b).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum();
+        let spread = |ranks: &[f64], mean: f64| ranks.iter().map(|x| (x - mean).powi(2)).sum::<f64>().sqrt();
+        covariance / (spread(&a, mean_a) * spread(&b, mean_b))
+    }
+
+    #[test]
+    fn test_estimate_ranks_pairs_closer_to_the_simulation() {
+        use crate::monte_carlo::{run_monte_carlo_simulation, MonteCarloConfig};
+        use crate::simulation::split_order_quantities;
+
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        let variant = |base: (u32, f64), surge: (u32, f64)| {
+            let mut variant = pair.clone();
+            variant.base_supplier.fixed_capacity = base.0;
+            variant.base_supplier.unit_costs = HashMap::from([(ProductId(0), base.1), (ProductId(1), base.1)]);
+            variant.surge_supplier.fixed_capacity = surge.0;
+            variant.surge_supplier.unit_costs = HashMap::from([(ProductId(0), surge.1), (ProductId(1), surge.1)]);
+            variant
+        };
+        let pairs = [
+            variant((7_000, 75.0), (5_000, 80.0)),
+            variant((7_000, 75.0), (5_000, 85.0)),
+            variant((7_000, 75.0), (5_000, 95.0)),
+            variant((4_000, 75.0), (1_000, 80.0)),
+            variant((3_000, 75.0), (2_000, 80.0)),
+            variant((7_000, 85.0), (5_000, 80.0)),
+        ];
+        let config = MonteCarloConfig { master_seed: 30, ..MonteCarloConfig::new(20) };
+        let simulated: Vec<f64> = pairs.iter()
+            .map(|pair| {
+                let order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], pair, &params);
+                run_monte_carlo_simulation(&params, pair, &order, &config).unwrap().mean_profit
+            })
+            .collect();
+        let estimated = |estimate: fn(&SimulationParams, &SupplierPair) -> f64| {
+            pairs.iter().map(|pair| estimate(&params, pair)).collect::<Vec<f64>>()
+        };
+
+        let before = rank_correlation(&estimated(base_only_estimate), &simulated);
+        let after = rank_correlation(&estimated(quick_profit_estimate), &simulated);
+        assert!(after > before, "{} against {} before", after, before);
+    }
+}
+
@@ -0,0 +1,202 @@
+//! Plans spanning several seasons with the same supplier pair
+//! Each season's order is optimized knowing what the season before it leaves behind: the stock
+//! it carries over, which the new order need not buy again, and the suppliers it onboarded, whose
+//! setup is not paid twice; later seasons' profits are discounted back to the first
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::demand::with_demand_stream;
+use crate::error::SimulationError;
+use crate::models::{MonteCarloStats, ProductId, SimulationParams, SupplierId, SupplierPair};
+use crate::monte_carlo::{replication_seed, run_monte_carlo_simulation, MonteCarloConfig, SampleRetention};
+use crate::optimizer::find_optimal_plan;
+use crate::progress::NoProgress;
+use crate::simulation::split_order_quantities;
+use crate::streaming::interpolated_quantile;
+
+/// How a multi-season plan is simulated and its seasons' profits combined
+#[derive(Clone, Debug)]
+pub struct MultiSeasonConfig {
+    /// Replications of each season; the master seed gives every season its own demand draws
+    pub monte_carlo: MonteCarloConfig,
+    /// Rate at which a season's profit is discounted for each season it lies beyond the first
+    pub discount_rate: f64,
+}
+
+/// One season of a multi-season plan: what it started with, what it ordered and how it did
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SeasonPlan {
+    /// Stock carried over from the season before, as (product, units)
+    pub opening_inventory: Vec<(ProductId, u32)>,
+    /// Suppliers onboarded in earlier seasons, whose setup this season does not pay
+    pub onboarded_suppliers: Vec<SupplierId>,
+    /// Optimal monthly quantity of each product
+    pub optimal_quantities: Vec<(ProductId, u32)>,
+    /// Months in the season, however finely it was simulated
+    pub season_months: usize,
+    /// Multiplier bringing the season's profit back to the first season
+    pub discount_factor: f64,
+    pub stats: MonteCarloStats,
+}
+
+impl SeasonPlan {
+    /// Units the season orders of every product over all its months
+    pub fn units_ordered(&self) -> u32 {
+        self.optimal_quantities.iter().map(|&(_, quantity)| quantity * self.season_months as u32).sum()
+    }
+}
+
+/// Seasons planned one after another, and the distribution of their discounted total profit
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MultiSeasonPlan {
+    pub seasons: Vec<SeasonPlan>,
+    /// Net present value of each replication's seasons, by replication
+    pub npv_samples: Vec<f64>,
+    pub mean_npv: f64,
+    /// NPV percentiles as (level in percent, NPV), at the Monte Carlo config's levels
+    pub npv_percentiles: Vec<(f64, f64)>,
+}
+
+/// Plan the seasons in `params_per_season` one after another with `pair`
+/// Each season's order is optimized and simulated with the mean stock the season before closed
+/// with as its opening inventory, rounded to whole units, and with the base and surge suppliers
+/// earlier seasons ordered from already onboarded; a backup or pooled supplier, which delivers in
+/// some replications and not others, pays its setup in every season it delivers. Every season but
+/// the last carries its leftovers over instead of liquidating them, though each is optimized as
+/// if it liquidated them, the liquidation price standing in for what they are worth next season.
+/// Replication i of every season adds to replication i's NPV
+pub fn multi_season(
+    params_per_season: Vec<SimulationParams>,
+    pair: &SupplierPair,
+    config: &MultiSeasonConfig,
+) -> Result<MultiSeasonPlan, SimulationError> {
+    let season_count = params_per_season.len();
+    let mut opening_inventory = Vec::new();
+    let mut onboarded_suppliers = Vec::new();
+    let mut seasons: Vec<SeasonPlan> = Vec::with_capacity(season_count);
+    let mut npv_samples = vec![0.0; config.monte_carlo.num_simulations];
+
+    for (season, params) in params_per_season.into_iter().enumerate() {
+        let season_seed = replication_seed(config.monte_carlo.master_seed, season);
+        let mut params = SimulationParams {
+            opening_inventory: opening_inventory.clone(),
+            onboarded_suppliers: onboarded_suppliers.clone(),
+            carry_over_stock: false,
+            ..params
+        };
+        let (optimal_quantities, reservations, _) = with_demand_stream(season_seed, || find_optimal_plan(&params, pair, &NoProgress))?;
+        let mut monthly_order = split_order_quantities(&optimal_quantities, pair, &params);
+        monthly_order.reservations = reservations;
+
+        params.carry_over_stock = season + 1 < season_count;
+        let monte_carlo = MonteCarloConfig {
+            master_seed: season_seed,
+            sample_retention: SampleRetention::All,
+            ..config.monte_carlo.clone()
+        };
+        let mut stats = run_monte_carlo_simulation(&params, pair, &monthly_order, &monte_carlo)?;
+
+        let discount_factor = (1.0 + config.discount_rate).powi(-(season as i32));
+        stats.samples.sort_by_key(|sample| sample.replication);
+        for (npv, sample) in npv_samples.iter_mut().zip(&stats.samples) {
+            *npv += discount_factor * sample.profit;
+        }
+        // Samples were kept only to pair replications across seasons
+        stats.samples = match config.monte_carlo.sample_retention {
+            SampleRetention::All => stats.samples,
+            _ => Vec::new(),
+        };
+
+        opening_inventory = stats.product_breakdown.iter()
+            .map(|product| (product.product_id, product.mean_closing_inventory.round() as u32))
+            .filter(|&(_, units)| units > 0)
+            .collect();
+        for (supplier, ordered) in [(&pair.base_supplier, monthly_order.total_base_quantity()), (&pair.surge_supplier, monthly_order.total_surge_quantity())] {
+            if ordered > 0 && !onboarded_suppliers.contains(&supplier.id) {
+                onboarded_suppliers.push(supplier.id);
+            }
+        }
+        seasons.push(SeasonPlan {
+            opening_inventory: params.opening_inventory,
+            onboarded_suppliers: params.onboarded_suppliers,
+            optimal_quantities,
+            season_months: params.season_months,
+            discount_factor,
+            stats,
+        });
+    }
+
+    let mean_npv = npv_samples.iter().sum::<f64>() / npv_samples.len().max(1) as f64;
+    let mut sorted = npv_samples.clone();
+    sorted.sort_by(f64::total_cmp);
+    let npv_percentiles = config.monte_carlo.percentile_levels.iter()
+        .map(|&level| (level, interpolated_quantile(&sorted, level / 100.0)))
+        .collect();
+    Ok(MultiSeasonPlan { seasons, npv_samples, mean_npv, npv_percentiles })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monte_carlo::tests::two_product_scenario;
+
+    fn config() -> MultiSeasonConfig {
+        MultiSeasonConfig {
+            monte_carlo: MonteCarloConfig { master_seed: 11, parallel: false, ..MonteCarloConfig::new(200) },
+            discount_rate: 0.1,
+        }
+    }
+
+    /// Two identical seasons in which demand turns out 15% below the forecast the plan is made on,
+    /// so the first leaves stock behind, with no order changes to hold it back
+    fn two_seasons() -> (Vec<SimulationParams>, SupplierPair) {
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees.clear();
+        for dp in &mut params.demand_params {
+            dp.actual_mean_demand = 0.85 * dp.mean_demand;
+        }
+        (vec![params.clone(), params], pair)
+    }
+
+    #[test]
+    fn test_second_season_orders_less_by_the_carried_over_stock() {
+        let (seasons, pair) = two_seasons();
+        let plan = multi_season(seasons, &pair, &config()).unwrap();
+        let [first, second] = &plan.seasons[..] else { panic!("two seasons") };
+
+        assert!(first.opening_inventory.is_empty());
+        let carried: u32 = second.opening_inventory.iter().map(|&(_, units)| units).sum();
+        let closing: f64 = first.stats.product_breakdown.iter().map(|product| product.mean_closing_inventory).sum();
+        assert!(carried > 0);
+        assert!((carried as f64 - closing).abs() <= 1.0, "{} vs {}", carried, closing);
+        // The first season carries its leftovers over, the last liquidates them
+        let liquidated = |season: &SeasonPlan| season.stats.product_breakdown.iter().map(|product| product.mean_liquidation_revenue).sum::<f64>();
+        assert_eq!(liquidated(first), 0.0);
+        assert!(liquidated(second) > 0.0);
+
+        // The optimizer searches the same grid shifted down by the carried-over stock, so the
+        // orders differ by it up to the fine pass's spacing, 7.5% of a quantity
+        let reduction = first.units_ordered() as f64 - second.units_ordered() as f64;
+        let spacing = 0.075 * first.units_ordered() as f64;
+        assert!((reduction - carried as f64).abs() <= spacing, "ordered {} less with {} carried over", reduction, carried);
+    }
+
+    #[test]
+    fn test_later_seasons_skip_setup_and_are_discounted() {
+        let (seasons, pair) = two_seasons();
+        let plan = multi_season(seasons, &pair, &config()).unwrap();
+        let [first, second] = &plan.seasons[..] else { panic!("two seasons") };
+
+        assert_eq!(first.stats.mean_setup_cost, pair.base_supplier.setup_cost + pair.surge_supplier.setup_cost);
+        assert_eq!(second.onboarded_suppliers, vec![pair.base_supplier.id, pair.surge_supplier.id]);
+        assert_eq!(second.stats.mean_setup_cost, 0.0);
+
+        assert_eq!(second.discount_factor, 1.0 / 1.1);
+        let expected = first.stats.mean_profit + second.stats.mean_profit / 1.1;
+        assert!((plan.mean_npv - expected).abs() < 1e-6 * expected.abs(), "{} vs {}", plan.mean_npv, expected);
+        assert_eq!(plan.npv_samples.len(), 200);
+        assert!(first.stats.samples.is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+//! Progress reporting for long runs
+//! The optimizer and Monte Carlo runner report through a `ProgressSink` instead of printing,
+//! so callers choose whether and how progress is shown
+
+#[cfg(feature = "cli")]
+use std::sync::Mutex;
+#[cfg(feature = "cli")]
+use indicatif::{ProgressBar, ProgressStyle};
+use crate::models::SupplierPair;
+
+/// Receiver of progress events; every method does nothing unless overridden
+/// Replication events arrive from the rayon worker threads, hence `Sync`
+pub trait ProgressSink: Sync {
+    /// A supplier pair is about to be optimized and simulated
+    fn on_pair_start(&self, _pair: &SupplierPair) {}
+
+    /// The optimizer has evaluated `evaluated` of its `total` candidate plans
+    fn on_optimizer_progress(&self, _evaluated: usize, _total: usize) {}
+
+    /// `done` of `total` Monte Carlo replications have finished; with parallel replications
+    /// the calls may arrive slightly out of order
+    fn on_replication(&self, _done: usize, _total: usize) {}
+
+    /// `done` of `total` supplier pairs evaluated in parallel have finished
+    fn on_pair_finished(&self, _done: usize, _total: usize) {}
+}
+
+/// Sink that ignores all progress
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {}
+
+/// Progress bars on stderr, one per optimizer search and Monte Carlo run, or one for all the
+/// pairs evaluated in parallel
+#[cfg(feature = "cli")]
+#[derive(Default)]
+pub struct TerminalProgress {
+    bar: Mutex<Option<ProgressBar>>,
+}
+
+#[cfg(feature = "cli")]
+impl TerminalProgress {
+    pub fn new() -> Self {
+        TerminalProgress { bar: Mutex::new(None) }
+    }
+
+    /// Advance the bar of the current phase by one step, starting it on the first step and
+    /// clearing it once all `total` steps are done
+    fn step(&self, phase: &'static str, total: usize) {
+        let mut current = self.bar.lock().unwrap();
+        let bar = current.get_or_insert_with(|| {
+            let bar = ProgressBar::new(total as u64);
+            bar.set_style(
+                ProgressStyle::with_template("  {prefix:<12} [{bar:40}] {pos}/{len} ({eta} left)")
+                    .unwrap()
+                    .progress_chars("=> "),
+            );
+            bar.set_prefix(phase);
+            bar
+        });
+        bar.inc(1);
+        if bar.position() >= total as u64 {
+            bar.finish_and_clear();
+            *current = None;
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl ProgressSink for TerminalProgress {
+    fn on_pair_start(&self, _pair: &SupplierPair) {
+        // Drop any bar an interrupted phase left behind
+        if let Some(bar) = self.bar.lock().unwrap().take() {
+            bar.finish_and_clear();
+        }
+    }
+
+    fn on_optimizer_progress(&self, _evaluated: usize, total: usize) {
+        self.step("Optimizing", total);
+    }
+
+    fn on_replication(&self, _done: usize, total: usize) {
+        self.step("Simulating", total);
+    }
+
+    fn on_pair_finished(&self, _done: usize, total: usize) {
+        self.step("Evaluating", total);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Mutex;
+    use super::*;
+
+    /// Sink recording every event, for checking callbacks against budgets
+    #[derive(Default)]
+    pub struct RecordingProgress {
+        pub pairs: Mutex<Vec<String>>,
+        pub optimizer: Mutex<Vec<(usize, usize)>>,
+        pub replications: Mutex<Vec<(usize, usize)>>,
+        pub finished: Mutex<Vec<(usize, usize)>>,
+    }
+
+    impl ProgressSink for RecordingProgress {
+        fn on_pair_start(&self, pair: &SupplierPair) {
+            self.pairs.lock().unwrap().push(pair.base_supplier.name.clone());
+        }
+
+        fn on_optimizer_progress(&self, evaluated: usize, total: usize) {
+            self.optimizer.lock().unwrap().push((evaluated, total));
+        }
+
+        fn on_replication(&self, done: usize, total: usize) {
+            self.replications.lock().unwrap().push((done, total));
+        }
+
+        fn on_pair_finished(&self, done: usize, total: usize) {
+            self.finished.lock().unwrap().push((done, total));
+        }
+    }
+}
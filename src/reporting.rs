@@ -1,117 +1,3395 @@
-/// Reporting and output formatting module
-/// Handles all console output and result presentation
-/// Updated for multi-product simulation
+//! Output of a run: the console tables through a `Reporter`, JSON, and the CSV, Parquet, Excel,
+//! Markdown and HTML exports
 
-use crate::models::MonteCarloStats;
+use std::cell::{RefCell, RefMut};
+use std::fmt::{Display, Write as _};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use anstream::{AutoStream, ColorChoice};
+use anstyle::{AnsiColor, Style};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use crate::models::{committed_tier_name, LossDriver, MonteCarloStats, MonthStats, MonthlyOrder, MonthlyResult, PairRun, PairedComparison, ProductId, ProductMonthlyResult, ProductPnl, RankingMetric, RejectedPair, RejectionReason, ReplicationTrace, RunOutput, ScreeningFailure, SimulationParams, Supplier, SupplierPair, TimeStep};
+use crate::analysis::{DualSourceValue, PlanComparison, ScenarioResults, SensitivityResult};
+#[cfg(feature = "money")]
+use crate::money::Money;
+#[cfg(feature = "sqlite")]
+use crate::history::RunSummary;
+use crate::capacity::{is_fragile, utilization, FRAGILE_UTILIZATION};
+use crate::optimizer::OptimizerDiagnostics;
+use crate::pairing::SupplierScore;
+use crate::planning::MultiSeasonPlan;
+use crate::selection::Selection;
+use crate::simulation::month_name;
 
-/// Display Monte Carlo results for all supplier combinations
-pub fn display_all_results(mut results: Vec<MonteCarloStats>) {
-    println!("\n╔═══════════════════════════════════════════════════════════════════════════════════════════════════╗");
-    println!("║                           MONTE CARLO RESULTS - ALL COMBINATIONS                                  ║");
-    println!("╚═══════════════════════════════════════════════════════════════════════════════════════════════════╝\n");
+/// Width assumed for the terminal when `COLUMNS` is not set
+const DEFAULT_TERMINAL_WIDTH: usize = 80;
 
-    // Sort results by mean profit
-    results.sort_by(|a, b| b.mean_profit.partial_cmp(&a.mean_profit).unwrap());
+/// Widest a banner is drawn, however wide the terminal
+const MAX_BANNER_WIDTH: usize = 100;
 
-    for (rank, result) in results.iter().enumerate() {
-        println!(
-            "{}. {} ({}mo lead) + {} ({}mo lead)",
-            rank + 1,
-            result.base_supplier, result.base_supplier_lead_time,
-            result.surge_supplier, result.surge_supplier_lead_time,
+/// Narrowest a truncatable table column is cut to, ellipsis included
+const MIN_TRUNCATED_WIDTH: usize = 8;
+
+/// The top-ranked pair
+const BEST: Style = AnsiColor::Green.on_default().bold();
+/// Negative profits in the distribution
+const LOSS: Style = AnsiColor::Red.on_default();
+/// Warnings about the plan or the statistics behind it
+const WARNING: Style = AnsiColor::Yellow.on_default();
+
+/// `print!` through a reporter's stream
+/// Console output is best effort: a closed stdout must not stop the run
+macro_rules! report {
+    ($reporter:expr, $($arg:tt)*) => {{
+        let _ = write!($reporter.out(), $($arg)*);
+    }};
+}
+
+/// `println!` through a reporter's stream
+macro_rules! reportln {
+    ($reporter:expr) => {{
+        let _ = writeln!($reporter.out());
+    }};
+    ($reporter:expr, $($arg:tt)*) => {{
+        let _ = writeln!($reporter.out(), $($arg)*);
+    }};
+}
+
+/// How a run presents its results on stdout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Tables and messages for a person at a terminal
+    Text,
+    /// A single JSON document describing the whole run, and nothing else
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("expected text or json, got \"{}\"", s)),
+        }
+    }
+}
+
+/// How much a run prints in text mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only the final ranked summary, for scripts
+    Quiet,
+    /// Banners, per-pair progress and the best pair's details
+    Normal,
+    /// Also the optimizer's diagnostics and how long each pair took
+    Verbose,
+}
+
+/// How amounts of money and counts are written: the currency symbol and which side of the
+/// number it goes, the decimal and grouping separators, and the decimal places on amounts
+#[derive(Clone, Debug, PartialEq)]
+pub struct Formatter {
+    pub currency: String,
+    /// Write the symbol after the amount, e.g. 1 234,50 € rather than €1 234,50
+    pub currency_after: bool,
+    pub decimal_separator: char,
+    pub grouping_separator: char,
+    /// Decimal places on amounts of money
+    pub decimal_places: usize,
+    /// Abbreviate amounts in the ranking table, e.g. $10.2M
+    pub compact: bool,
+}
+
+impl Default for Formatter {
+    /// US style: $10,234,567.89
+    fn default() -> Self {
+        Formatter {
+            currency: "$".to_string(),
+            currency_after: false,
+            decimal_separator: '.',
+            grouping_separator: ',',
+            decimal_places: 2,
+            compact: false,
+        }
+    }
+}
+
+impl Formatter {
+    /// Continental European style: 10 234 567,89 €
+    pub fn european() -> Self {
+        Formatter {
+            currency: "€".to_string(),
+            currency_after: true,
+            decimal_separator: ',',
+            grouping_separator: ' ',
+            ..Formatter::default()
+        }
+    }
+
+    /// Number rounded to `places` decimals with grouped thousands, e.g. -1,234.57
+    /// A value that rounds to zero is written without a sign
+    pub fn number(&self, value: f64, places: usize) -> String {
+        let rounded = format!("{:.*}", places, value.abs());
+        let (whole, fraction) = rounded.split_once('.').unwrap_or((&rounded, ""));
+        let is_zero = rounded.bytes().all(|byte| byte == b'0' || byte == b'.');
+        let mut number = String::with_capacity(rounded.len() + whole.len() / 3 + 1);
+        if value < 0.0 && !is_zero {
+            number.push('-');
+        }
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i).is_multiple_of(3) {
+                number.push(self.grouping_separator);
+            }
+            number.push(digit);
+        }
+        if !fraction.is_empty() {
+            number.push(self.decimal_separator);
+            number.push_str(fraction);
+        }
+        number
+    }
+
+    /// Amount rounded to `places` decimals with the currency symbol, e.g. -$1,234.57
+    pub fn money_with(&self, amount: f64, places: usize) -> String {
+        self.with_currency(self.number(amount, places))
+    }
+
+    /// Amount with the configured decimal places, e.g. $10,234,567.89
+    pub fn money(&self, amount: f64) -> String {
+        self.money_with(amount, self.decimal_places)
+    }
+
+    /// Amount in whole currency units, e.g. $10,234,568
+    pub fn whole_money(&self, amount: f64) -> String {
+        self.money_with(amount, 0)
+    }
+
+    /// Whole count with grouping separators, e.g. 35,000
+    pub fn count(&self, count: f64) -> String {
+        self.number(count, 0)
+    }
+
+    /// Whole count signed, e.g. +3,000 or -1,500; zero is written without a sign
+    pub fn signed_count(&self, count: f64) -> String {
+        let count = self.count(count);
+        if count.starts_with('-') || count == "0" { count } else { format!("+{}", count) }
+    }
+
+    /// Amount to one decimal in thousands, millions or billions, e.g. $10.2M or -$850.0k
+    /// Amounts under a thousand are written whole
+    pub fn compact_money(&self, amount: f64) -> String {
+        const UNITS: [(f64, &str); 3] = [(1e3, "k"), (1e6, "M"), (1e9, "B")];
+        let magnitude = amount.abs();
+        let Some(mut unit) = UNITS.iter().rposition(|&(scale, _)| magnitude.round() >= scale) else {
+            return self.whole_money(amount);
+        };
+        // Step up a unit when rounding would reach it, so 999,960 is $1.0M rather than $1,000.0k
+        if unit + 1 < UNITS.len() && (magnitude / UNITS[unit].0 * 10.0).round() >= 10_000.0 {
+            unit += 1;
+        }
+        let (scale, suffix) = UNITS[unit];
+        self.with_currency(format!("{}{}", self.number(amount / scale, 1), suffix))
+    }
+
+    /// Amount with its own currency's symbol and decimal places, e.g. ¥1,234,567
+    #[cfg(feature = "money")]
+    pub fn currency_money(&self, money: Money) -> String {
+        let formatter = Formatter { currency: money.currency().symbol().to_string(), ..self.clone() };
+        formatter.money_with(money.as_f64(), money.currency().decimal_places())
+    }
+
+    /// Amount for the ranking table: compact when asked for, otherwise as `money`
+    pub fn table_money(&self, amount: f64) -> String {
+        if self.compact { self.compact_money(amount) } else { self.money(amount) }
+    }
+
+    /// Place the currency symbol on a formatted number, outside its sign
+    fn with_currency(&self, number: String) -> String {
+        if self.currency_after {
+            format!("{} {}", number, self.currency)
+        } else if let Some(magnitude) = number.strip_prefix('-') {
+            format!("-{}{}", self.currency, magnitude)
+        } else {
+            format!("{}{}", self.currency, number)
+        }
+    }
+}
+
+/// Console output of a run
+/// Every human-oriented message goes through the reporter, which prints it only at a verbosity
+/// that asks for it and suppresses everything in JSON mode so that stdout carries only the
+/// JSON document
+/// Highlights are written as ANSI styles, which the stream strips unless color is on
+pub struct Reporter {
+    format: OutputFormat,
+    verbosity: Verbosity,
+    formatter: Formatter,
+    out: RefCell<AutoStream<Box<dyn Write>>>,
+}
+
+impl Reporter {
+    /// Reporter printing to stdout
+    /// `ColorChoice::Auto` colors only a terminal, and honors `NO_COLOR` and `CLICOLOR_FORCE`
+    pub fn new(format: OutputFormat, verbosity: Verbosity, formatter: Formatter, color: ColorChoice) -> Self {
+        let stdout = io::stdout();
+        let color = match color {
+            ColorChoice::Auto => AutoStream::choice(&stdout),
+            choice => choice,
+        };
+        Reporter::with_writer(format, verbosity, formatter, color, Box::new(stdout))
+    }
+
+    /// Reporter printing to `writer` in color or not as `color` says; `ColorChoice::Auto`
+    /// treats a writer as a terminal only if it is one
+    pub fn with_writer(
+        format: OutputFormat,
+        verbosity: Verbosity,
+        formatter: Formatter,
+        color: ColorChoice,
+        writer: Box<dyn Write>,
+    ) -> Self {
+        Reporter { format, verbosity, formatter, out: RefCell::new(AutoStream::new(writer, color)) }
+    }
+
+    /// The stream messages are written to
+    fn out(&self) -> RefMut<'_, AutoStream<Box<dyn Write>>> {
+        self.out.borrow_mut()
+    }
+
+    fn is_text(&self) -> bool {
+        self.format == OutputFormat::Text
+    }
+
+    /// Whether messages meant for `level` and above are printed
+    pub fn shows(&self, level: Verbosity) -> bool {
+        self.is_text() && self.verbosity >= level
+    }
+
+    /// Quantity of units with grouping separators
+    fn units(&self, units: u32) -> String {
+        self.formatter.count(units as f64)
+    }
+
+    /// Amount for a table column, without the currency symbol
+    fn amount(&self, amount: f64) -> String {
+        self.formatter.number(amount, self.formatter.decimal_places)
+    }
+
+    /// Print `value` as the run's JSON document; does nothing in text mode
+    #[cfg(feature = "serde")]
+    pub fn emit_json<T: Serialize>(&self, value: &T) -> io::Result<()> {
+        if self.is_text() {
+            return Ok(());
+        }
+        let mut out = self.out();
+        serde_json::to_writer_pretty(&mut *out, value).map_err(io::Error::from)?;
+        writeln!(out)
+    }
+
+    /// Display Monte Carlo results for all supplier combinations, best first under `ranking`
+    pub fn display_all_results(&self, mut results: Vec<MonteCarloStats>, ranking: RankingMetric) {
+        if !self.shows(Verbosity::Quiet) {
+            return;
+        }
+        // The ranked summary is all quiet mode prints, so it goes without the banner
+        if self.shows(Verbosity::Normal) {
+            reportln!(self);
+            self.display_banner("MONTE CARLO RESULTS - ALL COMBINATIONS");
+        }
+
+        // Sort results by the ranking metric
+        results.sort_by(|a, b| ranking.score(b).total_cmp(&ranking.score(a)));
+        match ranking {
+            RankingMetric::MeanProfit => {}
+            RankingMetric::ExpectedShortfall => {
+                reportln!(self, "Ranked by expected shortfall (mean profit of the worst 5% of seasons)\n");
+            }
+            RankingMetric::OperationalProfit => {
+                reportln!(self, "Ranked by operational profit (mean profit before setup costs and order change fees)\n");
+            }
+        }
+
+        let width = terminal_width();
+        reportln!(self, "Profit ({:.0}% CI on the mean):", results.first().map_or(95.0, |stats| stats.confidence_level * 100.0));
+        for line in profit_table(&results, &self.formatter, width) {
+            reportln!(self, "{}", line);
+        }
+        reportln!(self, "\nAllocations: units from the base / surge supplier");
+        for line in allocation_table(&results, &self.formatter, width) {
+            reportln!(self, "{}", line);
+        }
+        reportln!(self, "\nCosts (mean per season) and margins per unit:");
+        for line in cost_table(&results, &self.formatter, width) {
+            reportln!(self, "{}", line);
+        }
+
+        // Adjacent ranks whose intervals overlap are not distinguishable at this sample size
+        let mut overlaps = results.windows(2).enumerate()
+            .filter(|(_, pair)| pair[0].mean_ci_overlaps(&pair[1]))
+            .peekable();
+        if overlaps.peek().is_some() {
+            reportln!(self);
+        }
+        for (rank, pair) in overlaps {
+            let warning = format!(
+                "⚠ {:.0}% CIs of #{} and #{} overlap: ranking not significant",
+                pair[0].confidence_level * 100.0, rank + 1, rank + 2
+            );
+            reportln!(self, "{}", paint(WARNING, warning));
+        }
+        reportln!(self);
+        self.display_boxplots(&results);
+    }
+
+    /// Display each pair's profit distribution as a box plot on a shared axis, scaled to the
+    /// terminal width
+    pub fn display_boxplots(&self, results: &[MonteCarloStats]) {
+        if !self.shows(Verbosity::Verbose) || results.is_empty() {
+            return;
+        }
+        reportln!(self, "Profit Distributions (whiskers min to max, box quartiles, │ median):");
+        for row in boxplot_rows(results, terminal_width(), &self.formatter) {
+            reportln!(self, "{}", row);
+        }
+        reportln!(self);
+    }
+
+    /// Display `title` in a box across the terminal, then a blank line
+    fn display_banner(&self, title: &str) {
+        for line in banner(title, terminal_width()) {
+            reportln!(self, "{}", line);
+        }
+        reportln!(self);
+    }
+
+    /// Display a paired comparison of two plans simulated on the same demand paths
+    pub fn display_comparison(&self, comparison: &PairedComparison) {
+        if !self.shows(Verbosity::Quiet) {
+            return;
+        }
+        reportln!(self);
+        self.display_banner("PAIRED COMPARISON OF SUPPLIER PAIRS");
+
+        reportln!(self, "Simulations: {} shared demand paths", comparison.num_simulations);
+        let money = |amount: f64| self.formatter.money(amount);
+        reportln!(self, "  A: {:<30} Mean profit: {}", comparison.first, money(comparison.mean_profit_first));
+        reportln!(self, "  B: {:<30} Mean profit: {}", comparison.second, money(comparison.mean_profit_second));
+        reportln!(self,
+            "\nA − B: {} [{:.0}% CI: {} to {}] | Std Dev of difference: {} | p = {:.4}",
+            money(comparison.mean_difference),
+            comparison.confidence_level * 100.0,
+            money(comparison.difference_ci_low),
+            money(comparison.difference_ci_high),
+            money(comparison.std_dev_difference),
+            comparison.p_value,
         );
+        if comparison.is_significant() {
+            let better = if comparison.mean_difference > 0.0 { &comparison.first } else { &comparison.second };
+            reportln!(self, "{} is better, significant at the {:.0}% level", better, comparison.confidence_level * 100.0);
+        } else {
+            reportln!(self, "The difference is not significant: it could be noise at this sample size");
+        }
+    }
+
+    /// Display plans for one pair side by side: what each orders a month, its mean profit,
+    /// CVaR and fill rate, and its paired difference from the optimizer's plan, starred when
+    /// significant
+    pub fn display_plan_comparison(&self, comparison: &PlanComparison) {
+        if !self.shows(Verbosity::Quiet) {
+            return;
+        }
+        reportln!(self);
+        self.display_banner("PLAN COMPARISON");
+
+        reportln!(self, "Pair: {} | Simulations: {} shared demand paths", comparison.pair, comparison.num_simulations);
+        let headers = ["Base/mo", "Surge/mo", "Mean profit", "CVaR 5%", "Fill rate", "vs Optimizer", "p"].map(String::from);
+        for line in aligned_table(&headers, &plan_rows(comparison, &self.formatter)) {
+            reportln!(self, "  {}", line);
+        }
+        if let Some(first) = comparison.plans.iter().find_map(|plan| plan.versus_optimizer.as_ref()) {
+            reportln!(self, "\n  * differs significantly from the optimizer's plan at the {:.0}% confidence level", first.confidence_level * 100.0);
+        }
+    }
+
+    /// Display the best supplier combination with detailed statistics, and what its surge
+    /// supplier adds to the base supplier alone when `surge_value` gives it
+    pub fn display_best_result(&self, result: &MonteCarloStats, target_profit: Option<f64>, surge_value: Option<&DualSourceValue>) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        self.display_banner("BEST SUPPLIER COMBINATION (HIGHEST MEAN PROFIT)");
+
+        if result.single_source {
+            reportln!(self, "Single Source: {} ({} month lead time)", result.base_supplier, result.base_supplier_lead_time);
+        } else {
+            reportln!(self,
+                "Base Supplier: {} ({} month lead time)\nSurge Supplier: {} ({} month lead time)",
+                result.base_supplier, result.base_supplier_lead_time,
+                result.surge_supplier, result.surge_supplier_lead_time,
+            );
+        }
+        if !result.pooled_suppliers.is_empty() {
+            reportln!(self, "Pooled Suppliers: {}", result.pooled_suppliers.join(", "));
+        }
+        if let Some(backup) = &result.backup_supplier {
+            reportln!(self, "Backup Supplier: {} ({:.1}% of capacity used)", backup, result.mean_backup_utilization * 100.0);
+        }
+        reportln!(self);
         
-        // Display product allocations
-        print!("   Allocations: ");
-        for (i, alloc) in result.product_allocations.iter().enumerate() {
-            if i > 0 { print!(", "); }
-            print!("{}: {} (base: {}, surge: {})", 
-                   alloc.product_name, 
-                   alloc.base_quantity + alloc.surge_quantity,
-                   alloc.base_quantity,
-                   alloc.surge_quantity);
-        }
-        println!(" | Total: {}", result.total_capacity_used);
+        let money = |amount: f64| self.formatter.money(amount);
+        // Tables are indented two columns
+        let width = terminal_width().saturating_sub(2);
+        reportln!(self, "Product Allocations:");
+        let allocations: Vec<Vec<String>> = result.product_allocations.iter()
+            .map(|alloc| vec![
+                alloc.product_name.clone(),
+                self.units(alloc.base_quantity),
+                self.units(alloc.surge_quantity),
+                self.units(alloc.base_quantity + alloc.surge_quantity),
+            ])
+            .collect();
+        let columns = [Column::left("Product").truncated(), Column::right("Base"), Column::right("Surge"), Column::right("Total")];
+        for line in layout_table(&columns, &allocations, width) {
+            reportln!(self, "  {}", line);
+        }
+        reportln!(self, "  Total Capacity Used: {}\n", self.units(result.total_capacity_used));
         
-        println!(
-            "   Mean: ${:.2} ± ${:.2} | Median: ${:.2} | Range: [${:.2}, ${:.2}]",
-            result.mean_profit,
-            result.std_dev_profit,
-            result.percentile_50,
-            result.min_profit,
-            result.max_profit
+        reportln!(self,
+            "Expected Profit: {} ± {} (std dev)",
+            money(result.mean_profit), money(result.std_dev_profit)
+        );
+        reportln!(self,
+            "{:.0}% CI on Mean: [{}, {}] (± {:.2}% of mean over {} runs)",
+            result.confidence_level * 100.0,
+            money(result.mean_ci_low),
+            money(result.mean_ci_high),
+            relative_half_width(result) * 100.0,
+            result.num_simulations
+        );
+        if let Some(value) = surge_value {
+            reportln!(self, "Surge supplier adds {} ± {} to expected profit", money(value.mean()), money(value.ci_half_width()));
+        }
+        reportln!(self, "\nProfit Distribution:");
+        reportln!(self, "  Minimum:          {}", money(result.min_profit));
+        for &(level, profit) in &result.percentiles {
+            let label = if level == 50.0 {
+                "Median (50th):".to_string()
+            } else {
+                format!("{} Percentile:", ordinal(level))
+            };
+            reportln!(self, "  {:<17} {}", label, paint_loss(profit, money(profit)));
+        }
+        reportln!(self, "  Maximum:          {}", money(result.max_profit));
+
+        reportln!(self, "\nRisk Assessment:");
+        for row in risk_rows(result, target_profit, &self.formatter) {
+            reportln!(self, "{}", row);
+        }
+
+        reportln!(self, "\nMean Profit by Month:");
+        let mut columns = vec![
+            Column::left("Month"),
+            Column::right("Revenue"),
+            Column::right("Production"),
+            Column::right("Holding"),
+            Column::right("Liquidation"),
+            Column::right("Fees/Setup"),
+            Column::right("Profit"),
+        ];
+        let charges = month_charges(&result.monthly_breakdown);
+        columns.splice(4..4, charges.iter().map(|&(heading, _)| Column::right(heading)));
+        let months: Vec<Vec<String>> = result.monthly_breakdown.iter()
+            .map(|month| {
+                let mut cells = vec![
+                    month.month.clone(),
+                    self.amount(month.mean_revenue),
+                    self.amount(month.mean_production_cost),
+                    self.amount(month.mean_holding_cost),
+                    self.amount(month.mean_liquidation_revenue),
+                    self.amount(month.mean_other_costs),
+                    self.amount(month.mean_profit),
+                ];
+                cells.splice(4..4, charges.iter().map(|(_, charge)| self.amount(charge(month))));
+                cells
+            })
+            .collect();
+        for line in layout_table(&columns, &months, width) {
+            reportln!(self, "  {}", line);
+        }
+
+        if result.monthly_breakdown.iter().any(|month| month.reserved_units > 0) {
+            reportln!(self, "\nReserved Capacity by Month:");
+            let columns = [Column::left("Month"), Column::right("Reserved"), Column::right("Used"), Column::right("Used %")];
+            let months: Vec<Vec<String>> = result.monthly_breakdown.iter()
+                .map(|month| vec![
+                    month.month.clone(),
+                    self.units(month.reserved_units),
+                    self.formatter.count(month.mean_reserved_units_used),
+                    reserved_share_used(month).map_or_else(|| "-".to_string(), |share| format!("{:.1}%", share * 100.0)),
+                ])
+                .collect();
+            for line in layout_table(&columns, &months, width) {
+                reportln!(self, "  {}", line);
+            }
+        }
+
+        reportln!(self, "\nMean Season Totals by Product (contribution before fees and setup):");
+        let mut columns = vec![
+            Column::left("Product").truncated(),
+            Column::right("Demand"),
+            Column::right("Sold"),
+            Column::right("Revenue"),
+            Column::right("Production"),
+            Column::right("Holding"),
+            Column::right("Liquidation"),
+            Column::right("Contribution"),
+        ];
+        let charges = charged(&result.product_breakdown, [
+            ("Financing", |product| product.mean_financing_cost),
+            ("Goodwill", |product| product.mean_goodwill_penalty),
+        ]);
+        columns.splice(6..6, charges.iter().map(|&(heading, _)| Column::right(heading)));
+        let products: Vec<Vec<String>> = result.product_breakdown.iter()
+            .map(|product| {
+                let mut cells = vec![
+                    product.product_name.clone(),
+                    self.formatter.count(product.mean_demand),
+                    self.formatter.count(product.mean_units_sold),
+                    self.amount(product.mean_revenue),
+                    self.amount(product.mean_production_cost),
+                    self.amount(product.mean_holding_cost),
+                    self.amount(product.mean_liquidation_revenue),
+                    self.amount(product.mean_contribution),
+                ];
+                cells.splice(6..6, charges.iter().map(|(_, charge)| self.amount(charge(product))));
+                cells
+            })
+            .collect();
+        for line in layout_table(&columns, &products, width) {
+            reportln!(self, "  {}", line);
+        }
+
+        if !result.forecast_accuracy.is_empty() {
+            reportln!(self, "\nForecast Accuracy (mean monthly demand):");
+            let columns = [
+                Column::left("Product").truncated(),
+                Column::right("Forecast"),
+                Column::right("Realized"),
+                Column::right("Bias"),
+                Column::right("MAPE"),
+            ];
+            let products: Vec<Vec<String>> = result.forecast_accuracy.iter()
+                .map(|accuracy| vec![
+                    accuracy.product_name.clone(),
+                    self.formatter.count(accuracy.forecast_mean_demand),
+                    self.formatter.count(accuracy.realized_mean_demand),
+                    self.formatter.signed_count(accuracy.bias),
+                    format!("{:.1}%", accuracy.mape * 100.0),
+                ])
+                .collect();
+            for line in layout_table(&columns, &products, width) {
+                reportln!(self, "  {}", line);
+            }
+            // A cost counts only when the paired interval lies wholly above zero
+            match &result.cost_of_forecast_error {
+                Some(cost) if cost.difference_ci_low > 0.0 => reportln!(
+                    self,
+                    "  Cost of forecast error: {} [{:.0}% CI: {} to {}] against the plan made on the actual demand",
+                    money(cost.mean_difference),
+                    cost.confidence_level * 100.0,
+                    money(cost.difference_ci_low),
+                    money(cost.difference_ci_high)
+                ),
+                Some(_) => reportln!(self, "  Cost of forecast error: none beyond simulation noise"),
+                None => {}
+            }
+        }
+
+        reportln!(self, "\nOrder Change Activity by Month:");
+        let columns = [Column::left("Month"), Column::right("Mean Option Value"), Column::right("P(Exercise)")];
+        let activity: Vec<Vec<String>> = result.monthly_option_summary.iter()
+            .map(|summary| vec![
+                summary.month.clone(),
+                money(summary.mean_option_value),
+                format!("{:.1}%", summary.exercise_frequency * 100.0),
+            ])
+            .collect();
+        for line in layout_table(&columns, &activity, width) {
+            reportln!(self, "  {}", line);
+        }
+        for (index, acceptance) in result.expansion_acceptance.iter().enumerate() {
+            reportln!(self, "  Expansion offer {} taken in {:.1}% of runs", index + 1, acceptance * 100.0);
+        }
+
+        reportln!(self, "\nCapacity Usage (mean per season):");
+        let columns = [
+            Column::left("Supplier").truncated(),
+            Column::right("Delivered"),
+            Column::right("Nominal"),
+            Column::right("Used %"),
+            Column::right("Months Full"),
+            Column::right("Shortfall"),
+        ];
+        let suppliers: Vec<Vec<String>> = result.capacity_usage.iter()
+            .map(|usage| vec![
+                usage.supplier_name.clone(),
+                self.formatter.count(usage.mean_delivered),
+                self.formatter.count(usage.mean_nominal_capacity),
+                format!("{:.1}%", usage.utilization() * 100.0),
+                format!("{:.1}", usage.mean_months_at_capacity),
+                self.formatter.count(usage.mean_shortfall),
+            ])
+            .collect();
+        for line in layout_table(&columns, &suppliers, width) {
+            reportln!(self, "  {}", line);
+        }
+    }
+
+    /// Display a profit and loss statement with a row per product and their total
+    pub fn display_product_pnl(&self, rows: &[ProductPnl]) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        reportln!(self, "\nProfit and Loss by Product (mean per season, shared costs allocated by revenue):");
+        for line in pnl_table(rows, &self.formatter, terminal_width().saturating_sub(2)) {
+            reportln!(self, "  {}", line);
+        }
+    }
+
+    /// Display how much of each supplier's monthly capacity the plan books and how much the
+    /// simulated seasons actually take, after order changes and flexing, flagging fragile months
+    pub fn display_utilization(&self, pair: &SupplierPair, stats: &MonteCarloStats) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        reportln!(self, "\nSupplier Utilization (share of monthly capacity, ! above {:.0}%):", FRAGILE_UTILIZATION * 100.0);
+        // Headers leave room for the marks after the percentages
+        let months: Vec<String> = stats.monthly_breakdown.iter().map(|month| format!("{} ", month.month)).collect();
+        for line in aligned_table(&months, &utilization_rows(pair, stats)) {
+            reportln!(self, "  {}", line);
+        }
+        for warning in fragility_warnings(pair, stats) {
+            reportln!(self, "  {}", paint(WARNING, format!("⚠ {}", warning)));
+        }
+    }
+
+    /// Display sensitivity results as a tornado chart, the most influential input first: each
+    /// bar runs left of the axis for lost profit and right for gained profit, `-` marking the
+    /// lowered input and `+` the raised one
+    pub fn display_tornado(&self, results: &[SensitivityResult]) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        reportln!(self, "\nSensitivity of Mean Profit (plan fixed, same demand paths):");
+        for row in tornado_rows(results, 25, &self.formatter) {
+            reportln!(self, "  {}", row);
+        }
+    }
+
+    /// Display what perfect foresight of demand and the order-change options add to the best
+    /// pair's mean profit, each with the half-width of its confidence interval
+    pub fn display_information_value(&self, foresight: &PairedComparison, options: &PairedComparison) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        let money = |amount: f64| self.formatter.money(amount);
+        let half_width = |comparison: &PairedComparison| (comparison.difference_ci_high - comparison.difference_ci_low) / 2.0;
+        reportln!(self, "\nValue of Information and Flexibility (same demand paths):");
+        reportln!(self, "  Perfect foresight (EVPI):  {} ± {}", money(foresight.mean_difference), money(half_width(foresight)));
+        reportln!(self, "  Order-change options:      {} ± {}", money(options.mean_difference), money(half_width(options)));
+    }
+
+    /// Display the mean profit of each pair (columns) in each scenario (rows), the scenario's
+    /// winner starred, then each pair's largest regret and the pair that keeps it smallest
+    pub fn display_scenario_matrix(&self, results: &ScenarioResults) {
+        if !self.shows(Verbosity::Quiet) {
+            return;
+        }
+        reportln!(self, "\nMean Profit by Scenario (* scenario winner, - screened out):");
+        for line in aligned_table(&results.pairs, &scenario_rows(results, &self.formatter)) {
+            reportln!(self, "  {}", line);
+        }
+        if let Some(pair) = results.minimax_regret_pair {
+            reportln!(self, "\n  Smallest worst-case regret: {}", results.pairs[pair]);
+        }
+    }
+
+    /// Display each season of a multi-season plan: the stock it opened with, the units it
+    /// ordered, its setup cost and its mean profit before and after discounting, then the NPV
+    pub fn display_season_plan(&self, plan: &MultiSeasonPlan) {
+        if !self.shows(Verbosity::Quiet) {
+            return;
+        }
+        reportln!(self, "\nSeason by Season:");
+        let headers = ["Opening stock", "Ordered", "Setup", "Mean profit", "Discounted"].map(String::from);
+        for line in aligned_table(&headers, &season_rows(plan, &self.formatter)) {
+            reportln!(self, "  {}", line);
+        }
+        reportln!(self, "\nNet Present Value:");
+        reportln!(self, "  Mean:             {}", self.formatter.whole_money(plan.mean_npv));
+        for &(level, npv) in &plan.npv_percentiles {
+            let label = if level == 50.0 {
+                "Median (50th):".to_string()
+            } else {
+                format!("{} Percentile:", ordinal(level))
+            };
+            reportln!(self, "  {:<17} {}", label, paint_loss(npv, self.formatter.whole_money(npv)));
+        }
+    }
+
+    /// Display the profit histogram as a horizontal bar chart scaled to the terminal width
+    pub fn display_histogram(&self, result: &MonteCarloStats) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        reportln!(self, "\nProfit Histogram ({} runs):", result.num_simulations);
+        for row in histogram_rows(&result.histogram, terminal_width(), &self.formatter) {
+            reportln!(self, "{}", row);
+        }
+    }
+
+    /// Display one replication month by month: each product's stock, demand, sales and costs,
+    /// then the month's fees and profit
+    pub fn display_trace(&self, title: &str, trace: &ReplicationTrace) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        reportln!(self,
+            "\n{} (run {}, seed {}): profit {}",
+            title, trace.sample.replication + 1, trace.sample.seed, self.formatter.money(trace.sample.profit)
+        );
+        reportln!(self,
+            "  {:<10} {:<12} {:>9} {:>9} {:>9} {:>9} {:>9} {:>14} {:>14} {:>11} {:>13}",
+            "Month", "Product", "Start", "Incoming", "Demand", "Sold", "End", "Revenue", "Production", "Holding", "Liquidation"
+        );
+        for month in &trace.monthly_results {
+            for (i, result) in month.product_results.iter().enumerate() {
+                reportln!(self,
+                    "  {:<10} {:<12} {:>9} {:>9} {:>9} {:>9} {:>9} {:>14} {:>14} {:>11} {:>13}",
+                    if i == 0 { month.month.as_str() } else { "" },
+                    result.product_name,
+                    self.units(result.inventory_start),
+                    self.units(result.incoming),
+                    self.units(result.demand),
+                    self.units(result.units_sold),
+                    self.units(result.inventory_end),
+                    self.amount(result.revenue),
+                    self.amount(result.production_cost),
+                    self.amount(result.holding_cost),
+                    self.amount(result.liquidation_revenue)
+                );
+            }
+            reportln!(self,
+                "  {:<10} Order changes: {} | Setup: {} | Flex: {} | Month profit: {}",
+                "",
+                self.formatter.money(month.order_change_cost),
+                self.formatter.money(month.setup_cost),
+                self.formatter.money(month.flex_cost),
+                self.formatter.money(month.monthly_profit)
+            );
+        }
+    }
+
+    /// Display a plan month by month, one table per product: the deliveries ordered from each
+    /// supplier, then the demand, ending inventory and cumulative profit of `projection`, a run
+    /// of the plan such as `simulation::project_mean_demand`
+    /// A product's profit is its sales and liquidation less its production and holding costs;
+    /// setup costs and fees are left out
+    pub fn display_monthly_plan(
+        &self,
+        params: &SimulationParams,
+        pair: &SupplierPair,
+        order: &MonthlyOrder,
+        projection: &[MonthlyResult],
+    ) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        reportln!(self, "\nMonthly Plan (mean demand, no order changes):");
+        let months: Vec<String> = projection.iter().map(|month| month.month.clone()).collect();
+        let pooled: Vec<String> = pair.pooled_suppliers.iter().map(|supplier| supplier.name.clone()).collect();
+        for product in &params.products {
+            let results: Vec<_> = projection.iter()
+                .filter_map(|month| month.product_results.iter().find(|result| result.product_id == product.id))
+                .collect();
+            let mut profit = 0.0;
+            let cumulative_profit = results.iter()
+                .map(|result| {
+                    profit += result.revenue + result.liquidation_revenue - result.production_cost - result.holding_cost
+                        - result.financing_cost - result.goodwill_penalty;
+                    self.formatter.whole_money(profit)
+                })
+                .collect();
+            let rows = vec![
+                (format!("Base ({})", committed_tier_name(&pair.base_supplier.name, &pooled)), vec![self.units(order.base_quantity_for(product.id)); months.len()]),
+                (format!("Surge ({})", pair.surge_supplier.name), vec![self.units(order.surge_quantity_for(product.id)); months.len()]),
+                ("Expected demand".to_string(), results.iter().map(|result| self.units(result.demand)).collect()),
+                ("Ending inventory".to_string(), results.iter().map(|result| self.units(result.inventory_end)).collect()),
+                ("Cumulative profit".to_string(), cumulative_profit),
+            ];
+            reportln!(self, "\n  {}", product.name);
+            for line in aligned_table(&months, &rows) {
+                reportln!(self, "  {}", line);
+            }
+        }
+    }
+
+    /// Display the demand level at which each month's surge order becomes worthwhile for a plan
+    /// `boundaries` holds one entry per month, None where no demand level triggers a change
+    pub fn display_exercise_boundaries(&self, boundaries: &[Option<u32>]) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        reportln!(self, "\nSurge Order Triggers (average monthly demand observed since May):");
+        for (month_idx, boundary) in boundaries.iter().enumerate() {
+            match boundary {
+                Some(level) => reportln!(self,
+                    "  {:<10} above {:>8} units/month (cumulative {:>9})",
+                    month_name(month_idx),
+                    level,
+                    *level as u64 * (month_idx as u64 + 1)
+                ),
+                None => reportln!(self, "  {:<10} no change worthwhile", month_name(month_idx)),
+            }
+        }
+    }
+
+    /// Display optimization progress message
+    pub fn display_optimization_start(&self, pair: &SupplierPair) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        if pair.is_self_paired() {
+            reportln!(self, "\n=== Evaluating: {} ===", pair.name());
+            return;
+        }
+        let pooled: String = pair.pooled_suppliers.iter()
+            .map(|supplier| format!(" + {} (pooled)", supplier.name))
+            .collect();
+        let backup = pair.backup_supplier.as_ref()
+            .map(|backup| format!(" + {} (backup)", backup.name))
+            .unwrap_or_default();
+        reportln!(self,
+            "\n=== Evaluating: {} (base){} + {} (surge){} ===",
+            pair.base_supplier.name, pooled, pair.surge_supplier.name, backup
+        );
+    }
+
+    /// Display finding optimal supply level message
+    pub fn display_finding_optimal(&self) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        report!(self, "  Finding optimal supply levels... ");
+        let _ = self.out().flush();
+    }
+
+    /// Display found optimal quantities for all products
+    pub fn display_found_quantities(&self, quantities: &[(ProductId, String, u32)]) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        reportln!(self, "Found:");
+        for (_, name, qty) in quantities {
+            reportln!(self, "    {}: {} units", name, self.units(*qty));
+        }
+    }
+
+    /// Display Monte Carlo results for current combination
+    pub fn display_combination_results(&self, result: &MonteCarloStats) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        let money = |amount: f64| self.formatter.money(amount);
+        reportln!(self,
+            "  Mean Profit: {} ± {} | Min: {} | Max: {}",
+            money(result.mean_profit), money(result.std_dev_profit), money(result.min_profit), money(result.max_profit)
+        );
+        reportln!(self,
+            "  {:.0}% CI half-width: {} ({:.2}% of mean, {} runs{})",
+            result.confidence_level * 100.0,
+            money(result.mean_ci_half_width()),
+            relative_half_width(result) * 100.0,
+            result.num_simulations,
+            if result.converged { String::new() } else { paint(WARNING, ", did not converge") }
+        );
+        if result.adjusted_std_error != result.mean_std_error {
+            reportln!(self,
+                "  Variance-reduced mean: {} (std error {} against {} unadjusted)",
+                money(result.adjusted_mean_profit), money(result.adjusted_std_error), money(result.mean_std_error)
+            );
+        }
+    }
+
+    /// Display what the optimizer's search did for the current pair
+    pub fn display_optimizer_diagnostics(&self, diagnostics: &OptimizerDiagnostics) {
+        if !self.shows(Verbosity::Verbose) {
+            return;
+        }
+        reportln!(self, "  Optimizer Diagnostics:");
+        reportln!(self,
+            "    Candidates simulated: {} ({} over capacity skipped) | Seasons simulated: {}",
+            diagnostics.candidates_evaluated, diagnostics.candidates_over_capacity, diagnostics.simulations_run
+        );
+        if let Some(profit) = diagnostics.best_mean_profit {
+            reportln!(self, "    Best candidate's mean profit under the forecast: {}", self.formatter.money(profit));
+        }
+    }
+
+    /// Display how long the current pair took to optimize and to simulate
+    pub fn display_pair_timing(&self, optimizing: Duration, simulating: Duration) {
+        if !self.shows(Verbosity::Verbose) {
+            return;
+        }
+        reportln!(self,
+            "  Time: {:.2?} optimizing, {:.2?} simulating ({:.2?} total)",
+            optimizing, simulating, optimizing + simulating
+        );
+    }
+
+    /// Display the run's banner and each product's price, costs and demand assumptions
+    pub fn display_scenario(&self, params: &SimulationParams) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        self.display_banner("MULTI-PRODUCT SUPPLY CHAIN SIMULATION");
+
+        reportln!(self, "Products:");
+        for product in &params.products {
+            let dp = params.get_demand_params(product.id).unwrap();
+            reportln!(self, "  {}: Price={}, Holding={}/mo, Liquidation={}",
+                     product.name,
+                     self.formatter.whole_money(product.selling_price),
+                     self.formatter.money_with(product.monthly_holding_cost, 2),
+                     self.formatter.whole_money(product.liquidation_price));
+            reportln!(self, "      Expected Demand: {} ± {}, Actual: {} ± {}",
+                     self.formatter.count(dp.mean_demand), self.formatter.count(dp.std_dev_demand),
+                     self.formatter.count(dp.actual_mean_demand), self.formatter.count(dp.actual_std_dev_demand));
+        }
+        for disruption in &params.regional_disruptions {
+            reportln!(self, "  {} disrupted {:.1}% of months", disruption.region, disruption.monthly_probability * 100.0);
+        }
+        for (index, offer) in params.expansion_offers.iter().enumerate() {
+            reportln!(self, "  Expansion offer {}: supplier {} adds {} a month from {} for {} if taken by {}",
+                     index + 1, offer.supplier_id, self.units(offer.extra_capacity), month_name(offer.start_month),
+                     self.formatter.whole_money(offer.fee), month_name(offer.decision_month));
+        }
+        if params.time_step == TimeStep::Weekly {
+            reportln!(self, "  Simulated week by week: {} weeks", params.periods());
+        }
+        reportln!(self);
+    }
+
+    /// Display the suppliers shortlisting left out of every pair, with their scores
+    pub fn display_shortlist(&self, excluded: &[SupplierScore]) {
+        if !self.shows(Verbosity::Verbose) || excluded.is_empty() {
+            return;
+        }
+        reportln!(self, "\nNot shortlisted:");
+        for score in excluded {
+            reportln!(self, "  {}: score {:.3} (landed cost {}/unit, capacity {:.0}% of demand)",
+                score.supplier_name, score.score, self.formatter.money(score.landed_cost), score.capacity_coverage * 100.0);
+        }
+    }
+
+    /// Display how many supplier pairs were generated and how many passed screening
+    pub fn display_pair_screening(&self, generated: usize, promising: usize) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        reportln!(self, "\n{} supplier pairs generated. Filtering for promising combinations...", generated);
+        reportln!(self, "{} pairs passed initial profitability screening.\n", promising);
+    }
+
+    /// Display how much of the shared budget adaptive selection ran and how sure it is of the best pair
+    pub fn display_selection(&self, selection: &Selection) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        reportln!(self,
+            "\nAdaptive selection ran {} of {} replications; probability the best pair is correct: {:.1}%",
+            self.formatter.count(selection.replications as f64),
+            self.formatter.count(selection.budget as f64),
+            selection.probability_correct * 100.0
+        );
+    }
+
+    /// Display each pair screening dropped and the check it failed
+    pub fn display_screening_summary(&self, rejected: &[RejectedPair]) {
+        if !self.shows(Verbosity::Verbose) || rejected.is_empty() {
+            return;
+        }
+        reportln!(self, "Screened out:");
+        for pair in rejected {
+            reportln!(self, "  {}: {}", pair.name(), rejection(&pair.reason, &self.formatter));
+        }
+        reportln!(self);
+    }
+
+    /// Display the quick profit estimate of the pair being evaluated
+    pub fn display_quick_estimate(&self, quick_estimate: f64) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        reportln!(self, "  Quick estimate: {}", self.formatter.money(quick_estimate));
+    }
+
+    /// Display where result files were written
+    pub fn display_files_written(&self, path: &Path) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        reportln!(self, "Results written to {}", path.display());
+    }
+
+    /// Display the id a run was recorded under in the history database at `path`
+    #[cfg(feature = "sqlite")]
+    pub fn display_run_recorded(&self, path: &Path, run_id: i64) {
+        if !self.shows(Verbosity::Normal) {
+            return;
+        }
+        reportln!(self, "Run {} recorded in {}", run_id, path.display());
+    }
+
+    /// Display recorded runs, oldest first, with each run's best pair
+    #[cfg(feature = "sqlite")]
+    pub fn display_run_history(&self, runs: &[RunSummary]) {
+        if !self.shows(Verbosity::Quiet) {
+            return;
+        }
+        if runs.is_empty() {
+            reportln!(self, "No runs recorded");
+            return;
+        }
+        for line in history_table(runs, &self.formatter, terminal_width()) {
+            reportln!(self, "{}", line);
+        }
+    }
+
+    /// Display two recorded runs side by side: each pair's mean profit in both, with each run's
+    /// winner starred, then the scenario inputs that changed between them
+    #[cfg(feature = "sqlite")]
+    pub fn display_run_diff(&self, comparison: &ScenarioResults, changes: &[(String, Option<f64>, Option<f64>)]) {
+        if !self.shows(Verbosity::Quiet) {
+            return;
+        }
+        reportln!(self, "Mean Profit by Run (* run's best pair, - not simulated in the run):");
+        for line in aligned_table(&comparison.pairs, &scenario_rows(comparison, &self.formatter)) {
+            reportln!(self, "  {}", line);
+        }
+        if changes.is_empty() {
+            reportln!(self, "\nNo scenario inputs changed");
+            return;
+        }
+        reportln!(self, "\nScenario inputs changed:");
+        let value = |value: &Option<f64>| value.map_or_else(|| "-".to_string(), |value| self.formatter.number(value, 2));
+        let rows: Vec<Vec<String>> = changes.iter()
+            .map(|(name, first, second)| vec![name.clone(), value(first), value(second)])
+            .collect();
+        let columns = [Column::left("Input").truncated(), Column::right("Before"), Column::right("After")];
+        for line in layout_table(&columns, &rows, terminal_width().saturating_sub(2)) {
+            reportln!(self, "  {}", line);
+        }
+    }
+}
+
+/// Write the statistics of every pair in a run to `path` as JSON, for plotting and archiving
+#[cfg(feature = "serde")]
+#[allow(dead_code)]
+pub fn save_run_json(path: &Path, results: &[MonteCarloStats]) -> io::Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, results).map_err(io::Error::from)
+}
+
+/// Write the profit samples a pair's run retained to `path` as a one-column CSV
+/// Profits are written in full precision so they read back exactly
+#[allow(dead_code)]
+pub fn export_samples(path: &Path, result: &MonteCarloStats) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "profit")?;
+    for sample in &result.samples {
+        writeln!(writer, "{}", sample.profit)?;
+    }
+    writer.flush()
+}
+
+/// Write one row per pair to `path` as CSV: the suppliers, each product's base and surge
+/// allocation, capacity used, the profit summary including every computed percentile, and the
+/// revenue and landed cost per unit with the margin between them
+/// Product and percentile columns follow the first result, as every pair in a run shares them
+pub fn export_results_csv(path: &Path, results: &[MonteCarloStats]) -> io::Result<()> {
+    write_results_csv(File::create(path)?, results)
+}
+
+/// Write the rows `export_results_csv` writes to `out`
+pub fn write_results_csv(out: impl Write, results: &[MonteCarloStats]) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(out);
+    let Some(first) = results.first() else {
+        return writer.flush();
+    };
+
+    let mut header: Vec<String> = ["base_supplier", "base_lead_time", "surge_supplier", "surge_lead_time", "backup_supplier", "pooled_suppliers"]
+        .iter()
+        .map(|column| column.to_string())
+        .collect();
+    for alloc in &first.product_allocations {
+        header.push(format!("{} base", alloc.product_name));
+        header.push(format!("{} surge", alloc.product_name));
+    }
+    header.extend(["total_capacity_used", "mean_profit", "std_dev_profit", "min_profit", "max_profit"].map(String::from));
+    header.extend(first.percentiles.iter().map(|(level, _)| format!("p{}", level)));
+    header.extend(["revenue_per_unit", "landed_cost_per_unit", "landed_margin"].map(String::from));
+    for role in ["base", "surge", "backup"] {
+        header.extend(["delivered", "nominal_capacity", "months_at_capacity", "capacity_shortfall"].map(|column| format!("{}_{}", role, column)));
+    }
+    writer.write_record(&header)?;
+
+    for result in results {
+        let mut row = vec![
+            result.base_supplier.clone(),
+            result.base_supplier_lead_time.to_string(),
+            result.surge_supplier.clone(),
+            result.surge_supplier_lead_time.to_string(),
+            result.backup_supplier.clone().unwrap_or_default(),
+            result.pooled_suppliers.join(";"),
+        ];
+        for alloc in &result.product_allocations {
+            row.push(alloc.base_quantity.to_string());
+            row.push(alloc.surge_quantity.to_string());
+        }
+        row.push(result.total_capacity_used.to_string());
+        for value in [result.mean_profit, result.std_dev_profit, result.min_profit, result.max_profit] {
+            row.push(value.to_string());
+        }
+        row.extend(result.percentiles.iter().map(|(_, profit)| profit.to_string()));
+        for value in [result.revenue_per_unit, result.landed_cost_per_unit, result.landed_margin] {
+            row.push(value.to_string());
+        }
+        // Mean capacity usage of the supplier in each role, blank without a backup
+        for supplier in [Some(&result.base_supplier), Some(&result.surge_supplier), result.backup_supplier.as_ref()] {
+            let usage = supplier.and_then(|name| result.capacity_usage.iter().find(|usage| usage.supplier_name == *name));
+            match usage {
+                Some(usage) => row.extend([usage.mean_delivered, usage.mean_nominal_capacity, usage.mean_months_at_capacity, usage.mean_shortfall].map(|value| value.to_string())),
+                None => row.extend(std::iter::repeat_n(String::new(), 4)),
+            }
+        }
+        writer.write_record(&row)?;
+    }
+    writer.flush()
+}
+
+/// Write each pair's mean profit by month to `path` as CSV, one row per pair and month
+/// Returns false without writing when no result has a monthly breakdown
+pub fn export_monthly_csv(path: &Path, results: &[MonteCarloStats]) -> io::Result<bool> {
+    if results.iter().all(|result| result.monthly_breakdown.is_empty()) {
+        return Ok(false);
+    }
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record([
+        "base_supplier", "surge_supplier", "backup_supplier", "pooled_suppliers", "month", "mean_revenue", "mean_production_cost",
+        "mean_holding_cost", "mean_liquidation_revenue", "mean_other_costs", "mean_profit",
+    ])?;
+    for result in results {
+        for month in &result.monthly_breakdown {
+            writer.write_record([
+                result.base_supplier.clone(),
+                result.surge_supplier.clone(),
+                result.backup_supplier.clone().unwrap_or_default(),
+                result.pooled_suppliers.join(";"),
+                month.month.clone(),
+                month.mean_revenue.to_string(),
+                month.mean_production_cost.to_string(),
+                month.mean_holding_cost.to_string(),
+                month.mean_liquidation_revenue.to_string(),
+                month.mean_other_costs.to_string(),
+                month.mean_profit.to_string(),
+            ])?;
+        }
+    }
+    writer.flush()?;
+    Ok(true)
+}
+
+/// Version of the Parquet export's columns, stored in each file's `schema_version` metadata;
+/// bump it whenever a column is renamed, retyped or removed
+#[cfg(feature = "arrow")]
+pub const PARQUET_SCHEMA_VERSION: &str = "1";
+
+/// Write a run as Parquet files into `dir` for the analytics stack: `pairs.parquet` with one row
+/// of statistics per pair, and, when the runs retained samples, `replications.parquet` with one
+/// row per retained replication. Pairs are identified by their index in `run.pairs`
+#[cfg(feature = "arrow")]
+pub fn export_parquet(dir: &Path, run: &RunOutput) -> io::Result<()> {
+    use std::sync::Arc;
+    use arrow_array::{ArrayRef, BooleanArray, Float64Array, StringArray, UInt32Array, UInt64Array};
+    use crate::models::ProfitSample;
+
+    std::fs::create_dir_all(dir)?;
+    let stats: Vec<&MonteCarloStats> = run.pairs.iter().map(|pair| &pair.stats).collect();
+    let float = |value: fn(&MonteCarloStats) -> f64| -> ArrayRef {
+        Arc::new(stats.iter().map(|stats| value(stats)).collect::<Float64Array>())
+    };
+    write_parquet(&dir.join("pairs.parquet"), vec![
+        ("pair_id", Arc::new((0..run.pairs.len() as u32).collect::<UInt32Array>())),
+        ("base_supplier", Arc::new(stats.iter().map(|stats| Some(stats.base_supplier.as_str())).collect::<StringArray>())),
+        ("base_lead_time", Arc::new(stats.iter().map(|stats| stats.base_supplier_lead_time as u32).collect::<UInt32Array>())),
+        ("surge_supplier", Arc::new(stats.iter().map(|stats| Some(stats.surge_supplier.as_str())).collect::<StringArray>())),
+        ("surge_lead_time", Arc::new(stats.iter().map(|stats| stats.surge_supplier_lead_time as u32).collect::<UInt32Array>())),
+        ("backup_supplier", Arc::new(stats.iter().map(|stats| stats.backup_supplier.as_deref()).collect::<StringArray>())),
+        ("pooled_suppliers", Arc::new(stats.iter().map(|stats| Some(stats.pooled_suppliers.join(";"))).collect::<StringArray>())),
+        ("quick_estimate", Arc::new(run.pairs.iter().map(|pair| pair.quick_estimate).collect::<Float64Array>())),
+        ("total_capacity_used", Arc::new(stats.iter().map(|stats| stats.total_capacity_used).collect::<UInt32Array>())),
+        ("num_simulations", Arc::new(stats.iter().map(|stats| stats.num_simulations as u64).collect::<UInt64Array>())),
+        ("converged", Arc::new(stats.iter().map(|stats| Some(stats.converged)).collect::<BooleanArray>())),
+        ("mean_profit", float(|stats| stats.mean_profit)),
+        ("std_dev_profit", float(|stats| stats.std_dev_profit)),
+        ("min_profit", float(|stats| stats.min_profit)),
+        ("max_profit", float(|stats| stats.max_profit)),
+        ("mean_std_error", float(|stats| stats.mean_std_error)),
+        ("adjusted_mean_profit", float(|stats| stats.adjusted_mean_profit)),
+        ("adjusted_std_error", float(|stats| stats.adjusted_std_error)),
+        ("confidence_level", float(|stats| stats.confidence_level)),
+        ("mean_ci_low", float(|stats| stats.mean_ci_low)),
+        ("mean_ci_high", float(|stats| stats.mean_ci_high)),
+        ("var_5", float(|stats| stats.var_5)),
+        ("cvar_5", float(|stats| stats.cvar_5)),
+        ("prob_loss", float(|stats| stats.prob_loss)),
+        ("downside_deviation", Arc::new(stats.iter().map(|stats| stats.downside_deviation).collect::<Float64Array>())),
+        ("exercise_probability", float(|stats| stats.exercise_probability)),
+        ("mean_exercises", float(|stats| stats.mean_exercises)),
+        ("mean_fees_paid", float(|stats| stats.mean_fees_paid)),
+        ("mean_setup_cost", float(|stats| stats.mean_setup_cost)),
+        ("mean_operational_profit", float(|stats| stats.mean_operational_profit)),
+        ("mean_base_utilization", float(|stats| stats.mean_base_utilization)),
+        ("mean_surge_utilization", float(|stats| stats.mean_surge_utilization)),
+        ("mean_backup_utilization", float(|stats| stats.mean_backup_utilization)),
+    ])?;
+
+    let samples: Vec<(u32, &ProfitSample)> = (0..).zip(&stats)
+        .flat_map(|(pair_id, stats)| stats.samples.iter().map(move |sample| (pair_id, sample)))
+        .collect();
+    if samples.is_empty() {
+        return Ok(());
+    }
+    write_parquet(&dir.join("replications.parquet"), vec![
+        ("pair_id", Arc::new(samples.iter().map(|(pair_id, _)| *pair_id).collect::<UInt32Array>())),
+        ("replication", Arc::new(samples.iter().map(|(_, sample)| sample.replication as u64).collect::<UInt64Array>())),
+        ("seed", Arc::new(samples.iter().map(|(_, sample)| sample.seed).collect::<UInt64Array>())),
+        ("profit", Arc::new(samples.iter().map(|(_, sample)| sample.profit).collect::<Float64Array>())),
+        ("exercises", Arc::new(samples.iter().map(|(_, sample)| sample.exercises as u32).collect::<UInt32Array>())),
+        ("fill_rate", Arc::new(samples.iter().map(|(_, sample)| sample.fill_rate).collect::<Float64Array>())),
+    ])
+}
+
+/// Write named columns to `path` as a single Parquet table tagged with `PARQUET_SCHEMA_VERSION`
+#[cfg(feature = "arrow")]
+fn write_parquet(path: &Path, columns: Vec<(&str, arrow_array::ArrayRef)>) -> io::Result<()> {
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::metadata::KeyValue;
+    use parquet::file::properties::WriterProperties;
+
+    let batch = arrow_array::RecordBatch::try_from_iter(columns).map_err(io::Error::other)?;
+    let properties = WriterProperties::builder()
+        .set_key_value_metadata(Some(vec![KeyValue::new("schema_version".to_string(), PARQUET_SCHEMA_VERSION.to_string())]))
+        .build();
+    let mut writer = ArrowWriter::try_new(File::create(path)?, batch.schema(), Some(properties)).map_err(io::Error::other)?;
+    writer.write(&batch).map_err(io::Error::other)?;
+    writer.close().map_err(io::Error::other)?;
+    Ok(())
+}
+
+/// Write a run to `path` as an Excel workbook: a Summary sheet ranking the pairs, the best
+/// pair's month-by-month plan and simulated monthly means on Best Plan, every pair's
+/// percentiles and tail risk on Risk, and the inputs on Scenario
+/// Amounts stay numbers, shown in `formatter`'s currency, and losses are highlighted
+#[cfg(feature = "xlsx")]
+pub fn export_xlsx(path: &Path, run: &RunOutput, formatter: &Formatter) -> io::Result<()> {
+    use rust_xlsxwriter::Workbook;
+    use crate::simulation::{project_mean_demand, split_order_quantities};
+    use XlsxCell::{Count, Money, Percent, Price, Text};
+
+    let formats = XlsxFormats::new(formatter);
+    let mut ranked: Vec<&MonteCarloStats> = run.pairs.iter().map(|pair| &pair.stats).collect();
+    ranked.sort_by(|a, b| run.ranking.score(b).total_cmp(&run.ranking.score(a)));
+    let mut workbook = Workbook::new();
+
+    let sheet = workbook.add_worksheet().set_name("Summary").map_err(io::Error::other)?;
+    let rows: Vec<Vec<XlsxCell>> = ranked.iter().enumerate()
+        .map(|(rank, stats)| vec![
+            Count((rank + 1) as f64),
+            Text(committed_tier_name(&stats.base_supplier, &stats.pooled_suppliers)),
+            Text(stats.surge_supplier.clone()),
+            Money(stats.mean_profit),
+            Money(stats.mean_ci_low),
+            Money(stats.mean_ci_high),
+            Money(stats.std_dev_profit),
+            stats.p10().map_or(XlsxCell::Empty, Money),
+            stats.p90().map_or(XlsxCell::Empty, Money),
+            Percent(stats.prob_loss),
+            Percent(stats.exercise_probability),
+            Count(stats.num_simulations as f64),
+        ])
+        .collect();
+    let end = formats.table(sheet, 0, &[
+        "Rank", "Base supplier", "Surge supplier", "Mean profit", "CI low", "CI high", "Std dev",
+        "10th pct", "90th pct", "P(loss)", "Options exercised", "Simulations",
+    ], &rows, &[3]).map_err(io::Error::other)?;
+    sheet.write(end + 1, 0, format!("Ranked by {}.", ranking_description(run.ranking))).map_err(io::Error::other)?;
+    sheet.set_freeze_panes(1, 0).map_err(io::Error::other)?;
+    sheet.autofit();
+
+    let sheet = workbook.add_worksheet().set_name("Best Plan").map_err(io::Error::other)?;
+    let best = run.best_pair.and_then(|index| run.pairs.get(index));
+    let supplier = |name: &str| run.suppliers.iter().find(|supplier| supplier.name == name).cloned();
+    let mut rows = Vec::new();
+    if let Some(best) = best {
+        if let (Some(base_supplier), Some(surge_supplier)) = (supplier(&best.stats.base_supplier), supplier(&best.stats.surge_supplier)) {
+            let backup_supplier = best.stats.backup_supplier.as_deref().and_then(supplier);
+            let pooled_suppliers = best.stats.pooled_suppliers.iter().filter_map(|name| supplier(name)).collect();
+            let pair = SupplierPair { base_supplier, surge_supplier, backup_supplier, pooled_suppliers };
+            let order = split_order_quantities(&best.optimal_quantities, &pair, &run.params);
+            let projection = project_mean_demand(&run.params, &pair, &order).unwrap_or_default();
+            for product in &run.params.products {
+                let mut profit = 0.0;
+                for month in &projection {
+                    let Some(result) = month.product_results.iter().find(|result| result.product_id == product.id) else {
+                        continue;
+                    };
+                    profit += result.revenue + result.liquidation_revenue - result.production_cost - result.holding_cost
+                        - result.financing_cost - result.goodwill_penalty;
+                    rows.push(vec![
+                        Text(product.name.clone()),
+                        Text(month.month.clone()),
+                        Count(order.base_quantity_for(product.id) as f64),
+                        Count(order.surge_quantity_for(product.id) as f64),
+                        Count(result.demand as f64),
+                        Count(result.inventory_end as f64),
+                        Money(profit),
+                    ]);
+                }
+            }
+        }
+    }
+    let end = formats.table(sheet, 0, &[
+        "Product", "Month", "Base units", "Surge units", "Expected demand", "Ending inventory", "Cumulative profit",
+    ], &rows, &[6]).map_err(io::Error::other)?;
+    if let Some(best) = best {
+        let rows: Vec<Vec<XlsxCell>> = best.stats.monthly_breakdown.iter()
+            .map(|month| vec![
+                Text(month.month.clone()),
+                Money(month.mean_revenue),
+                Money(month.mean_production_cost),
+                Money(month.mean_holding_cost),
+                Money(month.mean_liquidation_revenue),
+                Money(month.mean_other_costs),
+                Money(month.mean_profit),
+            ])
+            .collect();
+        formats.table(sheet, end + 1, &[
+            "Simulated month", "Revenue", "Production", "Holding", "Liquidation", "Fees / setup", "Profit",
+        ], &rows, &[6]).map_err(io::Error::other)?;
+    }
+    sheet.set_freeze_panes(1, 0).map_err(io::Error::other)?;
+    sheet.autofit();
+
+    let sheet = workbook.add_worksheet().set_name("Risk").map_err(io::Error::other)?;
+    let levels: Vec<f64> = ranked.first().map_or(Vec::new(), |stats| stats.percentiles.iter().map(|(level, _)| *level).collect());
+    let mut headers = vec!["Pair".to_string(), "Mean profit".to_string(), "Minimum".to_string()];
+    headers.extend(levels.iter().map(|level| format!("{} pct", ordinal(*level))));
+    headers.extend(["Maximum", "VaR (5%)", "ES (5%)", "P(loss)", "Downside deviation"].map(String::from));
+    let rows: Vec<Vec<XlsxCell>> = ranked.iter()
+        .map(|stats| {
+            let mut row = vec![
+                Text(stats.pair_name()),
+                Money(stats.mean_profit),
+                Money(stats.min_profit),
+            ];
+            row.extend(levels.iter().map(|level| stats.percentile(*level).map_or(XlsxCell::Empty, Money)));
+            row.extend([
+                Money(stats.max_profit),
+                Money(stats.var_5),
+                Money(stats.cvar_5),
+                Percent(stats.prob_loss),
+                stats.downside_deviation.map_or(XlsxCell::Empty, Money),
+            ]);
+            row
+        })
+        .collect();
+    let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+    formats.table(sheet, 0, &headers, &rows, &[1]).map_err(io::Error::other)?;
+    sheet.set_freeze_panes(1, 0).map_err(io::Error::other)?;
+    sheet.autofit();
+
+    let sheet = workbook.add_worksheet().set_name("Scenario").map_err(io::Error::other)?;
+    let rows: Vec<Vec<XlsxCell>> = run.params.products.iter()
+        .map(|product| {
+            let demand = run.params.get_demand_params(product.id);
+            let demand = |value: fn(&crate::models::ProductDemandParams) -> f64| demand.map_or(XlsxCell::Empty, |dp| Count(value(dp)));
+            vec![
+                Text(product.name.clone()),
+                Price(product.selling_price),
+                Price(product.monthly_holding_cost),
+                Price(product.liquidation_price),
+                demand(|dp| dp.mean_demand),
+                demand(|dp| dp.std_dev_demand),
+                demand(|dp| dp.actual_mean_demand),
+                demand(|dp| dp.actual_std_dev_demand),
+            ]
+        })
+        .collect();
+    let end = formats.table(sheet, 0, &[
+        "Product", "Price", "Holding / month", "Liquidation", "Forecast demand / month", "Forecast std dev",
+        "Actual demand / month", "Actual std dev",
+    ], &rows, &[]).map_err(io::Error::other)?;
+
+    let mut headers = vec!["Supplier".to_string(), "Lead time (months)".to_string(), "Capacity / month".to_string(), "Setup cost".to_string()];
+    headers.extend(run.params.products.iter().map(|product| format!("{} unit cost", product.name)));
+    let rows: Vec<Vec<XlsxCell>> = run.suppliers.iter()
+        .map(|supplier| {
+            let mut row = vec![
+                Text(supplier.name.clone()),
+                Count(supplier.lead_time_months as f64),
+                Count(supplier.fixed_capacity as f64),
+                Money(supplier.setup_cost),
+            ];
+            row.extend(run.params.products.iter().map(|product| supplier.unit_costs.get(&product.id).map_or(XlsxCell::Empty, |cost| Price(*cost))));
+            row
+        })
+        .collect();
+    let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+    let end = formats.table(sheet, end + 1, &headers, &rows, &[]).map_err(io::Error::other)?;
+
+    let mut rows = vec![vec![Text("Season months".to_string()), Count(run.params.season_months as f64)]];
+    rows.extend(run.params.order_change_fees.iter().enumerate()
+        .map(|(index, fee)| vec![Text(format!("Order change {} fee", index + 1)), Money(*fee)]));
+    rows.push(vec![Text("Order change fee per product".to_string()), Money(run.params.order_change_fee_per_product)]);
+    rows.push(vec![Text("Cancellation fee per unit".to_string()), Price(run.params.cancellation_fee_per_unit)]);
+    formats.table(sheet, end + 1, &["Setting", "Value"], &rows, &[]).map_err(io::Error::other)?;
+    sheet.set_freeze_panes(1, 0).map_err(io::Error::other)?;
+    sheet.autofit();
+
+    workbook.save(path).map_err(io::Error::other)
+}
+
+/// A worksheet cell of `export_xlsx`, by how its number is shown
+#[cfg(feature = "xlsx")]
+enum XlsxCell {
+    Text(String),
+    /// Whole amount of money
+    Money(f64),
+    /// Amount of money to the cent, for prices and unit costs
+    Price(f64),
+    /// Units or other counts, grouped by thousands
+    Count(f64),
+    /// A share, shown as a percentage
+    Percent(f64),
+    Empty,
+}
+
+/// Cell formats of the workbook `export_xlsx` writes
+#[cfg(feature = "xlsx")]
+struct XlsxFormats {
+    header: rust_xlsxwriter::Format,
+    money: rust_xlsxwriter::Format,
+    price: rust_xlsxwriter::Format,
+    count: rust_xlsxwriter::Format,
+    percent: rust_xlsxwriter::Format,
+    loss: rust_xlsxwriter::Format,
+}
+
+#[cfg(feature = "xlsx")]
+impl XlsxFormats {
+    /// Formats showing amounts in `formatter`'s currency, before or after the number as it writes it
+    /// Excel applies the reader's own separators, so only the symbol carries over
+    fn new(formatter: &Formatter) -> Self {
+        use rust_xlsxwriter::{Format, FormatBorder, FormatPattern};
+
+        let currency = formatter.currency.replace('"', "");
+        let money = |number: &str| if formatter.currency_after {
+            format!("{} \"{}\"", number, currency)
+        } else {
+            format!("\"{}\"{}", currency, number)
+        };
+        XlsxFormats {
+            header: Format::new().set_bold().set_border_bottom(FormatBorder::Thin),
+            money: Format::new().set_num_format(money("#,##0")),
+            price: Format::new().set_num_format(money("#,##0.00")),
+            count: Format::new().set_num_format("#,##0"),
+            percent: Format::new().set_num_format("0.0%"),
+            loss: Format::new().set_font_color("#9C0006").set_background_color("#FFC7CE").set_pattern(FormatPattern::Solid),
+        }
+    }
+
+    /// Write a table with a bold header row at `first_row`, highlighting negative amounts in the
+    /// `profit_columns`, and return the row after it
+    fn table(
+        &self,
+        sheet: &mut rust_xlsxwriter::Worksheet,
+        first_row: u32,
+        headers: &[&str],
+        rows: &[Vec<XlsxCell>],
+        profit_columns: &[u16],
+    ) -> Result<u32, rust_xlsxwriter::XlsxError> {
+        use rust_xlsxwriter::{ConditionalFormatCell, ConditionalFormatCellRule};
+
+        for (col, header) in (0..).zip(headers) {
+            sheet.write_string_with_format(first_row, col, *header, &self.header)?;
+        }
+        for (row, cells) in (first_row + 1..).zip(rows) {
+            for (col, cell) in (0..).zip(cells) {
+                match cell {
+                    XlsxCell::Text(text) => sheet.write_string(row, col, text)?,
+                    XlsxCell::Money(amount) => sheet.write_number_with_format(row, col, *amount, &self.money)?,
+                    XlsxCell::Price(amount) => sheet.write_number_with_format(row, col, *amount, &self.price)?,
+                    XlsxCell::Count(count) => sheet.write_number_with_format(row, col, *count, &self.count)?,
+                    XlsxCell::Percent(share) => sheet.write_number_with_format(row, col, *share, &self.percent)?,
+                    XlsxCell::Empty => continue,
+                };
+            }
+        }
+        let last_row = first_row + rows.len() as u32;
+        if !rows.is_empty() {
+            let losses = ConditionalFormatCell::new()
+                .set_rule(ConditionalFormatCellRule::LessThan(0))
+                .set_format(&self.loss);
+            for &col in profit_columns {
+                sheet.add_conditional_format(first_row + 1, col, last_row, col, &losses)?;
+            }
+        }
+        Ok(last_row + 1)
+    }
+}
+
+/// A named field of a product's month, as a number
+type TraceField = (&'static str, fn(&ProductMonthlyResult) -> f64);
+
+/// Fields of `ProductMonthlyResult` written per product and month by `export_traces_csv`
+const TRACE_FIELDS: [TraceField; 14] = [
+    ("inventory_start", |result| result.inventory_start as f64),
+    ("incoming", |result| result.incoming as f64),
+    ("base_incoming", |result| result.base_incoming as f64),
+    ("surge_incoming", |result| result.surge_incoming as f64),
+    ("backup_incoming", |result| result.backup_incoming as f64),
+    ("demand", |result| result.demand as f64),
+    ("units_sold", |result| result.units_sold as f64),
+    ("inventory_end", |result| result.inventory_end as f64),
+    ("revenue", |result| result.revenue),
+    ("production_cost", |result| result.production_cost),
+    ("holding_cost", |result| result.holding_cost),
+    ("liquidation_revenue", |result| result.liquidation_revenue),
+    ("financing_cost", |result| result.financing_cost),
+    ("goodwill_penalty", |result| result.goodwill_penalty),
+];
+
+/// Write every sampled replication trace of the given pairs to `path` as long-format CSV, one
+/// row per pair, replication, month, product and field, with the seed that reproduces the
+/// replication. Returns false without writing when no pair kept any traces
+pub fn export_traces_csv(path: &Path, results: &[MonteCarloStats]) -> io::Result<bool> {
+    if results.iter().all(|result| result.sampled_traces.is_empty()) {
+        return Ok(false);
+    }
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["pair", "replication", "seed", "month", "product", "field", "value"])?;
+    for result in results {
+        let pair = result.pair_name();
+        for trace in &result.sampled_traces {
+            for month in &trace.monthly_results {
+                for product in &month.product_results {
+                    for (field, value) in TRACE_FIELDS {
+                        writer.write_record([
+                            pair.clone(),
+                            trace.sample.replication.to_string(),
+                            trace.sample.seed.to_string(),
+                            month.month.clone(),
+                            product.product_name.clone(),
+                            field.to_string(),
+                            value(product).to_string(),
+                        ])?;
+                    }
+                }
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(true)
+}
+
+/// Write a Markdown report of a run to `path` for distribution: the scenario, the ranked
+/// results, and the best pair's allocation, risk, monthly breakdown and option activity
+/// Amounts are in whole units of the currency, written by `formatter`
+pub fn write_markdown_report(path: &Path, run: &RunOutput, formatter: &Formatter) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_markdown(&mut writer, run, formatter)?;
+    writer.flush()
+}
+
+/// Write the Markdown report of a run to `out`, as `write_markdown_report` does to a file
+pub fn write_markdown(out: &mut dyn Write, run: &RunOutput, formatter: &Formatter) -> io::Result<()> {
+    out.write_all(markdown_report(run, formatter).as_bytes())
+}
+
+/// The Markdown report of a run as a string
+fn markdown_report(run: &RunOutput, formatter: &Formatter) -> String {
+    let money = |amount: f64| formatter.whole_money(amount);
+    let count = |count: f64| formatter.count(count);
+    let mut report = String::new();
+    // Writing to a String cannot fail
+    macro_rules! out {
+        ($($arg:tt)*) => { writeln!(report, $($arg)*).unwrap() };
+    }
+
+    out!("# Supply Chain Simulation Report\n");
+
+    out!("## Scenario\n");
+    out!("| Product | Price | Holding / month | Liquidation | Forecast demand / month | Actual demand / month |");
+    out!("|---|---:|---:|---:|---:|---:|");
+    for product in &run.params.products {
+        let (forecast, actual) = run.params.get_demand_params(product.id).map_or_else(
+            || ("n/a".to_string(), "n/a".to_string()),
+            |dp| (
+                format!("{} ± {}", count(dp.mean_demand), count(dp.std_dev_demand)),
+                format!("{} ± {}", count(dp.actual_mean_demand), count(dp.actual_std_dev_demand)),
+            ),
         );
-        println!(
-            "   10th-90th Percentile: [${:.2}, ${:.2}]\n",
-            result.percentile_10, result.percentile_90
+        out!(
+            "| {} | {} | {} | {} | {} | {} |",
+            product.name,
+            money(product.selling_price),
+            formatter.money_with(product.monthly_holding_cost, 2),
+            money(product.liquidation_price),
+            forecast,
+            actual
         );
     }
-}
+    out!();
+    out!("| Supplier | Lead time | Capacity / month | Setup cost | Unit costs |");
+    out!("|---|---:|---:|---:|---|");
+    for supplier in &run.suppliers {
+        let mut unit_costs: Vec<(&ProductId, &f64)> = supplier.unit_costs.iter().collect();
+        unit_costs.sort_by_key(|(product_id, _)| **product_id);
+        let unit_costs: Vec<String> = unit_costs.iter()
+            .map(|(product_id, cost)| {
+                let name = run.params.get_product(**product_id).map_or("?", |product| product.name.as_str());
+                format!("{} {}", name, money(**cost))
+            })
+            .collect();
+        out!(
+            "| {} | {} months | {} | {} | {} |",
+            supplier.name,
+            supplier.lead_time_months,
+            count(supplier.fixed_capacity as f64),
+            money(supplier.setup_cost),
+            unit_costs.join(", ")
+        );
+    }
+    out!();
+    let fees: Vec<String> = run.params.order_change_fees.iter().map(|&fee| money(fee)).collect();
+    out!(
+        "Season of {} months; order changes allowed: {} (fees: {}).\n",
+        run.params.season_months,
+        fees.len(),
+        if fees.is_empty() { "none".to_string() } else { fees.join(", ") }
+    );
 
-/// Display the best supplier combination with detailed statistics
-pub fn display_best_result(result: &MonteCarloStats) {
-    println!("╔════════════════════════════════════════════════════════════════════════════════════════════════════╗");
-    println!("║                     BEST SUPPLIER COMBINATION (HIGHEST MEAN PROFIT)                                ║");
-    println!("╚════════════════════════════════════════════════════════════════════════════════════════════════════╝\n");
+    let mut ranked: Vec<&PairRun> = run.pairs.iter().collect();
+    ranked.sort_by(|a, b| run.ranking.score(&b.stats).total_cmp(&run.ranking.score(&a.stats)));
+    out!("## Ranked Results\n");
+    out!("Ranked by {}.\n", ranking_description(run.ranking));
+    out!("| Rank | Base | Surge | Mean profit | CI half-width | Std dev | 10th pct | 90th pct | Options exercised |");
+    out!("|---:|---|---|---:|---:|---:|---:|---:|---:|");
+    for (rank, pair) in ranked.iter().enumerate() {
+        let stats = &pair.stats;
+        out!(
+            "| {} | {} | {} | {} | {} | {} | {} | {} | {:.0}% |",
+            rank + 1,
+            stats.base_supplier,
+            match &stats.backup_supplier {
+                Some(backup) => format!("{} (backup {})", stats.surge_supplier, backup),
+                None => stats.surge_supplier.clone(),
+            },
+            money(stats.mean_profit),
+            money(stats.mean_ci_half_width()),
+            money(stats.std_dev_profit),
+            stats.p10().map_or_else(|| "n/a".to_string(), money),
+            stats.p90().map_or_else(|| "n/a".to_string(), money),
+            stats.exercise_probability * 100.0
+        );
+    }
+    out!();
 
-    println!(
-        "Base Supplier: {} ({} month lead time)\nSurge Supplier: {} ({} month lead time)\n",
-        result.base_supplier, result.base_supplier_lead_time,
-        result.surge_supplier, result.surge_supplier_lead_time,
-    );
-    
-    println!("Product Allocations:");
-    for alloc in &result.product_allocations {
-        println!(
-            "  {}: {} total (base: {}, surge: {})",
+    let Some(best) = run.best_pair.and_then(|index| run.pairs.get(index)) else {
+        return report;
+    };
+    let stats = &best.stats;
+    out!("## Best Pair: {}\n", stats.pair_name());
+    out!("### Allocation\n");
+    out!("| Product | Base | Surge | Total |");
+    out!("|---|---:|---:|---:|");
+    for alloc in &stats.product_allocations {
+        out!(
+            "| {} | {} | {} | {} |",
             alloc.product_name,
-            alloc.base_quantity + alloc.surge_quantity,
-            alloc.base_quantity,
-            alloc.surge_quantity
+            count(alloc.base_quantity as f64),
+            count(alloc.surge_quantity as f64),
+            count((alloc.base_quantity + alloc.surge_quantity) as f64)
         );
     }
-    println!("  Total Capacity Used: {}\n", result.total_capacity_used);
-    
-    println!(
-        "Expected Profit: ${:.2} ± ${:.2} (std dev)",
-        result.mean_profit, result.std_dev_profit
+    out!();
+
+    out!("### Risk\n");
+    out!("| Measure | Value |");
+    out!("|---|---:|");
+    out!("| Mean profit | {} |", money(stats.mean_profit));
+    out!(
+        "| {:.0}% CI on the mean | {} to {} |",
+        stats.confidence_level * 100.0, money(stats.mean_ci_low), money(stats.mean_ci_high)
     );
-    println!("\nProfit Distribution:");
-    println!("  Minimum:          ${:.2}", result.min_profit);
-    println!("  10th Percentile:  ${:.2}", result.percentile_10);
-    println!("  25th Percentile:  ${:.2}", result.percentile_25);
-    println!("  Median (50th):    ${:.2}", result.percentile_50);
-    println!("  75th Percentile:  ${:.2}", result.percentile_75);
-    println!("  90th Percentile:  ${:.2}", result.percentile_90);
-    println!("  Maximum:          ${:.2}", result.max_profit);
-}
-
-/// Display optimization progress message
-pub fn display_optimization_start(base_name: &str, surge_name: &str) {
-    println!(
-        "\n=== Evaluating: {} (base) + {} (surge) ===",
-        base_name, surge_name
+    out!("| Minimum | {} |", money(stats.min_profit));
+    out!("| Maximum | {} |", money(stats.max_profit));
+    out!("| Value at risk (5%) | {} |", money(stats.var_5));
+    out!("| Expected shortfall (5%) | {} |", money(stats.cvar_5));
+    out!("| P(loss) | {:.1}% |", stats.prob_loss * 100.0);
+    out!("| Simulations | {} |", count(stats.num_simulations as f64));
+    out!();
+
+    if !stats.monthly_breakdown.is_empty() {
+        out!("### Monthly Breakdown\n");
+        let charges = month_charges(&stats.monthly_breakdown);
+        let headings: String = charges.iter().map(|(heading, _)| format!(" {} |", heading)).collect();
+        out!("| Month | Revenue | Production | Holding |{} Liquidation | Fees / setup | Profit |", headings);
+        out!("|---|---:|---:|---:|{}---:|---:|---:|", "---:|".repeat(charges.len()));
+        for month in &stats.monthly_breakdown {
+            let charged: String = charges.iter().map(|(_, charge)| format!(" {} |", money(charge(month)))).collect();
+            out!(
+                "| {} | {} | {} | {} |{} {} | {} | {} |",
+                month.month,
+                money(month.mean_revenue),
+                money(month.mean_production_cost),
+                money(month.mean_holding_cost),
+                charged,
+                money(month.mean_liquidation_revenue),
+                money(month.mean_other_costs),
+                money(month.mean_profit)
+            );
+        }
+        out!();
+    }
+
+    if stats.monthly_breakdown.iter().any(|month| month.reserved_units > 0) {
+        out!("### Reserved Capacity\n");
+        out!("| Month | Reserved | Used | Used % |");
+        out!("|---|---:|---:|---:|");
+        for month in &stats.monthly_breakdown {
+            out!(
+                "| {} | {} | {} | {} |",
+                month.month,
+                count(month.reserved_units as f64),
+                count(month.mean_reserved_units_used),
+                reserved_share_used(month).map_or_else(|| "-".to_string(), |share| format!("{:.1}%", share * 100.0))
+            );
+        }
+        out!();
+    }
+
+    if !stats.product_pnl.is_empty() {
+        out!("### Profit and Loss by Product\n");
+        out!("Setup costs and fees are allocated by revenue share.\n");
+        let total = pnl_total(&stats.product_pnl);
+        let charges = pnl_charges(std::slice::from_ref(&total));
+        let headings: String = charges.iter().map(|(heading, _)| format!(" {} |", heading)).collect();
+        out!("| Product | Revenue | Cost of goods | Holding |{} Liquidation | Setup | Fees | Profit |", headings);
+        out!("|---|---:|---:|---:|{}---:|---:|---:|---:|", "---:|".repeat(charges.len()));
+        for row in stats.product_pnl.iter().chain(std::iter::once(&total)) {
+            let charged: String = charges.iter().map(|(_, charge)| format!(" {} |", money(charge(row)))).collect();
+            out!(
+                "| {} | {} | {} | {} |{} {} | {} | {} | {} |",
+                row.product_name,
+                money(row.revenue),
+                money(row.cost_of_goods),
+                money(row.holding_cost),
+                charged,
+                money(row.liquidation_revenue),
+                money(row.setup_cost),
+                money(row.fees),
+                money(row.profit)
+            );
+        }
+        out!();
+    }
+
+    if !stats.forecast_accuracy.is_empty() {
+        out!("### Forecast Accuracy\n");
+        match &stats.cost_of_forecast_error {
+            Some(cost) if cost.difference_ci_low > 0.0 => out!(
+                "The plan made on the actual demand would have made {} more on the same demand ({:.0}% CI {} to {}).\n",
+                money(cost.mean_difference),
+                cost.confidence_level * 100.0,
+                money(cost.difference_ci_low),
+                money(cost.difference_ci_high)
+            ),
+            Some(_) => out!("The plan made on the actual demand would have made no more, beyond simulation noise.\n"),
+            None => {}
+        }
+        out!("| Product | Forecast a month | Realized a month | Bias | MAPE |");
+        out!("|---|---:|---:|---:|---:|");
+        for accuracy in &stats.forecast_accuracy {
+            out!(
+                "| {} | {} | {} | {} | {:.1}% |",
+                accuracy.product_name,
+                count(accuracy.forecast_mean_demand),
+                count(accuracy.realized_mean_demand),
+                formatter.signed_count(accuracy.bias),
+                accuracy.mape * 100.0
+            );
+        }
+        out!();
+    }
+
+    out!("### Option Exercise\n");
+    out!(
+        "Order changes were exercised in {:.1}% of runs, {:.2} times per run on average, for mean fees of {}.\n",
+        stats.exercise_probability * 100.0,
+        stats.mean_exercises,
+        money(stats.mean_fees_paid)
     );
+    out!("| Month | Mean option value | P(exercise) | First exercises |");
+    out!("|---|---:|---:|---:|");
+    for (summary, (_, first)) in stats.monthly_option_summary.iter().zip(&stats.first_exercise_counts) {
+        out!(
+            "| {} | {} | {:.1}% | {} |",
+            summary.month,
+            money(summary.mean_option_value),
+            summary.exercise_frequency * 100.0,
+            first
+        );
+    }
+
+    if !stats.expansion_acceptance.is_empty() {
+        out!();
+        out!("### Capacity Expansion Offers\n");
+        out!("| Supplier | Taken by | From | Extra units a month | Fee | Taken |");
+        out!("|---:|---|---|---:|---:|---:|");
+        for (offer, acceptance) in run.params.expansion_offers.iter().zip(&stats.expansion_acceptance) {
+            out!(
+                "| {} | {} | {} | {} | {} | {:.1}% |",
+                offer.supplier_id,
+                month_name(offer.decision_month),
+                month_name(offer.start_month),
+                count(offer.extra_capacity as f64),
+                money(offer.fee),
+                acceptance * 100.0
+            );
+        }
+    }
+    report
+}
+
+/// Write the scenario matrix of `results` to `path` as a Markdown document
+pub fn write_scenario_matrix(path: &Path, results: &ScenarioResults, formatter: &Formatter) -> io::Result<()> {
+    let mut report = String::from("# Mean Profit by Scenario\n\n");
+    report.push_str("\\* marks each scenario's winner and - a pair screened out of the scenario.\n\n");
+    let _ = writeln!(report, "| Scenario | {} |", results.pairs.join(" | "));
+    let _ = writeln!(report, "|---|{}", "---:|".repeat(results.pairs.len()));
+    for (label, cells) in scenario_rows(results, formatter) {
+        let _ = writeln!(report, "| {} | {} |", label, cells.join(" | "));
+    }
+    if let Some(pair) = results.minimax_regret_pair {
+        let _ = writeln!(report, "\nSmallest worst-case regret: {}.", results.pairs[pair]);
+    }
+    std::fs::write(path, report)
 }
 
-/// Display finding optimal supply level message
-pub fn display_finding_optimal() {
-    print!("  Finding optimal supply levels... ");
-    let _ = std::io::Write::flush(&mut std::io::stdout());
+/// Write a self-contained HTML report of a run to `path`, with SVG charts: each pair's profit
+/// histogram, a percentile box chart comparing the pairs, and the best plan's mean profit by
+/// month and inventory in its worst and best seasons
+#[cfg(feature = "charts")]
+pub fn write_html_report(path: &Path, run: &RunOutput, formatter: &Formatter) -> io::Result<()> {
+    std::fs::write(path, html_report(run, formatter).map_err(|err| io::Error::other(err.to_string()))?)
 }
 
-/// Display found optimal quantities for all products
-pub fn display_found_quantities(quantities: &[(usize, String, u32)]) {
-    println!("Found:");
-    for (_, name, qty) in quantities {
-        println!("    {}: {} units", name, qty);
+/// The HTML report of a run as a string
+#[cfg(feature = "charts")]
+fn html_report(run: &RunOutput, formatter: &Formatter) -> Result<String, Box<dyn std::error::Error>> {
+    use crate::charts;
+
+    let mut ranked: Vec<&MonteCarloStats> = run.pairs.iter().map(|pair| &pair.stats).collect();
+    ranked.sort_by(|a, b| run.ranking.score(b).total_cmp(&run.ranking.score(a)));
+    let ranked: Vec<MonteCarloStats> = ranked.into_iter().cloned().collect();
+
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(html, "<title>Supply Chain Simulation Report</title>")?;
+    writeln!(html, "<style>body {{ font-family: sans-serif; max-width: 860px; margin: auto; }}</style>")?;
+    writeln!(html, "</head>\n<body>\n<h1>Supply Chain Simulation Report</h1>")?;
+    writeln!(html, "<p>Pairs ranked by {}.</p>", ranking_description(run.ranking))?;
+
+    writeln!(html, "<h2>Pair Comparison</h2>")?;
+    writeln!(html, "{}", charts::percentile_boxes(&ranked)?)?;
+
+    if let Some(best) = run.best_pair.and_then(|index| run.pairs.get(index)) {
+        let stats = &best.stats;
+        writeln!(html, "<h2>Best Pair: {}</h2>", escape_html(&stats.pair_name()))?;
+        writeln!(html, "<p>Mean profit {}.</p>", formatter.whole_money(stats.mean_profit))?;
+        if !stats.monthly_breakdown.is_empty() {
+            writeln!(html, "{}", charts::monthly_profit(stats)?)?;
+        }
+        if let (Some(worst), Some(best)) = (&stats.worst_case_trace, &stats.best_case_trace) {
+            writeln!(html, "{}", charts::season_inventory(worst, best)?)?;
+        }
+    }
+
+    writeln!(html, "<h2>Profit Distributions</h2>")?;
+    for stats in &ranked {
+        writeln!(html, "{}", charts::profit_histogram(stats)?)?;
     }
+    writeln!(html, "</body>\n</html>")?;
+    Ok(html)
 }
 
-/// Display Monte Carlo results for current combination
-pub fn display_combination_results(mean_profit: f64, std_dev: f64, min_profit: f64, max_profit: f64) {
-    println!(
-        "  Mean Profit: ${:.2} ± ${:.2} | Min: ${:.2} | Max: ${:.2}",
-        mean_profit, std_dev, min_profit, max_profit
-    );
+/// Render the Jinja template at `template_path` against a run, for a report in a layout of
+/// one's own; amounts reach the template already written by `formatter`
+#[cfg(feature = "templates")]
+pub fn render_template(template_path: &Path, run: &RunOutput, formatter: &Formatter) -> Result<String, Box<dyn std::error::Error>> {
+    let source = std::fs::read_to_string(template_path)?;
+    let name = template_path.file_name().map_or_else(|| "template".to_string(), |name| name.to_string_lossy().into_owned());
+    Ok(crate::templates::render(&name, &source, run, formatter)?)
+}
+
+/// Render the built-in template `name` against a run: "executive" for a one-page summary of
+/// the recommendation, or "technical" for every pair's statistics and breakdowns
+#[cfg(feature = "templates")]
+pub fn render_builtin_template(name: &str, run: &RunOutput, formatter: &Formatter) -> Result<String, Box<dyn std::error::Error>> {
+    let source = crate::templates::builtin(name).ok_or_else(|| format!("no built-in template \"{}\"", name))?;
+    Ok(crate::templates::render(&format!("{}.md", name), source, run, formatter)?)
+}
+
+/// Text with the characters HTML reserves escaped
+#[cfg(feature = "charts")]
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Why a pair was screened out, with the numbers behind it
+pub fn rejection(reason: &RejectionReason, formatter: &Formatter) -> String {
+    match reason {
+        RejectionReason::Screening(ScreeningFailure::EstimateBelowThreshold { estimate, threshold }) => format!(
+            "quick estimate {} is below the {} threshold",
+            formatter.money(*estimate), formatter.money(*threshold)
+        ),
+        RejectionReason::Screening(ScreeningFailure::InadequateCapacity { capacity, demand, min_coverage }) => format!(
+            "capacity of {} units covers less than {:.0}% of the {} expected",
+            formatter.count(*capacity as f64), min_coverage * 100.0, formatter.count(*demand)
+        ),
+        RejectionReason::Screening(ScreeningFailure::CostlyForPrice { cost_to_price, max }) => format!(
+            "units cost {:.0}% of their selling price, above the {:.0}% allowed",
+            cost_to_price * 100.0, max * 100.0
+        ),
+        RejectionReason::Screening(ScreeningFailure::SetupCostOverCap { setup_cost, max }) => format!(
+            "setup costs of {} exceed the {} cap",
+            formatter.money(*setup_cost), formatter.money(*max)
+        ),
+        RejectionReason::SharedRegion { region } => format!("suppliers share the {} region", region),
+        RejectionReason::ConcentratedRiskClass { risk_class, suppliers, max } => format!(
+            "{} suppliers in risk class {}, more than the {} allowed", suppliers, risk_class, max
+        ),
+        RejectionReason::Dominated { by } => format!("{} is better on every count", by),
+    }
+}
+
+/// How a ranking orders pairs, for report prose
+pub fn ranking_description(ranking: RankingMetric) -> &'static str {
+    match ranking {
+        RankingMetric::MeanProfit => "mean profit",
+        RankingMetric::ExpectedShortfall => "expected shortfall (mean profit of the worst 5% of seasons)",
+        RankingMetric::OperationalProfit => "operational profit (before setup costs and order change fees)",
+    }
+}
+
+/// Total row of a profit and loss statement
+pub fn pnl_total(rows: &[ProductPnl]) -> ProductPnl {
+    let sum = |value: fn(&ProductPnl) -> f64| rows.iter().map(value).sum();
+    ProductPnl {
+        product_id: ProductId(u32::MAX),
+        product_name: "Total".to_string(),
+        revenue: sum(|row| row.revenue),
+        cost_of_goods: sum(|row| row.cost_of_goods),
+        holding_cost: sum(|row| row.holding_cost),
+        liquidation_revenue: sum(|row| row.liquidation_revenue),
+        financing_cost: sum(|row| row.financing_cost),
+        goodwill_penalty: sum(|row| row.goodwill_penalty),
+        setup_cost: sum(|row| row.setup_cost),
+        fees: sum(|row| row.fees),
+        profit: sum(|row| row.profit),
+    }
+}
+
+/// How a column's cells line up within it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+}
+
+/// A table column: its header, how its cells line up, and whether they may be cut short to
+/// fit the table within the terminal
+#[derive(Clone, Debug)]
+struct Column {
+    header: String,
+    align: Align,
+    truncate: bool,
+}
+
+impl Column {
+    fn left(header: impl Into<String>) -> Self {
+        Column { header: header.into(), align: Align::Left, truncate: false }
+    }
+
+    fn right(header: impl Into<String>) -> Self {
+        Column { header: header.into(), align: Align::Right, truncate: false }
+    }
+
+    /// The column, its cells cut short with an ellipsis when the table would not fit otherwise
+    fn truncated(self) -> Self {
+        Column { truncate: true, ..self }
+    }
+}
+
+/// Columns two spaces apart under a header line and a rule, every line the same display width
+/// Each column is as wide as its widest cell; when that makes the table wider than `max_width`,
+/// truncatable columns give up width, widest first, down to `MIN_TRUNCATED_WIDTH`
+fn layout_table(columns: &[Column], rows: &[Vec<String>], max_width: usize) -> Vec<String> {
+    let mut widths: Vec<usize> = columns.iter().enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .map(|cell| display_width(cell))
+                .fold(display_width(&column.header), usize::max)
+        })
+        .collect();
+    let table_width = |widths: &[usize]| widths.iter().sum::<usize>() + 2 * widths.len().saturating_sub(1);
+    while table_width(&widths) > max_width {
+        let widest = (0..columns.len())
+            .filter(|&i| columns[i].truncate && widths[i] > MIN_TRUNCATED_WIDTH)
+            .max_by_key(|&i| widths[i]);
+        let Some(i) = widest else {
+            break;
+        };
+        let excess = table_width(&widths) - max_width;
+        widths[i] = widths[i].saturating_sub(excess).max(MIN_TRUNCATED_WIDTH);
+    }
+
+    let line = |cells: &[String]| {
+        columns.iter().zip(&widths).enumerate()
+            .map(|(i, (column, &width))| {
+                let cell = cells.get(i).map_or("", String::as_str);
+                let cell = if display_width(cell) > width { truncate(&strip_styles(cell), width) } else { cell.to_string() };
+                let padding = " ".repeat(width - display_width(&cell));
+                match column.align {
+                    Align::Left => cell + &padding,
+                    Align::Right => padding + &cell,
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+    let headers: Vec<String> = columns.iter().map(|column| column.header.clone()).collect();
+    let mut lines = vec![line(&headers), "-".repeat(table_width(&widths))];
+    lines.extend(rows.iter().map(|row| line(row)));
+    lines
+}
+
+/// Table with a right-aligned column per header after a label column, each as wide as its
+/// widest cell
+fn aligned_table(headers: &[String], rows: &[(String, Vec<String>)]) -> Vec<String> {
+    let columns: Vec<Column> = std::iter::once(Column::left(""))
+        .chain(headers.iter().map(|header| Column::right(header.as_str())))
+        .collect();
+    let rows: Vec<Vec<String>> = rows.iter()
+        .map(|(label, cells)| std::iter::once(label.clone()).chain(cells.iter().cloned()).collect())
+        .collect();
+    layout_table(&columns, &rows, usize::MAX)
+}
+
+/// A title centered in a double-lined box as wide as the terminal, up to `MAX_BANNER_WIDTH`
+fn banner(title: &str, width: usize) -> [String; 3] {
+    let inner = width.min(MAX_BANNER_WIDTH).saturating_sub(2).max(display_width(title) + 2);
+    let left = (inner - display_width(title)) / 2;
+    [
+        format!("╔{}╗", "═".repeat(inner)),
+        format!("║{}{}{}║", " ".repeat(left), title, " ".repeat(inner - left - display_width(title))),
+        format!("╚{}╝", "═".repeat(inner)),
+    ]
+}
+
+/// Columns `text` takes on a terminal: its characters, box-drawing ones included, less any
+/// color codes
+fn display_width(text: &str) -> usize {
+    anstream::adapter::strip_str(text).map(|piece| piece.chars().count()).sum()
+}
+
+/// `text` without its color codes
+fn strip_styles(text: &str) -> String {
+    anstream::adapter::strip_str(text).to_string()
+}
+
+/// Scenario matrix rows: each scenario's mean profits, the winner starred, then each pair's
+/// largest regret
+fn scenario_rows(results: &ScenarioResults, formatter: &Formatter) -> Vec<(String, Vec<String>)> {
+    let mut rows: Vec<(String, Vec<String>)> = results.scenarios.iter().enumerate()
+        .map(|(scenario, name)| {
+            let cells = results.mean_profits[scenario].iter().enumerate()
+                .map(|(pair, profit)| match profit {
+                    Some(profit) if results.winners[scenario] == Some(pair) => format!("*{}", formatter.whole_money(*profit)),
+                    Some(profit) => formatter.whole_money(*profit),
+                    None => "-".to_string(),
+                })
+                .collect();
+            (name.clone(), cells)
+        })
+        .collect();
+    let max_regrets = results.max_regrets.iter()
+        .map(|regret| regret.map_or_else(|| "-".to_string(), |regret| formatter.whole_money(regret)))
+        .collect();
+    rows.push(("Max regret".to_string(), max_regrets));
+    rows
+}
+
+/// A row per season of a multi-season plan: its opening stock and units ordered, setup cost,
+/// and mean profit before and after discounting
+fn season_rows(plan: &MultiSeasonPlan, formatter: &Formatter) -> Vec<(String, Vec<String>)> {
+    plan.seasons.iter().enumerate()
+        .map(|(season, plan)| {
+            let opening: u32 = plan.opening_inventory.iter().map(|&(_, units)| units).sum();
+            let cells = vec![
+                formatter.count(opening as f64),
+                formatter.count(plan.units_ordered() as f64),
+                formatter.whole_money(plan.stats.mean_setup_cost),
+                formatter.whole_money(plan.stats.mean_profit),
+                formatter.whole_money(plan.discount_factor * plan.stats.mean_profit),
+            ];
+            (format!("Season {}", season + 1), cells)
+        })
+        .collect()
+}
+
+/// Each plan of a comparison with its monthly units, profit, fill rate and its difference from
+/// the optimizer's plan, with its half-width and p-value; the optimizer's own row has none
+fn plan_rows(comparison: &PlanComparison, formatter: &Formatter) -> Vec<(String, Vec<String>)> {
+    comparison.plans.iter()
+        .map(|plan| {
+            let (difference, p_value) = match &plan.versus_optimizer {
+                Some(versus) => (
+                    format!(
+                        "{} ± {}{}",
+                        formatter.whole_money(versus.mean_difference),
+                        formatter.whole_money((versus.difference_ci_high - versus.difference_ci_low) / 2.0),
+                        if versus.is_significant() { " *" } else { "" },
+                    ),
+                    format!("{:.4}", versus.p_value),
+                ),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            let cells = vec![
+                formatter.count(plan.base_units as f64),
+                formatter.count(plan.surge_units as f64),
+                formatter.whole_money(plan.mean_profit),
+                formatter.whole_money(plan.cvar_5),
+                format!("{:.1}%", plan.fill_rate * 100.0),
+                difference,
+                p_value,
+            ];
+            (plan.name.clone(), cells)
+        })
+        .collect()
+}
+
+/// Each supplier of a pair with its role, planned utilization, the same every month, and mean
+/// realized utilization by month; the backup plans for the surge units beyond the surge capacity
+fn supplier_utilization<'a>(pair: &'a SupplierPair, stats: &MonteCarloStats) -> Vec<(&'a Supplier, &'static str, f64, Vec<f64>)> {
+    let planned_base: u32 = stats.product_allocations.iter().map(|alloc| alloc.base_quantity).sum();
+    let planned_surge: u32 = stats.product_allocations.iter().map(|alloc| alloc.surge_quantity).sum();
+    let realized = |utilization: fn(&MonthStats) -> f64| stats.monthly_breakdown.iter().map(utilization).collect();
+    let surge_capacity = pair.surge_supplier.fixed_capacity;
+    let mut suppliers = vec![
+        (
+            &pair.base_supplier,
+            "base",
+            utilization(planned_base as f64, pair.base_supplier.fixed_capacity),
+            realized(|month| month.mean_base_utilization),
+        ),
+        (
+            &pair.surge_supplier,
+            "surge",
+            utilization(planned_surge.min(surge_capacity) as f64, surge_capacity),
+            realized(|month| month.mean_surge_utilization),
+        ),
+    ];
+    if let Some(backup) = &pair.backup_supplier {
+        suppliers.push((
+            backup,
+            "backup",
+            utilization(planned_surge.saturating_sub(surge_capacity) as f64, backup.fixed_capacity),
+            realized(|month| month.mean_backup_utilization),
+        ));
+    }
+    suppliers
+}
+
+/// Risk assessment lines for a result, leaving out the metrics its run did not compute and the
+/// chance of reaching `target_profit` when no target was given
+fn risk_rows(stats: &MonteCarloStats, target_profit: Option<f64>, formatter: &Formatter) -> Vec<String> {
+    let money = |amount: f64| formatter.money(amount);
+    let row = |label: String, value: String| format!("  {:<25} {}", label, value);
+    let mut rows = vec![
+        row("P(Loss):".to_string(), format!("{:.1}%", stats.prob_loss * 100.0)),
+        row("Value at Risk (5%):".to_string(), paint_loss(stats.var_5, money(stats.var_5))),
+        row("Expected Shortfall (5%):".to_string(), paint_loss(stats.cvar_5, money(stats.cvar_5))),
+    ];
+    if let Some(deviation) = stats.downside_deviation {
+        rows.push(row("Downside Deviation:".to_string(), money(deviation)));
+    }
+    // Probabilities are read off the ECDF summary, which a run may not have kept
+    if !stats.ecdf_summary.is_empty() {
+        let below_90 = stats.prob_below(0.9 * stats.mean_profit);
+        rows.push(row("P(Profit < 90% of Mean):".to_string(), format!("{:.1}%", below_90 * 100.0)));
+        if let Some(target) = target_profit {
+            let reaching = 1.0 - stats.prob_below(target);
+            rows.push(row(format!("P(Profit >= {}):", money(target)), format!("{:.1}%", reaching * 100.0)));
+        }
+    }
+    if let (Some(trace), Some(worst)) = (&stats.worst_case_trace, &stats.worst_month) {
+        let driver = match worst.driver {
+            LossDriver::Stockout => "lost sales",
+            LossDriver::Holding => "holding costs",
+            LossDriver::Fees => "fees",
+        };
+        rows.push(row("Worst Season:".to_string(), paint_loss(trace.sample.profit, money(trace.sample.profit))));
+        rows.push(row("Worst Month:".to_string(), format!(
+            "{}, {} (driven by {} of {})",
+            worst.month, paint_loss(worst.profit, money(worst.profit)), driver, money(worst.driver_cost)
+        )));
+    }
+    rows
+}
+
+/// Utilization table rows: each supplier's planned share of capacity, then its mean realized
+/// share, with fragile months marked `!`
+fn utilization_rows(pair: &SupplierPair, stats: &MonteCarloStats) -> Vec<(String, Vec<String>)> {
+    let percent = |utilization: f64| {
+        format!("{:.0}%{}", utilization * 100.0, if is_fragile(utilization) { "!" } else { " " })
+    };
+    supplier_utilization(pair, stats).into_iter()
+        .flat_map(|(supplier, role, planned, realized)| [
+            (format!("{} ({}) planned", supplier.name, role), vec![percent(planned); realized.len()]),
+            (format!("{} ({}) realized", supplier.name, role), realized.into_iter().map(percent).collect()),
+        ])
+        .collect()
+}
+
+/// A warning for each supplier whose planned or realized utilization is fragile in any month,
+/// naming those months and the peak
+fn fragility_warnings(pair: &SupplierPair, stats: &MonteCarloStats) -> Vec<String> {
+    supplier_utilization(pair, stats).into_iter()
+        .filter_map(|(supplier, _, planned, realized)| {
+            let peaks: Vec<f64> = realized.iter().map(|&realized| realized.max(planned)).collect();
+            let fragile: Vec<&str> = stats.monthly_breakdown.iter().zip(&peaks)
+                .filter(|(_, &peak)| is_fragile(peak))
+                .map(|(month, _)| month.month.as_str())
+                .collect();
+            if fragile.is_empty() {
+                return None;
+            }
+            Some(format!(
+                "{} runs above {:.0}% of capacity in {} (peak {:.0}%): little room to absorb a disruption",
+                supplier.name,
+                FRAGILE_UTILIZATION * 100.0,
+                fragile.join(", "),
+                peaks.iter().copied().fold(0.0, f64::max) * 100.0
+            ))
+        })
+        .collect()
+}
+
+/// Tornado chart rows sorted by impact: the label and range, then `half_width` columns either
+/// side of the axis, scaled so the largest change fills its side, then both changes
+fn tornado_rows(results: &[SensitivityResult], half_width: usize, formatter: &Formatter) -> Vec<String> {
+    let mut sorted: Vec<&SensitivityResult> = results.iter().collect();
+    sorted.sort_by(|a, b| b.impact().total_cmp(&a.impact()));
+    let largest = sorted.first().map_or(0.0, |result| result.impact());
+    let labels: Vec<String> = sorted.iter()
+        .map(|result| format!("{} (±{:.0}%)", result.label, result.fraction * 100.0))
+        .collect();
+    let label_width = labels.iter().map(|label| label.chars().count()).max().unwrap_or(0);
+    let signed = |delta: f64| {
+        let money = formatter.whole_money(delta);
+        if money.starts_with('-') { money } else { format!("+{}", money) }
+    };
+
+    sorted.iter().zip(labels)
+        .map(|(result, label)| {
+            // Columns covered by each change, the longer drawn first so the shorter stays visible
+            let columns = |delta: f64| {
+                if largest > 0.0 { (delta.abs() / largest * half_width as f64).round() as usize } else { 0 }
+            };
+            let mut left = vec![' '; half_width];
+            let mut right = vec![' '; half_width];
+            let mut changes = [(result.low_delta, '-'), (result.high_delta, '+')];
+            changes.sort_by(|a, b| b.0.abs().total_cmp(&a.0.abs()));
+            for (delta, mark) in changes {
+                if delta < 0.0 {
+                    left[half_width - columns(delta)..].fill(mark);
+                } else {
+                    right[..columns(delta)].fill(mark);
+                }
+            }
+            format!(
+                "{:<label_width$} {}|{} low {} / high {}",
+                label,
+                left.iter().collect::<String>(),
+                right.iter().collect::<String>(),
+                signed(result.low_delta),
+                signed(result.high_delta)
+            )
+        })
+        .collect()
+}
+
+/// A charge a table may show, as (heading, charge of a row)
+type Charge<T> = (&'static str, fn(&T) -> f64);
+
+/// Charges a table shows only when some of its rows incur them: stock financing and goodwill
+/// penalties, which most scenarios leave out
+fn charged<T>(rows: &[T], charges: [Charge<T>; 2]) -> Vec<Charge<T>> {
+    charges.into_iter()
+        .filter(|(_, charge)| rows.iter().any(|row| charge(row) != 0.0))
+        .collect()
+}
+
+/// Monthly charges shown for `months`
+fn month_charges(months: &[MonthStats]) -> Vec<Charge<MonthStats>> {
+    charged(months, [("Financing", |month| month.mean_financing_cost), ("Goodwill", |month| month.mean_goodwill_penalty)])
+}
+
+/// Profit and loss charges shown for `rows`
+fn pnl_charges(rows: &[ProductPnl]) -> Vec<Charge<ProductPnl>> {
+    charged(rows, [("Financing", |row| row.financing_cost), ("Goodwill", |row| row.goodwill_penalty)])
+}
+
+/// Profit and loss table with a row per product, then a rule and their total
+fn pnl_table(rows: &[ProductPnl], formatter: &Formatter, width: usize) -> Vec<String> {
+    let total = pnl_total(rows);
+    let charges = pnl_charges(std::slice::from_ref(&total));
+    let mut columns = vec![
+        Column::left("Product").truncated(),
+        Column::right("Revenue"),
+        Column::right("Cost of goods"),
+        Column::right("Holding"),
+        Column::right("Liquidation"),
+        Column::right("Setup"),
+        Column::right("Fees"),
+        Column::right("Profit"),
+    ];
+    columns.splice(4..4, charges.iter().map(|&(heading, _)| Column::right(heading)));
+    let money = |amount: f64| formatter.whole_money(amount);
+    let cells: Vec<Vec<String>> = rows.iter()
+        .chain([&total])
+        .map(|row| {
+            let mut cells = vec![
+                row.product_name.clone(),
+                money(row.revenue),
+                money(row.cost_of_goods),
+                money(row.holding_cost),
+                money(row.liquidation_revenue),
+                money(row.setup_cost),
+                money(row.fees),
+                money(row.profit),
+            ];
+            cells.splice(4..4, charges.iter().map(|(_, charge)| money(charge(row))));
+            cells
+        })
+        .collect();
+    let mut lines = layout_table(&columns, &cells, width);
+    lines.insert(lines.len() - 1, lines[1].clone());
+    lines
+}
+
+/// Ranking table of each pair's profit distribution, in the order given, the first marked best
+fn profit_table(results: &[MonteCarloStats], formatter: &Formatter, width: usize) -> Vec<String> {
+    let columns = [
+        Column::right("#"),
+        Column::left("Pair").truncated(),
+        Column::right("Mean"),
+        Column::right("± CI"),
+        Column::right("Std Dev"),
+        Column::right("P10"),
+        Column::right("Median"),
+        Column::right("P90"),
+        Column::right("P(Change)"),
+    ];
+    fit_amounts(formatter, width, |formatter| {
+        let money = |amount: f64| paint_loss(amount, formatter.table_money(amount));
+        // Percentiles that were not requested show as "n/a"
+        let percentile = |profit: Option<f64>| profit.map_or_else(|| "n/a".to_string(), money);
+        let rows: Vec<Vec<String>> = results.iter().enumerate()
+            .map(|(rank, stats)| {
+                vec![
+                    (rank + 1).to_string(),
+                    stats.pair_name(),
+                    money(stats.mean_profit),
+                    formatter.table_money(stats.mean_ci_half_width()),
+                    formatter.table_money(stats.std_dev_profit),
+                    percentile(stats.p10()),
+                    percentile(stats.p50()),
+                    percentile(stats.p90()),
+                    format!("{:.0}%", stats.exercise_probability * 100.0),
+                ]
+            })
+            .collect();
+        let mut lines = layout_table(&columns, &rows, width);
+        if let Some(best) = lines.get_mut(2) {
+            *best = paint(BEST, &best);
+        }
+        lines
+    })
+}
+
+/// Table of recorded runs: when each was recorded, how it ranked pairs, and its best pair
+#[cfg(feature = "sqlite")]
+fn history_table(runs: &[RunSummary], formatter: &Formatter, width: usize) -> Vec<String> {
+    let columns = [
+        Column::right("Run"),
+        Column::left("Recorded (UTC)"),
+        Column::left("Ranking"),
+        Column::right("Pairs"),
+        Column::left("Best pair").truncated(),
+        Column::right("Mean profit"),
+    ];
+    let rows: Vec<Vec<String>> = runs.iter()
+        .map(|run| vec![
+            run.id.to_string(),
+            run.recorded_at.clone(),
+            run.ranking.clone(),
+            run.pairs.to_string(),
+            run.best_pair.clone().unwrap_or_else(|| "-".to_string()),
+            run.best_mean_profit.map_or_else(|| "-".to_string(), |profit| paint_loss(profit, formatter.whole_money(profit))),
+        ])
+        .collect();
+    layout_table(&columns, &rows, width)
+}
+
+/// Table of each pair's suppliers and lead times, the base and surge units it orders of each
+/// product, and its total; backups get a column when any pair has one
+fn allocation_table(results: &[MonteCarloStats], formatter: &Formatter, width: usize) -> Vec<String> {
+    let products = results.first().map_or(&[][..], |stats| &stats.product_allocations[..]);
+    let backups = results.iter().any(|stats| stats.backup_supplier.is_some());
+    let columns: Vec<Column> = [Column::right("#"), Column::left("Base").truncated(), Column::left("Surge").truncated()]
+        .into_iter()
+        .chain(backups.then(|| Column::left("Backup").truncated()))
+        .chain(products.iter().map(|alloc| Column::right(alloc.product_name.as_str())))
+        .chain([Column::right("Total")])
+        .collect();
+    let units = |units: u32| formatter.count(units as f64);
+    let rows: Vec<Vec<String>> = results.iter().enumerate()
+        .map(|(rank, stats)| {
+            [
+                (rank + 1).to_string(),
+                format!("{} ({}mo)", stats.base_supplier, stats.base_supplier_lead_time),
+                format!("{} ({}mo)", stats.surge_supplier, stats.surge_supplier_lead_time),
+            ]
+            .into_iter()
+            .chain(backups.then(|| stats.backup_supplier.clone().unwrap_or_else(|| "-".to_string())))
+            .chain(stats.product_allocations.iter().map(|alloc| format!("{} / {}", units(alloc.base_quantity), units(alloc.surge_quantity))))
+            .chain([units(stats.total_capacity_used)])
+            .collect()
+        })
+        .collect();
+    layout_table(&columns, &rows, width)
+}
+
+/// Table of each pair's mean operational profit, setup costs and order change fees
+fn cost_table(results: &[MonteCarloStats], formatter: &Formatter, width: usize) -> Vec<String> {
+    let columns = [
+        Column::right("#"),
+        Column::left("Pair").truncated(),
+        Column::right("Operational"),
+        Column::right("Setup"),
+        Column::right("Order changes"),
+        Column::right("Rev/unit"),
+        Column::right("Landed/unit"),
+        Column::right("Margin"),
+    ];
+    fit_amounts(formatter, width, |formatter| {
+        let rows: Vec<Vec<String>> = results.iter().enumerate()
+            .map(|(rank, stats)| vec![
+                (rank + 1).to_string(),
+                stats.pair_name(),
+                formatter.table_money(stats.mean_operational_profit),
+                formatter.table_money(stats.mean_setup_cost),
+                formatter.table_money(stats.mean_fees_paid),
+                formatter.money_with(stats.revenue_per_unit, 2),
+                formatter.money_with(stats.landed_cost_per_unit, 2),
+                paint_loss(stats.landed_margin, format!("{:.1}%", stats.landed_margin * 100.0)),
+            ])
+            .collect();
+        layout_table(&columns, &rows, width)
+    })
+}
+
+/// A table of amounts laid out by `table`, again with abbreviated amounts if written in full it
+/// would be wider than `width`
+fn fit_amounts(formatter: &Formatter, width: usize, table: impl Fn(&Formatter) -> Vec<String>) -> Vec<String> {
+    let lines = table(formatter);
+    if formatter.compact || lines.first().is_none_or(|line| display_width(line) <= width) {
+        return lines;
+    }
+    table(&Formatter { compact: true, ..formatter.clone() })
+}
+
+/// Box plot rows, one per pair on an axis shared from the lowest to the highest profit, then
+/// the axis with its ends and midpoint labeled, all fitting within `width` columns
+/// Each row reads `├──[▓▓│▓▓]──┤`: whiskers at the minimum and maximum, the box between the
+/// quartiles (the 10th and 90th percentiles when quartiles were not computed) and `│` at the median
+fn boxplot_rows(results: &[MonteCarloStats], width: usize, formatter: &Formatter) -> Vec<String> {
+    let low = results.iter().map(|stats| stats.min_profit).fold(f64::INFINITY, f64::min);
+    let high = results.iter().map(|stats| stats.max_profit).fold(f64::NEG_INFINITY, f64::max);
+    let labels: Vec<String> = results.iter()
+        .map(|stats| stats.pair_name())
+        .collect();
+    // Long names are cut so the plot keeps at least two thirds of a narrow terminal
+    let label_width = labels.iter().map(|label| label.chars().count()).max().unwrap_or(0).min(width / 3);
+    let plot_width = width.saturating_sub(label_width + 4).max(20);
+    let column = |profit: f64| {
+        if high > low {
+            ((profit - low) / (high - low) * (plot_width - 1) as f64).round() as usize
+        } else {
+            (plot_width - 1) / 2
+        }
+    };
+
+    let mut rows: Vec<String> = results.iter().zip(&labels)
+        .map(|(stats, label)| {
+            let (box_low, box_high) = match (stats.percentile(25.0), stats.percentile(75.0)) {
+                (Some(q1), Some(q3)) => (q1, q3),
+                _ => (stats.p10().unwrap_or(stats.min_profit), stats.p90().unwrap_or(stats.max_profit)),
+            };
+            let median = stats.p50().unwrap_or(stats.mean_profit);
+            let mut plot = vec![' '; plot_width];
+            let (min, max) = (column(stats.min_profit), column(stats.max_profit));
+            plot[min..=max].fill('─');
+            plot[min] = '├';
+            plot[max] = '┤';
+            let (box_low, box_high) = (column(box_low), column(box_high));
+            plot[box_low..=box_high].fill('▓');
+            plot[box_low] = '[';
+            plot[box_high] = ']';
+            plot[column(median)] = '│';
+            format!("  {:<label_width$}  {}", truncate(label, label_width), plot.into_iter().collect::<String>().trim_end())
+        })
+        .collect();
+
+    let mut axis = vec!['─'; plot_width];
+    axis[0] = '└';
+    axis[plot_width / 2] = '┴';
+    axis[plot_width - 1] = '┘';
+    rows.push(format!("  {:label_width$}  {}", "", axis.into_iter().collect::<String>()));
+    let (low_tick, high_tick) = (formatter.compact_money(low), formatter.compact_money(high));
+    let mid_tick = formatter.compact_money((low + high) / 2.0);
+    // The midpoint label is centered under its tick, between the two end labels
+    let mid_start = (plot_width / 2).saturating_sub(mid_tick.chars().count() / 2).max(low_tick.chars().count() + 1);
+    let high_start = plot_width.saturating_sub(high_tick.chars().count()).max(mid_start + mid_tick.chars().count() + 1);
+    rows.push(format!(
+        "  {:label_width$}  {:<mid_start$}{:<mid_width$}{}",
+        "", low_tick, mid_tick, high_tick,
+        mid_width = high_start - mid_start
+    ));
+    rows
+}
+
+/// `text` cut to `width` characters, ending in an ellipsis when it was cut
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+/// One chart row per histogram bin, "$low to $high |#### count", fitting within `width` columns
+fn histogram_rows(histogram: &[(f64, f64, usize)], width: usize, formatter: &Formatter) -> Vec<String> {
+    let labels: Vec<String> = histogram.iter()
+        .map(|(low, high, _)| format!("{:>14} to {:>14}", formatter.whole_money(*low), formatter.whole_money(*high)))
+        .collect();
+    let largest = histogram.iter().map(|(_, _, count)| *count).max().unwrap_or(0);
+    let count_width = largest.to_string().len();
+    // Two leading spaces, the label, " |", a space and the count; keep some bar however narrow
+    let label_width = labels.first().map_or(0, |label| label.chars().count());
+    let bar_width = width.saturating_sub(label_width + count_width + 5).max(10);
+
+    histogram.iter().zip(labels)
+        .map(|((_, _, count), label)| {
+            let bar_length = (count * bar_width).checked_div(largest).unwrap_or(0);
+            format!("  {} |{:<bar_width$} {:>count_width$}", label, "#".repeat(bar_length), count)
+        })
+        .collect()
+}
+
+/// Terminal width from the `COLUMNS` environment variable, else the width of the terminal
+/// stdout is on, else `DEFAULT_TERMINAL_WIDTH`
+fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .or_else(stdout_width)
+        .unwrap_or(DEFAULT_TERMINAL_WIDTH)
+}
+
+/// Width of the terminal stdout is on, if it is on one
+#[cfg(feature = "cli")]
+fn stdout_width() -> Option<usize> {
+    terminal_size::terminal_size().map(|(width, _)| width.0 as usize)
+}
+
+/// Without the `cli` feature the terminal is never asked, leaving `COLUMNS` or the default
+#[cfg(not(feature = "cli"))]
+fn stdout_width() -> Option<usize> {
+    None
+}
+
+/// `text` in `style`; the styling is dropped when the reporter's stream is not colored
+fn paint(style: Style, text: impl Display) -> String {
+    format!("{style}{text}{style:#}")
+}
+
+/// `text`, an amount of profit, in the loss style when `profit` is negative
+fn paint_loss(profit: f64, text: String) -> String {
+    if profit < 0.0 { paint(LOSS, text) } else { text }
+}
+
+/// Percentile level as an ordinal, e.g. 1st, 22nd, 90th or 99.5th
+fn ordinal(level: f64) -> String {
+    if level.fract() != 0.0 {
+        return format!("{}th", level);
+    }
+    let whole = level as u64;
+    let suffix = match (whole % 10, whole % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", whole, suffix)
+}
+
+/// Confidence interval half-width as a fraction of the mean profit's magnitude
+fn relative_half_width(result: &MonteCarloStats) -> f64 {
+    if result.mean_profit == 0.0 {
+        return 0.0;
+    }
+    result.mean_ci_half_width() / result.mean_profit.abs()
+}
+
+/// Share of a month's reserved capacity the suppliers delivered on average, None when nothing
+/// was reserved that month
+fn reserved_share_used(month: &MonthStats) -> Option<f64> {
+    (month.reserved_units > 0).then(|| month.mean_reserved_units_used / month.reserved_units as f64)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::SupplierId;
+    use crate::capacity::create_order;
+    use crate::models::{ProductAllocation, WorstMonth};
+    use crate::monte_carlo::tests::two_product_scenario;
+    use crate::monte_carlo::{paired_comparison, run_monte_carlo_simulation, MonteCarloConfig};
+    use crate::simulation::{project_mean_demand, split_order_quantities};
+    use crate::schema::SCHEMA_VERSION;
+    use crate::planning::SeasonPlan;
+
+    #[test]
+    fn test_histogram_rows_fit_the_terminal() {
+        let histogram = vec![
+            (-250_000.0, 0.0, 3),
+            (0.0, 250_000.0, 40),
+            (250_000.0, 500_000.0, 0),
+            (500_000.0, 750_000.0, 17),
+        ];
+        for width in [60, 80, 132] {
+            let rows = histogram_rows(&histogram, width, &Formatter::default());
+            assert_eq!(rows.len(), histogram.len());
+            assert!(rows.iter().all(|row| row.chars().count() == width), "{:?}", rows);
+            // The fullest bin fills the bar, others scale against it, and an empty bin draws none
+            assert!(rows[1].ends_with("# 40"));
+            assert!(rows[3].matches('#').count() < rows[1].matches('#').count());
+            assert!(!rows[2].contains('#'));
+        }
+    }
+
+    #[test]
+    fn test_tornado_puts_the_largest_impact_first() {
+        let result = |label: &str, low_delta: f64, high_delta: f64| SensitivityResult {
+            label: label.to_string(),
+            fraction: 0.1,
+            base_mean_profit: 1_000_000.0,
+            low_delta,
+            high_delta,
+        };
+        let rows = tornado_rows(&[result("Holding", 20_000.0, -20_000.0), result("Price", -100_000.0, 50_000.0)], 10, &Formatter::default());
+        assert_eq!(rows[0], "Price (±10%)   ----------|+++++      low -$100,000 / high +$50,000");
+        assert_eq!(rows[1], "Holding (±10%)         ++|--         low +$20,000 / high -$20,000");
+    }
+
+    #[test]
+    fn test_scenario_matrix_marks_winners_and_gaps() {
+        let results = ScenarioResults::new(
+            vec!["Boom".to_string(), "Downturn".to_string()],
+            vec!["A + X".to_string(), "B + X".to_string(), "B + Y".to_string()],
+            vec![
+                vec![Some(1_000_000.0), Some(900_000.0), Some(950_000.0)],
+                vec![Some(200_000.0), Some(500_000.0), None],
+            ],
+        );
+        let lines = aligned_table(&results.pairs, &scenario_rows(&results, &Formatter::default()));
+        assert_eq!(lines[0], "                  A + X      B + X     B + Y");
+        assert_eq!(lines[2], "Boom        *$1,000,000   $900,000  $950,000");
+        assert_eq!(lines[3], "Downturn       $200,000  *$500,000         -");
+        assert_eq!(lines[4], "Max regret     $300,000   $100,000   $50,000");
+    }
+
+    #[test]
+    fn test_season_rows_discount_later_seasons() {
+        let (params, pair) = two_product_scenario();
+        let monthly_order = create_order(vec![(ProductId(0), 5_000), (ProductId(1), 2_000)], Vec::new());
+        let mut stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &MonteCarloConfig::new(5)).unwrap();
+        stats.mean_profit = 1_100_000.0;
+        let season = |opening_inventory: Vec<(ProductId, u32)>, discount_factor: f64, mean_setup_cost: f64| SeasonPlan {
+            opening_inventory,
+            onboarded_suppliers: Vec::new(),
+            optimal_quantities: vec![(ProductId(0), 5_000), (ProductId(1), 2_000)],
+            season_months: params.season_months,
+            discount_factor,
+            stats: MonteCarloStats { mean_setup_cost, ..stats.clone() },
+        };
+        let plan = MultiSeasonPlan {
+            seasons: vec![season(Vec::new(), 1.0, 20_000.0), season(vec![(ProductId(0), 9_000), (ProductId(1), 1_500)], 1.0 / 1.1, 0.0)],
+            npv_samples: Vec::new(),
+            mean_npv: 2_100_000.0,
+            npv_percentiles: Vec::new(),
+        };
+        let headers = ["Opening stock", "Ordered", "Setup", "Mean profit", "Discounted"].map(String::from);
+        let lines = aligned_table(&headers, &season_rows(&plan, &Formatter::default()));
+        assert_eq!(lines[0], "          Opening stock  Ordered    Setup  Mean profit  Discounted");
+        assert_eq!(lines[2], "Season 1              0   56,000  $20,000   $1,100,000  $1,100,000");
+        assert_eq!(lines[3], "Season 2         10,500   56,000       $0   $1,100,000  $1,000,000");
+    }
+
+    #[test]
+    fn test_utilization_flags_fragile_months() {
+        let (params, pair) = two_product_scenario();
+        // 6,650 of the base supplier's 7,000 is exactly 95%, not yet fragile
+        let monthly_order = create_order(vec![(ProductId(0), 5_650), (ProductId(1), 1_000)], vec![(ProductId(0), 1_000)]);
+        let mut stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &MonteCarloConfig::new(5)).unwrap();
+        for month in &mut stats.monthly_breakdown {
+            month.mean_base_utilization = 0.95;
+            month.mean_surge_utilization = 0.2;
+        }
+        assert!(fragility_warnings(&pair, &stats).is_empty());
+
+        stats.monthly_breakdown[1].mean_base_utilization = 0.97;
+        let rows = utilization_rows(&pair, &stats);
+        assert_eq!(rows[0].1[0], "95% ");
+        assert_eq!(rows[1].1[..2], ["95% ".to_string(), "97%!".to_string()]);
+        assert_eq!(
+            fragility_warnings(&pair, &stats),
+            ["Supplier 0 runs above 95% of capacity in June (peak 97%): little room to absorb a disruption"]
+        );
+    }
+
+    #[test]
+    fn test_csv_exports_read_back() {
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        params.products[0].name = "Widget, large".to_string();
+        let monthly_order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        let config = MonteCarloConfig { master_seed: 21, ..MonteCarloConfig::new(20) };
+        let results = vec![run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap()];
+
+        let dir = std::env::temp_dir().join(format!("supply-chain-sim-csv-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        export_results_csv(&dir.join("results.csv"), &results).unwrap();
+        assert!(export_monthly_csv(&dir.join("monthly.csv"), &results).unwrap());
+
+        let mut reader = csv::Reader::from_path(dir.join("results.csv")).unwrap();
+        let header = reader.headers().unwrap().clone();
+        assert_eq!(&header[6], "Widget, large base");
+        assert_eq!(&header[header.len() - 1], "backup_capacity_shortfall");
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(rows.len(), 1);
+        let result = &results[0];
+        let field = |name: &str| &rows[0][header.iter().position(|column| column == name).unwrap()];
+        assert_eq!(field("base_supplier"), result.base_supplier);
+        assert_eq!(field("backup_supplier"), "");
+        assert_eq!(field("Widget, large base").parse::<u32>().unwrap(), result.product_allocations[0].base_quantity);
+        assert_eq!(field("Gadget surge").parse::<u32>().unwrap(), result.product_allocations[1].surge_quantity);
+        assert_eq!(field("mean_profit").parse::<f64>().unwrap(), result.mean_profit);
+        assert_eq!(field("p50").parse::<f64>().unwrap(), result.p50().unwrap());
+        assert_eq!(field("landed_margin").parse::<f64>().unwrap(), result.landed_margin);
+        assert_eq!(field("base_delivered").parse::<f64>().unwrap(), result.capacity_usage[0].mean_delivered);
+        assert_eq!(field("surge_capacity_shortfall").parse::<f64>().unwrap(), result.capacity_usage[1].mean_shortfall);
+        assert_eq!(field("backup_delivered"), "");
+
+        let mut reader = csv::Reader::from_path(dir.join("monthly.csv")).unwrap();
+        let months: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(months.len(), result.monthly_breakdown.len());
+        for (row, month) in months.iter().zip(&result.monthly_breakdown) {
+            assert_eq!(&row[4], month.month);
+            assert_eq!(row[10].parse::<f64>().unwrap(), month.mean_profit);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_month_columns_fit_their_widest_cell() {
+        let months: Vec<String> = ["May", "June", "July"].map(String::from).to_vec();
+        let rows = vec![
+            ("Demand".to_string(), ["4,000", "4,000", "4,000"].map(String::from).to_vec()),
+            ("Ending inventory".to_string(), ["6,000", "12,000", "0"].map(String::from).to_vec()),
+        ];
+        let lines = aligned_table(&months, &rows);
+        assert_eq!(lines[0], "                    May    June   July");
+        assert_eq!(lines[3], "Ending inventory  6,000  12,000      0");
+        assert!(lines.iter().all(|line| line.len() == lines[0].len()));
+    }
+
+    #[test]
+    fn test_amounts_group_thousands() {
+        let us = Formatter::default();
+        assert_eq!(us.money(10_234_567.891), "$10,234,567.89");
+        assert_eq!(us.whole_money(1_234_567.6), "$1,234,568");
+        assert_eq!(us.money(-1_234.5), "-$1,234.50");
+        assert_eq!(us.money_with(4.6, 2), "$4.60");
+        assert_eq!(us.count(35_000.0), "35,000");
+        assert_eq!(us.count(100.0), "100");
+        // Zero, and negatives that round to it, carry no sign
+        assert_eq!(us.money(0.0), "$0.00");
+        assert_eq!(us.money(-0.004), "$0.00");
+        assert_eq!(us.whole_money(-0.2), "$0");
+        // Rounding can carry into a new group
+        assert_eq!(us.money(999.995), "$1,000.00");
+        assert_eq!(us.whole_money(999_999.5), "$1,000,000");
+        assert_eq!(us.whole_money(-999_999.5), "-$1,000,000");
+
+        let eu = Formatter::european();
+        assert_eq!(eu.money(10_234_567.891), "10 234 567,89 €");
+        assert_eq!(eu.money(-999.4), "-999,40 €");
+        assert_eq!(eu.count(35_000.0), "35 000");
+    }
+
+    #[test]
+    fn test_compact_amounts() {
+        let us = Formatter { compact: true, ..Formatter::default() };
+        assert_eq!(us.compact_money(10_234_567.89), "$10.2M");
+        assert_eq!(us.compact_money(-850_000.0), "-$850.0k");
+        assert_eq!(us.compact_money(2_500_000_000.0), "$2.5B");
+        assert_eq!(us.compact_money(999.0), "$999");
+        assert_eq!(us.compact_money(0.0), "$0");
+        // Rounding steps up a unit rather than writing 1,000.0k
+        assert_eq!(us.compact_money(999_960.0), "$1.0M");
+        assert_eq!(us.compact_money(999_000.0), "$999.0k");
+        assert_eq!(us.compact_money(999.6), "$1.0k");
+        assert_eq!(us.table_money(1_500.0), "$1.5k");
+        assert_eq!(Formatter::default().table_money(1_500.0), "$1,500.00");
+        assert_eq!(Formatter::european().compact_money(-10_234_567.89), "-10,2M €");
+    }
+
+    /// Writer whose bytes stay readable after it is handed to a reporter
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::rc::Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_color_is_only_added_on_request() {
+        let run = fixture_run();
+        let mut stats: Vec<MonteCarloStats> = run.pairs.iter().map(|pair| pair.stats.clone()).collect();
+        // A season that can lose money, so the percentiles include losses
+        stats[1].percentiles[0].1 = -25_000.0;
+        let print = |color: ColorChoice| {
+            let buffer = SharedBuffer::default();
+            let reporter = Reporter::with_writer(OutputFormat::Text, Verbosity::Normal, Formatter::default(), color, Box::new(buffer.clone()));
+            reporter.display_all_results(stats.clone(), RankingMetric::MeanProfit);
+            reporter.display_best_result(&stats[0], None, None);
+            reporter.display_utilization(&two_product_scenario().1, &stats[0]);
+            let bytes = buffer.0.borrow().clone();
+            String::from_utf8(bytes).unwrap()
+        };
+
+        // A writer that is not a terminal gets plain text, as does one with color turned off
+        let plain = print(ColorChoice::Auto);
+        assert!(!plain.contains('\u{1b}'));
+        assert_eq!(plain, print(ColorChoice::Never));
+
+        let colored = print(ColorChoice::AlwaysAnsi);
+        assert!(colored.contains(&format!("{}1  Supplie", BEST)));
+        assert!(colored.contains(&format!("{}-$25", LOSS)));
+        assert_eq!(anstream::adapter::strip_str(&colored).to_string(), plain);
+    }
+
+    #[test]
+    fn test_screening_summary_lists_rejections_when_verbose() {
+        let rejected = vec![
+            RejectedPair {
+                base_supplier: "Far".to_string(),
+                surge_supplier: "Near".to_string(),
+                backup_supplier: None,
+                pooled_suppliers: Vec::new(),
+                single_source: false,
+                reason: RejectionReason::Screening(ScreeningFailure::EstimateBelowThreshold { estimate: -1_500.0, threshold: 0.0 }),
+            },
+            RejectedPair {
+                base_supplier: "Far".to_string(),
+                surge_supplier: "Tiny".to_string(),
+                backup_supplier: None,
+                pooled_suppliers: Vec::new(),
+                single_source: false,
+                reason: RejectionReason::Screening(ScreeningFailure::InadequateCapacity { capacity: 2_000, demand: 10_000.0, min_coverage: 0.7 }),
+            },
+            RejectedPair {
+                base_supplier: "Far".to_string(),
+                surge_supplier: "Dear".to_string(),
+                backup_supplier: None,
+                pooled_suppliers: Vec::new(),
+                single_source: false,
+                reason: RejectionReason::Screening(ScreeningFailure::SetupCostOverCap { setup_cost: 3_000_000.0, max: 2_500_000.0 }),
+            },
+        ];
+        let print = |verbosity: Verbosity| {
+            let buffer = SharedBuffer::default();
+            let reporter = Reporter::with_writer(OutputFormat::Text, verbosity, Formatter::default(), ColorChoice::Never, Box::new(buffer.clone()));
+            reporter.display_screening_summary(&rejected);
+            let bytes = buffer.0.borrow().clone();
+            String::from_utf8(bytes).unwrap()
+        };
+
+        assert_eq!(print(Verbosity::Normal), "");
+        let verbose = print(Verbosity::Verbose);
+        assert!(verbose.contains("Far + Near: quick estimate -$1,500.00 is below the $0.00 threshold"), "{}", verbose);
+        assert!(verbose.contains("Far + Tiny: capacity of 2,000 units covers less than 70% of the 10,000 expected"), "{}", verbose);
+        assert!(verbose.contains("Far + Dear: setup costs of $3,000,000.00 exceed the $2,500,000.00 cap"), "{}", verbose);
+    }
+
+    #[test]
+    fn test_tables_keep_rows_aligned() {
+        let products = ["Model A", "Model B", "A product with a long name", "Model D"];
+        let mut stats = fixture_run().pairs[0].stats.clone();
+        stats.base_supplier = "An overseas supplier with a remarkably long name".to_string();
+        stats.product_allocations = products.iter().enumerate()
+            .map(|(id, name)| ProductAllocation {
+                product_id: ProductId(id as u32),
+                product_name: name.to_string(),
+                base_quantity: 120_000,
+                surge_quantity: 7_500,
+            })
+            .collect();
+        stats.percentiles[0].1 = -25_000.0;
+        let results = vec![stats.clone(), MonteCarloStats { surge_supplier: "S".to_string(), ..stats }];
+
+        let formatter = Formatter::default();
+        for table in [
+            profit_table(&results, &formatter, 80),
+            allocation_table(&results, &formatter, 80),
+            cost_table(&results, &formatter, 80),
+        ] {
+            let width = display_width(&table[0]);
+            assert!(table.iter().all(|line| display_width(line) == width), "{:#?}", table);
+        }
+
+        // Long supplier names are cut so the ranking fits, with amounts abbreviated if need be
+        let ranking = profit_table(&results, &formatter, 80);
+        assert!(ranking.iter().all(|line| display_width(line) <= 80), "{:#?}", ranking);
+        assert!(ranking[2].contains('…'));
+        assert!(strip_styles(&ranking[2]).contains("-$25.0k"));
+        // Amounts are only abbreviated when they would not otherwise fit
+        assert!(profit_table(&results, &formatter, 200)[2].contains(&formatter.money(results[0].mean_profit)));
+
+        let [top, title, bottom] = banner("RESULTS", 30);
+        assert_eq!(title, "║          RESULTS           ║");
+        assert!([&top, &bottom].iter().all(|line| display_width(line) == 30));
+    }
+
+    #[test]
+    fn test_risk_assessment_block() {
+        let mut stats = fixture_run().pairs[0].stats.clone();
+        stats.mean_profit = 500_000.0;
+        stats.prob_loss = 0.125;
+        stats.var_5 = -20_000.0;
+        stats.cvar_5 = -45_000.0;
+        stats.downside_deviation = Some(80_000.0);
+        // Profit spread evenly from zero to a million
+        stats.ecdf_summary = vec![0.0, 1_000_000.0];
+        stats.worst_case_trace.as_mut().unwrap().sample.profit = -60_000.0;
+        stats.worst_month = Some(WorstMonth {
+            month: "March".to_string(),
+            profit: -90_000.0,
+            driver: LossDriver::Stockout,
+            driver_cost: 120_000.0,
+        });
+        let block = |stats: &MonteCarloStats, target| {
+            risk_rows(stats, target, &Formatter::default()).iter()
+                .map(|row| anstream::adapter::strip_str(row).to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(block(&stats, Some(750_000.0)), [
+            "  P(Loss):                  12.5%",
+            "  Value at Risk (5%):       -$20,000.00",
+            "  Expected Shortfall (5%):  -$45,000.00",
+            "  Downside Deviation:       $80,000.00",
+            "  P(Profit < 90% of Mean):  45.0%",
+            "  P(Profit >= $750,000.00): 25.0%",
+            "  Worst Season:             -$60,000.00",
+            "  Worst Month:              March, -$90,000.00 (driven by lost sales of $120,000.00)",
+        ]);
+
+        // Without the optional metrics only the headline ones remain
+        stats.downside_deviation = None;
+        stats.ecdf_summary.clear();
+        stats.worst_case_trace = None;
+        stats.worst_month = None;
+        assert_eq!(block(&stats, Some(750_000.0)), [
+            "  P(Loss):                  12.5%",
+            "  Value at Risk (5%):       -$20,000.00",
+            "  Expected Shortfall (5%):  -$45,000.00",
+        ]);
+    }
+
+    #[test]
+    fn test_boxplots_share_one_axis() {
+        let stats = |base: &str, surge: &str, [min, q1, median, q3, max]: [f64; 5]| MonteCarloStats {
+            base_supplier: base.to_string(),
+            surge_supplier: surge.to_string(),
+            min_profit: min,
+            max_profit: max,
+            percentiles: vec![(25.0, q1), (50.0, median), (75.0, q3)],
+            ..fixture_run().pairs[0].stats.clone()
+        };
+        let results = [stats("A", "B", [0.0, 25.0, 50.0, 75.0, 100.0]), stats("C", "D", [40.0, 60.0, 70.0, 80.0, 100.0])];
+
+        // 30 columns leave 21 for the plot, one per 5 dollars
+        assert_eq!(boxplot_rows(&results, 30, &Formatter::default()), [
+            "  A + B  ├────[▓▓▓▓│▓▓▓▓]────┤",
+            "  C + D          ├───[▓│▓]───┤",
+            "         └─────────┴─────────┘",
+            "         $0       $50     $100",
+        ]);
+
+        // Long names are cut short so every row still fits in 80 columns
+        let long = [stats("A supplier with a very long name", "another one", [0.0, 25.0, 50.0, 75.0, 100.0])];
+        let rows = boxplot_rows(&long, 80, &Formatter::default());
+        assert!(rows[0].starts_with("  A supplier with a very lo…  ├"), "{}", rows[0]);
+        assert!(rows.iter().all(|row| row.chars().count() <= 80));
+    }
+
+    /// Two pairs sharing a base supplier, simulated on a fixed seed
+    pub fn fixture_run() -> RunOutput {
+        let (params, pair) = two_product_scenario();
+        let mut close_pair = pair.clone();
+        close_pair.surge_supplier.id = SupplierId(2);
+        close_pair.surge_supplier.name = "Supplier 2".to_string();
+        close_pair.surge_supplier.fixed_capacity = 3_000;
+        close_pair.surge_supplier.setup_cost = 5_000.0;
+
+        let config = MonteCarloConfig { master_seed: 22, ..MonteCarloConfig::new(20) };
+        let pairs: Vec<PairRun> = [&pair, &close_pair].into_iter()
+            .map(|pair| {
+                let optimal_quantities = vec![(ProductId(0), 6_000), (ProductId(1), 3_000)];
+                let monthly_order = split_order_quantities(&optimal_quantities, pair, &params);
+                let stats = run_monte_carlo_simulation(&params, pair, &monthly_order, &config).unwrap();
+                PairRun { quick_estimate: 0.0, optimal_quantities, stats }
+            })
+            .collect();
+        let best_pair = (0..pairs.len()).max_by(|&a, &b| pairs[a].stats.mean_profit.partial_cmp(&pairs[b].stats.mean_profit).unwrap());
+        RunOutput {
+            schema_version: SCHEMA_VERSION,
+            params,
+            suppliers: vec![pair.base_supplier.clone(), pair.surge_supplier.clone(), close_pair.surge_supplier.clone()],
+            ranking: RankingMetric::MeanProfit,
+            pairs,
+            best_pair,
+            rejected_pairs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_markdown_report_matches_golden_file() {
+        let report = markdown_report(&fixture_run(), &Formatter::default());
+
+        // Regenerate with UPDATE_GOLDEN=1 after an intended change to the report
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/report.md");
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(&golden, &report).unwrap();
+        }
+        assert_eq!(report, std::fs::read_to_string(&golden).unwrap());
+    }
+
+    #[test]
+    fn test_forecast_bias_is_signed_and_noise_is_no_gain() {
+        let mut run = fixture_run();
+        let best = run.best_pair.unwrap();
+        run.pairs[best].stats.forecast_accuracy[0].bias = 250.0;
+        // Ahead on average but as often behind as ahead: noise, though the mean is positive
+        let hindsight = |differences: &[f64]| {
+            let profits: Vec<(f64, f64)> = differences.iter().map(|difference| (100_000.0 + difference, 100_000.0)).collect();
+            Some(paired_comparison("actual".to_string(), "forecast".to_string(), &profits))
+        };
+        run.pairs[best].stats.cost_of_forecast_error = hindsight(&[2_000.0, -1_800.0, 1_500.0, -1_600.0]);
+        assert!(run.pairs[best].stats.cost_of_forecast_error.as_ref().unwrap().mean_difference > 0.0);
+        let console = rendered(Verbosity::Normal, |reporter| reporter.display_best_result(&run.pairs[best].stats, None, None));
+        assert!(console.contains("+250") && console.contains("none beyond simulation noise"), "{}", console);
+        let markdown = markdown_report(&run, &Formatter::default());
+        assert!(markdown.contains("| +250 |") && markdown.contains("no more, beyond simulation noise"), "{}", markdown);
+
+        // Ahead on every path: a cost
+        run.pairs[best].stats.cost_of_forecast_error = hindsight(&[1_200.0, 1_300.0, 1_250.0, 1_250.0]);
+        let console = rendered(Verbosity::Normal, |reporter| reporter.display_best_result(&run.pairs[best].stats, None, None));
+        assert!(console.contains("Cost of forecast error: $1,250.00 [95% CI: $1,"), "{}", console);
+        let markdown = markdown_report(&run, &Formatter::default());
+        assert!(markdown.contains("would have made $1,250 more on the same demand (95% CI $1,"), "{}", markdown);
+
+        // A forecast of the actual demand costs nothing and says nothing
+        run.pairs[best].stats.cost_of_forecast_error = None;
+        let console = rendered(Verbosity::Normal, |reporter| reporter.display_best_result(&run.pairs[best].stats, None, None));
+        assert!(!console.contains("Cost of forecast error"), "{}", console);
+    }
+
+    /// What `report` prints through a plain-text reporter with the default formatting, which
+    /// never depends on the machine's locale
+    fn rendered(verbosity: Verbosity, report: impl FnOnce(&Reporter)) -> String {
+        let buffer = SharedBuffer::default();
+        let reporter = Reporter::with_writer(OutputFormat::Text, verbosity, Formatter::default(), ColorChoice::Never, Box::new(buffer.clone()));
+        report(&reporter);
+        let bytes = buffer.0.borrow().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    // Review a changed snapshot with `cargo insta review`, or accept it with INSTA_UPDATE=always
+
+    #[test]
+    fn snapshot_all_results() {
+        let run = fixture_run();
+        let stats = run.pairs.iter().map(|pair| pair.stats.clone()).collect();
+        insta::assert_snapshot!(rendered(Verbosity::Normal, |reporter| reporter.display_all_results(stats, run.ranking)));
+    }
+
+    #[test]
+    fn snapshot_best_result() {
+        let run = fixture_run();
+        let best = &run.pairs[run.best_pair.unwrap()].stats;
+        insta::assert_snapshot!(rendered(Verbosity::Normal, |reporter| reporter.display_best_result(best, Some(50_000.0), None)));
+    }
+
+    #[test]
+    fn test_best_result_states_what_the_surge_supplier_adds() {
+        let run = fixture_run();
+        let best = &run.pairs[run.best_pair.unwrap()].stats;
+        let profits = [(1_100.0, 1_000.0), (1_300.0, 1_000.0), (900.0, 1_000.0)];
+        let value = DualSourceValue {
+            differences: profits.iter().map(|(with_surge, alone)| with_surge - alone).collect(),
+            comparison: crate::monte_carlo::paired_comparison("pair".to_string(), "base alone".to_string(), &profits),
+        };
+        let formatter = Formatter::default();
+        let line = format!("Surge supplier adds {} ± {} to expected profit", formatter.money(100.0), formatter.money(value.ci_half_width()));
+        assert!(rendered(Verbosity::Normal, |reporter| reporter.display_best_result(best, None, Some(&value))).contains(&line));
+        assert!(!rendered(Verbosity::Normal, |reporter| reporter.display_best_result(best, None, None)).contains("Surge supplier adds"));
+    }
+
+    #[test]
+    fn snapshot_monthly_plan() {
+        let run = fixture_run();
+        let (_, pair) = two_product_scenario();
+        let order = split_order_quantities(&run.pairs[0].optimal_quantities, &pair, &run.params);
+        let projection = project_mean_demand(&run.params, &pair, &order).unwrap();
+        insta::assert_snapshot!(rendered(Verbosity::Normal, |reporter| reporter.display_monthly_plan(&run.params, &pair, &order, &projection)));
+    }
+
+    #[test]
+    fn snapshot_screening_summary() {
+        let rejected = |surge: &str, reason: ScreeningFailure| RejectedPair {
+            base_supplier: "Far".to_string(),
+            surge_supplier: surge.to_string(),
+            backup_supplier: None,
+            pooled_suppliers: Vec::new(),
+            single_source: false,
+            reason: RejectionReason::Screening(reason),
+        };
+        let rejected = vec![
+            rejected("Near", ScreeningFailure::EstimateBelowThreshold { estimate: -1_500.0, threshold: 0.0 }),
+            rejected("Tiny", ScreeningFailure::InadequateCapacity { capacity: 2_000, demand: 10_000.0, min_coverage: 0.7 }),
+            rejected("Dear", ScreeningFailure::SetupCostOverCap { setup_cost: 3_000_000.0, max: 2_500_000.0 }),
+        ];
+        insta::assert_snapshot!(rendered(Verbosity::Verbose, |reporter| reporter.display_screening_summary(&rejected)));
+    }
+
+    #[test]
+    fn snapshot_markdown_report() {
+        let mut report = Vec::new();
+        write_markdown(&mut report, &fixture_run(), &Formatter::default()).unwrap();
+        insta::assert_snapshot!(String::from_utf8(report).unwrap());
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn test_parquet_export_reads_back() {
+        use arrow_array::{Float64Array, StringArray, UInt32Array, UInt64Array};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+        use crate::monte_carlo::SampleRetention;
+
+        let mut run = fixture_run();
+        // Keep every season of the second pair only
+        let (params, pair) = two_product_scenario();
+        let config = MonteCarloConfig { master_seed: 5, sample_retention: SampleRetention::All, ..MonteCarloConfig::new(8) };
+        let monthly_order = split_order_quantities(&run.pairs[1].optimal_quantities, &pair, &params);
+        run.pairs[1].stats.samples = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap().samples;
+
+        let dir = std::env::temp_dir().join(format!("supply-chain-sim-parquet-{}", std::process::id()));
+        export_parquet(&dir, &run).unwrap();
+        let read = |name: &str| {
+            let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(dir.join(name)).unwrap()).unwrap();
+            let version = builder.metadata().file_metadata().key_value_metadata().unwrap().iter()
+                .find(|entry| entry.key == "schema_version")
+                .and_then(|entry| entry.value.clone());
+            assert_eq!(version.as_deref(), Some(PARQUET_SCHEMA_VERSION));
+            let batches: Vec<_> = builder.build().unwrap().map(Result::unwrap).collect();
+            assert_eq!(batches.len(), 1);
+            batches.into_iter().next().unwrap()
+        };
+
+        let pairs = read("pairs.parquet");
+        assert_eq!(pairs.num_rows(), 2);
+        let column = |name: &str| pairs.column_by_name(name).unwrap().clone();
+        let surge = column("surge_supplier");
+        let surge = surge.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!((surge.value(0), surge.value(1)), ("Supplier 1", "Supplier 2"));
+        let mean = column("mean_profit");
+        let mean = mean.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(mean.value(1), run.pairs[1].stats.mean_profit);
+
+        let replications = read("replications.parquet");
+        assert_eq!(replications.num_rows(), 8);
+        let samples = &run.pairs[1].stats.samples;
+        let pair_ids = replications.column(0).as_any().downcast_ref::<UInt32Array>().unwrap();
+        let seeds = replications.column_by_name("seed").unwrap().clone();
+        let seeds = seeds.as_any().downcast_ref::<UInt64Array>().unwrap();
+        let profits = replications.column_by_name("profit").unwrap().clone();
+        let profits = profits.as_any().downcast_ref::<Float64Array>().unwrap();
+        for row in [0, 3, 7] {
+            assert_eq!(pair_ids.value(row), 1);
+            assert_eq!(seeds.value(row), samples[row].seed);
+            assert_eq!(profits.value(row), samples[row].profit);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "charts")]
+    #[test]
+    fn test_html_report_embeds_every_chart() {
+        let run = fixture_run();
+        let path = std::env::temp_dir().join(format!("supply-chain-sim-report-{}.html", std::process::id()));
+        write_html_report(&path, &run, &Formatter::default()).unwrap();
+        let html = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // A box chart, the best plan's profit and inventory lines, and a histogram per pair
+        assert_eq!(html.matches("<svg").count(), 3 + run.pairs.len());
+        assert!(html.contains("<rect") && html.contains("<polyline"));
+        assert!(html.contains("Supplier 0 + Supplier 2"));
+        assert!(html.contains("$1.5"), "axis labels in millions");
+        assert!(html.len() > 20_000, "{} bytes", html.len());
+    }
+
+    #[cfg(feature = "templates")]
+    #[test]
+    fn test_builtin_templates_render_the_run() {
+        let run = fixture_run();
+        let formatter = Formatter::default();
+        let best = &run.pairs[run.best_pair.unwrap()].stats;
+        let mean_profit = formatter.whole_money(best.mean_profit);
+
+        let executive = render_builtin_template("executive", &run, &formatter).unwrap();
+        assert!(executive.contains(&format!("order from {}, with {} for surge orders", best.base_supplier, best.surge_supplier)), "{}", executive);
+        assert!(executive.contains(&format!("**{}**", mean_profit)));
+        assert!(executive.contains("| 2 | "), "both pairs listed as alternatives");
+
+        let technical = render_builtin_template("technical", &run, &formatter).unwrap();
+        assert!(technical.contains("| Widget | "), "{}", technical);
+        assert!(technical.contains(&format!("| 1 | {} + {} | {} |", best.base_supplier, best.surge_supplier, mean_profit)));
+        assert!(technical.contains("| Total | "));
+        assert_eq!(technical.matches("\n## 1. ").count() + technical.matches("\n## 2. ").count(), 2);
+
+        assert!(render_builtin_template("quarterly", &run, &formatter).is_err());
+    }
+
+    #[cfg(feature = "templates")]
+    #[test]
+    fn test_custom_template_sees_raw_and_formatted_figures() {
+        let run = fixture_run();
+        let path = std::env::temp_dir().join(format!("supply-chain-sim-template-{}.txt", std::process::id()));
+        std::fs::write(&path, "{% for pair in results %}{{ pair.rank }} {{ pair.mean_profit }} {{ pair.raw.num_simulations }}\n{% endfor %}").unwrap();
+        let rendered = render_template(&path, &run, &Formatter::european());
+        std::fs::remove_file(&path).unwrap();
+
+        let lines: Vec<String> = rendered.unwrap().lines().map(String::from).collect();
+        assert_eq!(lines.len(), run.pairs.len());
+        assert!(lines[0].starts_with("1 ") && lines[0].ends_with(" € 20"), "{:?}", lines);
+        assert!(render_template(Path::new("no-such-template.j2"), &run, &Formatter::default()).is_err());
+    }
+
+    #[cfg(feature = "xlsx")]
+    #[test]
+    fn test_xlsx_export_reads_back() {
+        use calamine::{open_workbook, Data, Reader, Xlsx};
+
+        let run = fixture_run();
+        let path = std::env::temp_dir().join(format!("supply-chain-sim-{}.xlsx", std::process::id()));
+        export_xlsx(&path, &run, &Formatter::default()).unwrap();
+        let mut workbook: Xlsx<_> = open_workbook(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(workbook.sheet_names(), ["Summary", "Best Plan", "Risk", "Scenario"]);
+
+        // Ranked best first, amounts kept as numbers
+        let best = &run.pairs[run.best_pair.unwrap()].stats;
+        let summary = workbook.worksheet_range("Summary").unwrap();
+        assert_eq!(summary.get_value((0, 3)), Some(&Data::String("Mean profit".to_string())));
+        assert_eq!(summary.get_value((1, 2)), Some(&Data::String(best.surge_supplier.clone())));
+        assert_eq!(summary.get_value((1, 3)), Some(&Data::Float(best.mean_profit)));
+        assert_eq!(summary.get_value((2, 0)), Some(&Data::Float(2.0)));
+
+        // A row per product and month of the plan, each product's profit accumulating
+        let plan = workbook.worksheet_range("Best Plan").unwrap();
+        let months = run.params.season_months as u32;
+        assert_eq!(plan.get_value((1, 0)), Some(&Data::String("Widget".to_string())));
+        assert_eq!(plan.get_value((1 + months, 0)), Some(&Data::String("Gadget".to_string())));
+        let base = best.product_allocations[0].base_quantity as f64;
+        assert_eq!(plan.get_value((1, 2)), Some(&Data::Float(base)));
+
+        let risk = workbook.worksheet_range("Risk").unwrap();
+        assert_eq!(risk.get_value((1, 1)), Some(&Data::Float(best.mean_profit)));
+        let scenario = workbook.worksheet_range("Scenario").unwrap();
+        assert_eq!(scenario.get_value((1, 1)), Some(&Data::Float(run.params.products[0].selling_price)));
+    }
 }
@@ -0,0 +1,152 @@
+//! A scenario as an application embedding the simulator hands it over, as JSON: the season and
+//! the suppliers to choose among, evaluated end to end on a budget the caller sets
+//!
+//! A scenario is validated as it is read, so one that parses but cannot be simulated is refused
+//! up front with the reason rather than failing, or panicking, part way through a run
+
+use serde::Deserialize;
+use crate::error::{ScenarioError, SimulationError};
+use crate::evaluation::{evaluate_pairs, EvaluationConfig, PairEvaluation};
+use crate::models::{PairRun, RankingMetric, RunOutput, SimulationParams, Supplier, SupplierPair};
+use crate::monte_carlo::MonteCarloConfig;
+use crate::pairing::generate_intelligent_pairs;
+use crate::progress::ProgressSink;
+use crate::schema::SCHEMA_VERSION;
+use crate::validation::validate_scenario;
+
+/// The season and the suppliers to pair up for it
+#[derive(Clone, Debug, Deserialize)]
+pub struct Scenario {
+    pub params: SimulationParams,
+    pub suppliers: Vec<Supplier>,
+}
+
+/// How much work an evaluation does, and on which seed
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Budget {
+    /// Replications per pair
+    #[serde(default = "default_simulations")]
+    pub simulations: usize,
+    /// Master seed of every pair's replications, so a run can be replayed
+    pub seed: u64,
+    /// Most supplier pairs evaluated, in the order pairing generates them; None evaluates all
+    #[serde(default)]
+    pub max_pairs: Option<usize>,
+    /// Evaluate pairs in parallel; without the `parallel` feature they always run in turn
+    #[serde(default = "default_parallel")]
+    pub parallel: bool,
+}
+
+fn default_simulations() -> usize {
+    200
+}
+
+fn default_parallel() -> bool {
+    true
+}
+
+impl Scenario {
+    /// Read a scenario from JSON and validate it
+    pub fn from_json(json: &str) -> Result<Self, ScenarioError> {
+        let scenario: Scenario = serde_json::from_str(json)?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        validate_scenario(&self.params, &self.suppliers)
+    }
+
+    /// The supplier pairs `evaluate` runs within `budget`, in order
+    pub fn pairs(&self, budget: &Budget) -> Vec<SupplierPair> {
+        let mut pairs = generate_intelligent_pairs(&self.suppliers, false);
+        pairs.truncate(budget.max_pairs.unwrap_or(usize::MAX));
+        pairs
+    }
+
+    /// Pair the suppliers, then optimize and simulate each pair within `budget`, pairs ranked
+    /// by mean profit as the binary's run is by default; the scenario is validated first, as one
+    /// built in code has not been
+    pub fn evaluate(&self, budget: &Budget, progress: &dyn ProgressSink) -> Result<RunOutput, SimulationError> {
+        self.validate()?;
+        let pairs = self.pairs(budget);
+        let pairs: Vec<&SupplierPair> = pairs.iter().collect();
+        // Replications run in turn within a pair, as the pairs already share the thread pool
+        let config = EvaluationConfig {
+            monte_carlo: MonteCarloConfig { master_seed: budget.seed, parallel: false, ..MonteCarloConfig::new(budget.simulations) },
+            convergence: None,
+        };
+        let evaluations = evaluate_pairs(&self.params, &pairs, &config, budget.parallel, progress)?;
+
+        let ranking = RankingMetric::MeanProfit;
+        let pairs: Vec<PairRun> = evaluations.iter().map(PairEvaluation::to_run).collect();
+        let best_pair = (0..pairs.len()).max_by(|&a, &b| ranking.score(&pairs[a].stats).total_cmp(&ranking.score(&pairs[b].stats)));
+        Ok(RunOutput {
+            schema_version: SCHEMA_VERSION,
+            params: self.params.clone(),
+            suppliers: self.suppliers.clone(),
+            ranking,
+            pairs,
+            best_pair,
+            rejected_pairs: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NoProgress;
+
+    #[test]
+    fn test_budget_bounds_the_run() {
+        let scenario = Scenario::from_json(include_str!("../tests/fixtures/one_product_scenario.json")).unwrap();
+        let budget: Budget = serde_json::from_str(r#"{"simulations": 30, "seed": 4, "max_pairs": 1}"#).unwrap();
+        let run = scenario.evaluate(&budget, &NoProgress).unwrap();
+        assert_eq!(run.pairs.len(), 1);
+        assert_eq!(run.pairs[0].stats.num_simulations, 30);
+        assert_eq!(run.best_pair, Some(0));
+
+        // Pairs in parallel or in turn, the seed fixes the run
+        let serial = Budget { parallel: false, ..budget };
+        assert_eq!(scenario.evaluate(&serial, &NoProgress).unwrap().pairs[0].stats, run.pairs[0].stats);
+    }
+
+    #[test]
+    fn test_forecast_error_is_measured_and_priced() {
+        // Model A is forecast at 35,000 units a month but sells 32,000
+        let json = include_str!("../tests/fixtures/one_product_scenario.json");
+        let budget: Budget = serde_json::from_str(r#"{"simulations": 40, "seed": 5, "max_pairs": 1, "parallel": false}"#).unwrap();
+        let run = Scenario::from_json(json).unwrap().evaluate(&budget, &NoProgress).unwrap();
+        let stats = &run.pairs[0].stats;
+        let [accuracy] = &stats.forecast_accuracy[..] else { panic!("one product") };
+        assert_eq!(accuracy.forecast_mean_demand, 35_000.0);
+        assert!((accuracy.bias + 3_000.0).abs() < 1_500.0, "bias {}", accuracy.bias);
+        assert!(accuracy.mape > 0.1 && accuracy.mape < 0.5, "MAPE {}", accuracy.mape);
+        // Priced on the paths the pair was evaluated on, the chosen plan's mean profit among them
+        let cost = stats.cost_of_forecast_error.as_ref().expect("priced with the pair");
+        assert_eq!(cost.num_simulations, stats.num_simulations);
+        assert_eq!(cost.mean_profit_second, stats.mean_profit);
+
+        // Forecasting half as much again as sells costs more than simulation noise
+        let high = json.replace("\"mean_demand\": 35000.0", "\"mean_demand\": 48000.0");
+        let run = Scenario::from_json(&high).unwrap().evaluate(&budget, &NoProgress).unwrap();
+        let cost = run.pairs[0].stats.cost_of_forecast_error.as_ref().expect("priced with the pair");
+        assert!(cost.difference_ci_low > 0.0, "forecast error cost {:?}", cost);
+
+        // Forecasting the actual demand gives up nothing
+        let exact = json.replace("\"mean_demand\": 35000.0", "\"mean_demand\": 32000.0");
+        let run = Scenario::from_json(&exact).unwrap().evaluate(&budget, &NoProgress).unwrap();
+        let stats = &run.pairs[0].stats;
+        assert!(stats.forecast_accuracy[0].bias.abs() < 1_500.0, "bias {}", stats.forecast_accuracy[0].bias);
+        assert_eq!(stats.cost_of_forecast_error, None);
+    }
+
+    #[test]
+    fn test_scenarios_are_validated_as_read() {
+        let json = include_str!("../tests/fixtures/one_product_scenario.json").replace("\"season_months\": 8", "\"season_months\": 4000");
+        let err = Scenario::from_json(&json).unwrap_err();
+        assert!(matches!(err, ScenarioError::SeasonLength { months: 4000, .. }), "{}", err);
+        assert!(matches!(Scenario::from_json("{\"params\": {}}"), Err(ScenarioError::Json(_))));
+    }
+}
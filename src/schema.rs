@@ -0,0 +1,358 @@
+//! Layouts a run's JSON has been archived in, and their migration to the current result model
+//!
+//! A run written by this simulator carries `schema_version`. The layouts before it carried
+//! none: version 1 is told from version 2 by the ranking metric version 2 added. Each older
+//! layout is read into its own structs and migrated a version at a time, so a run archived in
+//! any of them loads as a current `RunOutput`; a newer version is refused rather than misread.
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde_json::Value;
+#[cfg(feature = "serde")]
+use crate::error::SchemaError;
+#[cfg(feature = "serde")]
+use crate::models::{MonteCarloStats, PairRun, ProductAllocation, ProductId, RankingMetric, RunOutput, SimulationParams, Supplier};
+#[cfg(feature = "serde")]
+use crate::monte_carlo::{ci_half_width, standard_error};
+#[cfg(feature = "serde")]
+use crate::streaming::{grid_cdf, interpolated_quantile};
+
+/// Version of the layout `RunOutput` is written in; raise it, and add a migration from the
+/// layout it replaces, whenever a field is renamed, removed or given a new meaning
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Read an archived run of any known layout as the current result model
+#[cfg(feature = "serde")]
+pub fn load_run(json: &str) -> Result<RunOutput, SchemaError> {
+    run_from_value(serde_json::from_str(json)?)
+}
+
+/// `load_run` for a run already parsed as JSON
+#[cfg(feature = "serde")]
+pub fn run_from_value(value: Value) -> Result<RunOutput, SchemaError> {
+    let version = match value.get("schema_version") {
+        Some(version) => u64::deserialize(version)?,
+        None if value.get("ranking").is_some() => 2,
+        None => 1,
+    };
+    match version {
+        1 => Ok(RunOutputV1::deserialize(value)?.migrate()),
+        2 => Ok(RunOutputV2::deserialize(value)?.migrate()),
+        3 => Ok(RunOutput::deserialize(value)?),
+        found => Err(SchemaError::UnsupportedVersion { found, supported: SCHEMA_VERSION }),
+    }
+}
+
+/// Version 1: pairs ranked by mean profit alone, each pair's allocation of a product given in
+/// base and surge units without its name
+#[cfg(feature = "serde")]
+#[derive(Debug, Deserialize)]
+pub struct RunOutputV1 {
+    pub params: SimulationParams,
+    pub suppliers: Vec<Supplier>,
+    pub pairs: Vec<PairRunV1>,
+    pub best_pair: Option<usize>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Deserialize)]
+pub struct PairRunV1 {
+    pub quick_estimate: f64,
+    pub optimal_quantities: Vec<(ProductId, u32)>,
+    pub stats: MonteCarloStatsV1,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Deserialize)]
+pub struct MonteCarloStatsV1 {
+    pub base_supplier: String,
+    pub base_supplier_lead_time: usize,
+    pub surge_supplier: String,
+    pub surge_supplier_lead_time: usize,
+    pub allocations: Vec<AllocationV1>,
+    pub total_capacity_used: u32,
+    pub num_simulations: usize,
+    pub mean_profit: f64,
+    pub std_dev_profit: f64,
+    pub min_profit: f64,
+    pub max_profit: f64,
+    pub percentile_10: f64,
+    pub percentile_25: f64,
+    pub percentile_50: f64,
+    pub percentile_75: f64,
+    pub percentile_90: f64,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Deserialize)]
+pub struct AllocationV1 {
+    pub product_id: ProductId,
+    pub base_units: u32,
+    pub surge_units: u32,
+}
+
+#[cfg(feature = "serde")]
+impl RunOutputV1 {
+    pub fn migrate(self) -> RunOutput {
+        self.upgrade().migrate()
+    }
+
+    /// The run in version 2's layout: ranked by mean profit, which was all version 1 ranked
+    /// by, and each allocation named after its product in the run's parameters
+    fn upgrade(self) -> RunOutputV2 {
+        let product_name = |product_id: ProductId| self.params.get_product(product_id).map(|product| product.name.clone()).unwrap_or_default();
+        let pairs = self.pairs.into_iter()
+            .map(|pair| {
+                let stats = pair.stats;
+                let product_allocations = stats.allocations.iter()
+                    .map(|allocation| ProductAllocation {
+                        product_id: allocation.product_id,
+                        product_name: product_name(allocation.product_id),
+                        base_quantity: allocation.base_units,
+                        surge_quantity: allocation.surge_units,
+                    })
+                    .collect();
+                PairRunV2 {
+                    quick_estimate: pair.quick_estimate,
+                    optimal_quantities: pair.optimal_quantities,
+                    stats: MonteCarloStatsV2 {
+                        base_supplier: stats.base_supplier,
+                        base_supplier_lead_time: stats.base_supplier_lead_time,
+                        surge_supplier: stats.surge_supplier,
+                        surge_supplier_lead_time: stats.surge_supplier_lead_time,
+                        product_allocations,
+                        total_capacity_used: stats.total_capacity_used,
+                        num_simulations: stats.num_simulations,
+                        mean_profit: stats.mean_profit,
+                        std_dev_profit: stats.std_dev_profit,
+                        min_profit: stats.min_profit,
+                        max_profit: stats.max_profit,
+                        percentile_10: stats.percentile_10,
+                        percentile_25: stats.percentile_25,
+                        percentile_50: stats.percentile_50,
+                        percentile_75: stats.percentile_75,
+                        percentile_90: stats.percentile_90,
+                    },
+                }
+            })
+            .collect();
+        RunOutputV2 {
+            params: self.params,
+            suppliers: self.suppliers,
+            ranking: RankingMetric::MeanProfit,
+            pairs,
+            best_pair: self.best_pair,
+        }
+    }
+}
+
+/// Version 2: named product allocations and a ranking metric, but five fixed profit
+/// percentiles and no risk measures, screened-out pairs or breakdowns
+#[cfg(feature = "serde")]
+#[derive(Debug, Deserialize)]
+pub struct RunOutputV2 {
+    pub params: SimulationParams,
+    pub suppliers: Vec<Supplier>,
+    pub ranking: RankingMetric,
+    pub pairs: Vec<PairRunV2>,
+    pub best_pair: Option<usize>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Deserialize)]
+pub struct PairRunV2 {
+    pub quick_estimate: f64,
+    pub optimal_quantities: Vec<(ProductId, u32)>,
+    pub stats: MonteCarloStatsV2,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Deserialize)]
+pub struct MonteCarloStatsV2 {
+    pub base_supplier: String,
+    pub base_supplier_lead_time: usize,
+    pub surge_supplier: String,
+    pub surge_supplier_lead_time: usize,
+    pub product_allocations: Vec<ProductAllocation>,
+    pub total_capacity_used: u32,
+    pub num_simulations: usize,
+    pub mean_profit: f64,
+    pub std_dev_profit: f64,
+    pub min_profit: f64,
+    pub max_profit: f64,
+    pub percentile_10: f64,
+    pub percentile_25: f64,
+    pub percentile_50: f64,
+    pub percentile_75: f64,
+    pub percentile_90: f64,
+}
+
+/// Spacing of the ECDF summary rebuilt for a version 2 run, the widest grid holding the 5% tail
+/// and each of the five percentiles it kept
+#[cfg(feature = "serde")]
+const MIGRATED_ECDF_STEPS: usize = 20;
+
+/// Confidence level of the interval on the mean rebuilt for a version 2 run, the default a run
+/// was made at then
+#[cfg(feature = "serde")]
+const MIGRATED_CONFIDENCE_LEVEL: f64 = 0.95;
+
+#[cfg(feature = "serde")]
+impl RunOutputV2 {
+    pub fn migrate(self) -> RunOutput {
+        RunOutput {
+            schema_version: SCHEMA_VERSION,
+            params: self.params,
+            suppliers: self.suppliers,
+            ranking: self.ranking,
+            pairs: self.pairs.into_iter()
+                .map(|pair| PairRun { quick_estimate: pair.quick_estimate, optimal_quantities: pair.optimal_quantities, stats: pair.stats.migrate() })
+                .collect(),
+            best_pair: self.best_pair,
+            rejected_pairs: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl MonteCarloStatsV2 {
+    /// Current statistics of a version 2 run
+    /// The profit distribution is taken to run linearly between the extremes and the five
+    /// percentiles kept, which gives the ECDF summary, value at risk, expected shortfall and
+    /// chance of a loss; the interval on the mean is rebuilt from the standard deviation. What
+    /// the layout did not record, such as fees, breakdowns and traces, is left empty or zero,
+    /// and a supplier paired with itself could then only be a single source
+    fn migrate(self) -> MonteCarloStats {
+        let knots = [
+            (0.0, self.min_profit),
+            (0.10, self.percentile_10),
+            (0.25, self.percentile_25),
+            (0.50, self.percentile_50),
+            (0.75, self.percentile_75),
+            (0.90, self.percentile_90),
+            (1.0, self.max_profit),
+        ];
+        let ecdf_summary: Vec<f64> = (0..=MIGRATED_ECDF_STEPS)
+            .map(|step| {
+                let p = step as f64 / MIGRATED_ECDF_STEPS as f64;
+                let upper = knots.iter().position(|&(level, _)| level >= p).unwrap_or(knots.len() - 1).max(1);
+                let ((p0, q0), (p1, q1)) = (knots[upper - 1], knots[upper]);
+                q0 + (p - p0) / (p1 - p0) * (q1 - q0)
+            })
+            .collect();
+        let var_5 = interpolated_quantile(&ecdf_summary, 0.05);
+        let ci_half_width = ci_half_width(self.std_dev_profit, self.num_simulations, MIGRATED_CONFIDENCE_LEVEL);
+        let mean_std_error = standard_error(self.std_dev_profit, self.num_simulations);
+        MonteCarloStats {
+            single_source: self.base_supplier == self.surge_supplier,
+            backup_supplier: None,
+            pooled_suppliers: Vec::new(),
+            converged: true,
+            mean_std_error,
+            adjusted_mean_profit: self.mean_profit,
+            adjusted_std_error: mean_std_error,
+            percentiles: knots[1..6].iter().map(|&(level, profit)| (level * 100.0, profit)).collect(),
+            var_5,
+            cvar_5: (ecdf_summary[0] + var_5) / 2.0,
+            prob_loss: grid_cdf(&ecdf_summary, 0.0),
+            downside_deviation: None,
+            ecdf_summary,
+            histogram: Vec::new(),
+            confidence_level: MIGRATED_CONFIDENCE_LEVEL,
+            mean_ci_low: self.mean_profit - ci_half_width,
+            mean_ci_high: self.mean_profit + ci_half_width,
+            product_exercise_counts: Vec::new(),
+            exercise_counts: Vec::new(),
+            exercise_probability: 0.0,
+            mean_exercises: 0.0,
+            first_exercise_counts: Vec::new(),
+            mean_fees_paid: 0.0,
+            expansion_acceptance: Vec::new(),
+            mean_setup_cost: 0.0,
+            mean_operational_profit: self.mean_profit,
+            revenue_per_unit: 0.0,
+            landed_cost_per_unit: 0.0,
+            landed_margin: 0.0,
+            monthly_option_summary: Vec::new(),
+            monthly_breakdown: Vec::new(),
+            product_breakdown: Vec::new(),
+            product_pnl: Vec::new(),
+            mean_base_utilization: 0.0,
+            mean_surge_utilization: 0.0,
+            mean_backup_utilization: 0.0,
+            capacity_usage: Vec::new(),
+            samples: Vec::new(),
+            worst_case_trace: None,
+            best_case_trace: None,
+            sampled_traces: Vec::new(),
+            worst_month: None,
+            forecast_accuracy: Vec::new(),
+            cost_of_forecast_error: None,
+            base_supplier: self.base_supplier,
+            base_supplier_lead_time: self.base_supplier_lead_time,
+            surge_supplier: self.surge_supplier,
+            surge_supplier_lead_time: self.surge_supplier_lead_time,
+            product_allocations: self.product_allocations,
+            total_capacity_used: self.total_capacity_used,
+            num_simulations: self.num_simulations,
+            mean_profit: self.mean_profit,
+            std_dev_profit: self.std_dev_profit,
+            min_profit: self.min_profit,
+            max_profit: self.max_profit,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use crate::reporting::tests::fixture_run;
+
+    const V1: &str = include_str!("../tests/fixtures/archived_run_v1.json");
+    const V2: &str = include_str!("../tests/fixtures/archived_run_v2.json");
+
+    #[test]
+    fn test_archived_layouts_migrate_to_the_same_run() {
+        let from_v1 = load_run(V1).unwrap();
+        let from_v2 = load_run(V2).unwrap();
+        assert_eq!(serde_json::to_value(&from_v1).unwrap(), serde_json::to_value(&from_v2).unwrap());
+
+        assert_eq!(from_v1.schema_version, SCHEMA_VERSION);
+        assert_eq!(from_v1.ranking, RankingMetric::MeanProfit);
+        assert_eq!(from_v1.best_pair, Some(0));
+        assert!(from_v1.rejected_pairs.is_empty());
+        let stats = &from_v1.pairs[0].stats;
+        assert_eq!(stats.product_allocations, vec![ProductAllocation {
+            product_id: ProductId(0),
+            product_name: "Model A".to_string(),
+            base_quantity: 30_000,
+            surge_quantity: 4_000,
+        }]);
+        assert_eq!(stats.percentiles, vec![(10.0, 1_000_000.0), (25.0, 1_600_000.0), (50.0, 2_000_000.0), (75.0, 2_400_000.0), (90.0, 2_800_000.0)]);
+        assert_eq!(stats.p50(), Some(2_000_000.0));
+        // Halfway from the minimum to the 10th percentile, and the tail below it averaged
+        assert_eq!(stats.var_5, -250_000.0);
+        assert_eq!(stats.cvar_5, -875_000.0);
+        // Zero lies three fifths of the way from the minimum to the 10th percentile
+        assert!((stats.prob_loss - 0.06).abs() < 1e-12, "{}", stats.prob_loss);
+        assert!((stats.mean_ci_half_width() - 1.96 * 700_000.0 / 99f64.sqrt()).abs() < 10.0, "{}", stats.mean_ci_half_width());
+        assert!(!stats.single_source && from_v1.pairs[1].stats.single_source);
+    }
+
+    #[test]
+    fn test_current_runs_load_unchanged() {
+        let run = fixture_run();
+        let json = serde_json::to_string(&run).unwrap();
+        assert_eq!(serde_json::to_value(load_run(&json).unwrap()).unwrap(), serde_json::to_value(&run).unwrap());
+    }
+
+    #[test]
+    fn test_newer_versions_are_refused() {
+        let mut run = serde_json::to_value(fixture_run()).unwrap();
+        run["schema_version"] = (SCHEMA_VERSION + 1).into();
+        let newer = run_from_value(run);
+        assert!(matches!(newer, Err(SchemaError::UnsupportedVersion { found, supported: SCHEMA_VERSION }) if found == SCHEMA_VERSION as u64 + 1));
+        assert!(matches!(load_run("{\"schema_version\": \"3\"}"), Err(SchemaError::Json(_))));
+    }
+}
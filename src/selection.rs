@@ -0,0 +1,262 @@
+//! Ranking and selection across supplier pairs: rather than the same number of replications for
+//! every pair, a shared budget goes where it helps tell the best pair from the rest
+//! Every pair first runs an initial batch; each round then spreads another batch over the pairs
+//! by optimal computing budget allocation (OCBA), which favours the leader and the pairs whose
+//! means are close to it relative to their noise, until the probability of correct selection
+//! reaches the confidence asked for or the budget runs out
+
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tracing::debug;
+use crate::error::SimulationError;
+use crate::models::{MonteCarloStats, MonthlyOrder, SimulationParams, SupplierPair};
+use crate::monte_carlo::{IncrementalRun, MonteCarloConfig};
+use crate::stats::standard_normal_cdf;
+
+/// How much to simulate and when to stop
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelectionConfig {
+    /// Replications every pair runs before any are allocated
+    pub initial_replications: usize,
+    /// Replications spread over the pairs each round
+    pub batch: usize,
+    /// Most replications across all pairs
+    pub budget: usize,
+    /// Probability of correct selection at which to stop
+    pub confidence: f64,
+}
+
+impl SelectionConfig {
+    /// 100 replications per pair to start with and rounds of 100, stopping at 95% confidence
+    pub fn new(budget: usize) -> Self {
+        SelectionConfig { initial_replications: 100, batch: 100, budget, confidence: 0.95 }
+    }
+}
+
+/// Whether every pair gets the same replications or a shared budget goes where it is needed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionMode {
+    Fixed,
+    Adaptive,
+}
+
+impl FromStr for SelectionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(SelectionMode::Fixed),
+            "adaptive" => Ok(SelectionMode::Adaptive),
+            _ => Err(format!("expected fixed or adaptive, got \"{}\"", s)),
+        }
+    }
+}
+
+/// One pair's statistics after selection
+#[derive(Clone, Debug)]
+pub struct SelectedRun {
+    pub stats: MonteCarloStats,
+    /// Time spent simulating the pair
+    pub simulating: Duration,
+}
+
+/// How much of the budget selection spent, and how sure it is of the best pair
+#[derive(Clone, Debug, PartialEq)]
+pub struct Selection {
+    /// Replications run across all pairs
+    pub replications: usize,
+    pub budget: usize,
+    /// Approximate probability that the pair with the highest mean profit is truly the best
+    pub probability_correct: f64,
+}
+
+/// Simulate each pair's plan until the one with the highest mean profit stands out from the
+/// rest at `config.confidence`, or until `config.budget` replications have run; the runs come
+/// back in the order given
+/// Replications draw on the master seed as in any run, so a pair's first n replications are
+/// those a fixed run of n would make
+/// Fails at the first replication that cannot be simulated or whose profit is NaN
+pub fn select_best(
+    params: &SimulationParams,
+    plans: &[(&SupplierPair, &MonthlyOrder)],
+    monte_carlo: &MonteCarloConfig,
+    config: &SelectionConfig,
+) -> Result<(Vec<SelectedRun>, Selection), SimulationError> {
+    let mut runs: Vec<IncrementalRun> = plans.iter().map(|_| IncrementalRun::new(params, monte_carlo, config.budget)).collect();
+    let mut simulating = vec![Duration::ZERO; plans.len()];
+    let mut extend = |runs: &mut [IncrementalRun], index: usize, count: usize| {
+        let (pair, monthly_order) = plans[index];
+        let started = Instant::now();
+        runs[index].extend(params, pair, monthly_order, count)?;
+        simulating[index] += started.elapsed();
+        Ok::<(), SimulationError>(())
+    };
+
+    let initial = config.initial_replications.max(2).min(config.budget / plans.len().max(1));
+    for index in 0..runs.len() {
+        extend(&mut runs, index, initial)?;
+    }
+    let mut replications: usize = runs.iter().map(IncrementalRun::completed).sum();
+    let mut probability_correct = probability_of_correct_selection(&runs);
+    while probability_correct < config.confidence && replications < config.budget {
+        let batch = config.batch.max(1).min(config.budget - replications);
+        for (index, count) in ocba_allocation(&runs, batch).into_iter().enumerate() {
+            if count > 0 {
+                extend(&mut runs, index, count)?;
+            }
+        }
+        replications = runs.iter().map(IncrementalRun::completed).sum();
+        probability_correct = probability_of_correct_selection(&runs);
+        debug!(replications, probability_correct, "selection round");
+    }
+
+    let selected = runs.into_iter().zip(plans).zip(simulating)
+        .map(|((run, (pair, monthly_order)), simulating)| Ok(SelectedRun { stats: run.finish(params, pair, monthly_order)?, simulating }))
+        .collect::<Result<_, SimulationError>>()?;
+    Ok((selected, Selection { replications, budget: config.budget, probability_correct }))
+}
+
+/// Index of the run with the highest mean profit
+fn leader(runs: &[IncrementalRun]) -> usize {
+    (0..runs.len()).max_by(|&a, &b| runs[a].mean().total_cmp(&runs[b].mean())).unwrap_or(0)
+}
+
+/// Probability that the leader's mean is truly the highest, taking the runs as independent
+/// normal estimates: the product over the others of the chance each lies below the leader,
+/// which understates it when the comparisons are positively correlated, as they are here
+fn probability_of_correct_selection(runs: &[IncrementalRun]) -> f64 {
+    let best = leader(runs);
+    runs.iter().enumerate()
+        .filter(|(index, _)| *index != best)
+        .map(|(_, run)| {
+            let gap = runs[best].mean() - run.mean();
+            let spread = runs[best].std_error().hypot(run.std_error());
+            if spread > 0.0 {
+                standard_normal_cdf(gap / spread)
+            } else if gap > 0.0 {
+                1.0
+            } else {
+                0.5
+            }
+        })
+        .product()
+}
+
+/// Replications to add to each run out of `batch`, moving the counts towards the OCBA
+/// allocation: run i other than the leader b gets a share proportional to (σᵢ / δᵢ)², with δᵢ
+/// its gap to the leader, and the leader σ_b √(Σ Nᵢ² / σᵢ²)
+fn ocba_allocation(runs: &[IncrementalRun], batch: usize) -> Vec<usize> {
+    let best = leader(runs);
+    // Per-replication spread, recovered from the standard error
+    let spread = |run: &IncrementalRun| run.std_error() * (run.completed() as f64).sqrt();
+    // A gap too small to measure counts as a sliver of the leader's spread, so exact ties do not
+    // claim the whole batch
+    let smallest_gap = 1e-6 * spread(&runs[best]).max(1.0);
+    let mut weights: Vec<f64> = runs.iter().enumerate()
+        .map(|(index, run)| {
+            if index == best {
+                return 0.0;
+            }
+            let gap = (runs[best].mean() - run.mean()).max(smallest_gap);
+            (spread(run) / gap).powi(2)
+        })
+        .collect();
+    weights[best] = spread(&runs[best]) * runs.iter().zip(&weights)
+        .filter(|(run, _)| spread(run) > 0.0)
+        .map(|(run, weight)| (weight / spread(run)).powi(2))
+        .sum::<f64>()
+        .sqrt();
+
+    // Targets for the counts after this round, and how far each run falls short of its own
+    let total_weight: f64 = weights.iter().sum();
+    let after: usize = runs.iter().map(IncrementalRun::completed).sum::<usize>() + batch;
+    let shortfalls: Vec<f64> = runs.iter().zip(&weights)
+        .map(|(run, weight)| {
+            let target = if total_weight > 0.0 { after as f64 * weight / total_weight } else { after as f64 / runs.len() as f64 };
+            (target - run.completed() as f64).max(0.0)
+        })
+        .collect();
+    let total_shortfall: f64 = shortfalls.iter().sum();
+    if total_shortfall <= 0.0 {
+        // Every run at its target already: the leader's estimate matters most
+        let mut allocation = vec![0; runs.len()];
+        allocation[best] = batch;
+        return allocation;
+    }
+
+    let mut allocation: Vec<usize> = shortfalls.iter().map(|shortfall| (batch as f64 * shortfall / total_shortfall) as usize).collect();
+    // Rounding leftovers go to the runs furthest short
+    let mut by_shortfall: Vec<usize> = (0..runs.len()).collect();
+    by_shortfall.sort_by(|&a, &b| shortfalls[b].total_cmp(&shortfalls[a]));
+    let leftover = batch - allocation.iter().sum::<usize>();
+    for index in by_shortfall.into_iter().cycle().take(leftover) {
+        allocation[index] += 1;
+    }
+    allocation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::monte_carlo::tests::two_product_scenario;
+    use crate::simulation::split_order_quantities;
+    use crate::models::ProductId;
+
+    /// The fixture's plan on pairs with the given base supplier unit cost
+    fn plans_with_base_costs(base_costs: &[f64]) -> (SimulationParams, Vec<(SupplierPair, MonthlyOrder)>) {
+        let (mut params, pair) = two_product_scenario();
+        params.order_change_fees = Vec::new();
+        let plans = base_costs.iter()
+            .map(|&unit_cost| {
+                let mut pair = pair.clone();
+                pair.base_supplier.unit_costs = HashMap::from([(ProductId(0), unit_cost), (ProductId(1), unit_cost)]);
+                let order = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+                (pair, order)
+            })
+            .collect();
+        (params, plans)
+    }
+
+    fn select(base_costs: &[f64], budget: usize) -> (Vec<MonteCarloStats>, Selection) {
+        let (params, plans) = plans_with_base_costs(base_costs);
+        let plans: Vec<(&SupplierPair, &MonthlyOrder)> = plans.iter().map(|(pair, order)| (pair, order)).collect();
+        let monte_carlo = MonteCarloConfig { master_seed: 33, ..MonteCarloConfig::new(0) };
+        let (selected, selection) = select_best(&params, &plans, &monte_carlo, &SelectionConfig::new(budget)).unwrap();
+        (selected.into_iter().map(|run| run.stats).collect(), selection)
+    }
+
+    #[test]
+    fn test_a_dominant_pair_is_found_on_a_fraction_of_the_budget() {
+        // The second and third pairs pay 10 and 15 more per base unit than the first
+        let (stats, selection) = select(&[75.0, 85.0, 90.0], 1_500);
+        assert!(selection.probability_correct >= 0.95, "{}", selection.probability_correct);
+        assert!(selection.replications < 1_500 / 3, "{} replications", selection.replications);
+        let means: Vec<f64> = stats.iter().map(|stats| stats.mean_profit).collect();
+        assert!(means[0] > means[1] && means[1] > means[2]);
+        assert_eq!(selection.replications, stats.iter().map(|stats| stats.num_simulations).sum::<usize>());
+    }
+
+    #[test]
+    fn test_a_near_tie_gets_the_budget() {
+        // Two pairs a cent apart and one far behind
+        let (stats, selection) = select(&[75.0, 75.01, 90.0], 1_500);
+        assert_eq!(selection.replications, 1_500);
+        assert!(selection.probability_correct < 0.95);
+        let counts: Vec<usize> = stats.iter().map(|stats| stats.num_simulations).collect();
+        assert!(counts[2] < 200 && counts[0] + counts[1] > 1_300, "{:?}", counts);
+    }
+
+    #[test]
+    fn test_the_first_replications_match_a_fixed_run() {
+        use crate::monte_carlo::run_monte_carlo_simulation;
+
+        let (params, plans) = plans_with_base_costs(&[75.0]);
+        let (pair, order) = &plans[0];
+        let monte_carlo = MonteCarloConfig { master_seed: 33, ..MonteCarloConfig::new(100) };
+        let (selected, selection) = select_best(&params, &[(pair, order)], &monte_carlo, &SelectionConfig::new(1_000)).unwrap();
+        // A lone pair is the best at once
+        assert_eq!((selection.replications, selection.probability_correct), (100, 1.0));
+        assert_eq!(selected[0].stats.mean_profit, run_monte_carlo_simulation(&params, pair, order, &monte_carlo).unwrap().mean_profit);
+    }
+}
@@ -0,0 +1,164 @@
+//! Evaluation over HTTP, for planning tools that submit scenarios and poll for their results:
+//! - `POST /runs` takes a scenario and its budget as `evaluate_scenario` does and answers with
+//!   the run's id
+//! - `GET /runs/{id}` gives the run's status and percent complete, with the results once done
+//! - `GET /runs/{id}/results.csv` gives the results as `--csv` writes them
+//!
+//! Runs execute on tokio's blocking pool and report progress to their own entry in the run
+//! table, so concurrent runs neither hold up the server nor share any output
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::json;
+use tokio::net::TcpListener;
+use crate::models::{MonteCarloStats, RunOutput, SupplierPair};
+use crate::progress::ProgressSink;
+use crate::reporting::write_results_csv;
+use crate::scenario::{Budget, Scenario};
+
+/// How far a run has got, from what the pipeline reports
+#[derive(Default)]
+struct RunProgress {
+    /// Pairs the run evaluates
+    pairs: usize,
+    /// Pairs started, heard of when pairs run in turn
+    started: AtomicUsize,
+    /// Replications of the current pair, as (done, total), when pairs run in turn
+    replications: Mutex<(usize, usize)>,
+    /// Pairs finished, heard of when pairs run in parallel
+    finished: AtomicUsize,
+}
+
+impl RunProgress {
+    fn percent_complete(&self) -> f64 {
+        if self.pairs == 0 {
+            return 0.0;
+        }
+        let (done, total) = *self.replications.lock().unwrap();
+        let current = if total == 0 { 0.0 } else { done as f64 / total as f64 };
+        let in_turn = self.started.load(Ordering::Relaxed).saturating_sub(1) as f64 + current;
+        let in_parallel = self.finished.load(Ordering::Relaxed) as f64;
+        100.0 * in_turn.max(in_parallel).min(self.pairs as f64) / self.pairs as f64
+    }
+}
+
+impl ProgressSink for RunProgress {
+    fn on_pair_start(&self, _pair: &SupplierPair) {
+        self.started.fetch_add(1, Ordering::Relaxed);
+        *self.replications.lock().unwrap() = (0, 0);
+    }
+
+    fn on_replication(&self, done: usize, total: usize) {
+        let mut replications = self.replications.lock().unwrap();
+        // Parallel replications may report slightly out of order
+        if done > replications.0 || total != replications.1 {
+            *replications = (done, total);
+        }
+    }
+
+    fn on_pair_finished(&self, done: usize, _total: usize) {
+        self.finished.fetch_max(done, Ordering::Relaxed);
+    }
+}
+
+enum RunState {
+    Running(Arc<RunProgress>),
+    Complete(Arc<RunOutput>),
+    Failed(String),
+}
+
+/// Every run submitted since the server started, by id
+#[derive(Clone, Default)]
+struct Runs {
+    last_id: Arc<AtomicU64>,
+    table: Arc<Mutex<HashMap<u64, RunState>>>,
+}
+
+impl Runs {
+    fn set(&self, id: u64, state: RunState) {
+        self.table.lock().unwrap().insert(id, state);
+    }
+}
+
+/// The server's routes, over a run table of their own
+pub fn router() -> Router {
+    Router::new()
+        .route("/runs", post(submit))
+        .route("/runs/{id}", get(status))
+        .route("/runs/{id}/results.csv", get(results_csv))
+        .with_state(Runs::default())
+}
+
+/// Serve on `listener` until the process ends
+pub async fn serve_on(listener: TcpListener) -> io::Result<()> {
+    axum::serve(listener, router()).await
+}
+
+/// Serve on `addr` until the process ends, from a tokio runtime of its own
+pub fn serve(addr: SocketAddr) -> io::Result<()> {
+    tokio::runtime::Runtime::new()?.block_on(async { serve_on(TcpListener::bind(addr).await?).await })
+}
+
+fn error(status: StatusCode, message: String) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+async fn submit(State(runs): State<Runs>, body: String) -> Response {
+    let parsed = Scenario::from_json(&body).and_then(|scenario| Ok((scenario, serde_json::from_str::<Budget>(&body)?)));
+    let (scenario, budget) = match parsed {
+        Ok(parsed) => parsed,
+        Err(err) => return error(StatusCode::BAD_REQUEST, format!("invalid scenario: {}", err)),
+    };
+    let progress = Arc::new(RunProgress { pairs: scenario.pairs(&budget).len(), ..RunProgress::default() });
+    let id = runs.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+    runs.set(id, RunState::Running(progress.clone()));
+
+    let run = tokio::task::spawn_blocking(move || scenario.evaluate(&budget, &*progress));
+    let finished = runs.clone();
+    tokio::spawn(async move {
+        let state = match run.await {
+            Ok(Ok(run)) => RunState::Complete(Arc::new(run)),
+            Ok(Err(err)) => RunState::Failed(err.to_string()),
+            // A panic ends the run rather than the server
+            Err(err) => RunState::Failed(format!("internal error: {}", err)),
+        };
+        finished.set(id, state);
+    });
+    (StatusCode::ACCEPTED, Json(json!({ "id": id }))).into_response()
+}
+
+async fn status(State(runs): State<Runs>, Path(id): Path<u64>) -> Response {
+    let table = runs.table.lock().unwrap();
+    let body = match table.get(&id) {
+        None => return error(StatusCode::NOT_FOUND, format!("no run {}", id)),
+        Some(RunState::Running(progress)) => json!({ "id": id, "status": "running", "percent_complete": progress.percent_complete() }),
+        Some(RunState::Complete(run)) => json!({ "id": id, "status": "complete", "percent_complete": 100.0, "results": &**run }),
+        Some(RunState::Failed(message)) => json!({ "id": id, "status": "failed", "error": message }),
+    };
+    Json(body).into_response()
+}
+
+async fn results_csv(State(runs): State<Runs>, Path(id): Path<u64>) -> Response {
+    let run = match runs.table.lock().unwrap().get(&id) {
+        None => return error(StatusCode::NOT_FOUND, format!("no run {}", id)),
+        Some(RunState::Running(_)) => return error(StatusCode::CONFLICT, format!("run {} is still running", id)),
+        Some(RunState::Failed(message)) => return error(StatusCode::CONFLICT, format!("run {} failed: {}", id, message)),
+        Some(RunState::Complete(run)) => run.clone(),
+    };
+    // Best first, as the binary writes them
+    let mut results: Vec<MonteCarloStats> = run.pairs.iter().map(|pair| pair.stats.clone()).collect();
+    results.sort_by(|a, b| run.ranking.score(b).total_cmp(&run.ranking.score(a)));
+    let mut csv = Vec::new();
+    match write_results_csv(&mut csv, &results) {
+        Ok(()) => ([(header::CONTENT_TYPE, "text/csv")], csv).into_response(),
+        Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+    }
+}
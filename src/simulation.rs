@@ -1,61 +1,517 @@
-/// Monthly simulation logic for inventory management and ordering decisions
-/// Supports multiple products with shared supplier capacity
+//! One season of a plan, month by month: deliveries within capacity, sales against demand,
+//! order changes through the options, and the season's profit
 
+use std::cell::RefCell;
 use std::cmp;
 use std::collections::HashMap;
-use crate::models::{MonthlyOrder, MonthlyResult, ProductMonthlyResult, ProductOrder, SimulationParams, SupplierPair};
-use crate::demand::simulation_demand;
-use crate::options::OptionValuation;
+use tracing::debug;
+use crate::capacity::{allocate_by_priority, fit_capacity_groups, largest_remainder, plan_first_tier_overtime, scale_to_capacity, unit_margins, CapacityError, CapacityLedger, CapacityUsage};
+use crate::error::SimulationError;
+use crate::models::{CapacityGroup, CapacityPolicy, ExerciseDirection, ExpansionOffer, GoodwillModel, MonthlyOrder, MonthlyResult, OptionState, Product, ProductDemandParams, ProductId, ProductMonthlyResult, ProductOrder, RegionalDisruption, Reservation, SimulationParams, Supplier, SupplierId, SupplierPair, TimeStep};
+use crate::demand::{clear_demand_script, script_mean_demand, with_demand_stream};
+pub use crate::demand::DemandSource;
+use crate::options::{OptionValuation, OptionsConfig};
 use crate::optimizer::find_optimal_production_quantities;
 
-const MONTHS: &[&str] = &[
+const CALENDAR: [&str; 12] = [
     "May", "June", "July", "August", "September", "October", "November", "December",
+    "January", "February", "March", "April",
 ];
-const TOTAL_MONTHS: usize = 8;
 
-/// Run monthly simulation for May through December (8 months)
+/// Standard deviations of demand above the expected level at which deliveries are flexed down
+const FLEX_DOWN_STD_DEVS: f64 = 1.0;
+
+/// Order change waiting on the surge lead time: (effective_month, new_order, changed products, fee)
+type PendingChange = (usize, MonthlyOrder, Vec<(ProductId, ExerciseDirection)>, f64);
+
+/// Seed of the demand stream used when re-planning orders in season
+const REPLAN_SEED: u64 = 0x2E71A4;
+
+/// In-season re-plan with the parameters and pair it was made for
+type Replan = (SimulationParams, SupplierPair, Vec<(ProductId, u32)>);
+
+thread_local! {
+    /// Last in-season re-plan made on this thread
+    static LAST_REPLAN: RefCell<Option<Replan>> = const { RefCell::new(None) };
+}
+
+/// Quantities an upward order change re-plans towards: the optimal plan at the forecast, found
+/// on a demand stream of its own so the re-plan neither consumes nor depends on the season's draws
+/// The re-plan depends on nothing else, and every replication of a run asks for the same one, so
+/// the thread keeps the last it made rather than searching again
+fn replan_quantities(params: &SimulationParams, pair: &SupplierPair) -> Result<Vec<(ProductId, u32)>, SimulationError> {
+    let cached = LAST_REPLAN.with(|last| {
+        last.borrow().as_ref()
+            .filter(|(last_params, last_pair, _)| last_params == params && last_pair == pair)
+            .map(|(_, _, allocations)| allocations.clone())
+    });
+    if let Some(allocations) = cached {
+        return Ok(allocations);
+    }
+    let allocations = with_demand_stream(REPLAN_SEED, || find_optimal_production_quantities(params, pair))?;
+    LAST_REPLAN.with(|last| *last.borrow_mut() = Some((params.clone(), pair.clone(), allocations.clone())));
+    Ok(allocations)
+}
+
+/// Weeks ahead a weekly season's option lattice looks, its tree growing fourfold with each
+const WEEKS_VALUED: usize = 8;
+
+/// Units the capacity ledger committed of one month's deliveries under an order
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Commitment {
+    /// Each committed tier member's units, in tier order
+    committed: Vec<u32>,
+    surge: u32,
+    backup: u32,
+}
+
+impl Commitment {
+    /// Units committed of each supplier, by supplier id
+    fn units(&self, pair: &SupplierPair) -> Vec<(SupplierId, u32)> {
+        pair.committed_tier().map(|supplier| supplier.id).zip(self.committed.iter().copied())
+            .chain([(pair.surge_supplier.id, self.surge)])
+            .chain(pair.backup_supplier.as_ref().map(|backup| (backup.id, self.backup)))
+            .collect()
+    }
+
+    fn release(&self, ledger: &mut CapacityLedger, pair: &SupplierPair, month: usize) {
+        for (supplier_id, units) in self.units(pair) {
+            ledger.release(supplier_id, month, units);
+        }
+    }
+
+    /// Commit again units just released, which are sure to fit
+    fn restore(&self, ledger: &mut CapacityLedger, pair: &SupplierPair, month: usize) {
+        for (supplier_id, units) in self.units(pair) {
+            ledger.try_reserve(supplier_id, month, units).expect("units just released fit again");
+        }
+    }
+}
+
+/// Commit a month of `order`'s deliveries in the ledger: base orders across the committed tier
+/// in tier order, within nominal capacity before any member works overtime, and surge orders to
+/// the surge supplier and then the backup; the ledger records what none of them could take
+fn commit_order(ledger: &mut CapacityLedger, pair: &SupplierPair, order: &MonthlyOrder, month: usize) -> Commitment {
+    let mut base_left = order.total_base_quantity();
+    let mut committed: Vec<u32> = pair.committed_tier()
+        .map(|member| {
+            let nominal_left = member.fixed_capacity.saturating_sub(ledger.committed(member.id, month));
+            let units = ledger.take_available(member.id, month, base_left.min(nominal_left));
+            base_left -= units;
+            units
+        })
+        .collect();
+    // Then overtime, the last member taking whatever is left so any shortfall is recorded there
+    let last_member = pair.pooled_suppliers.len();
+    for (index, member) in pair.committed_tier().enumerate() {
+        let units = if index == last_member {
+            ledger.release(member.id, month, committed[index]);
+            base_left += committed[index];
+            committed[index] = 0;
+            ledger.reserve_up_to(member.id, month, base_left)
+        } else {
+            ledger.take_available(member.id, month, base_left)
+        };
+        committed[index] += units;
+        base_left -= units;
+    }
+
+    // A single source has no surge capacity of its own, and the backup's nominal capacity comes
+    // before the surge supplier's overtime
+    let surge_units = order.total_surge_quantity();
+    let surge_id = pair.surge_supplier.id;
+    let (surge, backup) = match (&pair.backup_supplier, pair.is_single_source()) {
+        (None, true) => (0, 0),
+        (Some(backup), true) => (0, ledger.reserve_up_to(backup.id, month, surge_units)),
+        (Some(backup), false) => {
+            let nominal_left = pair.surge_supplier.fixed_capacity.saturating_sub(ledger.committed(surge_id, month));
+            let mut surge = ledger.take_available(surge_id, month, surge_units.min(nominal_left));
+            let mut backup_units = ledger.take_available(backup.id, month, surge_units - surge);
+            surge += ledger.take_available(surge_id, month, surge_units - surge - backup_units);
+            backup_units += ledger.reserve_up_to(backup.id, month, surge_units - surge - backup_units);
+            (surge, backup_units)
+        }
+        (None, false) => (ledger.reserve_up_to(surge_id, month, surge_units), 0),
+    };
+    Commitment { committed, surge, backup }
+}
+
+/// Start the ramp-up of every supplier `order` asks units of, from `first_month` on: the
+/// committed tier for base orders, and the surge supplier and the backup behind it for surge
+/// orders; orders `placed_in` a month of the season were placed then, and the plan's before the
+/// season by each supplier's lead time
+fn start_ramps(ledger: &mut CapacityLedger, pair: &SupplierPair, order: &MonthlyOrder, first_month: usize, placed_in: Option<usize>) {
+    let base = pair.committed_tier().filter(|_| order.total_base_quantity() > 0);
+    let surge = pair.surge_tier().filter(|_| order.total_surge_quantity() > 0);
+    for supplier in base.chain(surge) {
+        let months_ordered = placed_in.map_or(supplier.lead_time_months, |month| first_month - month);
+        ledger.ramp_up(supplier, first_month, months_ordered);
+    }
+}
+
+/// A ledger of the pair's capacity over `months` months, held to the order's reservations and
+/// its suppliers' ramp-up, with every month of the order committed in it
+fn commit_plan(pair: &SupplierPair, order: &MonthlyOrder, months: usize) -> (CapacityLedger, Vec<Commitment>) {
+    let mut ledger = CapacityLedger::for_pair(pair, months);
+    for reservation in &order.reservations {
+        ledger.limit_to(reservation);
+    }
+    start_ramps(&mut ledger, pair, order, 0, None);
+    let commitments = (0..months).map(|month| commit_order(&mut ledger, pair, order, month)).collect();
+    (ledger, commitments)
+}
+
+/// Check that every month of a plan's deliveries fits what its suppliers have, within any
+/// reservations it makes; the error names the first supplier and month it does not fit
+pub fn check_capacity(params: &SimulationParams, pair: &SupplierPair, order: &MonthlyOrder) -> Result<(), CapacityError> {
+    let (ledger, _) = commit_plan(pair, order, params.season_months);
+    ledger.shortfalls().first().map_or(Ok(()), |shortfall| Err(shortfall.to_error(pair)))
+}
+
+/// Commit every month from `first_month` on to `order` in place of what `commitments` hold
+fn recommit_from(ledger: &mut CapacityLedger, pair: &SupplierPair, commitments: &mut [Commitment], order: &MonthlyOrder, first_month: usize) {
+    for (month, commitment) in commitments.iter_mut().enumerate().skip(first_month) {
+        commitment.release(ledger, pair, month);
+        *commitment = commit_order(ledger, pair, order, month);
+    }
+}
+
+/// Surge units the ledger could take in every month from `first_month` on were `order` to
+/// replace what `commitments` hold, recording any shortfall; the ledger is left as it was
+fn surge_capacity_from(
+    ledger: &mut CapacityLedger,
+    pair: &SupplierPair,
+    commitments: &[Commitment],
+    order: &MonthlyOrder,
+    first_month: usize,
+) -> u32 {
+    (first_month..commitments.len())
+        .map(|month| {
+            commitments[month].release(ledger, pair, month);
+            let probe = commit_order(ledger, pair, order, month);
+            probe.release(ledger, pair, month);
+            commitments[month].restore(ledger, pair, month);
+            probe.surge + probe.backup
+        })
+        .min()
+        .unwrap_or(0)
+}
+
+/// Units of each request that suppliers with the given capacity left deliver, supplier by
+/// supplier, each taking in turn what those before it could not; products share a supplier short
+/// of capacity, or of room in one of its capacity groups, by their unit margin at it
+/// `delivering` holds units of each product a supplier already delivers this month in another
+/// role, as (supplier_id, units by product), which take their room in its groups
+fn fill_in_turn<'a>(
+    params: &SimulationParams,
+    requests: &[(ProductId, u32)],
+    suppliers: impl IntoIterator<Item = (&'a Supplier, u32)>,
+    delivering: &[(SupplierId, Vec<(ProductId, u32)>)],
+) -> Vec<Vec<u32>> {
+    let mut unfilled: Vec<(ProductId, u32)> = requests.to_vec();
+    suppliers.into_iter()
+        .map(|(supplier, capacity_left)| {
+            let margins = unit_margins(params, supplier);
+            let placed = delivering.iter().find(|(supplier_id, _)| *supplier_id == supplier.id).map_or(&[][..], |(_, units)| units);
+            let grantable = fit_capacity_groups(&unfilled, placed, std::iter::once(supplier), &margins);
+            let wanted: u32 = grantable.iter().map(|(_, units)| units).sum();
+            let units: Vec<u32> = if wanted <= capacity_left {
+                grantable.iter().map(|(_, units)| *units).collect()
+            } else {
+                allocate_by_priority(&grantable, capacity_left, &margins).into_iter().map(|order| order.quantity).collect()
+            };
+            for ((_, left), taken) in unfilled.iter_mut().zip(&units) {
+                *left -= taken;
+            }
+            units
+        })
+        .collect()
+}
+
+/// Name of a month of the season, counting from May
+pub fn month_name(month_idx: usize) -> &'static str {
+    CALENDAR[month_idx % CALENDAR.len()]
+}
+
+/// Name of a period of the season: its month, or its week counting from 1
+pub fn period_name(time_step: TimeStep, period: usize) -> String {
+    match time_step {
+        TimeStep::Monthly => month_name(period).to_string(),
+        TimeStep::Weekly => format!("Week {}", period + 1),
+    }
+}
+
+/// Month of the season with the given name, in any case, the first if the season runs longer
+/// than a year
+pub fn month_index(name: &str) -> Option<usize> {
+    CALENDAR.iter().position(|month| month.eq_ignore_ascii_case(name))
+}
+
+/// Run monthly simulation over the configured season, starting in May
+/// Under the strict capacity policy a plan beyond its suppliers' capacity is refused, and a product
+/// whose demand distribution cannot be sampled fails the season
+#[allow(dead_code)]
 pub fn run_monthly_simulation(
     params: &SimulationParams,
     pair: &SupplierPair,
     initial_order: &MonthlyOrder,
-) -> (Vec<MonthlyResult>, f64) {
+) -> Result<(Vec<MonthlyResult>, f64), SimulationError> {
     // Top-level simulation for final evaluation always uses actual demand
-    run_monthly_simulation_internal(params, pair, initial_order, true, true)
+    run_monthly_simulation_internal(params, pair, initial_order, true, true, DemandSource::Stochastic(None))
 }
 
-/// Internal monthly simulation with optional options valuation
+/// Internal monthly simulation with optional options valuation, on demand from `demand`
+/// Scripted demand is the same whichever of the forecast and the actuals `use_actual_demand`
+/// picks, and a script shorter than the season is refused
 pub fn run_monthly_simulation_internal(
     params: &SimulationParams,
     pair: &SupplierPair,
     initial_order: &MonthlyOrder,
     enable_options: bool,
     use_actual_demand: bool,
-) -> (Vec<MonthlyResult>, f64) {
+    demand: DemandSource,
+) -> Result<(Vec<MonthlyResult>, f64), SimulationError> {
+    let (monthly_results, total_profit, _) = run_monthly_simulation_with_ledger(params, pair, initial_order, enable_options, use_actual_demand, demand)?;
+    Ok((monthly_results, total_profit))
+}
+
+/// Run the season as `run_monthly_simulation` does, also returning how much of each supplier's
+/// capacity it used
+pub fn run_monthly_simulation_with_usage(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    initial_order: &MonthlyOrder,
+) -> Result<(Vec<MonthlyResult>, f64, Vec<CapacityUsage>), SimulationError> {
+    let (monthly_results, total_profit, ledger) = run_monthly_simulation_with_ledger(params, pair, initial_order, true, true, DemandSource::Stochastic(None))?;
+    Ok((monthly_results, total_profit, ledger.usage()))
+}
+
+/// Monthly simulation with optional options valuation, also returning the capacity ledger as the
+/// season left it: what the orders committed of each supplier month by month, and every
+/// shortfall
+/// Orders commit their months of capacity when placed or changed, so no supplier is ever
+/// committed beyond its capacity; under the strict capacity policy a plan that does not fit is
+/// refused instead, with the first supplier and month it does not fit
+/// A weekly season is simulated on the scenario converted to weeks, a demand script giving each
+/// week's demand
+pub fn run_monthly_simulation_with_ledger(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    initial_order: &MonthlyOrder,
+    enable_options: bool,
+    use_actual_demand: bool,
+    demand: DemandSource,
+) -> Result<(Vec<MonthlyResult>, f64, CapacityLedger), SimulationError> {
+    match params.time_step {
+        TimeStep::Monthly => simulate_periods(params, pair, initial_order, enable_options, use_actual_demand, demand, TimeStep::Monthly),
+        TimeStep::Weekly => {
+            let (params, pair, initial_order) = in_weeks(params, pair, initial_order);
+            simulate_periods(&params, &pair, &initial_order, enable_options, use_actual_demand, demand, TimeStep::Weekly)
+        }
+    }
+}
+
+/// Unit cost of stock once `units_in` units costing `cost_in` altogether join `units_held` units
+/// held at `unit_cost` each: the weighted average of the two, or `unit_cost` with nothing held
+pub(crate) fn weighted_average_cost(units_held: u32, unit_cost: f64, units_in: u32, cost_in: f64) -> f64 {
+    let units = units_held + units_in;
+    if units == 0 {
+        return unit_cost;
+    }
+    (units_held as f64 * unit_cost + cost_in) / units as f64
+}
+
+/// Cost of financing `units` of stock worth `unit_cost` each for a month at `annual_rate`
+pub(crate) fn monthly_financing_cost_of(annual_rate: f64, units: u32, unit_cost: f64) -> f64 {
+    annual_rate / 12.0 * units as f64 * unit_cost
+}
+
+/// Demand parameters of one of `per_month` periods of a month, independent draws adding up to
+/// the month's mean and variance
+pub(crate) fn period_demand(demand_params: &ProductDemandParams, per_month: f64) -> ProductDemandParams {
+    ProductDemandParams {
+        product_id: demand_params.product_id,
+        mean_demand: demand_params.mean_demand / per_month,
+        std_dev_demand: demand_params.std_dev_demand / per_month.sqrt(),
+        actual_mean_demand: demand_params.actual_mean_demand / per_month,
+        actual_std_dev_demand: demand_params.actual_std_dev_demand / per_month.sqrt(),
+    }
+}
+
+/// The season, the pair and the plan converted to weeks: demand, holding and financing costs,
+/// goodwill penalties, capacity, orders and reservations a week, adding up to the months' over
+/// the season, and lead times, ramp-ups, disruptions and expansion offers counted in weeks. The
+/// converted season steps by the month, each of its months being a week, so simulating it
+/// converts nothing again
+fn in_weeks(params: &SimulationParams, pair: &SupplierPair, order: &MonthlyOrder) -> (SimulationParams, SupplierPair, MonthlyOrder) {
+    let months = params.season_months;
+    let per_month = TimeStep::Weekly.periods_per_month(months);
+    let weeks = TimeStep::Weekly.periods(months);
+    // Week a month starts in, and month a week falls in, past the season as well as within it
+    let first_week = |month: usize| (month as f64 * per_month).round() as usize;
+    let month_of = |week: usize| ((week as f64 + 0.5) / per_month).ceil() as usize - 1;
+    let weekly = |units: u32| (units as f64 / per_month).round() as u32;
+
+    let supplier = |supplier: &Supplier| Supplier {
+        fixed_capacity: weekly(supplier.fixed_capacity),
+        product_capacity: supplier.product_capacity.as_ref()
+            .map(|limits| limits.iter().map(|(&product_id, &units)| (product_id, weekly(units))).collect()),
+        capacity_groups: supplier.capacity_groups.iter()
+            .map(|group| CapacityGroup { limit: weekly(group.limit), ..group.clone() })
+            .collect(),
+        lead_time_months: first_week(supplier.lead_time_months),
+        ramp_up_months: (0..first_week(supplier.ramp_up_months.len())).map(|week| supplier.ramp_up_months[month_of(week)]).collect(),
+        disrupted_months: (0..weeks).filter(|&week| supplier.is_disrupted(month_of(week))).collect(),
+        ..supplier.clone()
+    };
+    let weekly_params = SimulationParams {
+        products: params.products.iter()
+            .map(|product| Product {
+                monthly_holding_cost: product.monthly_holding_cost / per_month,
+                // A week of stockouts costs its share of the month's penalty
+                goodwill_penalty: product.goodwill_penalty.as_ref().map(|goodwill| GoodwillModel {
+                    per_consecutive_month: (0..first_week(goodwill.per_consecutive_month.len()))
+                        .map(|week| goodwill.per_consecutive_month[month_of(week)] / per_month)
+                        .collect(),
+                }),
+                ..product.clone()
+            })
+            .collect(),
+        demand_params: params.demand_params.iter().map(|dp| period_demand(dp, per_month)).collect(),
+        season_months: weeks,
+        regional_disruptions: params.regional_disruptions.iter()
+            .map(|disruption| RegionalDisruption {
+                monthly_probability: 1.0 - (1.0 - disruption.monthly_probability).powf(1.0 / per_month),
+                ..disruption.clone()
+            })
+            .collect(),
+        // An offer is decided by the last week of its decision month
+        expansion_offers: params.expansion_offers.iter()
+            .map(|offer| ExpansionOffer {
+                decision_month: first_week(offer.decision_month + 1) - 1,
+                start_month: first_week(offer.start_month),
+                extra_capacity: weekly(offer.extra_capacity),
+                ..offer.clone()
+            })
+            .collect(),
+        // Financing charged each week at a week's share of the month's rate
+        cost_of_capital_annual: params.cost_of_capital_annual / per_month,
+        time_step: TimeStep::Monthly,
+        ..params.clone()
+    };
+    let weekly_pair = SupplierPair {
+        base_supplier: supplier(&pair.base_supplier),
+        surge_supplier: supplier(&pair.surge_supplier),
+        backup_supplier: pair.backup_supplier.as_ref().map(supplier),
+        pooled_suppliers: pair.pooled_suppliers.iter().map(supplier).collect(),
+    };
+    let weekly_orders = |orders: &[ProductOrder]| orders.iter()
+        .map(|order| ProductOrder { product_id: order.product_id, quantity: weekly(order.quantity) })
+        .collect();
+    let weekly_order = MonthlyOrder::new(weekly_orders(order.base_orders()), weekly_orders(order.surge_orders()))
+        .expect("the monthly order names each product once")
+        .with_reservations(order.reservations.iter()
+            .map(|reservation| Reservation {
+                per_month: (0..weeks).map(|week| weekly(reservation.units_in(month_of(week)))).collect(),
+                ..reservation.clone()
+            })
+            .collect());
+    (weekly_params, weekly_pair, weekly_order)
+}
+
+/// Simulate the season period by period, `time_step` naming the periods
+fn simulate_periods(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    initial_order: &MonthlyOrder,
+    enable_options: bool,
+    use_actual_demand: bool,
+    demand: DemandSource,
+    time_step: TimeStep,
+) -> Result<(Vec<MonthlyResult>, f64, CapacityLedger), SimulationError> {
+    demand.covers(params.season_months)?;
     // Track inventory per product
-    let mut inventories: HashMap<usize, u32> = HashMap::new();
+    let mut inventories: HashMap<ProductId, u32> = HashMap::new();
     for product in &params.products {
         inventories.insert(product.id, 0);
     }
+    for &(product_id, units) in &params.opening_inventory {
+        *inventories.entry(product_id).or_insert(0) += units;
+    }
+    // Weighted average unit cost of each product's stock, opening stock valued at the base
+    // supplier's unit cost
+    let mut unit_cost_basis: HashMap<ProductId, f64> = params.products.iter()
+        .map(|product| (product.id, pair.base_supplier.unit_costs.get(&product.id).copied().unwrap_or(0.0)))
+        .collect();
     
+    // Months in a row each product has stocked out, up to the month before
+    let mut stockout_streaks: HashMap<ProductId, usize> = HashMap::new();
+
+    // Realized demand per product, used to calibrate the option lattice
+    let mut demand_history: HashMap<ProductId, Vec<u32>> = HashMap::new();
+
     let mut total_profit: f64 = 0.0;
     let mut monthly_results: Vec<MonthlyResult> = Vec::new();
 
     // Track order changes with lead time
     let mut current_order = initial_order.clone();
-    let mut pending_order: Option<(usize, MonthlyOrder)> = None; // (effective_month, new_order)
-    let mut base_setup_cost_deducted = false;
-    let mut surge_setup_cost_deducted = false;
+    let mut pending_order: Option<PendingChange> = None;
+    // Deliveries raised into capacity an expansion offer added, from the offer's first month
+    let mut pending_raise: Option<(usize, MonthlyOrder)> = None;
+    let mut exercises_used = 0;
+    // Suppliers onboarded in an earlier season have paid their setup already
+    let onboarded = |supplier: &Supplier| params.onboarded_suppliers.contains(&supplier.id);
+    let mut base_setup_cost_deducted = onboarded(&pair.base_supplier);
+    // A supplier paired with itself pays its setup once, as the base supplier
+    let mut surge_setup_cost_deducted = pair.is_self_paired() || onboarded(&pair.surge_supplier);
+    let mut backup_setup_cost_deducted = pair.backup_supplier.as_ref().is_some_and(onboarded);
+    let mut pooled_setup_cost_deducted: Vec<bool> = pair.pooled_suppliers.iter().map(onboarded).collect();
 
-    for (month_idx, month_name) in MONTHS.iter().enumerate() {
+    let season_months = params.season_months;
+    // Suppliers deliver no more than the plan reserved of them, reservations paid up front
+    let (mut ledger, mut commitments) = commit_plan(pair, initial_order, season_months);
+    if params.capacity_policy == CapacityPolicy::Strict {
+        if let Some(shortfall) = ledger.shortfalls().first() {
+            return Err(shortfall.to_error(pair).into());
+        }
+    }
+    let reservation_cost: f64 = initial_order.reservations.iter().map(Reservation::cost).sum();
+    let options_config = |product_ids: &[ProductId]| OptionsConfig {
+        max_periods_valued: match time_step {
+            TimeStep::Monthly => usize::MAX,
+            TimeStep::Weekly => WEEKS_VALUED,
+        },
+        ..OptionsConfig::for_products(product_ids)
+    };
+    for month_idx in 0..season_months {
         let mut order_change_cost_this_month = 0.0;
         let mut setup_cost_this_month = 0.0;
+        let mut exercised_changes = Vec::new();
+        let mut option_value = None;
+        let mut flex_adjustments = Vec::new();
+        let mut flex_cost_this_month = 0.0;
 
         // Check if a pending order should take effect this month
-        if let Some((effective_month, new_order)) = &pending_order {
+        if let Some((effective_month, new_order, changes, fee)) = &pending_order {
             if month_idx >= *effective_month {
+                order_change_cost_this_month = fee + params.order_change_fee_per_product * changes.len() as f64;
+                for (product_id, direction) in changes {
+                    if *direction == ExerciseDirection::Decrease {
+                        // Cancellation fee covers every unit dropped from the remaining deliveries
+                        let current_qty = current_order.base_quantity_for(*product_id) + current_order.surge_quantity_for(*product_id);
+                        let new_qty = new_order.base_quantity_for(*product_id) + new_order.surge_quantity_for(*product_id);
+                        let cancelled_units = current_qty.saturating_sub(new_qty) as usize * (season_months - month_idx);
+                        order_change_cost_this_month += cancelled_units as f64 * params.cancellation_fee_per_unit;
+                    }
+                }
                 current_order = new_order.clone();
                 pending_order = None;
-                order_change_cost_this_month = params.order_change_fee;
+            }
+        }
+        if let Some((first_month, raised_order)) = &pending_raise {
+            if month_idx >= *first_month {
+                current_order = raised_order.clone();
+                pending_raise = None;
             }
         }
 
@@ -73,45 +529,153 @@ pub fn run_monthly_simulation_internal(
         let mut monthly_revenue = 0.0;
         let mut monthly_production_cost = 0.0;
         let mut monthly_holding_cost = 0.0;
+        let mut monthly_financing_cost = 0.0;
+        let mut monthly_goodwill_penalty = 0.0;
         let mut monthly_liquidation_revenue = 0.0;
+        // Each product's cost at each supplier, as (supplier_id, cost), in product order
+        let mut supplier_costs: Vec<Vec<(SupplierId, f64)>> = Vec::new();
+
+        // Regions disrupted this month, drawn for every configured region whichever suppliers the
+        // pair uses, so all pairs see the same events
+        let regions_down: Vec<&str> = params.regional_disruptions.iter()
+            .filter(|disruption| demand.event_draw() < disruption.monthly_probability)
+            .map(|disruption| disruption.region.as_str())
+            .collect();
+        let is_down = |supplier: &Supplier| supplier.is_disrupted(month_idx) || supplier.is_in_any(&regions_down);
+
+        // Capacity left this month, shared across products: what the ledger committed to each
+        // supplier and what it has free, to take what disrupted suppliers cannot deliver or
+        // flexing adds; disrupted suppliers deliver nothing
+        let reserved = &commitments[month_idx];
+        let available = |supplier: &Supplier, units: u32| if is_down(supplier) { 0 } else { units + ledger.available(supplier.id, month_idx) };
+        // A single source has no surge capacity, and a supplier splitting its capacity only what
+        // its base orders leave, as the ledger holds them
+        let mut surge_capacity_left = if pair.is_single_source() { 0 } else { available(&pair.surge_supplier, reserved.surge) };
+        let backup_capacity_left = pair.backup_supplier.as_ref().map_or(0, |backup| available(backup, reserved.backup));
+        // Committed tier capacity left, in tier order
+        let mut committed_capacity_left: Vec<u32> = pair.committed_tier().zip(&reserved.committed)
+            .map(|(member, &units)| available(member, units))
+            .collect();
+
+        // Base orders fill the committed tier in order, and surge orders the surge supplier
+        // cannot deliver this month, exhausted or disrupted, spill over to the backup, if any;
+        // products share a supplier short of capacity by unit margin
+        let base_requests: Vec<(ProductId, u32)> = params.products.iter().map(|p| (p.id, current_order.base_quantity_for(p.id))).collect();
+        let member_units = fill_in_turn(params, &base_requests, pair.committed_tier().zip(committed_capacity_left.iter().copied()), &[]);
+        let surge_requests: Vec<(ProductId, u32)> = params.products.iter().map(|p| (p.id, current_order.surge_quantity_for(p.id))).collect();
+        let surge_suppliers = [(&pair.surge_supplier, surge_capacity_left)].into_iter()
+            .chain(pair.backup_supplier.iter().map(|backup| (backup, backup_capacity_left)));
+        // A supplier splitting its capacity makes both tranches within its capacity groups
+        let by_product = |units: &[u32]| -> Vec<(ProductId, u32)> { params.products.iter().zip(units).map(|(p, &units)| (p.id, units)).collect() };
+        let shared_base = if pair.shares_capacity() { vec![(pair.base_supplier.id, by_product(&member_units[0]))] } else { Vec::new() };
+        let surge_units = fill_in_turn(params, &surge_requests, surge_suppliers, &shared_base);
+        let mut committed_delivered: Vec<u32> = member_units.iter().map(|units| units.iter().sum()).collect();
+        for (left, delivered) in committed_capacity_left.iter_mut().zip(&committed_delivered) {
+            *left -= delivered;
+        }
+        let mut surge_delivered: u32 = surge_units[0].iter().sum();
+        surge_capacity_left -= surge_delivered;
+        let backup_delivered: u32 = surge_units.get(1).map_or(0, |units| units.iter().sum());
+        // Units of each product the flexing suppliers deliver, so flexing up keeps within their
+        // capacity groups; a supplier splitting its capacity counts both tranches in each
+        let mut base_group_units = by_product(&member_units[0]);
+        let mut surge_group_units = by_product(&surge_units[0]);
+        if pair.shares_capacity() {
+            for ((_, base), (_, surge)) in base_group_units.iter_mut().zip(surge_group_units.iter_mut()) {
+                (*base, *surge) = (*base + *surge, *base + *surge);
+            }
+        }
 
         // Process each product
-        for product in &params.products {
+        for (product_index, product) in params.products.iter().enumerate() {
             let product_id = product.id;
             let inventory_start = *inventories.get(&product_id).unwrap_or(&0);
 
             // Get incoming inventory for this product
-            let base_incoming = current_order.base_quantity_for(product_id);
-            let surge_incoming = current_order.surge_quantity_for(product_id);
-            let incoming = base_incoming + surge_incoming;
+            let demand_params = params.get_demand_params(product_id);
+            let planned_base: u32 = member_units.iter().map(|units| units[product_index]).sum();
+            let planned_surge = surge_units[0][product_index];
+            let backup_incoming = surge_units.get(1).map_or(0, |units| units[product_index]);
+
+            // Flex this month's deliveries within contract bounds, surge supplier first
+            let desired = desired_flex(inventory_start + planned_base + planned_surge, demand_params);
+            // Flexing up takes capacity left this month like any delivery
+            let surge_flex = bounded_flex(&pair.surge_supplier, planned_surge, desired)
+                .min(surge_capacity_left as i64)
+                .min(pair.surge_supplier.group_room_for(product_id, &surge_group_units) as i64);
+            surge_capacity_left -= surge_flex.max(0) as u32;
+            surge_delivered += surge_flex.max(0) as u32;
+            surge_group_units[product_index].1 += surge_flex.max(0) as u32;
+            if pair.shares_capacity() {
+                base_group_units[product_index].1 += surge_flex.max(0) as u32;
+            }
+            // The base supplier flexes only its own units, never the pooled suppliers'
+            let base_flex = bounded_flex(&pair.base_supplier, member_units[0][product_index], desired - surge_flex)
+                .min(committed_capacity_left[0] as i64)
+                .min(pair.base_supplier.group_room_for(product_id, &base_group_units) as i64);
+            committed_capacity_left[0] -= base_flex.max(0) as u32;
+            committed_delivered[0] += base_flex.max(0) as u32;
+            base_group_units[product_index].1 += base_flex.max(0) as u32;
+            if pair.shares_capacity() {
+                surge_group_units[product_index].1 += base_flex.max(0) as u32;
+            }
+            let base_incoming = (planned_base as i64 + base_flex) as u32;
+            let surge_incoming = (planned_surge as i64 + surge_flex) as u32;
+            if base_flex + surge_flex != 0 {
+                flex_adjustments.push((product_id, base_flex + surge_flex));
+                flex_cost_this_month += flex_fee(&pair.base_supplier, base_flex) + flex_fee(&pair.surge_supplier, surge_flex);
+            }
+            let incoming = base_incoming + surge_incoming + backup_incoming;
             
             let inventory_after_incoming = inventory_start + incoming;
 
             // Generate demand for this product
-            let demand_params = params.get_demand_params(product_id);
-            let monthly_demand = demand_params
-                .map(|dp| simulation_demand(dp, use_actual_demand))
-                .unwrap_or(0);
+            let monthly_demand = demand.demand(month_idx, product_id, demand_params, use_actual_demand)?;
+            demand_history.entry(product_id).or_default().push(monthly_demand);
 
             // Calculate sales
             let units_sold = cmp::min(inventory_after_incoming, monthly_demand);
             let mut inventory_end = inventory_after_incoming - units_sold;
 
+            // A stockout lengthens the product's run of them, and filling demand ends it
+            let streak = stockout_streaks.entry(product_id).or_insert(0);
+            *streak = if units_sold < monthly_demand { *streak + 1 } else { 0 };
+            let goodwill_penalty = product.goodwill_penalty.as_ref().map_or(0.0, |goodwill| goodwill.penalty(*streak));
+
             // Calculate revenue and costs for this product
             let revenue = (units_sold as f64) * product.selling_price;
             
             // Production cost uses supplier-specific unit costs for this product
             let base_unit_cost = pair.base_supplier.unit_costs.get(&product_id).copied().unwrap_or(0.0);
             let surge_unit_cost = pair.surge_supplier.unit_costs.get(&product_id).copied().unwrap_or(0.0);
-            let production_cost = (base_incoming as f64) * base_unit_cost 
-                + (surge_incoming as f64) * surge_unit_cost;
+            let backup_unit_cost = pair.backup_supplier.as_ref()
+                .and_then(|backup| backup.unit_costs.get(&product_id).copied())
+                .unwrap_or(0.0);
+            // Pooled suppliers deliver at their own unit costs, and only the base supplier flexes;
+            // each supplier's part is kept for its overtime premium once the month's deliveries
+            // are known
+            let pooled_incoming: u32 = member_units[1..].iter().map(|units| units[product_index]).sum();
+            let mut costed = vec![(pair.base_supplier.id, ((base_incoming - pooled_incoming) as f64) * base_unit_cost)];
+            costed.extend(pair.pooled_suppliers.iter().zip(&member_units[1..])
+                .map(|(supplier, units)| (supplier.id, units[product_index] as f64 * supplier.unit_costs.get(&product_id).copied().unwrap_or(0.0))));
+            costed.push((pair.surge_supplier.id, (surge_incoming as f64) * surge_unit_cost));
+            if let Some(backup) = &pair.backup_supplier {
+                costed.push((backup.id, (backup_incoming as f64) * backup_unit_cost));
+            }
+            let production_cost: f64 = costed.iter().map(|(_, cost)| cost).sum();
+            supplier_costs.push(costed);
             
             let holding_cost = (inventory_end as f64) * product.monthly_holding_cost;
+            // Deliveries join the stock at what they cost, and sales leave its unit cost as it was
+            let unit_cost = weighted_average_cost(inventory_start, unit_cost_basis[&product_id], incoming, production_cost);
+            unit_cost_basis.insert(product_id, unit_cost);
+            let financing_cost = monthly_financing_cost_of(params.cost_of_capital_annual, inventory_end, unit_cost);
 
             let mut liquidation_revenue = 0.0;
 
-            // If December, liquidate remaining inventory
-            if month_idx == TOTAL_MONTHS - 1 {
+            // In the last month of the season, liquidate remaining inventory unless it is
+            // carried over into the next season
+            if month_idx == season_months - 1 && !params.carry_over_stock {
                 liquidation_revenue = (inventory_end as f64) * product.liquidation_price;
                 inventory_end = 0;
             }
@@ -123,6 +687,8 @@ pub fn run_monthly_simulation_internal(
             monthly_revenue += revenue;
             monthly_production_cost += production_cost;
             monthly_holding_cost += holding_cost;
+            monthly_financing_cost += financing_cost;
+            monthly_goodwill_penalty += goodwill_penalty;
             monthly_liquidation_revenue += liquidation_revenue;
 
             product_results.push(ProductMonthlyResult {
@@ -130,6 +696,9 @@ pub fn run_monthly_simulation_internal(
                 product_name: product.name.clone(),
                 inventory_start,
                 incoming,
+                base_incoming,
+                surge_incoming,
+                backup_incoming,
                 demand: monthly_demand,
                 units_sold,
                 inventory_end,
@@ -137,140 +706,1400 @@ pub fn run_monthly_simulation_internal(
                 production_cost,
                 holding_cost,
                 liquidation_revenue,
+                financing_cost,
+                goodwill_penalty,
             });
         }
 
+        // Deliveries past what the orders committed take up the suppliers' free capacity
+        for ((member, delivered), units) in pair.committed_tier().zip(&committed_delivered).zip(&reserved.committed) {
+            ledger.reserve_up_to(member.id, month_idx, delivered.saturating_sub(*units));
+        }
+        ledger.reserve_up_to(pair.surge_supplier.id, month_idx, surge_delivered.saturating_sub(reserved.surge));
+        if let Some(backup) = &pair.backup_supplier {
+            ledger.reserve_up_to(backup.id, month_idx, backup_delivered.saturating_sub(reserved.backup));
+        }
+
+        // Units each supplier delivered this month, a supplier in two roles in both
+        let delivered_by = |supplier_id: SupplierId| -> u32 {
+            let tier: u32 = pair.committed_tier().zip(&committed_delivered)
+                .filter(|(member, _)| member.id == supplier_id)
+                .map(|(_, delivered)| delivered)
+                .sum();
+            let surge = if pair.surge_supplier.id == supplier_id { surge_delivered } else { 0 };
+            let backup = pair.backup_supplier.as_ref().filter(|backup| backup.id == supplier_id).map_or(0, |_| backup_delivered);
+            tier + surge + backup
+        };
+        ledger.record_deliveries(month_idx, delivered_by);
+        // Deliveries past a supplier's nominal capacity cost its overtime premium, which falls on
+        // the products it delivered in proportion to their cost there
+        let multipliers: Vec<(SupplierId, f64)> = pair.suppliers()
+            .map(|supplier| (supplier.id, supplier.cost_multiplier(delivered_by(supplier.id))))
+            .collect();
+        let multiplier_of = |supplier_id: SupplierId| multipliers.iter().find(|(id, _)| *id == supplier_id).map_or(1.0, |(_, multiplier)| *multiplier);
+        for (result, costed) in product_results.iter_mut().zip(&supplier_costs) {
+            let premium: f64 = costed.iter().map(|(supplier_id, cost)| cost * (multiplier_of(*supplier_id) - 1.0)).sum();
+            result.production_cost += premium;
+            monthly_production_cost += premium;
+        }
+
+        let reserved_units: u32 = initial_order.reservations.iter().map(|reservation| reservation.units_in(month_idx)).sum();
+        let reserved_units_used: u32 = initial_order.reservations.iter()
+            .map(|reservation| delivered_by(reservation.supplier_id).min(reservation.units_in(month_idx)))
+            .sum();
+
+        // Pooled suppliers' and the backup's setup costs are paid in the first month they deliver
+        for ((supplier, delivered), deducted) in pair.pooled_suppliers.iter().zip(&committed_delivered[1..]).zip(&mut pooled_setup_cost_deducted) {
+            if !*deducted && *delivered > 0 {
+                setup_cost_this_month += supplier.setup_cost;
+                *deducted = true;
+            }
+        }
+        if let Some(backup) = &pair.backup_supplier {
+            if !backup_setup_cost_deducted && product_results.iter().any(|result| result.backup_incoming > 0) {
+                setup_cost_this_month += backup.setup_cost;
+                backup_setup_cost_deducted = true;
+            }
+        }
+
+        // Capacity expansion offers decided this month: one is taken when its extra capacity is
+        // worth more than its fee, and deliveries are raised into it from its first month; offers
+        // wait on nothing, but none is weighed while an order change or another raise is on its
+        // way, which the raise would undo or be undone by
+        let mut expansions_accepted = Vec::new();
+        let mut expansion_cost_this_month = 0.0;
+        for (index, offer) in params.expansion_offers.iter().enumerate() {
+            let first_month = offer.start_month;
+            let open = enable_options && offer.decision_month == month_idx && month_idx < first_month && first_month < season_months;
+            if !open || pending_order.is_some() || pending_raise.is_some() || !pair.suppliers().any(|supplier| supplier.id == offer.supplier_id) {
+                continue;
+            }
+            let state = OptionState {
+                month: month_idx,
+                inventories: inventories.clone(),
+                current_order: current_order.clone(),
+                observed_demand: demand_history.clone(),
+                exercises_used,
+            };
+            let product_ids: Vec<ProductId> = params.products.iter().map(|product| product.id).collect();
+            let valuation = OptionValuation::from_state(&state, params, pair, &options_config(&product_ids))
+                .expect("decision month lies within the season");
+
+            // The offer is worth what the raise it allows adds over one into the capacity the
+            // supplier has free anyway
+            let delivered: u32 = commitments[first_month].units(pair).iter().map(|(_, units)| units).sum();
+            let free = (first_month..season_months).map(|month| ledger.available(offer.supplier_id, month)).min().unwrap_or(0);
+            let (with_offer, raise) = valuation.capacity_raise_value(delivered, free + offer.extra_capacity, first_month);
+            let (without_offer, _) = valuation.capacity_raise_value(delivered, free, first_month);
+            if with_offer - without_offer <= offer.fee {
+                continue;
+            }
+
+            // The raise goes to the supplier's role, shared among the products by their forecasts
+            let mut raised_order = current_order.clone();
+            let in_committed_tier = pair.committed_tier().any(|supplier| supplier.id == offer.supplier_id);
+            for (product_id, units) in share_by_forecast(params, raise) {
+                if in_committed_tier {
+                    raised_order.add_base_quantity(product_id, units);
+                } else {
+                    raised_order.add_surge_quantity(product_id, units);
+                }
+            }
+            start_ramps(&mut ledger, pair, &raised_order, first_month, Some(month_idx));
+            ledger.expand(offer.supplier_id, first_month, offer.extra_capacity);
+            ledger.forget_shortfalls_from(first_month);
+            recommit_from(&mut ledger, pair, &mut commitments, &raised_order, first_month);
+            debug!(month = month_idx, offer = index, raise, value = with_offer - without_offer, fee = offer.fee, "expansion offer taken");
+            pending_raise = Some((first_month, raised_order));
+            expansions_accepted.push(index);
+            expansion_cost_this_month += offer.fee;
+        }
+
         // Options valuation - evaluate whether to change monthly order
         // Only evaluate if we haven't already committed to a pending order change
-        if enable_options && pending_order.is_none() && month_idx < 7 {
-            // Get total current inventory
-            let total_inventory: u32 = inventories.values().sum();
-            let total_current_order = current_order.total_base_quantity() + current_order.total_surge_quantity();
-            
-            let option_valuer = OptionValuation::new(
-                total_current_order,
-                total_inventory,
-                month_idx,
-                params.clone(),
-                pair.clone(),
-            );
-
-            let option_value = option_valuer.value_option();
-
-            // Exercise the option if the value exceeds the fixed fee
-            if option_value > params.order_change_fee {
-                // Recalculate optimal based on FORECAST parameters
-                let new_allocations = find_optimal_production_quantities(params, pair);
-                
-                // Create new surge order based on optimal allocations
-                // Base quantity remains fixed from the initial plan
-                let new_surge_orders: Vec<ProductOrder> = new_allocations.iter()
-                    .map(|(product_id, optimal_qty)| {
-                        let current_base = current_order.base_quantity_for(*product_id);
-                        let desired_surge = optimal_qty.saturating_sub(current_base);
-                        ProductOrder {
-                            product_id: *product_id,
-                            quantity: desired_surge,
-                        }
-                    })
+        // and a change could still be delivered before the season ends: changes land after the
+        // surge lead time, and never before next month's delivery
+        let surge_lead_time = pair.surge_supplier.lead_time_months;
+        let change_can_land = month_idx + cmp::max(surge_lead_time, 1) < season_months;
+        // and the fee schedule still has a right left to exercise
+        let can_evaluate = enable_options && pending_order.is_none() && pending_raise.is_none() && change_can_land;
+        if let Some(next_fee) = params.next_order_change_fee(exercises_used).filter(|_| can_evaluate) {
+            let state = OptionState {
+                month: month_idx,
+                inventories: inventories.clone(),
+                current_order: current_order.clone(),
+                observed_demand: demand_history.clone(),
+                exercises_used,
+            };
+
+            // Value each product independently so an on-plan product is left untouched
+            let mut changes: Vec<(ProductId, ExerciseDirection, OptionValuation)> = Vec::new();
+            let mut total_change_value = 0.0;
+
+            for product in &params.products {
+                let option_valuer = OptionValuation::from_state(&state, params, pair, &options_config(&[product.id]))
+                    .expect("decision month lies within the season");
+                let option_value = option_valuer.value_option();
+
+                // A product's change is worthwhile when it covers its own per-product fee
+                if let Some(direction) = option_value.recommended_action {
+                    if option_value.value > params.order_change_fee_per_product {
+                        total_change_value += option_value.value;
+                        changes.push((product.id, direction, option_valuer));
+                    }
+                }
+            }
+
+            option_value = Some(total_change_value);
+
+            // Exercise if the worthwhile changes together exceed the next fee in the schedule
+            let applicable_fee = next_fee + params.order_change_fee_per_product * changes.len() as f64;
+            if !changes.is_empty() && total_change_value > applicable_fee {
+                let mut new_order = current_order.clone();
+                // Put back if the change comes to nothing
+                let ledger_before = ledger.clone();
+
+                // Cut deliveries back towards target, cancelling surge units first
+                for (_, direction, option_valuer) in &changes {
+                    if *direction == ExerciseDirection::Decrease {
+                        new_order = option_valuer.decreased_order(&new_order);
+                    }
+                }
+
+                let increased: Vec<ProductId> = changes.iter()
+                    .filter(|(_, direction, _)| *direction == ExerciseDirection::Increase)
+                    .map(|(product_id, _, _)| *product_id)
                     .collect();
 
-                // Ensure surge doesn't exceed capacity
-                let total_new_surge: u32 = new_surge_orders.iter().map(|o| o.quantity).sum();
-                let surge_capacity = pair.surge_supplier.fixed_capacity;
-                
-                let final_surge_orders = if total_new_surge > surge_capacity {
-                    // Scale down proportionally
-                    let scale = surge_capacity as f64 / total_new_surge as f64;
-                    new_surge_orders.iter()
-                        .map(|o| ProductOrder {
-                            product_id: o.product_id,
-                            quantity: (o.quantity as f64 * scale) as u32,
+                // The change lands after the surge lead time, and no sooner than next month
+                let effective_month = month_idx + surge_lead_time;
+                let first_month = cmp::max(effective_month, month_idx + 1);
+                // Those months' shortfalls are now what the change asks
+                ledger.forget_shortfalls_from(first_month);
+
+                if !increased.is_empty() {
+                    // Recalculate optimal based on FORECAST parameters
+                    let new_allocations = replan_quantities(params, pair)?;
+                    
+                    // The plan never asks less, and the demand seen so far may ask more of the rest of the season
+                    let mut raised = current_order.clone();
+                    for (_, direction, option_valuer) in &changes {
+                        if *direction == ExerciseDirection::Increase {
+                            raised = option_valuer.increased_order(&raised);
+                        }
+                    }
+
+                    // Create new surge orders for the increased products only
+                    // Base quantity remains fixed from the initial plan
+                    let new_surge_orders: Vec<ProductOrder> = new_allocations.iter()
+                        .filter(|(product_id, _)| increased.contains(product_id))
+                        .map(|(product_id, optimal_qty)| {
+                            let current_base = current_order.base_quantity_for(*product_id);
+                            let desired_surge = optimal_qty.saturating_sub(current_base).max(raised.surge_quantity_for(*product_id));
+                            // No more than the surge supplier and backup can make of the product
+                            let mut product_capacity = pair.surge_capacity_for(*product_id);
+                            if pair.shares_capacity() {
+                                product_capacity = product_capacity.saturating_sub(current_base);
+                            }
+                            ProductOrder {
+                                product_id: *product_id,
+                                quantity: desired_surge.min(product_capacity),
+                            }
                         })
-                        .collect()
-                } else {
-                    new_surge_orders
-                };
+                        .collect();
+
+                    // Ensure surge doesn't exceed what the ledger has left beside the untouched
+                    // products in any month the change covers; it records what it cannot take
+                    let untouched_surge: u32 = new_order.surge_orders().iter()
+                        .filter(|o| !increased.contains(&o.product_id))
+                        .map(|o| o.quantity)
+                        .sum();
+                    let mut requested = new_order.clone();
+                    for order in &new_surge_orders {
+                        requested.set_surge_quantity(order.product_id, order.quantity);
+                    }
+                    start_ramps(&mut ledger, pair, &requested, first_month, Some(month_idx));
+                    let surge_capacity = surge_capacity_from(&mut ledger, pair, &commitments, &requested, first_month)
+                        .saturating_sub(untouched_surge);
+                    
+                    // Scale down proportionally, down to the unit, so all the capacity left is used
+                    for order in scale_to_capacity(&new_surge_orders, surge_capacity) {
+                        new_order.set_surge_quantity(order.product_id, order.quantity);
+                    }
+                }
 
-                let new_order = MonthlyOrder {
-                    base_orders: current_order.base_orders.clone(),
-                    surge_orders: final_surge_orders,
+                // Only the products whose deliveries the change moves are changed, and paid for;
+                // one capacity scales down to nothing changes nothing
+                let moved = |product_id: ProductId| {
+                    new_order.base_quantity_for(product_id) != current_order.base_quantity_for(product_id)
+                        || new_order.surge_quantity_for(product_id) != current_order.surge_quantity_for(product_id)
                 };
+                let made: Vec<(ProductId, ExerciseDirection)> = changes.iter()
+                    .filter(|(product_id, _, _)| moved(*product_id))
+                    .map(|(product_id, direction, _)| (*product_id, *direction))
+                    .collect();
 
-                // Schedule the order change to take effect after SURGE supplier's lead time
-                let effective_month = month_idx + pair.surge_supplier.lead_time_months;
-                if effective_month < TOTAL_MONTHS {
-                    pending_order = Some((effective_month, new_order));
+                // Schedule the order change to take effect after SURGE supplier's lead time,
+                // committing the months it covers in place of the current order's
+                if effective_month < season_months && !made.is_empty() {
+                    start_ramps(&mut ledger, pair, &new_order, first_month, Some(month_idx));
+                    recommit_from(&mut ledger, pair, &mut commitments, &new_order, first_month);
+                    exercised_changes = made;
+                    debug!(month = month_idx, effective_month, fee = next_fee, changes = ?exercised_changes, "order change exercised");
+                    pending_order = Some((effective_month, new_order, exercised_changes.clone(), next_fee));
+                    exercises_used += 1;
+                } else {
+                    ledger = ledger_before;
                 }
             }
         }
 
         // Calculate monthly profit
-        let monthly_profit = monthly_revenue - monthly_production_cost - monthly_holding_cost 
-            + monthly_liquidation_revenue - order_change_cost_this_month - setup_cost_this_month;
+        let reservation_cost_this_month = if month_idx == 0 { reservation_cost } else { 0.0 };
+        let monthly_profit = monthly_revenue - monthly_production_cost - monthly_holding_cost - monthly_financing_cost
+            - monthly_goodwill_penalty + monthly_liquidation_revenue - order_change_cost_this_month - setup_cost_this_month
+            - flex_cost_this_month - reservation_cost_this_month - expansion_cost_this_month;
 
         total_profit += monthly_profit;
 
         monthly_results.push(MonthlyResult {
-            month: month_name.to_string(),
+            month: period_name(time_step, month_idx),
             product_results,
             order_change_cost: order_change_cost_this_month,
             setup_cost: setup_cost_this_month,
             monthly_profit,
+            option_exercised: !exercised_changes.is_empty(),
+            exercised_changes,
+            option_value,
+            flex_adjustments,
+            flex_cost: flex_cost_this_month,
+            reservation_cost: reservation_cost_this_month,
+            reserved_units,
+            reserved_units_used,
+            expansions_accepted,
+            expansion_cost: expansion_cost_this_month,
         });
     }
 
-    (monthly_results, total_profit)
+    Ok((monthly_results, total_profit, ledger))
+}
+
+/// Run the season once with every month's demand at its actual mean and no in-season order
+/// changes, projecting how the plan plays out as ordered
+pub fn project_mean_demand(
+    params: &SimulationParams,
+    pair: &SupplierPair,
+    order: &MonthlyOrder,
+) -> Result<Vec<MonthlyResult>, SimulationError> {
+    with_demand_stream(0, || {
+        script_mean_demand(params.periods() * params.demand_params.len());
+        let projection = run_monthly_simulation_internal(params, pair, order, false, true, DemandSource::Stochastic(None));
+        clear_demand_script();
+        projection.map(|(monthly_results, _)| monthly_results)
+    })
+}
+
+/// Share `units` among the products in proportion to their forecast demand, by largest remainder,
+/// as (product_id, units)
+fn share_by_forecast(params: &SimulationParams, units: u32) -> Vec<(ProductId, u32)> {
+    let forecasts: Vec<u32> = params.products.iter()
+        .map(|product| params.get_demand_params(product.id).map_or(0, |dp| dp.mean_demand.round() as u32))
+        .collect();
+    let total: u32 = forecasts.iter().sum();
+    // Weights scaled to cover the units, so largest remainder shares all of them out
+    let requests: Vec<u32> = if total == 0 {
+        vec![units; forecasts.len()]
+    } else {
+        let scale = units.div_ceil(total).max(1);
+        forecasts.iter().map(|forecast| forecast * scale).collect()
+    };
+    params.products.iter().map(|product| product.id).zip(largest_remainder(&requests, units)).collect()
+}
+
+/// Myopic swing rule for one product's delivery this month: top up to expected demand when the
+/// inventory position falls short, and trim back when it exceeds expected demand by more than
+/// `FLEX_DOWN_STD_DEVS` standard deviations
+/// Returns the desired signed adjustment before any supplier's flex bounds
+fn desired_flex(inventory_position: u32, demand_params: Option<&ProductDemandParams>) -> i64 {
+    let Some(dp) = demand_params else {
+        return 0;
+    };
+    let position = inventory_position as f64;
+    let flex_down_threshold = dp.mean_demand + FLEX_DOWN_STD_DEVS * dp.std_dev_demand;
+
+    if position < dp.mean_demand {
+        (dp.mean_demand - position) as i64
+    } else if position > flex_down_threshold {
+        -((position - flex_down_threshold) as i64)
+    } else {
+        0
+    }
+}
+
+/// Portion of a desired adjustment a supplier's flex contract allows on its planned quantity
+fn bounded_flex(supplier: &Supplier, planned_quantity: u32, desired: i64) -> i64 {
+    match &supplier.monthly_flex {
+        Some(flex) => {
+            let bound = (planned_quantity as f64 * flex.max_fraction) as i64;
+            desired.clamp(-bound, bound)
+        }
+        None => 0,
+    }
+}
+
+/// Fee for flexing a supplier's delivery by the given number of units
+fn flex_fee(supplier: &Supplier, units: i64) -> f64 {
+    supplier.monthly_flex.as_ref()
+        .map(|flex| units.unsigned_abs() as f64 * flex.fee_per_unit)
+        .unwrap_or(0.0)
 }
 
 /// Split order quantity between base and surge suppliers for multiple products
-/// Uses a heuristic based on available capacity and demand variability
+/// Uses a heuristic based on available capacity and demand variability; when the products'
+/// shares do not all fit a supplier, those with the highest unit margin at it are filled first
+/// Suppliers still ramping up at their first delivery are planned at the capacity they have then,
+/// and what they cannot make goes to the committed tier where it has room
 pub fn split_order_quantities(
-    product_quantities: &[(usize, u32)],  // (product_id, desired_quantity)
+    product_quantities: &[(ProductId, u32)],  // (product_id, desired_quantity)
     pair: &SupplierPair,
     params: &SimulationParams,
 ) -> MonthlyOrder {
-    let mut base_orders: Vec<ProductOrder> = Vec::new();
-    let mut surge_orders: Vec<ProductOrder> = Vec::new();
-    
-    let mut base_capacity_remaining = pair.base_supplier.fixed_capacity;
-    let mut surge_capacity_remaining = pair.surge_supplier.fixed_capacity;
-
-    for (product_id, total_quantity) in product_quantities {
-        // Get demand params for this product to calculate CV
-        let cv = params.get_demand_params(*product_id)
-            .map(|dp| dp.std_dev_demand / dp.mean_demand)
-            .unwrap_or(0.2);
-
-        // Allocation weights based on variability:
-        // Low variability: favor stable base supplier
-        // High variability: favor flexible surge supplier
-        let base_weight = 1.0 / (1.0 + cv);
-
-        // Desired allocation
-        let ideal_base = (*total_quantity as f64 * base_weight) as u32;
-
-        // Constrain to available capacity
-        let base_quantity = cmp::min(ideal_base, base_capacity_remaining);
-        let remaining = total_quantity.saturating_sub(base_quantity);
-        let surge_quantity = cmp::min(remaining, surge_capacity_remaining);
-
-        // Update remaining capacities
-        base_capacity_remaining = base_capacity_remaining.saturating_sub(base_quantity);
-        surge_capacity_remaining = surge_capacity_remaining.saturating_sub(surge_quantity);
-
-        base_orders.push(ProductOrder {
-            product_id: *product_id,
-            quantity: base_quantity,
+    split_ramped(product_quantities, pair, params, false)
+}
+
+/// As `split_order_quantities`, but each product goes to the base supplier as far as its capacity
+/// allows, and only the rest to the surge supplier
+pub fn split_base_first(
+    product_quantities: &[(ProductId, u32)],
+    pair: &SupplierPair,
+    params: &SimulationParams,
+) -> MonthlyOrder {
+    split_ramped(product_quantities, pair, params, true)
+}
+
+/// Split order quantities, planning suppliers still ramping up at the capacity they have at their
+/// first delivery
+fn split_ramped(
+    product_quantities: &[(ProductId, u32)],
+    pair: &SupplierPair,
+    params: &SimulationParams,
+    base_first: bool,
+) -> MonthlyOrder {
+    let order = split_within_capacity(product_quantities, pair, params, base_first);
+    if pair.suppliers().all(|supplier| supplier.ramp_fraction(supplier.lead_time_months) >= 1.0) {
+        return order;
+    }
+
+    // What the ramp holds back of each product, committed earlier as base orders instead
+    let ramped = pair.at_first_delivery();
+    let mut ramped_order = split_within_capacity(product_quantities, &ramped, params, base_first);
+    let placed = |order: &MonthlyOrder, product_id: ProductId| order.base_quantity_for(product_id) + order.surge_quantity_for(product_id);
+    let base_held = |product_id: ProductId| {
+        let shared = if ramped.shares_capacity() { ramped_order.surge_quantity_for(product_id) } else { 0 };
+        ramped_order.base_quantity_for(product_id) + shared
+    };
+    let held_back: Vec<(ProductId, u32)> = product_quantities.iter()
+        .map(|&(product_id, _)| {
+            let room = ramped.base_capacity_for(product_id).saturating_sub(base_held(product_id));
+            (product_id, placed(&order, product_id).saturating_sub(placed(&ramped_order, product_id)).min(room))
+        })
+        .collect();
+    let base_units: Vec<(ProductId, u32)> = product_quantities.iter().map(|&(product_id, _)| (product_id, base_held(product_id))).collect();
+    let base_room = ramped.base_capacity().saturating_sub(base_units.iter().map(|(_, units)| units).sum());
+    let base_margins = unit_margins(params, &ramped.base_supplier);
+    let held_back = fit_capacity_groups(&held_back, &base_units, ramped.committed_tier(), &base_margins);
+    for moved in allocate_by_priority(&held_back, base_room, &base_margins) {
+        ramped_order.set_base_quantity(moved.product_id, ramped_order.base_quantity_for(moved.product_id) + moved.quantity);
+    }
+    ramped_order
+}
+
+/// Split order quantities within the pair's capacity as it stands
+fn split_within_capacity(
+    product_quantities: &[(ProductId, u32)],
+    pair: &SupplierPair,
+    params: &SimulationParams,
+    base_first: bool,
+) -> MonthlyOrder {
+    // Each product's share for the base supplier, which the pooled suppliers take beyond its
+    // capacity, if any
+    let ideal_base: Vec<(ProductId, u32)> = product_quantities.iter()
+        .map(|(product_id, total_quantity)| {
+            // Get demand params for this product to calculate CV
+            let cv = params.get_demand_params(*product_id)
+                .map(|dp| dp.std_dev_demand / dp.mean_demand)
+                .unwrap_or(0.2);
+
+            // Allocation weights based on variability:
+            // Low variability: favor stable base supplier
+            // High variability: favor flexible surge supplier
+            // A single source, or a split filling the base supplier first, takes everything as
+            // base orders
+            let base_weight = if base_first || pair.is_single_source() { 1.0 } else { 1.0 / (1.0 + cv) };
+
+            // Desired allocation, no more than the suppliers can make of the product
+            let ideal_base = (*total_quantity as f64 * base_weight) as u32;
+            (*product_id, ideal_base.min(pair.base_capacity_for(*product_id)))
+        })
+        .collect();
+    let base_margins = unit_margins(params, &pair.base_supplier);
+    let ideal_base = fit_capacity_groups(&ideal_base, &[], pair.committed_tier(), &base_margins);
+    let base_orders = allocate_by_priority(&ideal_base, pair.base_capacity(), &base_margins);
+
+    // The rest goes to the surge supplier, and beyond its capacity to the backup, if there is
+    // one; a supplier splitting its capacity has only what the base tranche leaves
+    let mut surge_capacity = pair.surge_capacity();
+    if pair.shares_capacity() {
+        surge_capacity = surge_capacity.saturating_sub(base_orders.iter().map(|order| order.quantity).sum());
+    }
+    let surge_wanted: Vec<(ProductId, u32)> = product_quantities.iter().zip(&base_orders)
+        .map(|((product_id, total_quantity), base_order)| {
+            let mut surge_product_capacity = pair.surge_capacity_for(*product_id);
+            if pair.shares_capacity() {
+                surge_product_capacity = surge_product_capacity.saturating_sub(base_order.quantity);
+            }
+            (*product_id, total_quantity.saturating_sub(base_order.quantity).min(surge_product_capacity))
+        })
+        .collect();
+    // Within the surge supplier's capacity groups too, beside the base tranche when it shares them
+    let surge_margins = unit_margins(params, &pair.surge_supplier);
+    let shared_base: Vec<(ProductId, u32)> = if pair.shares_capacity() {
+        base_orders.iter().map(|order| (order.product_id, order.quantity)).collect()
+    } else {
+        Vec::new()
+    };
+    let surge_wanted = fit_capacity_groups(&surge_wanted, &shared_base, pair.surge_tier(), &surge_margins);
+    let surge_orders = allocate_by_priority(&surge_wanted, surge_capacity, &surge_margins);
+
+    // What nominal capacity cannot make goes to overtime where the margin bears its premium
+    let mut order = MonthlyOrder::new(base_orders, surge_orders).expect("one order per product planned");
+    plan_first_tier_overtime(params, pair, &mut order, product_quantities);
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demand::seed_demand_rng;
+    use crate::monte_carlo::{run_monte_carlo_simulation, MonteCarloConfig};
+    use crate::models::{Flex, Product, ProductDemandParams, Supplier};
+    use crate::capacity::create_order;
+    use proptest::prelude::*;
+
+    const SEASON_MONTHS: usize = 8;
+
+    /// One product whose actual demand collapses to 40% of the forecast
+    fn collapsing_demand_scenario(cancellation_fee_per_unit: f64) -> (SimulationParams, SupplierPair, MonthlyOrder) {
+        let params = SimulationParams {
+            products: vec![Product {
+                id: ProductId(0),
+                name: "Widget".to_string(),
+                selling_price: 100.0,
+                liquidation_price: 60.0,
+                monthly_holding_cost: 2.0,
+                goodwill_penalty: None,
+            }],
+            demand_params: vec![ProductDemandParams {
+                product_id: ProductId(0),
+                mean_demand: 10_000.0,
+                std_dev_demand: 1_000.0,
+                actual_mean_demand: 4_000.0,
+                actual_std_dev_demand: 400.0,
+            }],
+            order_change_fees: vec![50_000.0; SEASON_MONTHS],
+            order_change_fee_per_product: 0.0,
+            cancellation_fee_per_unit,
+            calibrate_to_observed_demand: true,
+            season_months: SEASON_MONTHS,
+            regional_disruptions: Vec::new(),
+            capacity_policy: CapacityPolicy::Clamp,
+            expansion_offers: Vec::new(),
+            opening_inventory: Vec::new(),
+            onboarded_suppliers: Vec::new(),
+            carry_over_stock: false,
+            time_step: TimeStep::Monthly,
+            cost_of_capital_annual: 0.0,
+        };
+        let supplier = |id: u32, lead_time_months: usize, unit_cost: f64| Supplier {
+            id: SupplierId(id),
+            name: format!("Supplier {}", id),
+            fixed_capacity: 20_000,
+            product_capacity: None,
+            capacity_groups: Vec::new(),
+            soft_capacity_tiers: Vec::new(),
+            lead_time_months,
+            ramp_up_months: Vec::new(),
+            unit_costs: HashMap::from([(ProductId(0), unit_cost)]),
+            setup_cost: 0.0,
+            reservation_fee: None,
+            monthly_flex: None,
+            disrupted_months: Vec::new(),
+            region: None,
+            risk_class: None,
+        };
+        let pair = SupplierPair {
+            base_supplier: supplier(0, 3, 80.0),
+            surge_supplier: supplier(1, 0, 85.0),
+            backup_supplier: None,
+            pooled_suppliers: Vec::new(),
+        };
+        let order = MonthlyOrder::new(
+            vec![ProductOrder { product_id: ProductId(0), quantity: 10_000 }],
+            vec![ProductOrder { product_id: ProductId(0), quantity: 0 }],
+        ).unwrap();
+        (params, pair, order)
+    }
+
+    /// Adds a second product with an exact, tight forecast whose order already sits on plan
+    fn one_product_off_plan_scenario() -> (SimulationParams, SupplierPair, MonthlyOrder) {
+        let (mut params, mut pair, mut order) = collapsing_demand_scenario(1.0);
+        params.products.push(Product {
+            id: ProductId(1),
+            name: "Gadget".to_string(),
+            ..params.products[0].clone()
         });
-        surge_orders.push(ProductOrder {
-            product_id: *product_id,
-            quantity: surge_quantity,
+        params.demand_params.push(ProductDemandParams {
+            product_id: ProductId(1),
+            mean_demand: 10_000.0,
+            std_dev_demand: 200.0,
+            actual_mean_demand: 10_000.0,
+            actual_std_dev_demand: 200.0,
         });
+        params.order_change_fees = vec![10_000.0; SEASON_MONTHS];
+        params.order_change_fee_per_product = 40_000.0;
+        for supplier in [&mut pair.base_supplier, &mut pair.surge_supplier] {
+            let unit_cost = supplier.unit_costs[&ProductId(0)];
+            supplier.unit_costs.insert(ProductId(1), unit_cost);
+            supplier.fixed_capacity *= 2;
+        }
+        order.set_base_quantity(ProductId(1), 10_000);
+        order.set_surge_quantity(ProductId(1), 0);
+        (params, pair, order)
+    }
+
+    fn mean_profit_with_seeds(params: &SimulationParams, pair: &SupplierPair, order: &MonthlyOrder, runs: u64) -> f64 {
+        let total: f64 = (0..runs)
+            .map(|seed| {
+                seed_demand_rng(seed);
+                run_monthly_simulation(params, pair, order).unwrap().1
+            })
+            .sum();
+        total / runs as f64
+    }
+
+    #[test]
+    fn test_collapsing_demand_exercises_downward_option() {
+        let (params, pair, order) = collapsing_demand_scenario(1.0);
+        seed_demand_rng(7);
+        let (monthly_results, _) = run_monthly_simulation(&params, &pair, &order).unwrap();
+
+        assert!(monthly_results.iter()
+            .any(|m| m.exercised_changes.contains(&(ProductId(0), ExerciseDirection::Decrease))));
+    }
+
+    #[test]
+    fn test_only_off_plan_product_is_changed() {
+        let (params, pair, order) = one_product_off_plan_scenario();
+        let mut changed_products = Vec::new();
+
+        for seed in 0..10 {
+            seed_demand_rng(seed);
+            let (monthly_results, _) = run_monthly_simulation(&params, &pair, &order).unwrap();
+            for month in &monthly_results {
+                changed_products.extend(month.exercised_changes.iter().map(|(product_id, _)| *product_id));
+            }
+        }
+
+        assert!(!changed_products.is_empty());
+        assert!(changed_products.iter().all(|&product_id| product_id == ProductId(0)));
+    }
+
+    #[test]
+    fn test_single_source_never_pays_for_an_unchanged_order() {
+        // Demand far above the plan tempts an increase, which one supplier without surge
+        // capacity cannot make; demand far below it still earns a cut
+        let (mut params, pair, order) = collapsing_demand_scenario(1.0);
+        let single = crate::pairing::generate_single_supplier_strategies(std::slice::from_ref(&pair.base_supplier), &Default::default()).remove(0);
+        assert!(single.is_single_source());
+        for actual_mean_demand in [16_000.0, 4_000.0] {
+            params.demand_params[0].actual_mean_demand = actual_mean_demand;
+            let mut paid = 0;
+            for seed in 0..10 {
+                seed_demand_rng(seed);
+                let (monthly_results, _) = run_monthly_simulation(&params, &single, &order).unwrap();
+                let deliveries: Vec<u32> = monthly_results.iter().map(|month| month.product_results[0].base_incoming).collect();
+                for (month_idx, month) in monthly_results.iter().enumerate() {
+                    assert!(!month.exercised_changes.iter().any(|&(_, direction)| direction == ExerciseDirection::Increase));
+                    if month.order_change_cost > 0.0 {
+                        // The fee falls due with the change, whose deliveries differ from the month before
+                        assert!(month_idx > 0 && deliveries[month_idx] != deliveries[month_idx - 1], "seed {} month {}: {:?}", seed, month_idx, deliveries);
+                        paid += 1;
+                    }
+                }
+            }
+            assert_eq!(paid > 0, actual_mean_demand < 10_000.0, "{} changes paid for at {}", paid, actual_mean_demand);
+        }
+    }
+
+    #[test]
+    fn test_downward_option_improves_mean_profit() {
+        // A prohibitive cancellation fee reproduces the upward-only behavior
+        let (upward_only, pair, order) = collapsing_demand_scenario(1e9);
+        let (with_cancellation, _, _) = collapsing_demand_scenario(1.0);
+
+        let baseline = mean_profit_with_seeds(&upward_only, &pair, &order, 20);
+        let improved = mean_profit_with_seeds(&with_cancellation, &pair, &order, 20);
+
+        assert!(improved > baseline, "cancellation {:.0} should beat upward-only {:.0}", improved, baseline);
+    }
+
+    #[test]
+    fn test_exercise_frequency_by_month() {
+        // The forecast miss shows up in May's sales, and a single right is all we get
+        let (mut params, mut pair, order) = collapsing_demand_scenario(1.0);
+        params.order_change_fees = vec![50_000.0];
+        pair.surge_supplier.lead_time_months = 2;
+
+        let config = MonteCarloConfig { master_seed: 0, ..MonteCarloConfig::new(50) };
+        let stats = run_monte_carlo_simulation(&params, &pair, &order, &config).unwrap();
+        let frequencies: Vec<f64> = stats.monthly_option_summary.iter().map(|m| m.exercise_frequency).collect();
+        assert_eq!(frequencies.len(), SEASON_MONTHS);
+
+        // November and December changes could not land before the season ends
+        assert_eq!(&frequencies[SEASON_MONTHS - 2..], &[0.0, 0.0]);
+        let peak_month = (0..SEASON_MONTHS).max_by(|&a, &b| frequencies[a].total_cmp(&frequencies[b])).unwrap();
+        assert_eq!(peak_month, 0, "exercise frequencies {:?}", frequencies);
+        assert!(stats.monthly_option_summary[0].mean_option_value > 50_000.0);
+    }
+
+    #[test]
+    fn test_flexing_reduces_stockouts_and_leftovers() {
+        // Forecast is right but noisy, and the plan sits just under it, split evenly across suppliers
+        let (mut params, mut rigid_pair, mut order) = collapsing_demand_scenario(1.0);
+        params.demand_params[0].std_dev_demand = 2_000.0;
+        params.demand_params[0].actual_mean_demand = 10_000.0;
+        params.demand_params[0].actual_std_dev_demand = 2_000.0;
+        rigid_pair.surge_supplier.unit_costs.insert(ProductId(0), 80.0);
+        order.set_base_quantity(ProductId(0), 4_800);
+        order.set_surge_quantity(ProductId(0), 4_800);
+        let mut flexible_pair = rigid_pair.clone();
+        flexible_pair.surge_supplier.monthly_flex = Some(Flex { max_fraction: 0.10, fee_per_unit: 1.0 });
+
+        // Total unmet demand across the season and units left over for liquidation in December
+        let shortfall_and_leftovers = |pair: &SupplierPair| {
+            (0..50).fold((0, 0), |(stockouts, leftovers), seed| {
+                seed_demand_rng(seed);
+                let (monthly_results, _) = run_monthly_simulation_internal(&params, pair, &order, false, true, DemandSource::Stochastic(None)).unwrap();
+                let unmet: u32 = monthly_results.iter()
+                    .flat_map(|m| &m.product_results)
+                    .map(|p| p.demand - p.units_sold)
+                    .sum();
+                let december = &monthly_results[SEASON_MONTHS - 1].product_results[0];
+                let liquidated = december.inventory_start + december.incoming - december.units_sold;
+                (stockouts + unmet, leftovers + liquidated)
+            })
+        };
+        let (rigid_stockouts, rigid_leftovers) = shortfall_and_leftovers(&rigid_pair);
+        let (flex_stockouts, flex_leftovers) = shortfall_and_leftovers(&flexible_pair);
+
+        assert!(flex_stockouts < rigid_stockouts, "stockouts {} vs rigid {}", flex_stockouts, rigid_stockouts);
+        assert!(flex_leftovers < rigid_leftovers, "leftovers {} vs rigid {}", flex_leftovers, rigid_leftovers);
+    }
+
+    #[test]
+    fn test_base_supplier_flexes_down_only_its_own_units_beside_a_pool() {
+        // The base supplier makes 2k of the 10k base order and a pooled supplier the other 8k;
+        // demand collapses, so the base supplier flexes down as far as its contract allows
+        let (params, mut pair, order) = collapsing_demand_scenario(1.0);
+        pair.base_supplier.fixed_capacity = 2_000;
+        pair.base_supplier.monthly_flex = Some(Flex { max_fraction: 0.5, fee_per_unit: 1.0 });
+        pair.pooled_suppliers.push(Supplier { id: SupplierId(2), name: "Supplier 2".to_string(), ..pair.surge_supplier.clone() });
+        pair.pooled_suppliers[0].lead_time_months = pair.base_supplier.lead_time_months;
+
+        seed_demand_rng(3);
+        let (monthly_results, _) = run_monthly_simulation_internal(&params, &pair, &order, false, true, DemandSource::Stochastic(None)).unwrap();
+        let flexed: Vec<i64> = monthly_results.iter().flat_map(|m| &m.flex_adjustments).map(|&(_, units)| units).collect();
+        assert!(flexed.contains(&-1_000), "{:?}", flexed);
+        for month in &monthly_results {
+            // Half the base supplier's own 2k, never half the pool's 10k
+            assert!(month.flex_adjustments.iter().all(|&(_, units)| units >= -1_000));
+            let result = &month.product_results[0];
+            assert!(result.base_incoming >= 9_000, "{}: {}", month.month, result.base_incoming);
+        }
     }
 
-    MonthlyOrder {
-        base_orders,
-        surge_orders,
+    #[test]
+    fn test_options_evaluated_while_a_change_can_land() {
+        // A prohibitive fee keeps the option unexercised, so it is evaluated whenever allowed
+        for (season_months, surge_lead_time, last_evaluable_month) in [(12, 0, 10), (4, 2, 1)] {
+            let (mut params, mut pair, order) = collapsing_demand_scenario(1.0);
+            params.season_months = season_months;
+            params.order_change_fees = vec![1e12];
+            pair.surge_supplier.lead_time_months = surge_lead_time;
+
+            seed_demand_rng(0);
+            let (monthly_results, _) = run_monthly_simulation(&params, &pair, &order).unwrap();
+            assert_eq!(monthly_results.len(), season_months);
+            for (month_idx, month) in monthly_results.iter().enumerate() {
+                assert_eq!(month.option_value.is_some(), month_idx <= last_evaluable_month, "{} of {}", month.month, season_months);
+            }
+
+            // Leftover stock is liquidated at the end of the configured season
+            let last_month = monthly_results.last().unwrap();
+            assert_eq!(last_month.month, month_name(season_months - 1));
+            assert!(last_month.product_results.iter().all(|p| p.inventory_end == 0));
+        }
+    }
+
+    #[test]
+    fn test_mean_demand_projection_carries_stock_forward() {
+        let (params, pair, order) = collapsing_demand_scenario(1.0);
+        let projection = project_mean_demand(&params, &pair, &order).unwrap();
+
+        // 10,000 units arrive each month against demand of 4,000, so 6,000 more are left over
+        // every month until the leftovers are liquidated at the end of the season
+        let ends: Vec<u32> = projection.iter().map(|month| month.product_results[0].inventory_end).collect();
+        assert_eq!(ends, vec![6_000, 12_000, 18_000, 24_000, 30_000, 36_000, 42_000, 0]);
+        for month in &projection {
+            let result = &month.product_results[0];
+            assert_eq!((result.incoming, result.demand, result.units_sold), (10_000, 4_000, 4_000));
+            assert!(!month.option_exercised);
+        }
+        assert_eq!(projection[7].product_results[0].liquidation_revenue, 48_000.0 * 60.0);
+    }
+
+    #[test]
+    fn test_backup_absorbs_a_disrupted_surge_supplier() {
+        let (mut params, mut pair, _) = collapsing_demand_scenario(1.0);
+        params.demand_params[0].actual_mean_demand = 10_000.0;
+        params.demand_params[0].actual_std_dev_demand = 1_000.0;
+        params.order_change_fees = Vec::new();
+        pair.surge_supplier.disrupted_months = vec![2, 3, 4, 5, 6];
+        let order = MonthlyOrder::new(
+            vec![ProductOrder { product_id: ProductId(0), quantity: 6_000 }],
+            vec![ProductOrder { product_id: ProductId(0), quantity: 4_000 }],
+        ).unwrap();
+        let triple = SupplierPair {
+            backup_supplier: Some(Supplier {
+                id: SupplierId(2),
+                name: "Supplier 2".to_string(),
+                unit_costs: HashMap::from([(ProductId(0), 90.0)]),
+                setup_cost: 20_000.0,
+                disrupted_months: Vec::new(),
+                region: None,
+                risk_class: None,
+                ..pair.surge_supplier.clone()
+            }),
+            ..pair.clone()
+        };
+
+        seed_demand_rng(3);
+        let (monthly_results, _) = run_monthly_simulation(&params, &triple, &order).unwrap();
+        for (month_idx, month) in monthly_results.iter().enumerate() {
+            let result = &month.product_results[0];
+            let disrupted = pair.surge_supplier.is_disrupted(month_idx);
+            assert_eq!((result.surge_incoming, result.backup_incoming), if disrupted { (0, 4_000) } else { (4_000, 0) }, "{}", month.month);
+            assert_eq!(result.incoming, 10_000);
+        }
+        // Its setup cost is paid once, in the first month it delivers
+        assert_eq!(monthly_results[2].setup_cost, 20_000.0);
+        assert_eq!(monthly_results.iter().map(|month| month.setup_cost).sum::<f64>(), 20_000.0);
+
+        let bare = mean_profit_with_seeds(&params, &pair, &order, 20);
+        let backed_up = mean_profit_with_seeds(&params, &triple, &order, 20);
+        assert!(backed_up > bare + 100_000.0, "triple {} vs pair {}", backed_up, bare);
+
+        let stats = run_monte_carlo_simulation(&params, &triple, &order, &MonteCarloConfig::new(20)).unwrap();
+        assert_eq!(stats.pair_name(), "Supplier 0 + Supplier 1 + Supplier 2");
+        // 4,000 of the backup's 20,000 units a month, in five months of eight
+        assert!((stats.mean_backup_utilization - 0.2 * 5.0 / 8.0).abs() < 1e-12, "{}", stats.mean_backup_utilization);
+    }
+
+    #[test]
+    fn test_split_capacity_caps_both_roles_and_pays_setup_once() {
+        let (mut params, pair, _) = collapsing_demand_scenario(1.0);
+        params.order_change_fees = Vec::new();
+        let supplier = Supplier { fixed_capacity: 10_000, setup_cost: 30_000.0, ..pair.surge_supplier.clone() };
+        let split = crate::pairing::generate_split_capacity_pairs(&[supplier], &Default::default()).remove(0);
+        assert!(split.shares_capacity() && !split.is_single_source());
+        assert_eq!((split.total_capacity(), split.surge_capacity_beside(7_000)), (10_000, 3_000));
+
+        // Planning never asks the one supplier for more than it has
+        let order = split_order_quantities(&[(ProductId(0), 10_500)], &split, &params);
+        assert_eq!(order.total_base_quantity() + order.total_surge_quantity(), 10_000);
+        assert!(order.total_surge_quantity() > 0);
+        assert!(crate::capacity::validate_capacity_constraint(&order, &split).is_ok());
+
+        // An order over the shared capacity fails validation and is cut back in its surge tranche
+        let over = MonthlyOrder::new(
+            vec![ProductOrder { product_id: ProductId(0), quantity: 7_000 }],
+            vec![ProductOrder { product_id: ProductId(0), quantity: 5_000 }],
+        ).unwrap();
+        assert!(crate::capacity::validate_capacity_constraint(&over, &split).is_err());
+        seed_demand_rng(5);
+        let (monthly_results, _) = run_monthly_simulation(&params, &split, &over).unwrap();
+        for month in &monthly_results {
+            let result = &month.product_results[0];
+            assert_eq!((result.base_incoming, result.surge_incoming), (7_000, 3_000), "{}", month.month);
+        }
+        assert_eq!(monthly_results.iter().map(|month| month.setup_cost).sum::<f64>(), 30_000.0);
+    }
+
+    #[test]
+    fn test_base_first_split_sends_the_surge_supplier_only_what_the_base_cannot_make() {
+        // The base supplier makes 7,000 a month of the two products
+        let (params, pair) = crate::monte_carlo::tests::two_product_scenario();
+        let within = split_base_first(&[(ProductId(0), 4_000), (ProductId(1), 2_000)], &pair, &params);
+        assert_eq!((within.total_base_quantity(), within.total_surge_quantity()), (6_000, 0));
+        assert!(split_order_quantities(&[(ProductId(0), 4_000), (ProductId(1), 2_000)], &pair, &params).total_surge_quantity() > 0);
+
+        let beyond = split_base_first(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        assert_eq!((beyond.total_base_quantity(), beyond.total_surge_quantity()), (7_000, 2_000));
+    }
+
+    #[test]
+    fn test_ledger_clips_an_exercise_beyond_the_surge_capacity_left() {
+        // Half the first product's forecast is on order, and the second product's surge order
+        // already takes a third of the surge supplier's small capacity
+        let (mut params, mut pair, mut order) = one_product_off_plan_scenario();
+        params.demand_params[0].actual_mean_demand = 10_000.0;
+        params.demand_params[0].actual_std_dev_demand = 1_000.0;
+        pair.surge_supplier.fixed_capacity = 3_000;
+        order.set_base_quantity(ProductId(0), 5_000);
+        order.set_base_quantity(ProductId(1), 9_000);
+        order.set_surge_quantity(ProductId(1), 1_000);
+
+        seed_demand_rng(11);
+        let (monthly_results, _, ledger) = run_monthly_simulation_with_ledger(&params, &pair, &order, true, false, DemandSource::Stochastic(None)).unwrap();
+        let exercised = monthly_results.iter()
+            .position(|m| m.exercised_changes == [(ProductId(0), ExerciseDirection::Increase)])
+            .expect("an upward exercise");
+
+        // The re-plan asked the surge supplier for more than it had left beside the second
+        // product, and the first product got only the rest
+        let surge_id = pair.surge_supplier.id;
+        let shortfall = ledger.shortfalls().iter().find(|s| s.supplier_id == surge_id).expect("a surge shortfall");
+        assert!(shortfall.month > exercised && shortfall.requested > 3_000 && shortfall.reserved == 3_000, "{:?}", shortfall);
+        for (month_idx, month) in monthly_results.iter().enumerate() {
+            assert_eq!(ledger.committed(surge_id, month_idx), if month_idx > exercised { 3_000 } else { 1_000 });
+            let surge_incoming: Vec<u32> = month.product_results.iter().map(|p| p.surge_incoming).collect();
+            assert_eq!(surge_incoming, if month_idx > exercised { [2_000, 1_000] } else { [0, 1_000] }, "{}", month.month);
+        }
+    }
+
+    #[test]
+    fn test_deliveries_keep_within_capacity_groups() {
+        use crate::capacity::create_order;
+        use crate::models::CapacityGroup;
+
+        // 6,000 units a month asked of a base supplier whose cutting line makes 4,500 of both
+        // models; the Widget's higher margin fills the line first
+        let (mut params, mut pair) = crate::monte_carlo::tests::two_product_scenario();
+        params.order_change_fees.clear();
+        params.products[0].selling_price = 120.0;
+        pair.base_supplier.capacity_groups = vec![CapacityGroup { name: "Cutting line".to_string(), product_ids: vec![ProductId(0), ProductId(1)], limit: 4_500 }];
+        let order = create_order(vec![(ProductId(0), 4_000), (ProductId(1), 2_000)], vec![(ProductId(0), 0), (ProductId(1), 1_000)]);
+
+        seed_demand_rng(12);
+        let (monthly_results, _) = run_monthly_simulation(&params, &pair, &order).unwrap();
+        for month in &monthly_results {
+            let base: Vec<u32> = month.product_results.iter().map(|p| p.base_incoming).collect();
+            let surge: Vec<u32> = month.product_results.iter().map(|p| p.surge_incoming).collect();
+            assert_eq!((base, surge), (vec![4_000, 500], vec![0, 1_000]), "{}", month.month);
+        }
+    }
+
+    #[test]
+    fn test_ramp_up_scales_capacity_from_the_first_delivery() {
+        use crate::capacity::create_order;
+        use crate::models::ReservationProfile;
+
+        // Both suppliers ramp 40% -> 70% -> 100% from their first order; the base supplier's
+        // three-month lead time has it ramped by May, and the surge supplier is reserved at 4,000
+        // a month
+        let (mut params, mut pair) = crate::monte_carlo::tests::two_product_scenario();
+        params.order_change_fees.clear();
+        pair.base_supplier.ramp_up_months = vec![0.4, 0.7, 1.0];
+        pair.surge_supplier.ramp_up_months = vec![0.4, 0.7, 1.0];
+        let mut order = create_order(vec![(ProductId(0), 4_000), (ProductId(1), 2_000)], vec![(ProductId(0), 2_000), (ProductId(1), 1_000)]);
+        order.reservations = vec![Reservation::with_profile(&pair.surge_supplier, ReservationProfile::Flat, 0.8, SEASON_MONTHS)];
+
+        seed_demand_rng(13);
+        let (monthly_results, _, ledger) = run_monthly_simulation_with_ledger(&params, &pair, &order, false, false, DemandSource::Stochastic(None)).unwrap();
+        let capacities = |supplier_id: u32| (0..SEASON_MONTHS).map(|month| ledger.capacity(SupplierId(supplier_id), month)).collect::<Vec<u32>>();
+        assert_eq!(capacities(0), [7_000; SEASON_MONTHS]);
+        assert_eq!(capacities(1), [1_600, 2_800, 4_000, 4_000, 4_000, 4_000, 4_000, 4_000]);
+        let surge_delivered: Vec<u32> = monthly_results.iter().map(|month| month.product_results.iter().map(|p| p.surge_incoming).sum()).collect();
+        assert_eq!(surge_delivered[..3], [1_600, 2_800, 3_000]);
+
+        // A supplier first ordered from a month before it delivers starts a month up its ramp
+        pair.surge_supplier.lead_time_months = 1;
+        let (_, _, ledger) = run_monthly_simulation_with_ledger(&params, &pair, &order, false, false, DemandSource::Stochastic(None)).unwrap();
+        assert_eq!(ledger.capacity(SupplierId(1), 0), 2_800);
+    }
+
+    #[test]
+    fn test_a_ramping_surge_supplier_commits_more_to_base() {
+        // The surge supplier makes a fifth of its 5,000 in its first month, so the 1,500 a month
+        // planned of it shrink to 1,000 and the rest is committed to the base supplier
+        let (params, mut pair) = crate::monte_carlo::tests::two_product_scenario();
+        pair.base_supplier.fixed_capacity = 10_000;
+        let steady = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        assert_eq!((steady.total_base_quantity(), steady.total_surge_quantity()), (7_500, 1_500));
+
+        pair.surge_supplier.ramp_up_months = vec![0.2, 0.6, 1.0];
+        let ramping = split_order_quantities(&[(ProductId(0), 6_000), (ProductId(1), 3_000)], &pair, &params);
+        assert_eq!((ramping.total_base_quantity(), ramping.total_surge_quantity()), (8_000, 1_000));
+        for (product_id, quantity) in [(0, 6_000), (1, 3_000)] {
+            assert_eq!(ramping.base_quantity_for(ProductId(product_id)) + ramping.surge_quantity_for(ProductId(product_id)), quantity);
+        }
+    }
+
+    #[test]
+    fn test_capacity_policies_on_a_plan_beyond_the_base_supplier() {
+        use crate::capacity::create_order;
+
+        // 8,000 units a month asked of a base supplier that makes 7,000
+        let (mut params, pair) = crate::monte_carlo::tests::two_product_scenario();
+        let order = create_order(vec![(ProductId(0), 5_000), (ProductId(1), 3_000)], vec![(ProductId(0), 0), (ProductId(1), 0)]);
+
+        seed_demand_rng(12);
+        let (monthly_results, _, ledger) = run_monthly_simulation_with_ledger(&params, &pair, &order, false, true, DemandSource::Stochastic(None)).unwrap();
+        let delivered: u32 = monthly_results[0].product_results.iter().map(|p| p.base_incoming).sum();
+        assert_eq!(delivered, 7_000);
+        assert_eq!(ledger.shortfalls().len(), params.season_months);
+
+        params.capacity_policy = CapacityPolicy::Strict;
+        let Err(SimulationError::Capacity(error)) = run_monthly_simulation(&params, &pair, &order) else {
+            panic!("the plan is refused for its capacity");
+        };
+        assert_eq!((error.month, error.requested, error.capacity), (Some(0), 8_000, 7_000));
+        assert_eq!(error.to_string(), "Capacity exceeded: Supplier 0 asked for 8000 units in May with 7000 available");
+        assert_eq!(check_capacity(&params, &pair, &order).unwrap_err().to_string(), error.to_string());
+
+        // Within capacity the policies agree
+        let order = create_order(vec![(ProductId(0), 5_000), (ProductId(1), 2_000)], vec![(ProductId(0), 0), (ProductId(1), 1_000)]);
+        assert!(check_capacity(&params, &pair, &order).is_ok());
+        seed_demand_rng(12);
+        let strict = run_monthly_simulation(&params, &pair, &order).unwrap().1;
+        params.capacity_policy = CapacityPolicy::Clamp;
+        seed_demand_rng(12);
+        assert_eq!(run_monthly_simulation(&params, &pair, &order).unwrap().1, strict);
+    }
+
+    #[test]
+    fn test_an_unused_reservation_is_pure_cost() {
+        use crate::capacity::create_order;
+        use crate::models::ReservationProfile;
+
+        // Steady demand the base supplier covers alone, with half the surge supplier's capacity
+        // reserved at 3 a unit and never ordered
+        let (mut params, mut pair) = crate::monte_carlo::tests::two_product_scenario();
+        for dp in &mut params.demand_params {
+            dp.std_dev_demand = 0.0;
+            dp.actual_std_dev_demand = 0.0;
+        }
+        params.order_change_fees.clear();
+        pair.surge_supplier.reservation_fee = Some(3.0);
+        let order = create_order(vec![(ProductId(0), 4_000), (ProductId(1), 3_000)], vec![(ProductId(0), 0), (ProductId(1), 0)]);
+        let reservation = Reservation::with_profile(&pair.surge_supplier, ReservationProfile::Flat, 0.5, params.season_months);
+        let reserved = order.clone().with_reservations(vec![reservation.clone()]);
+
+        let (_, unreserved_profit) = run_monthly_simulation(&params, &pair, &order).unwrap();
+        let (monthly_results, reserved_profit) = run_monthly_simulation(&params, &pair, &reserved).unwrap();
+        assert_eq!(reservation.cost(), 60_000.0);
+        assert_eq!(unreserved_profit - reserved_profit, reservation.cost());
+        assert_eq!(monthly_results[0].reservation_cost, reservation.cost());
+        for month in &monthly_results {
+            assert_eq!((month.reserved_units, month.reserved_units_used), (2_500, 0), "{}", month.month);
+        }
+    }
+
+    #[test]
+    fn test_deliveries_beyond_nominal_capacity_pay_the_overtime_premium() {
+        use crate::capacity::create_order;
+
+        // 12,000 a month asked of a base supplier making 10,000 at its unit cost of 75, 1,000
+        // more at +10% and 1,500 beyond those at +25%
+        let (params, mut pair) = crate::monte_carlo::tests::two_product_scenario();
+        pair.base_supplier.fixed_capacity = 12_500;
+        let order = create_order(vec![(ProductId(0), 8_000), (ProductId(1), 4_000)], vec![(ProductId(0), 0), (ProductId(1), 0)]);
+        seed_demand_rng(13);
+        let (flat, _) = run_monthly_simulation_internal(&params, &pair, &order, false, true, DemandSource::Stochastic(None)).unwrap();
+
+        pair.base_supplier.fixed_capacity = 10_000;
+        pair.base_supplier.soft_capacity_tiers = vec![(0.10, 1.10), (0.25, 1.25)];
+        assert!(check_capacity(&params, &pair, &order).is_ok());
+        seed_demand_rng(13);
+        let (tiered, _) = run_monthly_simulation_internal(&params, &pair, &order, false, true, DemandSource::Stochastic(None)).unwrap();
+        let production_cost = |month: &MonthlyResult| month.product_results.iter().map(|p| p.production_cost).sum::<f64>();
+        let mut overtime_months = 0;
+        for (flat, tiered) in flat.iter().zip(&tiered) {
+            // The same deliveries as a supplier making 12,500 at its unit cost, dearer by the
+            // premium on the 2,000 units past 10,000
+            let delivered: u32 = tiered.product_results.iter().map(|p| p.base_incoming).sum();
+            assert!(delivered == 0 || delivered == 12_000, "{}", tiered.month);
+            let premium = if delivered > 0 { 75.0 * (1_000.0 * 0.10 + 1_000.0 * 0.25) } else { 0.0 };
+            overtime_months += usize::from(delivered > 0);
+            assert!((production_cost(tiered) - production_cost(flat) - premium).abs() < 1e-6, "{}", tiered.month);
+            assert!((flat.monthly_profit - tiered.monthly_profit - premium).abs() < 1e-6, "{}", tiered.month);
+        }
+        assert!(overtime_months > 0);
+
+        // Nothing past the last tier
+        let over = create_order(vec![(ProductId(0), 8_000), (ProductId(1), 4_501)], vec![(ProductId(0), 0), (ProductId(1), 0)]);
+        assert_eq!(check_capacity(&params, &pair, &over).unwrap_err().capacity, 12_500);
+    }
+
+    #[test]
+    fn test_short_capacity_goes_to_the_higher_margin_product() {
+        // The surge supplier makes the second product $10 cheaper but has room for 3,000 of the
+        // 4,000 units asked of it
+        let (params, mut pair, mut order) = one_product_off_plan_scenario();
+        pair.surge_supplier.fixed_capacity = 3_000;
+        pair.surge_supplier.unit_costs.insert(ProductId(1), 75.0);
+        order.set_surge_quantity(ProductId(0), 2_000);
+        order.set_surge_quantity(ProductId(1), 2_000);
+
+        seed_demand_rng(2);
+        let (monthly_results, _) = run_monthly_simulation_internal(&params, &pair, &order, false, true, DemandSource::Stochastic(None)).unwrap();
+        for month in &monthly_results {
+            let surge_incoming: Vec<u32> = month.product_results.iter().map(|p| p.surge_incoming).collect();
+            assert_eq!(surge_incoming, vec![1_000, 2_000], "{}", month.month);
+        }
+
+        // Planning fills the cheaper product's surge share first too: 197 units of the second
+        // product's forecast sit above its base share, and 910 of the first's
+        pair.surge_supplier.fixed_capacity = 500;
+        let split = split_order_quantities(&[(ProductId(0), 10_000), (ProductId(1), 10_000)], &pair, &params);
+        assert_eq!((split.base_quantity_for(ProductId(0)), split.base_quantity_for(ProductId(1))), (9_090, 9_803));
+        assert_eq!((split.surge_quantity_for(ProductId(0)), split.surge_quantity_for(ProductId(1))), (303, 197));
+    }
+
+    /// A three month season of one product selling at $100, held at $2 a unit a month and
+    /// liquidated at $40, from a base supplier at $60 with a $1,000 setup and a surge supplier
+    /// at $70 with a $500 setup, both with capacity to spare, delivering `base` and `surge` units
+    /// a month; demand comes from a script, so every figure can be worked out by hand
+    fn accounting_scenario(base: u32, surge: u32) -> (SimulationParams, SupplierPair, MonthlyOrder) {
+        let (mut params, mut pair, _) = collapsing_demand_scenario(0.0);
+        params.products[0] = Product { selling_price: 100.0, liquidation_price: 40.0, monthly_holding_cost: 2.0, ..params.products[0].clone() };
+        params.season_months = 3;
+        params.order_change_fees = Vec::new();
+        pair.base_supplier.unit_costs.insert(ProductId(0), 60.0);
+        pair.base_supplier.setup_cost = 1_000.0;
+        pair.surge_supplier.unit_costs.insert(ProductId(0), 70.0);
+        pair.surge_supplier.setup_cost = 500.0;
+        let order = create_order(vec![(ProductId(0), base)], vec![(ProductId(0), surge)]);
+        (params, pair, order)
+    }
+
+    /// A month's units sold, ending inventory, holding cost, liquidation revenue, setup cost and
+    /// profit
+    type MonthFigures = (u32, u32, f64, f64, f64, f64);
+
+    /// Each month's figures for the scenario's product on `demand`, and the season's profit
+    fn scripted_season(base: u32, surge: u32, demand: &[u32]) -> (Vec<MonthFigures>, f64) {
+        let (params, pair, order) = accounting_scenario(base, surge);
+        let script: Vec<HashMap<ProductId, u32>> = demand.iter().map(|&units| HashMap::from([(ProductId(0), units)])).collect();
+        let (monthly_results, total_profit) = run_monthly_simulation_internal(&params, &pair, &order, false, true, DemandSource::Scripted(&script)).unwrap();
+        for (month, &demand) in monthly_results.iter().zip(demand) {
+            let product = &month.product_results[0];
+            assert_eq!(product.demand, demand);
+            assert_eq!(product.revenue, product.units_sold as f64 * 100.0);
+            assert_eq!(product.production_cost, base as f64 * 60.0 + surge as f64 * 70.0);
+            assert_eq!((month.order_change_cost, month.flex_cost, month.reservation_cost, month.expansion_cost), (0.0, 0.0, 0.0, 0.0));
+        }
+        assert_eq!(total_profit, monthly_results.iter().map(|month| month.monthly_profit).sum::<f64>());
+        let months = monthly_results.iter()
+            .map(|month| {
+                let product = &month.product_results[0];
+                (product.units_sold, product.inventory_end, product.holding_cost, product.liquidation_revenue, month.setup_cost, month.monthly_profit)
+            })
+            .collect();
+        (months, total_profit)
+    }
+
+    #[test]
+    fn test_deliveries_matching_demand_leave_nothing_to_hold_or_liquidate() {
+        // $100,000 of sales a month on $60,000 of production, less the base setup in May only
+        let (months, total_profit) = scripted_season(1_000, 0, &[1_000; 3]);
+        assert_eq!(months, [
+            (1_000, 0, 0.0, 0.0, 1_000.0, 39_000.0),
+            (1_000, 0, 0.0, 0.0, 0.0, 40_000.0),
+            (1_000, 0, 0.0, 0.0, 0.0, 40_000.0),
+        ]);
+        assert_eq!(total_profit, 119_000.0);
+    }
+
+    #[test]
+    fn test_over_ordering_holds_the_excess_and_liquidates_it_at_the_end() {
+        // 200 units a month pile up, held at $2 each month including the last, where the 600
+        // left are liquidated at $40
+        let (months, total_profit) = scripted_season(1_200, 0, &[1_000; 3]);
+        assert_eq!(months, [
+            (1_000, 200, 400.0, 0.0, 1_000.0, 26_600.0),
+            (1_000, 400, 800.0, 0.0, 0.0, 27_200.0),
+            (1_000, 0, 1_200.0, 24_000.0, 0.0, 50_800.0),
+        ]);
+        assert_eq!(total_profit, 104_600.0);
+    }
+
+    #[test]
+    fn test_financing_charges_the_stock_at_its_weighted_average_cost() {
+        // 900 base units at $60 and 300 surge units at $70 a month average $62.50 a unit, and the
+        // 600 units in stock at the start count at the base supplier's $60
+        assert_eq!(weighted_average_cost(0, 60.0, 1_200, 75_000.0), 62.5);
+        assert_eq!(weighted_average_cost(600, 60.0, 1_200, 75_000.0), 111_000.0 / 1_800.0);
+        assert_eq!(weighted_average_cost(0, 60.0, 0, 0.0), 60.0);
+        // 12% a year is 1% a month of what the stock cost
+        assert_eq!(monthly_financing_cost_of(0.12, 800, 50.0), 400.0);
+        assert_eq!(monthly_financing_cost_of(0.0, 800, 50.0), 0.0);
+
+        let (mut params, pair, order) = accounting_scenario(900, 300);
+        params.opening_inventory = vec![(ProductId(0), 600)];
+        let script = vec![HashMap::from([(ProductId(0), 1_000)]); 3];
+        let run = |params: &SimulationParams| run_monthly_simulation_internal(params, &pair, &order, false, true, DemandSource::Scripted(&script)).unwrap();
+        let (unfinanced, unfinanced_profit) = run(&params);
+        assert!(unfinanced.iter().all(|month| month.product_results[0].financing_cost == 0.0));
+        params.cost_of_capital_annual = 0.12;
+        let (financed, financed_profit) = run(&params);
+
+        // 800 units left in May at (600 × $60 + $75,000) / 1,800, then 1,000 in June once
+        // another 1,200 join them, and 1,200 in July before they are liquidated
+        let may = 111_000.0 / 1_800.0;
+        let june = (800.0 * may + 75_000.0) / 2_000.0;
+        let july = (1_000.0 * june + 75_000.0) / 2_200.0;
+        let expected = [0.01 * 800.0 * may, 0.01 * 1_000.0 * june, 0.01 * 1_200.0 * july];
+        for (month, expected) in financed.iter().zip(expected) {
+            assert!((month.product_results[0].financing_cost - expected).abs() < 1e-9, "{} vs {}", month.product_results[0].financing_cost, expected);
+        }
+        assert!((unfinanced_profit - financed_profit - expected.iter().sum::<f64>()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_goodwill_penalty_escalates_with_consecutive_stockouts_and_resets_once_filled() {
+        let goodwill = GoodwillModel { per_consecutive_month: vec![100.0, 250.0, 600.0] };
+        // The last entry applies to every month of a longer run
+        assert_eq!([0, 1, 2, 3, 4, 9].map(|streak| goodwill.penalty(streak)), [0.0, 100.0, 250.0, 600.0, 600.0, 600.0]);
+        assert_eq!(GoodwillModel { per_consecutive_month: Vec::new() }.penalty(2), 0.0);
+
+        // 800 units a month fall short from May to July, fill August's 500 and September's 1,000
+        // from the stock August leaves, then fall short again in October
+        let (mut params, pair, order) = accounting_scenario(800, 0);
+        params.season_months = 6;
+        let script: Vec<HashMap<ProductId, u32>> = [1_000, 1_000, 1_000, 500, 1_000, 1_000].iter()
+            .map(|&units| HashMap::from([(ProductId(0), units)]))
+            .collect();
+        let run = |params: &SimulationParams| run_monthly_simulation_internal(params, &pair, &order, false, true, DemandSource::Scripted(&script)).unwrap();
+        let (_, forgiving_profit) = run(&params);
+        params.products[0].goodwill_penalty = Some(goodwill);
+        let (monthly_results, profit) = run(&params);
+
+        let penalties: Vec<f64> = monthly_results.iter().map(|month| month.product_results[0].goodwill_penalty).collect();
+        assert_eq!(penalties, [100.0, 250.0, 600.0, 0.0, 0.0, 100.0]);
+        assert_eq!(forgiving_profit - profit, 1_050.0);
+    }
+
+    #[test]
+    fn test_under_ordering_loses_the_sales_it_cannot_fill() {
+        // 200 units of demand a month go unmet, earning nothing
+        let (months, total_profit) = scripted_season(800, 0, &[1_000; 3]);
+        assert_eq!(months, [
+            (800, 0, 0.0, 0.0, 1_000.0, 31_000.0),
+            (800, 0, 0.0, 0.0, 0.0, 32_000.0),
+            (800, 0, 0.0, 0.0, 0.0, 32_000.0),
+        ]);
+        assert_eq!(total_profit, 95_000.0);
+    }
+
+    #[test]
+    fn test_each_supplier_delivering_pays_its_setup_once() {
+        // 1,000 units a month at $64,000 against demand of 900, 1,000 and 1,100: 100 units are
+        // carried from May to July and sold there
+        let (months, total_profit) = scripted_season(600, 400, &[900, 1_000, 1_100]);
+        assert_eq!(months, [
+            (900, 100, 200.0, 0.0, 1_500.0, 24_300.0),
+            (1_000, 100, 200.0, 0.0, 0.0, 35_800.0),
+            (1_100, 0, 0.0, 0.0, 0.0, 46_000.0),
+        ]);
+        assert_eq!(total_profit, 106_100.0);
+
+        // A supplier asked for nothing pays no setup
+        let (months, total_profit) = scripted_season(0, 1_000, &[900, 1_000, 1_100]);
+        assert_eq!(months.iter().map(|month| month.4).collect::<Vec<f64>>(), [500.0, 0.0, 0.0]);
+        assert_eq!(total_profit, 19_300.0 + 29_800.0 + 40_000.0);
+    }
+
+    #[test]
+    fn test_scripted_and_seeded_seasons_replay_exactly() {
+        let (params, pair, order) = one_product_off_plan_scenario();
+        let script: Vec<HashMap<ProductId, u32>> = (0..SEASON_MONTHS as u32)
+            .map(|month| HashMap::from([(ProductId(0), 4_000 + 100 * month), (ProductId(1), 10_000)]))
+            .collect();
+        let run = |demand: DemandSource| run_monthly_simulation_internal(&params, &pair, &order, true, true, demand).unwrap();
+
+        // The script alone sets demand, whatever the thread's generator is doing
+        seed_demand_rng(1);
+        let scripted = run(DemandSource::Scripted(&script));
+        seed_demand_rng(2);
+        assert_eq!(run(DemandSource::Scripted(&script)), scripted);
+        let demand: Vec<u32> = scripted.0.iter().map(|month| month.product_results[0].demand).collect();
+        assert_eq!(demand, [4_000, 4_100, 4_200, 4_300, 4_400, 4_500, 4_600, 4_700]);
+        // Forecast or actuals, a script is the same season
+        assert_eq!(run_monthly_simulation_internal(&params, &pair, &order, true, false, DemandSource::Scripted(&script)).unwrap(), scripted);
+
+        seed_demand_rng(5);
+        let drawn = run(DemandSource::Stochastic(None));
+        seed_demand_rng(5);
+        assert_eq!(run(DemandSource::Stochastic(None)), drawn);
+
+        // A generator of the caller's own gives the same season from the same seed, and leaves
+        // the thread's stream where it was
+        use rand::{rngs::SmallRng, SeedableRng};
+        use std::cell::RefCell;
+        let own = |seed: u64| run(DemandSource::Stochastic(Some(&RefCell::new(SmallRng::seed_from_u64(seed)))));
+        seed_demand_rng(5);
+        assert_eq!(own(9), own(9));
+        assert_ne!(own(9), own(10));
+        assert_eq!(run(DemandSource::Stochastic(None)), drawn);
+
+        let short = run_monthly_simulation_internal(&params, &pair, &order, true, true, DemandSource::Scripted(&script[1..]));
+        assert!(matches!(short, Err(SimulationError::ShortDemandScript { months: 7, season_months: SEASON_MONTHS })));
+    }
+
+    #[test]
+    fn test_weekly_season_keeps_the_season_total_demand_moments() {
+        let (mut params, pair, order) = collapsing_demand_scenario(1.0);
+        params.time_step = TimeStep::Weekly;
+        let (weekly, _, _) = in_weeks(&params, &pair, &order);
+        assert_eq!(weekly.season_months, 35);
+        let (monthly, weekly) = (&params.demand_params[0], &weekly.demand_params[0]);
+        let (months, weeks) = (SEASON_MONTHS as f64, 35.0);
+        assert!((weeks * weekly.actual_mean_demand - months * monthly.actual_mean_demand).abs() < 1e-6);
+        assert!((weeks * weekly.actual_std_dev_demand.powi(2) - months * monthly.actual_std_dev_demand.powi(2)).abs() < 1e-3);
+
+        // And so do the seasons drawn, week by week or month by month
+        let season_totals = |time_step: TimeStep| -> Vec<f64> {
+            let params = SimulationParams { time_step, ..params.clone() };
+            (0..400)
+                .map(|seed| {
+                    seed_demand_rng(seed);
+                    let (monthly_results, _) = run_monthly_simulation_internal(&params, &pair, &order, false, true, DemandSource::Stochastic(None)).unwrap();
+                    monthly_results.iter().map(|period| period.product_results[0].demand as f64).sum()
+                })
+                .collect()
+        };
+        let moments = |totals: Vec<f64>| {
+            let mean = totals.iter().sum::<f64>() / totals.len() as f64;
+            let variance = totals.iter().map(|total| (total - mean).powi(2)).sum::<f64>() / (totals.len() - 1) as f64;
+            (mean, variance.sqrt())
+        };
+        let (monthly_mean, monthly_std_dev) = moments(season_totals(TimeStep::Monthly));
+        let (weekly_mean, weekly_std_dev) = moments(season_totals(TimeStep::Weekly));
+        assert!((weekly_mean - monthly_mean).abs() < 0.02 * monthly_mean, "{} vs {}", weekly_mean, monthly_mean);
+        assert!((weekly_std_dev - monthly_std_dev).abs() < 0.15 * monthly_std_dev, "{} vs {}", weekly_std_dev, monthly_std_dev);
+    }
+
+    #[test]
+    fn test_weekly_season_values_an_immediate_surge_supplier() {
+        // Demand runs 30% above the plan, so surge units are worth adding. Month by month a
+        // change lands next month whether the surge supplier takes no time or a month; week by
+        // week the immediate one delivers from next week, a month sooner
+        let (mut params, pair) = crate::monte_carlo::tests::two_product_scenario();
+        params.products.truncate(1);
+        params.demand_params.truncate(1);
+        params.demand_params[0].actual_mean_demand *= 1.3;
+        let mut order = create_order(vec![(ProductId(0), 5_000)], Vec::new());
+        order.set_surge_quantity(ProductId(0), 1_000);
+        let mut slower = pair.clone();
+        slower.surge_supplier.lead_time_months = 1;
+
+        let immediacy_value = |time_step: TimeStep| {
+            let params = SimulationParams { time_step, ..params.clone() };
+            mean_profit_with_seeds(&params, &pair, &order, 4) - mean_profit_with_seeds(&params, &slower, &order, 4)
+        };
+        assert_eq!(immediacy_value(TimeStep::Monthly), 0.0);
+        assert!(immediacy_value(TimeStep::Weekly) > 0.0);
+    }
+
+    /// Products priced from nothing up, demand forecasts from none to volatile, and an order
+    /// for each from suppliers as small as no capacity at all
+    fn arbitrary_season() -> impl Strategy<Value = (SimulationParams, SupplierPair, MonthlyOrder)> {
+        let product = (0.0..300.0f64, 0.0..1.0f64, 0.0..10.0f64, 0.0..5_000.0f64, 0.0..1.0f64, 0..6_000u32, 0..6_000u32);
+        let supplier = (0..10_000u32, 0.0..200.0f64, 0.0..10_000.0f64, 0..4usize);
+        (prop::collection::vec(product, 1..4), supplier.clone(), supplier, 1..=8usize, any::<bool>())
+            .prop_map(|(products, base, surge, season_months, strict)| {
+                let (mut params, mut pair, _) = collapsing_demand_scenario(1.0);
+                params.products = products.iter().enumerate()
+                    .map(|(id, &(price, liquidation, holding, ..))| Product {
+                        id: ProductId(id as u32),
+                        name: format!("Product {}", id),
+                        selling_price: price,
+                        liquidation_price: price * liquidation,
+                        monthly_holding_cost: holding,
+                        goodwill_penalty: None,
+                    })
+                    .collect();
+                params.demand_params = products.iter().enumerate()
+                    .map(|(id, &(.., mean, volatility, _, _))| ProductDemandParams {
+                        product_id: ProductId(id as u32),
+                        mean_demand: mean,
+                        std_dev_demand: mean * volatility,
+                        actual_mean_demand: mean,
+                        actual_std_dev_demand: mean * volatility,
+                    })
+                    .collect();
+                params.season_months = season_months;
+                params.capacity_policy = if strict { CapacityPolicy::Strict } else { CapacityPolicy::Clamp };
+                for (supplier, (capacity, unit_cost, setup_cost, lead_time)) in [(&mut pair.base_supplier, base), (&mut pair.surge_supplier, surge)] {
+                    supplier.fixed_capacity = capacity;
+                    supplier.unit_costs = params.products.iter().map(|product| (product.id, unit_cost)).collect();
+                    supplier.setup_cost = setup_cost;
+                    supplier.lead_time_months = lead_time;
+                }
+                let order = create_order(
+                    products.iter().enumerate().map(|(id, &(.., base, _))| (ProductId(id as u32), base)).collect(),
+                    products.iter().enumerate().map(|(id, &(.., surge))| (ProductId(id as u32), surge)).collect(),
+                );
+                (params, pair, order)
+            })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(200))]
+
+        #[test]
+        fn prop_the_season_accounts_for_every_unit_and_dollar((params, pair, order) in arbitrary_season(), seed in any::<u64>(), enable_options in any::<bool>()) {
+            seed_demand_rng(seed);
+            let result = run_monthly_simulation_internal(&params, &pair, &order, enable_options, true, DemandSource::Stochastic(None));
+            // A strict policy refuses a plan beyond its suppliers rather than running it
+            let (monthly_results, total_profit) = match result {
+                Err(SimulationError::Capacity(_)) if params.capacity_policy == CapacityPolicy::Strict => return Ok(()),
+                result => result.unwrap(),
+            };
+            prop_assert_eq!(monthly_results.len(), params.season_months);
+            let close = |a: f64, b: f64| (a - b).abs() <= 1e-6 * (1.0 + a.abs().max(b.abs()));
+
+            let mut carried: Vec<u32> = vec![0; params.products.len()];
+            for (month_idx, month) in monthly_results.iter().enumerate() {
+                let mut accounted = -(month.order_change_cost + month.setup_cost + month.flex_cost + month.reservation_cost + month.expansion_cost);
+                for (product, carried) in month.product_results.iter().zip(&mut carried) {
+                    let available = product.inventory_start + product.incoming;
+                    prop_assert_eq!(product.inventory_start, *carried);
+                    prop_assert!(product.units_sold <= product.demand && product.units_sold <= available);
+                    let left = if month_idx + 1 == params.season_months { 0 } else { available - product.units_sold };
+                    prop_assert_eq!(product.inventory_end, left);
+                    *carried = product.inventory_end;
+                    accounted += product.revenue + product.liquidation_revenue - product.production_cost - product.holding_cost
+                        - product.financing_cost - product.goodwill_penalty;
+                }
+                prop_assert!(close(accounted, month.monthly_profit), "{}: {} against {}", month.month, accounted, month.monthly_profit);
+
+                let delivered = |units: fn(&ProductMonthlyResult) -> u32| month.product_results.iter().map(units).sum::<u32>();
+                prop_assert!(delivered(|product| product.base_incoming) <= pair.base_supplier.fixed_capacity);
+                prop_assert!(delivered(|product| product.surge_incoming) <= pair.surge_supplier.fixed_capacity);
+            }
+            prop_assert!(close(total_profit, monthly_results.iter().map(|month| month.monthly_profit).sum()));
+        }
     }
 }
@@ -0,0 +1,97 @@
+//! Standard normal distribution helpers for newsvendor-style calculations
+//! Provides the density, distribution, its inverse, and the standard normal loss function
+
+use std::f64::consts::PI;
+
+/// Standard normal probability density φ(z)
+pub(crate) fn standard_normal_pdf(z: f64) -> f64 {
+    (-0.5 * z * z).exp() / (2.0 * PI).sqrt()
+}
+
+/// Standard normal cumulative distribution Φ(z)
+pub(crate) fn standard_normal_cdf(z: f64) -> f64 {
+    0.5 * (1.0 + erf(z / std::f64::consts::SQRT_2))
+}
+
+/// Inverse of the standard normal distribution, z such that Φ(z) = p
+/// Uses Acklam's rational approximation (relative error below 1.2e-9); p is clamped into (0, 1)
+pub(crate) fn inverse_standard_normal_cdf(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e+01, 2.209460984245205e+02, -2.759285104469687e+02,
+        1.38357751867269e+02, -3.066479806614716e+01, 2.506628277459239e+00,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e+01, 1.615858368580409e+02, -1.556989798598866e+02,
+        6.680131188771972e+01, -1.328068155288572e+01,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-03, -3.223964580411365e-01, -2.400758277161838e+00,
+        -2.549732539343734e+00, 4.374664141464968e+00, 2.938163982698783e+00,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-03, 3.224671290700398e-01, 2.445134137142996e+00,
+        3.754408661907416e+00,
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    let p = p.clamp(1e-12, 1.0 - 1e-12);
+    if p < P_LOW {
+        // Lower tail
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else if p <= 1.0 - P_LOW {
+        // Central region
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        // Upper tail, by symmetry
+        -inverse_standard_normal_cdf(1.0 - p)
+    }
+}
+
+/// Standard normal loss function L(z) = φ(z) − z·(1 − Φ(z))
+/// Expected units short per standard deviation when stocking z standard deviations above the mean
+pub(crate) fn normal_loss(z: f64) -> f64 {
+    standard_normal_pdf(z) - z * (1.0 - standard_normal_cdf(z))
+}
+
+/// Error function, Abramowitz and Stegun 7.1.26 (absolute error below 1.5e-7)
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let magnitude = 1.0 - poly * (-x * x).exp();
+    if x >= 0.0 { magnitude } else { -magnitude }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normal_loss_matches_tables() {
+        let tabulated = [
+            (-2.0, 2.00849),
+            (-1.0, 1.08332),
+            (-0.5, 0.69780),
+            (0.0, 0.39894),
+            (0.5, 0.19780),
+            (1.0, 0.08332),
+            (1.5, 0.02931),
+            (2.0, 0.00849),
+        ];
+        for (z, expected) in tabulated {
+            assert!((normal_loss(z) - expected).abs() < 1e-4, "L({}) = {}", z, normal_loss(z));
+        }
+    }
+
+    #[test]
+    fn test_inverse_cdf_round_trips() {
+        assert!((inverse_standard_normal_cdf(0.95) - 1.644854).abs() < 1e-5);
+        for p in [0.01, 0.2, 0.5, 0.8, 0.99] {
+            assert!((standard_normal_cdf(inverse_standard_normal_cdf(p)) - p).abs() < 1e-6);
+        }
+    }
+}
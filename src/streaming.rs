@@ -0,0 +1,383 @@
+//! Constant-memory accumulators for summarizing long runs of samples
+//! Welford's algorithm for the mean and variance, the P² algorithm for quantiles, and
+//! reservoir sampling for keeping a uniform subset of the samples themselves
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+/// Samples retained for exact quantiles; beyond this the P² estimates are used
+pub(crate) const EXACT_QUANTILE_THRESHOLD: usize = 2_000;
+
+/// Quantile at probability `p` of ascending `sorted` samples, interpolating linearly between
+/// the order statistics either side of position p·(n − 1); p = 0 and p = 1 give the extremes
+pub(crate) fn interpolated_quantile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let position = p.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let below = position.floor() as usize;
+    let above = (below + 1).min(sorted.len() - 1);
+    sorted[below] + (position - below as f64) * (sorted[above] - sorted[below])
+}
+
+/// Share of a distribution below `x`, given its quantiles at evenly spaced probabilities from
+/// 0 to 1 and interpolating linearly between them (accurate to the grid spacing)
+pub(crate) fn grid_cdf(grid: &[f64], x: f64) -> f64 {
+    let (Some(&lowest), Some(&highest)) = (grid.first(), grid.last()) else {
+        return 0.0;
+    };
+    if x <= lowest {
+        return 0.0;
+    }
+    if x > highest {
+        return 1.0;
+    }
+    // Last grid point below x, then interpolate towards the next one
+    let step = grid.iter().rposition(|&q| q < x).unwrap_or(0);
+    let fraction = (x - grid[step]) / (grid[step + 1] - grid[step]);
+    (step as f64 + fraction) / (grid.len() - 1) as f64
+}
+
+/// Running count, mean, variance, minimum and maximum of a stream of samples (Welford)
+#[derive(Clone, Debug)]
+pub(crate) struct StreamingStats {
+    count: usize,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl StreamingStats {
+    pub fn new() -> Self {
+        StreamingStats {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Add one sample
+    pub fn push(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Population variance, dividing by the number of samples
+    pub fn variance(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.m2 / self.count as f64
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Smallest sample, or 0 before any sample
+    pub fn min(&self) -> f64 {
+        if self.count == 0 { 0.0 } else { self.min }
+    }
+
+    /// Largest sample, or 0 before any sample
+    pub fn max(&self) -> f64 {
+        if self.count == 0 { 0.0 } else { self.max }
+    }
+}
+
+/// Running estimate of one quantile of a stream with five markers (Jain and Chlamtac's P²)
+/// On smooth unimodal distributions, such as season profit, the estimate after 10k samples
+/// is typically within 1–2% of a standard deviation of the exact sample quantile; heavy or
+/// multimodal tails converge more slowly
+#[derive(Clone, Debug)]
+pub(crate) struct P2Quantile {
+    p: f64,
+    count: usize,
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    /// Estimator for the quantile at probability `p` in [0, 1]
+    pub fn new(p: f64) -> Self {
+        P2Quantile {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    /// Add one sample
+    pub fn push(&mut self, x: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = x;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(|a, b| a.total_cmp(b));
+            }
+            return;
+        }
+        self.count += 1;
+
+        // Find the cell the sample falls in, widening the extremes if needed
+        let cell = if x < self.heights[0] {
+            self.heights[0] = x;
+            0
+        } else if x >= self.heights[4] {
+            self.heights[4] = x;
+            3
+        } else {
+            (0..4).find(|&i| x < self.heights[i + 1]).unwrap_or(3)
+        };
+        for position in &mut self.positions[cell + 1..] {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(&self.increments) {
+            *desired += increment;
+        }
+
+        // Nudge the middle markers towards their desired positions
+        for i in 1..4 {
+            let offset = self.desired[i] - self.positions[i];
+            let room_above = self.positions[i + 1] - self.positions[i];
+            let room_below = self.positions[i - 1] - self.positions[i];
+            if (offset >= 1.0 && room_above > 1.0) || (offset <= -1.0 && room_below < -1.0) {
+                let step = offset.signum();
+                let parabolic = self.parabolic(i, step);
+                self.heights[i] = if self.heights[i - 1] < parabolic && parabolic < self.heights[i + 1] {
+                    parabolic
+                } else {
+                    self.linear(i, step)
+                };
+                self.positions[i] += step;
+            }
+        }
+    }
+
+    /// Current estimate; exact for five samples or fewer, and at p = 0 or 1 where the outer
+    /// markers track the extremes
+    pub fn estimate(&self) -> f64 {
+        if self.count < 5 {
+            let mut samples = self.heights[..self.count].to_vec();
+            samples.sort_by(|a, b| a.total_cmp(b));
+            return interpolated_quantile(&samples, self.p);
+        }
+        if self.p <= 0.0 {
+            self.heights[0]
+        } else if self.p >= 1.0 {
+            self.heights[4]
+        } else {
+            self.heights[2]
+        }
+    }
+
+    /// Piecewise-parabolic prediction of marker `i` moved by `step`
+    fn parabolic(&self, i: usize, step: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + step / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + step) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - step) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    /// Linear prediction of marker `i` moved by `step`, used when the parabola overshoots
+    fn linear(&self, i: usize, step: f64) -> f64 {
+        let j = if step > 0.0 { i + 1 } else { i - 1 };
+        self.heights[i] + step * (self.heights[j] - self.heights[i]) / (self.positions[j] - self.positions[i])
+    }
+}
+
+/// Quantiles of a stream: exact while the stream is short, P² estimates beyond
+/// `EXACT_QUANTILE_THRESHOLD` samples
+#[derive(Clone, Debug)]
+pub(crate) struct StreamingQuantiles {
+    estimators: Vec<P2Quantile>,
+    retained: Vec<f64>,
+    count: usize,
+}
+
+impl StreamingQuantiles {
+    /// Track the quantiles at each probability in `probabilities`, all in [0, 1]
+    pub fn new(probabilities: &[f64]) -> Self {
+        StreamingQuantiles {
+            estimators: probabilities.iter().map(|&p| P2Quantile::new(p)).collect(),
+            retained: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Add one sample
+    pub fn push(&mut self, x: f64) {
+        self.count += 1;
+        if self.count <= EXACT_QUANTILE_THRESHOLD {
+            self.retained.push(x);
+        } else if !self.retained.is_empty() {
+            // Past the threshold the retained samples are no longer needed
+            self.retained = Vec::new();
+        }
+        for estimator in &mut self.estimators {
+            estimator.push(x);
+        }
+    }
+
+    /// Quantile estimates in the order the probabilities were given
+    /// Exact quantiles interpolate between order statistics, as in the Monte Carlo summary
+    pub fn estimates(&self) -> Vec<f64> {
+        if self.count > EXACT_QUANTILE_THRESHOLD {
+            return self.estimators.iter().map(P2Quantile::estimate).collect();
+        }
+        let mut sorted = self.retained.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        self.estimators.iter().map(|estimator| interpolated_quantile(&sorted, estimator.p)).collect()
+    }
+}
+
+/// Uniform random subset of at most `capacity` items from a stream (Vitter's Algorithm R)
+/// Every item seen has the same chance of being kept, and a stream no longer than the
+/// capacity is kept whole and in order
+#[derive(Clone, Debug)]
+pub(crate) struct Reservoir<T> {
+    capacity: usize,
+    seen: usize,
+    items: Vec<T>,
+    rng: SmallRng,
+}
+
+impl<T> Reservoir<T> {
+    /// Empty reservoir whose replacement choices are drawn from `seed`
+    pub fn new(capacity: usize, seed: u64) -> Self {
+        Reservoir {
+            capacity,
+            seen: 0,
+            items: Vec::new(),
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Offer one item, which replaces a kept item with probability capacity / items seen
+    pub fn push(&mut self, item: T) {
+        self.push_with(|| item);
+    }
+
+    /// Offer one item, made by `item` only if it is kept, for items that are costly to build
+    pub fn push_with(&mut self, item: impl FnOnce() -> T) {
+        self.seen += 1;
+        if self.items.len() < self.capacity {
+            self.items.push(item());
+        } else {
+            let slot = self.rng.gen_range(0..self.seen);
+            if slot < self.capacity {
+                self.items[slot] = item();
+            }
+        }
+    }
+
+    /// Kept items, in no particular order once the reservoir has overflowed
+    pub fn into_items(self) -> Vec<T> {
+        self.items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::SmallRng, SeedableRng};
+    use rand_distr::{Distribution, Normal};
+
+    #[test]
+    fn test_streaming_estimates_match_exact_on_10k_samples() {
+        let mut rng = SmallRng::seed_from_u64(42);
+        let normal = Normal::new(1_000_000.0, 150_000.0).unwrap();
+        let samples: Vec<f64> = (0..10_000).map(|_| normal.sample(&mut rng)).collect();
+
+        let probabilities = [0.10, 0.25, 0.50, 0.75, 0.90];
+        let mut stats = StreamingStats::new();
+        let mut quantiles = StreamingQuantiles::new(&probabilities);
+        for &x in &samples {
+            stats.push(x);
+            quantiles.push(x);
+        }
+
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+        assert!((stats.mean() - mean).abs() < 1e-6);
+        assert!((stats.variance() - variance).abs() / variance < 1e-9);
+        assert_eq!(stats.min(), samples.iter().copied().fold(f64::INFINITY, f64::min));
+        assert_eq!(stats.max(), samples.iter().copied().fold(f64::NEG_INFINITY, f64::max));
+
+        let mut sorted = samples.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        for (&p, estimate) in probabilities.iter().zip(quantiles.estimates()) {
+            let exact = interpolated_quantile(&sorted, p);
+            assert!((estimate - exact).abs() < 0.02 * 150_000.0, "p{}: {} against {}", p, estimate, exact);
+        }
+    }
+
+    #[test]
+    fn test_short_streams_use_exact_quantiles() {
+        let mut quantiles = StreamingQuantiles::new(&[0.0, 0.1, 0.5, 0.9, 1.0]);
+        for x in (0..101).rev() {
+            quantiles.push(x as f64);
+        }
+        assert_eq!(quantiles.estimates(), vec![0.0, 10.0, 50.0, 90.0, 100.0]);
+    }
+
+    #[test]
+    fn test_reservoir_keeps_items_with_equal_probability() {
+        let (stream, capacity, trials) = (100, 10, 4_000);
+        let mut inclusions = vec![0usize; stream];
+        for trial in 0..trials {
+            let mut reservoir = Reservoir::new(capacity, trial);
+            for item in 0..stream {
+                reservoir.push(item);
+            }
+            let kept = reservoir.into_items();
+            assert_eq!(kept.len(), capacity);
+            for item in kept {
+                inclusions[item] += 1;
+            }
+        }
+        // Each item is kept in 10% of trials; 4,000 trials put 3 standard errors near 1.4%
+        for count in inclusions {
+            assert!((count as f64 / trials as f64 - 0.1).abs() < 0.015, "kept {} times", count);
+        }
+
+        let mut short = Reservoir::new(capacity, 1);
+        (0..5).for_each(|item| short.push(item));
+        assert_eq!(short.into_items(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_interpolated_quantiles_of_small_samples() {
+        let sorted = [10.0, 20.0, 30.0, 40.0, 50.0];
+        // Position p·(n − 1) falls between order statistics for all but the quartiles here
+        assert_eq!(interpolated_quantile(&sorted, 0.0), 10.0);
+        assert_eq!(interpolated_quantile(&sorted, 0.1), 14.0);
+        assert_eq!(interpolated_quantile(&sorted, 0.25), 20.0);
+        assert_eq!(interpolated_quantile(&sorted, 0.5), 30.0);
+        assert!((interpolated_quantile(&sorted, 0.9) - 46.0).abs() < 1e-9);
+        assert_eq!(interpolated_quantile(&sorted, 1.0), 50.0);
+
+        assert_eq!(interpolated_quantile(&[1.0, 4.0], 0.5), 2.5);
+        assert_eq!(interpolated_quantile(&[7.0], 0.3), 7.0);
+    }
+}
@@ -0,0 +1,322 @@
+//! Reports laid out by Jinja templates (`templates` feature)
+//! A run is flattened into a context of plain values, with every amount already written out
+//! by the run's `Formatter`, so templates only arrange text; the raw figures stay available
+//! under each pair's `raw` for templates that compare or branch on them
+
+use minijinja::{Environment, Value};
+use serde::Serialize;
+use crate::models::{MonteCarloStats, ProductId, ProductPnl, RunOutput};
+use crate::reporting::{pnl_total, ranking_description, rejection, Formatter};
+
+/// Templates shipped with the simulator, by the name `--template-builtin` takes
+pub(crate) const BUILTIN_TEMPLATES: [(&str, &str); 2] = [
+    ("executive", include_str!("../templates/executive.md.j2")),
+    ("technical", include_str!("../templates/technical.md.j2")),
+];
+
+/// Source of the built-in template called `name`
+pub(crate) fn builtin(name: &str) -> Option<&'static str> {
+    BUILTIN_TEMPLATES.iter().find(|(builtin, _)| *builtin == name).map(|(_, source)| *source)
+}
+
+/// Render the template `source` against `run`
+/// `name` picks the escaping, as the template's file name would: HTML for .html, none for .md
+pub(crate) fn render(name: &str, source: &str, run: &RunOutput, formatter: &Formatter) -> Result<String, minijinja::Error> {
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.set_keep_trailing_newline(true);
+    env.add_template(name, source)?;
+    env.get_template(name)?.render(Value::from_serialize(context(run, formatter)))
+}
+
+/// Everything a template sees
+#[derive(Debug, Serialize)]
+struct Context {
+    /// What the pairs are ranked by, e.g. "mean profit"
+    ranking: &'static str,
+    scenario: ScenarioContext,
+    /// Every simulated pair, best first
+    results: Vec<PairContext>,
+    /// The best pair under the run's ranking
+    best: Option<PairContext>,
+    /// Pairs screening kept from being simulated
+    rejected: Vec<RejectedContext>,
+}
+
+#[derive(Debug, Serialize)]
+struct ScenarioContext {
+    season_months: usize,
+    products: Vec<ProductContext>,
+    suppliers: Vec<SupplierContext>,
+    order_change_fees: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProductContext {
+    name: String,
+    price: String,
+    holding_cost: String,
+    liquidation_price: String,
+    /// Demand per month as "mean ± std dev"
+    forecast_demand: String,
+    actual_demand: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SupplierContext {
+    name: String,
+    lead_time_months: usize,
+    capacity: String,
+    setup_cost: String,
+    /// e.g. "Model A $160, Model B $170"
+    unit_costs: String,
+}
+
+/// One pair's results, written out
+#[derive(Debug, Serialize)]
+struct PairContext {
+    rank: usize,
+    /// "base + surge", or "base + surge + backup", with pooled suppliers as "base & pooled"
+    name: String,
+    base_supplier: String,
+    surge_supplier: String,
+    /// Empty without a backup supplier
+    backup_supplier: String,
+    /// Suppliers sharing the base orders after the base supplier, if any
+    pooled_suppliers: Vec<String>,
+    /// Whether the base supplier takes every order alone
+    single_source: bool,
+    mean_profit: String,
+    /// e.g. "95%"
+    confidence_level: String,
+    ci_low: String,
+    ci_high: String,
+    ci_half_width: String,
+    std_dev: String,
+    min: String,
+    max: String,
+    /// Percentiles are "n/a" when the run did not compute them
+    p10: String,
+    p50: String,
+    p90: String,
+    var_5: String,
+    cvar_5: String,
+    prob_loss: String,
+    downside_deviation: String,
+    operational_profit: String,
+    exercise_probability: String,
+    mean_exercises: String,
+    mean_fees: String,
+    mean_setup_cost: String,
+    base_utilization: String,
+    surge_utilization: String,
+    backup_utilization: String,
+    simulations: String,
+    converged: bool,
+    raw: RawFigures,
+    allocations: Vec<AllocationContext>,
+    monthly: Vec<MonthContext>,
+    /// Profit and loss by product, then the total
+    pnl: Vec<PnlContext>,
+}
+
+/// Unformatted figures of a pair
+#[derive(Debug, Serialize)]
+struct RawFigures {
+    mean_profit: f64,
+    std_dev_profit: f64,
+    ci_half_width: f64,
+    prob_loss: f64,
+    exercise_probability: f64,
+    num_simulations: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct AllocationContext {
+    product: String,
+    base: String,
+    surge: String,
+    total: String,
+}
+
+#[derive(Debug, Serialize)]
+struct MonthContext {
+    month: String,
+    revenue: String,
+    production_cost: String,
+    holding_cost: String,
+    liquidation_revenue: String,
+    other_costs: String,
+    profit: String,
+}
+
+#[derive(Debug, Serialize)]
+struct PnlContext {
+    product: String,
+    revenue: String,
+    cost_of_goods: String,
+    holding_cost: String,
+    liquidation_revenue: String,
+    setup_cost: String,
+    fees: String,
+    profit: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RejectedContext {
+    name: String,
+    reason: String,
+}
+
+/// Flatten a run into the template context
+fn context(run: &RunOutput, formatter: &Formatter) -> Context {
+    let money = |amount: f64| formatter.whole_money(amount);
+    let count = |count: f64| formatter.count(count);
+
+    let products = run.params.products.iter()
+        .map(|product| {
+            let demand = |mean: f64, std_dev: f64| format!("{} ± {}", count(mean), count(std_dev));
+            let dp = run.params.get_demand_params(product.id);
+            ProductContext {
+                name: product.name.clone(),
+                price: money(product.selling_price),
+                holding_cost: formatter.money_with(product.monthly_holding_cost, 2),
+                liquidation_price: money(product.liquidation_price),
+                forecast_demand: dp.map_or("n/a".to_string(), |dp| demand(dp.mean_demand, dp.std_dev_demand)),
+                actual_demand: dp.map_or("n/a".to_string(), |dp| demand(dp.actual_mean_demand, dp.actual_std_dev_demand)),
+            }
+        })
+        .collect();
+    let suppliers = run.suppliers.iter()
+        .map(|supplier| {
+            let mut unit_costs: Vec<(&ProductId, &f64)> = supplier.unit_costs.iter().collect();
+            unit_costs.sort_by_key(|(product_id, _)| **product_id);
+            let unit_costs: Vec<String> = unit_costs.iter()
+                .map(|(product_id, cost)| {
+                    let name = run.params.get_product(**product_id).map_or("?", |product| product.name.as_str());
+                    format!("{} {}", name, money(**cost))
+                })
+                .collect();
+            SupplierContext {
+                name: supplier.name.clone(),
+                lead_time_months: supplier.lead_time_months,
+                capacity: count(supplier.fixed_capacity as f64),
+                setup_cost: money(supplier.setup_cost),
+                unit_costs: unit_costs.join(", "),
+            }
+        })
+        .collect();
+
+    let mut ranked: Vec<&MonteCarloStats> = run.pairs.iter().map(|pair| &pair.stats).collect();
+    ranked.sort_by(|a, b| run.ranking.score(b).total_cmp(&run.ranking.score(a)));
+    let best = run.best_pair
+        .and_then(|index| run.pairs.get(index))
+        .map(|best| {
+            let rank = ranked.iter().position(|stats| std::ptr::eq(*stats, &best.stats)).unwrap_or(0);
+            pair_context(rank + 1, &best.stats, formatter)
+        });
+
+    Context {
+        ranking: ranking_description(run.ranking),
+        scenario: ScenarioContext {
+            season_months: run.params.season_months,
+            products,
+            suppliers,
+            order_change_fees: run.params.order_change_fees.iter().map(|&fee| money(fee)).collect(),
+        },
+        results: ranked.iter().enumerate().map(|(rank, stats)| pair_context(rank + 1, stats, formatter)).collect(),
+        best,
+        rejected: run.rejected_pairs.iter()
+            .map(|pair| RejectedContext {
+                name: pair.name(),
+                reason: rejection(&pair.reason, formatter),
+            })
+            .collect(),
+    }
+}
+
+/// One pair's statistics and breakdowns, written out
+fn pair_context(rank: usize, stats: &MonteCarloStats, formatter: &Formatter) -> PairContext {
+    let money = |amount: f64| formatter.whole_money(amount);
+    let count = |count: f64| formatter.count(count);
+    let percent = |share: f64| format!("{:.1}%", share * 100.0);
+    let optional = |amount: Option<f64>| amount.map_or_else(|| "n/a".to_string(), money);
+    let pnl_row = |row: &ProductPnl| PnlContext {
+        product: row.product_name.clone(),
+        revenue: money(row.revenue),
+        cost_of_goods: money(row.cost_of_goods),
+        holding_cost: money(row.holding_cost),
+        liquidation_revenue: money(row.liquidation_revenue),
+        setup_cost: money(row.setup_cost),
+        fees: money(row.fees),
+        profit: money(row.profit),
+    };
+
+    PairContext {
+        rank,
+        name: stats.pair_name(),
+        base_supplier: stats.base_supplier.clone(),
+        surge_supplier: stats.surge_supplier.clone(),
+        backup_supplier: stats.backup_supplier.clone().unwrap_or_default(),
+        pooled_suppliers: stats.pooled_suppliers.clone(),
+        single_source: stats.single_source,
+        mean_profit: money(stats.mean_profit),
+        confidence_level: format!("{:.0}%", stats.confidence_level * 100.0),
+        ci_low: money(stats.mean_ci_low),
+        ci_high: money(stats.mean_ci_high),
+        ci_half_width: money(stats.mean_ci_half_width()),
+        std_dev: money(stats.std_dev_profit),
+        min: money(stats.min_profit),
+        max: money(stats.max_profit),
+        p10: optional(stats.p10()),
+        p50: optional(stats.p50()),
+        p90: optional(stats.p90()),
+        var_5: money(stats.var_5),
+        cvar_5: money(stats.cvar_5),
+        prob_loss: percent(stats.prob_loss),
+        downside_deviation: optional(stats.downside_deviation),
+        operational_profit: money(stats.mean_operational_profit),
+        exercise_probability: percent(stats.exercise_probability),
+        mean_exercises: formatter.number(stats.mean_exercises, 2),
+        mean_fees: money(stats.mean_fees_paid),
+        mean_setup_cost: money(stats.mean_setup_cost),
+        base_utilization: percent(stats.mean_base_utilization),
+        surge_utilization: percent(stats.mean_surge_utilization),
+        backup_utilization: percent(stats.mean_backup_utilization),
+        simulations: count(stats.num_simulations as f64),
+        converged: stats.converged,
+        raw: RawFigures {
+            mean_profit: stats.mean_profit,
+            std_dev_profit: stats.std_dev_profit,
+            ci_half_width: stats.mean_ci_half_width(),
+            prob_loss: stats.prob_loss,
+            exercise_probability: stats.exercise_probability,
+            num_simulations: stats.num_simulations,
+        },
+        allocations: stats.product_allocations.iter()
+            .map(|alloc| AllocationContext {
+                product: alloc.product_name.clone(),
+                base: count(alloc.base_quantity as f64),
+                surge: count(alloc.surge_quantity as f64),
+                total: count((alloc.base_quantity + alloc.surge_quantity) as f64),
+            })
+            .collect(),
+        monthly: stats.monthly_breakdown.iter()
+            .map(|month| MonthContext {
+                month: month.month.clone(),
+                revenue: money(month.mean_revenue),
+                production_cost: money(month.mean_production_cost),
+                holding_cost: money(month.mean_holding_cost),
+                liquidation_revenue: money(month.mean_liquidation_revenue),
+                other_costs: money(month.mean_other_costs),
+                profit: money(month.mean_profit),
+            })
+            .collect(),
+        pnl: if stats.product_pnl.is_empty() {
+            Vec::new()
+        } else {
+            stats.product_pnl.iter().chain(std::iter::once(&pnl_total(&stats.product_pnl))).map(pnl_row).collect()
+        },
+    }
+}
@@ -0,0 +1,263 @@
+//! Checks on a scenario from outside the program, such as a hand-edited file or a request to
+//! the server, run before any of it is simulated
+//!
+//! Beyond refusing what makes no sense, like a NaN price or a cost for a product nobody sells,
+//! the bounds keep every count the simulation adds up within a `u32` over a whole season, so
+//! arithmetic on units cannot overflow however far a scenario strays
+
+use std::collections::HashSet;
+use crate::error::ScenarioError;
+use crate::models::{ProductId, SimulationParams, Supplier};
+
+/// Longest season a scenario may run
+pub const MAX_SEASON_MONTHS: usize = 36;
+
+/// Most units a month the scenario's suppliers may make together, overtime and expansions
+/// included, and most its products' demand may come to; a season of them stays far within `u32`
+pub const MAX_MONTHLY_UNITS: f64 = 10_000_000.0;
+
+/// Most overtime a soft capacity tier may run to, as a fraction of the nominal capacity
+pub const MAX_OVERTIME_FRACTION: f64 = 10.0;
+
+/// Refuse a scenario the simulation cannot run as given, saying what is wrong with it
+pub fn validate_scenario(params: &SimulationParams, suppliers: &[Supplier]) -> Result<(), ScenarioError> {
+    validate_products(params)?;
+    validate_season(params, suppliers)?;
+    for supplier in suppliers {
+        validate_supplier(params, supplier)?;
+    }
+    validate_units(params, suppliers)
+}
+
+fn validate_products(params: &SimulationParams) -> Result<(), ScenarioError> {
+    if params.products.is_empty() {
+        return Err(ScenarioError::NoProducts);
+    }
+    let mut ids = HashSet::new();
+    for product in &params.products {
+        if !ids.insert(product.id) {
+            return Err(ScenarioError::DuplicateId { kind: "product", id: product.id.0 });
+        }
+        let field = |name: &str| format!("product {} {}", product.id, name);
+        amount(field("selling_price"), product.selling_price)?;
+        amount(field("liquidation_price"), product.liquidation_price)?;
+        amount(field("monthly_holding_cost"), product.monthly_holding_cost)?;
+        for (i, &penalty) in product.goodwill_penalty.iter().flat_map(|goodwill| &goodwill.per_consecutive_month).enumerate() {
+            amount(field(&format!("goodwill_penalty[{}]", i)), penalty)?;
+        }
+        if params.demand_params.iter().filter(|dp| dp.product_id == product.id).count() != 1 {
+            return Err(ScenarioError::DemandParams(product.id));
+        }
+    }
+    for dp in &params.demand_params {
+        known_product(params, "demand parameters", dp.product_id)?;
+        let field = |name: &str| format!("product {} {}", dp.product_id, name);
+        amount(field("mean_demand"), dp.mean_demand)?;
+        amount(field("std_dev_demand"), dp.std_dev_demand)?;
+        amount(field("actual_mean_demand"), dp.actual_mean_demand)?;
+        amount(field("actual_std_dev_demand"), dp.actual_std_dev_demand)?;
+    }
+    for (i, &fee) in params.order_change_fees.iter().enumerate() {
+        amount(format!("order_change_fees[{}]", i), fee)?;
+    }
+    amount("order_change_fee_per_product".to_string(), params.order_change_fee_per_product)?;
+    amount("cancellation_fee_per_unit".to_string(), params.cancellation_fee_per_unit)?;
+    amount("cost_of_capital_annual".to_string(), params.cost_of_capital_annual)?;
+    for disruption in &params.regional_disruptions {
+        share(format!("monthly_probability of region {}", disruption.region), disruption.monthly_probability, 1.0)?;
+    }
+    for &(product_id, _) in &params.opening_inventory {
+        known_product(params, "the opening inventory", product_id)?;
+    }
+    Ok(())
+}
+
+fn validate_season(params: &SimulationParams, suppliers: &[Supplier]) -> Result<(), ScenarioError> {
+    if !(1..=MAX_SEASON_MONTHS).contains(&params.season_months) {
+        return Err(ScenarioError::SeasonLength { months: params.season_months, max: MAX_SEASON_MONTHS });
+    }
+    let mut ids = HashSet::new();
+    for supplier in suppliers {
+        if !ids.insert(supplier.id) {
+            return Err(ScenarioError::DuplicateId { kind: "supplier", id: supplier.id.0 });
+        }
+    }
+    for offer in &params.expansion_offers {
+        if !ids.contains(&offer.supplier_id) {
+            return Err(ScenarioError::UnknownSupplier { context: "an expansion offer".to_string(), supplier_id: offer.supplier_id });
+        }
+        if offer.decision_month >= offer.start_month {
+            return Err(ScenarioError::ExpansionOrder(offer.supplier_id));
+        }
+        months(format!("start_month of supplier {}'s expansion offer", offer.supplier_id), offer.start_month)?;
+        amount(format!("fee of supplier {}'s expansion offer", offer.supplier_id), offer.fee)?;
+    }
+    for &supplier_id in &params.onboarded_suppliers {
+        if !ids.contains(&supplier_id) {
+            return Err(ScenarioError::UnknownSupplier { context: "the list of onboarded suppliers".to_string(), supplier_id });
+        }
+    }
+    Ok(())
+}
+
+fn validate_supplier(params: &SimulationParams, supplier: &Supplier) -> Result<(), ScenarioError> {
+    let field = |name: &str| format!("supplier {} {}", supplier.id, name);
+    months(field("lead_time_months"), supplier.lead_time_months)?;
+    amount(field("setup_cost"), supplier.setup_cost)?;
+    if let Some(fee) = supplier.reservation_fee {
+        amount(field("reservation_fee"), fee)?;
+    }
+    if let Some(flex) = &supplier.monthly_flex {
+        share(field("monthly_flex max_fraction"), flex.max_fraction, 1.0)?;
+        amount(field("monthly_flex fee_per_unit"), flex.fee_per_unit)?;
+    }
+    for product in &params.products {
+        if !supplier.unit_costs.contains_key(&product.id) {
+            return Err(ScenarioError::MissingUnitCost { supplier_id: supplier.id, product_id: product.id });
+        }
+    }
+    for (&product_id, &cost) in &supplier.unit_costs {
+        known_product(params, &format!("supplier {}'s unit costs", supplier.id), product_id)?;
+        amount(field(&format!("unit cost of product {}", product_id)), cost)?;
+    }
+    for &product_id in supplier.product_capacity.iter().flat_map(|limits| limits.keys()) {
+        known_product(params, &format!("supplier {}'s product capacity", supplier.id), product_id)?;
+    }
+    for group in &supplier.capacity_groups {
+        for &product_id in &group.product_ids {
+            known_product(params, &format!("supplier {}'s capacity group {}", supplier.id, group.name), product_id)?;
+        }
+        units("a capacity group's limit", group.limit as f64)?;
+    }
+    for (i, &(limit, multiplier)) in supplier.soft_capacity_tiers.iter().enumerate() {
+        share(field(&format!("soft capacity tier {} limit", i)), limit, MAX_OVERTIME_FRACTION)?;
+        amount(field(&format!("soft capacity tier {} multiplier", i)), multiplier)?;
+    }
+    for (i, &fraction) in supplier.ramp_up_months.iter().enumerate() {
+        share(field(&format!("ramp_up_months[{}]", i)), fraction, 1.0)?;
+    }
+    Ok(())
+}
+
+/// Total demand and capacity a month within `MAX_MONTHLY_UNITS`, counted in floating point so
+/// the check cannot overflow itself
+fn validate_units(params: &SimulationParams, suppliers: &[Supplier]) -> Result<(), ScenarioError> {
+    // Sampled demand is capped at three standard deviations above the mean
+    let demand: f64 = params.demand_params.iter()
+        .map(|dp| (dp.mean_demand + 3.0 * dp.std_dev_demand).max(dp.actual_mean_demand + 3.0 * dp.actual_std_dev_demand))
+        .sum();
+    units("the products' highest demand", demand)?;
+    units("the opening inventory", params.opening_inventory.iter().map(|&(_, units)| units as f64).sum())?;
+    let overtime = |supplier: &Supplier| supplier.soft_capacity_tiers.iter().map(|&(limit, _)| limit).fold(0.0, f64::max);
+    let capacity = suppliers.iter().map(|supplier| supplier.fixed_capacity as f64 * (1.0 + overtime(supplier))).sum::<f64>()
+        + params.expansion_offers.iter().map(|offer| offer.extra_capacity as f64).sum::<f64>();
+    units("the suppliers' capacity", capacity)
+}
+
+fn amount(field: String, value: f64) -> Result<(), ScenarioError> {
+    if value.is_finite() && value >= 0.0 {
+        Ok(())
+    } else {
+        Err(ScenarioError::InvalidAmount { field, value })
+    }
+}
+
+fn share(field: String, value: f64, max: f64) -> Result<(), ScenarioError> {
+    if (0.0..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(ScenarioError::OutOfRange { field, value, min: 0.0, max })
+    }
+}
+
+fn months(field: String, months: usize) -> Result<(), ScenarioError> {
+    if months <= MAX_SEASON_MONTHS {
+        Ok(())
+    } else {
+        Err(ScenarioError::TooManyMonths { field, months, max: MAX_SEASON_MONTHS })
+    }
+}
+
+fn units(what: &'static str, units: f64) -> Result<(), ScenarioError> {
+    if units <= MAX_MONTHLY_UNITS {
+        Ok(())
+    } else {
+        Err(ScenarioError::TooManyUnits { what, units, max: MAX_MONTHLY_UNITS })
+    }
+}
+
+fn known_product(params: &SimulationParams, context: &str, product_id: ProductId) -> Result<(), ScenarioError> {
+    match params.get_product(product_id) {
+        Some(_) => Ok(()),
+        None => Err(ScenarioError::UnknownProduct { context: context.to_string(), product_id }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::models::{Product, ProductDemandParams, SupplierId, TimeStep};
+
+    fn scenario() -> (SimulationParams, Vec<Supplier>) {
+        let params = SimulationParams {
+            products: vec![Product { id: ProductId(0), name: "Model A".to_string(), selling_price: 230.0, liquidation_price: 144.0, monthly_holding_cost: 4.6, goodwill_penalty: None }],
+            demand_params: vec![ProductDemandParams { product_id: ProductId(0), mean_demand: 35_000.0, std_dev_demand: 7_000.0, actual_mean_demand: 32_000.0, actual_std_dev_demand: 7_000.0 }],
+            order_change_fees: vec![2_000_000.0],
+            order_change_fee_per_product: 0.0,
+            cancellation_fee_per_unit: 15.0,
+            calibrate_to_observed_demand: false,
+            season_months: 8,
+            regional_disruptions: Vec::new(),
+            capacity_policy: Default::default(),
+            expansion_offers: Vec::new(),
+            opening_inventory: Vec::new(),
+            onboarded_suppliers: Vec::new(),
+            carry_over_stock: false,
+            time_step: TimeStep::Monthly,
+            cost_of_capital_annual: 0.0,
+        };
+        let supplier = Supplier {
+            id: SupplierId(0),
+            name: "FarFarAway".to_string(),
+            fixed_capacity: 40_000,
+            product_capacity: None,
+            capacity_groups: Vec::new(),
+            soft_capacity_tiers: Vec::new(),
+            lead_time_months: 4,
+            ramp_up_months: Vec::new(),
+            unit_costs: HashMap::from([(ProductId(0), 160.0)]),
+            setup_cost: 250_000.0,
+            reservation_fee: None,
+            monthly_flex: None,
+            disrupted_months: Vec::new(),
+            region: None,
+            risk_class: None,
+        };
+        (params, vec![supplier])
+    }
+
+    fn error_with(edit: impl FnOnce(&mut SimulationParams, &mut Vec<Supplier>)) -> String {
+        let (mut params, mut suppliers) = scenario();
+        edit(&mut params, &mut suppliers);
+        validate_scenario(&params, &suppliers).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_scenarios_out_of_bounds_are_refused() {
+        let (params, suppliers) = scenario();
+        assert!(validate_scenario(&params, &suppliers).is_ok());
+
+        assert_eq!(error_with(|params, _| params.products[0].selling_price = f64::NAN), "product 0 selling_price must be a finite amount, not negative, but is NaN");
+        assert_eq!(error_with(|params, _| params.season_months = 0), "the season must run from 1 to 36 months, but runs 0");
+        assert_eq!(error_with(|_, suppliers| suppliers[0].lead_time_months = usize::MAX), format!("supplier 0 lead_time_months must be at most 36 months, but is {}", usize::MAX));
+        assert_eq!(error_with(|_, suppliers| suppliers[0].fixed_capacity = u32::MAX), "the suppliers' capacity comes to 4294967295 units a month, more than the 10000000 allowed");
+        assert_eq!(error_with(|params, _| params.demand_params[0].std_dev_demand = 4e6), "the products' highest demand comes to 12035000 units a month, more than the 10000000 allowed");
+        assert_eq!(error_with(|_, suppliers| suppliers[0].unit_costs.clear()), "supplier 0 has no unit cost for product 0");
+        assert_eq!(error_with(|params, _| params.demand_params.clear()), "product 0 needs exactly one set of demand parameters");
+        assert_eq!(error_with(|_, suppliers| suppliers.push(suppliers[0].clone())), "supplier id 0 is used twice");
+        assert_eq!(error_with(|_, suppliers| suppliers[0].ramp_up_months = vec![0.5, 1.5]), "supplier 0 ramp_up_months[1] must be from 0 to 1, but is 1.5");
+        assert_eq!(error_with(|params, _| params.opening_inventory = vec![(ProductId(7), 100)]), "the opening inventory refers to product 7, which is not one of the scenario's products");
+        assert_eq!(error_with(|params, _| params.onboarded_suppliers = vec![SupplierId(9)]), "the list of onboarded suppliers refers to supplier 9, which is not one of the scenario's suppliers");
+    }
+}
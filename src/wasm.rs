@@ -0,0 +1,68 @@
+//! Entry point for running small scenarios in the browser through wasm-bindgen: a scenario goes
+//! in as JSON and its run comes back as the same JSON document `--format json` prints
+//!
+//! Nothing here draws on the operating system's entropy: the page passes the seed in with the
+//! scenario, so a run can be replayed, and the budget is bounded so a page never stalls on one
+
+use wasm_bindgen::prelude::*;
+use crate::progress::NoProgress;
+use crate::scenario::{Budget, Scenario};
+
+/// Most replications a pair gets, whatever the scenario asks for
+pub const MAX_SIMULATIONS: usize = 2_000;
+
+/// Most supplier pairs evaluated, in the order pairing generates them
+pub const MAX_PAIRS: usize = 12;
+
+/// Pair the scenario's suppliers, optimize and simulate each pair, and return the run as JSON,
+/// pairs ranked by mean profit; a scenario that cannot be read or run comes back as
+/// `{"error": "..."}`
+/// The budget's fields sit beside the scenario's, its seed below 2^53 to survive a JavaScript
+/// number
+#[wasm_bindgen]
+pub fn evaluate_scenario(json: &str) -> String {
+    let parsed = Scenario::from_json(json).and_then(|scenario| Ok((scenario, serde_json::from_str::<Budget>(json)?)));
+    let (scenario, budget) = match parsed {
+        Ok(parsed) => parsed,
+        Err(err) => return serde_json::json!({ "error": format!("invalid scenario: {}", err) }).to_string(),
+    };
+    // One thread is all a page has, so pairs and replications run in turn
+    let budget = Budget {
+        simulations: budget.simulations.clamp(1, MAX_SIMULATIONS),
+        max_pairs: Some(budget.max_pairs.unwrap_or(MAX_PAIRS).min(MAX_PAIRS)),
+        parallel: false,
+        ..budget
+    };
+    match scenario.evaluate(&budget, &NoProgress) {
+        Ok(run) => serde_json::to_string(&run).expect("a run serializes"),
+        Err(err) => serde_json::json!({ "error": err.to_string() }).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    const SCENARIO: &str = include_str!("../tests/fixtures/one_product_scenario.json");
+
+    #[test]
+    fn test_scenario_round_trips_as_json() {
+        let output = evaluate_scenario(SCENARIO);
+        let run: Value = serde_json::from_str(&output).unwrap();
+        let pairs = run["pairs"].as_array().unwrap();
+        assert!(!pairs.is_empty(), "{}", output);
+        assert!(pairs.iter().all(|pair| pair["stats"]["num_simulations"] == 50));
+        assert!(run["best_pair"].as_u64().unwrap() < pairs.len() as u64);
+        assert_eq!(run["params"]["products"][0]["name"], "Model A");
+
+        // The seed passed in fixes the run
+        assert_eq!(evaluate_scenario(SCENARIO), output);
+    }
+
+    #[test]
+    fn test_unreadable_scenario_comes_back_as_an_error() {
+        let error: Value = serde_json::from_str(&evaluate_scenario(r#"{"params": {}}"#)).unwrap();
+        assert!(error["error"].as_str().unwrap().starts_with("invalid scenario: "), "{}", error);
+    }
+}
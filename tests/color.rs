@@ -0,0 +1,25 @@
+//! End-to-end check that color appears only on request: never on a pipe, and never with
+//! `--no-color` or `NO_COLOR` set, even when forced with `CLICOLOR_FORCE`
+
+use std::process::Command;
+
+fn run_with(args: &[&str], env: &[(&str, &str)]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_supply-chain-sim"))
+        .args(["--simulations", "5"])
+        .args(args)
+        .env_remove("NO_COLOR")
+        .env_remove("CLICOLOR_FORCE")
+        .envs(env.iter().copied())
+        .output()
+        .expect("the simulator runs");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).expect("stdout is UTF-8")
+}
+
+#[test]
+fn test_piped_output_is_plain() {
+    assert!(!run_with(&[], &[]).contains('\u{1b}'));
+    assert!(run_with(&[], &[("CLICOLOR_FORCE", "1")]).contains("\u{1b}["));
+    assert!(!run_with(&["--no-color"], &[("CLICOLOR_FORCE", "1")]).contains('\u{1b}'));
+    assert!(!run_with(&[], &[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]).contains('\u{1b}'));
+}
@@ -0,0 +1,35 @@
+//! The C interface from C: a small program built against `include/supply_chain_sim.h` and
+//! linked to the library's cdylib evaluates the one-product scenario
+
+use std::path::Path;
+use std::process::Command;
+
+#[cfg(unix)]
+#[test]
+fn test_c_program_round_trips_a_scenario() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // Cargo builds the cdylib beside this test
+    let exe = std::env::current_exe().unwrap();
+    let deps = exe.parent().unwrap();
+    let program = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi_round_trip");
+    let compiled = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg(root.join("tests/ffi/round_trip.c"))
+        .arg("-I").arg(root.join("include"))
+        .arg("-L").arg(deps)
+        .arg("-lsupply_chain_sim")
+        .arg("-o").arg(&program)
+        .status()
+        .expect("a C compiler runs");
+    assert!(compiled.success());
+
+    let output = Command::new(&program)
+        .arg(root.join("tests/fixtures/one_product_scenario.json"))
+        .env("LD_LIBRARY_PATH", deps)
+        .env("DYLD_LIBRARY_PATH", deps)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let run: serde_json::Value = serde_json::from_slice(&output.stdout).expect("the program prints the run");
+    assert!(!run["pairs"].as_array().unwrap().is_empty());
+    assert_eq!(run["pairs"][0]["stats"]["num_simulations"], 20);
+}
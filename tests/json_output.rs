@@ -0,0 +1,68 @@
+//! End-to-end check of `--format json`: stdout must hold exactly one JSON document
+
+use std::process::Command;
+use serde_json::Value;
+
+#[test]
+fn test_json_mode_prints_only_the_run_document() {
+    let output = Command::new(env!("CARGO_BIN_EXE_supply-chain-sim"))
+        .args(["--simulations", "5", "--format", "json"])
+        .output()
+        .expect("the simulator runs");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // Any decorative line on stdout would make the document fail to parse
+    let run: Value = serde_json::from_slice(&output.stdout).expect("stdout is a single JSON document");
+    assert_eq!(run["params"]["products"].as_array().unwrap().len(), 2);
+    assert!(!run["suppliers"].as_array().unwrap().is_empty());
+    assert_eq!(run["ranking"], "MeanProfit");
+    // Every generated pair is either simulated or listed with the reason it was screened out
+    assert!(run["rejected_pairs"].is_array());
+
+    let pairs = run["pairs"].as_array().unwrap();
+    assert!(!pairs.is_empty());
+    for pair in pairs {
+        assert_eq!(pair["stats"]["num_simulations"], 5);
+        assert_eq!(pair["optimal_quantities"].as_array().unwrap().len(), 2);
+        assert!(pair["quick_estimate"].is_number());
+    }
+    let best = run["best_pair"].as_u64().unwrap() as usize;
+    let best_mean = pairs[best]["stats"]["mean_profit"].as_f64().unwrap();
+    assert!(pairs.iter().all(|pair| pair["stats"]["mean_profit"].as_f64().unwrap() <= best_mean));
+}
+
+#[test]
+fn test_scenarios_print_their_matrix() {
+    let output = Command::new(env!("CARGO_BIN_EXE_supply-chain-sim"))
+        .args(["--simulations", "5", "--format", "json", "scenarios"])
+        .args(["tests/fixtures/one_product_scenario.json", "tests/fixtures/one_product_slow_season.json"])
+        .output()
+        .expect("the simulator runs");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let matrix: Value = serde_json::from_slice(&output.stdout).expect("stdout is a single JSON document");
+    assert_eq!(matrix["scenarios"], serde_json::json!(["one_product_scenario", "one_product_slow_season"]));
+    let pairs = matrix["pairs"].as_array().unwrap().len();
+    assert!(pairs > 0);
+    for (row, winner) in matrix["regrets"].as_array().unwrap().iter().zip(matrix["winners"].as_array().unwrap()) {
+        assert_eq!(row.as_array().unwrap().len(), pairs);
+        assert_eq!(row[winner.as_u64().unwrap() as usize], 0.0);
+    }
+    assert!(matrix["minimax_regret_pair"].as_u64().unwrap() < pairs as u64);
+}
+
+#[test]
+fn test_archived_runs_join_the_scenario_matrix() {
+    let output = Command::new(env!("CARGO_BIN_EXE_supply-chain-sim"))
+        .args(["--simulations", "5", "--format", "json", "scenarios"])
+        .args(["tests/fixtures/archived_run_v1.json", "tests/fixtures/one_product_scenario.json"])
+        .output()
+        .expect("the simulator runs");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let matrix: Value = serde_json::from_slice(&output.stdout).expect("stdout is a single JSON document");
+    assert_eq!(matrix["scenarios"], serde_json::json!(["archived_run_v1", "one_product_scenario"]));
+    let pairs: Vec<&str> = matrix["pairs"].as_array().unwrap().iter().map(|pair| pair.as_str().unwrap()).collect();
+    let archived = pairs.iter().position(|&pair| pair == "FarFarAway + Nearshore").expect("the archived best pair");
+    assert_eq!(matrix["mean_profits"][0][archived], 1_900_000.0);
+}
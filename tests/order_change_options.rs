@@ -0,0 +1,90 @@
+//! Seasons of a fixed plan with the order-change options on and off, over the same seeded demand
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use supply_chain_sim::prelude::*;
+use supply_chain_sim::simulation::{run_monthly_simulation_internal, DemandSource};
+
+/// 10k monthly demand with a CV of 0.2, a price of 100 against a surge cost of 80, holding at 2 a
+/// month, and a single change right at 50k
+fn params() -> SimulationParams {
+    SimulationParams {
+        products: vec![Product {
+            id: ProductId(0),
+            name: "Widget".to_string(),
+            selling_price: 100.0,
+            liquidation_price: 60.0,
+            monthly_holding_cost: 2.0,
+            goodwill_penalty: None,
+        }],
+        demand_params: vec![ProductDemandParams {
+            product_id: ProductId(0),
+            mean_demand: 10_000.0,
+            std_dev_demand: 2_000.0,
+            actual_mean_demand: 10_000.0,
+            actual_std_dev_demand: 2_000.0,
+        }],
+        order_change_fees: vec![50_000.0],
+        order_change_fee_per_product: 0.0,
+        cancellation_fee_per_unit: 5.0,
+        calibrate_to_observed_demand: false,
+        season_months: 8,
+        regional_disruptions: Vec::new(),
+        capacity_policy: CapacityPolicy::Clamp,
+        expansion_offers: Vec::new(),
+        opening_inventory: Vec::new(),
+        onboarded_suppliers: Vec::new(),
+        carry_over_stock: false,
+        time_step: TimeStep::Monthly,
+        cost_of_capital_annual: 0.0,
+    }
+}
+
+fn supplier(id: u32, lead_time_months: usize, unit_cost: f64) -> Supplier {
+    Supplier {
+        id: SupplierId(id),
+        name: format!("Supplier {}", id),
+        fixed_capacity: 20_000,
+        product_capacity: None,
+        capacity_groups: Vec::new(),
+        soft_capacity_tiers: Vec::new(),
+        lead_time_months,
+        ramp_up_months: Vec::new(),
+        unit_costs: HashMap::from([(ProductId(0), unit_cost)]),
+        setup_cost: 0.0,
+        reservation_fee: None,
+        monthly_flex: None,
+        disrupted_months: Vec::new(),
+        region: None,
+        risk_class: None,
+    }
+}
+
+#[test]
+fn test_options_never_reduce_mean_profit() {
+    let params = params();
+    let pair = SupplierPair {
+        base_supplier: supplier(0, 3, 75.0),
+        surge_supplier: supplier(1, 0, 80.0),
+        backup_supplier: None,
+        pooled_suppliers: Vec::new(),
+    };
+    // 8,000 a month runs 2,000 short of the forecast
+    let order = MonthlyOrder::new(
+        vec![ProductOrder { product_id: ProductId(0), quantity: 8_000 }],
+        vec![ProductOrder { product_id: ProductId(0), quantity: 0 }],
+    ).unwrap();
+
+    let mean_profit = |enable_options: bool| {
+        (0..40)
+            .map(|seed| {
+                let rng = RefCell::new(SmallRng::seed_from_u64(seed));
+                run_monthly_simulation_internal(&params, &pair, &order, enable_options, true, DemandSource::Stochastic(Some(&rng))).unwrap().1
+            })
+            .sum::<f64>() / 40.0
+    };
+    let (with_options, without_options) = (mean_profit(true), mean_profit(false));
+    assert!(with_options >= without_options, "{:.0} against {:.0}", with_options, without_options);
+}
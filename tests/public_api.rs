@@ -0,0 +1,103 @@
+//! The pipeline through the library's prelude alone: pair suppliers, optimize a plan for each
+//! pair, split it between the pair's suppliers and simulate it
+
+use std::collections::HashMap;
+use supply_chain_sim::prelude::*;
+
+fn params() -> SimulationParams {
+    SimulationParams {
+        products: vec![
+            Product { id: ProductId(0), name: "Jacket".to_string(), selling_price: 200.0, liquidation_price: 120.0, monthly_holding_cost: 4.0, goodwill_penalty: None },
+            Product { id: ProductId(1), name: "Vest".to_string(), selling_price: 120.0, liquidation_price: 70.0, monthly_holding_cost: 2.5, goodwill_penalty: None },
+        ],
+        demand_params: vec![
+            ProductDemandParams { product_id: ProductId(0), mean_demand: 12_000.0, std_dev_demand: 2_500.0, actual_mean_demand: 12_000.0, actual_std_dev_demand: 2_500.0 },
+            ProductDemandParams { product_id: ProductId(1), mean_demand: 8_000.0, std_dev_demand: 2_000.0, actual_mean_demand: 8_000.0, actual_std_dev_demand: 2_000.0 },
+        ],
+        order_change_fees: vec![50_000.0],
+        order_change_fee_per_product: 0.0,
+        cancellation_fee_per_unit: 5.0,
+        calibrate_to_observed_demand: false,
+        season_months: 4,
+        regional_disruptions: Vec::new(),
+        capacity_policy: CapacityPolicy::Clamp,
+        expansion_offers: Vec::new(),
+        opening_inventory: Vec::new(),
+        onboarded_suppliers: Vec::new(),
+        carry_over_stock: false,
+        time_step: TimeStep::Monthly,
+        cost_of_capital_annual: 0.0,
+    }
+}
+
+fn supplier(id: u32, name: &str, lead_time_months: usize, capacity: u32, unit_cost: f64) -> Supplier {
+    Supplier {
+        id: SupplierId(id),
+        name: name.to_string(),
+        fixed_capacity: capacity,
+        product_capacity: None,
+        capacity_groups: Vec::new(),
+        soft_capacity_tiers: Vec::new(),
+        lead_time_months,
+        ramp_up_months: Vec::new(),
+        unit_costs: HashMap::from([(ProductId(0), unit_cost), (ProductId(1), unit_cost * 0.6)]),
+        setup_cost: 100_000.0,
+        reservation_fee: None,
+        monthly_flex: None,
+        disrupted_months: Vec::new(),
+        region: None,
+        risk_class: None,
+    }
+}
+
+#[test]
+fn test_a_scenario_runs_end_to_end_through_the_prelude() {
+    let params = params();
+    let suppliers = vec![
+        supplier(0, "Overseas", 2, 10_000, 100.0),
+        supplier(1, "Nearby", 0, 6_000, 115.0),
+    ];
+
+    let pairs = generate_intelligent_pairs(&suppliers, false);
+    let pair = pairs.iter()
+        .find(|pair| pair.base_supplier.name == "Overseas" && pair.surge_supplier.name == "Nearby")
+        .expect("the long lead time supplier is paired as base with the short one as surge");
+
+    let quantities = find_optimal_production_quantities(&params, pair).expect("the scenario's demand can be sampled");
+    assert_eq!(quantities.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![ProductId(0), ProductId(1)]);
+    assert!(quantities.iter().all(|(_, qty)| *qty > 0));
+
+    let order: MonthlyOrder = split_order_quantities(&quantities, pair, &params);
+    for (product_id, qty) in &quantities {
+        let ordered: u32 = order.base_orders().iter().chain(order.surge_orders())
+            .filter(|order| order.product_id == *product_id)
+            .map(|order| order.quantity)
+            .sum();
+        assert!(ordered <= *qty, "product {} ordered {} of {}", product_id, ordered, qty);
+    }
+
+    let stats: MonteCarloStats = run_monte_carlo_simulation(&params, pair, &order, &MonteCarloConfig::new(50)).expect("the plan simulates");
+    assert_eq!(stats.num_simulations, 50);
+    assert_eq!(stats.base_supplier, "Overseas");
+    assert_eq!(stats.surge_supplier, "Nearby");
+    assert!(stats.min_profit <= stats.mean_profit && stats.mean_profit <= stats.max_profit);
+}
+
+#[test]
+fn test_failures_come_back_as_errors_rather_than_panics() {
+    let mut params = params();
+    let suppliers = vec![
+        supplier(0, "Overseas", 2, 10_000, 100.0),
+        supplier(1, "Nearby", 0, 6_000, 115.0),
+    ];
+    let pair = &generate_intelligent_pairs(&suppliers, false)[0];
+    let order = split_order_quantities(&[(ProductId(0), 5_000), (ProductId(1), 3_000)], pair, &params);
+
+    let empty = run_monte_carlo_simulation(&params, pair, &order, &MonteCarloConfig::new(0));
+    assert!(matches!(empty, Err(SimulationError::EmptySamples(_))));
+
+    params.demand_params[1].actual_std_dev_demand = -500.0;
+    let invalid = run_monte_carlo_simulation(&params, pair, &order, &MonteCarloConfig::new(10));
+    assert!(matches!(invalid, Err(SimulationError::InvalidDistribution { product_id: ProductId(1), .. })));
+    assert!(matches!(find_optimal_production_quantities(&params, pair), Err(SimulationError::InvalidDistribution { .. })));
+}
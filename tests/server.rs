@@ -0,0 +1,96 @@
+//! End-to-end check of the HTTP server: runs submitted side by side on a random port, polled to
+//! completion, and their results fetched as JSON and CSV
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::Value;
+use supply_chain_sim::server::serve_on;
+
+/// Start a server on a port the system picks, for the rest of the test process
+fn start_server() -> SocketAddr {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let listener = runtime.block_on(tokio::net::TcpListener::bind("127.0.0.1:0")).unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || runtime.block_on(serve_on(listener)));
+    addr
+}
+
+/// Status code and body of a request, over a connection of its own
+fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        method, path, addr, body.len(), body
+    ).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").expect("a response has a head and a body");
+    let status = head.split(' ').nth(1).and_then(|code| code.parse().ok()).expect("a status line");
+    (status, body.to_string())
+}
+
+fn json(body: &str) -> Value {
+    serde_json::from_str(body).unwrap_or_else(|err| panic!("{}: {}", err, body))
+}
+
+/// The scenario run on `budget`, whose fields go beside the scenario's
+fn submission(budget: &str) -> String {
+    let mut scenario = json(include_str!("fixtures/one_product_scenario.json"));
+    for (field, value) in json(budget).as_object().unwrap() {
+        scenario[field] = value.clone();
+    }
+    scenario.to_string()
+}
+
+#[test]
+fn test_runs_complete_side_by_side() {
+    let addr = start_server();
+    let submit = |budget: &str| {
+        let (status, body) = request(addr, "POST", "/runs", &submission(budget));
+        assert_eq!(status, 202, "{}", body);
+        json(&body)["id"].as_u64().unwrap()
+    };
+    let first = submit(r#"{"simulations": 40, "seed": 1}"#);
+    let second = submit(r#"{"simulations": 60, "seed": 2, "parallel": false}"#);
+    assert_ne!(first, second);
+
+    for (id, simulations) in [(first, 40), (second, 60)] {
+        let started = Instant::now();
+        let run = loop {
+            let (status, body) = request(addr, "GET", &format!("/runs/{}", id), "");
+            assert_eq!(status, 200, "{}", body);
+            let run = json(&body);
+            match run["status"].as_str().unwrap() {
+                "running" => assert!((0.0..=100.0).contains(&run["percent_complete"].as_f64().unwrap())),
+                "complete" => break run,
+                other => panic!("run {} is {}: {}", id, other, body),
+            }
+            assert!(started.elapsed() < Duration::from_secs(120), "run {} never finished", id);
+            thread::sleep(Duration::from_millis(50));
+        };
+        assert_eq!(run["percent_complete"], 100.0);
+        let pairs = run["results"]["pairs"].as_array().unwrap();
+        assert!(!pairs.is_empty());
+        assert!(pairs.iter().all(|pair| pair["stats"]["num_simulations"] == simulations));
+
+        let (status, csv) = request(addr, "GET", &format!("/runs/{}/results.csv", id), "");
+        assert_eq!(status, 200);
+        let mut lines = csv.lines();
+        assert!(lines.next().unwrap().starts_with("base_supplier,"));
+        assert_eq!(lines.count(), pairs.len());
+    }
+}
+
+#[test]
+fn test_bad_requests_are_refused() {
+    let addr = start_server();
+    let (status, body) = request(addr, "POST", "/runs", r#"{"params": {}}"#);
+    assert_eq!(status, 400);
+    assert!(json(&body)["error"].as_str().unwrap().starts_with("invalid scenario: "));
+
+    assert_eq!(request(addr, "GET", "/runs/99", "").0, 404);
+    assert_eq!(request(addr, "GET", "/runs/99/results.csv", "").0, 404);
+}
@@ -0,0 +1,52 @@
+//! Variance reduction on the standard scenario: the demo's suppliers and products, order changes
+//! included
+
+#[path = "../examples/demo/scenario.rs"]
+mod scenario;
+
+use supply_chain_sim::monte_carlo::VarianceReduction;
+use supply_chain_sim::prelude::*;
+
+/// Both estimators cut the standard error by at least a fifth and agree with the plain mean over
+/// `replications` runs from seed 14
+fn check_variance_reduction(replications: usize) {
+    let params = scenario::params();
+    let suppliers = scenario::suppliers();
+    let pair = SupplierPair {
+        base_supplier: suppliers[0].clone(),
+        surge_supplier: suppliers[2].clone(),
+        backup_supplier: None,
+        pooled_suppliers: Vec::new(),
+    };
+    let monthly_order = split_order_quantities(&[(ProductId(0), 30_000), (ProductId(1), 20_000)], &pair, &params);
+    let plain = MonteCarloConfig { master_seed: 14, ..MonteCarloConfig::new(replications) };
+    let baseline = run_monte_carlo_simulation(&params, &pair, &monthly_order, &plain).unwrap();
+    // Order changes are in play in most seasons
+    assert!(baseline.exercise_probability > 0.5, "exercised in {} of runs", baseline.exercise_probability);
+
+    for variance_reduction in [VarianceReduction::Antithetic, VarianceReduction::ControlVariate] {
+        let config = MonteCarloConfig { variance_reduction, ..plain.clone() };
+        let stats = run_monte_carlo_simulation(&params, &pair, &monthly_order, &config).unwrap();
+        assert!(
+            stats.adjusted_std_error <= 0.8 * baseline.mean_std_error,
+            "{:?}: {} against {}", variance_reduction, stats.adjusted_std_error, baseline.mean_std_error
+        );
+        let gap = (stats.adjusted_mean_profit - baseline.mean_profit).abs();
+        assert!(gap < 3.0 * baseline.mean_std_error, "{:?}: off by {}", variance_reduction, gap);
+    }
+}
+
+/// Both methods leave 0.46 to 0.62 of the plain error at 30 to 50 runs whatever the seed, so 40
+/// runs keep well clear of the bound
+#[test]
+fn test_variance_reduction_on_a_short_run_of_the_standard_scenario() {
+    check_variance_reduction(40);
+}
+
+/// The full-size check at 200 replications, some 600 seasons with order changes, run with
+/// `cargo test --release --test variance_reduction -- --ignored`
+#[test]
+#[ignore]
+fn test_variance_reduction_on_the_standard_scenario() {
+    check_variance_reduction(200);
+}
@@ -0,0 +1,31 @@
+//! End-to-end check of `--quiet` and `--verbose`: what each level adds to or drops from stdout
+
+use std::process::Command;
+
+fn run_with(flag: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_supply-chain-sim"))
+        .args(["--simulations", "5", flag])
+        .output()
+        .expect("the simulator runs");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).expect("stdout is UTF-8")
+}
+
+#[test]
+fn test_quiet_prints_only_the_ranked_summary() {
+    let stdout = run_with("--quiet");
+    // No banners: box-drawing characters live in U+2500 to U+257F
+    assert!(!stdout.chars().any(|c| ('\u{2500}'..='\u{257F}').contains(&c)), "{}", stdout);
+    assert!(!stdout.contains("Evaluating"));
+    assert!(stdout.starts_with("Profit"), "{}", stdout);
+    assert!(stdout.contains("Allocations:"));
+}
+
+#[test]
+fn test_verbose_adds_diagnostics_and_timing() {
+    let stdout = run_with("-v");
+    assert!(stdout.contains("Optimizer Diagnostics:"));
+    assert!(stdout.contains("optimizing,"));
+    assert!(stdout.contains("MONTE CARLO RESULTS"));
+    assert!(stdout.contains("Profit Distributions"));
+}
@@ -0,0 +1,18 @@
+//! The browser entry point run in a JavaScript engine:
+//! `wasm-pack test --node -- --no-default-features --features wasm`
+
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use serde_json::Value;
+use supply_chain_sim::wasm::evaluate_scenario;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn test_one_product_scenario_round_trips() {
+    let output = evaluate_scenario(include_str!("fixtures/one_product_scenario.json"));
+    let run: Value = serde_json::from_str(&output).expect("the run comes back as JSON");
+    assert!(run.get("error").is_none(), "{}", output);
+    let pairs = run["pairs"].as_array().unwrap();
+    assert!(!pairs.is_empty());
+    assert!(run["best_pair"].as_u64().unwrap() < pairs.len() as u64);
+}